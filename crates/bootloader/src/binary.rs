@@ -0,0 +1,64 @@
+//! Flat binaries are kernels without ELF headers (like small assembly payloads or foreign kernels),
+//! which are loaded unchanged at the physical address of the entry. They are entered like ELF
+//! kernels through the trampoline, so the physical address of the boot information is passed in
+//! RDI and the page tables of the firmware stay active.
+
+use crate::{
+    early_alloc::EARLY_ALLOCATOR,
+    elf_loader::LoadedKernel,
+    error::Error,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::TlsTemplate,
+    fastmem,
+};
+use log::info;
+use uefi::{
+    prelude::BootServices,
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// This function loads the flat binary at the specified physical address. The remaining memory of
+/// the last page is zeroed. The entry offset must be located inside of the binary.
+pub fn load_flat_binary(
+    boot_services: &BootServices, data: &[u8], load_address: Option<u64>, entry_offset: u64,
+) -> Result<LoadedKernel, Error> {
+    let load_address = load_address.ok_or(Error::NoLoadAddress)?;
+    if entry_offset >= data.len() as u64 {
+        return Err(Error::EntryOutsideBinary(entry_offset, data.len()));
+    }
+
+    let size = (data.len() as u64).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    let page_count = (size / PAGE_SIZE) as usize;
+    boot_services
+        .allocate_pages(AllocateType::Address(load_address), MemoryType::LOADER_DATA, page_count)
+        .map_err(|_| Error::LoadAddressUnavailable(load_address, size))?;
+    unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(load_address), page_count)? };
+
+    let image = unsafe { core::slice::from_raw_parts_mut(load_address as *mut u8, size as usize) };
+    fastmem::copy(&mut image[..data.len()], data);
+    fastmem::zero(&mut image[data.len()..]);
+    info!(
+        "Loaded flat binary at 0x{:X} ({} kB, Entry offset: 0x{:X})\n",
+        load_address,
+        size / 1024,
+        entry_offset
+    );
+
+    Ok(LoadedKernel {
+        address: PhysAddr::new(load_address),
+        size,
+        entry: VirtAddr::new(load_address + entry_offset),
+        slide: 0,
+        tls_template: TlsTemplate::NONE,
+    })
+}
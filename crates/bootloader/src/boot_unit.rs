@@ -0,0 +1,160 @@
+use crate::{
+    early_alloc::early_alloc,
+    error::Error,
+    files::{
+        self,
+        SimpleFileSystemContext,
+        VolumeFilter,
+    },
+    verify::{
+        signatures_required,
+        verify_artifact,
+        DigestManifest,
+    },
+};
+use alloc::{
+    string::ToString,
+    vec::Vec,
+};
+use libcore::{
+    address::PhysAddr,
+    boot_info::{
+        BootUnitInfo,
+        BootUnitRole,
+        LoadProtocol,
+    },
+    config::{
+        BootConfig,
+        BootEntry,
+    },
+};
+use log::info;
+
+/// A boot unit is a kernel with its initrd, command line and load protocol. The primary unit is
+/// started by the bootloader, the other units are only loaded into reserved memory and described in
+/// the boot information, so the kernel can start them later.
+#[derive(Clone, Copy, Debug)]
+pub struct BootUnit<'a> {
+    pub title: &'a str,
+    pub kernel: &'a str,
+    pub initrd: Option<&'a str>,
+    pub cmdline: &'a str,
+    pub protocol: LoadProtocol,
+    pub role: BootUnitRole,
+}
+
+impl<'a> BootUnit<'a> {
+    pub fn from_entry(entry: &BootEntry<'a>, role: BootUnitRole) -> Self {
+        Self {
+            title: entry.title,
+            kernel: entry.kernel,
+            initrd: entry.initrd,
+            cmdline: entry.cmdline,
+            protocol: entry.protocol,
+            role,
+        }
+    }
+
+    /// This function reads the files of the unit from the volume, which contains the kernel, and
+    /// verifies them against the digest manifest by their file names. The files are allocated with
+    /// the early allocator, so they stay reserved for the kernel.
+    fn load(
+        &self, file_system_context: &mut SimpleFileSystemContext, manifest: Option<&DigestManifest>,
+        strict: bool, filter: VolumeFilter,
+    ) -> Result<BootUnitInfo, Error> {
+        let index = files::find_file(file_system_context, self.kernel, filter)?;
+        let kernel_data = files::read_file(file_system_context, index, self.kernel)?;
+        let initrd_data = match self.initrd {
+            Some(initrd) => Some(files::read_file(file_system_context, index, initrd)?),
+            None => None,
+        };
+        match manifest {
+            Some(manifest) => {
+                verify_artifact(manifest, file_name(self.kernel), kernel_data, strict)?;
+                if let (Some(initrd), Some(initrd_data)) = (self.initrd, &initrd_data) {
+                    verify_artifact(manifest, file_name(initrd), initrd_data, strict)?;
+                }
+            }
+            None if signatures_required() => {
+                return Err(Error::MissingSignature(file_name(self.kernel).to_string()));
+            }
+            None => {}
+        }
+
+        // The title and the command line point into the boot configuration, which is reserved
+        let (initrd_address, initrd_size) = match initrd_data {
+            Some(initrd_data) => (PhysAddr::new(initrd_data.as_ptr() as u64), initrd_data.len()),
+            None => (PhysAddr::NULL, 0),
+        };
+        Ok(BootUnitInfo {
+            title_address: PhysAddr::new(self.title.as_ptr() as u64),
+            title_size: self.title.len() as u64,
+            kernel_address: PhysAddr::new(kernel_data.as_ptr() as u64),
+            kernel_size: kernel_data.len() as u64,
+            initrd_address,
+            initrd_size: initrd_size as u64,
+            command_line_address: PhysAddr::new(self.cmdline.as_ptr() as u64),
+            command_line_size: self.cmdline.len() as u64,
+            protocol: self.protocol,
+            role: self.role,
+        })
+    }
+}
+
+/// This function returns the units, which are loaded besides the specified entry. These are the
+/// entries referenced by `units` and `crashkernel`. If a referenced entry doesn't exist, a
+/// [Error::UnknownBootUnit] error is returned.
+pub fn secondary_units<'a>(
+    config: &BootConfig<'a>, entry: &BootEntry,
+) -> Result<Vec<BootUnit<'a>>, Error> {
+    let find_unit = |title: &str, role| {
+        config
+            .entry_by_title(title)
+            .map(|entry| BootUnit::from_entry(&entry, role))
+            .ok_or_else(|| Error::UnknownBootUnit(title.into()))
+    };
+
+    let mut units = Vec::new();
+    for title in entry.unit_titles() {
+        units.push(find_unit(title, BootUnitRole::Secondary)?);
+    }
+    if let Some(title) = entry.crashkernel {
+        units.push(find_unit(title, BootUnitRole::CrashKernel)?);
+    }
+    Ok(units)
+}
+
+/// This function loads the specified units and returns the table of the units for the boot
+/// information. The table is allocated with the early allocator, so it's handed over to the kernel.
+pub fn load_units(
+    file_system_context: &mut SimpleFileSystemContext, units: &[BootUnit],
+    manifest: Option<&DigestManifest>, strict: bool, filter: VolumeFilter,
+) -> Result<&'static [BootUnitInfo], Error> {
+    if units.is_empty() {
+        return Ok(&[]);
+    }
+
+    let buffer = early_alloc(
+        file_system_context.boot_services,
+        units.len() * core::mem::size_of::<BootUnitInfo>(),
+        core::mem::align_of::<BootUnitInfo>(),
+    )?;
+    let table = buffer.as_mut_ptr() as *mut BootUnitInfo;
+    for (index, unit) in units.iter().enumerate() {
+        let info = unit.load(file_system_context, manifest, strict, filter)?;
+        info!(
+            "Loaded boot unit '{}' ({:?}, {} kB kernel data)\n",
+            unit.title,
+            unit.role,
+            info.kernel_size / 1024
+        );
+        unsafe { table.add(index).write(info) };
+    }
+    Ok(unsafe { core::slice::from_raw_parts(table, units.len()) })
+}
+
+/// This function returns the file name of the specified UEFI path, which is used as name in the
+/// digest manifest
+fn file_name(path: &str) -> &str {
+    path.rsplit('\\').next().unwrap_or(path)
+}
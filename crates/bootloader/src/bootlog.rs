@@ -0,0 +1,81 @@
+//! The boot log keeps the log of the bootloader across boots on the first volume, so the boots of a
+//! machine without serial console can be inspected afterwards. The state of the last boot (the
+//! selected entry and the time) is written into a separate file, which is replaced on every boot.
+
+use crate::{
+    error::Error,
+    files::{
+        self,
+        SimpleFileSystemContext,
+    },
+};
+use alloc::{
+    format,
+    string::String,
+};
+use core::fmt::Write;
+use libcore::config::BootEntry;
+use libgraphics::log::log_tail;
+use uefi::table::runtime::Time;
+
+/// The load option `bootlog`, which appends the log of every boot to the boot log
+pub const BOOT_LOG_OPTION: &str = "bootlog";
+pub const BOOT_LOG_PATH: &str = "\\EFI\\OVERFLOW\\BOOT.LOG";
+pub const LAST_BOOT_PATH: &str = "\\EFI\\OVERFLOW\\LASTBOOT.TXT";
+
+/// If the boot log would grow over this size, it's started again with the current boot
+const MAX_BOOT_LOG_SIZE: u64 = 256 * 1024;
+
+/// This function appends the last 4 KiB of the log with a header, which contains the time and the
+/// title of the entry, to the boot log on the first volume
+pub fn append_boot_log(
+    context: &mut SimpleFileSystemContext, time: Option<&Time>, title: &str,
+) -> Result<(), Error> {
+    let (older, newer) = log_tail().contents();
+    let mut data = format!("--- Boot of '{}' at {} ---\n", title, format_time(time)).into_bytes();
+    data.extend_from_slice(older);
+    data.extend_from_slice(newer);
+    if !data.ends_with(b"\n") {
+        data.push(b'\n');
+    }
+
+    let size = files::file_size(context, 0, BOOT_LOG_PATH).unwrap_or(0);
+    if size + data.len() as u64 > MAX_BOOT_LOG_SIZE {
+        files::write_file(context, 0, BOOT_LOG_PATH, &data)
+    } else {
+        files::append_file(context, 0, BOOT_LOG_PATH, &data)
+    }
+}
+
+/// This function writes the state of the boot (the entry and the time) into the last boot file on the
+/// first volume. The file uses the format of the boot configuration.
+pub fn write_last_boot(
+    context: &mut SimpleFileSystemContext, time: Option<&Time>, entry: &BootEntry,
+) -> Result<(), Error> {
+    let mut state = String::new();
+    let _ = writeln!(state, "# Written by the OverflowOS Bootloader v{}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(state, "title = \"{}\"", entry.title);
+    let _ = writeln!(state, "kernel = \"{}\"", entry.kernel);
+    let _ = writeln!(state, "cmdline = \"{}\"", entry.cmdline);
+    let _ = writeln!(state, "time = \"{}\"", format_time(time));
+    files::write_file(context, 0, LAST_BOOT_PATH, state.as_bytes())
+}
+
+/// This function formats the time as `YYYY-MM-DD hh:mm:ss`. Without real-time clock, the time is
+/// `unknown`.
+fn format_time(time: Option<&Time>) -> String {
+    match time {
+        Some(time) => {
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                time.year(),
+                time.month(),
+                time.day(),
+                time.hour(),
+                time.minute(),
+                time.second()
+            )
+        }
+        None => String::from("unknown"),
+    }
+}
@@ -0,0 +1,282 @@
+//! The console shows the log output of the bootloader. It has two backends: the graphical console,
+//! which draws the text into the framebuffer of the GOP, and the text console, which writes the text
+//! with the Simple Text Output protocol of the firmware. The text console is used, if the graphics
+//! can't be initialized (like on firmware without GOP), so the bootloader is never without output.
+
+use crate::error::Error;
+use alloc::boxed::Box;
+use core::fmt::{
+    self,
+    Write,
+};
+use libgraphics::{
+    embedded_graphics::{
+        mono_font::ascii,
+        pixelcolor::Rgb888,
+        prelude::RgbColor,
+    },
+    log::append_to_log_tail,
+    text::{
+        DARK_BLUE,
+        DARK_GRAY,
+        GREEN,
+        LIGHT_BLUE,
+        ORANGE,
+        RED,
+        TEXT_WRITER_CONTEXT,
+    },
+};
+use log::{
+    set_logger,
+    set_max_level,
+    warn,
+    Level,
+    Log,
+    Metadata,
+    Record,
+};
+use uefi::{
+    prelude::{
+        Boot,
+        BootServices,
+    },
+    proto::console::text::Color,
+    table::SystemTable,
+};
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+/// The backend, which shows the console output. It's removed, when the backend can't be used
+/// anymore (like the text console after exiting the Boot Services).
+static mut CONSOLE: Option<Box<dyn ConsoleBackend>> = None;
+
+/// The colors of the console, which are mapped to the colors of the backend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleColor {
+    White,
+    DarkGray,
+    Red,
+    Orange,
+    Green,
+    LightBlue,
+    DarkBlue,
+}
+
+/// The output device of the console
+pub trait ConsoleBackend {
+    /// This function returns the number of columns and rows of the console
+    fn size(&mut self) -> Result<(usize, usize), Error>;
+
+    /// This function sets the color of the following text. The background is always black.
+    fn set_color(&mut self, color: ConsoleColor) -> Result<(), Error>;
+
+    fn write_str(&mut self, text: &str) -> Result<(), Error>;
+
+    /// This function shows the written text on the screen
+    fn flush(&mut self) -> Result<(), Error>;
+
+    /// This function returns, whether the backend can be used. The graphical console can't be used
+    /// after the graphics were released for the handoff to the kernel.
+    fn is_available(&self) -> bool;
+
+    /// This function returns, whether the backend is provided by the Boot Services
+    fn needs_boot_services(&self) -> bool;
+}
+
+/// The console, which draws the text with the text writer of libgraphics
+pub struct GraphicsConsole;
+
+impl ConsoleBackend for GraphicsConsole {
+    fn size(&mut self) -> Result<(usize, usize), Error> {
+        Ok(libgraphics::text::text_size()?)
+    }
+
+    fn set_color(&mut self, color: ConsoleColor) -> Result<(), Error> {
+        let color = match color {
+            ConsoleColor::White => Rgb888::WHITE,
+            ConsoleColor::DarkGray => DARK_GRAY,
+            ConsoleColor::Red => RED,
+            ConsoleColor::Orange => ORANGE,
+            ConsoleColor::Green => GREEN,
+            ConsoleColor::LightBlue => LIGHT_BLUE,
+            ConsoleColor::DarkBlue => DARK_BLUE,
+        };
+        Ok(libgraphics::text::set_color(Rgb888::BLACK, color)?)
+    }
+
+    fn write_str(&mut self, text: &str) -> Result<(), Error> {
+        Ok(libgraphics::text::write_str(text)?)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(libgraphics::swap_buffers()?)
+    }
+
+    fn is_available(&self) -> bool {
+        unsafe { TEXT_WRITER_CONTEXT.is_some() }
+    }
+
+    fn needs_boot_services(&self) -> bool {
+        false
+    }
+}
+
+/// The console, which writes the text with the Simple Text Output protocol of the firmware
+pub struct TextConsole {
+    system_table: SystemTable<Boot>,
+}
+
+impl TextConsole {
+    /// This function clears the screen of the text output and creates the console
+    pub fn new(mut system_table: SystemTable<Boot>) -> Result<Self, Error> {
+        system_table.stdout().reset(false)?;
+        Ok(Self { system_table })
+    }
+}
+
+impl ConsoleBackend for TextConsole {
+    fn size(&mut self) -> Result<(usize, usize), Error> {
+        let mode = self
+            .system_table
+            .stdout()
+            .current_mode()?
+            .ok_or(Error::NoContext)?;
+        Ok((mode.columns(), mode.rows()))
+    }
+
+    fn set_color(&mut self, color: ConsoleColor) -> Result<(), Error> {
+        let color = match color {
+            ConsoleColor::White => Color::White,
+            ConsoleColor::DarkGray => Color::DarkGray,
+            ConsoleColor::Red => Color::LightRed,
+            ConsoleColor::Orange => Color::Yellow,
+            ConsoleColor::Green => Color::LightGreen,
+            ConsoleColor::LightBlue => Color::LightBlue,
+            ConsoleColor::DarkBlue => Color::Blue,
+        };
+        Ok(self.system_table.stdout().set_color(color, Color::Black)?)
+    }
+
+    fn write_str(&mut self, text: &str) -> Result<(), Error> {
+        self.system_table
+            .stdout()
+            .write_str(text)
+            .map_err(|_| Error::TextOutput)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn needs_boot_services(&self) -> bool {
+        true
+    }
+}
+
+/// The writer, which writes the log output into the console and the log tail
+struct LogWriter<'a>(&'a mut dyn ConsoleBackend);
+
+impl fmt::Write for LogWriter<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        append_to_log_tail(text.as_bytes());
+        self.0.write_str(text).map_err(|_| fmt::Error)
+    }
+}
+
+/// The logger, which writes the log records into the console
+pub struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        console().is_some()
+    }
+
+    fn log(&self, record: &Record) {
+        // Without console (e.g. after the graphics were released for the kernel handoff), the record
+        // is dropped
+        let Some(console) = console() else {
+            return;
+        };
+
+        let (color, level) = match record.level() {
+            Level::Error => (ConsoleColor::Red, "Error"),
+            Level::Warn => (ConsoleColor::Orange, "Warn"),
+            Level::Info => (ConsoleColor::Green, "Info"),
+            Level::Debug => (ConsoleColor::LightBlue, "Debug"),
+            Level::Trace => (ConsoleColor::DarkBlue, "Trace"),
+        };
+        let mut writer = LogWriter(console);
+        let _ = writer.0.set_color(ConsoleColor::DarkGray);
+        let _ = writer.write_str("[");
+        let _ = writer.0.set_color(color);
+        let _ = writer.write_str(level);
+        let _ = writer.0.set_color(ConsoleColor::DarkGray);
+        let _ = writer.write_str("]");
+        let _ = writer.0.set_color(ConsoleColor::White);
+        let _ = write!(writer, " {}", record.args());
+        let _ = writer.0.flush();
+    }
+
+    fn flush(&self) {}
+}
+
+/// This function returns the backend of the console, if it can be used
+fn console() -> Option<&'static mut dyn ConsoleBackend> {
+    unsafe { CONSOLE.as_deref_mut() }.filter(|console| console.is_available())
+}
+
+/// This function initializes the graphics and installs the logger on the graphical console. If the
+/// graphics can't be initialized, the text console of the firmware is used instead.
+pub fn init_console(system_table: &SystemTable<Boot>) -> Result<(), Error> {
+    set_max_level(log::STATIC_MAX_LEVEL);
+    set_logger(&LOGGER)?;
+
+    match init_graphics(system_table.boot_services()) {
+        Ok(()) => unsafe { CONSOLE = Some(Box::new(GraphicsConsole)) },
+        Err(error) => {
+            let console = TextConsole::new(unsafe { system_table.unsafe_clone() })?;
+            unsafe { CONSOLE = Some(Box::new(console)) };
+            warn!("Unable to initialize Graphics, using the text console => {}\n", error);
+        }
+    }
+    Ok(())
+}
+
+fn init_graphics(boot_services: &BootServices) -> Result<(), Error> {
+    libgraphics::create_context(boot_services)?;
+    libgraphics::text::create_text_writer_context(ascii::FONT_7X14_BOLD)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    libgraphics::swap_buffers()?;
+    Ok(())
+}
+
+/// This function returns, whether the console is drawn with graphics. The boot UI (like the boot
+/// menu) can only be shown on the graphical console.
+pub fn is_graphical() -> bool {
+    unsafe { TEXT_WRITER_CONTEXT.is_some() }
+}
+
+/// This function returns the number of columns and rows of the console
+pub fn size() -> Option<(usize, usize)> {
+    console()?.size().ok()
+}
+
+/// This function writes the text without log prefix into the console
+pub fn print(arguments: fmt::Arguments) {
+    if let Some(console) = console() {
+        let _ = LogWriter(&mut *console).write_fmt(arguments);
+        let _ = console.flush();
+    }
+}
+
+/// This function removes the backend of the console, if it's provided by the Boot Services. The
+/// following log output is dropped.
+pub fn prepare_exit_boot_services() {
+    if unsafe { CONSOLE.as_ref() }.map_or(false, |console| console.needs_boot_services()) {
+        unsafe { CONSOLE = None };
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    error::Error,
+    files::{
+        write_volume_file,
+        SimpleFileSystemContext,
+    },
+    menu::read_key,
+    messages::{
+        self,
+        format_message,
+        Message,
+    },
+    uefi_env,
+};
+use alloc::{
+    format,
+    string::String,
+};
+use core::{
+    fmt::Write,
+    panic::PanicInfo,
+    ptr::NonNull,
+};
+use libcore::{
+    backtrace::backtrace,
+    keymap::{
+        KeyCode,
+        Keymap,
+        US,
+    },
+};
+use libgraphics::{
+    embedded_graphics::{
+        pixelcolor::Rgb888,
+        prelude::RgbColor,
+    },
+    log::log_tail,
+    screenshot::screenshot,
+    text::{
+        self,
+        set_color,
+        set_cursor,
+        text_size,
+        write_str,
+        DARK_GRAY,
+        ORANGE,
+        TEXT_WRITER_CONTEXT,
+    },
+};
+use uefi::{
+    prelude::{
+        Boot,
+        BootServices,
+        SystemTable,
+    },
+    proto::{
+        loaded_image::LoadedImage,
+        media::file::Directory,
+    },
+    Handle,
+};
+
+pub const CRASH_PATH: &str = "\\EFI\\OVERFLOW\\LASTCRASH.TXT";
+const PANIC_LOG_PATH: &str = "\\EFI\\OVERFLOW\\PANIC.LOG";
+const PANIC_SCREENSHOT_PATH: &str = "\\EFI\\OVERFLOW\\PANIC.BMP";
+
+/// The load option `panic=<seconds>`, which sets the timeout of the countdown after a panic
+pub const PANIC_OPTION: &str = "panic";
+pub const DEFAULT_PANIC_TIMEOUT: u64 = 10;
+
+/// The action, which is selected in the countdown after a panic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicAction {
+    Shutdown,
+    Reboot,
+}
+
+/// The volume, on which the crash report is written. It's only set while the Boot Services are
+/// available, because the file system can't be accessed after exiting them.
+static mut CRASH_VOLUME: Option<NonNull<Directory>> = None;
+static mut IMAGE_BASE: u64 = 0;
+
+static mut PANIC_TIMEOUT: u64 = DEFAULT_PANIC_TIMEOUT;
+
+/// This function enables crash reports on the first volume. The image base of the bootloader is
+/// stored, so the addresses of the backtrace can be resolved with the bootloader binary.
+pub fn enable_crash_reports(
+    boot_services: &BootServices, image_handle: Handle, context: &mut SimpleFileSystemContext,
+) {
+    if let Ok(loaded_image) = boot_services.open_protocol_exclusive::<LoadedImage>(image_handle) {
+        unsafe { IMAGE_BASE = loaded_image.info().0 as u64 };
+    }
+    unsafe { CRASH_VOLUME = context.volumes.first_mut().map(NonNull::from) };
+}
+
+/// This function disables crash reports. It must be called before the volumes are closed.
+pub fn disable_crash_reports() {
+    unsafe { CRASH_VOLUME = None };
+}
+
+/// This function writes the panic message, the backtrace and the last 4 KiB of the log into the
+/// crash report. Errors are ignored, because the system is already in an unrecoverable state.
+pub fn write_crash_report(info: &PanicInfo) {
+    let Some(mut volume) = (unsafe { CRASH_VOLUME.take() }) else {
+        return;
+    };
+
+    let image_base = unsafe { IMAGE_BASE };
+    let mut report = String::new();
+    let _ = writeln!(report, "OverflowOS Bootloader v{} Crash Report", env!("CARGO_PKG_VERSION"));
+    if let Some(message) = info.message() {
+        let _ = writeln!(report, "Message: {}", message);
+    }
+    if let Some(location) = info.location() {
+        let _ = writeln!(
+            report,
+            "Location: {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        );
+    }
+
+    let _ = writeln!(report, "\nBacktrace (Image Base: 0x{:X}):", image_base);
+    for (index, address) in backtrace().enumerate() {
+        let _ = writeln!(
+            report,
+            "  #{:<2} 0x{:016X} (Image+0x{:X})",
+            index,
+            address,
+            address.wrapping_sub(image_base)
+        );
+    }
+
+    let (older, newer) = log_tail().contents();
+    report.push_str("\nLog:\n");
+    report.push_str(&String::from_utf8_lossy(older));
+    report.push_str(&String::from_utf8_lossy(newer));
+
+    let _ = write_volume_file(unsafe { volume.as_mut() }, CRASH_PATH, report.as_bytes());
+
+    // The volume is restored, so the diagnostics can be written from the countdown. It isn't
+    // restored, if writing the report panics.
+    unsafe { CRASH_VOLUME = Some(volume) };
+}
+
+/// This function sets the timeout of the countdown after a panic in seconds. A timeout of 0 waits
+/// until the user selects an action.
+pub fn set_panic_timeout(timeout: u64) {
+    unsafe { PANIC_TIMEOUT = timeout };
+}
+
+/// This function writes a screenshot of the screen and the last 4 KiB of the log into the
+/// diagnostics directory of the first volume
+fn write_diagnostics() -> Result<(), Error> {
+    let mut volume = unsafe { CRASH_VOLUME }.ok_or(Error::NoContext)?;
+    let volume = unsafe { volume.as_mut() };
+    let (older, newer) = log_tail().contents();
+    write_volume_file(volume, PANIC_LOG_PATH, &[older, newer].concat())?;
+    write_volume_file(volume, PANIC_SCREENSHOT_PATH, &screenshot()?)
+}
+
+fn draw_countdown(row: usize, text: &str) -> Result<(), Error> {
+    let (columns, _) = text_size()?;
+    set_cursor(0, row)?;
+    set_color(Rgb888::BLACK, ORANGE)?;
+    write_str(&format!("{:<1$}", text, columns.saturating_sub(1)))?;
+    libgraphics::swap_buffers()?;
+    Ok(())
+}
+
+/// This function shows the countdown until the system is shut down after a panic. The user can
+/// cancel the countdown to inspect the screen, reboot immediately or write the diagnostics (a
+/// screenshot and the log) to the first volume. Without console input (after the Boot Services
+/// were exited), the system is shut down immediately. Without graphics, the system is shut down
+/// after the timeout.
+pub fn panic_countdown() -> PanicAction {
+    let Some(mut system_table) = uefi_env::try_boot_system_table() else {
+        return PanicAction::Shutdown;
+    };
+    let Ok((_, row)) = text::cursor() else {
+        // Without graphics, the countdown can't be shown
+        let timeout = unsafe { PANIC_TIMEOUT }.max(1);
+        system_table
+            .boot_services()
+            .stall(timeout as usize * 1_000_000);
+        return PanicAction::Shutdown;
+    };
+
+    let mut status = None;
+    let mut remaining = match unsafe { PANIC_TIMEOUT } {
+        0 => None,
+        timeout => Some(timeout * 10),
+    };
+    loop {
+        let status = status.map_or("", messages::text);
+        let text = match remaining {
+            Some(ticks) => format_message(Message::PanicCountdown, &[&((ticks + 9) / 10), &status]),
+            None => format_message(Message::PanicActions, &[&status]),
+        };
+        let _ = draw_countdown(row, &text);
+
+        match read_key(&mut system_table, &US) {
+            Ok(Some(KeyCode::Escape)) => remaining = None,
+            Ok(Some(KeyCode::Char('r' | 'R'))) => return PanicAction::Reboot,
+            Ok(Some(KeyCode::Char('p' | 'P'))) => return PanicAction::Shutdown,
+            Ok(Some(KeyCode::Char('s' | 'S'))) => {
+                status = match write_diagnostics() {
+                    Ok(()) => Some(Message::DiagnosticsSaved),
+                    Err(_) => Some(Message::DiagnosticsFailed),
+                };
+            }
+            _ => {}
+        }
+
+        match remaining {
+            Some(0) => return PanicAction::Shutdown,
+            Some(ticks) => remaining = Some(ticks - 1),
+            None => {}
+        }
+        system_table.boot_services().stall(100_000);
+    }
+}
+
+/// The action, which can be applied to a report in the report viewer
+pub struct ReportAction {
+    pub key: char,
+    pub description: Message,
+}
+
+pub const DELETE_ACTION: ReportAction = ReportAction {
+    key: 'd',
+    description: Message::DeleteReport,
+};
+
+/// This function shows a report of the last boot (like the crash report or the log). The report is
+/// scrolled with the arrow keys. Returns true, if the user requested the action.
+pub fn show_report(
+    system_table: &mut SystemTable<Boot>, keymap: &Keymap, title: &str, report: &[u8],
+    action: &ReportAction,
+) -> Result<bool, Error> {
+    let report = String::from_utf8_lossy(report);
+    let line_count = report.lines().count();
+    let (_, rows) = text_size()?;
+    let page_size = rows.saturating_sub(4).max(1);
+
+    let mut first_line = 0;
+    loop {
+        draw_report(title, &report, first_line, page_size, action)?;
+        let key = loop {
+            if let Some(key) = read_key(system_table, keymap)? {
+                break key;
+            }
+            system_table.boot_services().stall(10_000);
+        };
+
+        let last_line = line_count.saturating_sub(page_size);
+        match key {
+            KeyCode::Up => first_line = first_line.saturating_sub(1),
+            KeyCode::Down => first_line = (first_line + 1).min(last_line),
+            KeyCode::PageUp => first_line = first_line.saturating_sub(page_size),
+            KeyCode::PageDown => first_line = (first_line + page_size).min(last_line),
+            KeyCode::Char(char) if char.to_ascii_lowercase() == action.key => return Ok(true),
+            KeyCode::Escape | KeyCode::Enter => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+fn draw_report(
+    title: &str, report: &str, first_line: usize, page_size: usize, action: &ReportAction,
+) -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    set_cursor(0, 0)?;
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    writeln!(context, "{}\n", title).unwrap();
+
+    set_color(Rgb888::BLACK, DARK_GRAY)?;
+    for line in report.lines().skip(first_line).take(page_size) {
+        writeln!(context, "{}", line).unwrap();
+    }
+
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    let description = messages::text(action.description);
+    let hint = format_message(Message::ReportHint, &[&action.key.to_ascii_uppercase(), &description]);
+    writeln!(context, "\n{}", hint).unwrap();
+    libgraphics::swap_buffers()?;
+    Ok(())
+}
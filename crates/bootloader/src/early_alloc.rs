@@ -0,0 +1,133 @@
+use crate::error::Error;
+use libcore::address::PhysAddr;
+use uefi::{
+    prelude::BootServices,
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Small allocations are served from chunks of 2 MiB, larger allocations get their own region
+const CHUNK_PAGES: usize = 512;
+const MAX_REGIONS: usize = 64;
+
+pub static mut EARLY_ALLOCATOR: EarlyAllocator = EarlyAllocator::new();
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EarlyRegion {
+    pub address: PhysAddr,
+    pub page_count: usize,
+}
+
+/// The early allocator is a bump allocator for large buffers (like the kernel or the initrd), which
+/// are allocated before the Boot Services are exited. The memory is allocated in whole pages with
+/// `allocate_pages`, so the pool of the firmware isn't fragmented. Every allocated region is
+/// tracked, so the regions can be reserved in the FrameAllocator after exiting the Boot Services.
+pub struct EarlyAllocator {
+    regions: [EarlyRegion; MAX_REGIONS],
+    region_count: usize,
+    bump_address: u64,
+    bump_end: u64,
+    /// The allocator is frozen, when the final memory map is retrieved
+    frozen: bool,
+}
+
+impl EarlyAllocator {
+    pub const fn new() -> Self {
+        Self {
+            regions: [EarlyRegion {
+                address: PhysAddr::NULL,
+                page_count: 0,
+            }; MAX_REGIONS],
+            region_count: 0,
+            bump_address: 0,
+            bump_end: 0,
+            frozen: false,
+        }
+    }
+
+    /// This function allocates a buffer with the specified size and alignment. The alignment must
+    /// not be larger than the page size.
+    pub fn allocate(
+        &mut self, boot_services: &BootServices, size: usize, align: usize,
+    ) -> Result<&'static mut [u8], Error> {
+        if self.frozen {
+            return Err(Error::EarlyAllocatorFrozen);
+        }
+
+        let align = align.max(1) as u64;
+        let address = (self.bump_address + align - 1) & !(align - 1);
+        if self.bump_address != 0 && address + size as u64 <= self.bump_end {
+            self.bump_address = address + size as u64;
+            return Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) });
+        }
+
+        // Allocate a dedicated region for large buffers, otherwise start a new chunk
+        let page_count = ((size as u64 + PAGE_SIZE - 1) / PAGE_SIZE).max(1) as usize;
+        let address = if page_count >= CHUNK_PAGES {
+            self.allocate_region(boot_services, page_count)?
+        } else {
+            let address = self.allocate_region(boot_services, CHUNK_PAGES)?;
+            self.bump_address = address + size as u64;
+            self.bump_end = address + CHUNK_PAGES as u64 * PAGE_SIZE;
+            address
+        };
+        Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) })
+    }
+
+    /// This function registers a region, which was allocated outside of the early allocator (like
+    /// the memory of the kernel image), so it's reserved in the FrameAllocator too.
+    pub fn register_region(&mut self, address: PhysAddr, page_count: usize) -> Result<(), Error> {
+        if self.frozen {
+            return Err(Error::EarlyAllocatorFrozen);
+        }
+
+        let region = self
+            .regions
+            .get_mut(self.region_count)
+            .ok_or(Error::TooManyEarlyRegions)?;
+        *region = EarlyRegion {
+            address,
+            page_count,
+        };
+        self.region_count += 1;
+        Ok(())
+    }
+
+    /// This function rejects all further allocations, so the memory map doesn't change anymore
+    #[inline]
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    #[inline]
+    pub fn regions(&self) -> &[EarlyRegion] {
+        &self.regions[..self.region_count]
+    }
+
+    fn allocate_region(
+        &mut self, boot_services: &BootServices, page_count: usize,
+    ) -> Result<u64, Error> {
+        if self.region_count >= MAX_REGIONS {
+            return Err(Error::TooManyEarlyRegions);
+        }
+
+        let address = boot_services.allocate_pages(
+            AllocateType::AnyPages,
+            MemoryType::LOADER_DATA,
+            page_count,
+        )?;
+        self.register_region(PhysAddr::new(address), page_count)?;
+        Ok(address)
+    }
+}
+
+/// This function allocates a buffer with the global early allocator
+pub fn early_alloc(
+    boot_services: &BootServices, size: usize, align: usize,
+) -> Result<&'static mut [u8], Error> {
+    unsafe { EARLY_ALLOCATOR.allocate(boot_services, size, align) }
+}
@@ -0,0 +1,204 @@
+use crate::{
+    error::Error,
+    menu::read_key,
+    messages::{
+        format_message,
+        text,
+        Message,
+    },
+};
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
+use libcore::keymap::{
+    KeyCode,
+    Keymap,
+};
+use libgraphics::{
+    embedded_graphics::{
+        pixelcolor::Rgb888,
+        prelude::RgbColor,
+    },
+    text::{
+        set_color,
+        set_cursor,
+        write_char,
+        write_str,
+        DARK_GRAY,
+        TEXT_WRITER_CONTEXT,
+    },
+};
+use log::warn;
+use uefi::{
+    cstr16,
+    guid,
+    prelude::{
+        Boot,
+        SystemTable,
+    },
+    table::runtime::{
+        VariableAttributes,
+        VariableVendor,
+    },
+    CStr16,
+};
+
+/// The edited command lines are stored in a non-volatile UEFI variable, so they survive a reboot
+/// into a broken kernel. The entries are separated by a null byte, the newest entry is the first.
+const HISTORY_VARIABLE: &CStr16 = cstr16!("OverflowCmdlineHistory");
+const HISTORY_VENDOR: VariableVendor = VariableVendor(guid!("9a1f4d2e-6c3b-4e8a-b5d7-0f2c8e6a1b93"));
+const HISTORY_SIZE: usize = 8;
+const HISTORY_BUFFER_SIZE: usize = 2048;
+
+/// The command line history, which is navigated with the up and down keys in the editor
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    /// This function reads the history from the UEFI variable. A missing or malformed variable
+    /// results in an empty history.
+    pub fn load(system_table: &SystemTable<Boot>) -> Self {
+        let mut buffer = [0; HISTORY_BUFFER_SIZE];
+        let entries = match system_table.runtime_services().get_variable(
+            HISTORY_VARIABLE,
+            &HISTORY_VENDOR,
+            &mut buffer,
+        ) {
+            Ok((data, _)) => {
+                core::str::from_utf8(data)
+                    .unwrap_or_default()
+                    .split('\0')
+                    .filter(|entry| !entry.is_empty())
+                    .take(HISTORY_SIZE)
+                    .map(String::from)
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        Self { entries }
+    }
+
+    /// This function moves the specified command line to the front of the history and writes the
+    /// history into the UEFI variable.
+    pub fn push(&mut self, system_table: &SystemTable<Boot>, command_line: &str) {
+        if command_line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != command_line);
+        self.entries.insert(0, String::from(command_line));
+        self.entries.truncate(HISTORY_SIZE);
+
+        // Drop the oldest entries, until the history fits into the variable
+        let mut data = self.entries.join("\0");
+        while data.len() > HISTORY_BUFFER_SIZE && self.entries.len() > 1 {
+            self.entries.pop();
+            data = self.entries.join("\0");
+        }
+
+        if let Err(error) = system_table.runtime_services().set_variable(
+            HISTORY_VARIABLE,
+            &HISTORY_VENDOR,
+            VariableAttributes::NON_VOLATILE
+                | VariableAttributes::BOOTSERVICE_ACCESS
+                | VariableAttributes::RUNTIME_ACCESS,
+            data.as_bytes(),
+        ) {
+            warn!("Unable to store command line history => {}\n", error);
+        }
+    }
+}
+
+/// This function opens the line editor for the command line of the specified entry. The cursor is
+/// moved with the arrow keys, Home and End, the history is navigated with the up and down keys.
+/// Returns the edited command line after Enter or `None`, if the editor was closed with Escape.
+pub fn edit_command_line(
+    system_table: &mut SystemTable<Boot>, keymap: &Keymap, title: &str, command_line: &str,
+    history: &History,
+) -> Result<Option<String>, Error> {
+    let mut line = command_line.chars().collect::<Vec<_>>();
+    let mut cursor = line.len();
+    let mut history_index = None;
+    loop {
+        draw_editor(title, &line, cursor, history_index)?;
+        let key = loop {
+            if let Some(key) = read_key(system_table, keymap)? {
+                break key;
+            }
+            system_table.boot_services().stall(10_000);
+        };
+
+        match key {
+            KeyCode::Enter => return Ok(Some(line.into_iter().collect())),
+            KeyCode::Escape => return Ok(None),
+            KeyCode::Left => cursor = cursor.saturating_sub(1),
+            KeyCode::Right => cursor = (cursor + 1).min(line.len()),
+            KeyCode::Home => cursor = 0,
+            KeyCode::End => cursor = line.len(),
+            KeyCode::Backspace if cursor > 0 => {
+                cursor -= 1;
+                line.remove(cursor);
+            }
+            KeyCode::Delete if cursor < line.len() => {
+                line.remove(cursor);
+            }
+            KeyCode::Char(char) if !char.is_control() => {
+                line.insert(cursor, char);
+                cursor += 1;
+            }
+            KeyCode::Up | KeyCode::Down => {
+                // Navigate from the newest to the oldest entry, below the newest entry is the
+                // original command line of the boot entry
+                history_index = match (key, history_index) {
+                    (KeyCode::Up, None) if !history.entries.is_empty() => Some(0),
+                    (KeyCode::Up, Some(index)) if index + 1 < history.entries.len() => {
+                        Some(index + 1)
+                    }
+                    (KeyCode::Down, Some(0)) => None,
+                    (KeyCode::Down, Some(index)) => Some(index - 1),
+                    (_, index) => index,
+                };
+                line = history_index
+                    .map_or(command_line, |index| history.entries[index].as_str())
+                    .chars()
+                    .collect();
+                cursor = line.len();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw_editor(
+    title: &str, line: &[char], cursor: usize, history_index: Option<usize>,
+) -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    set_cursor(0, 0)?;
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    writeln!(context, "{}\n", format_message(Message::EditorTitle, &[&title])).unwrap();
+
+    // Draw the line and highlight the character below the cursor
+    write_str("> ")?;
+    for (index, char) in line.iter().chain(core::iter::once(&' ')).enumerate() {
+        if index == cursor {
+            set_color(Rgb888::WHITE, Rgb888::BLACK)?;
+        } else {
+            set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+        }
+        write_char(*char)?;
+    }
+
+    set_color(Rgb888::BLACK, DARK_GRAY)?;
+    if let Some(history_index) = history_index {
+        let message = format_message(Message::HistoryEntry, &[&(history_index + 1)]);
+        writeln!(context, "\n\n{}", message).unwrap();
+    } else {
+        write_str("\n\n")?;
+    }
+    writeln!(context, "\n{}", text(Message::EditorHint)).unwrap();
+    libgraphics::swap_buffers()?;
+    Ok(())
+}
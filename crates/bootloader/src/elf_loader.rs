@@ -0,0 +1,273 @@
+use crate::{
+    early_alloc::EARLY_ALLOCATOR,
+    error::Error,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::TlsTemplate,
+    elf::{
+        apply_relocation,
+        ElfFile,
+        ET_DYN,
+        ET_EXEC,
+        PT_LOAD,
+        PT_TLS,
+        R_X86_64_NONE,
+        R_X86_64_RELATIVE,
+    },
+    error::Error as CoreError,
+    fastmem,
+    hexdump::HexDump,
+    rng::random_u64,
+};
+use log::{
+    debug,
+    info,
+    warn,
+};
+use uefi::{
+    prelude::BootServices,
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+};
+
+/// The flag, which disables the randomization of the kernel load address
+pub const NOKASLR_OPTION: &str = "nokaslr";
+
+const PAGE_SIZE: u64 = 4096;
+const ELF_HEADER_SIZE: usize = 64;
+
+/// Relocatable kernels are loaded at a random 2 MiB aligned address between 16 MiB and 1 GiB
+const KASLR_WINDOW_START: u64 = 0x100_0000;
+const KASLR_WINDOW_END: u64 = 0x4000_0000;
+const KASLR_ALIGN: u64 = 0x20_0000;
+const KASLR_ATTEMPTS: usize = 16;
+
+pub struct LoadedKernel {
+    pub address: PhysAddr,
+    pub size: u64,
+    pub entry: VirtAddr,
+    pub slide: i64,
+    /// The template of the thread-local storage in the loaded kernel
+    pub tls_template: TlsTemplate,
+}
+
+/// This function loads the loadable segments of the kernel into memory. Executables (`ET_EXEC`) are
+/// loaded at their link address. Position-independent kernels (`ET_DYN`) are loaded at a random
+/// address, if KASLR is enabled, and their relative relocations are applied. The load address keeps
+/// the alignment of the segments.
+pub fn load_kernel(
+    boot_services: &BootServices, data: &[u8], kaslr: bool,
+) -> Result<LoadedKernel, Error> {
+    let elf = ElfFile::parse(data)?;
+    if elf.header.kind != ET_EXEC && elf.header.kind != ET_DYN {
+        return Err(Error::UnsupportedKernelType(elf.header.kind));
+    }
+
+    debug!("Kernel ELF header:\n");
+    for line in HexDump::new(0, &data[..data.len().min(ELF_HEADER_SIZE)]).lines() {
+        debug!("{}\n", line);
+    }
+
+    // Calculate the range of memory, which is used by the loadable segments. The ranges are checked
+    // here again, so the loader doesn't depend on the validation of the ELF parser.
+    let mut start_address = u64::MAX;
+    let mut end_address = 0;
+    let mut alignment = PAGE_SIZE;
+    for segment in elf.program_headers() {
+        let segment = segment?;
+        if segment.kind == PT_LOAD {
+            start_address = start_address.min(segment.virtual_address & !(PAGE_SIZE - 1));
+            let segment_end = segment
+                .virtual_address
+                .checked_add(segment.memory_size)
+                .ok_or(CoreError::ElfOutOfBounds(segment.virtual_address as usize))?;
+            end_address = end_address.max(segment_end);
+            if segment.align.is_power_of_two() {
+                alignment = alignment.max(segment.align);
+            }
+        }
+    }
+
+    if start_address >= end_address {
+        return Err(Error::NoLoadableSegments);
+    }
+    let size = (end_address - start_address)
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(Error::NoLoadableSegments)?;
+    let page_count = (size / PAGE_SIZE) as usize;
+
+    // Allocate memory for the kernel
+    let address = match elf.header.kind {
+        ET_DYN => allocate_relocatable(boot_services, start_address, size, alignment, kaslr)?,
+        _ => {
+            if kaslr {
+                warn!("Kernel isn't position-independent, KASLR is not available\n");
+            }
+            boot_services.allocate_pages(
+                AllocateType::Address(start_address),
+                MemoryType::LOADER_DATA,
+                page_count,
+            )?
+        }
+    };
+    unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(address), page_count)? };
+    let slide = address.wrapping_sub(start_address) as i64;
+
+    // Copy file data of segments into memory, the remaining memory of the segments (like `.bss`) is
+    // zeroed. The TLS template is part of a loadable segment, so only its location is recorded.
+    let image = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size as usize) };
+    fastmem::zero(image);
+    let mut tls_template = TlsTemplate::NONE;
+    for segment in elf.program_headers() {
+        let segment = segment?;
+        match segment.kind {
+            PT_LOAD => {
+                let offset = (segment.virtual_address - start_address) as usize;
+                let segment_data = elf.segment_data(&segment)?;
+                offset
+                    .checked_add(segment_data.len())
+                    .and_then(|end| image.get_mut(offset..end))
+                    .ok_or(CoreError::ElfOutOfBounds(segment.virtual_address as usize))?
+                    .copy_from_slice(segment_data);
+            }
+            PT_TLS => {
+                let template_end = segment.virtual_address.checked_add(segment.file_size);
+                if segment.virtual_address < start_address
+                    || !matches!(template_end, Some(end) if end <= end_address)
+                {
+                    return Err(CoreError::ElfOutOfBounds(segment.virtual_address as usize).into());
+                }
+                tls_template = TlsTemplate {
+                    address: VirtAddr::new(segment.virtual_address.wrapping_add(slide as u64)),
+                    file_size: segment.file_size,
+                    memory_size: segment.memory_size,
+                    alignment: segment.align.max(1),
+                };
+            }
+            _ => {}
+        }
+    }
+    if tls_template.is_present() {
+        info!(
+            "Found TLS template at 0x{:X} ({} bytes, {} bytes initialized)\n",
+            tls_template.address, tls_template.memory_size, tls_template.file_size
+        );
+    }
+
+    // Apply relative relocations with the slide of the kernel
+    if elf.header.kind == ET_DYN {
+        let mut relocation_count = 0;
+        for relocation in elf.dynamic_relocations()? {
+            let relocation = relocation?;
+            match relocation.kind() {
+                R_X86_64_NONE => continue,
+                R_X86_64_RELATIVE => {}
+                kind => return Err(CoreError::UnsupportedRelocation(kind).into()),
+            }
+
+            let offset = relocation
+                .offset
+                .checked_sub(start_address)
+                .ok_or(CoreError::ElfOutOfBounds(relocation.offset as usize))?
+                as usize;
+            apply_relocation(
+                image,
+                offset,
+                relocation.kind(),
+                slide as u64,
+                relocation.addend,
+                address + offset as u64,
+            )?;
+            relocation_count += 1;
+        }
+        info!("Applied {} relocations to the kernel\n", relocation_count);
+    }
+
+    Ok(LoadedKernel {
+        address: PhysAddr::new(address),
+        size,
+        entry: VirtAddr::new(elf.header.entry.wrapping_add(slide as u64)),
+        slide,
+        tls_template,
+    })
+}
+
+/// This function selects the load address of a position-independent kernel. With KASLR, a random
+/// address in the KASLR window is selected. Without KASLR (or if the window is full), the kernel is
+/// loaded at its link address or at any address with the specified alignment.
+fn allocate_relocatable(
+    boot_services: &BootServices, link_address: u64, size: u64, alignment: u64, kaslr: bool,
+) -> Result<u64, Error> {
+    if kaslr {
+        match allocate_random(boot_services, size, alignment) {
+            Ok(address) => return Ok(address),
+            Err(error) => warn!("Unable to randomize the kernel address => {}\n", error),
+        }
+    }
+
+    let page_count = (size / PAGE_SIZE) as usize;
+    if link_address != 0 {
+        if let Ok(address) = boot_services.allocate_pages(
+            AllocateType::Address(link_address),
+            MemoryType::LOADER_DATA,
+            page_count,
+        ) {
+            return Ok(address);
+        }
+    }
+    allocate_aligned(boot_services, size, alignment)
+}
+
+/// This function allocates the specified amount of memory at any address with the specified
+/// alignment. The memory before and after the aligned memory is freed again.
+fn allocate_aligned(boot_services: &BootServices, size: u64, alignment: u64) -> Result<u64, Error> {
+    let padding_pages = (alignment - PAGE_SIZE) / PAGE_SIZE;
+    let memory = boot_services.allocate_pages(
+        AllocateType::AnyPages,
+        MemoryType::LOADER_DATA,
+        (size / PAGE_SIZE + padding_pages) as usize,
+    )?;
+
+    let address = memory.next_multiple_of(alignment);
+    let head_pages = (address - memory) / PAGE_SIZE;
+    if head_pages != 0 {
+        boot_services.free_pages(memory, head_pages as usize)?;
+    }
+    if padding_pages > head_pages {
+        boot_services.free_pages(address + size, (padding_pages - head_pages) as usize)?;
+    }
+    Ok(address)
+}
+
+/// This function allocates the specified amount of memory at a random address in the KASLR window.
+/// If the randomly selected memory is already in use, another address is selected.
+fn allocate_random(boot_services: &BootServices, size: u64, alignment: u64) -> Result<u64, Error> {
+    let alignment = alignment.max(KASLR_ALIGN);
+    let window_start = KASLR_WINDOW_START.next_multiple_of(alignment);
+    let slot_count = KASLR_WINDOW_END
+        .saturating_sub(window_start)
+        .saturating_sub(size)
+        / alignment;
+    if slot_count == 0 {
+        return Err(Error::NoKaslrSlot);
+    }
+
+    for _ in 0..KASLR_ATTEMPTS {
+        let slot = random_u64().ok_or(Error::NoKaslrSlot)? % slot_count;
+        let address = window_start + slot * alignment;
+        if let Ok(address) = boot_services.allocate_pages(
+            AllocateType::Address(address),
+            MemoryType::LOADER_DATA,
+            (size / PAGE_SIZE) as usize,
+        ) {
+            return Ok(address);
+        }
+    }
+    Err(Error::NoKaslrSlot)
+}
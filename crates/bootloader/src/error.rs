@@ -1,3 +1,4 @@
+use alloc::string::String;
 use log::SetLoggerError;
 use thiserror_no_std::Error;
 use uefi::data_types::FromStrError;
@@ -13,9 +14,141 @@ pub enum Error {
     #[error("Logger Error: Unable to set logger")]
     Logger(#[from] SetLoggerError),
 
+    #[error("Console Error: Unable to write to the text output")]
+    TextOutput,
+
+    #[error("Self Test Error: {0}")]
+    SelfTestFailed(&'static str),
+
     #[error("There is no context")]
     NoContext,
 
+    #[error("Core Error: {0}")]
+    Core(#[from] libcore::error::Error),
+
+    #[error("ACPI Error: {0}")]
+    Acpi(#[from] libacpi::error::Error),
+
     #[error("From String Error: {0}")]
     FromStr(#[from] FromStrError),
+
+    #[error(
+        "Netboot Error: Invalid URL '{0}' (expected tftp://<server>/<directory> or \
+         http://<server>/<directory>)"
+    )]
+    InvalidNetbootUrl(String),
+
+    #[error("Netboot Error: The firmware doesn't provide a HTTP boot driver")]
+    NoHttpBoot,
+
+    #[error("Netboot Error: Unable to download '{0}' ({1:?})")]
+    DownloadFailed(String, uefi::Status),
+
+    #[error("Netboot Error: Invalid checksum file for '{0}'")]
+    InvalidChecksum(String),
+
+    #[error("Manifest Error: Invalid digest manifest entry in line {0}")]
+    InvalidManifest(usize),
+
+    #[error("Checksum Error: SHA-256 checksum of '{0}' doesn't match")]
+    ChecksumMismatch(String),
+
+    #[error("Signature Error: No signature for '{0}' in the digest manifest")]
+    MissingSignature(String),
+
+    #[error("Signature Error: Ed25519 signature of '{0}' is invalid")]
+    InvalidSignature(String),
+
+    #[error("Signature Error: The embedded public key is invalid")]
+    InvalidSigningKey,
+
+    #[error("Kernel Error: Unsupported ELF type {0} (expected executable or shared object)")]
+    UnsupportedKernelType(u16),
+
+    #[error("Kernel Error: Kernel has no loadable segments")]
+    NoLoadableSegments,
+
+    #[error("KASLR Error: Unable to find free memory for the kernel")]
+    NoKaslrSlot,
+
+    #[error("Early Allocator Error: Too many memory regions allocated")]
+    TooManyEarlyRegions,
+
+    #[error("Early Allocator Error: Allocations are frozen for exiting the Boot Services")]
+    EarlyAllocatorFrozen,
+
+    #[error("UEFI Error: Unable to exit the Boot Services ({0:?})")]
+    ExitBootServices(uefi::Status),
+
+    #[error("UEFI Error: Unable to set the virtual address map ({0:?})")]
+    SetVirtualAddressMap(uefi::Status),
+
+    #[error("Runtime Error: The runtime memory at 0x{0:X} is outside of the runtime window")]
+    OutsideRuntimeWindow(u64),
+
+    #[error("Runtime Error: The first 512 GiB of the physical memory aren't mapped")]
+    RuntimeWindowUnavailable,
+
+    #[error("Boot Unit Error: No boot entry with the title '{0}' found")]
+    UnknownBootUnit(String),
+
+    #[error("Multiboot2 Error: Address 0x{0:X} isn't below 4 GiB")]
+    Multiboot2AddressTooHigh(u64),
+
+    #[error("Binary Error: The entry of the flat binary has no load address")]
+    NoLoadAddress,
+
+    #[error("Binary Error: Entry offset 0x{0:X} is outside of the binary ({1} bytes)")]
+    EntryOutsideBinary(u64, usize),
+
+    #[error("Binary Error: Unable to allocate {1} bytes at the load address 0x{0:X}")]
+    LoadAddressUnavailable(u64, u64),
+
+    #[error("Linux Error: Kernel isn't a bzImage (setup header not found)")]
+    InvalidLinuxKernel,
+
+    #[error(
+        "Linux Error: Boot protocol 0x{0:X} supports neither the 64-bit EFI handover nor the 64-bit \
+         entry"
+    )]
+    UnsupportedLinuxKernel(u16),
+
+    #[error("Linux Error: Command line has {0} bytes (kernel supports {1} bytes)")]
+    LinuxCommandLineTooLong(usize, usize),
+
+    #[error("Reservation Error: Invalid region '{1}' for '{0}' (expected <size>[@<address>])")]
+    InvalidRegion(String, String),
+
+    #[error("Catalog Error: Invalid entry in line {0} (expected <key> = \"<text>\")")]
+    InvalidCatalog(usize),
+
+    #[error("Stage Error: Stage '{0}' depends on the unknown stage '{1}'")]
+    UnknownStageDependency(&'static str, &'static str),
+
+    #[error("Stage Error: The dependencies of stage '{0}' can't be resolved (circular dependency)")]
+    CircularStageDependencies(&'static str),
+
+    #[error("Stage Error: Stage '{0}' didn't complete")]
+    StageNotCompleted(&'static str),
+
+    #[error("File Error: '{0}' wasn't found on any volume")]
+    FileNotFound(String),
+
+    #[error("File Error: '{0}' doesn't exist")]
+    PathNotFound(String),
+
+    #[error("File Error: '{0}' isn't a regular file")]
+    NotAFile(String),
+
+    #[error("File Error: Unable to write '{0}', the volume is write-protected")]
+    WriteProtected(String),
+
+    #[error("File Error: Unable to write '{0}', the volume is full")]
+    VolumeFull(String),
+
+    #[error("File Error: Access to '{0}' was denied")]
+    AccessDenied(String),
+
+    #[error("File Error: Unable to access '{0}' ({1:?})")]
+    FileAccess(String, uefi::Status),
 }
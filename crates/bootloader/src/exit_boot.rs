@@ -0,0 +1,116 @@
+//! The Boot Services are exited with the final memory map. Every allocation changes the memory map
+//! and invalidates its key, so the buffer of the final memory map is reserved before and the
+//! allocators are frozen, before the memory map is retrieved. If the firmware changes the memory map
+//! between the retrieval and the exit (like in an event), the exit fails and is retried with a new
+//! memory map as described in the UEFI specification.
+
+use crate::{
+    early_alloc::{
+        early_alloc,
+        EARLY_ALLOCATOR,
+    },
+    error::Error,
+    uefi_env,
+};
+use core::ffi::c_void;
+use libcore::memory_map::MemoryMap;
+use uefi::{
+    allocator,
+    prelude::{
+        Boot,
+        BootServices,
+    },
+    table::{
+        Runtime,
+        SystemTable,
+    },
+    Handle,
+    Status,
+};
+
+/// The buffer has room for additional descriptors, because the allocation of the buffer itself and
+/// events of the firmware can split entries of the memory map
+const EXTRA_DESCRIPTORS: usize = 16;
+const MAX_EXIT_ATTEMPTS: usize = 4;
+
+/// The offsets of `GetMemoryMap` and `ExitBootServices` in the Boot Services table. The uefi crate
+/// only exits the Boot Services with a memory map, which is allocated from the pool while exiting.
+const GET_MEMORY_MAP_OFFSET: usize = 0x38;
+const EXIT_BOOT_SERVICES_OFFSET: usize = 0xE8;
+
+type GetMemoryMap = unsafe extern "efiapi" fn(
+    map_size: *mut usize,
+    map: *mut u8,
+    map_key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> Status;
+type ExitBootServices =
+    unsafe extern "efiapi" fn(image_handle: *mut c_void, map_key: usize) -> Status;
+
+/// This function reserves the buffer for the final memory map. It must be called before the
+/// allocators are frozen by [exit_boot_services].
+pub fn reserve_memory_map(boot_services: &BootServices) -> Result<&'static mut [u8], Error> {
+    let size = boot_services.memory_map_size();
+    early_alloc(boot_services, size.map_size + EXTRA_DESCRIPTORS * size.entry_size, 8)
+}
+
+/// This function freezes the allocators, retrieves the final memory map into the reserved buffer
+/// and exits the Boot Services. Nothing may be allocated or logged between the retrieval and the
+/// exit, so the allocators stay frozen. If the Boot Services can't be exited, this function returns
+/// an error and the Boot Services can't be used anymore.
+pub fn exit_boot_services(
+    system_table: SystemTable<Boot>, image_handle: Handle, mut buffer: &'static mut [u8],
+) -> Result<(SystemTable<Runtime>, MemoryMap<'static>), Error> {
+    let table = system_table.boot_services() as *const BootServices as *const u8;
+    let (get_memory_map, exit): (GetMemoryMap, ExitBootServices) = unsafe {
+        (
+            *(table.add(GET_MEMORY_MAP_OFFSET) as *const GetMemoryMap),
+            *(table.add(EXIT_BOOT_SERVICES_OFFSET) as *const ExitBootServices),
+        )
+    };
+
+    // Allocations with the global allocator fail from here on instead of changing the memory map
+    unsafe { EARLY_ALLOCATOR.freeze() };
+    allocator::exit_boot_services();
+    uefi_env::invalidate_boot_services();
+
+    let mut status = Status::ABORTED;
+    for _ in 0..MAX_EXIT_ATTEMPTS {
+        let mut map_size = buffer.len();
+        let mut map_key = 0;
+        let mut descriptor_size = 0;
+        let mut descriptor_version = 0;
+        status = unsafe {
+            get_memory_map(
+                &mut map_size,
+                buffer.as_mut_ptr(),
+                &mut map_key,
+                &mut descriptor_size,
+                &mut descriptor_version,
+            )
+        };
+
+        // The buffer can't be enlarged without an allocation, so a too small buffer is fatal
+        if status != Status::SUCCESS {
+            break;
+        }
+
+        status = unsafe { exit(image_handle.as_ptr(), map_key) };
+        if status == Status::SUCCESS {
+            let mut memory_map =
+                MemoryMap::new(&mut core::mem::take(&mut buffer)[..map_size], descriptor_size)?;
+            memory_map.sort();
+            let system_table =
+                unsafe { SystemTable::<Runtime>::from_ptr(system_table.as_ptr() as *mut c_void) }
+                    .ok_or(Error::ExitBootServices(Status::INVALID_PARAMETER))?;
+            return Ok((system_table, memory_map));
+        }
+
+        // The memory map key is outdated, if the firmware changed the memory map after the retrieval
+        if status != Status::INVALID_PARAMETER {
+            break;
+        }
+    }
+    Err(Error::ExitBootServices(status))
+}
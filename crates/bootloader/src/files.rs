@@ -1,32 +1,178 @@
-use crate::error::Error;
-use alloc::vec::Vec;
-use log::info;
+use crate::{
+    early_alloc::early_alloc,
+    error::Error,
+};
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    vec::Vec,
+};
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+};
+use libcore::cmdline::CommandLine;
+use log::{
+    info,
+    warn,
+};
 use uefi::{
     prelude::BootServices,
-    proto::media::{
-        file::{
-            Directory,
-            File,
-            FileAttribute,
-            FileInfo,
-            FileMode,
+    proto::{
+        device_path::{
+            DevicePath,
+            DeviceSubType,
+            DeviceType,
+        },
+        media::{
+            file::{
+                Directory,
+                File,
+                FileAttribute,
+                FileInfo,
+                FileMode,
+                FileSystemInfo,
+                FileSystemVolumeLabel,
+                RegularFile,
+            },
+            fs::SimpleFileSystem,
         },
-        fs::SimpleFileSystem,
     },
     table::boot::{
-        MemoryType,
+        OpenProtocolAttributes,
+        OpenProtocolParams,
         ScopedProtocol,
         SearchType,
     },
     CString16,
+    Guid,
+    Handle,
     Identify,
+    Status,
 };
 
+pub const KERNEL_PATH: &str = "\\EFI\\BOOT\\KERNEL.ELF";
+pub const INITRD_PATH: &str = "\\EFI\\BOOT\\INITRD.TAR";
+pub const KERNEL_FILE_NAME: &str = "KERNEL.ELF";
+pub const INITRD_FILE_NAME: &str = "INITRD.TAR";
+
+/// The load options `volume=<label>` and `partuuid=<guid>`, which select the volume with the boot
+/// files. Without these options, all volumes are searched.
+pub const VOLUME_OPTION: &str = "volume";
+pub const PARTUUID_OPTION: &str = "partuuid";
+
+/// The signature type of a GPT partition in the hard drive node of a device path
+const GPT_SIGNATURE_TYPE: u8 = 0x02;
+
+const MIB: u64 = 1024 * 1024;
+
 pub(crate) struct SimpleFileSystemContext<'a> {
     pub(crate) volumes: Vec<Directory>,
+    /// The label, the size and the partition of the volumes, in the same order as the volumes
+    pub(crate) volume_infos: Vec<VolumeInfo>,
+    /// The volume, which was selected in the boot menu. It's searched before the other volumes.
+    pub(crate) preferred_volume: Option<usize>,
     pub(crate) boot_services: &'a BootServices,
 }
 
+/// The label and the size of a volume and the GUID of its GPT partition, if the volume is on a GPT
+/// partition
+#[derive(Clone, Debug)]
+pub(crate) struct VolumeInfo {
+    pub(crate) label: String,
+    /// The size and the free space of the volume in bytes
+    pub(crate) size: u64,
+    pub(crate) free_space: u64,
+    pub(crate) read_only: bool,
+    pub(crate) partition_guid: Option<Guid>,
+}
+
+impl VolumeInfo {
+    /// This function queries the label and the size of the volume. Some firmware only reports the
+    /// label with the file system information, so it's used if the volume label is empty.
+    fn query(volume: &mut Directory, partition_guid: Option<Guid>) -> Self {
+        let file_system_info = volume.get_boxed_info::<FileSystemInfo>().ok();
+        let label = volume
+            .get_boxed_info::<FileSystemVolumeLabel>()
+            .map(|label| label.volume_label().to_string())
+            .ok()
+            .filter(|label| !label.is_empty())
+            .or_else(|| {
+                file_system_info
+                    .as_ref()
+                    .map(|info| info.volume_label().to_string())
+            })
+            .unwrap_or_default();
+        let info = file_system_info.as_deref();
+        Self {
+            label,
+            size: info.map_or(0, FileSystemInfo::volume_size),
+            free_space: info.map_or(0, FileSystemInfo::free_space),
+            read_only: info.is_some_and(FileSystemInfo::read_only),
+            partition_guid,
+        }
+    }
+}
+
+impl Display for VolumeInfo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        let label = match self.label.is_empty() {
+            true => "<no label>",
+            false => &self.label,
+        };
+        write!(
+            formatter,
+            "{} ({} MiB, {} MiB free{})",
+            label,
+            self.size / MIB,
+            self.free_space / MIB,
+            if self.read_only { ", read-only" } else { "" }
+        )?;
+        match self.partition_guid {
+            Some(guid) => write!(formatter, " on partition {}", guid),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The filter, which selects the volumes that are searched for a file
+#[derive(Clone, Copy, Debug)]
+pub enum VolumeFilter<'a> {
+    Any,
+    /// The label of the volume, which is compared case-insensitive
+    Label(&'a str),
+    /// The unique GUID of the GPT partition (like `partuuid=` of Linux)
+    PartitionGuid(&'a str),
+}
+
+impl<'a> VolumeFilter<'a> {
+    /// This function creates the filter from the `volume` and `partuuid` load options. If both
+    /// options are specified, the partition GUID is used.
+    pub fn from_command_line(command_line: &CommandLine<'a>) -> Self {
+        match (command_line.get(PARTUUID_OPTION), command_line.get(VOLUME_OPTION)) {
+            (Some(guid), _) => Self::PartitionGuid(guid),
+            (None, Some(label)) => Self::Label(label),
+            (None, None) => Self::Any,
+        }
+    }
+
+    fn matches(&self, info: &VolumeInfo) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Label(label) => info.label.eq_ignore_ascii_case(label),
+            Self::PartitionGuid(guid) => {
+                info.partition_guid.is_some_and(|partition_guid| {
+                    format!("{}", partition_guid).eq_ignore_ascii_case(guid)
+                })
+            }
+        }
+    }
+}
+
 pub fn init_file_system_driver<'a>(
     boot_services: &BootServices,
 ) -> Result<SimpleFileSystemContext, Error> {
@@ -34,26 +180,73 @@ pub fn init_file_system_driver<'a>(
     let handle_buffer =
         boot_services.locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))?;
     let mut volumes = Vec::new();
+    let mut volume_infos = Vec::new();
 
     // Enumerate handles and acquire directories
     for (i, handle) in handle_buffer.iter().enumerate() {
         // Get protocol and open volumes to directory
         let mut protocol: ScopedProtocol<SimpleFileSystem> =
             boot_services.open_protocol_exclusive(*handle)?;
-        let directory = protocol.open_volume()?;
+        let mut directory = protocol.open_volume()?;
+        let volume_info = VolumeInfo::query(&mut directory, partition_guid(boot_services, *handle));
 
         // Notify user and and push directory into volumes vector
-        info!("Successfully opened File System Protocol #{} and acquired volume handle\n", i + 1);
+        info!("Successfully opened File System Protocol #{} => {}\n", i + 1, volume_info);
         volumes.push(directory);
+        volume_infos.push(volume_info);
     }
 
     // Create file system context
     Ok(SimpleFileSystemContext {
         volumes,
+        volume_infos,
+        preferred_volume: None,
         boot_services,
     })
 }
 
+/// This function returns the index of the volume, which contains the specified file. The volume,
+/// which was selected in the boot menu, is searched first, followed by the volumes that match the
+/// filter in order. If the file isn't found on these volumes, a warning is logged and all volumes
+/// are searched, so a wrong label doesn't prevent the boot. If no volume contains the file, a
+/// [Error::FileNotFound] error is returned.
+pub fn find_file(
+    context: &mut SimpleFileSystemContext, file_name: &str, filter: VolumeFilter,
+) -> Result<usize, Error> {
+    let preferred = context
+        .preferred_volume
+        .filter(|index| contains_file(context, *index, file_name));
+    let search = |context: &mut SimpleFileSystemContext, filter: VolumeFilter| {
+        (0..context.volumes.len()).find(|index| {
+            filter.matches(&context.volume_infos[*index]) && contains_file(context, *index, file_name)
+        })
+    };
+
+    let index = match preferred.or_else(|| search(context, filter)) {
+        Some(index) => index,
+        None if !matches!(filter, VolumeFilter::Any) => {
+            warn!("'{}' not found on volume {:?}, searching all volumes\n", file_name, filter);
+            search(context, VolumeFilter::Any)
+                .ok_or_else(|| Error::FileNotFound(file_name.to_string()))?
+        }
+        None => return Err(Error::FileNotFound(file_name.to_string())),
+    };
+    info!("Found '{}' on volume #{} => {}\n", file_name, index + 1, context.volume_infos[index]);
+    Ok(index)
+}
+
+/// This function returns, whether the volume with the specified index contains the file
+pub fn contains_file(context: &mut SimpleFileSystemContext, index: usize, file_name: &str) -> bool {
+    let Ok(path) = CString16::try_from(file_name) else {
+        return false;
+    };
+    context.volumes.get_mut(index).is_some_and(|volume| {
+        volume
+            .open(path.as_ref(), FileMode::Read, FileAttribute::empty())
+            .is_ok()
+    })
+}
+
 pub fn read_file<'a>(
     context: &mut SimpleFileSystemContext, index: usize, file_name: &str,
 ) -> Result<&'a mut [u8], Error> {
@@ -62,19 +255,178 @@ pub fn read_file<'a>(
         .volumes
         .get_mut(index)
         .unwrap()
-        .open(CString16::try_from(file_name)?.as_ref(), FileMode::Read, FileAttribute::empty())?
+        .open(CString16::try_from(file_name)?.as_ref(), FileMode::Read, FileAttribute::empty())
+        .map_err(|error| file_error(file_name, error))?
         .into_regular_file()
-        .unwrap();
+        .ok_or_else(|| Error::NotAFile(file_name.to_string()))?;
 
-    // Create buffer in size of file
-    let info = handle.get_boxed_info::<FileInfo>().unwrap();
-    let buffer = context
-        .boot_services
-        .allocate_pool(MemoryType::LOADER_DATA, info.file_size() as usize)
-        .unwrap();
-    let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, info.file_size() as usize) };
+    // Create buffer in size of file with the early allocator
+    let info = handle
+        .get_boxed_info::<FileInfo>()
+        .map_err(|error| file_error(file_name, error))?;
+    let buffer = early_alloc(context.boot_services, info.file_size() as usize, 1)?;
 
     // Read file
-    handle.read(buffer)?;
+    handle
+        .read(buffer)
+        .map_err(|error| file_error(file_name, error.to_err_without_payload()))?;
     Ok(buffer)
 }
+
+/// This function writes the data into the specified file. Missing parent directories are created
+/// and an existing file is replaced.
+pub fn write_file(
+    context: &mut SimpleFileSystemContext, index: usize, file_name: &str, data: &[u8],
+) -> Result<(), Error> {
+    write_volume_file(context.volumes.get_mut(index).unwrap(), file_name, data)
+}
+
+/// This function appends the data to the specified file. Missing parent directories and a missing
+/// file are created.
+pub fn append_file(
+    context: &mut SimpleFileSystemContext, index: usize, file_name: &str, data: &[u8],
+) -> Result<(), Error> {
+    append_volume_file(context.volumes.get_mut(index).unwrap(), file_name, data)
+}
+
+/// This function returns the size of the specified file or `None`, if the file doesn't exist
+pub fn file_size(
+    context: &mut SimpleFileSystemContext, index: usize, file_name: &str,
+) -> Option<u64> {
+    let path = CString16::try_from(file_name).ok()?;
+    let mut handle = context
+        .volumes
+        .get_mut(index)?
+        .open(path.as_ref(), FileMode::Read, FileAttribute::empty())
+        .ok()?;
+    Some(handle.get_boxed_info::<FileInfo>().ok()?.file_size())
+}
+
+pub fn delete_file(
+    context: &mut SimpleFileSystemContext, index: usize, file_name: &str,
+) -> Result<(), Error> {
+    context
+        .volumes
+        .get_mut(index)
+        .unwrap()
+        .open(CString16::try_from(file_name)?.as_ref(), FileMode::ReadWrite, FileAttribute::empty())
+        .map_err(|error| file_error(file_name, error))?
+        .delete()
+        .map_err(|error| file_error(file_name, error))
+}
+
+pub(crate) fn write_volume_file(
+    volume: &mut Directory, file_name: &str, data: &[u8],
+) -> Result<(), Error> {
+    create_parent_directories(volume, file_name)?;
+
+    // Delete the existing file, so no old content remains after the new content
+    let path = CString16::try_from(file_name)?;
+    if let Ok(handle) = volume.open(path.as_ref(), FileMode::ReadWrite, FileAttribute::empty()) {
+        handle
+            .delete()
+            .map_err(|error| file_error(file_name, error))?;
+    }
+
+    let mut handle = open_regular_file(volume, file_name)?;
+    write_regular_file(&mut handle, file_name, data)
+}
+
+pub(crate) fn append_volume_file(
+    volume: &mut Directory, file_name: &str, data: &[u8],
+) -> Result<(), Error> {
+    create_parent_directories(volume, file_name)?;
+    let mut handle = open_regular_file(volume, file_name)?;
+    handle
+        .set_position(RegularFile::END_OF_FILE)
+        .map_err(|error| file_error(file_name, error))?;
+    write_regular_file(&mut handle, file_name, data)
+}
+
+/// This function creates the missing parent directories of the file. The first separator is the
+/// root directory.
+fn create_parent_directories(volume: &mut Directory, file_name: &str) -> Result<(), Error> {
+    for (position, _) in file_name.match_indices('\\').skip(1) {
+        let directory = &file_name[..position];
+        volume
+            .open(
+                CString16::try_from(directory)?.as_ref(),
+                FileMode::CreateReadWrite,
+                FileAttribute::DIRECTORY,
+            )
+            .map_err(|error| file_error(directory, error))?;
+    }
+    Ok(())
+}
+
+/// This function opens the file for writing or creates it, if it doesn't exist
+fn open_regular_file(volume: &mut Directory, file_name: &str) -> Result<RegularFile, Error> {
+    volume
+        .open(
+            CString16::try_from(file_name)?.as_ref(),
+            FileMode::CreateReadWrite,
+            FileAttribute::empty(),
+        )
+        .map_err(|error| file_error(file_name, error))?
+        .into_regular_file()
+        .ok_or_else(|| Error::NotAFile(file_name.to_string()))
+}
+
+/// This function maps the not-found errors of an optional file to `None`, so the file can be missing
+/// while other errors (like I/O errors or checksum mismatches) are still returned
+pub fn optional_file<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(data) => Ok(Some(data)),
+        Err(Error::FileNotFound(_) | Error::PathNotFound(_)) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// This function writes the data at the current position of the file and flushes the file, so the
+/// data is on the volume, even if the volume isn't closed (like after a panic)
+fn write_regular_file(handle: &mut RegularFile, file_name: &str, data: &[u8]) -> Result<(), Error> {
+    handle
+        .write(data)
+        .map_err(|error| file_error(file_name, error.to_err_without_payload()))?;
+    handle.flush().map_err(|error| file_error(file_name, error))
+}
+
+/// This function maps the errors of the firmware, which are caused by the volume (like a full or
+/// write-protected volume), to errors with the path of the file
+fn file_error(file_name: &str, error: uefi::Error) -> Error {
+    let file_name = file_name.to_string();
+    match error.status() {
+        Status::WRITE_PROTECTED => Error::WriteProtected(file_name),
+        Status::VOLUME_FULL => Error::VolumeFull(file_name),
+        Status::ACCESS_DENIED => Error::AccessDenied(file_name),
+        Status::NOT_FOUND => Error::PathNotFound(file_name),
+        status => Error::FileAccess(file_name, status),
+    }
+}
+
+/// This function returns the unique GUID of the GPT partition, which the volume of the handle is
+/// stored on. The GUID is read from the hard drive node of the device path.
+fn partition_guid(boot_services: &BootServices, handle: Handle) -> Option<Guid> {
+    let device_path = unsafe {
+        boot_services.open_protocol::<DevicePath>(
+            OpenProtocolParams {
+                handle,
+                agent: boot_services.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .ok()?;
+
+    // The node data is the partition number, start and size followed by the signature, the MBR type
+    // and the signature type
+    let node = device_path.node_iter().find(|node| {
+        node.device_type() == DeviceType::MEDIA && node.sub_type() == DeviceSubType::MEDIA_HARD_DRIVE
+    })?;
+    let data = node.data();
+    if data.get(37) != Some(&GPT_SIGNATURE_TYPE) {
+        return None;
+    }
+    Some(Guid::from_bytes(data[20..36].try_into().ok()?))
+}
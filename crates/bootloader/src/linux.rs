@@ -0,0 +1,431 @@
+use crate::{
+    early_alloc::{
+        early_alloc,
+        EARLY_ALLOCATOR,
+    },
+    elf_loader::LoadedKernel,
+    error::Error,
+};
+use core::{
+    arch::global_asm,
+    ffi::c_void,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::{
+        BootInfo,
+        PixelFormat,
+        TlsTemplate,
+    },
+    fastmem,
+    memory_map::MemoryMap,
+};
+use log::{
+    info,
+    warn,
+};
+use uefi::{
+    prelude::{
+        Boot,
+        BootServices,
+        SystemTable,
+    },
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+    Handle,
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// The size of the zero page (`struct boot_params`), which contains the setup header
+const BOOT_PARAMS_SIZE: usize = 4096;
+const SETUP_HEADER_MAGIC: &[u8; 4] = b"HdrS";
+
+/// The offsets of the fields of the setup header in the bzImage and in the zero page
+const SETUP_SECTS: usize = 0x1F1;
+const SETUP_HEADER_START: usize = 0x1F1;
+const SETUP_HEADER_LENGTH: usize = 0x201;
+const HEADER: usize = 0x202;
+const VERSION: usize = 0x206;
+const TYPE_OF_LOADER: usize = 0x210;
+const CODE32_START: usize = 0x214;
+const RAMDISK_IMAGE: usize = 0x218;
+const RAMDISK_SIZE: usize = 0x21C;
+const CMD_LINE_PTR: usize = 0x228;
+const KERNEL_ALIGNMENT: usize = 0x230;
+const XLOADFLAGS: usize = 0x236;
+const CMDLINE_SIZE: usize = 0x238;
+const PREF_ADDRESS: usize = 0x258;
+const INIT_SIZE: usize = 0x260;
+const HANDOVER_OFFSET: usize = 0x264;
+
+/// The upper 32 bits of the initrd and command line addresses in the zero page
+const EXT_RAMDISK_IMAGE: usize = 0x0C0;
+const EXT_RAMDISK_SIZE: usize = 0x0C4;
+const EXT_CMD_LINE_PTR: usize = 0x0C8;
+
+/// The offsets of the screen information, the RSDP and the E820 table in the zero page
+const ORIG_VIDEO_IS_VGA: usize = 0x00F;
+const LFB_WIDTH: usize = 0x012;
+const LFB_HEIGHT: usize = 0x014;
+const LFB_DEPTH: usize = 0x016;
+const LFB_BASE: usize = 0x018;
+const LFB_SIZE: usize = 0x01C;
+const LFB_LINE_LENGTH: usize = 0x024;
+const LFB_FIELDS: usize = 0x026;
+const CAPABILITIES: usize = 0x036;
+const EXT_LFB_BASE: usize = 0x03A;
+const ACPI_RSDP_ADDR: usize = 0x070;
+const E820_ENTRIES: usize = 0x1E8;
+const E820_TABLE: usize = 0x2D0;
+const E820_MAX_ENTRIES: usize = 128;
+const E820_ENTRY_SIZE: usize = 20;
+
+const E820_RAM: u32 = 1;
+const E820_RESERVED: u32 = 2;
+const E820_ACPI: u32 = 3;
+const E820_NVS: u32 = 4;
+const E820_UNUSABLE: u32 = 5;
+
+const VIDEO_TYPE_EFI: u8 = 0x70;
+const VIDEO_CAPABILITY_64BIT_BASE: u32 = 1 << 1;
+
+/// The boot protocol 2.11 is needed for the handover offset. Kernels without the 64-bit EFI handover
+/// entry are entered at the 64-bit entry after exiting the Boot Services.
+const MIN_VERSION: u16 = 0x020B;
+const XLF_KERNEL_64: u16 = 1 << 0;
+const XLF_CAN_BE_LOADED_ABOVE_4G: u16 = 1 << 1;
+const XLF_EFI_HANDOVER_64: u16 = 1 << 3;
+const LOADER_TYPE_UNDEFINED: u8 = 0xFF;
+
+/// The values of a Linux kernel, which are needed to enter the EFI stub or the 64-bit entry
+pub struct LinuxKernel {
+    /// The 64-bit EFI handover entry or the 64-bit entry of the kernel
+    pub entry: u64,
+    /// Whether the kernel is entered with the EFI handover protocol before exiting the Boot Services
+    pub handover: bool,
+    /// The zero page with the setup header, the command line and the initrd
+    pub boot_params: &'static mut [u8],
+}
+
+extern "sysv64" {
+    fn linux_trampoline(entry: u64, boot_params: u64, stack_top: u64) -> !;
+}
+
+// The 64-bit entry expects the boot GDT of Linux with the flat code segment at 0x10 and the flat
+// data segment at 0x18, disabled interrupts and the zero page in RSI. The trampoline loads the GDT,
+// reloads the segments, switches to the stack and jumps to the entry.
+global_asm!(
+    ".global linux_trampoline",
+    "linux_trampoline:",
+    "cli",
+    "cld",
+    "lgdt [rip + linux_gdt_pointer]",
+    "mov ax, 0x18",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "xor eax, eax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov rsp, rdx",
+    "lea rax, [rip + linux_long_mode]",
+    "push 0x10",
+    "push rax",
+    "retfq",
+    "linux_long_mode:",
+    "xor ebp, ebp",
+    "jmp rdi",
+    ".balign 8",
+    "linux_gdt:",
+    ".quad 0",
+    ".quad 0",
+    ".quad 0x00AF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    "linux_gdt_pointer:",
+    ".word 31",
+    ".quad linux_gdt",
+);
+
+/// This function loads the protected-mode code of a bzImage at the preferred address or at an
+/// address with the alignment of the kernel and creates the zero page with the command line and
+/// the initrd. The kernel must support the 64-bit EFI handover protocol or the 64-bit entry.
+pub fn load_linux_kernel(
+    boot_services: &BootServices, data: &[u8], command_line: &str, initrd: Option<&[u8]>,
+) -> Result<(LoadedKernel, LinuxKernel), Error> {
+    if data.get(HEADER..HEADER + 4) != Some(&SETUP_HEADER_MAGIC[..]) {
+        return Err(Error::InvalidLinuxKernel);
+    }
+
+    let version = read_u16(data, VERSION)?;
+    let xloadflags = read_u16(data, XLOADFLAGS)?;
+    if version < MIN_VERSION || xloadflags & (XLF_EFI_HANDOVER_64 | XLF_KERNEL_64) == 0 {
+        return Err(Error::UnsupportedLinuxKernel(version));
+    }
+    let handover = xloadflags & XLF_EFI_HANDOVER_64 != 0;
+
+    // The protected-mode code follows the boot sector and the setup sectors (0 means 4 sectors)
+    let setup_sectors = match data[SETUP_SECTS] {
+        0 => 4,
+        sectors => sectors as usize,
+    };
+    let kernel_data = data
+        .get((setup_sectors + 1) * 512..)
+        .ok_or(Error::InvalidLinuxKernel)?;
+    let size = (kernel_data.len() as u64).max(read_u32(data, INIT_SIZE)? as u64);
+    let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let address = allocate_kernel(
+        boot_services,
+        size,
+        read_u64(data, PREF_ADDRESS)?,
+        (read_u32(data, KERNEL_ALIGNMENT)? as u64).max(PAGE_SIZE),
+        match xloadflags & XLF_CAN_BE_LOADED_ABOVE_4G {
+            0 => 0xFFFF_FFFF,
+            _ => u64::MAX,
+        },
+    )?;
+    let image = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size as usize) };
+    fastmem::zero(image);
+    fastmem::copy(&mut image[..kernel_data.len()], kernel_data);
+
+    // Copy the setup header into the zero page and fill the fields of the bootloader
+    let boot_params = early_alloc(boot_services, BOOT_PARAMS_SIZE, PAGE_SIZE as usize)?;
+    fastmem::zero(boot_params);
+    let header_end = HEADER + data[SETUP_HEADER_LENGTH] as usize;
+    fastmem::copy(
+        &mut boot_params[SETUP_HEADER_START..header_end],
+        data.get(SETUP_HEADER_START..header_end)
+            .ok_or(Error::InvalidLinuxKernel)?,
+    );
+    boot_params[TYPE_OF_LOADER] = LOADER_TYPE_UNDEFINED;
+    write_u32(boot_params, CODE32_START, address as u32);
+
+    let max_command_line = read_u32(data, CMDLINE_SIZE)? as usize;
+    if command_line.len() > max_command_line {
+        return Err(Error::LinuxCommandLineTooLong(command_line.len(), max_command_line));
+    }
+    let command_line_buffer = early_alloc(boot_services, command_line.len() + 1, 1)?;
+    command_line_buffer[..command_line.len()].copy_from_slice(command_line.as_bytes());
+    command_line_buffer[command_line.len()] = 0;
+    let command_line_address = command_line_buffer.as_ptr() as u64;
+    write_u32(boot_params, CMD_LINE_PTR, command_line_address as u32);
+    write_u32(boot_params, EXT_CMD_LINE_PTR, (command_line_address >> 32) as u32);
+
+    if let Some(initrd) = initrd {
+        let initrd_address = initrd.as_ptr() as u64;
+        write_u32(boot_params, RAMDISK_IMAGE, initrd_address as u32);
+        write_u32(boot_params, EXT_RAMDISK_IMAGE, (initrd_address >> 32) as u32);
+        write_u32(boot_params, RAMDISK_SIZE, initrd.len() as u32);
+        write_u32(boot_params, EXT_RAMDISK_SIZE, (initrd.len() as u64 >> 32) as u32);
+    }
+
+    // The 64-bit entry is located 512 bytes after the 32-bit entry, the handover entry is relative
+    // to the 64-bit entry
+    let entry = match handover {
+        true => address + 0x200 + read_u32(data, HANDOVER_OFFSET)? as u64,
+        false => address + 0x200,
+    };
+    info!(
+        "Loaded Linux kernel at 0x{:X} (Boot Protocol: {}.{}, {}: 0x{:X})\n",
+        address,
+        version >> 8,
+        version & 0xFF,
+        if handover { "Handover" } else { "64-bit Entry" },
+        entry
+    );
+
+    let kernel = LoadedKernel {
+        address: PhysAddr::new(address),
+        size,
+        entry: VirtAddr::new(entry),
+        slide: 0,
+        tls_template: TlsTemplate::NONE,
+    };
+    Ok((
+        kernel,
+        LinuxKernel {
+            entry,
+            handover,
+            boot_params,
+        },
+    ))
+}
+
+/// This function enters the EFI stub of the kernel with the handover protocol. The Boot Services
+/// must not be exited, the EFI stub exits them after reading the memory map.
+pub fn enter_kernel(
+    kernel: LinuxKernel, image_handle: Handle, system_table: &SystemTable<Boot>,
+) -> ! {
+    let handover: extern "sysv64" fn(*mut c_void, *const c_void, *mut u8) -> ! =
+        unsafe { core::mem::transmute(kernel.entry) };
+    handover(image_handle.as_ptr(), system_table.as_ptr(), kernel.boot_params.as_mut_ptr())
+}
+
+/// This function writes the final memory map as E820 table, the RSDP and the framebuffer into the
+/// zero page. The loader memory (the kernel, the initrd and the zero page) is passed as usable RAM,
+/// because the kernel reserves these regions itself.
+pub fn write_boot_params(kernel: &mut LinuxKernel, boot_info: &BootInfo, memory_map: &MemoryMap) {
+    let boot_params = &mut *kernel.boot_params;
+    write_u64(boot_params, ACPI_RSDP_ADDR, boot_info.rsdp_address.as_u64());
+
+    // Adjacent descriptors with the same type are merged, because the table has only 128 entries
+    let mut count = 0;
+    let mut dropped = 0;
+    let mut push_entry = |(start, end, ty): (u64, u64, u32)| {
+        if count == E820_MAX_ENTRIES {
+            dropped += 1;
+            return;
+        }
+        let offset = E820_TABLE + count * E820_ENTRY_SIZE;
+        write_u64(boot_params, offset, start);
+        write_u64(boot_params, offset + 8, end - start);
+        write_u32(boot_params, offset + 16, ty);
+        count += 1;
+    };
+    let mut current: Option<(u64, u64, u32)> = None;
+    for descriptor in memory_map.entries() {
+        let start = descriptor.phys_start;
+        let end = start + descriptor.page_count * PAGE_SIZE;
+        let ty = e820_type(descriptor.ty);
+        current = match current {
+            Some((current_start, current_end, current_ty))
+                if current_end == start && current_ty == ty =>
+            {
+                Some((current_start, end, ty))
+            }
+            Some(entry) => {
+                push_entry(entry);
+                Some((start, end, ty))
+            }
+            None => Some((start, end, ty)),
+        };
+    }
+    if let Some(entry) = current {
+        push_entry(entry);
+    }
+    if dropped > 0 {
+        warn!("{} memory regions don't fit into the E820 table of the kernel\n", dropped);
+    }
+    boot_params[E820_ENTRIES] = count as u8;
+
+    let framebuffer = &boot_info.framebuffer;
+    if !framebuffer.address.is_null() {
+        // The sizes and positions of the red, green, blue and reserved fields
+        let (depth, fields): (u16, [u8; 8]) = match framebuffer.pixel_format {
+            PixelFormat::Xrgb8888 => (32, [8, 16, 8, 8, 8, 0, 8, 24]),
+            PixelFormat::Xbgr8888 => (32, [8, 0, 8, 8, 8, 16, 8, 24]),
+            PixelFormat::Rgb888Packed => (24, [8, 16, 8, 8, 8, 0, 0, 0]),
+            PixelFormat::Rgb565 => (16, [5, 11, 6, 5, 5, 0, 0, 0]),
+        };
+        let address = framebuffer.address.as_u64();
+        boot_params[ORIG_VIDEO_IS_VGA] = VIDEO_TYPE_EFI;
+        write_u16(boot_params, LFB_WIDTH, framebuffer.width as u16);
+        write_u16(boot_params, LFB_HEIGHT, framebuffer.height as u16);
+        write_u16(boot_params, LFB_DEPTH, depth);
+        write_u32(boot_params, LFB_BASE, address as u32);
+        write_u32(boot_params, EXT_LFB_BASE, (address >> 32) as u32);
+        write_u32(boot_params, LFB_SIZE, framebuffer.size as u32);
+        write_u16(boot_params, LFB_LINE_LENGTH, (framebuffer.stride * depth as u32 / 8) as u16);
+        boot_params[LFB_FIELDS..LFB_FIELDS + fields.len()].copy_from_slice(&fields);
+        if address >> 32 != 0 {
+            write_u32(boot_params, CAPABILITIES, VIDEO_CAPABILITY_64BIT_BASE);
+        }
+    }
+}
+
+/// This function enters the 64-bit entry of the kernel with the zero page. The Boot Services must be
+/// exited and the zero page must be written with [write_boot_params].
+pub fn enter_kernel_64(kernel: &LinuxKernel, stack: &mut [u8]) -> ! {
+    let stack_top = stack.as_mut_ptr_range().end as u64 & !0xF;
+    unsafe { linux_trampoline(kernel.entry, kernel.boot_params.as_ptr() as u64, stack_top) }
+}
+
+/// This function returns the E820 type of the UEFI memory type
+fn e820_type(memory_type: MemoryType) -> u32 {
+    match memory_type {
+        MemoryType::CONVENTIONAL
+        | MemoryType::BOOT_SERVICES_CODE
+        | MemoryType::BOOT_SERVICES_DATA
+        | MemoryType::LOADER_CODE
+        | MemoryType::LOADER_DATA => E820_RAM,
+        MemoryType::ACPI_RECLAIM => E820_ACPI,
+        MemoryType::ACPI_NON_VOLATILE => E820_NVS,
+        MemoryType::UNUSABLE => E820_UNUSABLE,
+        _ => E820_RESERVED,
+    }
+}
+
+/// This function allocates the memory of the kernel at the preferred address. If the preferred
+/// address isn't free, the memory is allocated below the maximal address with the alignment of the
+/// kernel.
+fn allocate_kernel(
+    boot_services: &BootServices, size: u64, preferred_address: u64, alignment: u64, max_address: u64,
+) -> Result<u64, Error> {
+    let page_count = (size / PAGE_SIZE) as usize;
+    if preferred_address != 0
+        && boot_services
+            .allocate_pages(
+                AllocateType::Address(preferred_address),
+                MemoryType::LOADER_DATA,
+                page_count,
+            )
+            .is_ok()
+    {
+        unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(preferred_address), page_count)? };
+        return Ok(preferred_address);
+    }
+
+    // Allocate additional pages for the alignment, the unused pages stay allocated
+    let alignment_pages = (alignment / PAGE_SIZE) as usize;
+    let address = boot_services.allocate_pages(
+        AllocateType::MaxAddress(max_address),
+        MemoryType::LOADER_DATA,
+        page_count + alignment_pages,
+    )?;
+    unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(address), page_count + alignment_pages)? };
+    Ok((address + alignment - 1) & !(alignment - 1))
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(Error::InvalidLinuxKernel)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(Error::InvalidLinuxKernel)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[inline]
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let bytes = data
+        .get(offset..offset + 8)
+        .ok_or(Error::InvalidLinuxKernel)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[inline]
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn write_u64(data: &mut [u8], offset: usize, value: u64) {
+    data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
@@ -3,22 +3,39 @@
 #![feature(panic_info_message)]
 #![feature(abi_x86_interrupt)]
 
+pub(crate) mod binary;
+pub(crate) mod boot_unit;
+pub(crate) mod bootlog;
+pub(crate) mod console;
+pub(crate) mod crash;
+pub(crate) mod early_alloc;
+pub(crate) mod editor;
+pub(crate) mod elf_loader;
 pub(crate) mod error;
+pub(crate) mod exit_boot;
 pub(crate) mod files;
+pub(crate) mod linux;
+pub(crate) mod menu;
+pub(crate) mod messages;
+pub(crate) mod multiboot2;
+pub(crate) mod netboot;
+pub(crate) mod pointer;
+pub(crate) mod pstore;
+pub(crate) mod runtime;
+pub(crate) mod selftest;
+pub(crate) mod stage;
+pub(crate) mod trampoline;
+pub(crate) mod uefi_env;
+pub(crate) mod verify;
 
 extern crate alloc;
 
 use core::fmt::{
     Debug,
-    Write,
+    Display,
 };
 use libcpu::halt_cpu;
-use libgraphics::embedded_graphics::{
-    mono_font::ascii,
-    pixelcolor::Rgb888,
-    prelude::RgbColor,
-    Drawable,
-};
+use libgraphics::embedded_graphics::Drawable;
 use uefi::{
     allocator,
     entry,
@@ -29,116 +46,1122 @@ use uefi::{
 };
 
 use crate::{
+    binary::load_flat_binary,
+    boot_unit::BootUnit,
+    bootlog::{
+        BOOT_LOG_OPTION,
+        BOOT_LOG_PATH,
+    },
+    crash::{
+        PanicAction,
+        CRASH_PATH,
+        DEFAULT_PANIC_TIMEOUT,
+        PANIC_OPTION,
+    },
+    early_alloc::{
+        early_alloc,
+        EARLY_ALLOCATOR,
+    },
+    elf_loader::{
+        load_kernel,
+        LoadedKernel,
+        NOKASLR_OPTION,
+    },
     error::Error,
-    files::init_file_system_driver,
+    files::{
+        init_file_system_driver,
+        SimpleFileSystemContext,
+        VolumeFilter,
+        INITRD_FILE_NAME,
+        KERNEL_FILE_NAME,
+    },
+    linux::{
+        load_linux_kernel,
+        LinuxKernel,
+    },
+    menu::{
+        read_power_status,
+        select_boot_entry,
+        PowerStatus,
+        CONFIG_PATH,
+        VIDEO_OPTION,
+    },
+    messages::{
+        Message,
+        CATALOG_PATH,
+    },
+    multiboot2::{
+        load_multiboot2_kernel,
+        Multiboot2Kernel,
+    },
+    netboot::{
+        NetbootContext,
+        NetbootUrl,
+        NETBOOT_OPTION,
+    },
+    pstore::{
+        PreviousLog,
+        LOG_PATH,
+    },
+    selftest::SELFTEST_OPTION,
+    stage::{
+        FailurePolicy,
+        Stage,
+    },
+    verify::{
+        signatures_required,
+        verify_artifact,
+        verify_volume_file,
+        DigestManifest,
+        HASHES_OPTION,
+        MANIFEST_FILE_NAME,
+        MANIFEST_PATH,
+    },
+};
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
 };
 use core::{
     alloc::GlobalAlloc,
     panic::PanicInfo,
-    ptr::NonNull,
 };
-use libcore::FrameAllocator;
-use libgraphics::text::{
-    next_row,
-    TEXT_WRITER_CONTEXT,
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::{
+        BootInfo,
+        BootUnitInfo,
+        BootUnitRole,
+        FrameAllocatorHandoff,
+        FramebufferInfo,
+        LoadProtocol,
+        ReservedRegion,
+    },
+    build_stamp::BuildStamp,
+    cmdline::{
+        parse_region,
+        CommandLine,
+    },
+    config::{
+        parse_resolution,
+        BootConfig,
+        BootEntry,
+    },
+    cpu_features::{
+        missing_features,
+        Requirement,
+        CPU_FEATURES,
+    },
+    elf::ElfFile,
+    memory_map::MemoryMap,
+    paging::set_cache_type,
+    pat::{
+        init_pat,
+        CacheType,
+    },
+    rng::init_random_generator,
+    stack_protector::init_stack_guard,
+    table::{
+        Alignment,
+        Column,
+        Table,
+    },
+    trace::{
+        TraceBuffer,
+        TraceProcess,
+        TRACE_BUFFER_SIZE,
+        TRACE_OPTION,
+    },
+    FrameAllocator,
 };
 use log::{
     error,
     info,
+    warn,
 };
 use uefi::{
+    guid,
     prelude::{
         BootServices,
         RuntimeServices,
     },
+    proto::loaded_image::LoadedImage,
     table::{
-        boot::MemoryType,
+        boot::{
+            AllocateType,
+            MemoryType,
+        },
+        cfg::{
+            ACPI2_GUID,
+            ACPI_GUID,
+        },
         runtime::ResetType,
     },
+    Guid,
 };
 
-static mut BOOT_SERVICES: Option<NonNull<BootServices>> = None;
-static mut RUNTIME_SERVICES: Option<NonNull<RuntimeServices>> = None;
+/// The GUID of the flattened device tree in the UEFI configuration table
+const DEVICE_TREE_GUID: Guid = guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
+
+static MEMORY_MAP_TABLE: Table = Table::new(&[
+    Column::new("Type", 22, Alignment::Left),
+    Column::new("Physical Start", 16, Alignment::Right),
+    Column::new("Pages", 10, Alignment::Right),
+    Column::new("Reserved", 8, Alignment::Left),
+]);
+
+static CPU_FEATURE_TABLE: Table = Table::new(&[
+    Column::new("Feature", 8, Alignment::Left),
+    Column::new("Description", 28, Alignment::Left),
+    Column::new("Requirement", 11, Alignment::Left),
+    Column::new("Status", 6, Alignment::Left),
+]);
+
+/// The maximum number of reserved regions, which are handed over to the kernel. Adjacent regions
+/// are merged, so this limit is only reached with very fragmented memory maps.
+const MAX_RESERVED_REGIONS: usize = 512;
+
+/// The size of the buffer for the encoded boot information
+const BOOT_INFO_BUFFER_SIZE: usize = 4096;
+
+/// The load option `mem=<size>`, which limits the usable memory, that is handed to the frame
+/// allocator. The size accepts the suffixes `K`, `M` and `G`.
+pub const MEMORY_LIMIT_OPTION: &str = "mem";
+
+libcore::build_stamp!();
+
+/// The boot trace is recorded into this buffer, until it's moved into reserved memory for the kernel
+static mut EARLY_TRACE_BUFFER: TraceBuffer = TraceBuffer::new();
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // Show error with message
-    error!("Unrecoverable Error while booting into OverflowOS: ");
-    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.unwrap();
-    if let Some(message) = info.message() {
-        context.write_fmt(message.clone()).unwrap();
-    } else {
-        libgraphics::text::write_str("No error message provided").unwrap();
+    error!("{} ", messages::text(Message::PanicTitle));
+    match info.message() {
+        Some(message) => console::print(format_args!("{}\n", message)),
+        None => console::print(format_args!("{}\n", messages::text(Message::PanicNoMessage))),
     }
-    next_row().unwrap();
 
     // Show location
     if let Some(location) = info.location() {
-        error!(" => Error found in {} on {}:{}", location.file(), location.line(), location.column())
+        let arguments: [&dyn Display; 3] = [&location.file(), &location.line(), &location.column()];
+        error!("{}", messages::format_message(Message::PanicLocation, &arguments))
     }
 
-    // Wait 10 seconds and shutdown computer
-    unsafe {
-        BOOT_SERVICES.unwrap().as_ref().stall(10000000);
-        RUNTIME_SERVICES
-            .unwrap()
-            .as_ref()
-            .reset(ResetType::SHUTDOWN, Status::LOAD_ERROR, None)
+    // Write the crash report, so it can be shown in the boot menu of the next boot
+    crash::write_crash_report(info);
+
+    // Count down until the computer is shut down, the user can stay, reboot or save diagnostics
+    let reset_type = match crash::panic_countdown() {
+        PanicAction::Shutdown => ResetType::SHUTDOWN,
+        PanicAction::Reboot => ResetType::COLD,
+    };
+    match uefi_env::try_runtime_services() {
+        Some(runtime_services) => runtime_services.reset(reset_type, Status::LOAD_ERROR, None),
+        None => halt_cpu(),
+    }
+}
+
+/// This function checks the CPU features, which are needed by OverflowOS, and shows the result of
+/// every check. If a required feature is missing, the missing features are listed and the CPU is
+/// halted, instead of faulting later while booting.
+fn check_cpu_features() {
+    info!("{}\n", CPU_FEATURE_TABLE.header());
+    info!("{}\n", CPU_FEATURE_TABLE.separator());
+    for feature in CPU_FEATURES {
+        let status = match (feature.is_supported(), feature.requirement) {
+            (true, _) => "PASS",
+            (false, Requirement::Required) => "FAIL",
+            (false, Requirement::Optional) => "N/A",
+        };
+        info!(
+            "{}\n",
+            CPU_FEATURE_TABLE.row(&[
+                &feature.name,
+                &feature.description,
+                &feature.requirement,
+                &status
+            ])
+        );
+    }
+
+    for (routine, variant) in libcore::alternatives::alternatives() {
+        info!("Selected '{}' variant of '{}' routine\n", variant, routine);
+    }
+
+    if missing_features().next().is_none() {
+        return;
+    }
+
+    error!("OverflowOS can't boot on this machine, the following CPU features are missing:\n");
+    for feature in missing_features() {
+        error!(" - {} ({})\n", feature.name, feature.description);
+    }
+    error!("The system is halted, please turn off the machine\n");
+    halt_cpu();
+}
+
+/// This function returns, whether the memory of the specified type is usable after the Boot
+/// Services were exited
+fn is_usable_memory(memory_type: MemoryType) -> bool {
+    matches!(
+        memory_type,
+        MemoryType::BOOT_SERVICES_DATA
+            | MemoryType::BOOT_SERVICES_CODE
+            | MemoryType::PERSISTENT_MEMORY
+            | MemoryType::CONVENTIONAL
+    )
+}
+
+/// This function reserves the usable memory, which follows the first bytes of usable memory up to
+/// the specified limit, in the frame allocator. All frames from the first hidden page up to the end
+/// of the managed memory are reserved, so no allocation returns memory above the limit. The frames
+/// aren't handed over as reserved regions, so the kernel sees them like allocated memory. This
+/// function returns the number of hidden pages.
+fn limit_usable_memory(
+    frame_allocator: &mut FrameAllocator, memory_map: &MemoryMap, limit: u64,
+) -> u64 {
+    let mut remaining_pages = limit.div_ceil(4096);
+    let mut hidden_pages = 0;
+    let mut limit_address = None;
+    for descriptor in memory_map
+        .entries()
+        .filter(|descriptor| is_usable_memory(descriptor.ty))
+    {
+        let kept_pages = descriptor.page_count.min(remaining_pages);
+        remaining_pages -= kept_pages;
+        if kept_pages < descriptor.page_count {
+            limit_address.get_or_insert(PhysAddr::new(descriptor.phys_start + kept_pages * 4096));
+            hidden_pages += descriptor.page_count - kept_pages;
+        }
+    }
+
+    if let Some(limit_address) = limit_address {
+        if limit_address < frame_allocator.stop_address {
+            let page_count = (frame_allocator.stop_address - limit_address).div_ceil(4096);
+            frame_allocator.reserve_region(limit_address, page_count);
+        }
+
+        let free_frames = frame_allocator.free_frames_above(limit_address);
+        if free_frames != 0 {
+            panic!(
+                "{} frames above the memory limit at 0x{:X} are still free",
+                free_frames, limit_address
+            );
+        }
+    }
+    hidden_pages
+}
+
+/// This function appends the specified region to the reserved regions, which are handed over to the
+/// kernel. If the region directly follows the last region, the last region is extended. If the list
+/// is full, the region is only reserved in the frame table.
+fn push_reserved_region(
+    regions: &mut [ReservedRegion], count: &mut usize, address: PhysAddr, page_count: u64,
+) {
+    if let Some(last_region) = count
+        .checked_sub(1)
+        .and_then(|index| regions.get_mut(index))
+    {
+        if last_region.address + last_region.page_count * 4096 == address {
+            last_region.page_count += page_count;
+            return;
+        }
+    }
+
+    if let Some(region) = regions.get_mut(*count) {
+        *region = ReservedRegion {
+            address,
+            page_count,
+        };
+        *count += 1;
+    }
+}
+
+/// This function programs the PAT and maps the framebuffer with the write-combining memory type.
+/// Huge pages of the firmware mapping, which are partially covered by the framebuffer, are split
+/// with page tables allocated from the Boot Services.
+fn map_framebuffer_write_combining(boot_services: &BootServices) -> Result<(), Error> {
+    let (address, size) = libgraphics::framebuffer_region()?;
+    init_pat();
+
+    let mut allocate_table = || {
+        boot_services
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, 1)
+            .ok()
+            .map(PhysAddr::new)
+    };
+    unsafe { set_cache_type(address, size as u64, CacheType::WriteCombining, &mut allocate_table)? };
+    info!("Mapped framebuffer at 0x{:X} ({} kB) write-combining\n", address, size / 1024);
+    Ok(())
+}
+
+/// This function returns the address of the ACPI RSDP from the UEFI configuration table or the null
+/// address, if the firmware doesn't provide ACPI tables.
+fn find_rsdp(system_table: &SystemTable<Boot>) -> PhysAddr {
+    let config_table = system_table.config_table();
+    [ACPI2_GUID, ACPI_GUID]
+        .iter()
+        .find_map(|guid| config_table.iter().find(|entry| entry.guid == *guid))
+        .map(|entry| PhysAddr::new(entry.address as u64))
+        .unwrap_or_default()
+}
+
+/// This function returns the address of the flattened device tree from the UEFI configuration table
+/// or the null address, if the firmware doesn't provide a device tree (like on most x86 systems).
+fn find_device_tree(system_table: &SystemTable<Boot>) -> PhysAddr {
+    system_table
+        .config_table()
+        .iter()
+        .find(|entry| entry.guid == DEVICE_TREE_GUID)
+        .map(|entry| PhysAddr::new(entry.address as u64))
+        .unwrap_or_default()
+}
+
+/// This function reads the load options of the bootloader image. These options are passed by the
+/// boot manager entry or the UEFI shell and use the command line format of [CommandLine].
+fn read_load_options(boot_services: &BootServices, image_handle: Handle) -> Result<String, Error> {
+    let loaded_image = boot_services.open_protocol_exclusive::<LoadedImage>(image_handle)?;
+    Ok(match loaded_image.load_options_as_cstr16() {
+        Ok(options) => options.to_string(),
+        Err(_) => String::new(),
+    })
+}
+
+/// The files of the primary boot unit and the digest manifest, which is used to verify the other
+/// boot units
+type BootFiles = (&'static mut [u8], Option<&'static mut [u8]>, Option<DigestManifest>);
+
+/// This function loads the kernel and the optional initrd of the primary boot unit. If a netboot URL
+/// is configured (with the load options or the boot entry), the files are downloaded from the boot
+/// server, otherwise they are read from the volume, which contains the kernel (see [VolumeFilter]).
+/// A missing initrd or manifest is skipped, but other errors (like a checksum mismatch) abort the
+/// boot. After that, the files are verified against the digest manifest, if available.
+fn load_boot_files(
+    boot_services: &BootServices, file_system_context: &mut SimpleFileSystemContext,
+    command_line: &CommandLine, boot_unit: &BootUnit, netboot_url: Option<&str>,
+) -> Result<BootFiles, Error> {
+    let netboot_url = command_line.get(NETBOOT_OPTION).or(netboot_url);
+    let (kernel_data, initrd_data, manifest_data) = match netboot_url {
+        Some(url) => {
+            let mut netboot_context = NetbootContext::new(boot_services, &NetbootUrl::parse(url)?)?;
+            (
+                netboot_context.fetch_file(KERNEL_FILE_NAME)?,
+                files::optional_file(netboot_context.fetch_file(INITRD_FILE_NAME))?,
+                files::optional_file(netboot_context.fetch_file(MANIFEST_FILE_NAME))?,
+            )
+        }
+        None => {
+            let filter = VolumeFilter::from_command_line(command_line);
+            let index = files::find_file(file_system_context, boot_unit.kernel, filter)?;
+            (
+                files::read_file(file_system_context, index, boot_unit.kernel)?,
+                match boot_unit.initrd {
+                    Some(initrd) => {
+                        files::optional_file(files::read_file(file_system_context, index, initrd))?
+                    }
+                    None => None,
+                },
+                files::optional_file(files::read_file(file_system_context, index, MANIFEST_PATH))?,
+            )
+        }
+    };
+
+    // Verify boot artifacts against the digest manifest
+    let manifest = match manifest_data {
+        Some(manifest_data) => {
+            let manifest = DigestManifest::parse(manifest_data)?;
+            let strict = command_line.get(HASHES_OPTION) != Some("warn");
+            verify_artifact(&manifest, KERNEL_FILE_NAME, kernel_data, strict)?;
+            if let Some(initrd_data) = &initrd_data {
+                verify_artifact(&manifest, INITRD_FILE_NAME, initrd_data, strict)?;
+            }
+            Some(manifest)
+        }
+        None if signatures_required() => {
+            return Err(Error::MissingSignature(KERNEL_FILE_NAME.to_string()));
+        }
+        None => {
+            warn!("No digest manifest found, skipping verification of boot artifacts\n");
+            None
+        }
+    };
+    Ok((kernel_data, initrd_data, manifest))
+}
+
+/// This function loads the boot units, which are referenced by the boot entry, besides the primary
+/// boot unit. The boot configuration is parsed again, because the menu only returns the entry.
+fn load_secondary_units(
+    file_system_context: &mut SimpleFileSystemContext, config_data: Option<&[u8]>,
+    boot_entry: &BootEntry, manifest: Option<&DigestManifest>, command_line: &CommandLine,
+) -> Result<&'static [BootUnitInfo], Error> {
+    if boot_entry.units.is_none() && boot_entry.crashkernel.is_none() {
+        return Ok(&[]);
+    }
+
+    let text = core::str::from_utf8(config_data.unwrap_or_default()).unwrap_or_default();
+    let config = BootConfig::parse(text)?;
+    let units = boot_unit::secondary_units(&config, boot_entry)?;
+    let strict = command_line.get(HASHES_OPTION) != Some("warn");
+    let filter = VolumeFilter::from_command_line(command_line);
+    boot_unit::load_units(file_system_context, &units, manifest, strict, filter)
+}
+
+/// The state of the boot process, which is filled by the boot stages. The fields are set by the
+/// stage, which is named in the comment, and are only valid after the stage completed.
+pub(crate) struct BootContext<'a> {
+    system_table: &'a SystemTable<Boot>,
+    boot_services: &'a BootServices,
+    image_handle: Handle,
+    /// The load options with the command line of the boot entry (`load-options` and `boot-menu`)
+    load_options: String,
+    /// The file system and the files of the first volume (`file-system`)
+    file_system_context: Option<SimpleFileSystemContext<'a>>,
+    crash_report: Option<&'static [u8]>,
+    config_data: Option<&'static [u8]>,
+    volume_manifest: Option<DigestManifest>,
+    /// The persistent log and the log of the last boot (`persistent-log`)
+    persistent_log_region: Option<ReservedRegion>,
+    previous_log: Option<PreviousLog>,
+    /// The selected boot entry (`boot-menu`)
+    boot_entry: Option<BootEntry<'static>>,
+    /// The battery and thermal status of the boot menu (`firmware-tables`)
+    power_status: PowerStatus,
+    /// The memory for the crash kernel (`crash-kernel`)
+    crash_kernel_region: Option<ReservedRegion>,
+    /// The files of the primary boot unit (`boot-files`)
+    kernel_data: Option<&'static mut [u8]>,
+    initrd_data: Option<&'static mut [u8]>,
+    manifest: Option<DigestManifest>,
+    /// The other boot units of the boot entry (`boot-units`)
+    boot_units: &'static [BootUnitInfo],
+    /// The loaded kernel and the values of Multiboot2 and Linux kernels (`kernel`)
+    kernel: Option<LoadedKernel>,
+    multiboot2_kernel: Option<Multiboot2Kernel>,
+    linux_kernel: Option<LinuxKernel>,
+    /// The firmware tables, which are handed to the kernel (`firmware-tables`)
+    rsdp_address: PhysAddr,
+    device_tree_address: PhysAddr,
+    /// The boot information, the buffer for the encoded boot information, the list of reserved
+    /// regions and the stack for the kernel (`boot-info`)
+    boot_info: Option<BootInfo>,
+    boot_info_buffer: &'static mut [u8],
+    reserved_regions: &'static mut [ReservedRegion],
+    kernel_stack: &'static mut [u8],
+    /// The limit of the usable memory in bytes (`boot-info`)
+    memory_limit: Option<u64>,
+}
+
+/// The results of the boot stages, which are needed after the Boot Services were exited
+struct KernelHandoff {
+    kernel: LoadedKernel,
+    multiboot2_kernel: Option<Multiboot2Kernel>,
+    linux_kernel: Option<LinuxKernel>,
+    boot_info: BootInfo,
+    /// The buffer, which the boot information is encoded into before the kernel is entered
+    boot_info_buffer: &'static mut [u8],
+    reserved_regions: &'static mut [ReservedRegion],
+    /// The stack, which the kernel is entered with
+    kernel_stack: &'static mut [u8],
+    memory_limit: Option<u64>,
+}
+
+impl<'a> BootContext<'a> {
+    fn new(system_table: &'a SystemTable<Boot>, image_handle: Handle) -> Self {
+        Self {
+            system_table,
+            boot_services: system_table.boot_services(),
+            image_handle,
+            load_options: String::new(),
+            file_system_context: None,
+            crash_report: None,
+            config_data: None,
+            volume_manifest: None,
+            persistent_log_region: None,
+            previous_log: None,
+            boot_entry: None,
+            power_status: PowerStatus::default(),
+            crash_kernel_region: None,
+            kernel_data: None,
+            initrd_data: None,
+            manifest: None,
+            boot_units: &[],
+            kernel: None,
+            multiboot2_kernel: None,
+            linux_kernel: None,
+            rsdp_address: PhysAddr::NULL,
+            device_tree_address: PhysAddr::NULL,
+            boot_info: None,
+            boot_info_buffer: &mut [],
+            reserved_regions: &mut [],
+            kernel_stack: &mut [],
+            memory_limit: None,
+        }
+    }
+
+    /// This function returns the command line of the load options. Before the `load-options` stage
+    /// completed, the command line is empty.
+    pub(crate) fn command_line(&self) -> CommandLine {
+        CommandLine::new(&self.load_options)
+    }
+
+    /// This function returns the state, which is needed after the Boot Services were exited. The
+    /// other state (like the opened volumes) is dropped.
+    fn into_handoff(self) -> Result<KernelHandoff, Error> {
+        Ok(KernelHandoff {
+            kernel: self.kernel.ok_or(Error::StageNotCompleted("kernel"))?,
+            multiboot2_kernel: self.multiboot2_kernel,
+            linux_kernel: self.linux_kernel,
+            boot_info: self
+                .boot_info
+                .ok_or(Error::StageNotCompleted("boot-info"))?,
+            boot_info_buffer: self.boot_info_buffer,
+            reserved_regions: self.reserved_regions,
+            kernel_stack: self.kernel_stack,
+            memory_limit: self.memory_limit,
+        })
+    }
+}
+
+/// The stages of the boot process. Every stage runs after its dependencies, stages without
+/// dependencies between each other run in the order of this list.
+#[rustfmt::skip]
+static BOOT_STAGES: &[Stage] = &[
+    Stage { name: "cpu-features", dependencies: &[], policy: FailurePolicy::Fatal, run: |_| { check_cpu_features(); Ok(()) } },
+    Stage { name: "random", dependencies: &[], policy: FailurePolicy::Fatal, run: init_random },
+    Stage { name: "file-system", dependencies: &["load-options"], policy: FailurePolicy::Retry(2), run: init_file_system },
+    Stage { name: "messages", dependencies: &["file-system"], policy: FailurePolicy::Skip, run: load_messages },
+    Stage { name: "load-options", dependencies: &[], policy: FailurePolicy::Fatal, run: init_load_options },
+    Stage { name: "persistent-log", dependencies: &["load-options"], policy: FailurePolicy::Skip, run: init_persistent_log },
+    Stage { name: "firmware-tables", dependencies: &[], policy: FailurePolicy::Skip, run: find_firmware_tables },
+    Stage { name: "boot-menu", dependencies: &["file-system", "load-options"], policy: FailurePolicy::Fatal, run: select_entry },
+    Stage { name: "crash-kernel", dependencies: &["boot-menu"], policy: FailurePolicy::Skip, run: reserve_crash_kernel },
+    Stage { name: "framebuffer", dependencies: &["boot-menu"], policy: FailurePolicy::Skip, run: map_framebuffer },
+    Stage { name: "boot-files", dependencies: &["boot-menu"], policy: FailurePolicy::Retry(2), run: load_primary_unit },
+    Stage { name: "boot-units", dependencies: &["boot-files"], policy: FailurePolicy::Fatal, run: load_boot_units },
+    Stage { name: "kernel", dependencies: &["boot-files"], policy: FailurePolicy::Fatal, run: load_kernel_image },
+    Stage { name: "boot-info", dependencies: &["kernel", "boot-units"], policy: FailurePolicy::Fatal, run: create_boot_info },
+    Stage { name: "boot-state", dependencies: &["boot-info"], policy: FailurePolicy::Skip, run: save_boot_state },
+];
+
+/// This function initializes the random number generator with the current time as additional seed
+/// material and randomizes the stack canary
+fn init_random(context: &mut BootContext) -> Result<(), Error> {
+    let seed_material = match context.system_table.runtime_services().get_time() {
+        Ok(time) => {
+            (time.year() as u64) << 48
+                | (time.month() as u64) << 40
+                | (time.day() as u64) << 32
+                | (time.hour() as u64) << 24
+                | (time.minute() as u64) << 16
+                | (time.second() as u64) << 8 ^ time.nanosecond() as u64
+        }
+        Err(_) => 0,
+    };
+    let random_source = init_random_generator(seed_material);
+    info!("Initialized random number generator (Source: {})\n", random_source);
+    init_stack_guard();
+    Ok(())
+}
+
+/// This function opens the volumes, enables the crash reports and reads the crash report of the
+/// last boot and the boot configuration. The configuration can change the kernel and the load
+/// options, so it's verified against the digest manifest of the first volume. Only the load options
+/// of the bootloader (not the configuration itself) can relax the verification with `hashes=warn`.
+fn init_file_system(context: &mut BootContext) -> Result<(), Error> {
+    let mut file_system_context = init_file_system_driver(context.boot_services)?;
+    crash::enable_crash_reports(
+        context.boot_services,
+        context.image_handle,
+        &mut file_system_context,
+    );
+    context.crash_report = files::read_file(&mut file_system_context, 0, CRASH_PATH)
+        .ok()
+        .map(|data| &*data);
+    context.config_data = files::read_file(&mut file_system_context, 0, CONFIG_PATH)
+        .ok()
+        .map(|data| &*data);
+    context.volume_manifest =
+        files::optional_file(files::read_file(&mut file_system_context, 0, MANIFEST_PATH))?
+            .map(|data| DigestManifest::parse(data))
+            .transpose()?;
+    if let Some(config_data) = context.config_data {
+        let strict = context.command_line().get(HASHES_OPTION) != Some("warn");
+        verify_volume_file(context.volume_manifest.as_ref(), CONFIG_PATH, config_data, strict)?;
+    }
+    context.file_system_context = Some(file_system_context);
+    Ok(())
+}
+
+/// This function loads the message catalog from the first volume, which localizes the boot UI. If no
+/// catalog exists or the catalog doesn't match the digest manifest, the English defaults are used.
+fn load_messages(context: &mut BootContext) -> Result<(), Error> {
+    let strict = context.command_line().get(HASHES_OPTION) != Some("warn");
+    let file_system_context = context
+        .file_system_context
+        .as_mut()
+        .ok_or(Error::StageNotCompleted("file-system"))?;
+    if let Ok(data) = files::read_file(file_system_context, 0, CATALOG_PATH) {
+        verify_volume_file(context.volume_manifest.as_ref(), CATALOG_PATH, data, strict)?;
+        let count = messages::load_catalog(data)?;
+        info!("Loaded {} messages from {}\n", count, CATALOG_PATH);
     }
+    Ok(())
+}
+
+/// This function reads the load options of the bootloader and applies the panic timeout
+fn init_load_options(context: &mut BootContext) -> Result<(), Error> {
+    context.load_options = read_load_options(context.boot_services, context.image_handle)?;
+    let panic_timeout = context
+        .command_line()
+        .get(PANIC_OPTION)
+        .and_then(|timeout| timeout.parse().ok());
+    crash::set_panic_timeout(panic_timeout.unwrap_or(DEFAULT_PANIC_TIMEOUT));
+    Ok(())
+}
+
+/// This function takes the log of the last boot out of the persistent log, before the log output of
+/// this boot is written into it
+fn init_persistent_log(context: &mut BootContext) -> Result<(), Error> {
+    if let Some((region, previous_log)) =
+        pstore::init_persistent_log(context.boot_services, &context.command_line())?
+    {
+        context.persistent_log_region = Some(region);
+        context.previous_log = previous_log;
+    }
+    Ok(())
+}
+
+/// This function shows the boot menu with the entries of the boot configuration. The command line of
+/// the entry is appended to the load options. The file system context borrows the Boot Services of
+/// the system table, so the menu uses a copy of the system table for the console input.
+fn select_entry(context: &mut BootContext) -> Result<(), Error> {
+    let crash_report_found = context.crash_report.is_some();
+    let mut menu_system_table = unsafe { context.system_table.unsafe_clone() };
+    let file_system_context = context
+        .file_system_context
+        .as_mut()
+        .ok_or(Error::StageNotCompleted("file-system"))?;
+    let boot_entry = select_boot_entry(
+        &mut menu_system_table,
+        context.config_data,
+        &mut context.crash_report,
+        &mut context.previous_log,
+        &context.power_status,
+        file_system_context,
+    )?;
+
+    // Delete the crash report of the last boot, if requested in the boot menu
+    if crash_report_found && context.crash_report.is_none() {
+        if let Err(error) = files::delete_file(file_system_context, 0, CRASH_PATH) {
+            warn!("Unable to delete crash report => {}\n", error);
+        }
+    }
+
+    // Save the log of the last boot, if requested in the boot menu
+    let previous_log = context.previous_log.take();
+    if let Some(previous_log) = previous_log.filter(|previous_log| previous_log.save_requested) {
+        match files::write_file(file_system_context, 0, LOG_PATH, &previous_log.data) {
+            Ok(()) => info!("Saved log of the last boot to {}\n", LOG_PATH),
+            Err(error) => warn!("Unable to save log of the last boot => {}\n", error),
+        }
+    }
+    info!("Selected boot entry '{}'\n", boot_entry.title);
+    if !boot_entry.cmdline.is_empty() {
+        context.load_options = format!("{} {}", context.load_options, boot_entry.cmdline);
+    }
+
+    // The video option of the command line takes precedence over the resolution of the entry
+    let resolution = match context.command_line().get(VIDEO_OPTION) {
+        Some(video) => {
+            let resolution = parse_resolution(video);
+            if resolution.is_none() {
+                warn!("Ignoring invalid video option '{}', expected <width>x<height>\n", video);
+            }
+            resolution.or(boot_entry.resolution)
+        }
+        None => boot_entry.resolution,
+    };
+    if let Some((width, height)) = resolution {
+        if let Err(error) = libgraphics::set_resolution(context.boot_services, width, height) {
+            warn!("Unable to change resolution to {}x{} => {:?}\n", width, height, error);
+        }
+    }
+    context.boot_entry = Some(boot_entry);
+    Ok(())
 }
 
-fn init_graphics(boot_services: &BootServices) -> Result<(), Error> {
-    libgraphics::create_context(boot_services)?;
-    libgraphics::text::create_text_writer_context(ascii::FONT_7X14_BOLD).unwrap();
-    libgraphics::fill_buffer(Rgb888::BLACK).unwrap();
-    libgraphics::swap_buffers().unwrap();
-    libgraphics::log::install_logger().unwrap();
+/// This function reserves the memory for the crash kernel, the kernel loads the crash kernel into it
+fn reserve_crash_kernel(context: &mut BootContext) -> Result<(), Error> {
+    context.crash_kernel_region =
+        pstore::reserve_crash_kernel(context.boot_services, &context.command_line())?;
+    Ok(())
+}
+
+/// This function maps the framebuffer write-combining. In self test mode, the fill rate is measured
+/// before and after the mapping was changed. Displays in a BltOnly mode have no framebuffer, which
+/// can be mapped, so the stage fails for them.
+fn map_framebuffer(context: &mut BootContext) -> Result<(), Error> {
+    libgraphics::framebuffer_region()?;
+    let tsc_frequency = context
+        .command_line()
+        .has_flag(SELFTEST_OPTION)
+        .then(|| selftest::tsc_frequency(context.boot_services));
+    let uncached_rate = tsc_frequency.map(|frequency| selftest::bench_fill_rate(frequency, "before"));
+    map_framebuffer_write_combining(context.boot_services)?;
+    if let (Some(frequency), Some(uncached_rate)) = (tsc_frequency, uncached_rate) {
+        let combined_rate = selftest::bench_fill_rate(frequency, "write-combining");
+        info!("Write-combining speedup: {}x\n", combined_rate / uncached_rate.max(1));
+    }
+    Ok(())
+}
+
+/// This function loads the kernel and the initrd of the primary boot unit into memory
+fn load_primary_unit(context: &mut BootContext) -> Result<(), Error> {
+    let boot_entry = context
+        .boot_entry
+        .as_ref()
+        .ok_or(Error::StageNotCompleted("boot-menu"))?;
+    let file_system_context = context
+        .file_system_context
+        .as_mut()
+        .ok_or(Error::StageNotCompleted("file-system"))?;
+    let primary_unit = BootUnit::from_entry(boot_entry, BootUnitRole::Primary);
+    let (kernel_data, initrd_data, manifest) = load_boot_files(
+        context.boot_services,
+        file_system_context,
+        &CommandLine::new(&context.load_options),
+        &primary_unit,
+        boot_entry.netboot,
+    )?;
+    info!("Loaded {} kB of kernel data into the memory\n", kernel_data.len() / 1024);
+    if let Some(initrd_data) = &initrd_data {
+        info!("Loaded {} kB of initrd data into the memory\n", initrd_data.len() / 1024);
+    }
+
+    context.kernel_data = Some(kernel_data);
+    context.initrd_data = initrd_data;
+    context.manifest = manifest;
+    Ok(())
+}
+
+/// This function loads the other boot units of the entry, they are started by the kernel
+fn load_boot_units(context: &mut BootContext) -> Result<(), Error> {
+    let boot_entry = context
+        .boot_entry
+        .as_ref()
+        .ok_or(Error::StageNotCompleted("boot-menu"))?;
+    let file_system_context = context
+        .file_system_context
+        .as_mut()
+        .ok_or(Error::StageNotCompleted("file-system"))?;
+    context.boot_units = load_secondary_units(
+        file_system_context,
+        context.config_data,
+        boot_entry,
+        context.manifest.as_ref(),
+        &CommandLine::new(&context.load_options),
+    )?;
+    Ok(())
+}
+
+/// This function loads the kernel segments at a random address, unless KASLR is disabled by the user
+fn load_kernel_image(context: &mut BootContext) -> Result<(), Error> {
+    let boot_entry = context
+        .boot_entry
+        .as_ref()
+        .ok_or(Error::StageNotCompleted("boot-menu"))?;
+    let kernel_data = context
+        .kernel_data
+        .as_deref()
+        .ok_or(Error::StageNotCompleted("boot-files"))?;
+    let kaslr = !context.command_line().has_flag(NOKASLR_OPTION);
+    let kernel = match boot_entry.protocol {
+        LoadProtocol::Elf => load_kernel(context.boot_services, kernel_data, kaslr)?,
+        LoadProtocol::Multiboot2 => {
+            let (kernel, multiboot2_kernel) =
+                load_multiboot2_kernel(context.boot_services, kernel_data)?;
+            context.multiboot2_kernel = Some(multiboot2_kernel);
+            kernel
+        }
+        LoadProtocol::Binary => {
+            load_flat_binary(
+                context.boot_services,
+                kernel_data,
+                boot_entry.load_address,
+                boot_entry.entry_offset,
+            )?
+        }
+        LoadProtocol::Linux => {
+            let (kernel, linux_kernel) = load_linux_kernel(
+                context.boot_services,
+                kernel_data,
+                boot_entry.cmdline,
+                context.initrd_data.as_deref(),
+            )?;
+            context.linux_kernel = Some(linux_kernel);
+            kernel
+        }
+    };
+    info!(
+        "Loaded kernel at 0x{:X} (Entry: 0x{:X}, Slide: 0x{:X}, KASLR: {})\n",
+        kernel.address,
+        kernel.entry,
+        kernel.slide,
+        if kaslr { "enabled" } else { "disabled" }
+    );
+    match ElfFile::parse(kernel_data).map(|elf| BuildStamp::from_elf(&elf)) {
+        Ok(Some(stamp)) => info!("Kernel build {}\n", stamp),
+        _ => info!("Kernel has no build stamp\n"),
+    }
+    context.kernel = Some(kernel);
+    Ok(())
+}
+
+/// This function finds the ACPI RSDP and the device tree in the configuration table. ACPI 2.0 is
+/// preferred over ACPI 1.0 and platforms without ACPI describe their hardware with the device tree.
+/// The battery and thermal status for the boot menu is read from the ACPI tables, a firmware with
+/// broken tables only loses the status.
+fn find_firmware_tables(context: &mut BootContext) -> Result<(), Error> {
+    context.rsdp_address = find_rsdp(context.system_table);
+    if context.rsdp_address.is_null() {
+        warn!("No ACPI RSDP found in the UEFI configuration table\n");
+    } else {
+        match read_power_status(context.rsdp_address) {
+            Ok(power_status) => context.power_status = power_status,
+            Err(error) => warn!("Unable to read power status => {}\n", error),
+        }
+    }
+
+    context.device_tree_address = find_device_tree(context.system_table);
+    if !context.device_tree_address.is_null() {
+        info!("Found device tree at 0x{:X}\n", context.device_tree_address);
+    }
+    Ok(())
+}
+
+/// This function creates the boot information for the kernel and allocates the buffer for the
+/// encoded boot information, the list of reserved regions, the stack of the kernel and the trace
+/// buffer. They can't be allocated after exiting the Boot Services and the memory stays reserved
+/// for the kernel.
+fn create_boot_info(context: &mut BootContext) -> Result<(), Error> {
+    let kernel = context
+        .kernel
+        .as_ref()
+        .ok_or(Error::StageNotCompleted("kernel"))?;
+    let (initrd_address, initrd_size) = match &context.initrd_data {
+        Some(initrd_data) => (PhysAddr::new(initrd_data.as_ptr() as u64), initrd_data.len() as u64),
+        None => (PhysAddr::NULL, 0),
+    };
+    let persistent_log_region = context.persistent_log_region.unwrap_or_default();
+
+    // Move the boot trace into reserved memory, so the kernel continues it
+    let trace_buffer_address = if context.command_line().has_flag(TRACE_OPTION) {
+        let buffer = early_alloc(context.boot_services, TRACE_BUFFER_SIZE, 8)?;
+        unsafe {
+            libcore::trace::set_trace_buffer(
+                buffer.as_mut_ptr() as *mut TraceBuffer,
+                TraceProcess::Bootloader,
+            )
+        };
+        libcore::trace::set_tsc_frequency(selftest::tsc_frequency(context.boot_services));
+        PhysAddr::new(buffer.as_ptr() as u64)
+    } else {
+        PhysAddr::NULL
+    };
+    context.boot_info = Some(BootInfo {
+        initrd_address,
+        initrd_size,
+        kernel_address: kernel.address,
+        kernel_size: kernel.size,
+        kernel_slide: kernel.slide,
+        command_line_address: PhysAddr::new(context.load_options.as_ptr() as u64),
+        command_line_size: context.load_options.len() as u64,
+        rsdp_address: context.rsdp_address,
+        device_tree_address: context.device_tree_address,
+        framebuffer: FramebufferInfo::NONE,
+        frame_allocator: FrameAllocatorHandoff::NONE,
+        boot_units_address: PhysAddr::new(context.boot_units.as_ptr() as u64),
+        boot_unit_count: context.boot_units.len() as u64,
+        persistent_log_address: persistent_log_region.address,
+        persistent_log_size: persistent_log_region.page_count * 4096,
+        crash_kernel_region: context.crash_kernel_region.unwrap_or_default(),
+        tls_template: kernel.tls_template,
+        bootloader_stamp: BUILD_STAMP,
+        trace_buffer_address,
+        ..BootInfo::EMPTY
+    });
+    context.boot_info_buffer = early_alloc(context.boot_services, BOOT_INFO_BUFFER_SIZE, 8)?;
+
+    let buffer = early_alloc(
+        context.boot_services,
+        MAX_RESERVED_REGIONS * core::mem::size_of::<ReservedRegion>(),
+        core::mem::align_of::<ReservedRegion>(),
+    )?;
+    context.reserved_regions = unsafe {
+        core::slice::from_raw_parts_mut(
+            buffer.as_mut_ptr() as *mut ReservedRegion,
+            MAX_RESERVED_REGIONS,
+        )
+    };
+    context.kernel_stack = trampoline::allocate_kernel_stack(context.boot_services)?;
+
+    // Validate the memory limit now, it's applied to the frame allocator after exiting the Boot
+    // Services
+    if let Some(value) = context.command_line().get(MEMORY_LIMIT_OPTION) {
+        let limit = parse_region(value)
+            .filter(|(size, address)| *size != 0 && address.is_none())
+            .ok_or_else(|| {
+                Error::InvalidRegion(MEMORY_LIMIT_OPTION.to_string(), value.to_string())
+            })?;
+        context.memory_limit = Some(limit.0);
+    }
+    Ok(())
+}
+
+/// This function writes the state of the boot into the last boot file and appends the log to the
+/// boot log, if it's requested with the `bootlog` load option. The files are written on the first
+/// volume, so a write-protected volume only skips this stage.
+fn save_boot_state(context: &mut BootContext) -> Result<(), Error> {
+    let boot_entry = context
+        .boot_entry
+        .ok_or(Error::StageNotCompleted("boot-menu"))?;
+    let time = context.system_table.runtime_services().get_time().ok();
+    let save_boot_log = context.command_line().has_flag(BOOT_LOG_OPTION);
+    let file_system_context = context
+        .file_system_context
+        .as_mut()
+        .ok_or(Error::StageNotCompleted("file-system"))?;
+
+    bootlog::write_last_boot(file_system_context, time.as_ref(), &boot_entry)?;
+    if save_boot_log {
+        bootlog::append_boot_log(file_system_context, time.as_ref(), boot_entry.title)?;
+        info!("Appended the log to {}\n", BOOT_LOG_PATH);
+    }
     Ok(())
 }
 
 #[entry]
-fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+fn main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi_env::init_uefi_env(&system_table);
     unsafe {
-        allocator::init(system_table.boot_services());
-        BOOT_SERVICES = NonNull::new(system_table.boot_services() as *const _ as *mut _);
-        RUNTIME_SERVICES = NonNull::new(system_table.runtime_services() as *const _ as *mut _);
-    }
+        libcore::trace::set_trace_buffer(
+            core::ptr::addr_of_mut!(EARLY_TRACE_BUFFER),
+            TraceProcess::Bootloader,
+        )
+    };
+    unsafe { allocator::init(system_table.boot_services()) };
+
+    // Select the variants of the hot routines (like the copy into the framebuffer) for this CPU
+    libcore::alternatives::apply_alternatives();
 
     // Clear stdout and if failed, abort execution of bootloader. After that, initialize uefi services
     if let Err(status) = system_table.stdout().clear().map_err(|err| err.status()) {
         return status;
     }
 
-    // Initiate Graphics Driver with Logger and display welcome message with resolution information
-    if let Err(error) = init_graphics(system_table.boot_services()) {
-        panic!("Unable to initialize Graphics => {} (Shutdown in 10 seconds)", error);
+    // Initiate the console with the Graphics Driver (or the text output of the firmware as
+    // fallback) and display welcome message with resolution information
+    if let Err(error) = console::init_console(&system_table) {
+        panic!("Unable to initialize console => {}", error);
     }
 
-    let (width, height) = libgraphics::resolution().unwrap();
     info!("Welcome to OverflowOS Bootloader v{}\n", env!("CARGO_PKG_VERSION"));
-    info!("Detected resolution of {}x{} pixels\n", width, height);
+    info!("Bootloader build {}\n", BUILD_STAMP);
+    match (libgraphics::resolution(), console::size()) {
+        (Ok((width, height)), _) => info!("Detected resolution of {}x{} pixels\n", width, height),
+        (Err(_), Some((columns, rows))) => {
+            info!("Using text console with {}x{} characters\n", columns, rows)
+        }
+        (Err(_), None) => {}
+    }
+    for display in libgraphics::display::displays()
+        .iter()
+        .filter(|display| !display.primary)
+    {
+        let (width, height) = display.resolution;
+        info!("Detected additional display {} with {}x{} pixels\n", display.id.0, width, height);
+    }
+
+    // Run the boot stages, which load the kernel and create the boot information, and show the
+    // timing report
+    let mut context = BootContext::new(&system_table, image_handle);
+    let reports = stage::run_stages(&mut context, BOOT_STAGES);
+    stage::print_stage_report(&reports);
 
-    // Initialize file system over simple file system driver
-    let mut file_system_context = match init_file_system_driver(system_table.boot_services()) {
+    // Disable the crash reports before the volumes are closed
+    crash::disable_crash_reports();
+    let KernelHandoff {
+        kernel,
+        multiboot2_kernel,
+        linux_kernel,
+        mut boot_info,
+        boot_info_buffer,
+        reserved_regions,
+        kernel_stack,
+        memory_limit,
+    } = match context.into_handoff() {
         Err(error) => {
-            panic!("Unable to initialize File System Driver => {} (Shutdown in 10 seconds)", error);
+            panic!("Unable to hand over to the kernel => {}", error);
+        }
+        Ok(handoff) => handoff,
+    };
+    let mut reserved_region_count = 0;
+
+    // Linux kernels with the EFI handover are entered with the Boot Services, the EFI stub exits
+    // them. Other Linux kernels are entered at the 64-bit entry after exiting the Boot Services.
+    let linux_kernel = match linux_kernel {
+        Some(linux_kernel) if linux_kernel.handover => {
+            info!("Handing over to the EFI stub of the Linux kernel at 0x{:X}\n", kernel.entry);
+            linux::enter_kernel(linux_kernel, image_handle, &system_table);
         }
-        Ok(context) => context,
+        linux_kernel => linux_kernel,
     };
 
-    // Load kernel into memory and parse as ELF
-    //let kernel_data = files::read_file(&mut file_system_context, 0, "\\EFI\\BOOT\\KERNEL.ELF")
-    // .unwrap();
-    // info!("Loaded {} kB of kernel data into the memory\n",
-    // kernel_data.len() / 1024);
+    // Displays without framebuffer and the text console can't be used without the Boot Services
+    if libgraphics::prepare_exit_boot_services() {
+        warn!("Display has no linear framebuffer, continuing with the serial console only\n");
+    }
+    console::prepare_exit_boot_services();
+
+    // Reserve the memory for the final memory map, nothing is allocated after that
+    let memory_map_buffer = match exit_boot::reserve_memory_map(system_table.boot_services()) {
+        Err(error) => panic!("Unable to reserve memory for the memory map => {}", error),
+        Ok(buffer) => buffer,
+    };
 
-    // Exit Boot Services and notify user about that
-    let (system_table, memory_map) = system_table.exit_boot_services();
-    unsafe { RUNTIME_SERVICES = NonNull::new(system_table.runtime_services() as *const _ as *mut _) };
+    // Exit Boot Services with the final memory map and notify user about that
+    libcore::trace::trace_instant("exit-boot-services");
+    let (system_table, mut memory_map) =
+        match exit_boot::exit_boot_services(system_table, image_handle, memory_map_buffer) {
+            Err(error) => panic!("Unable to exit the Boot Services => {}", error),
+            Ok(result) => result,
+        };
 
     info!("Exited UEFI Boot Services, system is now in Runtime Services\n");
 
-    let mut frame_allocator = FrameAllocator::new(&memory_map, 4096);
+    // Relocate the Runtime Services into the runtime window, so the kernel can use them from the
+    // higher half. Without relocation, the kernel doesn't use the Runtime Services.
+    let relocation = unsafe { runtime::map_runtime_window() }
+        .and_then(|_| runtime::set_virtual_address_map(&system_table, &mut memory_map));
+    match relocation {
+        Ok(address) => {
+            boot_info.runtime_services_address = VirtAddr::new(address);
+            info!("Relocated Runtime Services (Table: 0x{:X})\n", address);
+        }
+        Err(error) => warn!("Unable to relocate the Runtime Services => {}\n", error),
+    }
+
+    let mut frame_allocator = match FrameAllocator::new(&memory_map, 4096) {
+        Ok(frame_allocator) => frame_allocator,
+        Err(error) => panic!("Unable to create the frame allocator => {}", error),
+    };
     info!(
         "FrameAllocator(Management Table: {:p}, Page Size: {} KiB, Start Address: 0x{:X}, End \
          Address: 0x{:X})\n",
@@ -148,24 +1171,65 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         frame_allocator.stop_address
     );
     info!(
-        "Reserved memory 0x{:?} from 0x{:?} for memory allocation\n",
+        "Reserved memory 0x{:X} from 0x{:X} for memory allocation\n",
         frame_allocator.start_address, frame_allocator.stop_address
     );
 
+    // Reserve all memory, which isn't usable after the Boot Services were exited, and show the
+    // memory map
+    info!("{}\n", MEMORY_MAP_TABLE.header());
+    info!("{}\n", MEMORY_MAP_TABLE.separator());
     for descriptor in memory_map.entries() {
-        match descriptor.ty {
-            MemoryType::BOOT_SERVICES_DATA
-            | MemoryType::BOOT_SERVICES_CODE
-            | MemoryType::PERSISTENT_MEMORY
-            | MemoryType::CONVENTIONAL => {}
-            _ => {
-                info!(
-                    "Reserving {:?} page as frame in Frame Allocator ({} pages with 4 KiB)\n",
-                    descriptor.ty, descriptor.page_count
-                );
-                frame_allocator.reserve_memory_section(&descriptor);
-            }
+        let reserved = !is_usable_memory(descriptor.ty);
+        if reserved {
+            frame_allocator.reserve_memory_section(&descriptor);
+            push_reserved_region(
+                reserved_regions,
+                &mut reserved_region_count,
+                PhysAddr::new(descriptor.phys_start),
+                descriptor.page_count,
+            );
         }
+
+        info!(
+            "{}\n",
+            MEMORY_MAP_TABLE.row(&[
+                &format_args!("{:?}", descriptor.ty),
+                &format_args!("0x{:X}", descriptor.phys_start),
+                &descriptor.page_count,
+                &if reserved { "Yes" } else { "No" },
+            ])
+        );
+    }
+
+    // Reserve the regions of the early allocator like the kernel and the initrd
+    let early_regions = unsafe { EARLY_ALLOCATOR.regions() };
+    for region in early_regions {
+        frame_allocator.reserve_region(region.address, region.page_count as u64);
+        push_reserved_region(
+            reserved_regions,
+            &mut reserved_region_count,
+            region.address,
+            region.page_count as u64,
+        );
+    }
+    info!(
+        "Reserved {} early allocated regions ({} kB)\n",
+        early_regions.len(),
+        early_regions
+            .iter()
+            .map(|region| region.page_count * 4)
+            .sum::<usize>()
+    );
+
+    // Hide the usable memory above the limit, so low-memory behavior can be tested
+    if let Some(limit) = memory_limit {
+        let hidden_pages = limit_usable_memory(&mut frame_allocator, &memory_map, limit);
+        info!(
+            "Limited usable memory to {} MiB ({} MiB hidden)\n",
+            limit / (1024 * 1024),
+            hidden_pages * 4096 / (1024 * 1024)
+        );
     }
 
     info!(
@@ -174,5 +1238,52 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         frame_allocator.available_frames(),
         frame_allocator.remaining_frames()
     );
-    halt_cpu();
+
+    // Hand the frame allocator and the final memory map over to the kernel, so the kernel doesn't
+    // allocate reserved frames
+    boot_info.frame_allocator = frame_allocator.handoff(&reserved_regions[..reserved_region_count]);
+    boot_info.memory_map_address = PhysAddr::new(memory_map.as_bytes().as_ptr() as u64);
+    boot_info.memory_map_size = memory_map.as_bytes().len() as u64;
+    boot_info.memory_descriptor_size = memory_map.descriptor_size() as u64;
+
+    // Jump into the kernel entry with the boot information
+    match (&multiboot2_kernel, &linux_kernel) {
+        (Some(_), _) => info!("Jumping into Multiboot2 kernel entry at 0x{:X}\n", kernel.entry),
+        (_, Some(_)) => info!("Jumping into 64-bit Linux kernel entry at 0x{:X}\n", kernel.entry),
+        _ => info!("Jumping into kernel entry at 0x{:X}\n", kernel.entry),
+    }
+
+    // Hand the framebuffer over to the kernel. The graphics contexts are torn down, so the
+    // bootloader can't draw into the buffers after the kernel took the ownership of them.
+    boot_info.framebuffer = libgraphics::release_context().unwrap_or(FramebufferInfo::NONE);
+
+    // Multiboot2 kernels get the Multiboot2 information instead of the boot information
+    if let Some(mut multiboot2_kernel) = multiboot2_kernel {
+        let system_table_address = system_table.as_ptr() as u64;
+        let info_address = match multiboot2::write_info(
+            &mut *multiboot2_kernel.info_buffer,
+            &boot_info,
+            &memory_map,
+            system_table_address,
+        ) {
+            Err(error) => panic!("Unable to write Multiboot2 information => {}", error),
+            Ok(info_address) => info_address,
+        };
+        multiboot2::enter_kernel(&multiboot2_kernel, info_address);
+    }
+
+    // Linux kernels without EFI handover get the zero page with the final memory map. The Runtime
+    // Services may be relocated, so the kernel isn't told about the EFI system table.
+    if let Some(mut linux_kernel) = linux_kernel {
+        linux::write_boot_params(&mut linux_kernel, &boot_info, &memory_map);
+        linux::enter_kernel_64(&linux_kernel, kernel_stack);
+    }
+
+    // Encode the boot information, the kernel decodes it with the version and the tags it knows
+    if let Err(error) = boot_info.encode(boot_info_buffer) {
+        panic!("Unable to encode boot information => {}", error);
+    }
+    let boot_info_address = PhysAddr::new(boot_info_buffer.as_ptr() as u64);
+    libcore::trace::trace_instant("kernel-entry");
+    trampoline::enter_kernel(&kernel, boot_info_address, kernel_stack)
 }
@@ -0,0 +1,627 @@
+use crate::{
+    console,
+    crash::{
+        show_report,
+        ReportAction,
+        CRASH_PATH,
+        DELETE_ACTION,
+    },
+    editor::{
+        edit_command_line,
+        History,
+    },
+    error::Error,
+    files::{
+        self,
+        SimpleFileSystemContext,
+        VolumeInfo,
+        INITRD_PATH,
+        KERNEL_PATH,
+    },
+    messages::{
+        format_message,
+        text,
+        Message,
+    },
+    pointer::{
+        PointerContext,
+        PointerEvent,
+    },
+    pstore::PreviousLog,
+    selftest,
+};
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
+use libacpi::{
+    aml::{
+        self,
+        BatteryStatus,
+    },
+    identity_mapper,
+    AcpiTables,
+};
+use libcore::{
+    address::PhysAddr,
+    boot_info::LoadProtocol,
+    config::{
+        BootConfig,
+        BootEntry,
+    },
+    error::Error as CoreError,
+    keymap::{
+        KeyCode,
+        Keymap,
+        US,
+    },
+};
+use libgraphics::{
+    display::{
+        self,
+        DisplayId,
+    },
+    embedded_graphics::{
+        pixelcolor::Rgb888,
+        prelude::RgbColor,
+    },
+    text::{
+        character_size,
+        cursor,
+        set_color,
+        set_cursor,
+        write_str,
+        DARK_GRAY,
+        LIGHT_BLUE,
+        ORANGE,
+        RED,
+        TEXT_WRITER_CONTEXT,
+    },
+};
+use log::{
+    info,
+    warn,
+};
+use uefi::{
+    prelude::{
+        Boot,
+        SystemTable,
+    },
+    proto::console::text::{
+        Key,
+        ScanCode,
+    },
+};
+
+pub const CONFIG_PATH: &str = "\\EFI\\BOOT\\OVERFLOW.CFG";
+
+/// The option, which overrides the resolution of the selected entry and the mode filter (like
+/// `video=1280x720`)
+pub const VIDEO_OPTION: &str = "video";
+
+const DEFAULT_TIMEOUT: u64 = 5;
+const POLL_INTERVAL: usize = 100_000;
+
+/// The fallback entry boots the kernel and the initrd from the default paths. It's used, if no boot
+/// configuration exists or if the boot configuration is malformed.
+pub const FALLBACK_ENTRY: BootEntry<'static> = BootEntry {
+    title: "OverflowOS (Default)",
+    kernel: KERNEL_PATH,
+    initrd: Some(INITRD_PATH),
+    cmdline: "",
+    resolution: None,
+    netboot: None,
+    protocol: LoadProtocol::Elf,
+    units: None,
+    crashkernel: None,
+    load_address: None,
+    entry_offset: 0,
+};
+
+/// The battery and thermal status, which is shown in the status bar of the boot menu
+#[derive(Clone, Copy, Default)]
+pub struct PowerStatus {
+    pub battery: Option<BatteryStatus>,
+    /// The temperature of the first thermal zone in tenths of Kelvin
+    pub temperature: Option<u64>,
+}
+
+impl PowerStatus {
+    /// This function returns the status bar of the boot menu or `None`, if neither the battery nor
+    /// the temperature is known.
+    fn status_line(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(battery) = self.battery {
+            if let Some(percentage) = battery.percentage() {
+                let message = match battery.charging {
+                    true => Message::BatteryCharging,
+                    false => Message::Battery,
+                };
+                parts.push(format_message(message, &[&percentage]));
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            let celsius = temperature as i64 - 2732;
+            parts.push(format_message(
+                Message::Temperature,
+                &[&(celsius / 10), &(celsius % 10).abs()],
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join(" | "))
+    }
+}
+
+/// The diagnostic of a malformed boot configuration with the line, which caused the error
+struct Diagnostic<'a> {
+    error: CoreError,
+    source_line: Option<&'a str>,
+}
+
+/// This function shows the boot menu with the entries of the boot configuration and returns the
+/// entry, which was selected by the user or by the timeout. If the boot configuration is malformed,
+/// the diagnostic is shown above the fallback entry and the menu waits for the user. The command
+/// line of the selected entry can be edited before booting by pressing `e`.
+///
+/// If a crash report of the last boot exists, the menu is always shown and the report can be shown
+/// by pressing `c`. The report is set to `None`, if the user requested to delete it. The same applies
+/// to the log of the last boot, which was found in the persistent log after an unexpected reboot. It
+/// can be shown by pressing `l` and saved on the first volume. If multiple volumes exist, the volume
+/// with the boot files can be selected by pressing `v`.
+pub fn select_boot_entry(
+    system_table: &mut SystemTable<Boot>, config_data: Option<&'static [u8]>,
+    crash_report: &mut Option<&'static [u8]>, previous_log: &mut Option<PreviousLog>,
+    power_status: &PowerStatus, file_system_context: &mut SimpleFileSystemContext,
+) -> Result<BootEntry<'static>, Error> {
+    let report_found = crash_report.is_some() || previous_log.is_some();
+    if config_data.is_none() && !report_found {
+        return Ok(FALLBACK_ENTRY);
+    }
+
+    let graphical = console::is_graphical();
+    let (mut entries, timeout, mut selected, keymap, diagnostic) =
+        match parse_config(config_data.unwrap_or_default()) {
+            Ok(config) => {
+                // Select the display and its mode and rotate the screen before the menu is drawn,
+                // the kernel takes over the display, the mode and the orientation
+                if let Some(policy) = config.display.filter(|_| graphical) {
+                    let boot_services = system_table.boot_services();
+                    if let Err(error) = display::apply_display_policy(boot_services, policy) {
+                        warn!("Unable to select display {:?} => {:?}\n", policy, error);
+                    }
+                }
+                let filter = config.mode_filter;
+                if graphical && !filter.is_empty() {
+                    let boot_services = system_table.boot_services();
+                    if let Err(error) = display::apply_mode_filter(boot_services, filter) {
+                        warn!("Unable to apply mode filter {:?} => {:?}\n", filter, error);
+                    }
+                }
+                if let Some(orientation) = config.orientation.filter(|_| graphical) {
+                    libgraphics::set_orientation(orientation)?;
+                }
+                (
+                    config.entries().collect::<Vec<_>>(),
+                    config.timeout.or(Some(DEFAULT_TIMEOUT)),
+                    config.default_index(),
+                    config.keymap.unwrap_or(&US),
+                    None,
+                )
+            }
+            Err(diagnostic) => (Vec::from([FALLBACK_ENTRY]), None, 0, &US, Some(diagnostic)),
+        };
+    if entries.is_empty() {
+        if !report_found {
+            return Ok(FALLBACK_ENTRY);
+        }
+        entries.push(FALLBACK_ENTRY);
+    }
+
+    // The menu can only be drawn on the graphical console, so the default entry is booted
+    if !graphical {
+        info!("Boot menu isn't available on the text console, booting the default entry\n");
+        return Ok(entries[selected]);
+    }
+    let timeout = match timeout {
+        Some(0) if report_found => Some(DEFAULT_TIMEOUT),
+        Some(0) => return Ok(entries[selected]),
+        timeout => timeout,
+    };
+
+    // The pointer is opened with a copy of the system table, because the keyboard input borrows the
+    // system table mutably. The protocol is closed, when the menu returns.
+    let pointer_table = unsafe { system_table.unsafe_clone() };
+    let mut pointer = PointerContext::open(pointer_table.boot_services());
+
+    // Wait for the user or the timeout and count down the remaining time in steps of 100 ms
+    let mut remaining = timeout.map(|timeout| timeout * 10);
+    let power_line = power_status.status_line();
+    let mut key_hints = Vec::new();
+    if pointer.is_some() {
+        key_hints.push(Message::ClickHint);
+    }
+    if file_system_context.volumes.len() > 1 {
+        key_hints.push(Message::VolumeHint);
+    }
+    loop {
+        let mut hints = Vec::new();
+        if crash_report.is_some() {
+            hints.push(Message::CrashReportHint);
+        }
+        if previous_log.is_some() {
+            hints.push(Message::PreviousLogHint);
+        }
+
+        // The status bar shows the volume, which was selected for the boot files
+        let volume_line = file_system_context.preferred_volume.map(|index| {
+            let volume = &file_system_context.volume_infos[index];
+            format_message(Message::SelectedVolume, &[&(index + 1), volume])
+        });
+        let status_parts: Vec<&str> = [power_line.as_deref(), volume_line.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let status_line = (!status_parts.is_empty()).then(|| status_parts.join(" | "));
+        let first_entry_row = draw_menu(
+            &entries,
+            selected,
+            diagnostic.as_ref(),
+            &hints,
+            status_line.as_deref(),
+            remaining.map(|ticks| (ticks + 9) / 10),
+            &key_hints,
+        )?;
+        match read_key(system_table, keymap)? {
+            Some(KeyCode::Up) => {
+                selected = selected.checked_sub(1).unwrap_or(entries.len() - 1);
+                remaining = None;
+            }
+            Some(KeyCode::Down) => {
+                selected = (selected + 1) % entries.len();
+                remaining = None;
+            }
+            Some(KeyCode::Char(char @ '1'..='9')) => {
+                let index = char as usize - '1' as usize;
+                selected = if index < entries.len() {
+                    index
+                } else {
+                    selected
+                };
+                remaining = None;
+            }
+            Some(KeyCode::Enter) => break,
+            Some(KeyCode::Char('e')) => {
+                // Boot the entry with the edited command line or return to the menu
+                let mut history = History::load(system_table);
+                let entry = &mut entries[selected];
+                match edit_command_line(system_table, keymap, entry.title, entry.cmdline, &history)? {
+                    Some(command_line) => {
+                        history.push(system_table, &command_line);
+                        entry.cmdline = command_line.leak();
+                        break;
+                    }
+                    None => remaining = None,
+                }
+            }
+            Some(KeyCode::Char('d')) => {
+                // Move the menu to the next display
+                let display_count = display::displays().len();
+                let DisplayId(primary) = display::primary_display()?;
+                display::select_display(DisplayId((primary + 1) % display_count))?;
+                remaining = None;
+            }
+            Some(KeyCode::Char('m')) => {
+                let mirroring = !display::is_mirroring();
+                display::set_mirroring(pointer_table.boot_services(), mirroring)?;
+                remaining = None;
+            }
+            Some(KeyCode::Char('c')) if crash_report.is_some() => {
+                let title = format_message(Message::CrashReportTitle, &[&CRASH_PATH]);
+                let report = crash_report.unwrap_or_default();
+                if show_report(system_table, keymap, &title, report, &DELETE_ACTION)? {
+                    *crash_report = None;
+                }
+                remaining = None;
+            }
+            Some(KeyCode::Char('l')) if previous_log.is_some() => {
+                let action = ReportAction {
+                    key: 's',
+                    description: Message::SaveLog,
+                };
+                let log = previous_log.as_mut().unwrap();
+                let title = text(Message::LogTitle);
+                if show_report(system_table, keymap, title, &log.data, &action)? {
+                    log.save_requested = true;
+                }
+                remaining = None;
+            }
+            Some(KeyCode::Char('v')) if file_system_context.volumes.len() > 1 => {
+                let kernel = entries[selected].kernel;
+                if let Some(index) = select_volume(system_table, keymap, file_system_context, kernel)?
+                {
+                    let volume = &file_system_context.volume_infos[index];
+                    info!("Selected volume #{} for the boot files => {}\n", index + 1, volume);
+                    file_system_context.preferred_volume = Some(index);
+                }
+                remaining = None;
+            }
+            Some(KeyCode::Char('t')) => {
+                // Run the self tests until the user returns to the menu
+                let action = ReportAction {
+                    key: 'r',
+                    description: Message::RunAgain,
+                };
+                let title = text(Message::DiagnosticsTitle);
+                loop {
+                    let summary = selftest::run_diagnostics(file_system_context);
+                    if !show_report(system_table, keymap, title, summary.as_bytes(), &action)? {
+                        break;
+                    }
+                }
+                remaining = None;
+            }
+            Some(_) => remaining = None,
+            None => {}
+        }
+
+        // Select the entry under the cursor and boot it, when it's clicked
+        if let Some(pointer) = pointer.as_mut() {
+            let event = pointer.poll()?;
+            let hovered = entry_at(pointer.y, first_entry_row, entries.len())?;
+            match (event, hovered) {
+                (Some(PointerEvent::Clicked), Some(index)) => {
+                    selected = index;
+                    break;
+                }
+                (Some(_), hovered) => {
+                    selected = hovered.unwrap_or(selected);
+                    remaining = None;
+                }
+                (None, _) => {}
+            }
+        }
+
+        match remaining {
+            Some(0) => break,
+            Some(ticks) => remaining = Some(ticks - 1),
+            None => {}
+        }
+        system_table.boot_services().stall(POLL_INTERVAL);
+    }
+
+    // Clear the menu, so the log of the boot starts at the top of the screen
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    set_cursor(0, 0)?;
+    Ok(entries[selected])
+}
+
+/// This function reads the battery and thermal status from the DSDT and the SSDTs. The Boot Services
+/// identity-map the memory, so the tables are read with the identity mapper.
+pub fn read_power_status(rsdp_address: PhysAddr) -> Result<PowerStatus, Error> {
+    let tables = AcpiTables::new(rsdp_address.as_u64(), identity_mapper)?;
+    let aml_tables = tables.aml_tables()?;
+    Ok(PowerStatus {
+        battery: aml_tables
+            .iter()
+            .find_map(|aml| aml::find_battery_status(aml)),
+        temperature: aml_tables.iter().find_map(|aml| aml::find_temperature(aml)),
+    })
+}
+
+/// This function reads the next key from the firmware and translates it into a key code. The
+/// firmware translates the keys with the US layout, so printable characters are remapped into the
+/// keymap of the boot configuration.
+pub fn read_key(
+    system_table: &mut SystemTable<Boot>, keymap: &Keymap,
+) -> Result<Option<KeyCode>, Error> {
+    Ok(match system_table.stdin().read_key()? {
+        Some(Key::Special(scan_code)) => {
+            match scan_code {
+                ScanCode::UP => Some(KeyCode::Up),
+                ScanCode::DOWN => Some(KeyCode::Down),
+                ScanCode::LEFT => Some(KeyCode::Left),
+                ScanCode::RIGHT => Some(KeyCode::Right),
+                ScanCode::HOME => Some(KeyCode::Home),
+                ScanCode::END => Some(KeyCode::End),
+                ScanCode::INSERT => Some(KeyCode::Insert),
+                ScanCode::DELETE => Some(KeyCode::Delete),
+                ScanCode::PAGE_UP => Some(KeyCode::PageUp),
+                ScanCode::PAGE_DOWN => Some(KeyCode::PageDown),
+                ScanCode::ESCAPE => Some(KeyCode::Escape),
+                _ => None,
+            }
+        }
+        Some(Key::Printable(key)) => {
+            match char::from(key) {
+                '\r' => Some(KeyCode::Enter),
+                '\x08' => Some(KeyCode::Backspace),
+                '\t' => Some(KeyCode::Tab),
+                char => Some(KeyCode::Char(keymap.remap_from(&US, char))),
+            }
+        }
+        None => None,
+    })
+}
+
+fn parse_config(config_data: &[u8]) -> Result<BootConfig<'_>, Diagnostic<'_>> {
+    BootConfig::parse_bytes(config_data).map_err(|error| {
+        // The source line is only shown, if the configuration is valid UTF-8
+        let source_line = match error {
+            CoreError::InvalidConfig(line, _, _) => {
+                core::str::from_utf8(config_data)
+                    .ok()
+                    .and_then(|text| text.lines().nth(line - 1))
+            }
+            _ => None,
+        };
+        Diagnostic { error, source_line }
+    })
+}
+
+/// This function returns the index of the entry, which is shown in the row under the cursor
+fn entry_at(y: usize, first_entry_row: usize, entry_count: usize) -> Result<Option<usize>, Error> {
+    let (_, character_height) = character_size()?;
+    Ok((y / character_height)
+        .checked_sub(first_entry_row)
+        .filter(|index| *index < entry_count))
+}
+
+/// This function draws the boot menu with the hints (like the crash report hint) above the entries
+/// and the status bar below the title. The optional key hints (like the click hint, if a pointer
+/// exists) are shown below the default key hints. It returns the row of the first entry.
+fn draw_menu(
+    entries: &[BootEntry], selected: usize, diagnostic: Option<&Diagnostic>, hints: &[Message],
+    status_line: Option<&str>, remaining_seconds: Option<u64>, key_hints: &[Message],
+) -> Result<usize, Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    set_cursor(0, 0)?;
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    match status_line {
+        Some(status_line) => {
+            writeln!(context, "{}", text(Message::MenuTitle)).unwrap();
+            set_color(Rgb888::BLACK, DARK_GRAY)?;
+            writeln!(context, "{}\n", status_line).unwrap();
+        }
+        None => writeln!(context, "{}\n", text(Message::MenuTitle)).unwrap(),
+    }
+
+    // Show the diagnostic with the line of the error and a marker below the column
+    if let Some(diagnostic) = diagnostic {
+        set_color(Rgb888::BLACK, RED)?;
+        let message = format_message(Message::ConfigError, &[&CONFIG_PATH, &diagnostic.error]);
+        writeln!(context, "{}", message).unwrap();
+        if let (Some(source_line), CoreError::InvalidConfig(line, column, _)) =
+            (diagnostic.source_line, &diagnostic.error)
+        {
+            set_color(Rgb888::BLACK, DARK_GRAY)?;
+            writeln!(context, "{:>5} | {}", line, source_line).unwrap();
+            writeln!(context, "      | {:>1$}", "^", column).unwrap();
+        }
+        write_str("\n")?;
+    }
+
+    for hint in hints {
+        set_color(Rgb888::BLACK, ORANGE)?;
+        writeln!(context, "{}\n", text(*hint)).unwrap();
+    }
+
+    let (_, first_entry_row) = cursor()?;
+    for (index, entry) in entries.iter().enumerate() {
+        if index == selected {
+            set_color(LIGHT_BLUE, Rgb888::WHITE)?;
+        } else {
+            set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+        }
+        write!(context, " {:2}. {} ", index + 1, entry.title).unwrap();
+        set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+        write_str("\n")?;
+    }
+
+    // Show the details of the selected entry
+    let entry = &entries[selected];
+    set_color(Rgb888::BLACK, DARK_GRAY)?;
+    writeln!(context, "\n{}", format_message(Message::EntryKernel, &[&entry.kernel])).unwrap();
+    let initrd = entry.initrd.unwrap_or("-");
+    writeln!(context, "{}", format_message(Message::EntryInitrd, &[&initrd])).unwrap();
+    writeln!(context, "{}", format_message(Message::EntryCommandLine, &[&entry.cmdline])).unwrap();
+    if let Some((width, height)) = entry.resolution {
+        writeln!(context, "{}", format_message(Message::EntryResolution, &[&width, &height]))
+            .unwrap();
+    }
+    if let Some(url) = entry.netboot {
+        writeln!(context, "{}", format_message(Message::EntryNetboot, &[&url])).unwrap();
+    }
+    if let Some(units) = entry.units {
+        writeln!(context, "{}", format_message(Message::EntryBootUnits, &[&units])).unwrap();
+    }
+    if let Some(crash_kernel) = entry.crashkernel {
+        writeln!(context, "{}", format_message(Message::EntryCrashKernel, &[&crash_kernel])).unwrap();
+    }
+
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    writeln!(context, "\n{}", text(Message::SelectHint)).unwrap();
+    writeln!(context, "{}", text(Message::EditHint)).unwrap();
+    writeln!(context, "{}", text(Message::DiagnosticsHint)).unwrap();
+    for key_hint in key_hints {
+        writeln!(context, "{}", text(*key_hint)).unwrap();
+    }
+    let displays = display::displays();
+    if let Some(primary) = displays.iter().position(|display| display.primary) {
+        if displays.len() > 1 {
+            let (width, height) = displays[primary].resolution;
+            let message = format_message(
+                Message::DisplayHint,
+                &[&(primary + 1), &displays.len(), &width, &height],
+            );
+            writeln!(context, "{}", message).unwrap();
+        }
+    }
+    if let Some(remaining_seconds) = remaining_seconds {
+        set_color(Rgb888::BLACK, ORANGE)?;
+        let message = format_message(Message::BootCountdown, &[&entry.title, &remaining_seconds]);
+        writeln!(context, "{}", message).unwrap();
+    }
+    libgraphics::swap_buffers()?;
+    Ok(first_entry_row)
+}
+
+/// This function shows the volumes with their label, size and free space and returns the index of
+/// the volume, which was selected by the user. The volumes, which contain the kernel of the selected
+/// entry, are marked. If the user returns with Escape, `None` is returned.
+fn select_volume(
+    system_table: &mut SystemTable<Boot>, keymap: &Keymap,
+    file_system_context: &mut SimpleFileSystemContext, kernel: &str,
+) -> Result<Option<usize>, Error> {
+    let volume_count = file_system_context.volumes.len();
+    let kernel_volumes: Vec<bool> = (0..volume_count)
+        .map(|index| files::contains_file(file_system_context, index, kernel))
+        .collect();
+    let mut selected = file_system_context.preferred_volume.unwrap_or(0);
+    loop {
+        draw_volumes(&file_system_context.volume_infos, &kernel_volumes, kernel, selected)?;
+        let key = loop {
+            if let Some(key) = read_key(system_table, keymap)? {
+                break key;
+            }
+            system_table.boot_services().stall(POLL_INTERVAL);
+        };
+
+        match key {
+            KeyCode::Up => selected = selected.checked_sub(1).unwrap_or(volume_count - 1),
+            KeyCode::Down => selected = (selected + 1) % volume_count,
+            KeyCode::Enter => return Ok(Some(selected)),
+            KeyCode::Escape => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+fn draw_volumes(
+    volumes: &[VolumeInfo], kernel_volumes: &[bool], kernel: &str, selected: usize,
+) -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    set_cursor(0, 0)?;
+    set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+    writeln!(context, "{}\n", text(Message::VolumesTitle)).unwrap();
+
+    for (index, (volume, contains_kernel)) in volumes.iter().zip(kernel_volumes).enumerate() {
+        if index == selected {
+            set_color(LIGHT_BLUE, Rgb888::WHITE)?;
+        } else {
+            set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+        }
+        write!(context, " {:2}. {} ", index + 1, volume).unwrap();
+        if *contains_kernel {
+            set_color(Rgb888::BLACK, ORANGE)?;
+            write!(context, " {}", format_message(Message::KernelOnVolume, &[&kernel])).unwrap();
+        }
+        set_color(Rgb888::BLACK, Rgb888::WHITE)?;
+        write_str("\n")?;
+    }
+
+    writeln!(context, "\n{}", text(Message::VolumesHint)).unwrap();
+    libgraphics::swap_buffers()?;
+    Ok(())
+}
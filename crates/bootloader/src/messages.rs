@@ -0,0 +1,171 @@
+//! The message catalog contains the user-facing texts of the boot UI (like the boot menu, the
+//! report viewer and the panic screen). The English texts are compiled in and can be overridden by
+//! a catalog file on the first volume, so the boot UI can be localized without changing the code.
+//!
+//! Every line of the catalog file has the format `<key> = "<text>"` and `#` starts a comment. The
+//! placeholders `{0}`, `{1}`, ... are replaced by the arguments of the message and can be reordered.
+
+use crate::error::Error;
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::fmt::{
+    Display,
+    Write,
+};
+use log::warn;
+
+pub const CATALOG_PATH: &str = "\\EFI\\OVERFLOW\\MESSAGES.TXT";
+
+/// The messages, which were loaded from the catalog file. Messages, which aren't in the catalog,
+/// use the English default.
+static mut CATALOG: Option<Vec<(Message, &'static str)>> = None;
+
+/// This macro declares the messages with their key in the catalog file and their English default
+macro_rules! messages {
+    ($($name:ident => $key:literal, $default:literal;)*) => {
+        /// A user-facing message of the boot UI
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum Message {
+            $($name,)*
+        }
+
+        impl Message {
+            pub const ALL: &'static [Self] = &[$(Self::$name),*];
+
+            /// This function returns the key of the message in the catalog file
+            pub const fn key(self) -> &'static str {
+                match self {
+                    $(Self::$name => $key,)*
+                }
+            }
+
+            /// This function returns the English text of the message
+            pub const fn default_text(self) -> &'static str {
+                match self {
+                    $(Self::$name => $default,)*
+                }
+            }
+        }
+    };
+}
+
+messages! {
+    MenuTitle => "menu.title", "OverflowOS Boot Menu";
+    ConfigError => "menu.config_error", "Unable to parse {0} => {1}";
+    CrashReportHint => "menu.crash_report_hint", "The last boot crashed, press 'c' to show the crash report";
+    PreviousLogHint => "menu.previous_log_hint", "The last boot ended unexpectedly, press 'l' to show or save the log";
+    EntryKernel => "menu.kernel", "Kernel: {0}";
+    EntryInitrd => "menu.initrd", "Initrd: {0}";
+    EntryCommandLine => "menu.cmdline", "Command Line: {0}";
+    EntryResolution => "menu.resolution", "Resolution: {0}x{1}";
+    EntryNetboot => "menu.netboot", "Netboot: {0}";
+    EntryBootUnits => "menu.units", "Boot Units: {0}";
+    EntryCrashKernel => "menu.crashkernel", "Crash Kernel: {0}";
+    Battery => "menu.battery", "Battery: {0}%";
+    BatteryCharging => "menu.battery_charging", "Battery: {0}% (charging)";
+    Temperature => "menu.temperature", "Temperature: {0}.{1} C";
+    SelectHint => "menu.select_hint", "Use the arrow keys or 1-9 to select an entry and press Enter to boot";
+    EditHint => "menu.edit_hint", "Press 'e' to edit the command line of the selected entry";
+    ClickHint => "menu.click_hint", "Click on an entry to boot it";
+    DiagnosticsHint => "menu.diagnostics_hint", "Press 't' to run the diagnostics";
+    VolumeHint => "menu.volume_hint", "Press 'v' to select the volume of the boot files";
+    SelectedVolume => "menu.volume", "Volume: #{0} {1}";
+    DisplayHint => "menu.display_hint", "Display {0} of {1} ({2}x{3}), press 'd' to switch and 'm' to mirror the display";
+    BootCountdown => "menu.countdown", "Booting '{0}' in {1} seconds";
+    CrashReportTitle => "report.crash_title", "Crash Report ({0})";
+    LogTitle => "report.log_title", "Log of the last boot";
+    DeleteReport => "report.delete", "delete the report";
+    SaveLog => "report.save_log", "save the log";
+    DiagnosticsTitle => "report.diagnostics_title", "Diagnostics";
+    RunAgain => "report.run_again", "run the tests again";
+    VolumesTitle => "volumes.title", "Volumes";
+    VolumesHint => "volumes.hint", "Use the arrow keys to select a volume, press Enter to use it or Escape to return";
+    KernelOnVolume => "volumes.kernel", "contains {0}";
+    ReportHint => "report.hint", "Use the arrow keys to scroll, press {0} to {1} and Escape to return";
+    EditorTitle => "editor.title", "Edit Command Line of '{0}'";
+    HistoryEntry => "editor.history_entry", "History entry {0}";
+    EditorHint => "editor.hint", "Press Enter to boot, Escape to cancel and Up/Down to browse the history";
+    PanicTitle => "panic.title", "Unrecoverable Error while booting into OverflowOS:";
+    PanicNoMessage => "panic.no_message", "No error message provided";
+    PanicLocation => "panic.location", " => Error found in {0} on {1}:{2}";
+    PanicCountdown => "panic.countdown", "Shutdown in {0} seconds, press Escape to cancel, R to reboot or S to save diagnostics {1}";
+    PanicActions => "panic.actions", "Press P to power off, R to reboot or S to save diagnostics {0}";
+    DiagnosticsSaved => "panic.saved", "(saved to \\EFI\\OVERFLOW)";
+    DiagnosticsFailed => "panic.save_failed", "(unable to save diagnostics)";
+}
+
+/// This function returns the text of the message from the catalog file or the English default
+pub fn text(message: Message) -> &'static str {
+    unsafe { CATALOG.as_ref() }
+        .and_then(|catalog| catalog.iter().find(|(entry, _)| *entry == message))
+        .map_or(message.default_text(), |(_, translation)| *translation)
+}
+
+/// This function returns the text of the message with the placeholders replaced by the arguments.
+/// Placeholders without argument are kept unchanged.
+pub fn format_message(message: Message, arguments: &[&dyn Display]) -> String {
+    let mut result = String::new();
+    let mut rest = text(message);
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let placeholder = rest[start..].find('}').and_then(|end| {
+            let index = rest[start + 1..start + end].parse::<usize>().ok()?;
+            Some((arguments.get(index)?, end))
+        });
+        match placeholder {
+            Some((argument, end)) => {
+                let _ = write!(result, "{}", argument);
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// This function loads the catalog file and overrides the defaults of the contained messages. It
+/// returns the number of loaded messages. Unknown keys (like from catalogs for newer versions) are
+/// skipped with a warning. If a line is malformed, this function returns an error.
+pub fn load_catalog(data: &'static [u8]) -> Result<usize, Error> {
+    let text = core::str::from_utf8(data).map_err(|error| {
+        Error::InvalidCatalog(
+            data[..error.valid_up_to()]
+                .split(|byte| *byte == b'\n')
+                .count(),
+        )
+    })?;
+
+    let mut catalog = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(Error::InvalidCatalog(index + 1))?;
+        let value = value
+            .trim()
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .ok_or(Error::InvalidCatalog(index + 1))?;
+        match Message::ALL
+            .iter()
+            .find(|message| message.key() == key.trim())
+        {
+            Some(message) => catalog.push((*message, value)),
+            None => warn!("Unknown message '{}' in line {} of catalog\n", key.trim(), index + 1),
+        }
+    }
+
+    let count = catalog.len();
+    unsafe { CATALOG = Some(catalog) };
+    Ok(count)
+}
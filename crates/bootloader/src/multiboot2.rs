@@ -0,0 +1,433 @@
+use crate::{
+    early_alloc::EARLY_ALLOCATOR,
+    elf_loader::LoadedKernel,
+    error::Error,
+};
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::{
+        BootInfo,
+        PixelFormat,
+        TlsTemplate,
+    },
+    elf::{
+        ElfFile,
+        ELF_MAGIC,
+        PT_LOAD,
+    },
+    error::Error as CoreError,
+    fastmem,
+    memory_map::MemoryMap,
+    multiboot2::{
+        InfoWriter,
+        Multiboot2Header,
+        INFO_TAG_ACPI_NEW,
+        INFO_TAG_ACPI_OLD,
+        INFO_TAG_BASIC_MEMORY,
+        INFO_TAG_BOOTLOADER_NAME,
+        INFO_TAG_COMMAND_LINE,
+        INFO_TAG_EFI64_SYSTEM_TABLE,
+        INFO_TAG_FRAMEBUFFER,
+        INFO_TAG_LOAD_BASE_ADDRESS,
+        INFO_TAG_MEMORY_MAP,
+        INFO_TAG_MODULE,
+        MEMORY_ACPI_RECLAIMABLE,
+        MEMORY_AVAILABLE,
+        MEMORY_BAD,
+        MEMORY_NVS,
+        MEMORY_RESERVED,
+    },
+};
+use log::{
+    info,
+    warn,
+};
+use uefi::{
+    prelude::BootServices,
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// The kernel, the information and the trampoline must be below 4 GiB, because the kernel is entered
+/// in 32-bit protected mode without paging
+const MAX_ADDRESS: u64 = 0xFFFF_FFFF;
+const INFO_PAGES: usize = 4;
+
+const ELF_CLASS_32: u8 = 1;
+const ELF_MACHINE_I386: u16 = 3;
+const MEMORY_MAP_ENTRY_SIZE: u32 = 24;
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// The values of a Multiboot2 kernel, which are needed after exiting the Boot Services
+pub struct Multiboot2Kernel {
+    pub entry: u32,
+    /// The buffer for the information below 4 GiB, it's filled after exiting the Boot Services
+    pub info_buffer: &'static mut [u8],
+}
+
+/// A loadable segment of an ELF32 or ELF64 kernel with its physical load address
+struct Segment {
+    physical_address: u64,
+    offset: usize,
+    file_size: usize,
+    memory_size: u64,
+}
+
+extern "sysv64" {
+    fn multiboot2_trampoline(entry: u32, info_address: u32) -> !;
+}
+
+// The trampoline switches from long mode into 32-bit protected mode without paging: It loads a GDT
+// with flat 32-bit segments, returns into the compatibility mode code segment, disables paging and
+// long mode and jumps to the entry with the Multiboot2 magic in eax and the information in ebx. The
+// code and the GDT must be identity-mapped below 4 GiB.
+global_asm!(
+    ".global multiboot2_trampoline",
+    "multiboot2_trampoline:",
+    "cli",
+    "lgdt [rip + multiboot2_gdt_pointer]",
+    "lea rax, [rip + multiboot2_protected_mode]",
+    "push 0x08",
+    "push rax",
+    "retfq",
+    ".code32",
+    "multiboot2_protected_mode:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    "mov eax, cr0",
+    "and eax, 0x7FFFFFFF",
+    "mov cr0, eax",
+    "mov ecx, 0xC0000080",
+    "rdmsr",
+    "and eax, 0xFFFFFEFF",
+    "wrmsr",
+    "mov eax, cr4",
+    "and eax, 0xFFFFFFDF",
+    "mov cr4, eax",
+    "mov eax, {magic}",
+    "mov ebx, esi",
+    "jmp edi",
+    ".code64",
+    ".balign 8",
+    "multiboot2_gdt:",
+    ".quad 0",
+    ".quad 0x00CF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    "multiboot2_gdt_pointer:",
+    ".word 23",
+    ".quad multiboot2_gdt",
+    magic = const libcore::multiboot2::BOOTLOADER_MAGIC,
+);
+
+/// This function loads a Multiboot2 kernel at the physical addresses of the address tag or of the
+/// ELF segments and allocates the buffer for the information. If the kernel prefers a framebuffer
+/// mode, the mode is set before the Boot Services are exited.
+pub fn load_multiboot2_kernel(
+    boot_services: &BootServices, data: &[u8],
+) -> Result<(LoadedKernel, Multiboot2Kernel), Error> {
+    let trampoline = multiboot2_trampoline as usize as u64;
+    if trampoline > MAX_ADDRESS {
+        return Err(Error::Multiboot2AddressTooHigh(trampoline));
+    }
+
+    let header = Multiboot2Header::find(data)?;
+    let (segments, elf_entry) = match header.address {
+        Some(address) => {
+            // The file offset of the load address is calculated from the offset of the header
+            let start = address
+                .header_address
+                .checked_sub(address.load_address)
+                .and_then(|distance| header.offset.checked_sub(distance as usize))
+                .ok_or(CoreError::InvalidMultiboot2Header)?;
+            let file_size = match address.load_end_address {
+                0 => data.len().saturating_sub(start),
+                end => end.saturating_sub(address.load_address) as usize,
+            };
+            let memory_size = match address.bss_end_address {
+                0 => file_size as u64,
+                end => end.saturating_sub(address.load_address) as u64,
+            };
+            let segment = Segment {
+                physical_address: address.load_address as u64,
+                offset: start,
+                file_size,
+                memory_size: memory_size.max(file_size as u64),
+            };
+            (Vec::from([segment]), None)
+        }
+        None => {
+            let (segments, entry) = elf_segments(data)?;
+            (segments, Some(entry))
+        }
+    };
+    let entry = header
+        .entry_address
+        .map(|entry| entry as u64)
+        .or(elf_entry)
+        .ok_or(CoreError::InvalidMultiboot2Header)?;
+
+    // Allocate the memory of all segments at once, because segments can share pages
+    let start_address = segments
+        .iter()
+        .map(|segment| segment.physical_address & !(PAGE_SIZE - 1))
+        .min()
+        .ok_or(Error::NoLoadableSegments)?;
+    let end_address = segments
+        .iter()
+        .map(|segment| segment.physical_address + segment.memory_size)
+        .max()
+        .unwrap_or(start_address);
+    if end_address > MAX_ADDRESS || entry > MAX_ADDRESS {
+        return Err(Error::Multiboot2AddressTooHigh(end_address.max(entry)));
+    }
+    let size = (end_address - start_address + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let page_count = (size / PAGE_SIZE) as usize;
+    boot_services.allocate_pages(
+        AllocateType::Address(start_address),
+        MemoryType::LOADER_DATA,
+        page_count,
+    )?;
+    unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(start_address), page_count)? };
+
+    // Copy the file data of the segments, the remaining memory is zeroed
+    let image = unsafe { core::slice::from_raw_parts_mut(start_address as *mut u8, size as usize) };
+    fastmem::zero(image);
+    for segment in segments.iter() {
+        let file_size = segment.file_size.min(segment.memory_size as usize);
+        let source = data
+            .get(segment.offset..segment.offset + file_size)
+            .ok_or(CoreError::ElfOutOfBounds(segment.offset))?;
+        let offset = (segment.physical_address - start_address) as usize;
+        fastmem::copy(&mut image[offset..offset + source.len()], source);
+    }
+
+    if let Some((width, height, _)) = header
+        .framebuffer
+        .filter(|(width, height, _)| *width != 0 && *height != 0)
+    {
+        if let Err(error) =
+            libgraphics::set_resolution(boot_services, width as usize, height as usize)
+        {
+            warn!("Unable to set preferred resolution {}x{} => {:?}\n", width, height, error);
+        }
+    }
+
+    let info_address = boot_services.allocate_pages(
+        AllocateType::MaxAddress(MAX_ADDRESS),
+        MemoryType::LOADER_DATA,
+        INFO_PAGES,
+    )?;
+    unsafe { EARLY_ALLOCATOR.register_region(PhysAddr::new(info_address), INFO_PAGES)? };
+    info!(
+        "Loaded Multiboot2 kernel at 0x{:X} ({} segments, {} kB)\n",
+        start_address,
+        segments.len(),
+        size / 1024
+    );
+
+    let kernel = LoadedKernel {
+        address: PhysAddr::new(start_address),
+        size,
+        entry: VirtAddr::new(entry),
+        slide: 0,
+        tls_template: TlsTemplate::NONE,
+    };
+    let multiboot2_kernel = Multiboot2Kernel {
+        entry: entry as u32,
+        info_buffer: unsafe {
+            core::slice::from_raw_parts_mut(info_address as *mut u8, INFO_PAGES * PAGE_SIZE as usize)
+        },
+    };
+    Ok((kernel, multiboot2_kernel))
+}
+
+/// This function writes the Multiboot2 information from the boot information and the final memory
+/// map into the buffer and returns the address of the information. The initrd is passed as module,
+/// if it's below 4 GiB.
+pub fn write_info(
+    buffer: &mut [u8], boot_info: &BootInfo, memory_map: &MemoryMap, system_table_address: u64,
+) -> Result<u32, Error> {
+    let address = buffer.as_ptr() as u32;
+    let mut writer = InfoWriter::new(buffer)?;
+
+    // The boot information is read with identity-mapped memory, so the addresses are used directly
+    let command_line = unsafe {
+        core::slice::from_raw_parts(
+            boot_info.command_line_address.as_u64() as *const u8,
+            boot_info.command_line_size as usize,
+        )
+    };
+    writer.begin_tag(INFO_TAG_COMMAND_LINE)?;
+    writer.write(command_line)?;
+    writer.write(&[0])?;
+    writer.end_tag()?;
+    writer.push(INFO_TAG_BOOTLOADER_NAME, b"OverflowOS Bootloader\0")?;
+    writer.push(
+        INFO_TAG_LOAD_BASE_ADDRESS,
+        &(boot_info.kernel_address.as_u64() as u32).to_le_bytes(),
+    )?;
+
+    let initrd_end = boot_info.initrd_address.as_u64() + boot_info.initrd_size;
+    match (boot_info.initrd_address.is_null(), initrd_end <= MAX_ADDRESS) {
+        (true, _) => {}
+        (false, true) => {
+            writer.begin_tag(INFO_TAG_MODULE)?;
+            writer.write(&(boot_info.initrd_address.as_u64() as u32).to_le_bytes())?;
+            writer.write(&(initrd_end as u32).to_le_bytes())?;
+            writer.write(b"initrd\0")?;
+            writer.end_tag()?;
+        }
+        (false, false) => warn!("Initrd is above 4 GiB, it's not passed as module\n"),
+    }
+
+    // The lower memory is counted up to 640 KiB and the upper memory up to the first hole after 1 MiB
+    let available_end = |start: u64| {
+        let mut end = start;
+        while let Some(descriptor) = memory_map.entries().find(|descriptor| {
+            memory_type(descriptor.ty) == MEMORY_AVAILABLE
+                && descriptor.phys_start <= end
+                && descriptor.phys_start + descriptor.page_count * PAGE_SIZE > end
+        }) {
+            end = descriptor.phys_start + descriptor.page_count * PAGE_SIZE;
+        }
+        end
+    };
+    let lower_memory = available_end(0).min(0xA0000) / 1024;
+    let upper_memory = (available_end(0x100000) - 0x100000) / 1024;
+    writer.begin_tag(INFO_TAG_BASIC_MEMORY)?;
+    writer.write(&(lower_memory as u32).to_le_bytes())?;
+    writer.write(&(upper_memory.min(MAX_ADDRESS) as u32).to_le_bytes())?;
+    writer.end_tag()?;
+
+    writer.begin_tag(INFO_TAG_MEMORY_MAP)?;
+    writer.write(&MEMORY_MAP_ENTRY_SIZE.to_le_bytes())?;
+    writer.write(&0u32.to_le_bytes())?;
+    for descriptor in memory_map.entries() {
+        writer.write(&descriptor.phys_start.to_le_bytes())?;
+        writer.write(&(descriptor.page_count * PAGE_SIZE).to_le_bytes())?;
+        writer.write(&memory_type(descriptor.ty).to_le_bytes())?;
+        writer.write(&0u32.to_le_bytes())?;
+    }
+    writer.end_tag()?;
+
+    let framebuffer = &boot_info.framebuffer;
+    if !framebuffer.address.is_null() {
+        // The positions and sizes of the red, green and blue fields
+        let (depth, fields): (u8, [u8; 6]) = match framebuffer.pixel_format {
+            PixelFormat::Xrgb8888 => (32, [16, 8, 8, 8, 0, 8]),
+            PixelFormat::Xbgr8888 => (32, [0, 8, 8, 8, 16, 8]),
+            PixelFormat::Rgb888Packed => (24, [16, 8, 8, 8, 0, 8]),
+            PixelFormat::Rgb565 => (16, [11, 5, 5, 6, 0, 5]),
+        };
+        writer.begin_tag(INFO_TAG_FRAMEBUFFER)?;
+        writer.write(&framebuffer.address.as_u64().to_le_bytes())?;
+        writer.write(&(framebuffer.stride * depth as u32 / 8).to_le_bytes())?;
+        writer.write(&framebuffer.width.to_le_bytes())?;
+        writer.write(&framebuffer.height.to_le_bytes())?;
+        writer.write(&[depth, FRAMEBUFFER_TYPE_RGB, 0, 0])?;
+        writer.write(&fields)?;
+        writer.end_tag()?;
+    }
+
+    writer.push(INFO_TAG_EFI64_SYSTEM_TABLE, &system_table_address.to_le_bytes())?;
+
+    // The tags contain a copy of the RSDP, the revision selects the size
+    if !boot_info.rsdp_address.is_null() {
+        let rsdp = boot_info.rsdp_address.as_u64() as *const u8;
+        match unsafe { *rsdp.add(15) } {
+            0 => writer.push(INFO_TAG_ACPI_OLD, unsafe { core::slice::from_raw_parts(rsdp, 20) })?,
+            _ => writer.push(INFO_TAG_ACPI_NEW, unsafe { core::slice::from_raw_parts(rsdp, 36) })?,
+        }
+    }
+
+    writer.finish()?;
+    Ok(address)
+}
+
+/// This function enters the kernel in 32-bit protected mode. The Boot Services must be exited.
+pub fn enter_kernel(kernel: &Multiboot2Kernel, info_address: u32) -> ! {
+    unsafe { multiboot2_trampoline(kernel.entry, info_address) }
+}
+
+/// This function returns the loadable segments and the entry of an ELF32 (i386) or ELF64 kernel.
+/// Multiboot2 kernels are loaded at the physical addresses of the segments.
+fn elf_segments(data: &[u8]) -> Result<(Vec<Segment>, u64), Error> {
+    if data.get(..4) != Some(&ELF_MAGIC[..]) {
+        return Err(CoreError::InvalidElfMagic.into());
+    }
+
+    if data.get(4) != Some(&ELF_CLASS_32) {
+        let elf = ElfFile::parse(data)?;
+        let mut segments = Vec::new();
+        for segment in elf.program_headers() {
+            let segment = segment?;
+            if segment.kind == PT_LOAD {
+                segments.push(Segment {
+                    physical_address: segment.physical_address,
+                    offset: segment.offset as usize,
+                    file_size: segment.file_size as usize,
+                    memory_size: segment.memory_size,
+                });
+            }
+        }
+        return Ok((segments, elf.header.entry));
+    }
+
+    let read_u16 = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(CoreError::ElfOutOfBounds(offset))
+    };
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(CoreError::ElfOutOfBounds(offset))
+    };
+    let machine = read_u16(0x12)?;
+    if machine != ELF_MACHINE_I386 {
+        return Err(CoreError::UnsupportedElf(ELF_CLASS_32, machine).into());
+    }
+
+    let program_header_offset = read_u32(0x1C)? as usize;
+    let program_header_entry_size = read_u16(0x2A)? as usize;
+    let mut segments = Vec::new();
+    for index in 0..read_u16(0x2C)? as usize {
+        let offset = program_header_offset + index * program_header_entry_size;
+        if read_u32(offset)? == PT_LOAD {
+            segments.push(Segment {
+                physical_address: read_u32(offset + 12)? as u64,
+                offset: read_u32(offset + 4)? as usize,
+                file_size: read_u32(offset + 16)? as usize,
+                memory_size: read_u32(offset + 20)? as u64,
+            });
+        }
+    }
+    Ok((segments, read_u32(0x18)? as u64))
+}
+
+/// This function returns the Multiboot2 type of the UEFI memory type. The memory of the Boot Services
+/// is available after exiting them, the memory of the bootloader (like the modules) is reserved.
+fn memory_type(memory_type: MemoryType) -> u32 {
+    match memory_type {
+        MemoryType::CONVENTIONAL
+        | MemoryType::BOOT_SERVICES_CODE
+        | MemoryType::BOOT_SERVICES_DATA => MEMORY_AVAILABLE,
+        MemoryType::ACPI_RECLAIM => MEMORY_ACPI_RECLAIMABLE,
+        MemoryType::ACPI_NON_VOLATILE => MEMORY_NVS,
+        MemoryType::UNUSABLE => MEMORY_BAD,
+        _ => MEMORY_RESERVED,
+    }
+}
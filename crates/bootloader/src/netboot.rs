@@ -0,0 +1,517 @@
+use crate::{
+    early_alloc::early_alloc,
+    error::Error,
+    files::optional_file,
+    verify::decode_sha256,
+};
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    vec,
+    vec::Vec,
+};
+use core::{
+    ffi::c_void,
+    ptr,
+};
+use libcore::tftp;
+use log::{
+    info,
+    warn,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use uefi::{
+    prelude::BootServices,
+    proto::{
+        device_path::{
+            DevicePath,
+            DeviceSubType,
+            DeviceType,
+        },
+        network::{
+            pxe::{
+                BaseCode,
+                DhcpV4Packet,
+            },
+            IpAddress,
+        },
+        unsafe_protocol,
+    },
+    table::boot::{
+        OpenProtocolAttributes,
+        OpenProtocolParams,
+        ScopedProtocol,
+        SearchType,
+    },
+    CStr8,
+    Guid,
+    Handle,
+    Identify,
+    Status,
+};
+
+/// The load option, which enables the network boot (`netboot=tftp://<server>/<directory>` or
+/// `netboot=http://<server>/<directory>`)
+pub const NETBOOT_OPTION: &str = "netboot";
+
+/// The download progress is reported in steps of 10 percent
+const PROGRESS_STEP: usize = 10;
+
+/// The type of data, for which the HTTP boot callback reports the received payload
+const HTTP_BOOT_ENTITY_BODY: u32 = 4;
+
+/// A netboot URL has the format `tftp://<server>/<directory>` or `http://<server>/<directory>`
+pub enum NetbootUrl<'a> {
+    /// If the server is omitted (like in `tftp:///boot`), the boot server from the DHCP
+    /// acknowledgement is used.
+    Tftp {
+        server: Option<[u8; 4]>,
+        directory: &'a str,
+    },
+    /// The files are downloaded with the HTTP boot driver of the firmware, so the server can be
+    /// specified with a host name and a port.
+    Http { base_url: &'a str },
+}
+
+impl<'a> NetbootUrl<'a> {
+    pub fn parse(url: &'a str) -> Result<Self, Error> {
+        if let Some(location) = url.strip_prefix("tftp://") {
+            let (host, directory) = location.split_once('/').unwrap_or((location, ""));
+            let server = match host.is_empty() {
+                true => None,
+                false => {
+                    Some(parse_ipv4(host).ok_or_else(|| Error::InvalidNetbootUrl(url.to_string()))?)
+                }
+            };
+            return Ok(Self::Tftp {
+                server,
+                directory: directory.trim_matches('/'),
+            });
+        }
+
+        let location = url
+            .strip_prefix("http://")
+            .or_else(|| url.strip_prefix("https://"))
+            .ok_or_else(|| Error::InvalidNetbootUrl(url.to_string()))?;
+        if location.split('/').next().unwrap_or_default().is_empty() {
+            return Err(Error::InvalidNetbootUrl(url.to_string()));
+        }
+        Ok(Self::Http {
+            base_url: url.trim_end_matches('/'),
+        })
+    }
+}
+
+/// The Load File Protocol, which is provided by the HTTP boot driver of the firmware. The file is
+/// selected with an URI device path.
+#[repr(C)]
+#[unsafe_protocol("56ec3091-954c-11d2-8e3f-00a0c969723b")]
+struct LoadFile {
+    load_file: unsafe extern "efiapi" fn(
+        this: *mut LoadFile,
+        file_path: *const u8,
+        boot_policy: bool,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+}
+
+/// The callback of the PXE Base Code Protocol, which is called for every received TFTP packet
+#[repr(C)]
+#[unsafe_protocol("245dca21-fb7b-11d3-8f01-00a0c969723b")]
+struct PxeBaseCodeCallback {
+    revision: u64,
+    callback: unsafe extern "efiapi" fn(
+        this: *mut PxeBaseCodeCallback,
+        function: u32,
+        received: bool,
+        packet_length: u32,
+        packet: *const u8,
+    ) -> u32,
+}
+
+/// The callback of the HTTP boot driver, which is called for every received part of the body
+#[repr(C)]
+#[unsafe_protocol("ba23b311-343d-11e6-9185-5820b1d65299")]
+struct HttpBootCallback {
+    callback: unsafe extern "efiapi" fn(
+        this: *mut HttpBootCallback,
+        data_type: u32,
+        received: bool,
+        data_length: u32,
+        data: *const c_void,
+    ) -> Status,
+}
+
+/// The progress of the current download, which is updated by the callbacks of the firmware
+struct DownloadProgress {
+    total: usize,
+    received: usize,
+    reported_percent: usize,
+}
+
+static mut DOWNLOAD_PROGRESS: DownloadProgress = DownloadProgress {
+    total: 0,
+    received: 0,
+    reported_percent: 0,
+};
+static mut PXE_CALLBACK: PxeBaseCodeCallback = PxeBaseCodeCallback {
+    revision: 0x0001_0000,
+    callback: pxe_callback,
+};
+static mut HTTP_CALLBACK: HttpBootCallback = HttpBootCallback {
+    callback: http_callback,
+};
+
+enum Transport<'a> {
+    Tftp {
+        protocol: ScopedProtocol<'a, BaseCode>,
+        server: IpAddress,
+        directory: String,
+    },
+    Http {
+        protocol: ScopedProtocol<'a, LoadFile>,
+        base_url: String,
+    },
+}
+
+/// The netboot context downloads files from the boot server. TFTP downloads use the PXE Base Code
+/// Protocol of the firmware, which acquires an address over DHCP. HTTP downloads use the HTTP boot
+/// driver of the firmware. The progress is reported with the callback protocols of both drivers.
+pub struct NetbootContext<'a> {
+    transport: Transport<'a>,
+    boot_services: &'a BootServices,
+    handle: Handle,
+    callback: Option<(&'static Guid, *mut c_void)>,
+}
+
+impl<'a> NetbootContext<'a> {
+    pub fn new(boot_services: &'a BootServices, url: &NetbootUrl) -> Result<Self, Error> {
+        let mut context = match url {
+            NetbootUrl::Tftp { server, directory } => {
+                Self::new_tftp(boot_services, *server, directory)?
+            }
+            NetbootUrl::Http { base_url } => Self::new_http(boot_services, base_url)?,
+        };
+        context.install_callback();
+        Ok(context)
+    }
+
+    fn new_tftp(
+        boot_services: &'a BootServices, server: Option<[u8; 4]>, directory: &str,
+    ) -> Result<Self, Error> {
+        let handle = boot_services.get_handle_for_protocol::<BaseCode>()?;
+        let mut protocol = boot_services.open_protocol_exclusive::<BaseCode>(handle)?;
+        if !protocol.mode().started {
+            protocol.start(false)?;
+        }
+
+        if !protocol.mode().dhcp_ack_received {
+            info!("Requesting network configuration over DHCP\n");
+            protocol.dhcp(false)?;
+        }
+
+        let server = match server {
+            Some(server) => server,
+            None => {
+                let acknowledgement: &DhcpV4Packet = protocol.mode().dhcp_ack.as_ref();
+                acknowledgement.bootp_si_addr
+            }
+        };
+        info!(
+            "Using boot server {}.{}.{}.{} for network boot\n",
+            server[0], server[1], server[2], server[3]
+        );
+
+        Ok(Self {
+            transport: Transport::Tftp {
+                protocol,
+                server: IpAddress::new_v4(server),
+                directory: directory.to_string(),
+            },
+            boot_services,
+            handle,
+            callback: None,
+        })
+    }
+
+    fn new_http(boot_services: &'a BootServices, base_url: &str) -> Result<Self, Error> {
+        let handle = find_http_boot_handle(boot_services).ok_or(Error::NoHttpBoot)?;
+        let protocol = boot_services.open_protocol_exclusive::<LoadFile>(handle)?;
+        info!("Using {} for network boot\n", base_url);
+
+        Ok(Self {
+            transport: Transport::Http {
+                protocol,
+                base_url: base_url.to_string(),
+            },
+            boot_services,
+            handle,
+            callback: None,
+        })
+    }
+
+    /// This function installs the callback protocol of the transport on the handle of the driver.
+    /// Without the callback, the downloads work, but the progress isn't reported.
+    fn install_callback(&mut self) {
+        let (guid, interface) = match &self.transport {
+            Transport::Tftp { .. } => {
+                let interface = unsafe { ptr::addr_of_mut!(PXE_CALLBACK) } as *mut c_void;
+                (&PxeBaseCodeCallback::GUID, interface)
+            }
+            Transport::Http { .. } => {
+                let interface = unsafe { ptr::addr_of_mut!(HTTP_CALLBACK) } as *mut c_void;
+                (&HttpBootCallback::GUID, interface)
+            }
+        };
+
+        let result = unsafe {
+            self.boot_services
+                .install_protocol_interface(Some(self.handle), guid, interface)
+        };
+        if result.is_err() {
+            warn!("Unable to install netboot callback, the progress isn't reported\n");
+            return;
+        }
+
+        // The PXE driver only calls the callback, if it's enabled in the parameters
+        if let Transport::Tftp { protocol, .. } = &mut self.transport {
+            let _ = protocol.set_parameters(None, None, None, None, Some(true));
+        }
+        self.callback = Some((guid, interface));
+    }
+
+    /// This function downloads the specified file from the boot server into early memory and
+    /// verifies it against the SHA-256 checksum in `<file>.sha256`, if the server provides one.
+    pub fn fetch_file(&mut self, file_name: &str) -> Result<&'static mut [u8], Error> {
+        let size = self.file_size(file_name)?;
+        info!("Downloading {} ({} kB) from boot server...\n", file_name, size / 1024);
+
+        let buffer = early_alloc(self.boot_services, size, 1)?;
+        unsafe {
+            DOWNLOAD_PROGRESS = DownloadProgress {
+                total: size,
+                received: 0,
+                reported_percent: 0,
+            }
+        };
+        let read_size = self.read_file(file_name, buffer)?;
+        info!("Downloaded {} ({} kB of {} kB)\n", file_name, read_size / 1024, size / 1024);
+
+        self.verify_checksum(file_name, &buffer[..read_size])?;
+        Ok(&mut buffer[..read_size])
+    }
+
+    fn verify_checksum(&mut self, file_name: &str, data: &[u8]) -> Result<(), Error> {
+        // Skip verification if the boot server doesn't provide a checksum
+        let checksum_name = format!("{}.sha256", file_name);
+        let Some(checksum_size) = optional_file(self.file_size(&checksum_name))? else {
+            warn!("No checksum for {} found on boot server, skipping verification\n", file_name);
+            return Ok(());
+        };
+
+        // The checksum file is small, so the progress isn't reported
+        unsafe { DOWNLOAD_PROGRESS.total = 0 };
+        let mut checksum_file = vec![0; checksum_size];
+        let read_size = self.read_file(&checksum_name, &mut checksum_file)?;
+        let expected_digest = decode_sha256(&checksum_file[..read_size])
+            .ok_or_else(|| Error::InvalidChecksum(file_name.to_string()))?;
+
+        if Sha256::digest(data).as_slice() != expected_digest {
+            return Err(Error::ChecksumMismatch(file_name.to_string()));
+        }
+        info!("Verified SHA-256 checksum of {}\n", file_name);
+        Ok(())
+    }
+
+    fn file_size(&mut self, file_name: &str) -> Result<usize, Error> {
+        match &mut self.transport {
+            Transport::Tftp {
+                protocol,
+                server,
+                directory,
+            } => {
+                let path = remote_path(directory, file_name);
+                let path = CStr8::from_bytes_with_nul(path.as_bytes())
+                    .map_err(|_| Error::InvalidNetbootUrl(path.clone()))?;
+                let size = protocol.tftp_get_file_size(server, path).map_err(|error| {
+                    let error_code = protocol.mode().tftp_error.error_code;
+                    download_error(file_name, path.to_string(), error.status(), Some(error_code))
+                })?;
+                Ok(size as usize)
+            }
+            Transport::Http { protocol, base_url } => {
+                let url = format!("{}/{}", base_url, file_name);
+                let mut size = 0;
+                match load_file(protocol, &url, &mut size, ptr::null_mut()) {
+                    Status::BUFFER_TOO_SMALL => Ok(size),
+                    status => Err(download_error(file_name, url, status, None)),
+                }
+            }
+        }
+    }
+
+    fn read_file(&mut self, file_name: &str, buffer: &mut [u8]) -> Result<usize, Error> {
+        match &mut self.transport {
+            Transport::Tftp {
+                protocol,
+                server,
+                directory,
+            } => {
+                let path = remote_path(directory, file_name);
+                let path = CStr8::from_bytes_with_nul(path.as_bytes())
+                    .map_err(|_| Error::InvalidNetbootUrl(path.clone()))?;
+                let size = protocol
+                    .tftp_read_file(server, path, Some(buffer))
+                    .map_err(|error| {
+                        let error_code = protocol.mode().tftp_error.error_code;
+                        download_error(file_name, path.to_string(), error.status(), Some(error_code))
+                    })?;
+                Ok(size as usize)
+            }
+            Transport::Http { protocol, base_url } => {
+                let url = format!("{}/{}", base_url, file_name);
+                let mut size = buffer.len();
+                match load_file(protocol, &url, &mut size, buffer.as_mut_ptr() as *mut c_void) {
+                    Status::SUCCESS => Ok(size),
+                    status => Err(download_error(file_name, url, status, None)),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NetbootContext<'_> {
+    fn drop(&mut self) {
+        if let Some((guid, interface)) = self.callback.take() {
+            if let Transport::Tftp { protocol, .. } = &mut self.transport {
+                let _ = protocol.set_parameters(None, None, None, None, Some(false));
+            }
+            let _ = unsafe {
+                self.boot_services
+                    .uninstall_protocol_interface(self.handle, guid, interface)
+            };
+        }
+    }
+}
+
+/// This function returns the handle of the HTTP boot driver. The driver installs the Load File
+/// Protocol on a handle, whose device path contains an URI node, so it's distinguished from the
+/// Load File Protocol of the PXE driver.
+fn find_http_boot_handle(boot_services: &BootServices) -> Option<Handle> {
+    let handles = boot_services
+        .locate_handle_buffer(SearchType::ByProtocol(&LoadFile::GUID))
+        .ok()?;
+    handles.iter().copied().find(|handle| {
+        let device_path = unsafe {
+            boot_services.open_protocol::<DevicePath>(
+                OpenProtocolParams {
+                    handle: *handle,
+                    agent: boot_services.image_handle(),
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+        };
+        device_path.is_ok_and(|device_path| {
+            device_path.node_iter().any(|node| {
+                node.device_type() == DeviceType::MESSAGING
+                    && node.sub_type() == DeviceSubType::MESSAGING_URI
+            })
+        })
+    })
+}
+
+/// This function calls the Load File Protocol with an URI device path, which selects the URL
+fn load_file(protocol: &mut LoadFile, url: &str, size: &mut usize, buffer: *mut c_void) -> Status {
+    let node_length = (4 + url.len()) as u16;
+    let mut device_path = Vec::with_capacity(url.len() + 8);
+    device_path.extend_from_slice(&[DeviceType::MESSAGING.0, DeviceSubType::MESSAGING_URI.0]);
+    device_path.extend_from_slice(&node_length.to_le_bytes());
+    device_path.extend_from_slice(url.as_bytes());
+    device_path.extend_from_slice(&[0x7F, 0xFF, 0x04, 0x00]); // End of the device path
+
+    unsafe { (protocol.load_file)(protocol, device_path.as_ptr(), false, size, buffer) }
+}
+
+fn remote_path(directory: &str, file_name: &str) -> String {
+    match directory.is_empty() {
+        true => format!("{}\0", file_name),
+        false => format!("{}/{}\0", directory, file_name),
+    }
+}
+
+/// This function maps the status of a failed download to an error. Missing files are reported by
+/// the HTTP boot driver with `NOT_FOUND` and by the PXE driver with `TFTP_ERROR` and the error code
+/// of the error packet of the server. Only the "File not found" error code is a missing file, other
+/// errors of the server (like an access violation) are reported as failed download.
+fn download_error(
+    file_name: &str, url: String, status: Status, tftp_error_code: Option<u8>,
+) -> Error {
+    match (status, tftp_error_code) {
+        (Status::NOT_FOUND, _) | (Status::TFTP_ERROR, Some(tftp::ERROR_FILE_NOT_FOUND)) => {
+            Error::PathNotFound(file_name.to_string())
+        }
+        (status, _) => Error::DownloadFailed(url, status),
+    }
+}
+
+/// This function adds the received bytes to the progress and reports every 10 percent
+fn update_progress(length: usize) {
+    let progress = unsafe { &mut *ptr::addr_of_mut!(DOWNLOAD_PROGRESS) };
+    if progress.total == 0 {
+        return;
+    }
+
+    progress.received = (progress.received + length).min(progress.total);
+    let percent = progress.received * 100 / progress.total;
+    if percent >= progress.reported_percent + PROGRESS_STEP && percent < 100 {
+        progress.reported_percent = percent - percent % PROGRESS_STEP;
+        info!(
+            "Downloaded {} kB of {} kB ({}%)\n",
+            progress.received / 1024,
+            progress.total / 1024,
+            progress.reported_percent
+        );
+    }
+}
+
+unsafe extern "efiapi" fn pxe_callback(
+    _this: *mut PxeBaseCodeCallback, function: u32, received: bool, packet_length: u32,
+    packet: *const u8,
+) -> u32 {
+    if packet.is_null() {
+        return 0; // Continue
+    }
+
+    let packet = core::slice::from_raw_parts(packet, packet_length as usize);
+    if let Some(length) = tftp::received_payload_length(function, received, packet) {
+        update_progress(length);
+    }
+    0 // Continue
+}
+
+unsafe extern "efiapi" fn http_callback(
+    _this: *mut HttpBootCallback, data_type: u32, received: bool, data_length: u32,
+    _data: *const c_void,
+) -> Status {
+    if data_type == HTTP_BOOT_ENTITY_BODY && received {
+        update_progress(data_length as usize);
+    }
+    Status::SUCCESS
+}
+
+fn parse_ipv4(host: &str) -> Option<[u8; 4]> {
+    let mut address = [0u8; 4];
+    let mut parts = host.split('.');
+    for byte in address.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(address)
+}
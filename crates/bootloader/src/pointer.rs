@@ -0,0 +1,97 @@
+use crate::error::Error;
+use libgraphics::cursor::{
+    hide_cursor,
+    show_cursor,
+};
+use uefi::{
+    prelude::BootServices,
+    proto::console::pointer::Pointer,
+    table::boot::ScopedProtocol,
+};
+
+/// The cursor moves 4 pixels per millimeter of pointer movement
+const PIXELS_PER_MILLIMETER: i64 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerEvent {
+    Moved,
+    Clicked,
+}
+
+/// The pointer context reads the Simple Pointer Protocol of the firmware (mice, touchpads and
+/// touchscreens) and moves the cursor overlay of libgraphics. The cursor is hidden, when the context
+/// is dropped.
+pub struct PointerContext<'a> {
+    pointer: ScopedProtocol<'a, Pointer>,
+    pub x: usize,
+    pub y: usize,
+    /// The movement in counts, which wasn't converted into pixels yet
+    remainder: (i64, i64),
+    left_button: bool,
+}
+
+impl<'a> PointerContext<'a> {
+    /// This function opens the first pointer device of the firmware and places the cursor in the
+    /// center of the screen. Returns `None`, if the firmware doesn't expose a pointer device.
+    pub fn open(boot_services: &'a BootServices) -> Option<Self> {
+        let handle = boot_services.get_handle_for_protocol::<Pointer>().ok()?;
+        let mut pointer = boot_services
+            .open_protocol_exclusive::<Pointer>(handle)
+            .ok()?;
+        pointer.reset(false).ok()?;
+
+        let (width, height) = libgraphics::resolution().ok()?;
+        show_cursor(width / 2, height / 2).ok()?;
+        Some(Self {
+            pointer,
+            x: width / 2,
+            y: height / 2,
+            remainder: (0, 0),
+            left_button: false,
+        })
+    }
+
+    /// This function reads the state of the pointer device and moves the cursor. A click is
+    /// reported, when the left button was pressed since the last poll.
+    pub fn poll(&mut self) -> Result<Option<PointerEvent>, Error> {
+        let Some(state) = self.pointer.read_state()? else {
+            return Ok(None);
+        };
+
+        let (width, height) = libgraphics::resolution()?;
+        let (x_resolution, y_resolution, _) = self.pointer.mode().resolution;
+        let (x_movement, y_movement, _) = state.relative_movement;
+        self.x = move_axis(self.x, &mut self.remainder.0, x_movement, x_resolution, width);
+        self.y = move_axis(self.y, &mut self.remainder.1, y_movement, y_resolution, height);
+        show_cursor(self.x, self.y)?;
+
+        let clicked = state.button.0 && !self.left_button;
+        self.left_button = state.button.0;
+        Ok(Some(if clicked {
+            PointerEvent::Clicked
+        } else {
+            PointerEvent::Moved
+        }))
+    }
+}
+
+impl Drop for PointerContext<'_> {
+    fn drop(&mut self) {
+        let _ = hide_cursor();
+    }
+}
+
+/// This function moves the position on one axis. The resolution is specified in counts per
+/// millimeter, a resolution of 0 means that the resolution is unknown and the counts are pixels.
+/// Counts, which don't make up a full pixel, are kept in the remainder for the next movement.
+fn move_axis(
+    position: usize, remainder: &mut i64, movement: i32, resolution: u64, limit: usize,
+) -> usize {
+    let (multiplier, divisor) = match resolution {
+        0 => (1, 1),
+        resolution => (PIXELS_PER_MILLIMETER, resolution as i64),
+    };
+    let counts = *remainder + movement as i64 * multiplier;
+    *remainder = counts % divisor;
+    (position as i64 + counts / divisor).clamp(0, limit as i64 - 1) as usize
+}
@@ -0,0 +1,121 @@
+use crate::error::Error;
+use alloc::{
+    string::ToString,
+    vec::Vec,
+};
+use libcore::{
+    address::PhysAddr,
+    boot_info::ReservedRegion,
+    cmdline::{
+        parse_region,
+        CommandLine,
+    },
+    persistent_log::{
+        PersistentLog,
+        PSTORE_OPTION,
+    },
+};
+use log::info;
+use uefi::{
+    prelude::BootServices,
+    table::boot::{
+        AllocateType,
+        MemoryType,
+    },
+};
+
+pub const LOG_PATH: &str = "\\EFI\\OVERFLOW\\LASTLOG.TXT";
+
+/// The load option `crashkernel=<size>[@<address>]`, which reserves memory for a crash kernel
+pub const CRASHKERNEL_OPTION: &str = "crashkernel";
+
+/// The persistent log has to be at the same address in every boot, so it's placed at a fixed
+/// address, if no address is specified
+const DEFAULT_PSTORE_SIZE: u64 = 64 * 1024;
+const DEFAULT_PSTORE_ADDRESS: u64 = 0x0100_0000;
+
+/// The log of the last boot, which was found in the persistent log after an unexpected reboot
+pub struct PreviousLog {
+    pub data: Vec<u8>,
+    /// The user requested in the boot menu to save the log on the first volume
+    pub save_requested: bool,
+}
+
+/// This function allocates the region, which is specified by the value of the option. If the value
+/// is malformed, this function returns a [Error::InvalidRegion] error.
+fn allocate_region(
+    boot_services: &BootServices, option: &str, value: Option<&str>, default: (u64, Option<u64>),
+) -> Result<ReservedRegion, Error> {
+    let (size, address) = match value {
+        Some(value) => {
+            parse_region(value)
+                .filter(|(size, _)| *size != 0)
+                .ok_or_else(|| Error::InvalidRegion(option.to_string(), value.to_string()))?
+        }
+        None => default,
+    };
+
+    let page_count = size.div_ceil(4096);
+    let allocate_type = match address {
+        Some(address) => AllocateType::Address(address),
+        None => AllocateType::AnyPages,
+    };
+    let address =
+        boot_services.allocate_pages(allocate_type, MemoryType::LOADER_DATA, page_count as usize)?;
+    Ok(ReservedRegion {
+        address: PhysAddr::new(address),
+        page_count,
+    })
+}
+
+/// This function allocates the region of the persistent log, if it's enabled with the load options.
+/// If the region contains a valid log of the last boot, which wasn't closed by a clean shutdown or
+/// reboot, the log is copied and returned. After that, the log is reset and installed, so the log
+/// output of this boot is written into it.
+///
+/// The log only survives a warm reset, if the firmware doesn't clear the memory while booting.
+pub fn init_persistent_log(
+    boot_services: &BootServices, command_line: &CommandLine,
+) -> Result<Option<(ReservedRegion, Option<PreviousLog>)>, Error> {
+    let value = command_line.get(PSTORE_OPTION);
+    if value.is_none() && !command_line.has_flag(PSTORE_OPTION) {
+        return Ok(None);
+    }
+
+    let default = (DEFAULT_PSTORE_SIZE, Some(DEFAULT_PSTORE_ADDRESS));
+    let region = allocate_region(boot_services, PSTORE_OPTION, value, default)?;
+    let size = (region.page_count * 4096) as usize;
+    let Some(mut persistent_log) = (unsafe { PersistentLog::new(region.address.to_virt(), size) })
+    else {
+        return Err(Error::InvalidRegion(
+            PSTORE_OPTION.to_string(),
+            value.unwrap_or_default().into(),
+        ));
+    };
+
+    let previous_log = (persistent_log.is_valid() && !persistent_log.is_clean()).then(|| {
+        let (older, newer) = persistent_log.contents();
+        PreviousLog {
+            data: [older, newer].concat(),
+            save_requested: false,
+        }
+    });
+    persistent_log.reset();
+    libgraphics::log::set_persistent_log(persistent_log);
+    info!("Enabled persistent log at 0x{:X} ({} kB)\n", region.address, region.page_count * 4);
+    Ok(Some((region, previous_log)))
+}
+
+/// This function reserves the memory for a crash kernel, if it's requested with the load options.
+/// The memory stays reserved for the kernel, which loads the crash kernel into it.
+pub fn reserve_crash_kernel(
+    boot_services: &BootServices, command_line: &CommandLine,
+) -> Result<Option<ReservedRegion>, Error> {
+    let Some(value) = command_line.get(CRASHKERNEL_OPTION) else {
+        return Ok(None);
+    };
+
+    let region = allocate_region(boot_services, CRASHKERNEL_OPTION, Some(value), (0, None))?;
+    info!("Reserved {} kB at 0x{:X} for the crash kernel\n", region.page_count * 4, region.address);
+    Ok(Some(region))
+}
@@ -0,0 +1,97 @@
+//! The Runtime Services are relocated into the runtime window of the higher half after exiting the
+//! Boot Services, so the kernel can keep using them from the higher half. The window aliases the
+//! identity mapping of the first 512 GiB in the page tables of the firmware, which are kept by the
+//! kernel, and the runtime memory gets its address in the window with `SetVirtualAddressMap`.
+
+use crate::error::Error;
+use libcore::{
+    memory_map::MemoryMap,
+    paging::{
+        active_page_table,
+        flush_tlb,
+        PageTable,
+        PRESENT,
+    },
+};
+use libruntime::{
+    RUNTIME_WINDOW_OFFSET,
+    RUNTIME_WINDOW_SIZE,
+};
+use uefi::{
+    table::{
+        boot::{
+            MemoryAttribute,
+            MemoryDescriptor,
+        },
+        Runtime,
+        SystemTable,
+    },
+    Status,
+};
+
+/// The offset of `SetVirtualAddressMap` in the Runtime Services table. The uefi crate expects the
+/// descriptors with the size of [MemoryDescriptor], but the final memory map has the descriptor size
+/// of the firmware.
+const SET_VIRTUAL_ADDRESS_MAP_OFFSET: usize = 0x38;
+
+type SetVirtualAddressMap = unsafe extern "efiapi" fn(
+    map_size: usize,
+    descriptor_size: usize,
+    descriptor_version: u32,
+    map: *mut u8,
+) -> Status;
+
+/// This function maps the runtime window by copying the PML4 entry of the identity mapping. The
+/// window is shared with the kernel, because the kernel keeps the page tables of the firmware.
+///
+/// # Safety
+/// The caller has to ensure, that the page tables of the firmware are writable.
+pub unsafe fn map_runtime_window() -> Result<(), Error> {
+    let table = &mut *active_page_table().to_virt().as_mut_ptr::<PageTable>();
+    if table.entries[0] & PRESENT == 0 {
+        return Err(Error::RuntimeWindowUnavailable);
+    }
+
+    let index = ((RUNTIME_WINDOW_OFFSET >> 39) & 0x1FF) as usize;
+    table.entries[index] = table.entries[0];
+    flush_tlb();
+    Ok(())
+}
+
+/// This function assigns the addresses in the runtime window to the runtime memory of the memory
+/// map and relocates the Runtime Services. The function returns the virtual address of the Runtime
+/// Services table, which is handed over to the kernel. The runtime window must be mapped with
+/// [map_runtime_window] before.
+pub fn set_virtual_address_map(
+    system_table: &SystemTable<Runtime>, memory_map: &mut MemoryMap,
+) -> Result<u64, Error> {
+    for descriptor in memory_map.entries_mut() {
+        if !descriptor.att.contains(MemoryAttribute::RUNTIME) {
+            continue;
+        }
+
+        let end_address = descriptor.phys_start + descriptor.page_count * 4096;
+        if end_address > RUNTIME_WINDOW_SIZE {
+            return Err(Error::OutsideRuntimeWindow(descriptor.phys_start));
+        }
+        descriptor.virt_start = descriptor.phys_start + RUNTIME_WINDOW_OFFSET;
+    }
+
+    // The table is read before the relocation, because the firmware converts its own pointers
+    let table = system_table.runtime_services() as *const _ as *const u8;
+    let set_virtual_address_map =
+        unsafe { *(table.add(SET_VIRTUAL_ADDRESS_MAP_OFFSET) as *const SetVirtualAddressMap) };
+    let data = memory_map.as_bytes();
+    let status = unsafe {
+        set_virtual_address_map(
+            data.len(),
+            memory_map.descriptor_size(),
+            MemoryDescriptor::VERSION,
+            data.as_ptr() as *mut u8,
+        )
+    };
+    if status != Status::SUCCESS {
+        return Err(Error::SetVirtualAddressMap(status));
+    }
+    Ok(table as u64 + RUNTIME_WINDOW_OFFSET)
+}
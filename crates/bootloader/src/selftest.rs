@@ -0,0 +1,343 @@
+use crate::{
+    error::Error,
+    files::{
+        self,
+        SimpleFileSystemContext,
+    },
+};
+use alloc::{
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    arch::{
+        asm,
+        x86_64::_rdtsc,
+    },
+    fmt::Write,
+};
+use libcore::{
+    descriptors::{
+        load_idt,
+        read_cs,
+        read_idtr,
+        DescriptorTablePointer,
+        GateDescriptor,
+        GATE_INTERRUPT,
+    },
+    registers::{
+        disable_interrupts,
+        enable_interrupts,
+    },
+    table::{
+        Alignment,
+        Column,
+        Table,
+    },
+    FrameTable,
+};
+use libgraphics::embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::RgbColor,
+};
+use log::info;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use uefi::prelude::BootServices;
+
+/// The flag, which enables the self tests and benchmarks of the bootloader
+pub const SELFTEST_OPTION: &str = "selftest";
+
+const CALIBRATION_TIME_US: usize = 10_000;
+const FILL_RATE_ITERATIONS: usize = 16;
+
+/// This function measures the frequency of the timestamp counter in Hz with the stall function of
+/// the Boot Services.
+pub fn tsc_frequency(boot_services: &BootServices) -> u64 {
+    let start = unsafe { _rdtsc() };
+    boot_services.stall(CALIBRATION_TIME_US);
+    let end = unsafe { _rdtsc() };
+    (end - start) * (1_000_000 / CALIBRATION_TIME_US as u64)
+}
+
+/// This function measures the rate in MiB/s, with which the swap buffer is copied into the frame
+/// buffer. The result depends heavily on the memory type of the frame buffer mapping.
+pub fn bench_fill_rate(tsc_frequency: u64, label: &str) -> u64 {
+    let (_, size) = libgraphics::framebuffer_region().unwrap();
+    let start = unsafe { _rdtsc() };
+    for _ in 0..FILL_RATE_ITERATIONS {
+        libgraphics::swap_buffers().unwrap();
+    }
+    let ticks = (unsafe { _rdtsc() } - start).max(1);
+
+    let bytes = (size * FILL_RATE_ITERATIONS) as u128;
+    let rate = (bytes * tsc_frequency as u128 / ticks as u128 / (1024 * 1024)) as u64;
+    info!("Framebuffer fill rate ({}): {} MiB/s\n", label, rate);
+    rate
+}
+
+/// The file, which is written and read back by the file test
+const SELFTEST_FILE_PATH: &str = "\\EFI\\OVERFLOW\\SELFTEST.BIN";
+const SELFTEST_FILE_SIZE: usize = 16384;
+
+/// The sizes and alignments of the allocations of the allocator test
+const ALLOCATION_SIZES: &[usize] = &[1, 7, 64, 1000, 4096, 65536];
+const ALLOCATION_ALIGNMENTS: &[usize] = &[1, 8, 64, 4096];
+
+const IDT_ENTRIES: usize = 256;
+const BREAKPOINT_VECTOR: usize = 3;
+const RFLAGS_INTERRUPT: u64 = 1 << 9;
+
+/// The size of the pattern of the graphics test in pixels
+const PATTERN_SIZE: usize = 64;
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+static DIAGNOSTICS_TABLE: Table = Table::new(&[
+    Column::new("Test", 12, Alignment::Left),
+    Column::new("Result", 6, Alignment::Left),
+    Column::new("Details", 48, Alignment::Left),
+]);
+
+/// The number of breakpoints, which were handled by the handler of the interrupt test
+static mut BREAKPOINT_COUNT: u32 = 0;
+
+/// A test of the diagnostics, which are run from the boot menu
+struct SelfTest {
+    name: &'static str,
+    run: fn(&mut SimpleFileSystemContext) -> Result<(), Error>,
+}
+
+#[rustfmt::skip]
+static SELF_TESTS: &[SelfTest] = &[
+    SelfTest { name: "allocator", run: |_| test_allocator() },
+    SelfTest { name: "bitmap", run: |_| test_bitmap() },
+    SelfTest { name: "interrupts", run: |_| test_interrupts() },
+    SelfTest { name: "graphics", run: |_| test_graphics() },
+    SelfTest { name: "file", run: test_file },
+];
+
+/// The stack frame, which is pushed by the CPU for an interrupt
+#[repr(C)]
+struct InterruptStackFrame {
+    instruction_pointer: u64,
+    code_segment: u64,
+    flags: u64,
+    stack_pointer: u64,
+    stack_segment: u64,
+}
+
+/// This function runs all self tests and returns the summary with the result of every test
+pub fn run_diagnostics(file_system_context: &mut SimpleFileSystemContext) -> String {
+    let mut summary = String::new();
+    let _ = writeln!(summary, "{}", DIAGNOSTICS_TABLE.header());
+    let _ = writeln!(summary, "{}", DIAGNOSTICS_TABLE.separator());
+    let mut passed = 0;
+    for test in SELF_TESTS {
+        let result = (test.run)(file_system_context);
+        let (status, details) = match &result {
+            Ok(()) => ("PASS", String::new()),
+            Err(error) => ("FAIL", format!("{}", error)),
+        };
+        info!("Self test '{}': {}\n", test.name, status);
+        let _ = writeln!(summary, "{}", DIAGNOSTICS_TABLE.row(&[&test.name, &status, &details]));
+        passed += result.is_ok() as usize;
+    }
+    let _ = writeln!(summary, "\n{} of {} tests passed", passed, SELF_TESTS.len());
+    summary
+}
+
+/// This function allocates buffers with different sizes and alignments, fills them with patterns
+/// and frees them in an interleaved order. The patterns of the remaining buffers are checked after
+/// every step, so overlapping allocations are detected.
+fn test_allocator() -> Result<(), Error> {
+    let mut allocations = Vec::new();
+    for (index, (size, align)) in ALLOCATION_SIZES
+        .iter()
+        .flat_map(|size| {
+            ALLOCATION_ALIGNMENTS
+                .iter()
+                .map(move |align| (*size, *align))
+        })
+        .enumerate()
+    {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let pointer = unsafe { alloc::alloc::alloc(layout) };
+        if pointer.is_null() {
+            return Err(Error::SelfTestFailed("allocation failed"));
+        }
+        if pointer as usize % align != 0 {
+            return Err(Error::SelfTestFailed("allocation is misaligned"));
+        }
+        unsafe { core::ptr::write_bytes(pointer, index as u8, size) };
+        allocations.push((pointer, layout, index as u8));
+    }
+
+    // Free every second allocation and then the rest, the patterns of the others must be unchanged
+    for pass in 0..2 {
+        let mut index = 0;
+        allocations.retain(|(pointer, layout, _)| {
+            index += 1;
+            let free = pass == 1 || index % 2 == 0;
+            if free {
+                unsafe { alloc::alloc::dealloc(*pointer, *layout) };
+            }
+            !free
+        });
+
+        let corrupted = allocations.iter().any(|(pointer, layout, pattern)| {
+            unsafe { core::slice::from_raw_parts(*pointer, layout.size()) }
+                .iter()
+                .any(|byte| byte != pattern)
+        });
+        if corrupted {
+            return Err(Error::SelfTestFailed("allocations overlap"));
+        }
+    }
+    Ok(())
+}
+
+/// This function checks the bitmap operations of the frame table, which is handed over to the
+/// kernel
+fn test_bitmap() -> Result<(), Error> {
+    let mut buffer = [0u8; 64];
+    let mut frame_table = FrameTable {
+        frame_table: &mut buffer,
+    };
+    let frame_count = 64 * 8;
+    let expected = |index: usize| index % 3 == 0 || index % 7 == 0;
+
+    for index in (0..frame_count).filter(|index| expected(*index)) {
+        frame_table.set_frame_allocated(index);
+        frame_table.set_frame_allocated(index);
+    }
+    if (0..frame_count).any(|index| frame_table.page_allocated(index) != expected(index)) {
+        return Err(Error::SelfTestFailed("set frames don't match"));
+    }
+
+    for index in 0..frame_count {
+        frame_table.toggle_frame_alloc_status(index);
+    }
+    if (0..frame_count).any(|index| frame_table.page_allocated(index) == expected(index)) {
+        return Err(Error::SelfTestFailed("toggled frames don't match"));
+    }
+
+    // Frames after the end of the table are never allocated
+    if frame_table.page_allocated(frame_count) {
+        return Err(Error::SelfTestFailed("frame out of bounds is allocated"));
+    }
+    Ok(())
+}
+
+extern "x86-interrupt" fn breakpoint_handler(_frame: InterruptStackFrame) {
+    unsafe { BREAKPOINT_COUNT += 1 };
+}
+
+/// This function loads a copy of the IDT of the firmware with an own breakpoint handler, triggers
+/// a breakpoint and restores the IDT of the firmware. Interrupts are disabled in the meantime,
+/// because the handlers of the firmware (like the timer) stay in the copy.
+fn test_interrupts() -> Result<(), Error> {
+    let firmware_idt = read_idtr();
+    let mut table = vec![0u64; IDT_ENTRIES * 2];
+    let size = firmware_idt.size().min(table.len() * 8);
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            firmware_idt.base as *const u8,
+            table.as_mut_ptr() as *mut u8,
+            size,
+        )
+    };
+
+    let gate = GateDescriptor {
+        vector: BREAKPOINT_VECTOR as u8,
+        handler: breakpoint_handler as usize as u64,
+        selector: read_cs(),
+        ist: 0,
+        kind: GATE_INTERRUPT,
+        dpl: 0,
+        present: true,
+    };
+    table[BREAKPOINT_VECTOR * 2..BREAKPOINT_VECTOR * 2 + 2].copy_from_slice(&gate.encode());
+    let idt = DescriptorTablePointer {
+        limit: (table.len() * 8 - 1) as u16,
+        base: table.as_ptr() as u64,
+    };
+
+    let flags: u64;
+    unsafe { asm!("pushfq", "pop {}", out(reg) flags) };
+    disable_interrupts();
+    let count = unsafe { BREAKPOINT_COUNT };
+    unsafe {
+        load_idt(&idt);
+        asm!("int3");
+        load_idt(&firmware_idt);
+    }
+    if flags & RFLAGS_INTERRUPT != 0 {
+        unsafe { enable_interrupts() };
+    }
+
+    match unsafe { BREAKPOINT_COUNT } == count + 1 {
+        true => Ok(()),
+        false => Err(Error::SelfTestFailed("breakpoint wasn't handled")),
+    }
+}
+
+/// This function draws a pattern into the swap buffer and compares the checksum of the pixels,
+/// which are read back, with the checksum of the pattern. The colors survive the conversion into
+/// every supported pixel format, so the checksums match on every display.
+fn test_graphics() -> Result<(), Error> {
+    let pattern = |x: usize, y: usize| {
+        Rgb888::new((x * 8) as u8 & 0xF8, (y * 8) as u8 & 0xF8, ((x ^ y) * 8) as u8 & 0xF8)
+    };
+    let checksum = |pixels: &mut dyn Iterator<Item = Rgb888>| {
+        pixels.fold(FNV_OFFSET_BASIS, |hash, color| {
+            [color.r(), color.g(), color.b()]
+                .iter()
+                .fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+        })
+    };
+
+    let coordinates = || (0..PATTERN_SIZE).flat_map(|y| (0..PATTERN_SIZE).map(move |x| (x, y)));
+    for (x, y) in coordinates() {
+        libgraphics::set_pixel_at(x, y, pattern(x, y))?;
+    }
+    let expected = checksum(&mut coordinates().map(|(x, y)| pattern(x, y)));
+    let mut read_back = Vec::with_capacity(PATTERN_SIZE * PATTERN_SIZE);
+    for (x, y) in coordinates() {
+        read_back.push(libgraphics::get_pixel_at(x, y)?);
+    }
+    libgraphics::fill(0, 0, PATTERN_SIZE, PATTERN_SIZE, Rgb888::BLACK)?;
+
+    match checksum(&mut read_back.into_iter()) == expected {
+        true => Ok(()),
+        false => Err(Error::SelfTestFailed("checksum of the pattern doesn't match")),
+    }
+}
+
+/// This function writes a file with pseudo-random data to the first volume, reads it back and
+/// compares the SHA-256 hashes. The file is deleted afterwards.
+fn test_file(file_system_context: &mut SimpleFileSystemContext) -> Result<(), Error> {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let data = (0..SELFTEST_FILE_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect::<Vec<_>>();
+
+    files::write_file(file_system_context, 0, SELFTEST_FILE_PATH, &data)?;
+    let read_back = files::read_file(file_system_context, 0, SELFTEST_FILE_PATH)?;
+    let matches = Sha256::digest(&data) == Sha256::digest(&*read_back);
+    files::delete_file(file_system_context, 0, SELFTEST_FILE_PATH)?;
+    match matches {
+        true => Ok(()),
+        false => Err(Error::SelfTestFailed("hash of the read back file doesn't match")),
+    }
+}
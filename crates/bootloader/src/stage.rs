@@ -0,0 +1,246 @@
+use crate::{
+    error::Error,
+    BootContext,
+};
+use alloc::vec::Vec;
+use core::{
+    arch::x86_64::_rdtsc,
+    fmt::{
+        Display,
+        Formatter,
+    },
+};
+use libcore::table::{
+    Alignment,
+    Column,
+    Table,
+};
+use log::{
+    info,
+    warn,
+};
+
+/// The load option `skipstages=<name>,...`, which skips the specified boot stages. Only stages with
+/// the [FailurePolicy::Skip] policy can be skipped.
+pub const SKIP_STAGES_OPTION: &str = "skipstages";
+
+static STAGE_TABLE: Table = Table::new(&[
+    Column::new("Stage", 16, Alignment::Left),
+    Column::new("Policy", 8, Alignment::Left),
+    Column::new("Result", 9, Alignment::Left),
+    Column::new("Attempts", 8, Alignment::Right),
+    Column::new("kcycles", 10, Alignment::Right),
+]);
+
+/// The behavior of the runner, if a stage fails
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// The boot is aborted with a panic
+    Fatal,
+    /// The boot continues, but the stages, which depend on the stage, are skipped
+    Skip,
+    /// The stage is run again up to the specified number of times, before the boot is aborted
+    Retry(u32),
+}
+
+impl Display for FailurePolicy {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Fatal => write!(formatter, "fatal"),
+            Self::Skip => write!(formatter, "skip"),
+            Self::Retry(retries) => write!(formatter, "retry {}", retries),
+        }
+    }
+}
+
+/// A stage of the boot process, which works on the shared [BootContext]
+pub struct Stage {
+    pub name: &'static str,
+    /// The names of the stages, which must be completed before this stage
+    pub dependencies: &'static [&'static str],
+    pub policy: FailurePolicy,
+    pub run: fn(&mut BootContext) -> Result<(), Error>,
+}
+
+/// The result of a stage
+pub enum StageStatus {
+    Completed,
+    Failed(Error),
+    /// The stage was skipped, because a dependency didn't complete or it was disabled by the user
+    Skipped,
+}
+
+impl Display for StageStatus {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Completed => write!(formatter, "OK"),
+            Self::Failed(_) => write!(formatter, "FAILED"),
+            Self::Skipped => write!(formatter, "SKIPPED"),
+        }
+    }
+}
+
+/// The report of a stage for the timing report
+pub struct StageReport {
+    pub name: &'static str,
+    pub policy: FailurePolicy,
+    pub status: StageStatus,
+    pub attempts: u32,
+    /// The TSC cycles, which were spent in all attempts of the stage
+    pub cycles: u64,
+}
+
+/// This function returns the stages in the order of execution. Every stage follows its dependencies
+/// and stages without dependencies between each other keep their order. If a stage depends on an
+/// unknown stage or the dependencies are circular, this function returns an error.
+fn sort_stages(stages: &'static [Stage]) -> Result<Vec<&'static Stage>, Error> {
+    for stage in stages {
+        if let Some(dependency) = stage
+            .dependencies
+            .iter()
+            .find(|dependency| !stages.iter().any(|stage| stage.name == **dependency))
+        {
+            return Err(Error::UnknownStageDependency(stage.name, dependency));
+        }
+    }
+
+    let mut sorted: Vec<&'static Stage> = Vec::with_capacity(stages.len());
+    while sorted.len() < stages.len() {
+        let next = stages.iter().find(|stage| {
+            !sorted.iter().any(|sorted| sorted.name == stage.name)
+                && stage
+                    .dependencies
+                    .iter()
+                    .all(|dependency| sorted.iter().any(|sorted| sorted.name == *dependency))
+        });
+        match next {
+            Some(stage) => sorted.push(stage),
+            None => {
+                let stage = stages
+                    .iter()
+                    .find(|stage| !sorted.iter().any(|sorted| sorted.name == stage.name))
+                    .unwrap();
+                return Err(Error::CircularStageDependencies(stage.name));
+            }
+        }
+    }
+    Ok(sorted)
+}
+
+/// This function runs the stages after their dependencies and returns the report of every stage.
+/// Failed stages are handled with their failure policy. If a fatal stage fails or can't run,
+/// because a dependency didn't complete, the boot is aborted with a panic.
+pub fn run_stages(context: &mut BootContext, stages: &'static [Stage]) -> Vec<StageReport> {
+    let sorted = match sort_stages(stages) {
+        Err(error) => panic!("Unable to order boot stages => {}", error),
+        Ok(sorted) => sorted,
+    };
+
+    let mut reports: Vec<StageReport> = Vec::with_capacity(sorted.len());
+    for stage in sorted {
+        let failed_dependency = stage.dependencies.iter().find(|dependency| {
+            reports.iter().any(|report| {
+                report.name == **dependency && !matches!(report.status, StageStatus::Completed)
+            })
+        });
+        let disabled = context
+            .command_line()
+            .get(SKIP_STAGES_OPTION)
+            .unwrap_or_default()
+            .split(',')
+            .any(|name| name.trim() == stage.name);
+
+        let skip_reason = match (failed_dependency, disabled) {
+            (Some(dependency), _) if stage.policy != FailurePolicy::Skip => {
+                panic!(
+                    "Unable to run boot stage '{}' => '{}' didn't complete",
+                    stage.name, dependency
+                )
+            }
+            (Some(_), _) => Some("dependency didn't complete"),
+            (None, true) if stage.policy == FailurePolicy::Skip => Some("disabled by load options"),
+            (None, true) => {
+                warn!("Boot stage '{}' is required and can't be skipped\n", stage.name);
+                None
+            }
+            (None, false) => None,
+        };
+        if let Some(reason) = skip_reason {
+            info!("Skipped boot stage '{}' ({})\n", stage.name, reason);
+            reports.push(StageReport {
+                name: stage.name,
+                policy: stage.policy,
+                status: StageStatus::Skipped,
+                attempts: 0,
+                cycles: 0,
+            });
+            continue;
+        }
+
+        // Run the stage until it completes or no attempts are left
+        let max_attempts = match stage.policy {
+            FailurePolicy::Retry(retries) => retries + 1,
+            _ => 1,
+        };
+        let mut attempts = 0;
+        let mut cycles = 0;
+        let _trace_scope = libcore::trace::trace_scope(stage.name);
+        info!("Starting boot stage '{}'\n", stage.name);
+        let status = loop {
+            attempts += 1;
+            let start = unsafe { _rdtsc() };
+            let result = (stage.run)(context);
+            cycles += unsafe { _rdtsc() } - start;
+            match result {
+                Ok(()) => break StageStatus::Completed,
+                Err(error) if attempts < max_attempts => {
+                    warn!(
+                        "Boot stage '{}' failed (Attempt {}/{}) => {}\n",
+                        stage.name, attempts, max_attempts, error
+                    );
+                }
+                Err(error) => break StageStatus::Failed(error),
+            }
+        };
+
+        match &status {
+            StageStatus::Failed(error) if stage.policy != FailurePolicy::Skip => {
+                panic!("Boot stage '{}' failed => {}", stage.name, error)
+            }
+            StageStatus::Failed(error) => {
+                warn!("Boot stage '{}' failed, continuing without it => {}\n", stage.name, error)
+            }
+            _ => info!("Finished boot stage '{}' ({} kcycles)\n", stage.name, cycles / 1000),
+        }
+        reports.push(StageReport {
+            name: stage.name,
+            policy: stage.policy,
+            status,
+            attempts,
+            cycles,
+        });
+    }
+    reports
+}
+
+/// This function shows the timing report of the stages
+pub fn print_stage_report(reports: &[StageReport]) {
+    info!("{}\n", STAGE_TABLE.header());
+    info!("{}\n", STAGE_TABLE.separator());
+    for report in reports {
+        info!(
+            "{}\n",
+            STAGE_TABLE.row(&[
+                &report.name,
+                &report.policy,
+                &report.status,
+                &report.attempts,
+                &(report.cycles / 1000),
+            ])
+        );
+    }
+    info!(
+        "Boot stages took {} kcycles\n",
+        reports.iter().map(|report| report.cycles).sum::<u64>() / 1000
+    );
+}
@@ -0,0 +1,70 @@
+//! The kernel is entered through a trampoline, which sets a defined register state instead of
+//! calling the entry on the stack of the firmware. The entry ABI of the kernel is:
+//!
+//! - RSP points to the top of a dedicated kernel stack, which is reserved in the memory map. The
+//!   stack is aligned like after a `call`, so RSP + 8 is aligned to 16 bytes.
+//! - RDI contains the physical address of the encoded boot information (`sysv64` calling convention).
+//! - RFLAGS is 0x2, so interrupts are disabled and the direction flag is cleared.
+//! - RBP and the return address on the stack are zero, so backtraces stop at the entry. All other
+//!   general purpose registers are zero.
+//! - The page tables of the firmware are active and the Boot Services were exited.
+
+use crate::{
+    early_alloc::early_alloc,
+    elf_loader::LoadedKernel,
+    error::Error,
+};
+use core::arch::global_asm;
+use libcore::address::PhysAddr;
+use uefi::prelude::BootServices;
+
+/// The size of the stack, which the kernel is entered with
+pub const KERNEL_STACK_SIZE: usize = 64 * 1024;
+const KERNEL_STACK_ALIGNMENT: usize = 16;
+
+extern "sysv64" {
+    fn kernel_trampoline(entry: u64, boot_info_address: u64, stack_top: u64) -> !;
+}
+
+// The trampoline switches to the kernel stack, sets RFLAGS and clears all registers besides the
+// boot information in RDI. The zero return address is pushed, so the stack is aligned like after a
+// call into the entry.
+global_asm!(
+    ".global kernel_trampoline",
+    "kernel_trampoline:",
+    "cli",
+    "cld",
+    "mov rsp, rdx",
+    "push 0x2",
+    "popfq",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    "xor ebx, ebx",
+    "xor ecx, ecx",
+    "xor edx, edx",
+    "xor esi, esi",
+    "xor ebp, ebp",
+    "xor r8d, r8d",
+    "xor r9d, r9d",
+    "xor r10d, r10d",
+    "xor r11d, r11d",
+    "xor r12d, r12d",
+    "xor r13d, r13d",
+    "xor r14d, r14d",
+    "xor r15d, r15d",
+    "push 0",
+    "jmp rax",
+);
+
+/// This function allocates the stack, which the kernel is entered with. The stack can't be allocated
+/// after exiting the Boot Services and the memory stays reserved for the kernel.
+pub fn allocate_kernel_stack(boot_services: &BootServices) -> Result<&'static mut [u8], Error> {
+    early_alloc(boot_services, KERNEL_STACK_SIZE, KERNEL_STACK_ALIGNMENT)
+}
+
+/// This function enters the kernel with the entry ABI of this module. The Boot Services must be
+/// exited and the boot information must be encoded into the buffer at the specified address.
+pub fn enter_kernel(kernel: &LoadedKernel, boot_info_address: PhysAddr, stack: &mut [u8]) -> ! {
+    let stack_top = stack.as_mut_ptr_range().end as u64 & !(KERNEL_STACK_ALIGNMENT as u64 - 1);
+    unsafe { kernel_trampoline(kernel.entry.as_u64(), boot_info_address.as_u64(), stack_top) }
+}
@@ -0,0 +1,84 @@
+//! The UEFI environment stores the system table of the firmware, so the panic handler and the
+//! countdown after a panic can reach the Boot and Runtime Services without a table. The Boot
+//! Services are invalidated before they are exited, so a use after the exit panics with a clear
+//! message instead of calling into freed firmware memory.
+
+use core::{
+    ffi::c_void,
+    ptr::null_mut,
+    sync::atomic::{
+        AtomicBool,
+        AtomicPtr,
+        Ordering,
+    },
+};
+use uefi::{
+    prelude::{
+        Boot,
+        BootServices,
+        RuntimeServices,
+    },
+    table::SystemTable,
+};
+
+static SYSTEM_TABLE: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+static RUNTIME_SERVICES: AtomicPtr<RuntimeServices> = AtomicPtr::new(null_mut());
+static BOOT_SERVICES_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// This function stores the system table, which was passed to the entry of the bootloader. It must
+/// be called before the services are used through this module.
+pub fn init_uefi_env(system_table: &SystemTable<Boot>) {
+    let runtime_services = system_table.runtime_services() as *const RuntimeServices;
+    RUNTIME_SERVICES.store(runtime_services as *mut _, Ordering::Release);
+    SYSTEM_TABLE.store(system_table.as_ptr() as *mut _, Ordering::Release);
+    BOOT_SERVICES_ACTIVE.store(true, Ordering::Release);
+}
+
+/// This function invalidates the access to the Boot Services. It's called before the Boot Services
+/// are exited, because they can't be used anymore, even if the exit fails.
+pub fn invalidate_boot_services() {
+    BOOT_SERVICES_ACTIVE.store(false, Ordering::Release);
+}
+
+/// This function returns, whether the Boot Services can be used
+#[inline]
+pub fn boot_services_active() -> bool {
+    BOOT_SERVICES_ACTIVE.load(Ordering::Acquire) && !SYSTEM_TABLE.load(Ordering::Acquire).is_null()
+}
+
+/// This function returns a copy of the system table with the Boot Services. If the Boot Services
+/// were exited, this function returns [None].
+pub fn try_boot_system_table() -> Option<SystemTable<Boot>> {
+    if !boot_services_active() {
+        return None;
+    }
+    unsafe { SystemTable::<Boot>::from_ptr(SYSTEM_TABLE.load(Ordering::Acquire)) }
+}
+
+/// This function returns the Boot Services. If the environment isn't initialized or the Boot
+/// Services were exited, this function panics.
+pub fn boot_services() -> &'static BootServices {
+    if SYSTEM_TABLE.load(Ordering::Acquire).is_null() {
+        panic!("The Boot Services were used before the UEFI environment was initialized");
+    }
+
+    let Some(system_table) = try_boot_system_table() else {
+        panic!("The Boot Services were used after exiting them");
+    };
+    unsafe { &*(system_table.boot_services() as *const BootServices) }
+}
+
+/// This function returns the Runtime Services, which are available before and after exiting the
+/// Boot Services. If the environment isn't initialized, this function returns [None].
+pub fn try_runtime_services() -> Option<&'static RuntimeServices> {
+    unsafe { RUNTIME_SERVICES.load(Ordering::Acquire).as_ref() }
+}
+
+/// This function returns the Runtime Services. If the environment isn't initialized, this function
+/// panics.
+pub fn runtime_services() -> &'static RuntimeServices {
+    match try_runtime_services() {
+        Some(runtime_services) => runtime_services,
+        None => panic!("The Runtime Services were used before the UEFI environment was initialized"),
+    }
+}
@@ -0,0 +1,184 @@
+use crate::error::Error;
+use alloc::{
+    string::{
+        String,
+        ToString,
+    },
+    vec::Vec,
+};
+use ed25519_compact::{
+    PublicKey,
+    Signature,
+};
+use log::{
+    info,
+    warn,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+pub const MANIFEST_PATH: &str = "\\EFI\\BOOT\\HASHES.TOML";
+pub const MANIFEST_FILE_NAME: &str = "HASHES.TOML";
+
+/// The load option, which controls the reaction on a digest mismatch (`hashes=strict` refuses to
+/// boot and `hashes=warn` only reports the mismatch)
+pub const HASHES_OPTION: &str = "hashes";
+
+/// The hex-encoded Ed25519 public key, which is embedded by the image tool at build time. With a
+/// public key, every boot artifact must be signed, independent of the load options.
+const SIGNING_KEY: Option<&str> = option_env!("OVERFLOW_SIGNING_KEY");
+
+/// The digest manifest is generated by the image tool at build time and contains the SHA-256 digest
+/// of every boot artifact. Every line has the format `"<file name>" = "<hex value>"` and comments are
+/// ignored. The entries of the `[ed25519]` table are the signatures of the artifacts, all other
+/// entries (like in the `[sha256]` table) are digests.
+pub struct DigestManifest {
+    entries: Vec<(String, [u8; 32])>,
+    signatures: Vec<(String, [u8; 64])>,
+}
+
+impl DigestManifest {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let content = core::str::from_utf8(data).map_err(|_| Error::InvalidManifest(0))?;
+        let mut entries = Vec::new();
+        let mut signatures = Vec::new();
+        let mut in_signatures = false;
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_signatures = line == "[ed25519]";
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(Error::InvalidManifest(index + 1))?;
+            let name = name.trim().trim_matches('"').to_string();
+            let value = value.trim().trim_matches('"').as_bytes();
+            if in_signatures {
+                let signature = decode_hex(value).ok_or(Error::InvalidManifest(index + 1))?;
+                signatures.push((name, signature));
+            } else {
+                let digest = decode_sha256(value).ok_or(Error::InvalidManifest(index + 1))?;
+                entries.push((name, digest));
+            }
+        }
+        Ok(Self {
+            entries,
+            signatures,
+        })
+    }
+
+    /// This function verifies the Ed25519 signature of the specified data with the embedded public
+    /// key. Without public key, this function does nothing. Files without signature are rejected.
+    pub fn verify_signature(&self, file_name: &str, data: &[u8]) -> Result<(), Error> {
+        let Some(public_key) = signing_key()? else {
+            return Ok(());
+        };
+
+        let signature = self
+            .signatures
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(file_name))
+            .map(|(_, signature)| Signature::new(*signature))
+            .ok_or_else(|| Error::MissingSignature(file_name.to_string()))?;
+        public_key
+            .verify(data, &signature)
+            .map_err(|_| Error::InvalidSignature(file_name.to_string()))?;
+        info!("Verified signature of {}\n", file_name);
+        Ok(())
+    }
+
+    /// This function compares the SHA-256 digest of the specified data with the digest in the
+    /// manifest. Files without entry in the manifest are reported, but accepted.
+    pub fn verify(&self, file_name: &str, data: &[u8]) -> Result<(), Error> {
+        let expected_digest = match self
+            .entries
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(file_name))
+        {
+            Some((_, digest)) => digest,
+            None => {
+                warn!("No digest for {} in manifest, skipping verification\n", file_name);
+                return Ok(());
+            }
+        };
+
+        if Sha256::digest(data).as_slice() != expected_digest {
+            return Err(Error::ChecksumMismatch(file_name.to_string()));
+        }
+        info!("Verified {} against digest manifest\n", file_name);
+        Ok(())
+    }
+}
+
+/// This function verifies the specified file against the manifest. If the strict mode is disabled,
+/// a digest mismatch is only reported as warning. The signature is always verified strictly.
+pub fn verify_artifact(
+    manifest: &DigestManifest, file_name: &str, data: &[u8], strict: bool,
+) -> Result<(), Error> {
+    manifest.verify_signature(file_name, data)?;
+    match manifest.verify(file_name, data) {
+        Err(Error::ChecksumMismatch(file_name)) if !strict => {
+            warn!("SHA-256 digest of {} doesn't match the manifest, booting anyway\n", file_name);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// This function verifies a file of the first volume (like the boot configuration), which is read
+/// before the boot menu, against the manifest of the volume. The file is listed in the manifest with
+/// its file name. Without manifest, the file is only rejected, if every artifact must be signed.
+pub fn verify_volume_file(
+    manifest: Option<&DigestManifest>, path: &str, data: &[u8], strict: bool,
+) -> Result<(), Error> {
+    let file_name = path.rsplit('\\').next().unwrap_or(path);
+    match manifest {
+        Some(manifest) => verify_artifact(manifest, file_name, data, strict),
+        None if signatures_required() => Err(Error::MissingSignature(file_name.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// This function returns, whether a public key is embedded, so every boot artifact must be signed
+#[inline]
+pub fn signatures_required() -> bool {
+    SIGNING_KEY.is_some()
+}
+
+/// This function returns the embedded public key. Without public key, this function returns
+/// [None].
+fn signing_key() -> Result<Option<PublicKey>, Error> {
+    let Some(key) = SIGNING_KEY else {
+        return Ok(None);
+    };
+    let key = key.trim();
+    match decode_hex(key.as_bytes()) {
+        Some(public_key) if key.len() == 64 => Ok(Some(PublicKey::new(public_key))),
+        _ => Err(Error::InvalidSigningKey),
+    }
+}
+
+/// This function decodes the hex-encoded SHA-256 digest at the start of the specified text. This is
+/// compatible with the output of `sha256sum`.
+pub fn decode_sha256(text: &[u8]) -> Option<[u8; 32]> {
+    decode_hex(text)
+}
+
+/// This function decodes the hex-encoded bytes at the start of the specified text
+fn decode_hex<const N: usize>(text: &[u8]) -> Option<[u8; N]> {
+    let mut bytes = [0u8; N];
+    let hex = text.get(..N * 2)?;
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        let high = (hex[index * 2] as char).to_digit(16)?;
+        let low = (hex[index * 2 + 1] as char).to_digit(16)?;
+        *byte = (high << 4 | low) as u8;
+    }
+    Some(bytes)
+}
@@ -0,0 +1,154 @@
+use crate::error::Error;
+use libacpi::{
+    acpi_tables,
+    aml::{
+        find_pci_root_bridges,
+        find_sleep_state,
+        SleepState,
+    },
+    init_acpi_tables,
+    Fadt,
+    Hpet,
+    Madt,
+    Mcfg,
+};
+use libcore::{
+    address::PhysAddr,
+    port,
+};
+use log::info;
+
+pub const POWEROFF_OPTION: &str = "poweroff";
+
+const SOFT_OFF_STATE: u8 = 5;
+const SCI_ENABLE: u16 = 1 << 0;
+const SLEEP_TYPE_SHIFT: u16 = 10;
+const SLEEP_ENABLE: u16 = 1 << 13;
+
+crate::driver!("acpi", [], |boot_info| init_acpi(boot_info.rsdp_address));
+
+/// This function creates the ACPI table registry from the RSDP, which was found by the bootloader,
+/// and logs the discovered tables with a summary of the MADT, HPET and MCFG.
+pub fn init_acpi(rsdp_address: PhysAddr) -> Result<(), Error> {
+    if rsdp_address.is_null() {
+        return Err(Error::NoRsdp);
+    }
+
+    init_acpi_tables(rsdp_address.as_u64(), physmap_mapper)?;
+    let tables = acpi_tables()?;
+    info!(
+        "Found {} ACPI tables (Revision: {}, OEM: {})\n",
+        tables.tables().len(),
+        tables.revision,
+        core::str::from_utf8(&tables.oem_id).unwrap_or("??????")
+    );
+    for entry in tables.tables() {
+        info!(
+            " => {} at 0x{:X} ({} bytes)\n",
+            entry.signature(),
+            entry.physical_address,
+            entry.length
+        );
+    }
+
+    if let Ok(madt) = tables.table::<Madt>() {
+        info!(
+            "MADT: {} processors, Local APIC at 0x{:X}\n",
+            madt.processor_count(),
+            madt.local_apic_address()
+        );
+    }
+
+    if let Ok(hpet) = tables.table::<Hpet>() {
+        info!(
+            "HPET: Base at 0x{:X}, {} comparators\n",
+            hpet.base_address().address,
+            hpet.comparator_count()
+        );
+    }
+
+    if let Ok(mcfg) = tables.table::<Mcfg>() {
+        for entry in mcfg.entries() {
+            info!(
+                "MCFG: Segment {} (Bus {}-{}) at 0x{:X}\n",
+                entry.segment_group, entry.start_bus, entry.end_bus, entry.base_address
+            );
+        }
+    }
+
+    // Evaluate the static objects of the DSDT and SSDTs
+    let aml_tables = tables.aml_tables()?;
+    for aml in aml_tables.iter() {
+        for bridge in find_pci_root_bridges(aml) {
+            info!(
+                "AML: PCI Root Bridge {} (Segment {}, Bus {})\n",
+                bridge.path, bridge.segment, bridge.bus
+            );
+        }
+    }
+
+    if let Some(sleep_state) = soft_off_state() {
+        info!(
+            "AML: \\_S5 (SLP_TYPa: {}, SLP_TYPb: {})\n",
+            sleep_state.sleep_type_a, sleep_state.sleep_type_b
+        );
+    }
+    Ok(())
+}
+
+/// The ACPI tables are accessed through the physmap of the kernel
+fn physmap_mapper(physical_address: u64, _size: usize) -> u64 {
+    PhysAddr::new(physical_address).to_virt().as_u64()
+}
+
+/// This function returns the sleep type values of the soft-off state (`\_S5`) from the DSDT or SSDTs
+fn soft_off_state() -> Option<SleepState> {
+    acpi_tables()
+        .ok()?
+        .aml_tables()
+        .ok()?
+        .iter()
+        .find_map(|aml| find_sleep_state(aml, SOFT_OFF_STATE))
+}
+
+/// This function switches the system into the soft-off state (S5) with the sleep type values of the
+/// `\_S5` object. The system is switched into ACPI mode before, if the firmware didn't do it. This
+/// function only returns, if the system wasn't powered off.
+pub fn power_off() -> Result<(), Error> {
+    let fadt = acpi_tables()?.table::<Fadt>()?;
+    let sleep_state = soft_off_state().ok_or(Error::NoSleepState)?;
+    let pm1a_control = fadt.pm1a_control_block() as u16;
+    let pm1b_control = fadt.pm1b_control_block() as u16;
+    if pm1a_control == 0 {
+        return Err(Error::NoPmControlBlock);
+    }
+
+    unsafe {
+        // Enable ACPI mode with the SMI command port and wait until the SCI is enabled
+        let smi_command_port = fadt.smi_command_port() as u16;
+        let acpi_enable = fadt.acpi_enable_value();
+        if port::read_u16(pm1a_control) & SCI_ENABLE == 0 && smi_command_port != 0 && acpi_enable != 0
+        {
+            port::write_u8(smi_command_port, acpi_enable);
+            while port::read_u16(pm1a_control) & SCI_ENABLE == 0 {
+                core::hint::spin_loop();
+            }
+        }
+
+        info!("Powering off the system\n");
+        libgraphics::log::close_persistent_log();
+        let control = port::read_u16(pm1a_control) & !(0x7 << SLEEP_TYPE_SHIFT);
+        port::write_u16(
+            pm1a_control,
+            control | (sleep_state.sleep_type_a as u16) << SLEEP_TYPE_SHIFT | SLEEP_ENABLE,
+        );
+        if pm1b_control != 0 {
+            let control = port::read_u16(pm1b_control) & !(0x7 << SLEEP_TYPE_SHIFT);
+            port::write_u16(
+                pm1b_control,
+                control | (sleep_state.sleep_type_b as u16) << SLEEP_TYPE_SHIFT | SLEEP_ENABLE,
+            );
+        }
+    }
+    Err(Error::PowerOffFailed)
+}
@@ -0,0 +1,86 @@
+//! Block devices are accessed in blocks of a fixed size (like the 512-byte sectors of a disk). The
+//! file system drivers are written against the [BlockDevice] trait, so they don't depend on the
+//! storage driver. The kernel has no disk drivers yet, so the only block device is the RAM disk,
+//! whose image is a file of the initrd. The reads of the file systems are cached by wrapping the
+//! device into a [CachedDevice](crate::page_cache::CachedDevice).
+
+use crate::error::Error;
+use alloc::string::String;
+
+/// The block size of the RAM disk
+pub const RAM_DISK_BLOCK_SIZE: usize = 512;
+
+/// A device, which is read and written in blocks
+pub trait BlockDevice: Send {
+    fn name(&self) -> &str;
+
+    /// This function returns the size of a block in bytes
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// This function reads the blocks starting at the specified block into the buffer. The length of
+    /// the buffer must be a multiple of the block size.
+    fn read_blocks(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), Error>;
+
+    /// This function writes the data into the blocks starting at the specified block. The length of
+    /// the data must be a multiple of the block size.
+    fn write_blocks(&mut self, block: u64, data: &[u8]) -> Result<(), Error>;
+}
+
+/// A block device in memory. The RAM disk is used for file system images, which are loaded with the
+/// initrd, so the changes are lost on reboot.
+pub struct RamDisk {
+    name: String,
+    data: &'static mut [u8],
+}
+
+impl RamDisk {
+    /// This function creates the RAM disk over the specified memory. Trailing bytes, which don't
+    /// fill a whole block, aren't accessible.
+    pub fn new(name: String, data: &'static mut [u8]) -> Self {
+        Self { name, data }
+    }
+
+    /// This function returns the byte range of the blocks or an error, if the range exceeds the disk
+    fn range(&self, block: u64, length: usize) -> Result<core::ops::Range<usize>, Error> {
+        if length % RAM_DISK_BLOCK_SIZE != 0 {
+            return Err(Error::UnalignedBlockAccess(length));
+        }
+
+        let start = block as usize * RAM_DISK_BLOCK_SIZE;
+        let end = start + length;
+        if block >= self.block_count() || end > self.block_count() as usize * RAM_DISK_BLOCK_SIZE {
+            return Err(Error::BlockOutOfRange(block));
+        }
+        Ok(start..end)
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        RAM_DISK_BLOCK_SIZE
+    }
+
+    #[inline]
+    fn block_count(&self) -> u64 {
+        (self.data.len() / RAM_DISK_BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        let range = self.range(block, buffer.len())?;
+        buffer.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block: u64, data: &[u8]) -> Result<(), Error> {
+        let range = self.range(block, data.len())?;
+        self.data[range].copy_from_slice(data);
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    error::Error,
+    serial,
+    timer,
+};
+use alloc::format;
+use core::fmt;
+use libcore::{
+    boot_info::FramebufferInfo,
+    cmdline::CommandLine,
+    kfmt::KWrite,
+};
+use libgraphics::{
+    embedded_graphics::{
+        mono_font::ascii,
+        pixelcolor::Rgb888,
+        prelude::RgbColor,
+    },
+    text::{
+        blink_text_cursor,
+        cursor,
+        set_cursor,
+        set_text_cursor,
+        write_char,
+        CursorShape,
+        TEXT_WRITER_CONTEXT,
+    },
+    GraphicsContext,
+};
+use log::warn;
+
+/// The shape of the text cursor of the shell (`block`, `underscore` or `none`)
+pub const CURSOR_OPTION: &str = "cursor";
+
+/// The flag, which disables the blinking of the text cursor
+pub const NO_BLINK_OPTION: &str = "noblink";
+
+/// The interval between two phases of the blinking text cursor in milliseconds
+const CURSOR_BLINK_INTERVAL: u64 = 500;
+
+/// This function re-creates the graphics context from the framebuffer, which was handed over by
+/// the bootloader, and installs the logger of the kernel on it. The contexts of the bootloader
+/// were torn down before the handoff, so the kernel doesn't use any bootloader state.
+pub fn init_console(framebuffer: &FramebufferInfo) -> Result<(), Error> {
+    libgraphics::install_context(GraphicsContext::from_boot_info(framebuffer)?)?;
+    libgraphics::text::create_text_writer_context(ascii::FONT_7X14_BOLD)?;
+    libgraphics::fill_buffer(Rgb888::BLACK)?;
+    libgraphics::swap_buffers()?;
+    libgraphics::log::install_logger().map_err(|_| Error::LoggerAlreadyInstalled)?;
+    Ok(())
+}
+
+/// This function writes the formatted text without log prefix to the console and the serial port
+pub fn print(arguments: fmt::Arguments) {
+    let text = format!("{}", arguments);
+    serial::write_bytes(text.as_bytes());
+    if let Some(context) = unsafe { TEXT_WRITER_CONTEXT.as_mut() } {
+        let _ = fmt::Write::write_str(context, &text);
+        let _ = libgraphics::swap_buffers();
+    }
+}
+
+/// This macro writes the text, which is formatted with [libcore::kfmt], to the console, the serial
+/// port and the log tail. It's used instead of the log macros by exception handlers, which should
+/// stay small and fast.
+#[macro_export]
+macro_rules! kprintf {
+    ($($argument:tt)*) => {
+        libcore::kwrite!(&mut $crate::console::ConsoleWriter, $($argument)*)
+    };
+}
+
+/// The writer of [kprintf], the swap buffer is shown once after the complete text was written
+pub struct ConsoleWriter;
+
+impl KWrite for ConsoleWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        serial::write_bytes(bytes);
+        libgraphics::log::append_to_log_tail(bytes);
+        if let Ok(text) = core::str::from_utf8(bytes) {
+            let _ = libgraphics::text::write_str(text);
+        }
+    }
+}
+
+impl Drop for ConsoleWriter {
+    fn drop(&mut self) {
+        if unsafe { TEXT_WRITER_CONTEXT.is_some() } {
+            let _ = libgraphics::swap_buffers();
+        }
+    }
+}
+
+/// This function removes the character before the cursor from the console. The cursor doesn't move
+/// into the previous row.
+pub fn erase_char() -> Result<(), Error> {
+    serial::write_bytes(b"\x08 \x08");
+    let (column, row) = cursor()?;
+    if column > 0 {
+        set_cursor(column - 1, row)?;
+        write_char(' ')?;
+        set_cursor(column - 1, row)?;
+        libgraphics::swap_buffers()?;
+    }
+    Ok(())
+}
+
+/// This function shows the text cursor at the position of the next character and lets it blink
+/// with the timer. The cursor is shown again, when text is written, so it stays visible while
+/// typing. Without blinking or without cursor, this function returns after the cursor was set.
+pub async fn run_text_cursor(command_line: CommandLine<'static>) {
+    let shape = match command_line.get(CURSOR_OPTION) {
+        None | Some("block") => Some(CursorShape::Block),
+        Some("underscore") => Some(CursorShape::Underscore),
+        Some("none") => None,
+        Some(value) => {
+            warn!("Unknown cursor shape '{}', the block cursor is used\n", value);
+            Some(CursorShape::Block)
+        }
+    };
+    if set_text_cursor(shape)
+        .and_then(|_| libgraphics::swap_buffers())
+        .is_err()
+    {
+        return;
+    }
+    if shape.is_none() || command_line.has_flag(NO_BLINK_OPTION) {
+        return;
+    }
+
+    loop {
+        timer::sleep(CURSOR_BLINK_INTERVAL).await;
+        if let Err(error) = blink_text_cursor() {
+            warn!("Unable to blink the text cursor => {}\n", error);
+            return;
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! The core dump writes the state of the kernel on a panic as ELF core file, which can be analyzed
+//! offline with `gdb <kernel> <core>`. The core contains the registers of the panicking code as
+//! `NT_PRSTATUS` note, the log and the kernel slide as `OVERFLOW` notes and the kernel image (with
+//! the statics and the heap) and the stack as loadable segments.
+//!
+//! The dump is streamed over a serial port in checksummed chunks or written into a file of the root
+//! file system. It's assembled from slices of the memory without allocation, because the panicking
+//! code may hold the lock of the heap.
+
+use crate::{
+    error::Error,
+    ext2,
+    heap,
+    page_cache,
+    serial::{
+        self,
+        COM1,
+        COM2,
+    },
+};
+use core::{
+    arch::asm,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+use libcore::{
+    boot_info::BootInfo,
+    hexdump::mapped_slice,
+};
+use log::info;
+
+/// The option `coredump=<com1|com2|path>`, which streams the core dump on a panic over the serial
+/// port or writes it into the file of the root file system
+pub const COREDUMP_OPTION: &str = "coredump";
+
+const PAGE_SIZE: u64 = 4096;
+
+/// The maximal number of pages of the stack above the stack pointer, which are dumped
+const MAX_STACK_PAGES: u64 = 16;
+
+/// The number of bytes in a chunk of the serial stream
+const CHUNK_SIZE: usize = 256;
+
+/// The maximal number of slices, which the core is assembled from
+const MAX_PARTS: usize = 12;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const NOTE_HEADER_SIZE: usize = 12;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_R: u32 = 4;
+const PF_W: u32 = 2;
+const PF_X: u32 = 1;
+
+/// The notes of the core. The `NT_PRSTATUS` note has the layout of `struct elf_prstatus` of Linux,
+/// so GDB finds the registers.
+const CORE_NOTE_NAME: &[u8] = b"CORE\0";
+const OVERFLOW_NOTE_NAME: &[u8] = b"OVERFLOW\0";
+const NT_PRSTATUS: u32 = 1;
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_REGISTERS_OFFSET: usize = 112;
+const SIGNAL_ABORT: u16 = 6;
+const NT_OVERFLOW_LOG: u32 = 1;
+const NT_OVERFLOW_KERNEL: u32 = 2;
+const KERNEL_NOTE_SIZE: usize = 16;
+
+/// The headers and the notes without the log fit into this buffer
+const HEADER_BUFFER_SIZE: usize = 1024;
+
+static ZERO_PAGE: [u8; PAGE_SIZE as usize] = [0; PAGE_SIZE as usize];
+
+static mut TARGET: Option<DumpTarget> = None;
+static mut KERNEL_REGION: (u64, u64) = (0, 0);
+static mut KERNEL_SLIDE: i64 = 0;
+static DUMPING: AtomicBool = AtomicBool::new(false);
+
+/// The destination of the core dump
+#[derive(Clone, Copy)]
+enum DumpTarget {
+    Serial(u16),
+    File(&'static str),
+}
+
+/// The registers of the panicking code in the order of `struct user_regs_struct` of Linux
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+impl Registers {
+    /// This function captures the registers at the call site. RDI and RAX are used by the capture,
+    /// so they contain the address of the registers and the instruction pointer.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut registers = Self::default();
+        unsafe {
+            asm!(
+                "mov [rdi + 0x00], r15",
+                "mov [rdi + 0x08], r14",
+                "mov [rdi + 0x10], r13",
+                "mov [rdi + 0x18], r12",
+                "mov [rdi + 0x20], rbp",
+                "mov [rdi + 0x28], rbx",
+                "mov [rdi + 0x30], r11",
+                "mov [rdi + 0x38], r10",
+                "mov [rdi + 0x40], r9",
+                "mov [rdi + 0x48], r8",
+                "mov [rdi + 0x50], rax",
+                "mov [rdi + 0x58], rcx",
+                "mov [rdi + 0x60], rdx",
+                "mov [rdi + 0x68], rsi",
+                "mov [rdi + 0x70], rdi",
+                "lea rax, [rip]",
+                "mov [rdi + 0x80], rax",
+                "mov word ptr [rdi + 0x88], cs",
+                "pushfq",
+                "pop qword ptr [rdi + 0x90]",
+                "mov [rdi + 0x98], rsp",
+                "mov word ptr [rdi + 0xA0], ss",
+                "mov word ptr [rdi + 0xB8], ds",
+                "mov word ptr [rdi + 0xC0], es",
+                "mov word ptr [rdi + 0xC8], fs",
+                "mov word ptr [rdi + 0xD0], gs",
+                in("rdi") &mut registers as *mut Self,
+                out("rax") _,
+            );
+        }
+        registers
+    }
+
+    fn to_bytes(self) -> [u8; 27 * 8] {
+        let values: [u64; 27] = unsafe { core::mem::transmute(self) };
+        let mut bytes = [0; 27 * 8];
+        for (chunk, value) in bytes.chunks_exact_mut(8).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// This function enables the core dump, if it's requested on the command line. The region of the
+/// kernel image is stored, because the boot information can't be accessed safely in the panic
+/// handler.
+pub fn init_coredump(boot_info: &'static BootInfo) {
+    let target = match boot_info.command_line().get(COREDUMP_OPTION) {
+        None => return,
+        Some("com1") => DumpTarget::Serial(COM1),
+        Some("com2") => {
+            serial::init_port(COM2);
+            DumpTarget::Serial(COM2)
+        }
+        Some(path) => DumpTarget::File(path),
+    };
+
+    unsafe {
+        KERNEL_REGION = (boot_info.kernel_address.as_u64(), boot_info.kernel_size);
+        KERNEL_SLIDE = boot_info.kernel_slide;
+        TARGET = Some(target);
+    }
+    match target {
+        DumpTarget::Serial(port) => info!("Enabled core dumps over serial port 0x{:X}\n", port),
+        DumpTarget::File(path) => info!("Enabled core dumps to {}\n", path),
+    }
+}
+
+/// This function writes the core dump with the registers, if core dumps are enabled. A panic while
+/// dumping doesn't start another dump.
+pub fn write_core_dump(registers: &Registers) -> Result<(), Error> {
+    let Some(target) = (unsafe { TARGET }) else {
+        return Ok(());
+    };
+    if DUMPING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let mut headers = [0; HEADER_BUFFER_SIZE];
+    let core = CoreImage::assemble(&mut headers, registers);
+    match target {
+        DumpTarget::Serial(port) => {
+            stream_core(port, &core);
+            info!("Streamed core dump with {} bytes over serial port 0x{:X}\n", core.size(), port);
+        }
+        DumpTarget::File(path) => {
+            // The file system allocates its buffers on the heap and reads over the page cache
+            if heap::is_locked() {
+                return Err(Error::LockedByPanic("kernel heap"));
+            }
+            if page_cache::is_locked() {
+                return Err(Error::LockedByPanic("page cache"));
+            }
+            ext2::try_with_root_file_system(|file_system| {
+                file_system.write_file_parts(path, core.parts())
+            })?;
+            info!("Wrote core dump with {} bytes to {}\n", core.size(), path);
+        }
+    }
+    Ok(())
+}
+
+/// The core file, which is assembled from the headers and slices of the memory
+struct CoreImage<'a> {
+    parts: [&'a [u8]; MAX_PARTS],
+    count: usize,
+}
+
+impl<'a> CoreImage<'a> {
+    fn assemble(headers: &'a mut [u8; HEADER_BUFFER_SIZE], registers: &Registers) -> Self {
+        let (older_log, newer_log) = libgraphics::log::log_tail().contents();
+        let log_size = older_log.len() + newer_log.len();
+        let segments = memory_segments(registers.rsp);
+        let segment_count = segments.iter().flatten().count();
+
+        // The notes follow the program headers, the segments start at page boundaries
+        let program_headers_end = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE * (1 + segment_count);
+        let notes_size = note_size(CORE_NOTE_NAME, PRSTATUS_SIZE)
+            + note_size(OVERFLOW_NOTE_NAME, KERNEL_NOTE_SIZE)
+            + note_size(OVERFLOW_NOTE_NAME, log_size);
+        let mut offset = align_up((program_headers_end + notes_size) as u64, PAGE_SIZE);
+
+        let mut writer = HeaderWriter {
+            buffer: &mut *headers,
+            position: 0,
+        };
+        writer.elf_header(1 + segment_count as u16);
+        writer.program_header(PT_NOTE, 0, program_headers_end as u64, 0, notes_size as u64, 4);
+        for (address, data) in segments.iter().flatten() {
+            writer.program_header(
+                PT_LOAD,
+                PF_R | PF_W | PF_X,
+                offset,
+                *address,
+                data.len() as u64,
+                PAGE_SIZE,
+            );
+            offset = align_up(offset + data.len() as u64, PAGE_SIZE);
+        }
+
+        let mut prstatus = [0; PRSTATUS_SIZE];
+        prstatus[12..14].copy_from_slice(&SIGNAL_ABORT.to_le_bytes());
+        prstatus[32..36].copy_from_slice(&1_u32.to_le_bytes());
+        prstatus[PRSTATUS_REGISTERS_OFFSET..PRSTATUS_REGISTERS_OFFSET + 27 * 8]
+            .copy_from_slice(&registers.to_bytes());
+        writer.note(CORE_NOTE_NAME, NT_PRSTATUS, PRSTATUS_SIZE);
+        writer.bytes(&prstatus);
+
+        let (kernel_address, _) = unsafe { KERNEL_REGION };
+        writer.note(OVERFLOW_NOTE_NAME, NT_OVERFLOW_KERNEL, KERNEL_NOTE_SIZE);
+        writer.bytes(&kernel_address.to_le_bytes());
+        writer.bytes(&unsafe { KERNEL_SLIDE }.to_le_bytes());
+        writer.note(OVERFLOW_NOTE_NAME, NT_OVERFLOW_LOG, log_size);
+        let header_size = writer.position;
+        let headers: &'a [u8] = headers;
+
+        let mut core = Self {
+            parts: [&[]; MAX_PARTS],
+            count: 0,
+        };
+        core.push(&headers[..header_size]);
+        core.push(older_log);
+        core.push(newer_log);
+        let mut size = align_up((header_size + log_size) as u64, 4);
+        core.push(&ZERO_PAGE[..size as usize - header_size - log_size]);
+        for (_, data) in segments.iter().flatten() {
+            let padding = align_up(size, PAGE_SIZE) - size;
+            core.push(&ZERO_PAGE[..padding as usize]);
+            core.push(data);
+            size += padding + data.len() as u64;
+        }
+        core
+    }
+
+    fn push(&mut self, part: &'a [u8]) {
+        if !part.is_empty() {
+            self.parts[self.count] = part;
+            self.count += 1;
+        }
+    }
+
+    #[inline]
+    fn parts(&self) -> &[&'a [u8]] {
+        &self.parts[..self.count]
+    }
+
+    fn size(&self) -> u64 {
+        self.parts().iter().map(|part| part.len() as u64).sum()
+    }
+}
+
+/// The writer of the ELF header, the program headers and the notes into the header buffer
+struct HeaderWriter<'a> {
+    buffer: &'a mut [u8; HEADER_BUFFER_SIZE],
+    position: usize,
+}
+
+impl HeaderWriter<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer[self.position..self.position + bytes.len()].copy_from_slice(bytes);
+        self.position += bytes.len();
+    }
+
+    fn elf_header(&mut self, program_header_count: u16) {
+        self.bytes(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        self.bytes(&ET_CORE.to_le_bytes());
+        self.bytes(&EM_X86_64.to_le_bytes());
+        self.bytes(&1_u32.to_le_bytes()); // Version
+        self.bytes(&0_u64.to_le_bytes()); // Entry
+        self.bytes(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // Program header offset
+        self.bytes(&0_u64.to_le_bytes()); // Section header offset
+        self.bytes(&0_u32.to_le_bytes()); // Flags
+        self.bytes(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+        self.bytes(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        self.bytes(&program_header_count.to_le_bytes());
+        self.bytes(&[0; 6]); // No section headers
+    }
+
+    fn program_header(
+        &mut self, kind: u32, flags: u32, offset: u64, address: u64, size: u64, align: u64,
+    ) {
+        self.bytes(&kind.to_le_bytes());
+        self.bytes(&flags.to_le_bytes());
+        self.bytes(&offset.to_le_bytes());
+        self.bytes(&address.to_le_bytes()); // Virtual address
+        self.bytes(&address.to_le_bytes()); // Physical address
+        let memory_size = match kind {
+            PT_LOAD => size,
+            _ => 0,
+        };
+        self.bytes(&size.to_le_bytes()); // File size
+        self.bytes(&memory_size.to_le_bytes());
+        self.bytes(&align.to_le_bytes());
+    }
+
+    /// This function writes the header and the padded name of a note. The description follows.
+    fn note(&mut self, name: &[u8], kind: u32, description_size: usize) {
+        self.bytes(&(name.len() as u32).to_le_bytes());
+        self.bytes(&(description_size as u32).to_le_bytes());
+        self.bytes(&kind.to_le_bytes());
+        self.bytes(name);
+        let padding = align_up(name.len() as u64, 4) as usize - name.len();
+        self.bytes(&[0; 3][..padding]);
+    }
+}
+
+/// This function returns the kernel image and the mapped pages of the stack above the stack pointer
+/// with their addresses. The stack is skipped, if it's inside of the kernel image.
+fn memory_segments(stack_pointer: u64) -> [Option<(u64, &'static [u8])>; 2] {
+    let (kernel_address, kernel_size) = unsafe { KERNEL_REGION };
+    let kernel = unsafe { mapped_slice(kernel_address, kernel_size as usize) }
+        .map(|data| (kernel_address, data));
+
+    let stack_start = stack_pointer & !(PAGE_SIZE - 1);
+    let stack_pages = (0..MAX_STACK_PAGES)
+        .take_while(|page| {
+            unsafe { mapped_slice(stack_start + page * PAGE_SIZE, PAGE_SIZE as usize) }.is_some()
+        })
+        .count() as u64;
+    let in_kernel = stack_start >= kernel_address && stack_start < kernel_address + kernel_size;
+    let stack = unsafe { mapped_slice(stack_start, (stack_pages * PAGE_SIZE) as usize) }
+        .filter(|_| !in_kernel)
+        .map(|data| (stack_start, data));
+    [kernel, stack]
+}
+
+/// This function streams the core over the serial port. The stream starts with `COREDUMP BEGIN
+/// <size>`, every chunk is written as `COREDUMP <offset> <crc32> <data>` with hexadecimal numbers and
+/// data and the stream ends with `COREDUMP END <crc32>` with the checksum of the whole core.
+fn stream_core(port: u16, core: &CoreImage) {
+    serial::write_raw(port, b"\nCOREDUMP BEGIN ");
+    write_hex(port, core.size(), 16);
+    serial::write_raw(port, b"\n");
+
+    let mut chunk = [0; CHUNK_SIZE];
+    let mut length = 0;
+    let mut offset = 0;
+    let mut total_crc = !0;
+    for part in core.parts() {
+        for byte in part.iter() {
+            chunk[length] = *byte;
+            length += 1;
+            if length == CHUNK_SIZE {
+                total_crc = crc32_update(total_crc, &chunk);
+                write_chunk(port, offset, &chunk);
+                offset += CHUNK_SIZE as u64;
+                length = 0;
+            }
+        }
+    }
+    if length != 0 {
+        total_crc = crc32_update(total_crc, &chunk[..length]);
+        write_chunk(port, offset, &chunk[..length]);
+    }
+
+    serial::write_raw(port, b"COREDUMP END ");
+    write_hex(port, !total_crc as u64, 8);
+    serial::write_raw(port, b"\n");
+}
+
+fn write_chunk(port: u16, offset: u64, data: &[u8]) {
+    serial::write_raw(port, b"COREDUMP ");
+    write_hex(port, offset, 16);
+    serial::write_raw(port, b" ");
+    write_hex(port, !crc32_update(!0, data) as u64, 8);
+    serial::write_raw(port, b" ");
+    for byte in data {
+        write_hex(port, *byte as u64, 2);
+    }
+    serial::write_raw(port, b"\n");
+}
+
+/// This function writes the value with the specified number of hexadecimal digits
+fn write_hex(port: u16, value: u64, digits: usize) {
+    let mut buffer = [0; 16];
+    for (index, digit) in buffer[..digits].iter_mut().rev().enumerate() {
+        *digit = b"0123456789abcdef"[(value >> (index * 4)) as usize & 0xF];
+    }
+    serial::write_raw(port, &buffer[..digits]);
+}
+
+/// This function updates the CRC-32 (IEEE 802.3) with the data. The checksum starts with `!0` and is
+/// inverted at the end, so it matches `zlib.crc32` of Python.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
+/// This function returns the size of a note with the padded name and the padded description
+#[inline]
+fn note_size(name: &[u8], description_size: usize) -> usize {
+    NOTE_HEADER_SIZE
+        + align_up(name.len() as u64, 4) as usize
+        + align_up(description_size as u64, 4) as usize
+}
+
+#[inline]
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
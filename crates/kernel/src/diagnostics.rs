@@ -0,0 +1,197 @@
+use crate::{
+    frames,
+    heap,
+    serial,
+};
+use alloc::{
+    format,
+    string::String,
+};
+use libcore::{
+    address::VirtAddr,
+    alloc_stats::AllocationReport,
+    descriptors::{
+        gdt_entries,
+        idt_entries,
+        read_gdtr,
+        read_idtr,
+    },
+    paging::{
+        level_page_size,
+        mapping_chain,
+        EntryFlags,
+        PRESENT,
+    },
+    table::{
+        Alignment,
+        Column,
+        Table,
+    },
+    trace,
+};
+use log::{
+    error,
+    info,
+    warn,
+};
+
+/// The flag, which dumps the descriptor tables while booting the kernel
+pub const DUMP_DESCRIPTORS_OPTION: &str = "dump-descriptors";
+
+/// The flag, which dumps the allocation statistics of the heap and the frame allocator after boot
+pub const DUMP_MEMORY_OPTION: &str = "dump-memory";
+
+/// The markers of the boot trace in the serial output, so the JSON can be cut out of the log
+const TRACE_BEGIN_MARKER: &[u8] = b"@@TRACE-BEGIN\n";
+const TRACE_END_MARKER: &[u8] = b"\n@@TRACE-END\n";
+
+static GDT_TABLE: Table = Table::new(&[
+    Column::new("Selector", 8, Alignment::Right),
+    Column::new("Type", 16, Alignment::Left),
+    Column::new("Base", 18, Alignment::Right),
+    Column::new("Limit", 7, Alignment::Right),
+    Column::new("DPL", 3, Alignment::Right),
+    Column::new("Present", 7, Alignment::Left),
+]);
+
+static IDT_TABLE: Table = Table::new(&[
+    Column::new("Vector", 6, Alignment::Right),
+    Column::new("Type", 9, Alignment::Left),
+    Column::new("Handler", 18, Alignment::Right),
+    Column::new("Selector", 8, Alignment::Right),
+    Column::new("IST", 3, Alignment::Right),
+    Column::new("DPL", 3, Alignment::Right),
+]);
+
+/// This function reads back the current GDTR and IDTR, decodes every descriptor of both tables and
+/// dumps them through the logger. Descriptors, which violate an invariant, are reported as warning.
+/// Returns the number of detected issues.
+pub fn dump_descriptor_tables() -> usize {
+    let mut issue_count = 0;
+    let gdtr = read_gdtr();
+    let (gdt_base, gdt_limit) = (gdtr.base, gdtr.limit);
+    info!("GDT at 0x{:X} (Limit: 0x{:X})\n", gdt_base, gdt_limit);
+    info!("{}\n", GDT_TABLE.header());
+    info!("{}\n", GDT_TABLE.separator());
+    for descriptor in unsafe { gdt_entries(&gdtr) } {
+        info!(
+            "{}\n",
+            GDT_TABLE.row(&[
+                &format_args!("0x{:02X}", descriptor.selector),
+                &descriptor.kind,
+                &format_args!("0x{:X}", descriptor.base),
+                &format_args!("0x{:X}", descriptor.limit),
+                &descriptor.dpl,
+                &if descriptor.present { "Yes" } else { "No" },
+            ])
+        );
+
+        if let Some(issue) = descriptor.validate() {
+            warn!(" => Selector 0x{:02X}: {}\n", descriptor.selector, issue);
+            issue_count += 1;
+        }
+    }
+
+    let idtr = read_idtr();
+    let (idt_base, idt_limit) = (idtr.base, idtr.limit);
+    info!("IDT at 0x{:X} (Limit: 0x{:X})\n", idt_base, idt_limit);
+    info!("{}\n", IDT_TABLE.header());
+    info!("{}\n", IDT_TABLE.separator());
+    let mut absent_gates = 0;
+    for gate in unsafe { idt_entries(&idtr) } {
+        if !gate.present {
+            absent_gates += 1;
+            continue;
+        }
+
+        info!(
+            "{}\n",
+            IDT_TABLE.row(&[
+                &gate.vector,
+                &gate.kind_name(),
+                &format_args!("0x{:X}", gate.handler),
+                &format_args!("0x{:02X}", gate.selector),
+                &gate.ist,
+                &gate.dpl,
+            ])
+        );
+
+        if let Some(issue) = unsafe { gate.validate(&gdtr) } {
+            warn!(" => Vector {}: {}\n", gate.vector, issue);
+            issue_count += 1;
+        }
+    }
+
+    info!("{} gates not present, {} issues found in descriptor tables\n", absent_gates, issue_count);
+    issue_count
+}
+
+/// This function prints the chain of page table entries (PML4E -> PDPTE -> PDE -> PTE), which map
+/// the specified address, with the flags of every entry.
+pub fn dump_mapping(address: VirtAddr) {
+    error!("Mapping of 0x{:X}:\n", address);
+    for step in unsafe { mapping_chain(address) } {
+        if step.entry & PRESENT == 0 {
+            error!(
+                " => {}[{}] = 0x{:016X} (not present)\n",
+                step.level_name(),
+                step.index,
+                step.entry
+            );
+            return;
+        }
+
+        error!(
+            " => {}[{}] = 0x{:016X} ({})\n",
+            step.level_name(),
+            step.index,
+            step.entry,
+            EntryFlags(step.entry)
+        );
+        if step.is_leaf() {
+            error!(" => Mapped by {} kB page\n", level_page_size(step.level) / 1024);
+        }
+    }
+}
+
+/// This function dumps the allocation statistics of the kernel heap and the frame allocator, which
+/// was handed over by the bootloader.
+pub fn dump_memory_report() {
+    dump_allocation_report("Kernel Heap", &heap::heap_report());
+    match frames::frame_report() {
+        Some(report) => dump_allocation_report("Frame Allocator", &report),
+        None => warn!("Frame Allocator: Not available\n"),
+    }
+}
+
+fn dump_allocation_report(name: &str, report: &AllocationReport) {
+    for (index, line) in format!("{}", report).lines().enumerate() {
+        match index {
+            0 => info!("{}: {}\n", name, line),
+            _ => info!("{}\n", line),
+        }
+    }
+}
+
+/// This function emits the boot trace as JSON for `chrome://tracing` over the serial console. The
+/// JSON is written between the `@@TRACE-BEGIN` and `@@TRACE-END` lines, so it can be saved from the
+/// log of the serial console or by the test harness.
+pub fn dump_boot_trace() {
+    let Some(buffer) = trace::trace_buffer() else {
+        warn!("No boot trace was handed over by the bootloader\n");
+        return;
+    };
+
+    let mut json = String::new();
+    if buffer.write_json(&mut json).is_err() {
+        return;
+    }
+    serial::write_bytes(TRACE_BEGIN_MARKER);
+    serial::write_bytes(json.as_bytes());
+    serial::write_bytes(TRACE_END_MARKER);
+    info!(
+        "Emitted boot trace with {} events over the serial console ({} dropped)\n",
+        buffer.events().len(),
+        buffer.dropped_events()
+    );
+}
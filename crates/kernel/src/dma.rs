@@ -0,0 +1,104 @@
+//! DMA buffers are physically contiguous memory, which is shared between the kernel and a device.
+//! The kernel accesses the buffer through the physmap and the device through the physical address.
+//! The frames are allocated from the frame allocator of the bootloader, so the buffer can be placed
+//! below the address limit of the device (like 4 GiB for devices with 32-bit addresses).
+//!
+//! The DMA of x86 is cache coherent, so buffers are mapped write-back by default. Other memory types
+//! are selected through the PAT, which is programmed by the bootloader.
+
+use crate::{
+    error::Error,
+    frames,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    error::Error as CoreError,
+    paging::{
+        set_cache_type,
+        PAGE_SIZE,
+    },
+    pat::{
+        pat_index,
+        read_pat,
+        CacheType,
+    },
+};
+
+/// A physically contiguous buffer for the DMA of a device. The buffer is zeroed on allocation and
+/// the frames are freed (and mapped write-back again), when the buffer is dropped.
+pub struct DmaBuffer {
+    physical_address: PhysAddr,
+    virtual_address: VirtAddr,
+    page_count: usize,
+    cache_type: CacheType,
+}
+
+impl DmaBuffer {
+    /// This function allocates a buffer with the specified size below the specified address. The
+    /// physical address of the buffer has the specified alignment (at least the page size) and the
+    /// buffer is mapped with the specified memory type.
+    pub fn allocate(
+        size: usize, max_address: PhysAddr, alignment: u64, cache_type: CacheType,
+    ) -> Result<Self, Error> {
+        let page_count = size.max(1).div_ceil(PAGE_SIZE as usize);
+        let physical_address = frames::alloc_contiguous(page_count, max_address, alignment)?;
+        let mut buffer = Self {
+            physical_address,
+            virtual_address: physical_address.to_virt(),
+            page_count,
+            cache_type: CacheType::WriteBack,
+        };
+
+        if cache_type != CacheType::WriteBack {
+            buffer.set_cache_type(cache_type)?;
+        }
+        Ok(buffer)
+    }
+
+    /// This function changes the memory type of the buffer in the page tables. The PAT has to contain
+    /// the memory type at the index, which is expected by the [libcore::pat::PAT_LAYOUT].
+    fn set_cache_type(&mut self, cache_type: CacheType) -> Result<(), Error> {
+        let index = pat_index(cache_type).ok_or(CoreError::UnsupportedCacheType(cache_type as u8))?;
+        if (read_pat() >> (index * 8)) as u8 & 0x07 != cache_type as u8 {
+            return Err(CoreError::UnsupportedCacheType(cache_type as u8).into());
+        }
+
+        // The page tables of split huge pages stay in use, so their frames are never released
+        let mut allocate_table = frames::allocate_frame;
+        unsafe {
+            set_cache_type(self.virtual_address, self.size() as u64, cache_type, &mut allocate_table)?
+        };
+        self.cache_type = cache_type;
+        Ok(())
+    }
+
+    /// This function returns the physical address of the buffer, which is passed to the device
+    #[inline]
+    pub fn physical_address(&self) -> PhysAddr {
+        self.physical_address
+    }
+
+    /// This function returns the virtual address of the buffer, which is used by the kernel
+    #[inline]
+    pub fn virtual_address(&self) -> VirtAddr {
+        self.virtual_address
+    }
+
+    /// This function returns the size of the buffer, which is rounded up to whole pages
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.page_count * PAGE_SIZE as usize
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        if self.cache_type != CacheType::WriteBack {
+            let _ = self.set_cache_type(CacheType::WriteBack);
+        }
+        unsafe { frames::free_contiguous(self.physical_address, self.page_count) };
+    }
+}
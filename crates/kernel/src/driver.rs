@@ -0,0 +1,161 @@
+use crate::{
+    acpi,
+    error::Error,
+    fdt,
+    iommu,
+    keyboard,
+    mouse,
+    pic,
+    timer,
+};
+use alloc::vec::Vec;
+use core::arch::x86_64::_rdtsc;
+use libcore::boot_info::BootInfo;
+use log::{
+    info,
+    warn,
+};
+
+/// This macro declares the driver of the module as `DRIVER` with the name, the names of the drivers,
+/// which must be initialized before, and the init function. The driver is initialized by the
+/// registry, when it's added to [DRIVERS].
+#[macro_export]
+macro_rules! driver {
+    ($name:literal, [$($dependency:literal),* $(,)?], $init:expr) => {
+        pub(crate) static DRIVER: $crate::driver::Driver = $crate::driver::Driver {
+            name: $name,
+            dependencies: &[$($dependency),*],
+            init: $init,
+        };
+    };
+}
+
+/// All drivers of the kernel. The order doesn't matter, the drivers are initialized after their
+/// dependencies.
+static DRIVERS: &[&Driver] = &[
+    &acpi::DRIVER,
+    &fdt::DRIVER,
+    &iommu::DRIVER,
+    &keyboard::DRIVER,
+    &mouse::DRIVER,
+    &pic::DRIVER,
+    &timer::DRIVER,
+];
+
+/// The results of the driver initialization, which are shown by `lsdrv`
+static mut DRIVER_REPORTS: Option<Vec<DriverReport>> = None;
+
+/// A driver, which is declared with [driver]
+pub struct Driver {
+    pub name: &'static str,
+    pub dependencies: &'static [&'static str],
+    pub init: fn(&'static BootInfo) -> Result<(), Error>,
+}
+
+/// The result of the initialization of a driver
+pub struct DriverReport {
+    pub name: &'static str,
+    pub result: Result<(), Error>,
+    /// The TSC cycles, which were spent in the init function
+    pub cycles: u64,
+}
+
+/// This function returns the drivers in the order of their initialization. Every driver follows its
+/// dependencies. Drivers with unknown, circular or failed dependencies are returned separately with
+/// the error.
+fn sort_drivers() -> (Vec<&'static Driver>, Vec<(&'static Driver, Error)>) {
+    let mut sorted: Vec<&'static Driver> = Vec::new();
+    let mut failed = Vec::new();
+    let mut pending = Vec::new();
+    for driver in DRIVERS {
+        let unknown = driver
+            .dependencies
+            .iter()
+            .find(|dependency| !DRIVERS.iter().any(|driver| driver.name == **dependency));
+        match unknown {
+            Some(dependency) => {
+                failed.push((*driver, Error::UnknownDependency(driver.name, dependency)))
+            }
+            None => pending.push(*driver),
+        }
+    }
+
+    // Move the drivers, whose dependencies are sorted, until no driver can be moved anymore
+    loop {
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|driver| {
+            driver
+                .dependencies
+                .iter()
+                .all(|dependency| sorted.iter().any(|sorted| sorted.name == *dependency))
+        });
+        pending = waiting;
+        if ready.is_empty() {
+            break;
+        }
+        sorted.extend(ready);
+    }
+
+    // The remaining drivers depend on each other or on a driver, which failed to be sorted
+    for driver in pending.iter() {
+        let failed_dependency = driver.dependencies.iter().find(|dependency| {
+            !sorted.iter().any(|sorted| sorted.name == **dependency)
+                && !pending.iter().any(|pending| pending.name == **dependency)
+        });
+        let error = match failed_dependency {
+            Some(dependency) => Error::DependencyFailed(dependency),
+            None => Error::UnresolvedDependencies(driver.name),
+        };
+        failed.push((*driver, error));
+    }
+    (sorted, failed)
+}
+
+/// This function initializes all drivers after their dependencies and logs the result and the init
+/// time of every driver. A failed driver doesn't stop the boot, only the drivers, which depend on
+/// it, are skipped.
+pub fn init_drivers(boot_info: &'static BootInfo) {
+    let (sorted, failed) = sort_drivers();
+    let mut reports = Vec::with_capacity(DRIVERS.len());
+    for (driver, error) in failed {
+        warn!("Unable to initialize driver '{}' => {}\n", driver.name, error);
+        reports.push(DriverReport {
+            name: driver.name,
+            result: Err(error),
+            cycles: 0,
+        });
+    }
+
+    for driver in sorted {
+        let failed_dependency = driver.dependencies.iter().find(|dependency| {
+            reports
+                .iter()
+                .any(|report: &DriverReport| report.name == **dependency && report.result.is_err())
+        });
+        let (result, cycles) = match failed_dependency {
+            Some(dependency) => (Err(Error::DependencyFailed(dependency)), 0),
+            None => {
+                let _trace_scope = libcore::trace::trace_scope(driver.name);
+                let start = unsafe { _rdtsc() };
+                let result = (driver.init)(boot_info);
+                (result, unsafe { _rdtsc() } - start)
+            }
+        };
+
+        match &result {
+            Ok(()) => info!("Initialized driver '{}' ({} kcycles)\n", driver.name, cycles / 1000),
+            Err(error) => warn!("Unable to initialize driver '{}' => {}\n", driver.name, error),
+        }
+        reports.push(DriverReport {
+            name: driver.name,
+            result,
+            cycles,
+        });
+    }
+    unsafe { DRIVER_REPORTS = Some(reports) };
+}
+
+/// This function returns the results of the driver initialization in the order of initialization.
+/// Drivers with unknown or circular dependencies come first.
+pub fn driver_reports() -> &'static [DriverReport] {
+    unsafe { DRIVER_REPORTS.as_deref() }.unwrap_or_default()
+}
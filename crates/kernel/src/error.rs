@@ -0,0 +1,256 @@
+use alloc::string::String;
+use thiserror_no_std::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Core Error: {0}")]
+    Core(#[from] libcore::error::Error),
+
+    #[error("ACPI Error: {0}")]
+    Acpi(#[from] libacpi::error::Error),
+
+    #[error("Graphics Error: {0:?}")]
+    Graphics(#[from] libgraphics::error::Error),
+
+    #[error("Runtime Error: {0}")]
+    Runtime(#[from] libruntime::error::Error),
+
+    #[error("Console Error: A logger is already installed")]
+    LoggerAlreadyInstalled,
+
+    #[error("ACPI Error: No RSDP was passed by the bootloader")]
+    NoRsdp,
+
+    #[error("ACPI Error: No \\_S5 object was found in the DSDT or SSDTs")]
+    NoSleepState,
+
+    #[error("ACPI Error: The FADT doesn't describe a PM1a control block")]
+    NoPmControlBlock,
+
+    #[error("ACPI Error: The system wasn't powered off")]
+    PowerOffFailed,
+
+    #[error("Keyboard Error: The keyboard didn't respond to the command")]
+    KeyboardTimeout,
+
+    #[error("Keyboard Error: Unexpected response 0x{0:02X} to the command")]
+    UnexpectedKeyboardResponse(u8),
+
+    #[error("Keyboard Error: Invalid scancode set {0} (expected 1, 2 or 3)")]
+    InvalidScancodeSet(u8),
+
+    #[error("Memory Error: No physical frame is available")]
+    OutOfFrames,
+
+    #[error("Memory Error: The bootloader didn't hand over the frame allocator")]
+    NoFrameAllocator,
+
+    #[error("Memory Error: No {0} contiguous frames are available below 0x{1:X}")]
+    NoContiguousFrames(usize, u64),
+
+    #[error("Memory Error: Page at 0x{0:X} is already mapped")]
+    PageAlreadyMapped(u64),
+
+    #[error("Memory Error: Page at 0x{0:X} is mapped by a huge page")]
+    HugePageMapped(u64),
+
+    #[error("Process Error: Unsupported ELF type {0} (expected executable or shared object)")]
+    UnsupportedProcessType(u16),
+
+    #[error("Process Error: Segment at 0x{0:X} is located outside of the user space")]
+    InvalidSegmentAddress(u64),
+
+    #[error("Process Error: Arguments and environment don't fit on the user stack")]
+    ArgumentsTooLarge,
+
+    #[error("Process Error: No random generator for the AT_RANDOM bytes")]
+    NoRandomGenerator,
+
+    #[error("Module Error: Module is not a relocatable ELF file")]
+    NotRelocatable,
+
+    #[error("Module Error: Module has no symbol table")]
+    NoSymbolTable,
+
+    #[error("Module Error: Unable to resolve symbol '{0}'")]
+    UnresolvedSymbol(String),
+
+    #[error("Module Error: Symbol '{0}' is exported with another signature than the module imports")]
+    SymbolAbiMismatch(String),
+
+    #[error("Module Error: Symbol '{0}' isn't declared with module_import!")]
+    MissingSymbolImport(String),
+
+    #[error("Module Error: Module '{0}' doesn't declare a kernel ABI version")]
+    MissingAbiVersion(String),
+
+    #[error("Module Error: Module '{0}' was built against ABI version {1} (kernel ABI version {2})")]
+    ModuleAbiMismatch(String, u32, u32),
+
+    #[error("Module Error: Symbol references invalid section {0}")]
+    InvalidSymbolSection(u16),
+
+    #[error("Module Error: Section {0} has the alignment {1}, which isn't a power of two")]
+    InvalidSectionAlignment(usize, u64),
+
+    #[error("Module Error: Section {0} exceeds the size of the module memory")]
+    ModuleTooLarge(usize),
+
+    #[error("Module Error: Unable to allocate {0} bytes for module")]
+    OutOfMemory(usize),
+
+    #[error("Module Error: Module '{0}' is already loaded")]
+    ModuleAlreadyLoaded(String),
+
+    #[error("Module Error: Module '{0}' is not loaded")]
+    ModuleNotLoaded(String),
+
+    #[error("Module Error: Initialization of module '{0}' failed with code {1}")]
+    ModuleInitFailed(String, i32),
+
+    #[error("Debugger Error: Unknown serial port '{0}' (expected com1 or com2)")]
+    UnknownSerialPort(String),
+
+    #[error("Mouse Error: The PS/2 controller didn't respond")]
+    MouseTimeout,
+
+    #[error("Mouse Error: Unexpected response 0x{0:02X} from mouse")]
+    UnexpectedMouseResponse(u8),
+
+    #[error("Shell Error: Unknown command '{0}', type 'help' for a list of commands")]
+    UnknownCommand(String),
+
+    #[error("Shell Error: Usage: {0}")]
+    InvalidUsage(&'static str),
+
+    #[error("Shell Error: '{0}' is not a valid number")]
+    InvalidNumber(String),
+
+    #[error("Shell Error: No initrd was passed by the bootloader")]
+    NoInitrd,
+
+    #[error("Shell Error: File '{0}' not found in initrd")]
+    FileNotFound(String),
+
+    #[error("Shell Error: Address 0x{0:X} is not mapped")]
+    UnmappedAddress(u64),
+
+    #[error("Shell Error: The system wasn't reset")]
+    ResetFailed,
+
+    #[error("Network Error: No virtio-net device with legacy I/O interface found")]
+    NoNetworkDevice,
+
+    #[error("Network Error: Virtqueue {0} is unavailable or too small")]
+    VirtqueueUnavailable(u16),
+
+    #[error("Network Error: Unable to allocate {0} bytes for virtqueue")]
+    QueueAllocationFailed(usize),
+
+    #[error("Network Error: Frame with {0} bytes exceeds the maximal frame size")]
+    FrameTooLarge(usize),
+
+    #[error("Network Error: No free transmit buffer")]
+    TransmitQueueFull,
+
+    #[error("Network Error: Invalid network option '{0}'")]
+    InvalidNetworkOption(String),
+
+    #[error("PCI Error: No free vector for message signaled interrupts")]
+    NoFreeVector,
+
+    #[error("PCI Error: MSI-X table entry {0} doesn't exist")]
+    InvalidMsiEntry(u16),
+
+    #[error("Hardening Error: {0} is not supported by the CPU")]
+    UnsupportedMitigation(&'static str),
+
+    #[error("Hardening Error: Unknown mitigation '{0}'")]
+    UnknownMitigation(String),
+
+    #[error("Driver Error: Driver '{0}' depends on the unknown driver '{1}'")]
+    UnknownDependency(&'static str, &'static str),
+
+    #[error(
+        "Driver Error: The dependencies of driver '{0}' can't be resolved (circular dependency)"
+    )]
+    UnresolvedDependencies(&'static str),
+
+    #[error("Driver Error: Dependency '{0}' failed to initialize")]
+    DependencyFailed(&'static str),
+
+    #[error("Profiler Error: Invalid sample frequency {0} Hz (expected 1 to 10000 Hz)")]
+    InvalidSampleFrequency(u64),
+
+    #[error("Profiler Error: The PIT doesn't tick, the APIC timer can't be calibrated")]
+    TimerNotTicking,
+
+    #[error("Block Error: Access with {0} bytes isn't a multiple of the block size")]
+    UnalignedBlockAccess(usize),
+
+    #[error("Block Error: Block {0} is outside of the device")]
+    BlockOutOfRange(u64),
+
+    #[error("Ext2 Error: No ext2 file system found (invalid superblock magic)")]
+    NoExt2FileSystem,
+
+    #[error("Ext2 Error: Unsupported incompatible features 0x{0:X}")]
+    UnsupportedExt2Features(u32),
+
+    #[error("Ext2 Error: Unsupported block size of {0} bytes")]
+    UnsupportedBlockSize(usize),
+
+    #[error("Ext2 Error: Unsupported inode size of {0} bytes")]
+    UnsupportedInodeSize(usize),
+
+    #[error("Ext2 Error: Inode {0} is invalid")]
+    InvalidInode(u32),
+
+    #[error("Ext2 Error: Directory with inode {0} is corrupt")]
+    CorruptDirectory(u32),
+
+    #[error("File System Error: No root file system is mounted")]
+    NoRootFileSystem,
+
+    #[error("File System Error: Image '{0}' not found in initrd")]
+    RootImageNotFound(String),
+
+    #[error("File System Error: '{0}' not found")]
+    PathNotFound(String),
+
+    #[error("File System Error: '{0}' is not a directory")]
+    NotADirectory(String),
+
+    #[error("File System Error: '{0}' is a directory")]
+    IsADirectory(String),
+
+    #[error("File System Error: '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("File System Error: Invalid file name '{0}'")]
+    InvalidFileName(String),
+
+    #[error("File System Error: The file system is mounted read-only")]
+    ReadOnlyFileSystem,
+
+    #[error("File System Error: No free blocks or inodes left")]
+    NoSpaceLeft,
+
+    #[error("File System Error: File exceeds the maximal file size")]
+    FileTooLarge,
+
+    #[error("Core Dump Error: The {0} is locked by the panicking code")]
+    LockedByPanic(&'static str),
+
+    #[error("IOMMU Error: The remapping unit didn't complete the {0}")]
+    IommuTimeout(&'static str),
+
+    #[error("IOMMU Error: No supported address width (SAGAW 0x{0:X})")]
+    UnsupportedAddressWidth(u64),
+
+    #[error("IOMMU Error: Interrupt remapping of the unit at 0x{0:X} is enabled by the firmware")]
+    InterruptRemappingEnabled(u64),
+
+    #[error("IOMMU Error: The unit at 0x{0:X} supports neither pass-through nor large pages")]
+    UnsupportedRemappingUnit(u64),
+}
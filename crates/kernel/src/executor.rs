@@ -0,0 +1,187 @@
+use crate::{
+    fpu::{
+        self,
+        FpuState,
+    },
+    timer,
+};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::Arc,
+    task::Wake,
+    vec::Vec,
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+};
+use libcore::registers::{
+    disable_interrupts,
+    enable_interrupts,
+    wait_for_interrupt,
+};
+use libsync::Spinlock;
+use log::info;
+
+/// The wakers of the sleeping tasks with their deadline in timer ticks. The list is only accessed
+/// by the executor and the tasks, never by interrupt handlers.
+static SLEEPING_TASKS: Spinlock<Vec<(u64, Waker)>> = Spinlock::new(Vec::new());
+
+type TaskFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+struct Task {
+    name: &'static str,
+    future: TaskFuture,
+    waker: Arc<TaskWaker>,
+    /// The FPU registers of the task, which are switched in before the task is polled
+    fpu: FpuState,
+}
+
+/// The task waker only sets a flag, so tasks can be woken from interrupt handlers without locking
+/// or allocating.
+struct TaskWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+}
+
+/// The executor runs the spawned tasks cooperatively on the current CPU. A task is only polled
+/// after its waker was woken, so tasks, which wait for an interrupt or a timer, don't use the CPU.
+/// If no task is woken, the CPU is halted until the next interrupt.
+///
+/// Every task has its own FPU state, so the control words and the registers of a task aren't
+/// changed by other tasks. The code of the executor between the polls runs with the state of the
+/// last polled task.
+pub struct Executor {
+    tasks: BTreeMap<usize, Task>,
+    next_id: usize,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// This function adds the future as a new task, which is polled on the next run
+    pub fn spawn(&mut self, name: &'static str, future: impl Future<Output = ()> + 'static) {
+        self.tasks.insert(
+            self.next_id,
+            Task {
+                name,
+                future: Box::pin(future),
+                waker: Arc::new(TaskWaker {
+                    woken: AtomicBool::new(true),
+                }),
+                fpu: FpuState::new(),
+            },
+        );
+        self.next_id += 1;
+    }
+
+    /// This function runs the tasks until all tasks are completed. Interrupts must be enabled, so
+    /// tasks, which wait for interrupts or timers, can be woken.
+    pub fn run(&mut self) {
+        let start_ticks = timer::ticks();
+        while !self.tasks.is_empty() {
+            wake_sleeping_tasks();
+            if !self.poll_woken_tasks() {
+                // Check the state again without interrupts, so a wake isn't missed before halting
+                disable_interrupts();
+                let woken = self
+                    .tasks
+                    .values()
+                    .any(|task| task.waker.woken.load(Ordering::Acquire));
+                if woken || has_expired_sleepers() {
+                    unsafe { enable_interrupts() };
+                } else {
+                    unsafe { wait_for_interrupt() };
+                }
+            }
+        }
+        info!("All boot tasks completed in {} ms\n", timer::ticks() - start_ticks);
+    }
+
+    /// This function polls every woken task once and removes the completed tasks. Returns true, if
+    /// at least one task was polled.
+    fn poll_woken_tasks(&mut self) -> bool {
+        let mut completed_tasks = Vec::new();
+        let mut polled = false;
+        for (id, task) in self.tasks.iter_mut() {
+            if !task.waker.woken.swap(false, Ordering::AcqRel) {
+                continue;
+            }
+
+            polled = true;
+            fpu::switch_fpu(&task.fpu);
+            let waker = Waker::from(task.waker.clone());
+            if let Poll::Ready(()) = task.future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                completed_tasks.push(*id);
+            }
+        }
+
+        for id in completed_tasks {
+            if let Some(task) = self.tasks.remove(&id) {
+                info!("Boot task '{}' completed\n", task.name);
+            }
+        }
+        polled
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// This function registers the waker, which is woken after the timer reached the deadline
+pub fn wake_at(deadline: u64, waker: &Waker) {
+    let mut sleeping_tasks = SLEEPING_TASKS.lock();
+    match sleeping_tasks
+        .iter_mut()
+        .find(|(_, sleeping_waker)| sleeping_waker.will_wake(waker))
+    {
+        Some(entry) => entry.0 = deadline,
+        None => sleeping_tasks.push((deadline, waker.clone())),
+    }
+}
+
+fn wake_sleeping_tasks() {
+    let now = timer::ticks();
+    SLEEPING_TASKS.lock().retain(|(deadline, waker)| {
+        if *deadline > now {
+            return true;
+        }
+        waker.wake_by_ref();
+        false
+    });
+}
+
+fn has_expired_sleepers() -> bool {
+    let now = timer::ticks();
+    SLEEPING_TASKS
+        .lock()
+        .iter()
+        .any(|(deadline, _)| *deadline <= now)
+}
@@ -0,0 +1,1024 @@
+//! The ext2 driver reads and writes the second extended file system on a [BlockDevice]. The file
+//! system is divided into block groups, which have a bitmap of the used blocks, a bitmap of the used
+//! inodes and a table of the inodes. Every file and directory is described by an inode, whose data
+//! blocks are referenced directly or over indirect blocks. Directories contain linked entries with
+//! the name and the inode of their files. The blocks are read over the page cache, so the lookups
+//! of paths don't read the same inode tables and directories from the device every time.
+//!
+//! Only the primary superblock and group descriptors are updated, the backup copies are left to
+//! `e2fsck`. File systems with incompatible features (like extents of ext4 or a journal, which must
+//! be recovered) are rejected, file systems with unknown read-only features are mounted read-only.
+
+use crate::{
+    block::{
+        BlockDevice,
+        RamDisk,
+    },
+    error::Error,
+    page_cache::CachedDevice,
+};
+use alloc::{
+    boxed::Box,
+    string::{
+        String,
+        ToString,
+    },
+    vec,
+    vec::Vec,
+};
+use libcore::boot_info::BootInfo;
+use libsync::Spinlock;
+use log::{
+    info,
+    warn,
+};
+
+/// The option `root=<path>`, which mounts the ext2 image at the path of the initrd as root file
+/// system
+pub const ROOT_OPTION: &str = "root";
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const EXT2_MAGIC: u16 = 0xEF53;
+const ROOT_INODE: u32 = 2;
+const MAX_NAME_LENGTH: usize = 255;
+
+/// The fields of the superblock
+const SB_INODES_COUNT: usize = 0;
+const SB_BLOCKS_COUNT: usize = 4;
+const SB_FREE_BLOCKS_COUNT: usize = 12;
+const SB_FREE_INODES_COUNT: usize = 16;
+const SB_FIRST_DATA_BLOCK: usize = 20;
+const SB_LOG_BLOCK_SIZE: usize = 24;
+const SB_BLOCKS_PER_GROUP: usize = 32;
+const SB_INODES_PER_GROUP: usize = 40;
+const SB_MAGIC: usize = 56;
+const SB_REV_LEVEL: usize = 76;
+const SB_FIRST_INODE: usize = 84;
+const SB_INODE_SIZE: usize = 88;
+const SB_FEATURE_INCOMPAT: usize = 96;
+const SB_FEATURE_RO_COMPAT: usize = 100;
+const SB_VOLUME_NAME: usize = 120;
+
+/// The values of the revision 0, which has no fields for the first inode and the inode size
+const GOOD_OLD_FIRST_INODE: u32 = 11;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// The directory entries contain the file type
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Sparse superblock backups, 64-bit file sizes and hashed directories (which keep the linear
+/// directory entries) don't change the layout, which is used by this driver
+const SUPPORTED_RO_COMPAT: u32 = 0x0001 | 0x0002 | 0x0004;
+
+/// The fields of a block group descriptor
+const GROUP_DESCRIPTOR_SIZE: usize = 32;
+const BG_BLOCK_BITMAP: usize = 0;
+const BG_INODE_BITMAP: usize = 4;
+const BG_INODE_TABLE: usize = 8;
+const BG_FREE_BLOCKS_COUNT: usize = 12;
+const BG_FREE_INODES_COUNT: usize = 14;
+const BG_USED_DIRS_COUNT: usize = 16;
+
+/// The fields of an inode
+const INODE_MODE: usize = 0;
+const INODE_SIZE: usize = 4;
+const INODE_ATIME: usize = 8;
+const INODE_CTIME: usize = 12;
+const INODE_MTIME: usize = 16;
+const INODE_LINKS_COUNT: usize = 26;
+const INODE_SECTORS: usize = 28;
+const INODE_FLAGS: usize = 32;
+const INODE_BLOCK: usize = 40;
+const INODE_SIZE_HIGH: usize = 108;
+
+/// The inode references 12 data blocks directly, followed by the single, double and triple
+/// indirect block
+const DIRECT_BLOCKS: u64 = 12;
+const INDIRECT_SLOTS: usize = 3;
+
+const MODE_TYPE_MASK: u16 = 0xF000;
+const MODE_REGULAR: u16 = 0x8000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_SYMLINK: u16 = 0xA000;
+const DEFAULT_FILE_PERMISSIONS: u16 = 0o644;
+const DEFAULT_DIRECTORY_PERMISSIONS: u16 = 0o755;
+
+/// The directory has a hash index, whose blocks are valid linear directory blocks
+const INODE_INDEX_FLAG: u32 = 0x1000;
+
+/// The size of the header of a directory entry before the name
+const DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+/// The size of the sectors, which are counted by the inode
+const SECTOR_SIZE: usize = 512;
+
+static ROOT_FILE_SYSTEM: Spinlock<Option<Ext2FileSystem>> = Spinlock::new(None);
+
+/// The type of a file, like it's stored in the mode of the inode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Regular,
+    Directory,
+    Symlink,
+    Other,
+}
+
+impl FileKind {
+    fn from_mode(mode: u16) -> Self {
+        match mode & MODE_TYPE_MASK {
+            MODE_REGULAR => Self::Regular,
+            MODE_DIRECTORY => Self::Directory,
+            MODE_SYMLINK => Self::Symlink,
+            _ => Self::Other,
+        }
+    }
+
+    /// This function returns the file type of the directory entries
+    fn entry_type(&self) -> u8 {
+        match self {
+            Self::Regular => 1,
+            Self::Directory => 2,
+            Self::Symlink => 7,
+            Self::Other => 0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Regular => "file",
+            Self::Directory => "directory",
+            Self::Symlink => "symlink",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// An entry of a directory
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: u32,
+}
+
+/// The metadata of a file, which is read from its inode
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub inode: u32,
+    pub kind: FileKind,
+    pub size: u64,
+    /// The permissions of the file
+    pub permissions: u16,
+    pub links: u16,
+}
+
+/// An inode with its raw bytes, so the fields, which aren't used by this driver, are preserved when
+/// the inode is written back
+#[derive(Clone)]
+struct Inode {
+    data: Vec<u8>,
+}
+
+impl Inode {
+    #[inline]
+    fn mode(&self) -> u16 {
+        read_u16(&self.data, INODE_MODE)
+    }
+
+    #[inline]
+    fn kind(&self) -> FileKind {
+        FileKind::from_mode(self.mode())
+    }
+
+    /// This function returns the size of the file. The upper 32 bits are only used by regular files.
+    fn size(&self) -> u64 {
+        let high = match self.kind() {
+            FileKind::Regular => read_u32(&self.data, INODE_SIZE_HIGH) as u64,
+            _ => 0,
+        };
+        (high << 32) | read_u32(&self.data, INODE_SIZE) as u64
+    }
+
+    fn set_size(&mut self, size: u64) {
+        write_u32(&mut self.data, INODE_SIZE, size as u32);
+        if self.kind() == FileKind::Regular {
+            write_u32(&mut self.data, INODE_SIZE_HIGH, (size >> 32) as u32);
+        }
+    }
+
+    #[inline]
+    fn block(&self, slot: usize) -> u32 {
+        read_u32(&self.data, INODE_BLOCK + slot * 4)
+    }
+
+    #[inline]
+    fn set_block(&mut self, slot: usize, block: u32) {
+        write_u32(&mut self.data, INODE_BLOCK + slot * 4, block);
+    }
+
+    fn add_sectors(&mut self, sectors: u32) {
+        let sectors = read_u32(&self.data, INODE_SECTORS).wrapping_add(sectors);
+        write_u32(&mut self.data, INODE_SECTORS, sectors);
+    }
+
+    #[inline]
+    fn links(&self) -> u16 {
+        read_u16(&self.data, INODE_LINKS_COUNT)
+    }
+
+    #[inline]
+    fn set_links(&mut self, links: u16) {
+        write_u16(&mut self.data, INODE_LINKS_COUNT, links);
+    }
+
+    /// This function sets the modification time. The access time and the change time follow it.
+    fn touch(&mut self, time: u32) {
+        for field in [INODE_ATIME, INODE_CTIME, INODE_MTIME] {
+            write_u32(&mut self.data, field, time);
+        }
+    }
+}
+
+/// A mounted ext2 file system. The superblock and the group descriptors are kept in memory and
+/// written back after every change.
+pub struct Ext2FileSystem {
+    device: Box<dyn BlockDevice>,
+    superblock: Vec<u8>,
+    /// The blocks of the group descriptor table
+    descriptors: Vec<u8>,
+    block_size: usize,
+    group_count: usize,
+    inode_size: usize,
+    first_inode: u32,
+    has_file_type: bool,
+    read_only: bool,
+}
+
+impl Ext2FileSystem {
+    /// This function reads the superblock and the group descriptors of the file system on the
+    /// device. If the device doesn't contain an ext2 file system or the file system uses
+    /// incompatible features, an error is returned.
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<Self, Error> {
+        let device_block_size = device.block_size();
+        if device_block_size == 0 || SUPERBLOCK_SIZE % device_block_size != 0 {
+            return Err(Error::UnsupportedBlockSize(device_block_size));
+        }
+
+        let mut superblock = vec![0; SUPERBLOCK_SIZE];
+        device.read_blocks(SUPERBLOCK_OFFSET / device_block_size as u64, &mut superblock)?;
+        if read_u16(&superblock, SB_MAGIC) != EXT2_MAGIC {
+            return Err(Error::NoExt2FileSystem);
+        }
+
+        let incompatible_features = read_u32(&superblock, SB_FEATURE_INCOMPAT) & !INCOMPAT_FILETYPE;
+        if incompatible_features != 0 {
+            return Err(Error::UnsupportedExt2Features(incompatible_features));
+        }
+
+        let log_block_size = read_u32(&superblock, SB_LOG_BLOCK_SIZE);
+        if log_block_size > 6 {
+            return Err(Error::UnsupportedBlockSize(1024 << log_block_size.min(16)));
+        }
+        let block_size = 1024 << log_block_size;
+        let (first_inode, inode_size) = match read_u32(&superblock, SB_REV_LEVEL) {
+            0 => (GOOD_OLD_FIRST_INODE, GOOD_OLD_INODE_SIZE),
+            _ => {
+                (read_u32(&superblock, SB_FIRST_INODE), read_u16(&superblock, SB_INODE_SIZE) as usize)
+            }
+        };
+        if inode_size < GOOD_OLD_INODE_SIZE || inode_size > block_size {
+            return Err(Error::UnsupportedInodeSize(inode_size));
+        }
+
+        let data_blocks = read_u32(&superblock, SB_BLOCKS_COUNT)
+            .saturating_sub(read_u32(&superblock, SB_FIRST_DATA_BLOCK));
+        let blocks_per_group = read_u32(&superblock, SB_BLOCKS_PER_GROUP).max(1);
+        let group_count = data_blocks.div_ceil(blocks_per_group) as usize;
+        let mut file_system = Self {
+            device,
+            superblock,
+            descriptors: Vec::new(),
+            block_size,
+            group_count,
+            inode_size,
+            first_inode,
+            has_file_type: false,
+            read_only: false,
+        };
+        file_system.has_file_type = file_system.incompatible_features() & INCOMPAT_FILETYPE != 0;
+        file_system.read_only = file_system.read_only_features() & !SUPPORTED_RO_COMPAT != 0;
+
+        // The group descriptor table starts in the block after the superblock
+        let table_block = file_system.first_data_block() + 1;
+        let table_blocks = (group_count * GROUP_DESCRIPTOR_SIZE).div_ceil(block_size);
+        for index in 0..table_blocks {
+            let block = file_system.read_block(table_block + index as u32)?;
+            file_system.descriptors.extend_from_slice(&block);
+        }
+        Ok(file_system)
+    }
+
+    /// This function returns the label of the volume
+    pub fn label(&self) -> &str {
+        let name = &self.superblock[SB_VOLUME_NAME..SB_VOLUME_NAME + 16];
+        let length = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(name.len());
+        core::str::from_utf8(&name[..length]).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn device_name(&self) -> &str {
+        self.device.name()
+    }
+
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    #[inline]
+    pub fn block_count(&self) -> u32 {
+        read_u32(&self.superblock, SB_BLOCKS_COUNT)
+    }
+
+    #[inline]
+    pub fn free_blocks(&self) -> u32 {
+        read_u32(&self.superblock, SB_FREE_BLOCKS_COUNT)
+    }
+
+    #[inline]
+    pub fn free_inodes(&self) -> u32 {
+        read_u32(&self.superblock, SB_FREE_INODES_COUNT)
+    }
+
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// This function returns the metadata of the file or directory at the specified path
+    pub fn metadata(&mut self, path: &str) -> Result<Metadata, Error> {
+        let number = self.lookup(path)?;
+        self.inode_metadata(number)
+    }
+
+    /// This function returns the metadata of the inode with the specified number
+    pub fn inode_metadata(&mut self, number: u32) -> Result<Metadata, Error> {
+        let inode = self.read_inode(number)?;
+        Ok(Metadata {
+            inode: number,
+            kind: inode.kind(),
+            size: inode.size(),
+            permissions: inode.mode() & !MODE_TYPE_MASK,
+            links: inode.links(),
+        })
+    }
+
+    /// This function reads the content of the regular file at the specified path
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let number = self.lookup(path)?;
+        let inode = self.read_inode(number)?;
+        match inode.kind() {
+            FileKind::Directory => Err(Error::IsADirectory(path.to_string())),
+            _ => self.read_data(inode),
+        }
+    }
+
+    /// This function returns the entries of the directory at the specified path, including the `.`
+    /// and `..` entries
+    pub fn read_directory(&mut self, path: &str) -> Result<Vec<DirEntry>, Error> {
+        let number = self.lookup(path)?;
+        if self.read_inode(number)?.kind() != FileKind::Directory {
+            return Err(Error::NotADirectory(path.to_string()));
+        }
+        self.directory_entries(number)
+    }
+
+    /// This function replaces the content of the regular file at the specified path with the data.
+    /// If the file doesn't exist, it's created in the parent directory.
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.write_file_parts(path, &[data])
+    }
+
+    /// This function replaces the content of the regular file with the concatenated parts, so large
+    /// files (like a core dump) can be written from memory without copying them into one buffer
+    pub fn write_file_parts(&mut self, path: &str, parts: &[&[u8]]) -> Result<(), Error> {
+        let (parent, name) = self.prepare_create(path)?;
+        let time = current_time();
+        let number = match self.find_entry(parent, name)? {
+            Some(number) => {
+                let mut inode = self.read_inode(number)?;
+                if inode.kind() == FileKind::Directory {
+                    return Err(Error::IsADirectory(path.to_string()));
+                }
+                self.free_data_blocks(&mut inode)?;
+                self.write_inode(number, &inode)?;
+                number
+            }
+            None => {
+                let number = self.allocate_inode(false)?;
+                let mut inode = self.new_inode(MODE_REGULAR | DEFAULT_FILE_PERMISSIONS, time);
+                inode.set_links(1);
+                self.write_inode(number, &inode)?;
+                self.add_entry(parent, name, number, FileKind::Regular)?;
+                number
+            }
+        };
+
+        // The parts are copied into the buffer of a block, which is written when it's full
+        let mut inode = self.read_inode(number)?;
+        let mut buffer = vec![0; self.block_size];
+        let mut index = 0;
+        let mut filled = 0;
+        let mut size = 0;
+        for mut part in parts.iter().copied() {
+            while !part.is_empty() {
+                let length = (self.block_size - filled).min(part.len());
+                buffer[filled..filled + length].copy_from_slice(&part[..length]);
+                part = &part[length..];
+                filled += length;
+                size += length as u64;
+                if filled == self.block_size {
+                    let block = self.map_block(&mut inode, index, true)?;
+                    self.write_block(block, &buffer)?;
+                    index += 1;
+                    filled = 0;
+                }
+            }
+        }
+        if filled != 0 {
+            buffer[filled..].fill(0);
+            let block = self.map_block(&mut inode, index, true)?;
+            self.write_block(block, &buffer)?;
+        }
+        inode.set_size(size);
+        inode.touch(time);
+        self.write_inode(number, &inode)?;
+        self.sync_metadata()
+    }
+
+    /// This function creates the directory at the specified path with the `.` and `..` entries
+    pub fn create_directory(&mut self, path: &str) -> Result<(), Error> {
+        let (parent, name) = self.prepare_create(path)?;
+        if self.find_entry(parent, name)?.is_some() {
+            return Err(Error::AlreadyExists(path.to_string()));
+        }
+
+        let time = current_time();
+        let number = self.allocate_inode(true)?;
+        let mut inode = self.new_inode(MODE_DIRECTORY | DEFAULT_DIRECTORY_PERMISSIONS, time);
+        inode.set_links(2);
+        let block = self.map_block(&mut inode, 0, true)?;
+        let mut buffer = vec![0; self.block_size];
+        let dot_size = entry_size(1);
+        self.encode_entry(&mut buffer[..dot_size], number, ".", FileKind::Directory);
+        self.encode_entry(&mut buffer[dot_size..], parent, "..", FileKind::Directory);
+        self.write_block(block, &buffer)?;
+        inode.set_size(self.block_size as u64);
+        self.write_inode(number, &inode)?;
+        self.add_entry(parent, name, number, FileKind::Directory)?;
+
+        // The `..` entry links the parent directory
+        let mut parent_inode = self.read_inode(parent)?;
+        parent_inode.set_links(parent_inode.links() + 1);
+        self.write_inode(parent, &parent_inode)?;
+        self.sync_metadata()
+    }
+
+    /// This function returns the number of the inode at the specified path. The path is resolved
+    /// from the root directory, symbolic links aren't followed.
+    fn lookup(&mut self, path: &str) -> Result<u32, Error> {
+        let mut number = ROOT_INODE;
+        for name in path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+        {
+            if self.read_inode(number)?.kind() != FileKind::Directory {
+                return Err(Error::NotADirectory(path.to_string()));
+            }
+            number = self
+                .find_entry(number, name)?
+                .ok_or_else(|| Error::PathNotFound(path.to_string()))?;
+        }
+        Ok(number)
+    }
+
+    /// This function checks, that a file can be created at the specified path, and returns the
+    /// inode of the parent directory with the name of the file
+    fn prepare_create<'a>(&mut self, path: &'a str) -> Result<(u32, &'a str), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnlyFileSystem);
+        }
+
+        let (parent_path, name) = path
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." || name.len() > MAX_NAME_LENGTH {
+            return Err(Error::InvalidFileName(name.to_string()));
+        }
+        let parent = self.lookup(parent_path)?;
+        if self.read_inode(parent)?.kind() != FileKind::Directory {
+            return Err(Error::NotADirectory(parent_path.to_string()));
+        }
+        Ok((parent, name))
+    }
+
+    fn find_entry(&mut self, directory: u32, name: &str) -> Result<Option<u32>, Error> {
+        Ok(self
+            .directory_entries(directory)?
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.inode))
+    }
+
+    /// This function parses the entries of the directory. Deleted entries (with inode 0) are
+    /// skipped.
+    fn directory_entries(&mut self, directory: u32) -> Result<Vec<DirEntry>, Error> {
+        let inode = self.read_inode(directory)?;
+        let data = self.read_data(inode)?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + DIR_ENTRY_HEADER_SIZE <= data.len() {
+            let number = read_u32(&data, offset);
+            let record_length = read_u16(&data, offset + 4) as usize;
+            if record_length < DIR_ENTRY_HEADER_SIZE {
+                return Err(Error::CorruptDirectory(directory));
+            }
+
+            let name_length = self.name_length(&data[offset..]);
+            let name = data
+                .get(offset + DIR_ENTRY_HEADER_SIZE..offset + DIR_ENTRY_HEADER_SIZE + name_length)
+                .ok_or(Error::CorruptDirectory(directory))?;
+            if number != 0 {
+                entries.push(DirEntry {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    inode: number,
+                });
+            }
+            offset += record_length;
+        }
+        Ok(entries)
+    }
+
+    /// This function adds the entry into the first gap of the directory, which is large enough. If
+    /// the directory has no gap, a block is appended. The hash index of the directory isn't updated,
+    /// so the index is dropped and the directory is used linearly.
+    fn add_entry(
+        &mut self, directory: u32, name: &str, number: u32, kind: FileKind,
+    ) -> Result<(), Error> {
+        let needed = entry_size(name.len());
+        let mut inode = self.read_inode(directory)?;
+        let flags = read_u32(&inode.data, INODE_FLAGS);
+        if flags & INODE_INDEX_FLAG != 0 {
+            write_u32(&mut inode.data, INODE_FLAGS, flags & !INODE_INDEX_FLAG);
+            self.write_inode(directory, &inode)?;
+        }
+        let blocks = inode.size().div_ceil(self.block_size as u64);
+        for index in 0..blocks {
+            let block = self.map_block(&mut inode, index, false)?;
+            if block == 0 {
+                continue;
+            }
+
+            let mut data = self.read_block(block)?;
+            let mut offset = 0;
+            while offset + DIR_ENTRY_HEADER_SIZE <= self.block_size {
+                let record_length = read_u16(&data, offset + 4) as usize;
+                if record_length < DIR_ENTRY_HEADER_SIZE || offset + record_length > self.block_size {
+                    return Err(Error::CorruptDirectory(directory));
+                }
+
+                // A deleted entry is reused, otherwise the entry is shortened to its used size
+                let used = match read_u32(&data, offset) {
+                    0 => 0,
+                    _ => entry_size(self.name_length(&data[offset..])),
+                };
+                if record_length.saturating_sub(used) >= needed {
+                    if used != 0 {
+                        write_u16(&mut data, offset + 4, used as u16);
+                    }
+                    let entry = &mut data[offset + used..offset + record_length];
+                    self.encode_entry(entry, number, name, kind);
+                    return self.write_block(block, &data);
+                }
+                offset += record_length;
+            }
+        }
+
+        let block = self.map_block(&mut inode, blocks, true)?;
+        let mut data = vec![0; self.block_size];
+        self.encode_entry(&mut data, number, name, kind);
+        self.write_block(block, &data)?;
+        inode.set_size((blocks + 1) * self.block_size as u64);
+        self.write_inode(directory, &inode)
+    }
+
+    /// This function writes the entry into the buffer, whose length is the record length
+    fn encode_entry(&self, buffer: &mut [u8], number: u32, name: &str, kind: FileKind) {
+        write_u32(buffer, 0, number);
+        write_u16(buffer, 4, buffer.len() as u16);
+        buffer[6] = name.len() as u8;
+        buffer[7] = match self.has_file_type {
+            true => kind.entry_type(),
+            false => 0,
+        };
+        buffer[DIR_ENTRY_HEADER_SIZE..DIR_ENTRY_HEADER_SIZE + name.len()]
+            .copy_from_slice(name.as_bytes());
+    }
+
+    /// This function returns the length of the name of the entry. Without file types, the length
+    /// has 16 bits.
+    fn name_length(&self, entry: &[u8]) -> usize {
+        match self.has_file_type {
+            true => entry[6] as usize,
+            false => read_u16(entry, 6) as usize,
+        }
+    }
+
+    /// This function reads the data of the inode. Sparse blocks are read as zeros.
+    fn read_data(&mut self, mut inode: Inode) -> Result<Vec<u8>, Error> {
+        let size = inode.size() as usize;
+
+        // Short symbolic links store the target in the block references instead of a data block
+        if inode.kind() == FileKind::Symlink && read_u32(&inode.data, INODE_SECTORS) == 0 {
+            let target = inode.data.get(INODE_BLOCK..INODE_BLOCK + size);
+            return target
+                .map(|target| target.to_vec())
+                .ok_or(Error::InvalidInode(0));
+        }
+
+        let mut data = Vec::with_capacity(size);
+        for index in 0..size.div_ceil(self.block_size) {
+            let length = (size - data.len()).min(self.block_size);
+            match self.map_block(&mut inode, index as u64, false)? {
+                0 => data.resize(data.len() + length, 0),
+                block => data.extend_from_slice(&self.read_block(block)?[..length]),
+            }
+        }
+        Ok(data)
+    }
+
+    /// This function returns the block, which contains the block with the specified index of the
+    /// file. If the block isn't allocated, zero is returned or the block (with the missing indirect
+    /// blocks) is allocated. The caller has to write the inode back after an allocation.
+    fn map_block(&mut self, inode: &mut Inode, index: u64, allocate: bool) -> Result<u32, Error> {
+        let pointers = (self.block_size / 4) as u64;
+        let (slot, depth, index) = match index.checked_sub(DIRECT_BLOCKS) {
+            None => (index as usize, 0, 0),
+            Some(mut index) => {
+                let mut depth = 1;
+                while index >= pointers.pow(depth) {
+                    index -= pointers.pow(depth);
+                    depth += 1;
+                    if depth as usize > INDIRECT_SLOTS {
+                        return Err(Error::FileTooLarge);
+                    }
+                }
+                (DIRECT_BLOCKS as usize + depth as usize - 1, depth, index)
+            }
+        };
+
+        let sectors = (self.block_size / SECTOR_SIZE) as u32;
+        let mut block = inode.block(slot);
+        if block == 0 {
+            if !allocate {
+                return Ok(0);
+            }
+            block = self.allocate_block(depth > 0)?;
+            inode.set_block(slot, block);
+            inode.add_sectors(sectors);
+        }
+
+        for level in (0..depth).rev() {
+            let entry = ((index / pointers.pow(level)) % pointers) as usize * 4;
+            let mut data = self.read_block(block)?;
+            let mut next = read_u32(&data, entry);
+            if next == 0 {
+                if !allocate {
+                    return Ok(0);
+                }
+                next = self.allocate_block(level > 0)?;
+                write_u32(&mut data, entry, next);
+                self.write_block(block, &data)?;
+                inode.add_sectors(sectors);
+            }
+            block = next;
+        }
+        Ok(block)
+    }
+
+    /// This function frees the data and indirect blocks of the inode and sets its size to zero
+    fn free_data_blocks(&mut self, inode: &mut Inode) -> Result<(), Error> {
+        for slot in 0..DIRECT_BLOCKS as usize + INDIRECT_SLOTS {
+            let depth = slot.saturating_sub(DIRECT_BLOCKS as usize - 1) as u32;
+            self.free_block_tree(inode.block(slot), depth)?;
+            inode.set_block(slot, 0);
+        }
+        write_u32(&mut inode.data, INODE_SECTORS, 0);
+        inode.set_size(0);
+        Ok(())
+    }
+
+    fn free_block_tree(&mut self, block: u32, depth: u32) -> Result<(), Error> {
+        if block == 0 {
+            return Ok(());
+        }
+        if depth > 0 {
+            let data = self.read_block(block)?;
+            for entry in data.chunks_exact(4) {
+                self.free_block_tree(u32::from_le_bytes(entry.try_into().unwrap()), depth - 1)?;
+            }
+        }
+        self.free_block(block)
+    }
+
+    /// This function allocates a free block in the first group with free blocks. Indirect blocks are
+    /// zeroed, so they don't reference stale blocks.
+    fn allocate_block(&mut self, zeroed: bool) -> Result<u32, Error> {
+        let blocks_per_group = read_u32(&self.superblock, SB_BLOCKS_PER_GROUP);
+        for group in 0..self.group_count {
+            if self.group_field(group, BG_FREE_BLOCKS_COUNT) == 0 {
+                continue;
+            }
+
+            let first_block = self.first_data_block() + group as u32 * blocks_per_group;
+            let count = blocks_per_group.min(self.block_count() - first_block);
+            let bitmap = self.group_u32(group, BG_BLOCK_BITMAP);
+            if let Some(bit) = self.allocate_bit(bitmap, count)? {
+                self.adjust_group_field(group, BG_FREE_BLOCKS_COUNT, -1);
+                self.adjust_superblock_field(SB_FREE_BLOCKS_COUNT, -1);
+                let block = first_block + bit;
+                if zeroed {
+                    self.write_block(block, &vec![0; self.block_size])?;
+                }
+                return Ok(block);
+            }
+        }
+        Err(Error::NoSpaceLeft)
+    }
+
+    fn free_block(&mut self, block: u32) -> Result<(), Error> {
+        let blocks_per_group = read_u32(&self.superblock, SB_BLOCKS_PER_GROUP);
+        let index = block - self.first_data_block();
+        let group = (index / blocks_per_group) as usize;
+        self.free_bit(self.group_u32(group, BG_BLOCK_BITMAP), index % blocks_per_group)?;
+        self.adjust_group_field(group, BG_FREE_BLOCKS_COUNT, 1);
+        self.adjust_superblock_field(SB_FREE_BLOCKS_COUNT, 1);
+        Ok(())
+    }
+
+    /// This function allocates a free inode after the reserved inodes
+    fn allocate_inode(&mut self, directory: bool) -> Result<u32, Error> {
+        let inodes_per_group = read_u32(&self.superblock, SB_INODES_PER_GROUP);
+        for group in 0..self.group_count {
+            if self.group_field(group, BG_FREE_INODES_COUNT) == 0 {
+                continue;
+            }
+
+            // The reserved inodes are marked as used by mkfs, but the bitmap isn't trusted
+            let bitmap = self.group_u32(group, BG_INODE_BITMAP);
+            let mut data = self.read_block(bitmap)?;
+            let first_number = group as u32 * inodes_per_group + 1;
+            let bit = (0..inodes_per_group).find(|bit| {
+                first_number + bit >= self.first_inode
+                    && data[*bit as usize / 8] & (1 << (bit % 8)) == 0
+            });
+            if let Some(bit) = bit {
+                data[bit as usize / 8] |= 1 << (bit % 8);
+                self.write_block(bitmap, &data)?;
+                self.adjust_group_field(group, BG_FREE_INODES_COUNT, -1);
+                self.adjust_superblock_field(SB_FREE_INODES_COUNT, -1);
+                if directory {
+                    self.adjust_group_field(group, BG_USED_DIRS_COUNT, 1);
+                }
+                return Ok(first_number + bit);
+            }
+        }
+        Err(Error::NoSpaceLeft)
+    }
+
+    /// This function sets the first clear bit of the bitmap and returns its index
+    fn allocate_bit(&mut self, bitmap: u32, count: u32) -> Result<Option<u32>, Error> {
+        let mut data = self.read_block(bitmap)?;
+        let bit = (0..count).find(|bit| data[*bit as usize / 8] & (1 << (bit % 8)) == 0);
+        if let Some(bit) = bit {
+            data[bit as usize / 8] |= 1 << (bit % 8);
+            self.write_block(bitmap, &data)?;
+        }
+        Ok(bit)
+    }
+
+    fn free_bit(&mut self, bitmap: u32, bit: u32) -> Result<(), Error> {
+        let mut data = self.read_block(bitmap)?;
+        data[bit as usize / 8] &= !(1 << (bit % 8));
+        self.write_block(bitmap, &data)
+    }
+
+    /// This function creates an empty inode with the mode and the time
+    fn new_inode(&self, mode: u16, time: u32) -> Inode {
+        let mut inode = Inode {
+            data: vec![0; self.inode_size],
+        };
+        write_u16(&mut inode.data, INODE_MODE, mode);
+        inode.touch(time);
+        inode
+    }
+
+    fn read_inode(&mut self, number: u32) -> Result<Inode, Error> {
+        let (block, offset) = self.inode_location(number)?;
+        let data = self.read_block(block)?;
+        Ok(Inode {
+            data: data[offset..offset + self.inode_size].to_vec(),
+        })
+    }
+
+    fn write_inode(&mut self, number: u32, inode: &Inode) -> Result<(), Error> {
+        let (block, offset) = self.inode_location(number)?;
+        let mut data = self.read_block(block)?;
+        data[offset..offset + self.inode_size].copy_from_slice(&inode.data);
+        self.write_block(block, &data)
+    }
+
+    /// This function returns the block of the inode table and the offset in the block, which
+    /// contain the inode
+    fn inode_location(&self, number: u32) -> Result<(u32, usize), Error> {
+        if number == 0 || number > read_u32(&self.superblock, SB_INODES_COUNT) {
+            return Err(Error::InvalidInode(number));
+        }
+
+        let inodes_per_group = read_u32(&self.superblock, SB_INODES_PER_GROUP);
+        let group = ((number - 1) / inodes_per_group) as usize;
+        let offset = ((number - 1) % inodes_per_group) as usize * self.inode_size;
+        let block = self.group_u32(group, BG_INODE_TABLE) + (offset / self.block_size) as u32;
+        Ok((block, offset % self.block_size))
+    }
+
+    /// This function writes the superblock and the group descriptors back to the device
+    fn sync_metadata(&mut self) -> Result<(), Error> {
+        let device_block_size = self.device.block_size() as u64;
+        self.device
+            .write_blocks(SUPERBLOCK_OFFSET / device_block_size, &self.superblock)?;
+        let table_block = self.first_data_block() + 1;
+        let descriptors = core::mem::take(&mut self.descriptors);
+        let result = descriptors
+            .chunks(self.block_size)
+            .enumerate()
+            .try_for_each(|(index, data)| self.write_block(table_block + index as u32, data));
+        self.descriptors = descriptors;
+        result
+    }
+
+    fn read_block(&mut self, block: u32) -> Result<Vec<u8>, Error> {
+        let mut data = vec![0; self.block_size];
+        let device_blocks = (self.block_size / self.device.block_size()) as u64;
+        self.device
+            .read_blocks(block as u64 * device_blocks, &mut data)?;
+        Ok(data)
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8]) -> Result<(), Error> {
+        let device_blocks = (self.block_size / self.device.block_size()) as u64;
+        self.device.write_blocks(block as u64 * device_blocks, data)
+    }
+
+    #[inline]
+    fn first_data_block(&self) -> u32 {
+        read_u32(&self.superblock, SB_FIRST_DATA_BLOCK)
+    }
+
+    #[inline]
+    fn incompatible_features(&self) -> u32 {
+        read_u32(&self.superblock, SB_FEATURE_INCOMPAT)
+    }
+
+    #[inline]
+    fn read_only_features(&self) -> u32 {
+        read_u32(&self.superblock, SB_FEATURE_RO_COMPAT)
+    }
+
+    #[inline]
+    fn group_u32(&self, group: usize, field: usize) -> u32 {
+        read_u32(&self.descriptors, group * GROUP_DESCRIPTOR_SIZE + field)
+    }
+
+    #[inline]
+    fn group_field(&self, group: usize, field: usize) -> u16 {
+        read_u16(&self.descriptors, group * GROUP_DESCRIPTOR_SIZE + field)
+    }
+
+    fn adjust_group_field(&mut self, group: usize, field: usize, delta: i16) {
+        let value = self.group_field(group, field).wrapping_add_signed(delta);
+        write_u16(&mut self.descriptors, group * GROUP_DESCRIPTOR_SIZE + field, value);
+    }
+
+    fn adjust_superblock_field(&mut self, field: usize, delta: i32) {
+        let value = read_u32(&self.superblock, field).wrapping_add_signed(delta);
+        write_u32(&mut self.superblock, field, value);
+    }
+}
+
+/// This function mounts the root file system, if it's requested on the command line. Errors are
+/// logged, because the kernel runs without root file system.
+pub fn init_root_file_system(boot_info: &BootInfo) {
+    let Some(path) = boot_info.command_line().get(ROOT_OPTION) else {
+        return;
+    };
+
+    if let Err(error) = mount_root(boot_info, path) {
+        warn!("Unable to mount root file system '{}' => {}\n", path, error);
+    }
+}
+
+fn mount_root(boot_info: &BootInfo, path: &str) -> Result<(), Error> {
+    let file = boot_info
+        .initrd()
+        .map(|initrd| initrd.find(path))
+        .transpose()?
+        .flatten()
+        .ok_or_else(|| Error::RootImageNotFound(path.to_string()))?;
+
+    // The initrd is reserved for the kernel and the image is only accessed over the RAM disk after
+    // mounting, so the RAM disk takes the memory of the image over
+    let data =
+        unsafe { core::slice::from_raw_parts_mut(file.data.as_ptr() as *mut u8, file.data.len()) };
+    let ram_disk = Box::new(RamDisk::new(path.to_string(), data));
+    let file_system = Ext2FileSystem::mount(Box::new(CachedDevice::new(ram_disk)))?;
+    info!(
+        "Mounted ext2 file system '{}' from {} as root ({} of {} blocks free, {} bytes per \
+         block{})\n",
+        file_system.label(),
+        path,
+        file_system.free_blocks(),
+        file_system.block_count(),
+        file_system.block_size(),
+        if file_system.is_read_only() {
+            ", read-only"
+        } else {
+            ""
+        }
+    );
+    *ROOT_FILE_SYSTEM.lock() = Some(file_system);
+    Ok(())
+}
+
+/// This function returns, whether a root file system is mounted
+pub fn has_root_file_system() -> bool {
+    ROOT_FILE_SYSTEM.lock().is_some()
+}
+
+/// This function calls the function with the root file system. The file system is locked during the
+/// call.
+pub fn with_root_file_system<T>(
+    function: impl FnOnce(&mut Ext2FileSystem) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut root_file_system = ROOT_FILE_SYSTEM.lock();
+    function(root_file_system.as_mut().ok_or(Error::NoRootFileSystem)?)
+}
+
+/// This function calls the function with the root file system like [with_root_file_system], but
+/// returns an error instead of waiting, if the file system is locked. It's used by the panic handler.
+pub fn try_with_root_file_system<T>(
+    function: impl FnOnce(&mut Ext2FileSystem) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut root_file_system = ROOT_FILE_SYSTEM
+        .try_lock()
+        .ok_or(Error::LockedByPanic("root file system"))?;
+    function(root_file_system.as_mut().ok_or(Error::NoRootFileSystem)?)
+}
+
+/// This function returns the current time as UNIX timestamp for the inodes. Without real-time
+/// clock, the timestamps are zero.
+fn current_time() -> u32 {
+    libruntime::runtime_services()
+        .and_then(|runtime_services| runtime_services.time())
+        .map_or(0, |(time, _)| time.unix_timestamp() as u32)
+}
+
+/// This function returns the size of a directory entry with the name length, which is aligned to 4
+/// bytes
+#[inline]
+fn entry_size(name_length: usize) -> usize {
+    (DIR_ENTRY_HEADER_SIZE + name_length + 3) & !3
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[inline]
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
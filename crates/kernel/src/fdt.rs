@@ -0,0 +1,55 @@
+use crate::error::Error;
+use libcore::{
+    address::PhysAddr,
+    fdt::DeviceTree,
+};
+use log::info;
+
+crate::driver!("fdt", [], |boot_info| init_device_tree(boot_info.device_tree_address));
+
+/// The device tree, which was passed by the bootloader
+static mut DEVICE_TREE: Option<DeviceTree<'static>> = None;
+
+/// This function parses the device tree, which was found by the bootloader, and logs the memory,
+/// the UART, the interrupt controller and the virtio-mmio devices. Most x86 systems have no device
+/// tree, so a missing device tree isn't an error.
+pub fn init_device_tree(device_tree_address: PhysAddr) -> Result<(), Error> {
+    if device_tree_address.is_null() {
+        info!("No device tree was passed by the bootloader, using ACPI only\n");
+        return Ok(());
+    }
+
+    let device_tree = unsafe { DeviceTree::from_address(device_tree_address.to_virt())? };
+    info!(
+        "Found device tree at 0x{:X} ({} MB memory)\n",
+        device_tree_address,
+        device_tree.memory_size() / (1024 * 1024)
+    );
+    for (address, size) in device_tree.memory_regions() {
+        info!(" => Memory at 0x{:X} ({} kB)\n", address, size / 1024);
+    }
+
+    if let Some(uart) = device_tree.uart() {
+        info!("UART: {} at 0x{:X}\n", uart.compatible, uart.address);
+    }
+    if let Some(gic) = device_tree.gic() {
+        info!(
+            "GICv{}: Distributor at 0x{:X}, CPU interface at 0x{:X}, Redistributor at 0x{:X}\n",
+            gic.version, gic.distributor, gic.cpu_interface, gic.redistributor
+        );
+    }
+    for device in device_tree.virtio_mmio_devices() {
+        info!(
+            "virtio-mmio: Device at 0x{:X} ({} bytes, Interrupt: {:?})\n",
+            device.address, device.size, device.interrupt
+        );
+    }
+
+    unsafe { DEVICE_TREE = Some(device_tree) };
+    Ok(())
+}
+
+/// This function returns the device tree, if one was passed by the bootloader
+pub fn device_tree() -> Option<DeviceTree<'static>> {
+    unsafe { DEVICE_TREE }
+}
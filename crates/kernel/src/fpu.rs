@@ -0,0 +1,269 @@
+use crate::interrupts::set_exception_handler;
+use alloc::alloc::{
+    alloc_zeroed,
+    dealloc,
+};
+use core::{
+    alloc::Layout,
+    arch::{
+        asm,
+        global_asm,
+        x86_64::{
+            __cpuid,
+            __cpuid_count,
+        },
+    },
+    ptr,
+};
+use libcore::registers::{
+    read_cr0,
+    read_cr4,
+    write_cr0,
+    write_cr4,
+    CR0_EMULATION,
+    CR0_MONITOR_COPROCESSOR,
+    CR0_NUMERIC_ERROR,
+    CR0_TASK_SWITCHED,
+    CR4_OSFXSR,
+    CR4_OSXMMEXCPT,
+    CR4_OSXSAVE,
+};
+
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+const XCR0_OPMASK: u64 = 1 << 5;
+const XCR0_ZMM_HI256: u64 = 1 << 6;
+const XCR0_HI16_ZMM: u64 = 1 << 7;
+const XCR0_SUPPORTED: u64 =
+    XCR0_X87 | XCR0_SSE | XCR0_AVX | XCR0_OPMASK | XCR0_ZMM_HI256 | XCR0_HI16_ZMM;
+
+const FXSAVE_AREA_SIZE: usize = 512;
+const SAVE_AREA_ALIGN: usize = 64;
+const DEVICE_NOT_AVAILABLE_VECTOR: u8 = 7;
+
+static mut FPU_CONFIG: FpuConfig = FpuConfig {
+    xsave: false,
+    features: 0,
+    area_size: FXSAVE_AREA_SIZE,
+    lazy: false,
+};
+
+/// The save area of the state, which was the last state loaded into the FPU. With lazy switching,
+/// the registers of the FPU belong to this owner until the next task uses the FPU. The save area is
+/// allocated on the heap, so it doesn't move with the [FpuState].
+static mut FPU_OWNER: *mut u8 = ptr::null_mut();
+
+/// The save area of the state of the running task, which is loaded by the #NM handler after a lazy
+/// switch
+static mut FPU_CURRENT: *mut u8 = ptr::null_mut();
+
+struct FpuConfig {
+    xsave: bool,
+    features: u64,
+    area_size: usize,
+    lazy: bool,
+}
+
+/// The FPU state holds the x87, SSE and (with XSAVE) AVX registers of a thread. The size of the
+/// save area is determined by CPUID while initializing the FPU.
+pub struct FpuState {
+    area: *mut u8,
+    layout: Layout,
+}
+
+impl FpuState {
+    /// This function creates a new FPU state with the initial register values
+    pub fn new() -> Self {
+        let area_size = unsafe { FPU_CONFIG.area_size };
+        let layout = Layout::from_size_align(area_size, SAVE_AREA_ALIGN).unwrap();
+        let area = unsafe { alloc_zeroed(layout) };
+        if area.is_null() {
+            panic!("Unable to allocate FPU save area of {} bytes", area_size);
+        }
+
+        // Set default control words (all exceptions masked) like FNINIT
+        unsafe {
+            (area as *mut u16).write(0x037F);
+            (area.add(24) as *mut u32).write(0x1F80);
+        }
+        Self { area, layout }
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FpuState {
+    fn drop(&mut self) {
+        unsafe {
+            if FPU_OWNER == self.area {
+                FPU_OWNER = ptr::null_mut();
+            }
+            if FPU_CURRENT == self.area {
+                FPU_CURRENT = ptr::null_mut();
+            }
+            dealloc(self.area, self.layout);
+        }
+    }
+}
+
+extern "C" {
+    fn device_not_available_entry();
+}
+
+// The entry clears the task switched flag before the handler is called, so the handler can use the
+// FPU (like for saved SSE registers) without raising #NM again. Only the registers, which aren't
+// preserved by the System V ABI, are saved. The stack is aligned to 16 bytes before the call.
+global_asm!(
+    ".global device_not_available_entry",
+    "device_not_available_entry:",
+    "clts",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "call {handler}",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "iretq",
+    handler = sym handle_device_not_available,
+);
+
+/// This function enables the FPU with SSE and, if supported, XSAVE with all supported state
+/// components and installs the handler of the device not available exception (#NM). If lazy
+/// switching is enabled, the FPU state is only switched after the next task uses the FPU. The IDT
+/// must be loaded before.
+pub fn init_fpu(lazy: bool) {
+    unsafe {
+        let mut cr0 = read_cr0();
+        cr0 = (cr0 & !(CR0_EMULATION | CR0_TASK_SWITCHED))
+            | CR0_MONITOR_COPROCESSOR
+            | CR0_NUMERIC_ERROR;
+        write_cr0(cr0);
+
+        let mut cr4 = read_cr4() | CR4_OSFXSR | CR4_OSXMMEXCPT;
+        let xsave = __cpuid(0x01).ecx & (1 << 26) != 0;
+        if xsave {
+            cr4 |= CR4_OSXSAVE;
+        }
+        write_cr4(cr4);
+        asm!("fninit", options(nomem, nostack));
+
+        FPU_CONFIG.lazy = lazy;
+        if xsave {
+            // Enable all supported state components and query the size of the save area for them
+            let leaf = __cpuid_count(0x0D, 0);
+            let features = ((leaf.edx as u64) << 32 | leaf.eax as u64) & XCR0_SUPPORTED;
+            write_xcr0(features);
+
+            FPU_CONFIG.xsave = true;
+            FPU_CONFIG.features = features;
+            FPU_CONFIG.area_size = __cpuid_count(0x0D, 0).ebx as usize;
+        }
+    }
+    set_exception_handler(DEVICE_NOT_AVAILABLE_VECTOR, device_not_available_entry as u64);
+}
+
+/// This function returns the size of the save area of a FPU state in bytes
+pub fn save_area_size() -> usize {
+    unsafe { FPU_CONFIG.area_size }
+}
+
+/// This function switches the FPU to the state of the next task. It's called by the executor before
+/// a task is polled. The registers are saved into the state of the previous owner. With lazy
+/// switching, only the task switched flag is set and the state is switched by the #NM handler, if
+/// the next task uses the FPU.
+pub fn switch_fpu(next: &FpuState) {
+    unsafe {
+        FPU_CURRENT = next.area;
+        if FPU_OWNER == next.area {
+            asm!("clts", options(nomem, nostack));
+            return;
+        }
+
+        if FPU_CONFIG.lazy {
+            write_cr0(read_cr0() | CR0_TASK_SWITCHED);
+            return;
+        }
+        switch_owner(next.area);
+    }
+}
+
+/// This function handles the device not available exception (#NM), which is raised by the first
+/// FPU instruction after a lazy switch. The entry already cleared the task switched flag, so the
+/// state of the previous owner is saved and the state of the current task is loaded. The handler
+/// only compares pointers and uses inline assembly, so the SSE registers of the owner aren't changed
+/// before they are saved.
+extern "sysv64" fn handle_device_not_available() {
+    unsafe {
+        if !FPU_CURRENT.is_null() && FPU_OWNER != FPU_CURRENT {
+            switch_owner(FPU_CURRENT);
+        }
+    }
+}
+
+/// This function saves the registers into the save area of the owner and loads the specified save
+/// area. If no state owns the FPU (like before the first switch), the registers are discarded.
+unsafe fn switch_owner(area: *mut u8) {
+    if !FPU_OWNER.is_null() {
+        save_area(FPU_OWNER);
+    }
+    restore_area(area);
+    FPU_OWNER = area;
+}
+
+unsafe fn save_area(area: *mut u8) {
+    if FPU_CONFIG.xsave {
+        let features = FPU_CONFIG.features;
+        asm!(
+            "xsave64 [{}]",
+            in(reg) area,
+            in("eax") features as u32,
+            in("edx") (features >> 32) as u32,
+            options(nostack)
+        );
+    } else {
+        asm!("fxsave64 [{}]", in(reg) area, options(nostack));
+    }
+}
+
+unsafe fn restore_area(area: *const u8) {
+    if FPU_CONFIG.xsave {
+        let features = FPU_CONFIG.features;
+        asm!(
+            "xrstor64 [{}]",
+            in(reg) area,
+            in("eax") features as u32,
+            in("edx") (features >> 32) as u32,
+            options(nostack)
+        );
+    } else {
+        asm!("fxrstor64 [{}]", in(reg) area, options(nostack));
+    }
+}
+
+unsafe fn write_xcr0(value: u64) {
+    asm!(
+        "xsetbv",
+        in("ecx") 0,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nomem, nostack)
+    );
+}
@@ -0,0 +1,171 @@
+use crate::error::Error;
+use alloc::{
+    alloc::{
+        alloc_zeroed,
+        dealloc,
+    },
+    collections::BTreeMap,
+};
+use core::{
+    alloc::Layout,
+    ptr,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    alloc_stats::AllocationReport,
+    boot_info::FrameAllocatorHandoff,
+    paging::{
+        translate,
+        PAGE_SIZE,
+    },
+    FrameAllocator,
+};
+use libsync::Spinlock;
+
+const FRAME_LAYOUT: Layout =
+    unsafe { Layout::from_size_align_unchecked(PAGE_SIZE as usize, PAGE_SIZE as usize) };
+
+/// The frames, which were allocated by the kernel, with the address of the allocation and the
+/// number of mappings, which reference the frame.
+static FRAMES: Spinlock<BTreeMap<PhysAddr, Frame>> = Spinlock::new(BTreeMap::new());
+
+/// The frame allocator of the bootloader, which manages the physical memory outside of the kernel
+static FRAME_ALLOCATOR: Spinlock<Option<FrameAllocator<'static>>> = Spinlock::new(None);
+
+struct Frame {
+    address: VirtAddr,
+    ref_count: usize,
+}
+
+/// This function allocates a zeroed frame from the kernel heap. The frame starts with a reference
+/// count of one, which is owned by the caller.
+pub fn allocate_frame() -> Option<PhysAddr> {
+    let pointer = unsafe { alloc_zeroed(FRAME_LAYOUT) };
+    if pointer.is_null() {
+        return None;
+    }
+
+    let address = VirtAddr::from_ptr(pointer);
+    let Some((frame, _, _)) = translate(address) else {
+        unsafe { dealloc(pointer, FRAME_LAYOUT) };
+        return None;
+    };
+    FRAMES.lock().insert(
+        frame,
+        Frame {
+            address,
+            ref_count: 1,
+        },
+    );
+    Some(frame)
+}
+
+/// This function adds a reference to the specified frame and returns the new reference count.
+/// Frames, which weren't allocated by the kernel (like MMIO), aren't reference counted, so `None`
+/// is returned for them.
+pub fn share_frame(frame: PhysAddr) -> Option<usize> {
+    let mut frames = FRAMES.lock();
+    let frame = frames.get_mut(&frame)?;
+    frame.ref_count += 1;
+    Some(frame.ref_count)
+}
+
+/// This function removes a reference from the specified frame and frees the frame, if it was the
+/// last reference. Returns true, if the frame was freed.
+pub fn release_frame(frame: PhysAddr) -> bool {
+    let mut frames = FRAMES.lock();
+    let Some(entry) = frames.get_mut(&frame) else {
+        return false;
+    };
+
+    entry.ref_count -= 1;
+    if entry.ref_count > 0 {
+        return false;
+    }
+
+    let address = entry.address;
+    frames.remove(&frame);
+    drop(frames);
+    unsafe { dealloc(address.as_mut_ptr(), FRAME_LAYOUT) };
+    true
+}
+
+/// This function returns the number of references to the specified frame or zero, if the frame
+/// isn't reference counted.
+pub fn ref_count(frame: PhysAddr) -> usize {
+    FRAMES.lock().get(&frame).map_or(0, |frame| frame.ref_count)
+}
+
+/// This function returns the number of frames, which are currently allocated by the kernel
+pub fn allocated_frames() -> usize {
+    FRAMES.lock().len()
+}
+
+/// This function takes over the frame allocator of the bootloader. The frames, which are used by
+/// the bootloader, the kernel or the firmware, stay reserved, so the physical memory isn't scanned
+/// again. Returns false, if no frame allocator was handed over.
+pub fn init_frame_allocator(handoff: &FrameAllocatorHandoff) -> bool {
+    let Some(frame_allocator) = FrameAllocator::from_handoff(handoff) else {
+        return false;
+    };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+    true
+}
+
+/// This function allocates the specified number of zeroed, physically contiguous frames from the
+/// frame allocator of the bootloader. The frames end at or below the specified address and the first
+/// frame has the specified alignment, so the frames can be used by devices with a limited address
+/// width. The frames have to be freed with [free_contiguous].
+pub fn alloc_contiguous(
+    page_count: usize, max_address: PhysAddr, alignment: u64,
+) -> Result<PhysAddr, Error> {
+    let frame_allocator = FRAME_ALLOCATOR.lock();
+    let frame_allocator = frame_allocator.as_ref().ok_or(Error::NoFrameAllocator)?;
+    let address = frame_allocator
+        .alloc_contiguous(page_count, max_address, alignment)
+        .ok_or(Error::NoContiguousFrames(page_count, max_address.as_u64()))?;
+
+    let size = page_count * PAGE_SIZE as usize;
+    unsafe { ptr::write_bytes(address.to_virt().as_mut_ptr::<u8>(), 0, size) };
+    Ok(address)
+}
+
+/// This function frees the frames, which were allocated with [alloc_contiguous]
+///
+/// # Safety
+/// The caller has to ensure, that the frames aren't used by the kernel or a device anymore.
+pub unsafe fn free_contiguous(address: PhysAddr, page_count: usize) {
+    if let Some(frame_allocator) = FRAME_ALLOCATOR.lock().as_ref() {
+        frame_allocator.free_contiguous(address, page_count);
+    }
+}
+
+/// This function returns the number of allocated and remaining frames of the frame allocator, which
+/// was handed over by the bootloader.
+pub fn physical_frames() -> Option<(usize, usize)> {
+    FRAME_ALLOCATOR
+        .lock()
+        .as_ref()
+        .map(|allocator| (allocator.allocated_frames(), allocator.remaining_frames()))
+}
+
+/// This function returns the end of the physical memory, which is managed by the frame allocator of
+/// the bootloader
+pub fn physical_memory_end() -> Option<PhysAddr> {
+    FRAME_ALLOCATOR
+        .lock()
+        .as_ref()
+        .map(|allocator| allocator.stop_address)
+}
+
+/// This function returns the allocation statistics of the frame allocator, which was handed over by
+/// the bootloader.
+pub fn frame_report() -> Option<AllocationReport> {
+    FRAME_ALLOCATOR
+        .lock()
+        .as_ref()
+        .map(|allocator| allocator.stats.report())
+}
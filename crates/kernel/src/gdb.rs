@@ -0,0 +1,588 @@
+use crate::{
+    error::Error,
+    interrupts::set_exception_handler,
+    serial::{
+        self,
+        COM1,
+        COM2,
+    },
+};
+use alloc::string::ToString;
+use core::arch::{
+    asm,
+    global_asm,
+};
+use libcore::{
+    cmdline::CommandLine,
+    hexdump::find_unmapped,
+    registers::{
+        read_cr0,
+        write_cr0,
+        CR0_WRITE_PROTECT,
+        RFLAGS_TRAP,
+    },
+};
+use log::{
+    info,
+    warn,
+};
+
+/// The option, which enables the GDB stub on a serial port (`gdb`, `gdb=com1` or `gdb=com2`). The
+/// kernel stops at boot and waits for the debugger.
+pub const GDB_OPTION: &str = "gdb";
+
+const DEBUG_VECTOR: u64 = 1;
+const BREAKPOINT_VECTOR: u64 = 3;
+const INT3: u8 = 0xCC;
+const MAX_BREAKPOINTS: usize = 32;
+
+/// The maximal size of the packet data, which is announced to GDB with `qSupported`
+const MAX_PACKET_SIZE: usize = 4096;
+
+/// The signals, which are reported to GDB in stop replies
+const SIGTRAP: u8 = 5;
+const SIGABRT: u8 = 6;
+
+/// The registers of the interrupted code, which are saved by the assembly entry. The order matches
+/// the pushes of the entry, the last five fields are pushed by the CPU.
+#[repr(C)]
+struct TrapFrame {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rbp: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    rbx: u64,
+    rax: u64,
+    vector: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+impl TrapFrame {
+    /// This function returns the 64-bit registers in the order of the `g` packet of GDB
+    fn registers_mut(&mut self) -> [&mut u64; 17] {
+        [
+            &mut self.rax,
+            &mut self.rbx,
+            &mut self.rcx,
+            &mut self.rdx,
+            &mut self.rsi,
+            &mut self.rdi,
+            &mut self.rbp,
+            &mut self.rsp,
+            &mut self.r8,
+            &mut self.r9,
+            &mut self.r10,
+            &mut self.r11,
+            &mut self.r12,
+            &mut self.r13,
+            &mut self.r14,
+            &mut self.r15,
+            &mut self.rip,
+        ]
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    original: u8,
+    inserted: bool,
+}
+
+/// The stub doesn't allocate, because the debugger may be entered while the heap is locked
+struct GdbStub {
+    port: u16,
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    /// The breakpoint at the instruction pointer is skipped with a single step before it's inserted
+    stepping_over: bool,
+    /// GDB waits for a stop reply after `c` and `s`
+    resumed: bool,
+}
+
+static mut GDB_STUB: Option<GdbStub> = None;
+static mut PANICKING: bool = false;
+static mut PACKET: [u8; MAX_PACKET_SIZE] = [0; MAX_PACKET_SIZE];
+static mut RESPONSE: Response = Response {
+    buffer: [0; MAX_PACKET_SIZE],
+    length: 0,
+};
+
+extern "C" {
+    fn gdb_debug_entry();
+    fn gdb_breakpoint_entry();
+}
+
+// The entries push the vector (1 for debug exceptions, 3 for breakpoints) and all general purpose
+// registers, so the stub can read and change every register of the interrupted code. The stack is
+// aligned to 16 bytes before the call.
+global_asm!(
+    ".global gdb_debug_entry",
+    "gdb_debug_entry:",
+    "push 1",
+    "jmp 2f",
+    ".global gdb_breakpoint_entry",
+    "gdb_breakpoint_entry:",
+    "push 3",
+    "2:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "sub rsp, 8",
+    "call {trap}",
+    "add rsp, 8",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "add rsp, 8",
+    "iretq",
+    trap = sym gdb_trap,
+);
+
+/// This function installs the debug and breakpoint handlers of the stub and waits for GDB, if the
+/// stub is enabled with the command line. The IDT must be loaded before.
+pub fn init_gdb(command_line: &CommandLine) {
+    let port = match command_line.get(GDB_OPTION) {
+        None if command_line.has_flag(GDB_OPTION) => COM1,
+        None => return,
+        Some("com1") => COM1,
+        Some("com2") => COM2,
+        Some(name) => {
+            warn!(
+                "Unable to initialize GDB stub => {}\n",
+                Error::UnknownSerialPort(name.to_string())
+            );
+            return;
+        }
+    };
+
+    if port != COM1 {
+        serial::init_port(port);
+    }
+    unsafe {
+        GDB_STUB = Some(GdbStub {
+            port,
+            breakpoints: [None; MAX_BREAKPOINTS],
+            stepping_over: false,
+            resumed: false,
+        });
+    }
+    set_exception_handler(DEBUG_VECTOR as u8, gdb_debug_entry as u64);
+    set_exception_handler(BREAKPOINT_VECTOR as u8, gdb_breakpoint_entry as u64);
+
+    info!("Waiting for GDB on serial port 0x{:X}\n", port);
+    unsafe { asm!("int3") };
+}
+
+/// This function stops the kernel in the debugger, if the stub is enabled. It's called by the panic
+/// handler, so the state of the panicking kernel can be inspected.
+pub fn break_on_panic() {
+    unsafe {
+        if GDB_STUB.is_some() {
+            PANICKING = true;
+            asm!("int3");
+        }
+    }
+}
+
+extern "sysv64" fn gdb_trap(frame: &mut TrapFrame) {
+    let Some(stub) = (unsafe { GDB_STUB.as_mut() }) else {
+        return;
+    };
+
+    // Show the original code to GDB and report the address of the breakpoint instead of the next
+    // instruction
+    frame.rflags &= !RFLAGS_TRAP;
+    if frame.vector == BREAKPOINT_VECTOR && stub.is_breakpoint(frame.rip - 1) {
+        frame.rip -= 1;
+    }
+    stub.remove_breakpoints();
+
+    // The instruction below a breakpoint was executed, so the breakpoint can be inserted again
+    if frame.vector == DEBUG_VECTOR && stub.stepping_over {
+        stub.resume(frame, false);
+        return;
+    }
+
+    if stub.resumed {
+        stub.send_stop_reply(stop_signal());
+    }
+    stub.run(frame);
+}
+
+/// This function returns the signal, which is reported to GDB for the current stop
+fn stop_signal() -> u8 {
+    if unsafe { PANICKING } {
+        SIGABRT
+    } else {
+        SIGTRAP
+    }
+}
+
+impl GdbStub {
+    /// This function handles the packets of GDB until the execution is resumed
+    fn run(&mut self, frame: &mut TrapFrame) {
+        loop {
+            let length = self.read_packet();
+            let packet = unsafe { &PACKET[..length] };
+            let response = unsafe { &mut RESPONSE };
+            response.clear();
+
+            match packet.first() {
+                Some(b'?') => {
+                    response.push_str("S");
+                    response.push_hex(&[stop_signal()]);
+                }
+                Some(b'g') => read_registers(frame, response),
+                Some(b'G') => {
+                    write_registers(frame, &packet[1..]);
+                    response.push_str("OK");
+                }
+                Some(b'm') => read_memory(&packet[1..], response),
+                Some(b'M') => write_memory(&packet[1..], response),
+                Some(b'Z') => self.set_breakpoint(&packet[1..], true, response),
+                Some(b'z') => self.set_breakpoint(&packet[1..], false, response),
+                Some(command @ (b'c' | b's')) => {
+                    if let Some(address) = parse_hex(&packet[1..]) {
+                        frame.rip = address;
+                    }
+                    self.resume(frame, *command == b's');
+                    return;
+                }
+                Some(command @ (b'D' | b'k')) => {
+                    if *command == b'D' {
+                        response.push_str("OK");
+                        self.send_packet(response.as_bytes());
+                    }
+                    self.breakpoints = [None; MAX_BREAKPOINTS];
+                    self.resumed = false;
+                    return;
+                }
+                Some(b'H') => response.push_str("OK"),
+                _ if packet.starts_with(b"qSupported") => {
+                    response.push_str("PacketSize=");
+                    response.push_hex(&(MAX_PACKET_SIZE as u16).to_be_bytes());
+                }
+                _ if packet == b"qAttached" => response.push_str("1"),
+                _ => {}
+            }
+            self.send_packet(response.as_bytes());
+        }
+    }
+
+    /// This function inserts the breakpoints and resumes the execution. A breakpoint at the
+    /// instruction pointer is inserted after the next instruction, which is executed with a single
+    /// step.
+    fn resume(&mut self, frame: &mut TrapFrame, step: bool) {
+        let skipped = self.insert_breakpoints(frame.rip);
+        self.stepping_over = skipped && !step;
+        self.resumed = true;
+        if step || skipped {
+            frame.rflags |= RFLAGS_TRAP;
+        }
+    }
+
+    fn is_breakpoint(&self, address: u64) -> bool {
+        self.breakpoints
+            .iter()
+            .flatten()
+            .any(|breakpoint| breakpoint.address == address)
+    }
+
+    fn set_breakpoint(&mut self, arguments: &[u8], insert: bool, response: &mut Response) {
+        // Only software breakpoints (`Z0,address,kind`) are supported
+        let mut arguments = arguments.split(|byte| *byte == b',');
+        if arguments.next() != Some(b"0") {
+            return;
+        }
+        let Some(address) = arguments.next().and_then(parse_hex) else {
+            response.push_str("E01");
+            return;
+        };
+
+        if !insert {
+            for slot in self.breakpoints.iter_mut() {
+                if slot.is_some_and(|breakpoint| breakpoint.address == address) {
+                    *slot = None;
+                }
+            }
+            response.push_str("OK");
+            return;
+        }
+
+        if find_unmapped(address, 1).is_some() {
+            response.push_str("E14");
+            return;
+        }
+        if self.is_breakpoint(address) {
+            response.push_str("OK");
+            return;
+        }
+        match self.breakpoints.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(Breakpoint {
+                    address,
+                    original: 0,
+                    inserted: false,
+                });
+                response.push_str("OK");
+            }
+            None => response.push_str("E12"),
+        }
+    }
+
+    /// This function patches an `int3` over every breakpoint except the breakpoint at the specified
+    /// address. Returns true, if a breakpoint was skipped.
+    fn insert_breakpoints(&mut self, skipped_address: u64) -> bool {
+        let mut skipped = false;
+        for breakpoint in self.breakpoints.iter_mut().flatten() {
+            if breakpoint.address == skipped_address {
+                skipped = true;
+                continue;
+            }
+
+            let address = breakpoint.address as *mut u8;
+            unsafe {
+                breakpoint.original = address.read_volatile();
+                with_write_access(|| address.write_volatile(INT3));
+            }
+            breakpoint.inserted = true;
+        }
+        skipped
+    }
+
+    fn remove_breakpoints(&mut self) {
+        for breakpoint in self.breakpoints.iter_mut().flatten() {
+            if breakpoint.inserted {
+                let address = breakpoint.address as *mut u8;
+                unsafe { with_write_access(|| address.write_volatile(breakpoint.original)) };
+                breakpoint.inserted = false;
+            }
+        }
+    }
+
+    fn send_stop_reply(&self, signal: u8) {
+        let response = unsafe { &mut RESPONSE };
+        response.clear();
+        response.push_str("S");
+        response.push_hex(&[signal]);
+        self.send_packet(response.as_bytes());
+    }
+
+    /// This function waits for the next packet (`$data#checksum`) and acknowledges it. Packets with
+    /// an invalid checksum are requested again. Returns the length of the data in [PACKET].
+    fn read_packet(&self) -> usize {
+        loop {
+            while serial::read_byte(self.port) != b'$' {}
+
+            let mut length = 0;
+            let mut checksum = 0u8;
+            loop {
+                let byte = serial::read_byte(self.port);
+                if byte == b'#' {
+                    break;
+                }
+                checksum = checksum.wrapping_add(byte);
+                if length < MAX_PACKET_SIZE {
+                    unsafe { PACKET[length] = byte };
+                    length += 1;
+                }
+            }
+
+            let expected = [serial::read_byte(self.port), serial::read_byte(self.port)];
+            if parse_hex(&expected) == Some(checksum as u64) {
+                serial::write_raw(self.port, b"+");
+                return length;
+            }
+            serial::write_raw(self.port, b"-");
+        }
+    }
+
+    /// This function sends the data as packet, until GDB acknowledges it
+    fn send_packet(&self, data: &[u8]) {
+        let checksum = data
+            .iter()
+            .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte));
+        loop {
+            serial::write_raw(self.port, b"$");
+            serial::write_raw(self.port, data);
+            serial::write_raw(self.port, b"#");
+            serial::write_raw(self.port, &hex_byte(checksum));
+            if serial::read_byte(self.port) != b'-' {
+                return;
+            }
+        }
+    }
+}
+
+/// The response is built in a static buffer, so it can be sent again without allocating
+struct Response {
+    buffer: [u8; MAX_PACKET_SIZE],
+    length: usize,
+}
+
+impl Response {
+    fn clear(&mut self) {
+        self.length = 0;
+    }
+
+    fn push_str(&mut self, value: &str) {
+        for byte in value.bytes() {
+            if self.length < MAX_PACKET_SIZE {
+                self.buffer[self.length] = byte;
+                self.length += 1;
+            }
+        }
+    }
+
+    fn push_hex(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let digits = hex_byte(*byte);
+            self.push_str(core::str::from_utf8(&digits).unwrap());
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+}
+
+/// This function writes the registers in the order of GDB for x86-64. The segment registers are 32
+/// bit wide, the data segments are reported as zero.
+fn read_registers(frame: &mut TrapFrame, response: &mut Response) {
+    for register in frame.registers_mut() {
+        response.push_hex(&register.to_le_bytes());
+    }
+    for register in [frame.rflags, frame.cs, frame.ss, 0, 0, 0, 0] {
+        response.push_hex(&(register as u32).to_le_bytes());
+    }
+}
+
+/// This function changes the general purpose registers and the flags. The segment registers are
+/// ignored.
+fn write_registers(frame: &mut TrapFrame, data: &[u8]) {
+    let mut chunks = data.chunks_exact(16);
+    for register in frame.registers_mut() {
+        if let Some(value) = chunks.next().and_then(parse_le_hex) {
+            *register = value;
+        }
+    }
+    if let Some(rflags) = data.get(17 * 16..17 * 16 + 8).and_then(parse_le_hex) {
+        frame.rflags = rflags;
+    }
+}
+
+fn read_memory(arguments: &[u8], response: &mut Response) {
+    let Some((address, length)) = parse_range(arguments) else {
+        response.push_str("E01");
+        return;
+    };
+    let length = length.min(MAX_PACKET_SIZE / 2);
+    if length > 0 && find_unmapped(address, length).is_some() {
+        response.push_str("E14");
+        return;
+    }
+
+    for offset in 0..length {
+        let byte = unsafe { (address as *const u8).add(offset).read_volatile() };
+        response.push_hex(&[byte]);
+    }
+}
+
+fn write_memory(arguments: &[u8], response: &mut Response) {
+    let Some(separator) = arguments.iter().position(|byte| *byte == b':') else {
+        response.push_str("E01");
+        return;
+    };
+    let Some((address, length)) = parse_range(&arguments[..separator]) else {
+        response.push_str("E01");
+        return;
+    };
+    let data = &arguments[separator + 1..];
+    if data.len() != length * 2 {
+        response.push_str("E01");
+        return;
+    }
+    if length > 0 && find_unmapped(address, length).is_some() {
+        response.push_str("E14");
+        return;
+    }
+
+    for (offset, digits) in data.chunks_exact(2).enumerate() {
+        let byte = parse_hex(digits).unwrap_or_default() as u8;
+        unsafe { with_write_access(|| (address as *mut u8).add(offset).write_volatile(byte)) };
+    }
+    response.push_str("OK");
+}
+
+/// This function disables the write protection of the kernel for the closure, so breakpoints can be
+/// written into read-only code pages
+unsafe fn with_write_access(function: impl FnOnce()) {
+    let cr0 = read_cr0();
+    write_cr0(cr0 & !CR0_WRITE_PROTECT);
+    function();
+    write_cr0(cr0);
+}
+
+/// This function parses the `address,length` arguments of memory packets
+fn parse_range(arguments: &[u8]) -> Option<(u64, usize)> {
+    let separator = arguments.iter().position(|byte| *byte == b',')?;
+    Some((parse_hex(&arguments[..separator])?, parse_hex(&arguments[separator + 1..])? as usize))
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    u64::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// This function parses a register value, which is sent by GDB as little-endian hex bytes
+fn parse_le_hex(digits: &[u8]) -> Option<u64> {
+    digits
+        .chunks_exact(2)
+        .rev()
+        .try_fold(0u64, |value, byte| Some(value << 8 | parse_hex(byte)?))
+}
+
+fn hex_byte(byte: u8) -> [u8; 2] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    [DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0xF) as usize]]
+}
@@ -0,0 +1,88 @@
+use libcore::descriptors::{
+    load_gdt,
+    load_segments,
+    load_tss,
+    DescriptorTablePointer,
+};
+
+/// The selectors of the kernel GDT. The order of the user segments (data before code) is required
+/// by SYSRET, which derives both selectors from one base selector.
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const USER_DATA_SELECTOR: u16 = 0x18 | 3;
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+pub const TSS_SELECTOR: u16 = 0x28;
+
+const KERNEL_CODE_SEGMENT: u64 = 0x00AF_9A00_0000_FFFF;
+const KERNEL_DATA_SEGMENT: u64 = 0x00CF_9200_0000_FFFF;
+const USER_DATA_SEGMENT: u64 = 0x00CF_F200_0000_FFFF;
+const USER_CODE_SEGMENT: u64 = 0x00AF_FA00_0000_FFFF;
+const TSS_AVAILABLE: u64 = 0x89;
+
+const KERNEL_STACK_SIZE: usize = 16 * 1024;
+
+/// The Task State Segment holds the stack, which is loaded by the CPU on a switch from user mode
+/// into the kernel. The I/O permission bitmap isn't used, so user mode can't access any port.
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved0: u32,
+    pub privilege_stacks: [u64; 3],
+    reserved1: u64,
+    pub interrupt_stacks: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    pub io_map_base: u16,
+}
+
+#[repr(C, align(16))]
+struct KernelStack([u8; KERNEL_STACK_SIZE]);
+
+static mut KERNEL_STACK: KernelStack = KernelStack([0; KERNEL_STACK_SIZE]);
+
+pub(crate) static mut TSS: TaskStateSegment = TaskStateSegment {
+    reserved0: 0,
+    privilege_stacks: [0; 3],
+    reserved1: 0,
+    interrupt_stacks: [0; 7],
+    reserved2: 0,
+    reserved3: 0,
+    io_map_base: core::mem::size_of::<TaskStateSegment>() as u16,
+};
+
+static mut GDT: [u64; 7] = [
+    0,
+    KERNEL_CODE_SEGMENT,
+    KERNEL_DATA_SEGMENT,
+    USER_DATA_SEGMENT,
+    USER_CODE_SEGMENT,
+    0,
+    0,
+];
+
+/// This function replaces the GDT of the firmware with the kernel GDT, which contains the user
+/// segments and the TSS, and reloads the segment registers. It must be called before the IDT is
+/// created, so the gates use the kernel code selector.
+pub fn init_gdt() {
+    unsafe {
+        TSS.privilege_stacks[0] = KERNEL_STACK.0.as_ptr_range().end as u64;
+
+        // The TSS descriptor is a system descriptor, which occupies two entries
+        let base = core::ptr::addr_of!(TSS) as u64;
+        let limit = (core::mem::size_of::<TaskStateSegment>() - 1) as u64;
+        GDT[5] = limit | (base & 0xFF_FFFF) << 16 | TSS_AVAILABLE << 40 | ((base >> 24) & 0xFF) << 56;
+        GDT[6] = base >> 32;
+
+        load_gdt(&DescriptorTablePointer {
+            limit: (core::mem::size_of_val(&GDT) - 1) as u16,
+            base: GDT.as_ptr() as u64,
+        });
+        load_segments(KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR);
+        load_tss(TSS_SELECTOR);
+    }
+}
+
+/// This function sets the stack, which is used by the CPU on the next switch from user mode into
+/// the kernel (interrupts and system calls).
+pub fn set_kernel_stack(stack_top: u64) {
+    unsafe { TSS.privilege_stacks[0] = stack_top };
+}
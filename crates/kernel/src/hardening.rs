@@ -0,0 +1,258 @@
+use crate::error::Error;
+use core::{
+    arch::{
+        asm,
+        x86_64::{
+            __cpuid,
+            __cpuid_count,
+        },
+    },
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+use libcore::{
+    cmdline::CommandLine,
+    registers::{
+        read_cr4,
+        read_msr,
+        write_cr4,
+        write_msr,
+        CR4_SMAP,
+        CR4_SMEP,
+        CR4_UMIP,
+        IA32_PRED_CMD,
+        IA32_SPEC_CTRL,
+        PRED_CMD_IBPB,
+        SPEC_CTRL_IBRS,
+        SPEC_CTRL_SSBD,
+    },
+};
+use log::info;
+
+/// The flags, which disable the hardening features and mitigations for debugging
+pub const NOSMEP_OPTION: &str = "nosmep";
+pub const NOSMAP_OPTION: &str = "nosmap";
+pub const NOUMIP_OPTION: &str = "noumip";
+pub const NOIBRS_OPTION: &str = "noibrs";
+pub const NOIBPB_OPTION: &str = "noibpb";
+pub const NOSSBD_OPTION: &str = "nossbd";
+
+/// The option `mitigations=off`, which disables all hardening features and mitigations
+pub const MITIGATIONS_OPTION: &str = "mitigations";
+
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// IBPB is a command without state, so the kernel remembers, whether barriers are issued
+static IBPB_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The hardening features of CR4 and the speculative-execution mitigations of IA32_SPEC_CTRL and
+/// IA32_PRED_CMD, which can be enabled and disabled at runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mitigation {
+    /// Supervisor Mode Execution Prevention
+    Smep,
+    /// Supervisor Mode Access Prevention
+    Smap,
+    /// User Mode Instruction Prevention
+    Umip,
+    /// Indirect Branch Restricted Speculation
+    Ibrs,
+    /// Indirect Branch Prediction Barrier
+    Ibpb,
+    /// Speculative Store Bypass Disable
+    Ssbd,
+}
+
+impl Mitigation {
+    pub const ALL: [Self; 6] = [
+        Self::Smep,
+        Self::Smap,
+        Self::Umip,
+        Self::Ibrs,
+        Self::Ibpb,
+        Self::Ssbd,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Smep => "SMEP",
+            Self::Smap => "SMAP",
+            Self::Umip => "UMIP",
+            Self::Ibrs => "IBRS",
+            Self::Ibpb => "IBPB",
+            Self::Ssbd => "SSBD",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mitigation| mitigation.name().eq_ignore_ascii_case(name))
+    }
+
+    const fn disable_option(self) -> &'static str {
+        match self {
+            Self::Smep => NOSMEP_OPTION,
+            Self::Smap => NOSMAP_OPTION,
+            Self::Umip => NOUMIP_OPTION,
+            Self::Ibrs => NOIBRS_OPTION,
+            Self::Ibpb => NOIBPB_OPTION,
+            Self::Ssbd => NOSSBD_OPTION,
+        }
+    }
+
+    /// This function returns true, if the mitigation is enumerated by CPUID. The speculation
+    /// controls are enumerated by Intel in leaf 0x07 and by AMD in leaf 0x80000008.
+    pub fn is_supported(self) -> bool {
+        let features = unsafe { __cpuid_count(0x07, 0) };
+        let extended_features = match unsafe { __cpuid(0x8000_0000) }.eax >= 0x8000_0008 {
+            true => unsafe { __cpuid(0x8000_0008) }.ebx,
+            false => 0,
+        };
+        let spec_ctrl = features.edx & (1 << 26) != 0;
+        match self {
+            Self::Smep => features.ebx & (1 << 7) != 0,
+            Self::Smap => features.ebx & (1 << 20) != 0,
+            Self::Umip => features.ecx & (1 << 2) != 0,
+            Self::Ibrs => spec_ctrl || extended_features & (1 << 14) != 0,
+            Self::Ibpb => spec_ctrl || extended_features & (1 << 12) != 0,
+            Self::Ssbd => features.edx & (1 << 31) != 0 || extended_features & (1 << 24) != 0,
+        }
+    }
+
+    /// This function returns true, if the mitigation is currently enabled
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Self::Smep => read_cr4() & CR4_SMEP != 0,
+            Self::Smap => read_cr4() & CR4_SMAP != 0,
+            Self::Umip => read_cr4() & CR4_UMIP != 0,
+            Self::Ibrs => self.is_supported() && read_spec_ctrl() & SPEC_CTRL_IBRS != 0,
+            Self::Ibpb => IBPB_ENABLED.load(Ordering::Relaxed),
+            Self::Ssbd => self.is_supported() && read_spec_ctrl() & SPEC_CTRL_SSBD != 0,
+        }
+    }
+}
+
+#[inline]
+fn read_spec_ctrl() -> u64 {
+    unsafe { read_msr(IA32_SPEC_CTRL) }
+}
+
+/// The guard allows the kernel to access user memory while SMAP is enabled. The access is forbidden
+/// again, when the guard is dropped.
+pub struct UserAccessGuard {
+    _private: (),
+}
+
+impl UserAccessGuard {
+    pub fn new() -> Self {
+        if SMAP_ENABLED.load(Ordering::Relaxed) {
+            unsafe { asm!("stac", options(nomem, nostack)) };
+        }
+        Self { _private: () }
+    }
+}
+
+impl Default for UserAccessGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for UserAccessGuard {
+    fn drop(&mut self) {
+        if SMAP_ENABLED.load(Ordering::Relaxed) {
+            unsafe { asm!("clac", options(nomem, nostack)) };
+        }
+    }
+}
+
+/// This function executes the specified function with access to user memory. It must be used by
+/// every path, which legitimately reads or writes user memory.
+#[inline]
+pub fn with_user_access<R>(function: impl FnOnce() -> R) -> R {
+    let _guard = UserAccessGuard::new();
+    function()
+}
+
+/// This function enables or disables the specified mitigation. If the mitigation should be enabled
+/// but isn't supported by the CPU, this function returns a [Error::UnsupportedMitigation] error.
+pub fn set_mitigation(mitigation: Mitigation, enabled: bool) -> Result<(), Error> {
+    if !mitigation.is_supported() {
+        return match enabled {
+            true => Err(Error::UnsupportedMitigation(mitigation.name())),
+            false => Ok(()),
+        };
+    }
+
+    let update = |value: u64, bit: u64| {
+        match enabled {
+            true => value | bit,
+            false => value & !bit,
+        }
+    };
+    match mitigation {
+        Mitigation::Smep => unsafe { write_cr4(update(read_cr4(), CR4_SMEP)) },
+        Mitigation::Smap => {
+            unsafe { write_cr4(update(read_cr4(), CR4_SMAP)) };
+            SMAP_ENABLED.store(enabled, Ordering::Relaxed);
+        }
+        Mitigation::Umip => unsafe { write_cr4(update(read_cr4(), CR4_UMIP)) },
+        Mitigation::Ibrs => unsafe {
+            write_msr(IA32_SPEC_CTRL, update(read_spec_ctrl(), SPEC_CTRL_IBRS))
+        },
+        Mitigation::Ibpb => {
+            IBPB_ENABLED.store(enabled, Ordering::Relaxed);
+            indirect_branch_barrier();
+        }
+        Mitigation::Ssbd => unsafe {
+            write_msr(IA32_SPEC_CTRL, update(read_spec_ctrl(), SPEC_CTRL_SSBD))
+        },
+    }
+    Ok(())
+}
+
+/// This function prevents, that indirect branches after the barrier are predicted by branches
+/// before the barrier. It must be called, when the kernel switches between untrusted contexts. If
+/// IBPB is disabled, this function does nothing.
+#[inline]
+pub fn indirect_branch_barrier() {
+    if IBPB_ENABLED.load(Ordering::Relaxed) {
+        unsafe { write_msr(IA32_PRED_CMD, PRED_CMD_IBPB) };
+    }
+}
+
+/// This function returns the state of the mitigation as text for the log and the shell
+pub fn mitigation_state(mitigation: Mitigation) -> &'static str {
+    match (mitigation.is_supported(), mitigation.is_enabled()) {
+        (false, _) => "not supported",
+        (true, true) => "enabled",
+        (true, false) => "disabled",
+    }
+}
+
+/// This function enables SMEP, SMAP, UMIP, IBRS, IBPB and SSBD, if they are reported by CPUID and
+/// not disabled on the command line. All of them are disabled with `mitigations=off`. The effective
+/// state of the mitigations and of CR4 is logged.
+pub fn init_hardening(command_line: &CommandLine) {
+    let all_disabled = command_line.get(MITIGATIONS_OPTION) == Some("off");
+    for mitigation in Mitigation::ALL {
+        let disabled = all_disabled || command_line.has_flag(mitigation.disable_option());
+        if mitigation.is_supported() {
+            set_mitigation(mitigation, !disabled).unwrap();
+        }
+
+        let state = match (mitigation.is_supported(), disabled) {
+            (true, true) => "disabled by command line",
+            _ => mitigation_state(mitigation),
+        };
+        info!("{}: {}\n", mitigation.name(), state);
+    }
+
+    info!("CR4 = 0x{:X}\n", read_cr4());
+    if Mitigation::Ibrs.is_supported() || Mitigation::Ssbd.is_supported() {
+        info!("IA32_SPEC_CTRL = 0x{:X}\n", read_spec_ctrl());
+    }
+}
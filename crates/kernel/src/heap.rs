@@ -0,0 +1,511 @@
+use crate::{
+    symbols,
+    timer,
+};
+use core::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+    },
+    mem,
+    ptr,
+};
+use libcore::{
+    alloc_stats::{
+        AllocationReport,
+        AllocationStats,
+    },
+    backtrace::backtrace,
+    poison,
+};
+use libsync::Spinlock;
+use log::error;
+
+const HEAP_SIZE: usize = 4 * 1024 * 1024;
+
+/// The redzones are only enabled in debug builds, because every allocation gets a header and both
+/// redzones
+const REDZONE_ENABLED: bool = cfg!(debug_assertions);
+
+/// The size of the redzones in front of and behind every allocation
+const REDZONE_SIZE: usize = 16;
+
+/// The byte, which is written into the redzones. A changed byte indicates a buffer overflow.
+const REDZONE_BYTE: u8 = 0xFD;
+
+const ALLOCATION_MAGIC: u64 = 0x4F56_4652_4845_4150;
+
+/// The number of return addresses, which are captured for every allocation
+const ALLOCATION_BACKTRACE_DEPTH: usize = 8;
+
+/// The interval of the background check of the redzones in milliseconds
+const REDZONE_CHECK_INTERVAL: u64 = 1000;
+
+#[repr(C, align(4096))]
+struct HeapMemory([u8; HEAP_SIZE]);
+
+static mut HEAP_MEMORY: HeapMemory = HeapMemory([0; HEAP_SIZE]);
+
+/// The statistics of the kernel heap, the sizes include the padding to the block alignment
+static HEAP_STATS: AllocationStats = AllocationStats::new();
+
+#[global_allocator]
+static ALLOCATOR: KernelHeap = KernelHeap {
+    head: Spinlock::new(FreeRegion {
+        size: 0,
+        next: None,
+    }),
+    allocations: Spinlock::new(AllocationList {
+        head: ptr::null_mut(),
+    }),
+};
+
+struct FreeRegion {
+    size: usize,
+    next: Option<&'static mut FreeRegion>,
+}
+
+impl FreeRegion {
+    #[inline]
+    fn start_address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    #[inline]
+    fn end_address(&self) -> usize {
+        self.start_address() + self.size
+    }
+}
+
+/// The header in front of the front redzone of every allocation in debug builds. The headers of the
+/// live allocations are linked, so the redzones can be verified by the background check.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct AllocationHeader {
+    magic: u64,
+    size: usize,
+    previous: *mut AllocationHeader,
+    next: *mut AllocationHeader,
+    backtrace: [u64; ALLOCATION_BACKTRACE_DEPTH],
+}
+
+struct AllocationList {
+    head: *mut AllocationHeader,
+}
+
+// The headers are only accessed with the lock of the list
+unsafe impl Send for AllocationList {}
+
+/// The kernel heap is a first-fit linked list allocator over a statically reserved memory region.
+/// The free regions are stored in the free memory itself, so the heap has no metadata overhead. The
+/// list is sorted by address and neighbouring regions are merged, so the heap doesn't fragment. In
+/// debug builds, every allocation is surrounded by redzones, which are verified on free.
+pub struct KernelHeap {
+    head: Spinlock<FreeRegion>,
+    allocations: Spinlock<AllocationList>,
+}
+
+impl KernelHeap {
+    /// This function inserts the specified memory into the free regions, which are sorted by
+    /// address. The memory is merged with the free regions in front of and behind it.
+    unsafe fn add_free_region(head: &mut FreeRegion, address: usize, mut size: usize) {
+        debug_assert_eq!(address % mem::align_of::<FreeRegion>(), 0);
+        debug_assert!(size >= mem::size_of::<FreeRegion>());
+
+        // Find the last free region in front of the memory
+        let mut previous: *mut FreeRegion = head;
+        while let Some(region) = (*previous).next.as_deref_mut() {
+            if region.start_address() > address {
+                break;
+            }
+            previous = region;
+        }
+        let previous = &mut *previous;
+
+        // Merge with the following region. Its header becomes free memory, so it's poisoned.
+        let mut next = previous.next.take();
+        if next
+            .as_ref()
+            .is_some_and(|region| region.start_address() == address + size)
+        {
+            let region = next.take().unwrap();
+            size += region.size;
+            next = region.next.take();
+            if poison::POISON_ENABLED {
+                let header = region as *mut FreeRegion as *mut u8;
+                poison::poison(core::slice::from_raw_parts_mut(header, mem::size_of::<FreeRegion>()));
+            }
+        }
+
+        // Merge with the previous region, the head has no memory and is never merged
+        if previous.size != 0 && previous.end_address() == address {
+            previous.size += size;
+            previous.next = next;
+            return;
+        }
+
+        let region = address as *mut FreeRegion;
+        region.write(FreeRegion { size, next });
+        previous.next = Some(&mut *region);
+    }
+
+    fn find_region(
+        head: &mut FreeRegion, size: usize, align: usize,
+    ) -> Option<(&'static mut FreeRegion, usize)> {
+        let mut current = head;
+        while let Some(ref mut region) = current.next {
+            if let Some(start_address) = Self::allocation_start(region, size, align) {
+                let next = region.next.take();
+                let result = Some((current.next.take().unwrap(), start_address));
+                current.next = next;
+                return result;
+            } else {
+                current = current.next.as_mut().unwrap();
+            }
+        }
+        None
+    }
+
+    fn allocation_start(region: &FreeRegion, size: usize, align: usize) -> Option<usize> {
+        // The padding in front of the allocation is returned to the free regions, so it must be able
+        // to hold the free region header
+        let mut start_address = region.start_address().next_multiple_of(align);
+        let padding = start_address - region.start_address();
+        if padding > 0 && padding < mem::size_of::<FreeRegion>() {
+            start_address =
+                (region.start_address() + mem::size_of::<FreeRegion>()).next_multiple_of(align);
+        }
+
+        let end_address = start_address.checked_add(size)?;
+        if end_address > region.end_address() {
+            return None;
+        }
+
+        // The remaining memory must be able to hold the free region header
+        let excess_size = region.end_address() - end_address;
+        if excess_size > 0 && excess_size < mem::size_of::<FreeRegion>() {
+            return None;
+        }
+        Some(start_address)
+    }
+
+    /// This function verifies, that the allocated memory wasn't written after it was freed. The
+    /// header of the free region isn't poisoned, so it's skipped.
+    unsafe fn verify_poison(region_start: usize, start_address: usize, size: usize) {
+        let check_start = start_address.max(region_start + mem::size_of::<FreeRegion>());
+        let memory =
+            core::slice::from_raw_parts(check_start as *const u8, start_address + size - check_start);
+        if let Some(offset) = poison::find_corruption(memory) {
+            panic!(
+                "Write after free at 0x{:X} in heap block 0x{:X}-0x{:X} (expected poison 0x{:02X}, \
+                 found 0x{:02X})",
+                check_start + offset,
+                start_address,
+                start_address + size,
+                poison::POISON_BYTE,
+                memory[offset]
+            );
+        }
+    }
+
+    /// This function checks, that the specified block is located in the heap memory and doesn't
+    /// overlap with a free region. Otherwise the block is freed twice or wasn't allocated by this
+    /// heap.
+    unsafe fn verify_free(head: &FreeRegion, address: usize, size: usize) {
+        let heap_start = HEAP_MEMORY.0.as_ptr() as usize;
+        if address < heap_start
+            || address + size > heap_start + HEAP_SIZE
+            || address % mem::align_of::<FreeRegion>() != 0
+        {
+            panic!(
+                "Invalid free of heap block 0x{:X}-0x{:X} (expected an aligned block between 0x{:X} \
+                 and 0x{:X})",
+                address,
+                address + size,
+                heap_start,
+                heap_start + HEAP_SIZE
+            );
+        }
+
+        let mut current = head.next.as_deref();
+        while let Some(region) = current {
+            if address < region.end_address() && region.start_address() < address + size {
+                panic!(
+                    "Double free of heap block 0x{:X}-0x{:X} (expected an allocated block, but it \
+                     overlaps with free region 0x{:X}-0x{:X})",
+                    address,
+                    address + size,
+                    region.start_address(),
+                    region.end_address()
+                );
+            }
+            current = region.next.as_deref();
+        }
+    }
+
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout
+            .align_to(mem::align_of::<FreeRegion>())
+            .unwrap()
+            .pad_to_align();
+        (layout.size().max(mem::size_of::<FreeRegion>()), layout.align())
+    }
+
+    /// This function allocates a block with the specified layout from the free regions
+    unsafe fn allocate_block(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+        let mut head = self.head.lock();
+        match Self::find_region(&mut head, size, align) {
+            Some((region, start_address)) => {
+                let (region_start, region_end) = (region.start_address(), region.end_address());
+                if poison::POISON_ENABLED {
+                    Self::verify_poison(region_start, start_address, size);
+                }
+
+                // Return the padding in front of and the memory behind the block to the free regions
+                if start_address > region_start {
+                    Self::add_free_region(&mut head, region_start, start_address - region_start);
+                }
+                let end_address = start_address + size;
+                let excess_size = region_end - end_address;
+                if excess_size > 0 {
+                    Self::add_free_region(&mut head, end_address, excess_size);
+                }
+                HEAP_STATS.record_allocation(size);
+                start_address as *mut u8
+            }
+            None => {
+                HEAP_STATS.record_failure(size);
+                ptr::null_mut()
+            }
+        }
+    }
+
+    /// This function returns the block with the specified layout to the free regions
+    unsafe fn free_block(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::size_align(layout);
+        let mut head = self.head.lock();
+        if poison::POISON_ENABLED {
+            Self::verify_free(&head, ptr as usize, size);
+            poison::poison(core::slice::from_raw_parts_mut(ptr, size));
+        }
+        Self::add_free_region(&mut head, ptr as usize, size);
+        HEAP_STATS.record_free(size);
+    }
+
+    /// This function returns the layout of the block, which contains the header, the redzones and
+    /// the allocation, and the offset of the allocation in the block
+    fn redzone_layout(layout: Layout) -> (Layout, usize) {
+        let align = layout.align().max(mem::align_of::<AllocationHeader>());
+        let offset = (mem::size_of::<AllocationHeader>() + REDZONE_SIZE).next_multiple_of(align);
+        let size = offset + layout.size() + REDZONE_SIZE;
+        (Layout::from_size_align(size, align).unwrap(), offset)
+    }
+
+    /// This function writes the header and the redzones of the allocation and links the header into
+    /// the list of the live allocations
+    unsafe fn guard_allocation(&self, address: usize, size: usize) {
+        let mut header = AllocationHeader {
+            magic: ALLOCATION_MAGIC,
+            size,
+            previous: ptr::null_mut(),
+            next: ptr::null_mut(),
+            backtrace: [0; ALLOCATION_BACKTRACE_DEPTH],
+        };
+        for (entry, return_address) in header.backtrace.iter_mut().zip(backtrace().skip(1)) {
+            *entry = return_address;
+        }
+        redzone(address - REDZONE_SIZE).fill(REDZONE_BYTE);
+        redzone(address + size).fill(REDZONE_BYTE);
+
+        let header_pointer = header_of(address);
+        let mut allocations = self.allocations.lock();
+        header.next = allocations.head;
+        if let Some(next) = allocations.head.as_mut() {
+            next.previous = header_pointer;
+        }
+        header_pointer.write(header);
+        allocations.head = header_pointer;
+    }
+
+    /// This function verifies the header and the redzones of the allocation and unlinks the header
+    /// from the list of the live allocations. The lock of the list is released before a corruption
+    /// is reported, so the report can allocate.
+    unsafe fn release_allocation(&self, address: usize, size: usize) {
+        let header_pointer = header_of(address);
+        let header = header_pointer.read();
+        if header.magic != ALLOCATION_MAGIC || header.size != size {
+            panic!(
+                "Corrupted header of heap block 0x{:X} (expected {} bytes, found {} bytes with \
+                 magic 0x{:X})",
+                address, size, header.size, header.magic
+            );
+        }
+        if let Some(corruption) = find_redzone_corruption(address, &header) {
+            report_corruption(address, &header, corruption);
+        }
+
+        let mut allocations = self.allocations.lock();
+        match header.previous.as_mut() {
+            Some(previous) => previous.next = header.next,
+            None => allocations.head = header.next,
+        }
+        if let Some(next) = header.next.as_mut() {
+            next.previous = header.previous;
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !REDZONE_ENABLED {
+            return self.allocate_block(layout);
+        }
+
+        let (block_layout, offset) = Self::redzone_layout(layout);
+        let block = self.allocate_block(block_layout);
+        if block.is_null() {
+            return block;
+        }
+        let address = block as usize + offset;
+        self.guard_allocation(address, layout.size());
+        address as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !REDZONE_ENABLED {
+            return self.free_block(ptr, layout);
+        }
+
+        let (block_layout, offset) = Self::redzone_layout(layout);
+        self.release_allocation(ptr as usize, layout.size());
+        self.free_block(ptr.sub(offset), block_layout);
+    }
+}
+
+/// The location of a changed byte in a redzone
+struct RedzoneCorruption {
+    address: usize,
+    found: u8,
+}
+
+/// This function returns the header in front of the front redzone of the specified allocation
+#[inline]
+fn header_of(address: usize) -> *mut AllocationHeader {
+    (address - REDZONE_SIZE - mem::size_of::<AllocationHeader>()) as *mut AllocationHeader
+}
+
+#[inline]
+unsafe fn redzone(address: usize) -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(address as *mut u8, REDZONE_SIZE)
+}
+
+/// This function returns the first changed byte in the redzones of the specified allocation
+unsafe fn find_redzone_corruption(
+    address: usize, header: &AllocationHeader,
+) -> Option<RedzoneCorruption> {
+    [address - REDZONE_SIZE, address + header.size]
+        .into_iter()
+        .find_map(|start| {
+            let redzone = redzone(start);
+            let offset = redzone.iter().position(|byte| *byte != REDZONE_BYTE)?;
+            Some(RedzoneCorruption {
+                address: start + offset,
+                found: redzone[offset],
+            })
+        })
+}
+
+/// This function reports the corrupted redzone with the backtrace, which was captured when the
+/// block was allocated, and panics
+fn report_corruption(address: usize, header: &AllocationHeader, corruption: RedzoneCorruption) -> ! {
+    let (direction, distance) = if corruption.address < address {
+        ("before", address - corruption.address)
+    } else {
+        ("after", corruption.address - (address + header.size) + 1)
+    };
+    error!("Heap block 0x{:X} ({} bytes) was allocated at:\n", address, header.size);
+    for return_address in header
+        .backtrace
+        .iter()
+        .take_while(|return_address| **return_address != 0)
+    {
+        match symbols::resolve_address(*return_address) {
+            Some((name, offset)) => error!("  0x{:X} ({}+0x{:X})\n", return_address, name, offset),
+            None => error!("  0x{:X}\n", return_address),
+        }
+    }
+    panic!(
+        "Heap buffer overflow at 0x{:X}, {} bytes {} block 0x{:X} (expected redzone 0x{:02X}, found \
+         0x{:02X})",
+        corruption.address, distance, direction, address, REDZONE_BYTE, corruption.found
+    );
+}
+
+/// This function hands the statically reserved heap memory to the kernel allocator. It must be
+/// called once before the first allocation. In debug builds, the heap memory is poisoned, so every
+/// allocation can be checked for writes after free.
+pub fn init_heap() {
+    unsafe {
+        if poison::POISON_ENABLED {
+            poison::poison(&mut HEAP_MEMORY.0);
+        }
+        KernelHeap::add_free_region(
+            &mut ALLOCATOR.head.lock(),
+            HEAP_MEMORY.0.as_mut_ptr() as usize,
+            HEAP_SIZE,
+        )
+    };
+}
+
+/// This function returns, whether the heap is locked. The panic handler checks it before it
+/// allocates, because the panicking code may hold the lock.
+pub fn is_locked() -> bool {
+    ALLOCATOR.head.is_locked() || ALLOCATOR.allocations.is_locked()
+}
+
+/// This function returns the allocation statistics of the kernel heap
+pub fn heap_report() -> AllocationReport {
+    HEAP_STATS.report()
+}
+
+/// This function verifies the redzones of all live allocations and returns the number of verified
+/// allocations. A corruption is reported with the backtrace of the allocation and panics. In
+/// release builds, the allocations have no redzones and this function returns zero.
+pub fn verify_redzones() -> usize {
+    if !REDZONE_ENABLED {
+        return 0;
+    }
+
+    let allocations = ALLOCATOR.allocations.lock();
+    let mut current = allocations.head;
+    let mut count = 0;
+    while let Some(header) = unsafe { current.as_ref() } {
+        let address = current as usize + mem::size_of::<AllocationHeader>() + REDZONE_SIZE;
+        if header.magic != ALLOCATION_MAGIC {
+            drop(allocations);
+            panic!("Corrupted header of live heap block 0x{:X}", address);
+        }
+        if let Some(corruption) = unsafe { find_redzone_corruption(address, header) } {
+            let header = *header;
+            drop(allocations);
+            report_corruption(address, &header, corruption);
+        }
+        current = header.next;
+        count += 1;
+    }
+    count
+}
+
+/// This function verifies the redzones of all live allocations periodically, so overflows of
+/// long-living buffers are detected before they are freed
+pub async fn check_redzones() {
+    if !REDZONE_ENABLED {
+        return;
+    }
+
+    loop {
+        timer::sleep(REDZONE_CHECK_INTERVAL).await;
+        verify_redzones();
+    }
+}
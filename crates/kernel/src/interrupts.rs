@@ -0,0 +1,169 @@
+use crate::{
+    diagnostics,
+    kprintf,
+    pic,
+    symbols,
+    vmm,
+};
+use libcore::{
+    address::VirtAddr,
+    descriptors::{
+        load_idt,
+        read_cs,
+        read_idtr,
+        DescriptorTablePointer,
+        GateDescriptor,
+        GATE_INTERRUPT,
+    },
+    registers::read_cr2,
+};
+
+const PAGE_FAULT_VECTOR: u8 = 14;
+
+/// The bits of the error code, which is pushed by the CPU on a page fault
+const PAGE_FAULT_PRESENT: u64 = 1 << 0;
+const PAGE_FAULT_WRITE: u64 = 1 << 1;
+const PAGE_FAULT_USER: u64 = 1 << 2;
+const PAGE_FAULT_RESERVED: u64 = 1 << 3;
+const PAGE_FAULT_INSTRUCTION: u64 = 1 << 4;
+
+#[repr(C, align(16))]
+struct InterruptDescriptorTable([[u64; 2]; 256]);
+
+static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable([[0; 2]; 256]);
+
+/// The names of the drivers, which installed a handler for the IRQ
+static mut IRQ_HANDLERS: [Option<&'static str>; pic::IRQ_COUNT as usize] =
+    [None; pic::IRQ_COUNT as usize];
+
+/// The stack frame, which is pushed by the CPU before an interrupt handler is called
+#[repr(C)]
+#[derive(Debug)]
+pub struct InterruptStackFrame {
+    pub instruction_pointer: u64,
+    pub code_segment: u64,
+    pub cpu_flags: u64,
+    pub stack_pointer: u64,
+    pub stack_segment: u64,
+}
+
+/// This function creates the IDT of the kernel with the exception handlers and loads it. The gates of
+/// the firmware are taken over for all vectors, which aren't handled by the kernel yet. The kernel
+/// GDT must be loaded before.
+pub fn init_idt() {
+    // The firmware gates reference the code segment of the firmware GDT, so the selector is
+    // replaced with the code selector of the kernel GDT
+    let firmware_idt = read_idtr();
+    let gate_count = (firmware_idt.size() / 16).min(256);
+    for vector in 0..gate_count {
+        let mut gate =
+            unsafe { ((firmware_idt.base as *const [u64; 2]).add(vector)).read_unaligned() };
+        gate[0] = (gate[0] & !(0xFFFF << 16)) | (read_cs() as u64) << 16;
+        unsafe { IDT.0[vector] = gate };
+    }
+
+    set_handler(PAGE_FAULT_VECTOR, page_fault_handler as u64);
+    unsafe {
+        load_idt(&DescriptorTablePointer {
+            limit: (core::mem::size_of::<InterruptDescriptorTable>() - 1) as u16,
+            base: IDT.0.as_ptr() as u64,
+        })
+    };
+}
+
+/// This function installs the handler of the specified IRQ of the legacy PICs. The handler has to
+/// acknowledge the IRQ with [pic::end_of_interrupt]. The name identifies the driver in `lsirq`.
+pub fn set_irq_handler(irq: u8, name: &'static str, handler: u64) {
+    set_handler(pic::PIC1_OFFSET + irq, handler);
+    unsafe { IRQ_HANDLERS[irq as usize] = Some(name) };
+}
+
+/// This function returns the IRQs, for which a handler is installed, with the name of the driver
+pub fn irq_handlers() -> impl Iterator<Item = (u8, &'static str)> {
+    (0..pic::IRQ_COUNT)
+        .filter_map(|irq| unsafe { IRQ_HANDLERS[irq as usize] }.map(|name| (irq, name)))
+}
+
+/// This function installs the handler of the specified CPU exception. The handler is an assembly
+/// entry, if it needs more than the stack frame of the CPU.
+pub fn set_exception_handler(vector: u8, handler: u64) {
+    set_handler(vector, handler);
+}
+
+/// This function installs the handler of a vector, which was allocated for message signaled
+/// interrupts by [crate::msi::allocate_vector]
+pub fn set_msi_handler(vector: u8, handler: u64) {
+    set_handler(vector, handler);
+}
+
+fn set_handler(vector: u8, handler: u64) {
+    let gate = GateDescriptor {
+        vector,
+        handler,
+        selector: read_cs(),
+        ist: 0,
+        kind: GATE_INTERRUPT,
+        dpl: 0,
+        present: true,
+    };
+    unsafe { IDT.0[vector as usize] = gate.encode() };
+}
+
+/// The page fault handler resolves writes to copy-on-write pages. For all other faults, the reason
+/// of the fault and the page table entries, which map the faulting address, are printed, so mapping
+/// bugs can be found without a debugger.
+extern "x86-interrupt" fn page_fault_handler(frame: InterruptStackFrame, error_code: u64) {
+    let address = read_cr2();
+
+    // Copy the page on writes to copy-on-write pages and continue the execution
+    if error_code & (PAGE_FAULT_PRESENT | PAGE_FAULT_WRITE | PAGE_FAULT_RESERVED)
+        == PAGE_FAULT_PRESENT | PAGE_FAULT_WRITE
+    {
+        if let Some(address) = VirtAddr::try_new(address) {
+            if vmm::handle_copy_on_write_fault(address) {
+                return;
+            }
+        }
+    }
+
+    let access = match error_code {
+        code if code & PAGE_FAULT_INSTRUCTION != 0 => "Instruction fetch",
+        code if code & PAGE_FAULT_WRITE != 0 => "Write",
+        _ => "Read",
+    };
+    let reason = match error_code {
+        code if code & PAGE_FAULT_RESERVED != 0 => "reserved bit set",
+        code if code & PAGE_FAULT_PRESENT != 0 => "protection violation",
+        _ => "page not present",
+    };
+    kprintf!(
+        "[Error] Page Fault: %s of 0x%X from %s mode (%s, Error Code: 0x%X)\n",
+        access,
+        address,
+        if error_code & PAGE_FAULT_USER != 0 {
+            "user"
+        } else {
+            "kernel"
+        },
+        reason,
+        error_code
+    );
+
+    match symbols::resolve_address(frame.instruction_pointer) {
+        Some((name, offset)) => {
+            kprintf!(
+                "[Error]  => Instruction at %s+0x%X (0x%X)\n",
+                name,
+                offset,
+                frame.instruction_pointer
+            )
+        }
+        None => kprintf!("[Error]  => Instruction at 0x%X\n", frame.instruction_pointer),
+    }
+
+    match VirtAddr::try_new(address) {
+        Some(address) => diagnostics::dump_mapping(address),
+        None => kprintf!("[Error]  => Address 0x%X isn't canonical\n", address),
+    }
+    panic!("Unhandled page fault at 0x{:X}", address);
+}
@@ -0,0 +1,409 @@
+//! The IOMMU driver detects the VT-d remapping units, which are described by the DMAR table. The
+//! firmware can leave the DMA remapping of a unit enabled, so the DMA of the devices would be
+//! translated by the tables of the firmware. Every enabled unit is configured with one domain, which
+//! contains all devices and passes their DMA through untranslated. Units without the pass-through
+//! translation type get second-level page tables, which map the physical memory identically. With
+//! the `iommu` option, the units are also configured and enabled, if the firmware left them disabled.
+//!
+//! All buses share one context table, so isolation domains can be added later by giving the devices
+//! their own context entries.
+
+use crate::{
+    dma::DmaBuffer,
+    error::Error,
+    frames,
+    timer,
+};
+use alloc::vec::Vec;
+use core::arch::asm;
+use libacpi::{
+    acpi_tables,
+    dmar::DmarEntry,
+    Dmar,
+};
+use libcore::{
+    address::PhysAddr,
+    paging::{
+        PageTable,
+        PAGE_SIZE,
+    },
+    pat::CacheType,
+};
+use libsync::Spinlock;
+use log::info;
+
+/// The option `iommu`, which enables the remapping units, even if the firmware left them disabled
+pub const IOMMU_OPTION: &str = "iommu";
+
+const REG_VERSION: u64 = 0x00;
+const REG_CAPABILITY: u64 = 0x08;
+const REG_EXTENDED_CAPABILITY: u64 = 0x10;
+const REG_GLOBAL_COMMAND: u64 = 0x18;
+const REG_GLOBAL_STATUS: u64 = 0x1C;
+const REG_ROOT_TABLE_ADDRESS: u64 = 0x20;
+const REG_CONTEXT_COMMAND: u64 = 0x28;
+
+const TRANSLATION_ENABLE: u32 = 1 << 31;
+const SET_ROOT_TABLE_POINTER: u32 = 1 << 30;
+const WRITE_BUFFER_FLUSH: u32 = 1 << 27;
+const QUEUED_INVALIDATION_ENABLE: u32 = 1 << 26;
+const INTERRUPT_REMAPPING_ENABLE: u32 = 1 << 25;
+
+/// The status bits, which are written back with every command. The one-shot bits must be zero.
+const PERSISTENT_STATUS: u32 = 1 << 31 | 1 << 26 | 1 << 25 | 1 << 23;
+
+const CAP_WRITE_BUFFER_FLUSH: u64 = 1 << 4;
+const CAP_2M_PAGES: u64 = 1 << 34;
+const CAP_1G_PAGES: u64 = 1 << 35;
+const ECAP_COHERENT: u64 = 1 << 0;
+const ECAP_PASS_THROUGH: u64 = 1 << 6;
+
+/// The global invalidation of the context-cache and the IOTLB. Bit 63 stays set, until the
+/// invalidation is completed.
+const INVALIDATE_BUSY: u64 = 1 << 63;
+const INVALIDATE_CONTEXT_GLOBAL: u64 = INVALIDATE_BUSY | 1 << 61;
+const INVALIDATE_IOTLB_GLOBAL: u64 = INVALIDATE_BUSY | 1 << 60;
+
+const ENTRY_PRESENT: u64 = 1 << 0;
+const TRANSLATION_PASS_THROUGH: u64 = 0b10 << 2;
+const PAGE_READ_WRITE: u64 = 0b11;
+const PAGE_LARGE: u64 = 1 << 7;
+
+/// The domain, which contains all devices
+const DEFAULT_DOMAIN: u64 = 1;
+
+/// The tables are allocated below the smallest address width of the remapping hardware (39 bits)
+const TABLE_ADDRESS_LIMIT: u64 = 1 << 39;
+
+/// The identity mapping covers at least the memory below 4 GiB and at most one page directory
+/// pointer table (512 GiB)
+const MIN_IDENTITY_SIZE: u64 = 4 << 30;
+const MAX_IDENTITY_SIZE: u64 = 512 << 30;
+
+/// The number of timer ticks (milliseconds), in which the unit has to complete a command
+const COMMAND_TIMEOUT: u64 = 100;
+
+/// The tables of the configured units, which must stay allocated while the translation is enabled
+static REMAPPING_TABLES: Spinlock<Vec<DmaBuffer>> = Spinlock::new(Vec::new());
+
+crate::driver!("iommu", ["acpi", "timer"], |boot_info| {
+    init_iommu(boot_info.command_line().has_flag(IOMMU_OPTION))
+});
+
+/// This function logs the remapping units and reserved memory regions of the DMAR table and
+/// configures the units, which were left enabled by the firmware (or all units, if forced). Systems
+/// without DMAR table have no remapping hardware, so they're skipped without error.
+pub fn init_iommu(force: bool) -> Result<(), Error> {
+    let Ok(dmar) = acpi_tables()?.table::<Dmar>() else {
+        info!("IOMMU: No DMAR table was found, DMA isn't remapped\n");
+        return Ok(());
+    };
+    info!(
+        "DMAR: Host address width {} bits, interrupt remapping {}\n",
+        dmar.host_address_width(),
+        if dmar.interrupt_remapping() {
+            "supported"
+        } else {
+            "unsupported"
+        }
+    );
+
+    for entry in dmar.entries() {
+        match entry? {
+            DmarEntry::RemappingUnit {
+                segment,
+                register_base,
+                include_pci_all,
+                device_scope_count,
+            } => {
+                let unit = RemappingUnit::new(PhysAddr::new(register_base));
+                let version = unit.read_u32(REG_VERSION);
+                let enabled = unit.status() & TRANSLATION_ENABLE != 0;
+                info!(
+                    "DMAR: Remapping unit at 0x{:X} (Segment {}, {} device scopes{}, Version {}.{}, \
+                     {} domains, pass-through {}, {})\n",
+                    register_base,
+                    segment,
+                    device_scope_count,
+                    if include_pci_all {
+                        " and all other devices"
+                    } else {
+                        ""
+                    },
+                    (version >> 4) & 0xF,
+                    version & 0xF,
+                    1u64 << (4 + 2 * (unit.capability() & 0x7)),
+                    if unit.supports_pass_through() {
+                        "supported"
+                    } else {
+                        "unsupported"
+                    },
+                    if enabled {
+                        "enabled by the firmware"
+                    } else {
+                        "disabled"
+                    }
+                );
+
+                if enabled || force {
+                    let tables = unit.enable_default_domain()?;
+                    REMAPPING_TABLES.lock().push(tables);
+                }
+            }
+            DmarEntry::ReservedMemory {
+                segment,
+                base_address,
+                limit_address,
+            } => {
+                info!(
+                    "DMAR: Reserved memory 0x{:X}-0x{:X} (Segment {})\n",
+                    base_address, limit_address, segment
+                );
+            }
+            DmarEntry::Unknown(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The registers of a VT-d remapping unit, which are accessed through the physmap
+struct RemappingUnit {
+    register_base: PhysAddr,
+}
+
+impl RemappingUnit {
+    #[inline]
+    fn new(register_base: PhysAddr) -> Self {
+        Self { register_base }
+    }
+
+    #[inline]
+    fn read_u32(&self, register: u64) -> u32 {
+        unsafe {
+            (self.register_base + register)
+                .to_virt()
+                .as_ptr::<u32>()
+                .read_volatile()
+        }
+    }
+
+    #[inline]
+    fn write_u32(&self, register: u64, value: u32) {
+        unsafe {
+            (self.register_base + register)
+                .to_virt()
+                .as_mut_ptr::<u32>()
+                .write_volatile(value)
+        }
+    }
+
+    #[inline]
+    fn read_u64(&self, register: u64) -> u64 {
+        unsafe {
+            (self.register_base + register)
+                .to_virt()
+                .as_ptr::<u64>()
+                .read_volatile()
+        }
+    }
+
+    #[inline]
+    fn write_u64(&self, register: u64, value: u64) {
+        unsafe {
+            (self.register_base + register)
+                .to_virt()
+                .as_mut_ptr::<u64>()
+                .write_volatile(value)
+        }
+    }
+
+    #[inline]
+    fn capability(&self) -> u64 {
+        self.read_u64(REG_CAPABILITY)
+    }
+
+    #[inline]
+    fn extended_capability(&self) -> u64 {
+        self.read_u64(REG_EXTENDED_CAPABILITY)
+    }
+
+    #[inline]
+    fn status(&self) -> u32 {
+        self.read_u32(REG_GLOBAL_STATUS)
+    }
+
+    #[inline]
+    fn supports_pass_through(&self) -> bool {
+        self.extended_capability() & ECAP_PASS_THROUGH != 0
+    }
+
+    /// This function sets or clears the specified bit of the global command register. The register
+    /// has no memory, so the other persistent bits are written with their current status.
+    fn global_command(&self, command: u32, set: bool) {
+        let status = self.status() & PERSISTENT_STATUS;
+        let value = if set {
+            status | command
+        } else {
+            status & !command
+        };
+        self.write_u32(REG_GLOBAL_COMMAND, value);
+    }
+
+    /// This function waits, until the condition is true or the command timed out
+    fn wait(&self, operation: &'static str, condition: impl Fn(&Self) -> bool) -> Result<(), Error> {
+        let deadline = timer::ticks() + COMMAND_TIMEOUT;
+        while !condition(self) {
+            if timer::ticks() >= deadline {
+                return Err(Error::IommuTimeout(operation));
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    /// This function returns the address width value of the context entries and the number of page
+    /// table levels of the largest supported address width
+    fn address_width(&self) -> Result<(u64, usize), Error> {
+        let supported_widths = (self.capability() >> 8) & 0x1F;
+        match supported_widths {
+            widths if widths & 0b100 != 0 => Ok((2, 4)),
+            widths if widths & 0b010 != 0 => Ok((1, 3)),
+            widths => Err(Error::UnsupportedAddressWidth(widths)),
+        }
+    }
+
+    /// This function creates the tables of the default domain, which contains all devices of the
+    /// unit, and enables the translation with them. The queued invalidation of the firmware is
+    /// disabled, because the caches are invalidated with the registers.
+    fn enable_default_domain(&self) -> Result<DmaBuffer, Error> {
+        if self.status() & INTERRUPT_REMAPPING_ENABLE != 0 {
+            return Err(Error::InterruptRemappingEnabled(self.register_base.as_u64()));
+        }
+
+        let tables = self.create_tables()?;
+        if self.extended_capability() & ECAP_COHERENT == 0 {
+            unsafe { asm!("wbinvd", options(nostack)) };
+        }
+
+        if self.status() & TRANSLATION_ENABLE != 0 {
+            self.global_command(TRANSLATION_ENABLE, false);
+            self.wait("translation disable", |unit| unit.status() & TRANSLATION_ENABLE == 0)?;
+        }
+        if self.status() & QUEUED_INVALIDATION_ENABLE != 0 {
+            self.global_command(QUEUED_INVALIDATION_ENABLE, false);
+            self.wait("queued invalidation disable", |unit| {
+                unit.status() & QUEUED_INVALIDATION_ENABLE == 0
+            })?;
+        }
+
+        self.write_u64(REG_ROOT_TABLE_ADDRESS, tables.physical_address().as_u64());
+        self.global_command(SET_ROOT_TABLE_POINTER, true);
+        self.wait("root table update", |unit| unit.status() & SET_ROOT_TABLE_POINTER != 0)?;
+
+        if self.capability() & CAP_WRITE_BUFFER_FLUSH != 0 {
+            self.global_command(WRITE_BUFFER_FLUSH, true);
+            self.wait("write buffer flush", |unit| unit.status() & WRITE_BUFFER_FLUSH == 0)?;
+        }
+
+        self.write_u64(REG_CONTEXT_COMMAND, INVALIDATE_CONTEXT_GLOBAL);
+        self.wait("context-cache invalidation", |unit| {
+            unit.read_u64(REG_CONTEXT_COMMAND) & INVALIDATE_BUSY == 0
+        })?;
+
+        // The IOTLB registers are located at the offset, which is reported in 16-byte units
+        let iotlb_register = ((self.extended_capability() >> 8) & 0x3FF) * 16 + 8;
+        self.write_u64(iotlb_register, INVALIDATE_IOTLB_GLOBAL);
+        self.wait("IOTLB invalidation", |unit| unit.read_u64(iotlb_register) & INVALIDATE_BUSY == 0)?;
+
+        self.global_command(TRANSLATION_ENABLE, true);
+        self.wait("translation enable", |unit| unit.status() & TRANSLATION_ENABLE != 0)?;
+        info!(
+            "DMAR: Enabled remapping unit at 0x{:X} with {} domain\n",
+            self.register_base,
+            if self.supports_pass_through() {
+                "pass-through"
+            } else {
+                "identity-mapped"
+            }
+        );
+        Ok(tables)
+    }
+
+    /// This function creates the root table, the context table and (without pass-through support)
+    /// the identity-mapped page tables in one buffer. Every bus references the same context table,
+    /// which assigns all devices to the default domain.
+    fn create_tables(&self) -> Result<DmaBuffer, Error> {
+        let (address_width, levels) = self.address_width()?;
+        let capability = self.capability();
+        let pass_through = self.supports_pass_through();
+        if !pass_through && capability & (CAP_2M_PAGES | CAP_1G_PAGES) == 0 {
+            return Err(Error::UnsupportedRemappingUnit(self.register_base.as_u64()));
+        }
+
+        let gigabytes = frames::physical_memory_end()
+            .map_or(0, |address| address.as_u64())
+            .clamp(MIN_IDENTITY_SIZE, MAX_IDENTITY_SIZE)
+            .div_ceil(1 << 30);
+        let huge_pages = capability & CAP_1G_PAGES != 0;
+
+        // The root table and the context table are followed by the tables above the page directories
+        // and the page directories, if no 1 GiB pages are supported
+        let page_count = match pass_through {
+            true => 2,
+            false if huge_pages => levels,
+            false => levels + gigabytes as usize,
+        };
+        let tables = DmaBuffer::allocate(
+            page_count * PAGE_SIZE as usize,
+            PhysAddr::new(TABLE_ADDRESS_LIMIT),
+            PAGE_SIZE,
+            CacheType::WriteBack,
+        )?;
+
+        let base = tables.physical_address();
+        let page = |index: usize| base + index as u64 * PAGE_SIZE;
+        let (root_table, context_table) = unsafe { (table(page(0)), table(page(1))) };
+
+        // The entries of the root and context tables are 128 bits wide
+        for bus in 0..256 {
+            root_table.entries[2 * bus] = page(1).as_u64() | ENTRY_PRESENT;
+        }
+        let translation = match pass_through {
+            true => TRANSLATION_PASS_THROUGH,
+            false => page(2).as_u64(),
+        };
+        for function in 0..256 {
+            context_table.entries[2 * function] = translation | ENTRY_PRESENT;
+            context_table.entries[2 * function + 1] = address_width | DEFAULT_DOMAIN << 8;
+        }
+        if pass_through {
+            return Ok(tables);
+        }
+
+        // Map the physical memory with 1 GiB pages or 2 MiB pages
+        let pdpt_index = if levels == 4 { 3 } else { 2 };
+        if levels == 4 {
+            unsafe { table(page(2)) }.entries[0] = page(3).as_u64() | PAGE_READ_WRITE;
+        }
+        let pdpt = unsafe { table(page(pdpt_index)) };
+        for gigabyte in 0..gigabytes as usize {
+            let address = (gigabyte as u64) << 30;
+            if huge_pages {
+                pdpt.entries[gigabyte] = address | PAGE_READ_WRITE | PAGE_LARGE;
+                continue;
+            }
+
+            let directory = page(pdpt_index + 1 + gigabyte);
+            pdpt.entries[gigabyte] = directory.as_u64() | PAGE_READ_WRITE;
+            for (index, entry) in unsafe { table(directory) }.entries.iter_mut().enumerate() {
+                *entry = (address + ((index as u64) << 21)) | PAGE_READ_WRITE | PAGE_LARGE;
+            }
+        }
+        Ok(tables)
+    }
+}
+
+#[inline]
+unsafe fn table(address: PhysAddr) -> &'static mut PageTable {
+    &mut *address.to_virt().as_mut_ptr::<PageTable>()
+}
@@ -0,0 +1,296 @@
+use crate::{
+    error::Error,
+    interrupts::{
+        set_irq_handler,
+        InterruptStackFrame,
+    },
+    pic,
+    timer,
+};
+use core::sync::atomic::{
+    AtomicBool,
+    AtomicU8,
+    Ordering,
+};
+use libcore::{
+    cmdline::CommandLine,
+    keymap::{
+        keymap_by_name,
+        KeyCode,
+        KeyboardState,
+        US,
+    },
+    port::{
+        read_u8,
+        write_u8,
+    },
+    registers::wait_for_interrupt,
+};
+use libsync::{
+    Spinlock,
+    SpscRing,
+    WaitRing,
+};
+use log::warn;
+
+/// The option, which selects the keymap of the keyboard (like `keymap=de`)
+pub const KEYMAP_OPTION: &str = "keymap";
+
+const DATA_PORT: u16 = 0x60;
+const COMMAND_PORT: u16 = 0x64;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+const COMMAND_PULSE_RESET: u8 = 0xFE;
+const KEYBOARD_IRQ: u8 = 1;
+
+/// The commands of the keyboard and the responses to every command byte
+const KEYBOARD_SET_LEDS: u8 = 0xED;
+const KEYBOARD_SCANCODE_SET: u8 = 0xF0;
+const KEYBOARD_SET_TYPEMATIC: u8 = 0xF3;
+const RESPONSE_ACK: u8 = 0xFA;
+const RESPONSE_RESEND: u8 = 0xFE;
+const MAX_RESENDS: usize = 3;
+const RESPONSE_TIMEOUT: u64 = 50;
+
+/// The typematic rates in tenths of characters per second, which are selected by the lower 5 bits of
+/// the typematic byte
+#[rustfmt::skip]
+const TYPEMATIC_RATES: [u16; 32] = [
+    300, 267, 240, 218, 200, 185, 171, 160, 150, 133, 120, 109, 100, 92, 86, 80,
+    75, 67, 60, 55, 50, 46, 43, 40, 37, 33, 30, 27, 25, 23, 21, 20,
+];
+
+/// The scancodes are pushed by the interrupt handler and decoded by the consumer, so the handler
+/// doesn't need to lock the keyboard state. Scancodes, which don't fit into the ring, are dropped.
+static SCANCODES: WaitRing<u8, 128> = WaitRing::new();
+static KEYBOARD_STATE: Spinlock<KeyboardState> = Spinlock::new(KeyboardState::new(&US));
+
+/// While a command is sent, the received bytes are responses to the command instead of scancodes.
+/// Keys, which are pressed during a command, are lost.
+static COMMAND_PENDING: AtomicBool = AtomicBool::new(false);
+static RESPONSES: SpscRing<u8, 8> = SpscRing::new();
+static COMMAND_LOCK: Spinlock<()> = Spinlock::new(());
+
+/// The LEDs, which are currently shown by the keyboard
+static LED_STATE: AtomicU8 = AtomicU8::new(0);
+
+/// The lock key LEDs of the keyboard
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl Leds {
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            scroll_lock: bits & (1 << 0) != 0,
+            num_lock: bits & (1 << 1) != 0,
+            caps_lock: bits & (1 << 2) != 0,
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        (self.scroll_lock as u8) | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+}
+
+crate::driver!("keyboard", ["pic"], |boot_info| {
+    init_keyboard(&boot_info.command_line());
+    Ok(())
+});
+
+/// This function installs the interrupt handler of the PS/2 keyboard and unmasks the keyboard IRQ.
+/// The keymap is selected with the command line, the US keymap is used by default. The PICs must
+/// be initialized before.
+pub fn init_keyboard(command_line: &CommandLine) {
+    if let Some(name) = command_line.get(KEYMAP_OPTION) {
+        match keymap_by_name(name) {
+            Some(keymap) => KEYBOARD_STATE.lock().set_keymap(keymap),
+            None => warn!("Unknown keymap '{}', using the US keymap\n", name),
+        }
+    }
+
+    set_irq_handler(KEYBOARD_IRQ, "keyboard", keyboard_handler as u64);
+    pic::unmask_irq(KEYBOARD_IRQ);
+}
+
+extern "x86-interrupt" fn keyboard_handler(_frame: InterruptStackFrame) {
+    let scancode = unsafe { read_u8(DATA_PORT) };
+    if COMMAND_PENDING.load(Ordering::Acquire) {
+        let _ = RESPONSES.push(scancode);
+    } else {
+        let _ = SCANCODES.push(scancode);
+    }
+    pic::end_of_interrupt(KEYBOARD_IRQ);
+}
+
+/// This function waits for the next pressed key. The CPU is halted until the next interrupt, while
+/// no scancode is available.
+pub fn read_key() -> KeyCode {
+    loop {
+        while let Some(scancode) = SCANCODES.try_pop() {
+            if let Some(key) = process_scancode(scancode) {
+                return key;
+            }
+        }
+        unsafe { wait_for_interrupt() };
+    }
+}
+
+/// This function returns the next pressed key. The task is woken by the interrupt handler, when a
+/// scancode was received.
+pub async fn next_key() -> KeyCode {
+    loop {
+        let scancode = SCANCODES.pop().await;
+        if let Some(key) = process_scancode(scancode) {
+            return key;
+        }
+    }
+}
+
+/// This function decodes the scancode and updates the LEDs, if a lock key was pressed
+fn process_scancode(scancode: u8) -> Option<KeyCode> {
+    let (key, modifiers) = {
+        let mut state = KEYBOARD_STATE.lock();
+        (state.process(scancode), state.modifiers())
+    };
+
+    let leds = Leds {
+        scroll_lock: modifiers.scroll_lock,
+        num_lock: modifiers.num_lock,
+        caps_lock: modifiers.caps_lock,
+    };
+    if leds.bits() != LED_STATE.load(Ordering::Relaxed) {
+        if let Err(error) = update_leds(leds) {
+            warn!("Unable to update keyboard LEDs => {}\n", error);
+        }
+    }
+    key
+}
+
+/// This function returns the LEDs, which are currently shown by the keyboard
+pub fn leds() -> Leds {
+    Leds::from_bits(LED_STATE.load(Ordering::Relaxed))
+}
+
+/// This function sets the LEDs of the keyboard. The state of the lock keys is changed too, so the
+/// LEDs match the behavior of the keyboard.
+pub fn set_leds(leds: Leds) -> Result<(), Error> {
+    KEYBOARD_STATE
+        .lock()
+        .set_lock_keys(leds.caps_lock, leds.num_lock, leds.scroll_lock);
+    update_leds(leds)
+}
+
+fn update_leds(leds: Leds) -> Result<(), Error> {
+    send_command(KEYBOARD_SET_LEDS, Some(leds.bits()))?;
+    LED_STATE.store(leds.bits(), Ordering::Relaxed);
+    Ok(())
+}
+
+/// This function sets the delay until a held key is repeated (250 to 1000 ms in steps of 250 ms) and
+/// the repeat rate (2 to 30 characters per second). The nearest supported values are used.
+pub fn set_typematic(delay: u16, rate: u16) -> Result<(), Error> {
+    let delay_bits = (delay.clamp(250, 1000) + 125) / 250 - 1;
+    let rate_bits = TYPEMATIC_RATES
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, supported_rate)| supported_rate.abs_diff(rate.saturating_mul(10)))
+        .map_or(0, |(index, _)| index);
+    send_command(KEYBOARD_SET_TYPEMATIC, Some((delay_bits as u8) << 5 | rate_bits as u8))
+}
+
+/// This function returns the active scancode set of the keyboard
+pub fn scancode_set() -> Result<u8, Error> {
+    with_command_mode(|| {
+        write_command_byte(KEYBOARD_SCANCODE_SET)?;
+        write_command_byte(0)?;
+
+        // Some controllers translate the response like a scancode
+        match read_response()? {
+            1 | 0x43 => Ok(1),
+            2 | 0x41 => Ok(2),
+            3 | 0x3F => Ok(3),
+            response => Err(Error::UnexpectedKeyboardResponse(response)),
+        }
+    })
+}
+
+/// This function selects the scancode set of the keyboard. The controller translates set 2 into set
+/// 1, which is decoded by the kernel, so other sets are only useful with disabled translation.
+pub fn set_scancode_set(set: u8) -> Result<(), Error> {
+    if !(1..=3).contains(&set) {
+        return Err(Error::InvalidScancodeSet(set));
+    }
+    send_command(KEYBOARD_SCANCODE_SET, Some(set))
+}
+
+/// This function sends the command with the optional data byte to the keyboard
+fn send_command(command: u8, data: Option<u8>) -> Result<(), Error> {
+    with_command_mode(|| {
+        write_command_byte(command)?;
+        match data {
+            Some(data) => write_command_byte(data),
+            None => Ok(()),
+        }
+    })
+}
+
+/// This function redirects the received bytes into the response ring, while the function is
+/// executed. Only one command is sent at the same time.
+fn with_command_mode<T>(function: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    let _guard = COMMAND_LOCK.lock();
+    while RESPONSES.pop().is_some() {}
+    COMMAND_PENDING.store(true, Ordering::Release);
+    let result = function();
+    COMMAND_PENDING.store(false, Ordering::Release);
+    result
+}
+
+/// This function writes a byte of a command and waits for the acknowledgement. The byte is sent
+/// again, if the keyboard requests it.
+fn write_command_byte(byte: u8) -> Result<(), Error> {
+    for _ in 0..MAX_RESENDS {
+        unsafe {
+            while read_u8(COMMAND_PORT) & STATUS_INPUT_FULL != 0 {
+                core::hint::spin_loop();
+            }
+            write_u8(DATA_PORT, byte);
+        }
+
+        match read_response()? {
+            RESPONSE_ACK => return Ok(()),
+            RESPONSE_RESEND => continue,
+            response => return Err(Error::UnexpectedKeyboardResponse(response)),
+        }
+    }
+    Err(Error::UnexpectedKeyboardResponse(RESPONSE_RESEND))
+}
+
+/// This function waits for the next response of the keyboard. The timer must be initialized.
+fn read_response() -> Result<u8, Error> {
+    let deadline = timer::ticks() + RESPONSE_TIMEOUT;
+    loop {
+        if let Some(response) = RESPONSES.pop() {
+            return Ok(response);
+        }
+        if timer::ticks() >= deadline {
+            return Err(Error::KeyboardTimeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// This function resets the system by pulsing the reset line of the PS/2 controller. This function
+/// only returns, if the system wasn't reset.
+pub fn reset_system() {
+    // The reset is requested, so the log isn't offered in the boot menu of the next boot
+    libgraphics::log::close_persistent_log();
+    unsafe {
+        while read_u8(COMMAND_PORT) & STATUS_INPUT_FULL != 0 {
+            core::hint::spin_loop();
+        }
+        write_u8(COMMAND_PORT, COMMAND_PULSE_RESET);
+    }
+}
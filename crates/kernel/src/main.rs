@@ -0,0 +1,367 @@
+#![no_std]
+#![no_main]
+#![feature(panic_info_message)]
+#![feature(abi_x86_interrupt)]
+
+pub(crate) mod acpi;
+pub(crate) mod block;
+pub(crate) mod console;
+pub(crate) mod coredump;
+pub(crate) mod diagnostics;
+pub(crate) mod dma;
+pub(crate) mod driver;
+pub(crate) mod error;
+pub(crate) mod executor;
+pub(crate) mod ext2;
+pub(crate) mod fdt;
+pub(crate) mod fpu;
+pub(crate) mod frames;
+pub(crate) mod gdb;
+pub(crate) mod gdt;
+pub(crate) mod hardening;
+pub(crate) mod heap;
+pub(crate) mod interrupts;
+pub(crate) mod iommu;
+pub(crate) mod keyboard;
+pub(crate) mod module;
+pub(crate) mod mouse;
+pub(crate) mod msi;
+pub(crate) mod net;
+pub(crate) mod page_cache;
+pub(crate) mod pci;
+pub(crate) mod pic;
+pub(crate) mod process;
+pub(crate) mod profiler;
+pub(crate) mod serial;
+pub(crate) mod shell;
+pub(crate) mod symbols;
+pub(crate) mod syscall;
+pub(crate) mod testmode;
+pub(crate) mod timer;
+pub(crate) mod virtio_net;
+pub(crate) mod vmm;
+
+extern crate alloc;
+
+/// The number of bytes of the stack, which are dumped on a panic
+const PANIC_STACK_DUMP_SIZE: usize = 128;
+
+/// The boot information, which was decoded from the handoff of the bootloader
+static mut BOOT_INFO: Option<BootInfo> = None;
+
+libcore::build_stamp!();
+
+use crate::executor::Executor;
+use core::{
+    arch::x86_64::_rdtsc,
+    panic::PanicInfo,
+};
+use libcore::{
+    address::PhysAddr,
+    alternatives,
+    boot_info::BootInfo,
+    fastmem,
+    hexdump::{
+        mapped_slice,
+        HexDump,
+    },
+    registers::{
+        enable_interrupts,
+        read_rsp,
+    },
+    rng::init_random_generator,
+    stack_protector::{
+        init_stack_guard,
+        set_symbol_resolver,
+    },
+    trace::{
+        self,
+        TraceProcess,
+    },
+};
+use libcpu::halt_cpu;
+use log::{
+    error,
+    info,
+    warn,
+};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // Capture the registers first, so the core dump shows the state of the panic handler's caller
+    let registers = coredump::Registers::capture();
+    if let Some(message) = info.message() {
+        error!("Kernel Panic: {}\n", message);
+    }
+
+    if let Some(location) = info.location() {
+        error!(
+            " => Error found in {} on {}:{}\n",
+            location.file(),
+            location.line(),
+            location.column()
+        )
+    }
+
+    // Dump the top of the stack, if the stack pointer isn't corrupted
+    let stack_pointer = read_rsp();
+    if let Some(stack) = unsafe { mapped_slice(stack_pointer, PANIC_STACK_DUMP_SIZE) } {
+        error!(" => Stack at 0x{:X}:\n", stack_pointer);
+        for line in HexDump::new(stack_pointer, stack).lines() {
+            error!("{}\n", line);
+        }
+    }
+
+    if let Err(error) = coredump::write_core_dump(&registers) {
+        error!(" => Unable to write core dump => {}\n", error);
+    }
+
+    // Stop in the debugger, so the state of the kernel can be inspected
+    gdb::break_on_panic();
+    halt_cpu();
+}
+
+/// The entry of the kernel, which is entered by the trampoline of the bootloader on a dedicated stack
+/// with interrupts disabled and the physical address of the boot information in RDI
+#[no_mangle]
+pub extern "sysv64" fn _start(boot_info_address: PhysAddr) -> ! {
+    // Decode the boot information of the bootloader, a mismatch can't be reported without console
+    let boot_info: &'static BootInfo =
+        match unsafe { BootInfo::from_address(boot_info_address.to_virt()) } {
+            Ok(boot_info) => unsafe { BOOT_INFO.insert(boot_info) },
+            Err(_) => halt_cpu(),
+        };
+
+    // Continue the boot trace of the bootloader, if tracing is enabled
+    if !boot_info.trace_buffer_address.is_null() {
+        unsafe {
+            trace::attach_trace_buffer(boot_info.trace_buffer_address.to_virt(), TraceProcess::Kernel)
+        };
+    }
+
+    // Randomize the stack canary before any protected function is called
+    init_stack_guard();
+    set_symbol_resolver(symbols::resolve_address);
+    alternatives::apply_alternatives();
+
+    heap::init_heap();
+    serial::init_serial();
+
+    // Take over the framebuffer of the bootloader, the kernel has no log output without it
+    let console_result = console::init_console(&boot_info.framebuffer);
+
+    // Capture the log output for the test harness, if the kernel runs in test mode
+    testmode::init_test_mode(&boot_info.command_line());
+    if console_result.is_ok() {
+        testmode::assert_boot_milestone("console");
+    }
+
+    gdt::init_gdt();
+    interrupts::init_idt();
+    testmode::assert_boot_milestone("descriptor-tables");
+
+    // Wait for the debugger before the kernel is initialized, if the GDB stub is enabled
+    gdb::init_gdb(&boot_info.command_line());
+    coredump::init_coredump(boot_info);
+    info!("Welcome to OverflowOS Kernel v{}\n", env!("CARGO_PKG_VERSION"));
+    info!("Kernel build {}\n", BUILD_STAMP);
+    if boot_info.bootloader_stamp.is_present() {
+        info!("Bootloader build {}\n", boot_info.bootloader_stamp);
+    }
+    match console_result {
+        Ok(()) => {
+            info!(
+                "Initialized console on framebuffer at 0x{:X} ({}x{})\n",
+                boot_info.framebuffer.address,
+                boot_info.framebuffer.width,
+                boot_info.framebuffer.height
+            )
+        }
+        Err(error) => warn!("Unable to initialize console => {}\n", error),
+    }
+    info!(
+        "Kernel loaded at 0x{:X} ({} kB, Slide: 0x{:X})\n",
+        boot_info.kernel_address,
+        boot_info.kernel_size / 1024,
+        boot_info.kernel_slide
+    );
+    if boot_info.tls_template.is_present() {
+        info!(
+            "TLS template at 0x{:X} ({} bytes, Alignment: {})\n",
+            boot_info.tls_template.address,
+            boot_info.tls_template.memory_size,
+            boot_info.tls_template.alignment
+        );
+    }
+    for unit in boot_info.boot_units() {
+        info!(
+            "Boot unit '{}' available ({:?}, Protocol: {}, {} kB)\n",
+            unit.title(),
+            unit.role,
+            unit.protocol.name(),
+            unit.kernel_size / 1024
+        );
+    }
+
+    // Continue the persistent log of the bootloader, so the log survives a warm reset
+    if let Some(persistent_log) = boot_info.persistent_log().filter(|log| log.is_valid()) {
+        libgraphics::log::set_persistent_log(persistent_log);
+        info!(
+            "Took over persistent log at 0x{:X} ({} kB)\n",
+            boot_info.persistent_log_address,
+            boot_info.persistent_log_size / 1024
+        );
+    }
+    if boot_info.crash_kernel_region.page_count != 0 {
+        info!(
+            "Memory for crash kernel reserved at 0x{:X} ({} kB)\n",
+            boot_info.crash_kernel_region.address,
+            boot_info.crash_kernel_region.page_count * 4
+        );
+    }
+
+    // Use the Runtime Services, which were relocated into the runtime window by the bootloader
+    let runtime_services_address = boot_info.runtime_services_address;
+    if !runtime_services_address.is_null() {
+        match unsafe { libruntime::init_runtime_services(runtime_services_address.as_u64()) } {
+            Ok(runtime_services) => {
+                let (major, minor) = runtime_services.revision();
+                match runtime_services.time() {
+                    Ok((time, _)) => {
+                        info!(
+                            "Initialized UEFI {}.{} Runtime Services (Time: {})\n",
+                            major,
+                            minor / 10,
+                            time
+                        )
+                    }
+                    Err(error) => warn!("Unable to read the time of the firmware => {}\n", error),
+                }
+            }
+            Err(error) => warn!("Unable to initialize the Runtime Services => {}\n", error),
+        }
+    }
+
+    // The random generator provides the AT_RANDOM bytes of the user processes
+    let random_source = init_random_generator(unsafe { _rdtsc() });
+    info!("Initialized random number generator (Source: {})\n", random_source);
+
+    // Take over the frame allocator of the bootloader
+    if frames::init_frame_allocator(&boot_info.frame_allocator) {
+        testmode::assert_boot_milestone("frame-allocator");
+        let (allocated_frames, remaining_frames) = frames::physical_frames().unwrap_or_default();
+        info!(
+            "Took over frame allocator ({} frames allocated, {} frames remaining, {} reserved \
+             regions)\n",
+            allocated_frames,
+            remaining_frames,
+            boot_info.frame_allocator.reserved_regions().len()
+        );
+    } else {
+        warn!("No frame allocator was handed over by the bootloader\n");
+    }
+
+    // Dump the descriptor tables, which were handed over by the bootloader
+    if boot_info
+        .command_line()
+        .has_flag(diagnostics::DUMP_DESCRIPTORS_OPTION)
+    {
+        diagnostics::dump_descriptor_tables();
+    }
+
+    // Enable SMEP, SMAP and UMIP, if supported and not disabled
+    hardening::init_hardening(&boot_info.command_line());
+    syscall::init_syscalls();
+    testmode::assert_boot_milestone("syscalls");
+
+    // Enable FPU and SIMD state handling for the tasks of the executor
+    fpu::init_fpu(cfg!(feature = "lazy-fpu"));
+    let simd_level = fastmem::detect_simd_level();
+    info!(
+        "Initialized FPU (Save Area: {} bytes, SIMD: {:?}, Lazy Switching: {})\n",
+        fpu::save_area_size(),
+        simd_level,
+        cfg!(feature = "lazy-fpu")
+    );
+
+    // Select the variants of the hot routines again, the FPU configuration enables more extensions
+    alternatives::apply_alternatives();
+    for (routine, variant) in alternatives::alternatives() {
+        info!("Selected '{}' variant of '{}' routine\n", variant, routine);
+    }
+    testmode::assert_boot_milestone("fpu");
+
+    // Initialize the drivers (like the legacy PICs, the timer, the PS/2 devices and the ACPI tables)
+    // after their dependencies. A failed driver is reported, but doesn't stop the boot.
+    driver::init_drivers(boot_info);
+    unsafe { enable_interrupts() };
+    testmode::assert_boot_milestone("drivers");
+
+    // Mount the ext2 image of the initrd as root file system, if it's requested on the command line
+    ext2::init_root_file_system(boot_info);
+
+    // Sample the boot tasks with the profiler, if it's requested on the command line
+    profiler::init_profiler(&boot_info.command_line());
+
+    // Run the boot tasks concurrently, so tasks, which wait for devices, don't delay other tasks
+    let mut executor = Executor::new();
+    executor.spawn("modules", async move {
+        // Load kernel modules from initrd
+        if let Some(initrd) = boot_info.initrd() {
+            let loaded_modules = module::load_modules_from_initrd(&initrd);
+            info!("Loaded {} kernel modules from initrd\n", loaded_modules);
+        }
+    });
+
+    // Answer pings and stream the log output over the network, if an address is configured
+    match net::NetworkConfig::from_command_line(&boot_info.command_line()) {
+        Ok(Some(config)) => executor.spawn("network", net::run_network(config)),
+        Ok(None) => {}
+        Err(error) => {
+            warn!("Unable to configure network => {}\n", error)
+        }
+    }
+
+    // The shell and network tasks never finish, so the steps after the boot tasks are skipped
+    if boot_info.command_line().has_flag(shell::SHELL_OPTION) {
+        executor.spawn("shell", shell::run_shell(boot_info));
+        executor.spawn("cursor", mouse::track_cursor());
+        executor.spawn("text-cursor", console::run_text_cursor(boot_info.command_line()));
+        executor.spawn("redzones", heap::check_redzones());
+    }
+    executor.run();
+    testmode::assert_boot_milestone("boot-tasks");
+
+    // Verify the redzones of the heap once, the background check only runs with the shell
+    heap::verify_redzones();
+
+    // Dump the allocation statistics, which were collected while booting
+    if boot_info
+        .command_line()
+        .has_flag(diagnostics::DUMP_MEMORY_OPTION)
+    {
+        diagnostics::dump_memory_report();
+    }
+
+    // Dump the profile of the boot tasks, the shell shows the profile with the `profile` command
+    if profiler::is_running() {
+        profiler::stop_profiler();
+        profiler::dump_profile();
+    }
+
+    // Emit the boot trace of the bootloader and the kernel for `chrome://tracing`
+    if boot_info.command_line().has_flag(trace::TRACE_OPTION) {
+        diagnostics::dump_boot_trace();
+    }
+
+    // Dump the log capture for the test harness and exit QEMU with the result of the test run
+    testmode::finish_test_run();
+
+    // Power off the system instead of halting, if requested
+    if boot_info.command_line().has_flag(acpi::POWEROFF_OPTION) {
+        if let Err(error) = acpi::power_off() {
+            error!("Unable to power off the system => {}\n", error);
+        }
+    }
+    halt_cpu();
+}
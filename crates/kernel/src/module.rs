@@ -0,0 +1,345 @@
+use crate::{
+    error::Error,
+    symbols::lookup_symbol,
+};
+use alloc::{
+    string::{
+        String,
+        ToString,
+    },
+    vec,
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    ptr::NonNull,
+};
+use libcore::{
+    elf::{
+        apply_relocation,
+        ElfFile,
+        SectionHeader,
+        Symbol,
+        ET_REL,
+        SHF_ALLOC,
+        SHN_ABS,
+        SHN_UNDEF,
+        SHT_NOBITS,
+        SHT_RELA,
+        SHT_SYMTAB,
+    },
+    initrd::Initrd,
+    module_abi::KERNEL_ABI_VERSION,
+};
+use log::{
+    error,
+    info,
+};
+
+/// The directory in the initrd, which is scanned for kernel modules
+pub const MODULE_DIRECTORY: &str = "modules/";
+
+pub type ModuleInitFn = extern "C" fn() -> i32;
+pub type ModuleExitFn = extern "C" fn();
+
+static mut LOADED_MODULES: Vec<Module> = Vec::new();
+
+/// A module is a relocatable ELF object, which is linked against the kernel symbols at runtime. The
+/// module declares its name and version as `name=` and `version=` entries in the `.modinfo`
+/// section (see `libcore::module_info!`), the used kernel symbols as `import=` entries (see
+/// `libcore::module_import!`) and provides the entry points `module_init` and (optionally)
+/// `module_exit`.
+pub struct Module {
+    pub name: String,
+    pub version: String,
+    pub abi_version: Option<u32>,
+    memory: NonNull<u8>,
+    layout: Layout,
+    exit: Option<ModuleExitFn>,
+}
+
+impl Module {
+    #[inline]
+    pub fn base_address(&self) -> u64 {
+        self.memory.as_ptr() as u64
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.memory.as_ptr(), self.layout) };
+    }
+}
+
+/// This function loads the specified relocatable ELF file as kernel module. All allocated sections
+/// are copied into a single memory block, the relocations are applied against the module itself and
+/// the kernel symbols and after that, the init function of the module is called.
+pub fn load_module(data: &[u8]) -> Result<(), Error> {
+    let elf = ElfFile::parse(data)?;
+    if elf.header.kind != ET_REL {
+        return Err(Error::NotRelocatable);
+    }
+
+    // Calculate the offsets of all allocated sections in the module memory
+    let mut section_offsets = vec![None; elf.header.section_header_count as usize];
+    let mut size = 0;
+    let mut align = 1;
+    for (index, section) in elf.section_headers().enumerate() {
+        let section = section?;
+        if section.flags & SHF_ALLOC == 0 || section.size == 0 {
+            continue;
+        }
+
+        // The alignment and the size are read from the module, so they are validated before use
+        let section_align = section.address_align.max(1);
+        if !section_align.is_power_of_two() {
+            return Err(Error::InvalidSectionAlignment(index, section.address_align));
+        }
+        let section_align =
+            usize::try_from(section_align).map_err(|_| Error::ModuleTooLarge(index))?;
+        let offset = size
+            .checked_next_multiple_of(section_align)
+            .ok_or(Error::ModuleTooLarge(index))?;
+        section_offsets[index] = Some(offset);
+        size = usize::try_from(section.size)
+            .ok()
+            .and_then(|section_size| offset.checked_add(section_size))
+            .ok_or(Error::ModuleTooLarge(index))?;
+        align = align.max(section_align);
+    }
+
+    // Allocate module memory and copy sections into it, the memory of NOBITS sections is zeroed
+    let layout = Layout::from_size_align(size.max(1), align).map_err(|_| Error::OutOfMemory(size))?;
+    let memory = NonNull::new(unsafe { alloc::alloc::alloc_zeroed(layout) })
+        .ok_or(Error::OutOfMemory(size))?;
+    let mut module = Module {
+        name: String::new(),
+        version: String::new(),
+        abi_version: None,
+        memory,
+        layout,
+        exit: None,
+    };
+    let image = unsafe { core::slice::from_raw_parts_mut(memory.as_ptr(), layout.size()) };
+
+    for (index, section) in elf.section_headers().enumerate() {
+        let section = section?;
+        if let Some(offset) = section_offsets[index] {
+            if section.kind != SHT_NOBITS {
+                image[offset..offset + section.size as usize]
+                    .copy_from_slice(elf.section_data(&section)?);
+            }
+        }
+    }
+
+    // Read module information and refuse modules built against another kernel ABI. The imports
+    // are checked against the exported symbols, when the symbols are resolved.
+    let mut imports = Vec::new();
+    if let Some(section) = elf.find_section(".modinfo")? {
+        for entry in elf.section_data(&section)?.split(|byte| *byte == 0) {
+            let entry = core::str::from_utf8(entry).unwrap_or_default();
+            if let Some(name) = entry.strip_prefix("name=") {
+                module.name = name.to_string();
+            } else if let Some(version) = entry.strip_prefix("version=") {
+                module.version = version.to_string();
+            } else if let Some(abi_version) = entry.strip_prefix("abi=") {
+                module.abi_version = abi_version.parse().ok();
+            } else if let Some((name, abi_hash)) = entry
+                .strip_prefix("import=")
+                .and_then(|import| import.split_once(':'))
+            {
+                if let Ok(abi_hash) = u64::from_str_radix(abi_hash, 16) {
+                    imports.push((name, abi_hash));
+                }
+            }
+        }
+    }
+
+    if module.name.is_empty() {
+        module.name = "unnamed".to_string();
+    }
+
+    let abi_version = module
+        .abi_version
+        .ok_or_else(|| Error::MissingAbiVersion(module.name.clone()))?;
+    if abi_version != KERNEL_ABI_VERSION {
+        return Err(Error::ModuleAbiMismatch(module.name.clone(), abi_version, KERNEL_ABI_VERSION));
+    }
+
+    // Apply relocations of all allocated sections
+    let symbol_table = elf
+        .section_headers()
+        .find(|section| matches!(section, Ok(section) if section.kind == SHT_SYMTAB))
+        .ok_or(Error::NoSymbolTable)??;
+    let string_table = elf.section_header(symbol_table.link as usize)?;
+    let base_address = module.base_address();
+
+    for section in elf.section_headers() {
+        let section = section?;
+        if section.kind != SHT_RELA {
+            continue;
+        }
+
+        let target_offset = match section_offsets.get(section.info as usize) {
+            Some(Some(offset)) => *offset,
+            _ => continue,
+        };
+
+        for relocation in elf.relocations(&section) {
+            let relocation = relocation?;
+            let symbol = elf.symbol(&symbol_table, relocation.symbol_index())?;
+            let symbol_value = resolve_symbol(
+                &elf,
+                &string_table,
+                &symbol,
+                &section_offsets,
+                base_address,
+                &imports,
+            )?;
+            let offset = target_offset + relocation.offset as usize;
+            apply_relocation(
+                image,
+                offset,
+                relocation.kind(),
+                symbol_value,
+                relocation.addend,
+                base_address + offset as u64,
+            )?;
+        }
+    }
+
+    // Read entry points of module
+    let mut init = None;
+    for symbol in elf.symbols(&symbol_table) {
+        let symbol = symbol?;
+        if symbol.section_index == SHN_UNDEF {
+            continue;
+        }
+
+        match elf.string(&string_table, symbol.name as usize)? {
+            "module_init" => {
+                let address = resolve_symbol(
+                    &elf,
+                    &string_table,
+                    &symbol,
+                    &section_offsets,
+                    base_address,
+                    &imports,
+                )?;
+                init = Some(unsafe { core::mem::transmute::<u64, ModuleInitFn>(address) });
+            }
+            "module_exit" => {
+                let address = resolve_symbol(
+                    &elf,
+                    &string_table,
+                    &symbol,
+                    &section_offsets,
+                    base_address,
+                    &imports,
+                )?;
+                module.exit = Some(unsafe { core::mem::transmute::<u64, ModuleExitFn>(address) });
+            }
+            _ => {}
+        }
+    }
+
+    if module_by_name(&module.name).is_some() {
+        return Err(Error::ModuleAlreadyLoaded(module.name.clone()));
+    }
+
+    // Initialize module and add it to the loaded modules
+    if let Some(init) = init {
+        let code = init();
+        if code != 0 {
+            return Err(Error::ModuleInitFailed(module.name.clone(), code));
+        }
+    }
+
+    info!(
+        "Loaded module {} v{} at 0x{:X} ({} bytes)\n",
+        module.name,
+        module.version,
+        module.base_address(),
+        module.size()
+    );
+    unsafe { LOADED_MODULES.push(module) };
+    Ok(())
+}
+
+/// This function calls the exit function of the module with the specified name and frees the memory
+/// of the module.
+pub fn unload_module(name: &str) -> Result<(), Error> {
+    let modules = unsafe { &mut LOADED_MODULES };
+    let index = modules
+        .iter()
+        .position(|module| module.name == name)
+        .ok_or_else(|| Error::ModuleNotLoaded(name.to_string()))?;
+
+    let module = modules.remove(index);
+    if let Some(exit) = module.exit {
+        exit();
+    }
+    info!("Unloaded module {} v{}\n", module.name, module.version);
+    Ok(())
+}
+
+/// This function loads all `.ko` files in the module directory of the initrd. Modules, which fail to
+/// load, are reported and skipped. The count of the loaded modules is returned.
+pub fn load_modules_from_initrd(initrd: &Initrd) -> usize {
+    let mut loaded_modules = 0;
+    for file in initrd.files() {
+        let file = match file {
+            Ok(file) => file,
+            Err(error) => {
+                error!("Unable to read initrd => {}\n", error);
+                break;
+            }
+        };
+
+        if !file.name.starts_with(MODULE_DIRECTORY) || !file.name.ends_with(".ko") {
+            continue;
+        }
+
+        match load_module(file.data) {
+            Ok(_) => loaded_modules += 1,
+            Err(error) => error!("Unable to load module {} => {}\n", file.name, error),
+        }
+    }
+    loaded_modules
+}
+
+pub fn module_by_name(name: &str) -> Option<&'static Module> {
+    unsafe { LOADED_MODULES.iter().find(|module| module.name == name) }
+}
+
+pub fn loaded_modules() -> &'static [Module] {
+    unsafe { LOADED_MODULES.as_slice() }
+}
+
+fn resolve_symbol(
+    elf: &ElfFile, string_table: &SectionHeader, symbol: &Symbol, section_offsets: &[Option<usize>],
+    base_address: u64, imports: &[(&str, u64)],
+) -> Result<u64, Error> {
+    match symbol.section_index {
+        SHN_UNDEF => {
+            let name = elf.string(string_table, symbol.name as usize)?;
+            let (_, abi_hash) = imports
+                .iter()
+                .find(|(import, _)| *import == name)
+                .ok_or_else(|| Error::MissingSymbolImport(name.to_string()))?;
+            lookup_symbol(name, *abi_hash)
+        }
+        SHN_ABS => Ok(symbol.value),
+        index => {
+            match section_offsets.get(index as usize) {
+                Some(Some(offset)) => Ok(base_address + *offset as u64 + symbol.value),
+                _ => Err(Error::InvalidSymbolSection(index)),
+            }
+        }
+    }
+}
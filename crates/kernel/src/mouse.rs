@@ -0,0 +1,189 @@
+use crate::{
+    error::Error,
+    interrupts::{
+        set_irq_handler,
+        InterruptStackFrame,
+    },
+    pic,
+};
+use libcore::{
+    cmdline::CommandLine,
+    port::{
+        read_u8,
+        write_u8,
+    },
+};
+use libgraphics::cursor::show_cursor;
+use libsync::WaitRing;
+
+/// The flag, which disables the initialization of the PS/2 mouse
+pub const NO_MOUSE_OPTION: &str = "nomouse";
+
+const DATA_PORT: u16 = 0x60;
+const COMMAND_PORT: u16 = 0x64;
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+const MOUSE_IRQ: u8 = 12;
+
+/// The commands of the controller, which configure the auxiliary port
+const CONTROLLER_READ_CONFIG: u8 = 0x20;
+const CONTROLLER_WRITE_CONFIG: u8 = 0x60;
+const CONTROLLER_ENABLE_AUX: u8 = 0xA8;
+const CONTROLLER_WRITE_AUX: u8 = 0xD4;
+const CONFIG_AUX_INTERRUPT: u8 = 1 << 1;
+const CONFIG_AUX_CLOCK_DISABLED: u8 = 1 << 5;
+
+/// The commands of the mouse
+const MOUSE_SET_DEFAULTS: u8 = 0xF6;
+const MOUSE_ENABLE_REPORTING: u8 = 0xF4;
+const RESPONSE_ACK: u8 = 0xFA;
+
+/// The mouse is initialized before the interrupts are enabled, so the controller is polled for
+/// a bounded number of status reads instead of waiting for the timer
+const MAX_STATUS_READS: usize = 100_000;
+
+/// The first byte of every packet has this bit set, which is used to find the start of a packet
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_OVERFLOW: u8 = 0b11 << 6;
+
+/// The bytes of the packets are pushed by the interrupt handler and decoded by the consumer like the
+/// scancodes of the keyboard
+static PACKET_BYTES: WaitRing<u8, 128> = WaitRing::new();
+
+/// The state of the mouse buttons
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// A decoded packet of a 3-button mouse. The movement on the y axis is positive, when the mouse is
+/// moved towards the user, like the coordinates of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub x_movement: i16,
+    pub y_movement: i16,
+    pub buttons: MouseButtons,
+}
+
+impl MouseEvent {
+    /// This function decodes the specified 3-byte packet. Packets with an overflow are dropped.
+    fn decode(packet: [u8; 3]) -> Option<Self> {
+        if packet[0] & PACKET_OVERFLOW != 0 {
+            return None;
+        }
+
+        let x_movement = packet[1] as i16 - (((packet[0] & PACKET_X_SIGN) as i16) << 4);
+        let y_movement = packet[2] as i16 - (((packet[0] & PACKET_Y_SIGN) as i16) << 3);
+        Some(Self {
+            x_movement,
+            y_movement: -y_movement,
+            buttons: MouseButtons {
+                left: packet[0] & (1 << 0) != 0,
+                right: packet[0] & (1 << 1) != 0,
+                middle: packet[0] & (1 << 2) != 0,
+            },
+        })
+    }
+}
+
+crate::driver!("mouse", ["keyboard"], |boot_info| init_mouse(&boot_info.command_line()));
+
+/// This function enables the auxiliary port of the PS/2 controller, enables the data reporting of
+/// the mouse and unmasks the mouse IRQ. It must be called before the interrupts are enabled.
+pub fn init_mouse(command_line: &CommandLine) -> Result<(), Error> {
+    if command_line.has_flag(NO_MOUSE_OPTION) {
+        return Ok(());
+    }
+
+    enable_mouse()?;
+    set_irq_handler(MOUSE_IRQ, "mouse", mouse_handler as u64);
+    pic::unmask_irq(MOUSE_IRQ);
+    Ok(())
+}
+
+fn enable_mouse() -> Result<(), Error> {
+    write_controller(COMMAND_PORT, CONTROLLER_ENABLE_AUX)?;
+    write_controller(COMMAND_PORT, CONTROLLER_READ_CONFIG)?;
+    let config = read_controller()?;
+    write_controller(COMMAND_PORT, CONTROLLER_WRITE_CONFIG)?;
+    write_controller(DATA_PORT, (config | CONFIG_AUX_INTERRUPT) & !CONFIG_AUX_CLOCK_DISABLED)?;
+
+    for command in [MOUSE_SET_DEFAULTS, MOUSE_ENABLE_REPORTING] {
+        write_controller(COMMAND_PORT, CONTROLLER_WRITE_AUX)?;
+        write_controller(DATA_PORT, command)?;
+        match read_controller()? {
+            RESPONSE_ACK => {}
+            response => return Err(Error::UnexpectedMouseResponse(response)),
+        }
+    }
+    Ok(())
+}
+
+fn write_controller(port: u16, byte: u8) -> Result<(), Error> {
+    for _ in 0..MAX_STATUS_READS {
+        unsafe {
+            if read_u8(COMMAND_PORT) & STATUS_INPUT_FULL == 0 {
+                write_u8(port, byte);
+                return Ok(());
+            }
+        }
+        core::hint::spin_loop();
+    }
+    Err(Error::MouseTimeout)
+}
+
+fn read_controller() -> Result<u8, Error> {
+    for _ in 0..MAX_STATUS_READS {
+        unsafe {
+            if read_u8(COMMAND_PORT) & STATUS_OUTPUT_FULL != 0 {
+                return Ok(read_u8(DATA_PORT));
+            }
+        }
+        core::hint::spin_loop();
+    }
+    Err(Error::MouseTimeout)
+}
+
+extern "x86-interrupt" fn mouse_handler(_frame: InterruptStackFrame) {
+    let _ = PACKET_BYTES.push(unsafe { read_u8(DATA_PORT) });
+    pic::end_of_interrupt(MOUSE_IRQ);
+}
+
+/// This function returns the next packet of the mouse. Bytes are skipped until the first byte of a
+/// packet is found, so the decoding recovers from lost bytes.
+pub async fn next_event() -> MouseEvent {
+    loop {
+        let first = PACKET_BYTES.pop().await;
+        if first & PACKET_ALWAYS_ONE == 0 {
+            continue;
+        }
+
+        let packet = [first, PACKET_BYTES.pop().await, PACKET_BYTES.pop().await];
+        if let Some(event) = MouseEvent::decode(packet) {
+            return event;
+        }
+    }
+}
+
+/// This function shows the cursor overlay in the center of the screen and moves it with the mouse.
+/// The task only returns, if no console is available.
+pub async fn track_cursor() {
+    let Ok((width, height)) = libgraphics::resolution() else {
+        return;
+    };
+
+    let (mut x, mut y) = (width / 2, height / 2);
+    loop {
+        if show_cursor(x, y).is_err() {
+            return;
+        }
+
+        let event = next_event().await;
+        x = (x as isize + event.x_movement as isize).clamp(0, width as isize - 1) as usize;
+        y = (y as isize + event.y_movement as isize).clamp(0, height as isize - 1) as usize;
+    }
+}
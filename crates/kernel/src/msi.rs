@@ -0,0 +1,315 @@
+use crate::{
+    error::Error,
+    interrupts::{
+        set_msi_handler,
+        InterruptStackFrame,
+    },
+    pci::{
+        PciDevice,
+        CAPABILITY_MSI,
+        CAPABILITY_MSI_X,
+    },
+};
+use core::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    Ordering,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    registers::read_msr,
+};
+
+/// The registers of the local APIC, which receives the message signaled interrupts
+const APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const APIC_ID_REGISTER: u64 = 0x20;
+const APIC_EOI_REGISTER: u64 = 0xB0;
+const APIC_SPURIOUS_REGISTER: u64 = 0xF0;
+const APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// The messages are written into the interrupt address range of the local APICs
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+/// The vectors behind the IRQs of the legacy PICs are allocated for message signaled interrupts.
+/// The vectors from 0xF0 are reserved for the spurious interrupts of the local APIC.
+const FIRST_VECTOR: u8 = 0x30;
+const VECTOR_COUNT: usize = 0xC0;
+
+/// The bits of the message control register of the MSI capability
+const MSI_CONTROL_ENABLE: u16 = 1 << 0;
+const MSI_CONTROL_MULTIPLE_ENABLE: u16 = 0b111 << 4;
+const MSI_CONTROL_64_BIT: u16 = 1 << 7;
+const MSI_CONTROL_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+/// The bits of the message control register of the MSI-X capability
+const MSI_X_CONTROL_TABLE_SIZE: u16 = 0x7FF;
+const MSI_X_CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+const MSI_X_CONTROL_ENABLE: u16 = 1 << 15;
+const MSI_X_BAR_INDICATOR: u32 = 0x7;
+const MSI_X_ENTRY_SIZE: u64 = 16;
+const MSI_X_VECTOR_MASKED: u32 = 1 << 0;
+
+/// The names of the drivers, which allocated the vectors
+static mut VECTORS: [Option<&'static str>; VECTOR_COUNT] = [None; VECTOR_COUNT];
+
+/// The number of acknowledged interrupts per vector
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static VECTOR_COUNTERS: [AtomicU64; VECTOR_COUNT] = [NO_INTERRUPTS; VECTOR_COUNT];
+
+static LOCAL_APIC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A message, which is written by the device to raise an interrupt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiMessage {
+    pub address: u64,
+    pub data: u32,
+}
+
+impl MsiMessage {
+    /// This function creates the message, which delivers the specified vector as fixed and
+    /// edge-triggered interrupt to the local APIC with the specified ID
+    pub fn new(vector: u8, apic_id: u8) -> Self {
+        Self {
+            address: MSI_ADDRESS_BASE | (apic_id as u64) << 12,
+            data: vector as u32,
+        }
+    }
+
+    /// This function creates the message, which delivers the specified vector to the current CPU
+    pub fn current_cpu(vector: u8) -> Self {
+        Self::new(vector, local_apic_id())
+    }
+}
+
+/// The MSI capability of a function. MSI supports a single vector per function in the kernel,
+/// because multiple messages need a block of aligned vectors. Functions with multiple queues use
+/// [MsiX] instead.
+pub struct Msi {
+    device: PciDevice,
+    offset: u8,
+}
+
+impl Msi {
+    /// This function returns the MSI capability of the function, if it's supported
+    pub fn find(device: &PciDevice) -> Option<Self> {
+        Some(Self {
+            device: *device,
+            offset: device.find_capability(CAPABILITY_MSI)?,
+        })
+    }
+
+    #[inline]
+    fn control(&self) -> u16 {
+        self.device.read_config_u16(self.offset + 2)
+    }
+
+    /// This function returns the offset of the specified register behind the address. The message
+    /// data and the mask bits are moved by a dword, if the function supports 64-bit addresses.
+    #[inline]
+    fn register_offset(&self, offset: u8) -> u8 {
+        match self.control() & MSI_CONTROL_64_BIT {
+            0 => self.offset + offset,
+            _ => self.offset + offset + 4,
+        }
+    }
+
+    /// This function writes the message of the function. The function must be disabled or masked,
+    /// while the message is changed.
+    pub fn configure(&self, message: MsiMessage) {
+        let control = self.control();
+        self.device
+            .write_config(self.offset + 4, message.address as u32);
+        if control & MSI_CONTROL_64_BIT != 0 {
+            self.device
+                .write_config(self.offset + 8, (message.address >> 32) as u32);
+        }
+        self.device
+            .write_config_u16(self.register_offset(8), message.data as u16);
+        self.device
+            .write_config_u16(self.offset + 2, control & !MSI_CONTROL_MULTIPLE_ENABLE);
+    }
+
+    /// This function masks or unmasks the vector of the function. If the function doesn't support
+    /// per-vector masking, this function returns false.
+    pub fn set_masked(&self, masked: bool) -> bool {
+        if self.control() & MSI_CONTROL_PER_VECTOR_MASKING == 0 {
+            return false;
+        }
+
+        let mask_offset = self.register_offset(0xC);
+        let mask_bits = self.device.read_config(mask_offset);
+        self.device
+            .write_config(mask_offset, (mask_bits & !1) | masked as u32);
+        true
+    }
+
+    /// This function enables the message signaled interrupts and disables the legacy interrupt of
+    /// the function
+    pub fn enable(&self) {
+        self.device
+            .write_config_u16(self.offset + 2, self.control() | MSI_CONTROL_ENABLE);
+        self.device.disable_legacy_interrupt();
+    }
+
+    pub fn disable(&self) {
+        self.device
+            .write_config_u16(self.offset + 2, self.control() & !MSI_CONTROL_ENABLE);
+    }
+}
+
+/// The MSI-X capability of a function. Every entry of the table has its own message, so every queue
+/// of a device can raise its own vector on its own CPU.
+pub struct MsiX {
+    device: PciDevice,
+    offset: u8,
+    table: VirtAddr,
+    table_size: u16,
+}
+
+impl MsiX {
+    /// This function returns the MSI-X capability of the function, if it's supported and the table
+    /// is located in a memory BAR
+    pub fn find(device: &PciDevice) -> Option<Self> {
+        let offset = device.find_capability(CAPABILITY_MSI_X)?;
+        let control = device.read_config_u16(offset + 2);
+        let table = device.read_config(offset + 4);
+        let bar = device.memory_bar((table & MSI_X_BAR_INDICATOR) as u8)?;
+        Some(Self {
+            device: *device,
+            offset,
+            table: (bar + (table & !MSI_X_BAR_INDICATOR) as u64).to_virt(),
+            table_size: (control & MSI_X_CONTROL_TABLE_SIZE) + 1,
+        })
+    }
+
+    /// This function returns the number of entries in the table
+    #[inline]
+    pub fn table_size(&self) -> u16 {
+        self.table_size
+    }
+
+    fn entry(&self, entry: u16) -> Result<*mut u32, Error> {
+        if entry >= self.table_size {
+            return Err(Error::InvalidMsiEntry(entry));
+        }
+        Ok((self.table + entry as u64 * MSI_X_ENTRY_SIZE).as_mut_ptr())
+    }
+
+    /// This function writes the message of the specified entry. The entry should be masked, while
+    /// the message is changed.
+    pub fn configure(&self, entry: u16, message: MsiMessage) -> Result<(), Error> {
+        let entry = self.entry(entry)?;
+        unsafe {
+            entry.write_volatile(message.address as u32);
+            entry.add(1).write_volatile((message.address >> 32) as u32);
+            entry.add(2).write_volatile(message.data);
+        }
+        Ok(())
+    }
+
+    /// This function masks or unmasks the specified entry
+    pub fn set_masked(&self, entry: u16, masked: bool) -> Result<(), Error> {
+        let vector_control = unsafe { self.entry(entry)?.add(3) };
+        unsafe {
+            let value = vector_control.read_volatile() & !MSI_X_VECTOR_MASKED;
+            vector_control.write_volatile(value | masked as u32);
+        }
+        Ok(())
+    }
+
+    /// This function enables the message signaled interrupts and disables the legacy interrupt of
+    /// the function. The entries are masked separately with [MsiX::set_masked].
+    pub fn enable(&self) {
+        self.device.enable_memory_space();
+        let control = self.device.read_config_u16(self.offset + 2);
+        self.device.write_config_u16(
+            self.offset + 2,
+            (control | MSI_X_CONTROL_ENABLE) & !MSI_X_CONTROL_FUNCTION_MASK,
+        );
+        self.device.disable_legacy_interrupt();
+    }
+
+    pub fn disable(&self) {
+        let control = self.device.read_config_u16(self.offset + 2);
+        self.device
+            .write_config_u16(self.offset + 2, control & !MSI_X_CONTROL_ENABLE);
+    }
+}
+
+/// This function allocates a free vector for message signaled interrupts and installs the specified
+/// handler. The handler has to acknowledge the interrupt with [end_of_interrupt]. The name
+/// identifies the driver in `lsirq`.
+pub fn allocate_vector(name: &'static str, handler: u64) -> Result<u8, Error> {
+    let index = unsafe { VECTORS.iter() }
+        .position(|vector| vector.is_none())
+        .ok_or(Error::NoFreeVector)?;
+    enable_local_apic();
+
+    let vector = FIRST_VECTOR + index as u8;
+    set_msi_handler(vector, handler);
+    unsafe { VECTORS[index] = Some(name) };
+    Ok(vector)
+}
+
+/// This function releases the specified vector. The device must not raise it anymore.
+pub fn free_vector(vector: u8) {
+    if let Some(name) = unsafe { VECTORS.get_mut(vector.wrapping_sub(FIRST_VECTOR) as usize) } {
+        *name = None;
+    }
+}
+
+/// This function returns the allocated vectors with the name of the driver
+pub fn allocated_vectors() -> impl Iterator<Item = (u8, &'static str)> {
+    (0..VECTOR_COUNT)
+        .filter_map(|index| unsafe { VECTORS[index] }.map(|name| (FIRST_VECTOR + index as u8, name)))
+}
+
+/// This function acknowledges the specified vector, it must be called at the end of every handler of
+/// a message signaled interrupt
+pub fn end_of_interrupt(vector: u8) {
+    if let Some(counter) = VECTOR_COUNTERS.get(vector.wrapping_sub(FIRST_VECTOR) as usize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    unsafe { local_apic_register(APIC_EOI_REGISTER).write_volatile(0) };
+}
+
+/// This function returns the number of acknowledged interrupts of the specified vector
+pub fn interrupt_count(vector: u8) -> u64 {
+    VECTOR_COUNTERS
+        .get(vector.wrapping_sub(FIRST_VECTOR) as usize)
+        .map_or(0, |counter| counter.load(Ordering::Relaxed))
+}
+
+/// This function returns the ID of the local APIC of the current CPU
+pub fn local_apic_id() -> u8 {
+    (unsafe { local_apic_register(APIC_ID_REGISTER).read_volatile() } >> 24) as u8
+}
+
+/// This function returns the pointer to the specified register of the local APIC of the current CPU
+pub(crate) fn local_apic_register(register: u64) -> *mut u32 {
+    let base = unsafe { read_msr(APIC_BASE_MSR) } & APIC_BASE_MASK;
+    (PhysAddr::new(base) + register).to_virt().as_mut_ptr()
+}
+
+/// The legacy PICs stay in use for the IRQs, so the local APIC is only enabled by software, when the
+/// first vector is allocated. Spurious interrupts are ignored.
+fn enable_local_apic() {
+    if LOCAL_APIC_ENABLED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    set_msi_handler(SPURIOUS_VECTOR, spurious_handler as u64);
+    let register = local_apic_register(APIC_SPURIOUS_REGISTER);
+    unsafe {
+        let value = register.read_volatile() & !0xFF;
+        register.write_volatile(value | APIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+}
+
+extern "x86-interrupt" fn spurious_handler(_frame: InterruptStackFrame) {}
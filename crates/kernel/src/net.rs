@@ -0,0 +1,363 @@
+use crate::{
+    error::Error,
+    pci,
+    timer,
+    virtio_net::{
+        VirtioNet,
+        MAX_FRAME_SIZE,
+        VIRTIO_NET_DEVICE_ID,
+        VIRTIO_VENDOR_ID,
+    },
+};
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    vec::Vec,
+};
+use libcore::cmdline::CommandLine;
+use log::{
+    info,
+    warn,
+};
+
+/// The option, which sets the IPv4 address of the kernel and enables the network stack
+pub const IP_OPTION: &str = "ip";
+
+/// The option, which sets the router for destinations outside of the local network
+pub const GATEWAY_OPTION: &str = "gateway";
+
+/// The option, which streams the log output to the UDP collector with the specified address
+pub const NETLOG_OPTION: &str = "netlog";
+
+/// The interval between two polls of the network device in milliseconds
+const POLL_INTERVAL: u64 = 10;
+
+/// The minimal interval between two ARP requests for the same address in milliseconds
+const ARP_RETRY_INTERVAL: u64 = 1000;
+
+/// The maximal payload of a log datagram, so datagrams are never fragmented
+const MAX_LOG_DATAGRAM: usize = 1400;
+const LOG_SOURCE_PORT: u16 = 5140;
+
+const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
+const ETHERNET_HEADER_SIZE: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+
+const ARP_PACKET_SIZE: usize = 28;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV4_TTL: u8 = 64;
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_UDP: u8 = 17;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const UDP_HEADER_SIZE: usize = 8;
+
+pub type Ipv4Address = [u8; 4];
+
+/// The static configuration of the network stack, which is read from the kernel command line
+#[derive(Clone, Copy)]
+pub struct NetworkConfig {
+    pub address: Ipv4Address,
+    pub gateway: Option<Ipv4Address>,
+    pub log_collector: Option<(Ipv4Address, u16)>,
+}
+
+impl NetworkConfig {
+    /// This function returns the network configuration, or none if no address was specified
+    pub fn from_command_line(command_line: &CommandLine) -> Result<Option<Self>, Error> {
+        let Some(address) = command_line.get(IP_OPTION) else {
+            return Ok(None);
+        };
+        let invalid = |value: &str| Error::InvalidNetworkOption(value.to_string());
+
+        let address = parse_ipv4(address).ok_or_else(|| invalid(address))?;
+        let gateway = match command_line.get(GATEWAY_OPTION) {
+            Some(gateway) => Some(parse_ipv4(gateway).ok_or_else(|| invalid(gateway))?),
+            None => None,
+        };
+        let log_collector = match command_line.get(NETLOG_OPTION) {
+            Some(collector) => {
+                let (host, port) = collector
+                    .split_once(':')
+                    .ok_or_else(|| invalid(collector))?;
+                Some((
+                    parse_ipv4(host).ok_or_else(|| invalid(collector))?,
+                    port.parse().map_err(|_| invalid(collector))?,
+                ))
+            }
+            None => None,
+        };
+
+        Ok(Some(Self {
+            address,
+            gateway,
+            log_collector,
+        }))
+    }
+}
+
+/// A minimal network stack, which answers ARP requests and pings and sends the log output as UDP
+/// datagrams
+struct NetworkStack {
+    device: VirtioNet,
+    config: NetworkConfig,
+    arp_cache: Vec<(Ipv4Address, [u8; 6])>,
+    last_arp_request: Option<u64>,
+    log_position: u64,
+    identification: u16,
+}
+
+impl NetworkStack {
+    fn poll(&mut self) {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        while let Some(length) = self.device.receive(&mut frame) {
+            self.handle_frame(&frame[..length]);
+        }
+        self.flush_log();
+    }
+
+    fn handle_frame(&mut self, frame: &[u8]) {
+        if frame.len() < ETHERNET_HEADER_SIZE {
+            return;
+        }
+
+        let source_mac = frame[6..12].try_into().unwrap();
+        match read_u16(&frame[12..]) {
+            ETHERTYPE_ARP => self.handle_arp(&frame[ETHERNET_HEADER_SIZE..]),
+            ETHERTYPE_IPV4 => self.handle_ipv4(source_mac, &frame[ETHERNET_HEADER_SIZE..]),
+            _ => {}
+        }
+    }
+
+    fn handle_arp(&mut self, packet: &[u8]) {
+        if packet.len() < ARP_PACKET_SIZE
+            || read_u16(&packet[0..]) != 1
+            || read_u16(&packet[2..]) != ETHERTYPE_IPV4
+        {
+            return;
+        }
+
+        let sender_mac: [u8; 6] = packet[8..14].try_into().unwrap();
+        let sender_address: Ipv4Address = packet[14..18].try_into().unwrap();
+        let target_address: Ipv4Address = packet[24..28].try_into().unwrap();
+        if !self
+            .arp_cache
+            .iter()
+            .any(|(address, _)| *address == sender_address)
+        {
+            self.arp_cache.push((sender_address, sender_mac));
+        }
+
+        if read_u16(&packet[6..]) == ARP_REQUEST && target_address == self.config.address {
+            self.send_arp(ARP_REPLY, sender_mac, sender_address);
+        }
+    }
+
+    fn handle_ipv4(&mut self, source_mac: [u8; 6], packet: &[u8]) {
+        if packet.len() < IPV4_HEADER_SIZE || packet[0] >> 4 != 4 {
+            return;
+        }
+
+        let header_length = (packet[0] & 0xF) as usize * 4;
+        let total_length = (read_u16(&packet[2..]) as usize).min(packet.len());
+        if header_length < IPV4_HEADER_SIZE || total_length < header_length {
+            return;
+        }
+
+        let source: Ipv4Address = packet[12..16].try_into().unwrap();
+        if packet[16..20] != self.config.address {
+            return;
+        }
+
+        // Answer pings with the same identifier, sequence number and payload
+        let payload = &packet[header_length..total_length];
+        if packet[9] == PROTOCOL_ICMP && payload.len() >= 8 && payload[0] == ICMP_ECHO_REQUEST {
+            let mut reply = payload.to_vec();
+            reply[0] = ICMP_ECHO_REPLY;
+            reply[2..4].fill(0);
+            let checksum = internet_checksum(&reply);
+            reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+            self.send_ipv4(source_mac, source, PROTOCOL_ICMP, &reply);
+        }
+    }
+
+    /// This function sends the log output, which was written since the last flush, to the
+    /// collector. The output is kept until the MAC address of the next hop is resolved.
+    fn flush_log(&mut self) {
+        let Some((collector, port)) = self.config.log_collector else {
+            return;
+        };
+
+        let next_hop = self.config.gateway.unwrap_or(collector);
+        let Some(mac) = self.resolve(next_hop) else {
+            return;
+        };
+
+        let log_tail = libgraphics::log::log_tail();
+        let written = log_tail.written();
+        let (older, newer) = log_tail.since(self.log_position);
+        let chunks: Vec<Vec<u8>> = older
+            .chunks(MAX_LOG_DATAGRAM)
+            .chain(newer.chunks(MAX_LOG_DATAGRAM))
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        self.log_position = written;
+
+        for chunk in chunks {
+            let mut datagram = Vec::with_capacity(UDP_HEADER_SIZE + chunk.len());
+            datagram.extend_from_slice(&LOG_SOURCE_PORT.to_be_bytes());
+            datagram.extend_from_slice(&port.to_be_bytes());
+            datagram.extend_from_slice(&((UDP_HEADER_SIZE + chunk.len()) as u16).to_be_bytes());
+            datagram.extend_from_slice(&[0, 0]); // The checksum is optional for IPv4
+            datagram.extend_from_slice(&chunk);
+            self.send_ipv4(mac, collector, PROTOCOL_UDP, &datagram);
+        }
+    }
+
+    /// This function returns the MAC address of the specified address or sends an ARP request for
+    /// it, if it's unknown
+    fn resolve(&mut self, address: Ipv4Address) -> Option<[u8; 6]> {
+        if let Some((_, mac)) = self.arp_cache.iter().find(|(entry, _)| *entry == address) {
+            return Some(*mac);
+        }
+
+        let now = timer::ticks();
+        if self
+            .last_arp_request
+            .map_or(true, |last_request| now - last_request >= ARP_RETRY_INTERVAL)
+        {
+            self.last_arp_request = Some(now);
+            self.send_arp(ARP_REQUEST, BROADCAST_MAC, address);
+        }
+        None
+    }
+
+    fn send_arp(&mut self, operation: u16, target_mac: [u8; 6], target_address: Ipv4Address) {
+        let mut packet = Vec::with_capacity(ARP_PACKET_SIZE);
+        packet.extend_from_slice(&1u16.to_be_bytes());
+        packet.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        packet.extend_from_slice(&[6, 4]);
+        packet.extend_from_slice(&operation.to_be_bytes());
+        packet.extend_from_slice(&self.device.mac_address());
+        packet.extend_from_slice(&self.config.address);
+        packet.extend_from_slice(&if operation == ARP_REQUEST {
+            [0; 6]
+        } else {
+            target_mac
+        });
+        packet.extend_from_slice(&target_address);
+        self.send_frame(target_mac, ETHERTYPE_ARP, &packet);
+    }
+
+    fn send_ipv4(
+        &mut self, destination_mac: [u8; 6], destination: Ipv4Address, protocol: u8, payload: &[u8],
+    ) {
+        self.identification = self.identification.wrapping_add(1);
+
+        let mut packet = Vec::with_capacity(IPV4_HEADER_SIZE + payload.len());
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&((IPV4_HEADER_SIZE + payload.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&self.identification.to_be_bytes());
+        packet.extend_from_slice(&[0x40, 0, IPV4_TTL, protocol, 0, 0]); // Don't fragment
+        packet.extend_from_slice(&self.config.address);
+        packet.extend_from_slice(&destination);
+        let checksum = internet_checksum(&packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.send_frame(destination_mac, ETHERTYPE_IPV4, &packet);
+    }
+
+    fn send_frame(&mut self, destination_mac: [u8; 6], ethertype: u16, payload: &[u8]) {
+        let mut frame = Vec::with_capacity(ETHERNET_HEADER_SIZE + payload.len());
+        frame.extend_from_slice(&destination_mac);
+        frame.extend_from_slice(&self.device.mac_address());
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+
+        // Frames are dropped, if the device is busy. Logging the error would create more frames.
+        let _ = self.device.transmit(&frame);
+    }
+}
+
+/// This function initializes the first virtio-net device and polls it forever. The task returns
+/// early, if no device is available.
+pub async fn run_network(config: NetworkConfig) {
+    let Some(device) = pci::find_device(VIRTIO_VENDOR_ID, VIRTIO_NET_DEVICE_ID) else {
+        warn!("Unable to initialize network => {}\n", Error::NoNetworkDevice);
+        return;
+    };
+
+    let device = match VirtioNet::init(&device) {
+        Ok(device) => device,
+        Err(error) => {
+            warn!("Unable to initialize network => {}\n", error);
+            return;
+        }
+    };
+
+    let mac = device.mac_address();
+    info!(
+        "Initialized virtio-net (MAC: {:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}, IP: {})\n",
+        mac[0],
+        mac[1],
+        mac[2],
+        mac[3],
+        mac[4],
+        mac[5],
+        format_ipv4(config.address)
+    );
+    if let Some((collector, port)) = config.log_collector {
+        info!("Streaming log output to {}:{}\n", format_ipv4(collector), port);
+    }
+
+    let mut stack = NetworkStack {
+        device,
+        config,
+        arp_cache: Vec::new(),
+        last_arp_request: None,
+        log_position: 0,
+        identification: 0,
+    };
+    loop {
+        stack.poll();
+        timer::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn parse_ipv4(value: &str) -> Option<Ipv4Address> {
+    let mut address = [0u8; 4];
+    let mut parts = value.split('.');
+    for byte in address.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(address)
+}
+
+fn format_ipv4(address: Ipv4Address) -> String {
+    format!("{}.{}.{}.{}", address[0], address[1], address[2], address[3])
+}
+
+#[inline]
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// This function calculates the one's complement checksum of IPv4 headers and ICMP messages
+fn internet_checksum(bytes: &[u8]) -> u16 {
+    let mut sum = bytes
+        .chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+        .sum::<u32>();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
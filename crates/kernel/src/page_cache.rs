@@ -0,0 +1,305 @@
+//! The page cache keeps recently used pages of the block devices in memory, so the file system
+//! drivers don't read the same metadata blocks (like the inode tables and directories) from the
+//! device on every lookup. The pages of all devices share one cache, which is keyed by the device
+//! and the page index. If the cache is full, the least recently used page is evicted. On a miss, the
+//! following pages are read ahead with the same device request. Writes go through to the device, so
+//! the cache never contains dirty pages.
+
+use crate::{
+    block::BlockDevice,
+    error::Error,
+};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+    sync::atomic::{
+        AtomicU32,
+        Ordering,
+    },
+};
+use libsync::Spinlock;
+
+/// The size of a cached page. Devices, whose block size doesn't divide the page size, aren't cached.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The maximum number of cached pages of all devices (512 KiB)
+const MAX_CACHED_PAGES: usize = 128;
+
+/// The number of pages, which are read with a missed page (including the missed page)
+const READ_AHEAD_PAGES: u64 = 4;
+
+static PAGE_CACHE: Spinlock<PageCache> = Spinlock::new(PageCache::new());
+static NEXT_DEVICE_ID: AtomicU32 = AtomicU32::new(1);
+
+/// The key of a cached page with the ID of the device and the index of the page on the device
+type PageKey = (u32, u64);
+
+struct Page {
+    data: Box<[u8]>,
+    last_use: u64,
+}
+
+/// The statistics of the page cache
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheReport {
+    pub cached_pages: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub read_ahead: u64,
+    pub evictions: u64,
+}
+
+impl Display for CacheReport {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} of {} pages ({} KiB), {} hits, {} misses, {} pages read ahead, {} evictions",
+            self.cached_pages,
+            MAX_CACHED_PAGES,
+            self.cached_pages * PAGE_SIZE / 1024,
+            self.hits,
+            self.misses,
+            self.read_ahead,
+            self.evictions
+        )
+    }
+}
+
+struct PageCache {
+    pages: BTreeMap<PageKey, Page>,
+    /// The keys of the pages ordered by their last use, the first key is evicted first
+    lru: BTreeMap<u64, PageKey>,
+    clock: u64,
+    report: CacheReport,
+}
+
+impl PageCache {
+    const fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            report: CacheReport {
+                cached_pages: 0,
+                hits: 0,
+                misses: 0,
+                read_ahead: 0,
+                evictions: 0,
+            },
+        }
+    }
+
+    /// This function returns the page and marks it as most recently used
+    fn get(&mut self, key: PageKey) -> Option<&[u8]> {
+        let page = self.pages.get_mut(&key)?;
+        self.lru.remove(&page.last_use);
+        self.clock += 1;
+        page.last_use = self.clock;
+        self.lru.insert(self.clock, key);
+        Some(&page.data)
+    }
+
+    /// This function inserts the page and evicts the least recently used pages, if the cache is full
+    fn insert(&mut self, key: PageKey, data: Box<[u8]>) {
+        self.remove(key);
+        while self.pages.len() >= MAX_CACHED_PAGES {
+            let Some((_, evicted)) = self.lru.pop_first() else {
+                break;
+            };
+            self.pages.remove(&evicted);
+            self.report.evictions += 1;
+        }
+
+        self.clock += 1;
+        self.lru.insert(self.clock, key);
+        self.pages.insert(
+            key,
+            Page {
+                data,
+                last_use: self.clock,
+            },
+        );
+        self.report.cached_pages = self.pages.len();
+    }
+
+    fn remove(&mut self, key: PageKey) {
+        if let Some(page) = self.pages.remove(&key) {
+            self.lru.remove(&page.last_use);
+            self.report.cached_pages = self.pages.len();
+        }
+    }
+
+    /// This function removes all pages of the device
+    fn invalidate_device(&mut self, device_id: u32) {
+        let keys: Vec<PageKey> = self
+            .pages
+            .range((device_id, 0)..=(device_id, u64::MAX))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.remove(key);
+        }
+    }
+}
+
+/// A block device, whose reads are served from the page cache. The pages of the device are removed
+/// from the cache, when the device is dropped.
+pub struct CachedDevice {
+    device: Box<dyn BlockDevice>,
+    id: u32,
+}
+
+impl CachedDevice {
+    pub fn new(device: Box<dyn BlockDevice>) -> Self {
+        Self {
+            device,
+            id: NEXT_DEVICE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// This function returns, whether the block size of the device allows caching in pages
+    #[inline]
+    fn is_cacheable(&self) -> bool {
+        let block_size = self.device.block_size();
+        block_size != 0 && block_size <= PAGE_SIZE && PAGE_SIZE % block_size == 0
+    }
+
+    #[inline]
+    fn size(&self) -> u64 {
+        self.device.block_count() * self.device.block_size() as u64
+    }
+
+    /// This function reads the missed page with the following pages, which aren't cached yet, from
+    /// the device and inserts them into the cache. The last page of the device can be shorter than a
+    /// page.
+    fn load_pages(&mut self, cache: &mut PageCache, page: u64) -> Result<(), Error> {
+        let page_count = self.size().div_ceil(PAGE_SIZE as u64);
+        let mut end = page + 1;
+        while end < (page + READ_AHEAD_PAGES).min(page_count)
+            && !cache.pages.contains_key(&(self.id, end))
+        {
+            end += 1;
+        }
+
+        let start_offset = page * PAGE_SIZE as u64;
+        let end_offset = (end * PAGE_SIZE as u64).min(self.size());
+        let mut data = vec![0; (end_offset - start_offset) as usize];
+        let block_size = self.device.block_size() as u64;
+        self.device
+            .read_blocks(start_offset / block_size, &mut data)?;
+
+        cache.report.misses += 1;
+        cache.report.read_ahead += end - page - 1;
+        for (index, chunk) in data.chunks(PAGE_SIZE).enumerate() {
+            cache.insert((self.id, page + index as u64), chunk.into());
+        }
+        Ok(())
+    }
+
+    /// This function checks, whether the blocks are inside of the device and the length is a
+    /// multiple of the block size
+    fn check_range(&self, block: u64, length: usize) -> Result<(), Error> {
+        let block_size = self.device.block_size();
+        if length % block_size != 0 {
+            return Err(Error::UnalignedBlockAccess(length));
+        }
+        if block + (length / block_size) as u64 > self.device.block_count() {
+            return Err(Error::BlockOutOfRange(block));
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for CachedDevice {
+    fn name(&self) -> &str {
+        self.device.name()
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    #[inline]
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_blocks(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        if !self.is_cacheable() {
+            return self.device.read_blocks(block, buffer);
+        }
+        self.check_range(block, buffer.len())?;
+
+        let mut cache = PAGE_CACHE.lock();
+        let mut offset = block * self.device.block_size() as u64;
+        let mut position = 0;
+        while position < buffer.len() {
+            let page = offset / PAGE_SIZE as u64;
+            let page_offset = (offset % PAGE_SIZE as u64) as usize;
+            if cache.pages.contains_key(&(self.id, page)) {
+                cache.report.hits += 1;
+            } else {
+                self.load_pages(&mut cache, page)?;
+            }
+
+            let data = cache.get((self.id, page)).unwrap();
+            let length = (data.len() - page_offset).min(buffer.len() - position);
+            buffer[position..position + length]
+                .copy_from_slice(&data[page_offset..page_offset + length]);
+            position += length;
+            offset += length as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block: u64, data: &[u8]) -> Result<(), Error> {
+        self.device.write_blocks(block, data)?;
+        if !self.is_cacheable() {
+            return Ok(());
+        }
+
+        // Update the cached pages, which overlap with the written blocks
+        let mut cache = PAGE_CACHE.lock();
+        let mut offset = block * self.device.block_size() as u64;
+        let mut position = 0;
+        while position < data.len() {
+            let page = offset / PAGE_SIZE as u64;
+            let page_offset = (offset % PAGE_SIZE as u64) as usize;
+            let length = (PAGE_SIZE - page_offset).min(data.len() - position);
+            if let Some(cached) = cache.pages.get_mut(&(self.id, page)) {
+                cached.data[page_offset..page_offset + length]
+                    .copy_from_slice(&data[position..position + length]);
+            }
+            position += length;
+            offset += length as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CachedDevice {
+    fn drop(&mut self) {
+        PAGE_CACHE.lock().invalidate_device(self.id);
+    }
+}
+
+/// This function returns, whether the page cache is locked. It's checked by the panic handler like
+/// the lock of the heap.
+pub fn is_locked() -> bool {
+    PAGE_CACHE.is_locked()
+}
+
+/// This function returns the statistics of the page cache
+pub fn cache_report() -> CacheReport {
+    PAGE_CACHE.lock().report
+}
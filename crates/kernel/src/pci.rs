@@ -0,0 +1,231 @@
+use alloc::vec::Vec;
+use libcore::{
+    address::PhysAddr,
+    port::{
+        read_u32,
+        write_u32,
+    },
+};
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+const COMMAND_OFFSET: u8 = 0x04;
+const COMMAND_IO_SPACE: u32 = 1 << 0;
+const COMMAND_MEMORY_SPACE: u32 = 1 << 1;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+const COMMAND_INTERRUPT_DISABLE: u32 = 1 << 10;
+const STATUS_CAPABILITIES: u32 = 1 << 20;
+const BAR0_OFFSET: u8 = 0x10;
+const BAR_IO_SPACE: u32 = 1 << 0;
+const BAR_64_BIT: u32 = 0b10 << 1;
+const CAPABILITIES_POINTER_OFFSET: u8 = 0x34;
+
+/// The IDs of the capabilities, which are used by the kernel
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_MSI_X: u8 = 0x11;
+
+/// The capability list is limited to the 48 dwords behind the header, so a malformed list with a
+/// loop can't hang the kernel
+const MAX_CAPABILITIES: usize = 48;
+
+const MULTI_FUNCTION: u8 = 0x80;
+const INVALID_VENDOR: u16 = 0xFFFF;
+
+/// A function of a PCI device, which was found while scanning the configuration space
+#[derive(Clone, Copy, Debug)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub interface: u8,
+}
+
+impl PciDevice {
+    /// This function returns the name of the base class of the device
+    pub fn class_name(&self) -> &'static str {
+        match self.class {
+            0x00 => "Unclassified",
+            0x01 => "Mass Storage Controller",
+            0x02 => "Network Controller",
+            0x03 => "Display Controller",
+            0x04 => "Multimedia Controller",
+            0x05 => "Memory Controller",
+            0x06 => "Bridge",
+            0x07 => "Communication Controller",
+            0x08 => "System Peripheral",
+            0x09 => "Input Device Controller",
+            0x0C => "Serial Bus Controller",
+            0x0D => "Wireless Controller",
+            _ => "Unknown",
+        }
+    }
+
+    #[inline]
+    pub fn read_config(&self, offset: u8) -> u32 {
+        read_config(self.bus, self.device, self.function, offset)
+    }
+
+    #[inline]
+    pub fn write_config(&self, offset: u8, value: u32) {
+        write_config(self.bus, self.device, self.function, offset, value)
+    }
+
+    /// This function returns the port of the specified base address register, if it's an I/O BAR
+    pub fn io_bar(&self, index: u8) -> Option<u16> {
+        let bar = self.read_config(BAR0_OFFSET + index * 4);
+        (bar & BAR_IO_SPACE != 0).then_some((bar & !0x3) as u16)
+    }
+
+    /// This function reads the specified word from the configuration space of the function
+    pub fn read_config_u16(&self, offset: u8) -> u16 {
+        (self.read_config(offset) >> ((offset & 0x2) * 8)) as u16
+    }
+
+    /// This function writes the specified word into the configuration space of the function. The
+    /// other word of the dword is written back unchanged.
+    pub fn write_config_u16(&self, offset: u8, value: u16) {
+        let shift = (offset & 0x2) * 8;
+        let dword = self.read_config(offset) & !(0xFFFF << shift);
+        self.write_config(offset, dword | (value as u32) << shift);
+    }
+
+    /// This function returns the address of the specified base address register, if it's a memory
+    /// BAR. 64-bit BARs use the following register for the upper half of the address.
+    pub fn memory_bar(&self, index: u8) -> Option<PhysAddr> {
+        let bar = self.read_config(BAR0_OFFSET + index * 4);
+        if bar & BAR_IO_SPACE != 0 {
+            return None;
+        }
+
+        let mut address = (bar & !0xF) as u64;
+        if bar & BAR_64_BIT != 0 {
+            address |= (self.read_config(BAR0_OFFSET + (index + 1) * 4) as u64) << 32;
+        }
+        Some(PhysAddr::new(address))
+    }
+
+    /// This function enables the I/O space decoding and the DMA of the function
+    pub fn enable_bus_master(&self) {
+        let command = self.read_config(COMMAND_OFFSET);
+        self.write_config(COMMAND_OFFSET, command | COMMAND_IO_SPACE | COMMAND_BUS_MASTER);
+    }
+
+    /// This function enables the memory space decoding of the function, so memory BARs can be
+    /// accessed
+    pub fn enable_memory_space(&self) {
+        let command = self.read_config(COMMAND_OFFSET);
+        self.write_config(COMMAND_OFFSET, command | COMMAND_MEMORY_SPACE);
+    }
+
+    /// This function disables the legacy INTx interrupt of the function. It's called after message
+    /// signaled interrupts were enabled, so the function doesn't share the legacy IRQ anymore.
+    pub fn disable_legacy_interrupt(&self) {
+        let command = self.read_config(COMMAND_OFFSET);
+        self.write_config(COMMAND_OFFSET, command | COMMAND_INTERRUPT_DISABLE);
+    }
+
+    /// This function returns the IDs and the offsets of the capabilities in the configuration
+    /// space of the function
+    pub fn capabilities(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        let mut offset = match self.read_config(COMMAND_OFFSET) & STATUS_CAPABILITIES {
+            0 => 0,
+            _ => self.read_config(CAPABILITIES_POINTER_OFFSET) as u8 & 0xFC,
+        };
+        core::iter::from_fn(move || {
+            if offset == 0 {
+                return None;
+            }
+
+            let header = self.read_config(offset);
+            let capability = (header as u8, offset);
+            offset = (header >> 8) as u8 & 0xFC;
+            Some(capability)
+        })
+        .take(MAX_CAPABILITIES)
+    }
+
+    /// This function returns the offset of the capability with the specified ID
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        self.capabilities()
+            .find(|(capability_id, _)| *capability_id == id)
+            .map(|(_, offset)| offset)
+    }
+}
+
+/// This function reads the specified dword from the configuration space of the function with the
+/// legacy configuration mechanism (port 0xCF8 and 0xCFC).
+pub fn read_config(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    unsafe {
+        write_u32(CONFIG_ADDRESS, config_address(bus, device, function, offset));
+        read_u32(CONFIG_DATA)
+    }
+}
+
+/// This function writes the specified dword into the configuration space of the function
+pub fn write_config(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    unsafe {
+        write_u32(CONFIG_ADDRESS, config_address(bus, device, function, offset));
+        write_u32(CONFIG_DATA, value);
+    }
+}
+
+#[inline]
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    CONFIG_ENABLE
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (function as u32) << 8
+        | (offset as u32 & 0xFC)
+}
+
+/// This function scans all buses for present functions. The other functions of a device are only
+/// scanned, if the first function is a multi-function device.
+pub fn scan_devices() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=255 {
+        for device in 0..32 {
+            let Some(first_function) = read_device(bus, device, 0) else {
+                continue;
+            };
+            devices.push(first_function);
+
+            let header_type = (read_config(bus, device, 0, 0x0C) >> 16) as u8;
+            if header_type & MULTI_FUNCTION != 0 {
+                devices.extend((1..8).filter_map(|function| read_device(bus, device, function)));
+            }
+        }
+    }
+    devices
+}
+
+/// This function returns the first function with the specified vendor and device ID
+pub fn find_device(vendor_id: u16, device_id: u16) -> Option<PciDevice> {
+    scan_devices()
+        .into_iter()
+        .find(|device| device.vendor_id == vendor_id && device.device_id == device_id)
+}
+
+fn read_device(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let id = read_config(bus, device, function, 0x00);
+    if id as u16 == INVALID_VENDOR {
+        return None;
+    }
+
+    let class = read_config(bus, device, function, 0x08);
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        interface: (class >> 8) as u8,
+    })
+}
@@ -0,0 +1,77 @@
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use libcore::port::{
+    read_u8,
+    write_u8,
+};
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+
+const ICW1_INIT: u8 = 0x11;
+const ICW4_8086: u8 = 0x01;
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// The IRQs of the legacy PICs are remapped behind the CPU exceptions
+pub const PIC1_OFFSET: u8 = 0x20;
+pub const PIC2_OFFSET: u8 = 0x28;
+const CASCADE_IRQ: u8 = 2;
+pub const IRQ_COUNT: u8 = 16;
+
+/// The number of acknowledged interrupts per IRQ
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static IRQ_COUNTERS: [AtomicU64; IRQ_COUNT as usize] = [NO_INTERRUPTS; IRQ_COUNT as usize];
+
+crate::driver!("pic", [], |_| {
+    init_pic();
+    Ok(())
+});
+
+/// This function remaps the IRQs of both 8259 PICs to the vectors 0x20 to 0x2F and masks all IRQs.
+/// The drivers unmask their IRQ after installing the handler.
+pub fn init_pic() {
+    unsafe {
+        write_u8(PIC1_COMMAND, ICW1_INIT);
+        write_u8(PIC2_COMMAND, ICW1_INIT);
+        write_u8(PIC1_DATA, PIC1_OFFSET);
+        write_u8(PIC2_DATA, PIC2_OFFSET);
+        write_u8(PIC1_DATA, 1 << CASCADE_IRQ); // The secondary PIC is connected to IRQ 2
+        write_u8(PIC2_DATA, CASCADE_IRQ); // The cascade identity of the secondary PIC
+        write_u8(PIC1_DATA, ICW4_8086);
+        write_u8(PIC2_DATA, ICW4_8086);
+
+        // Mask all IRQs except the cascade
+        write_u8(PIC1_DATA, !(1 << CASCADE_IRQ));
+        write_u8(PIC2_DATA, 0xFF);
+    }
+}
+
+/// This function unmasks the specified IRQ, so the PIC delivers it to the CPU
+pub fn unmask_irq(irq: u8) {
+    let (port, bit) = match irq {
+        0..=7 => (PIC1_DATA, irq),
+        _ => (PIC2_DATA, irq - 8),
+    };
+    unsafe { write_u8(port, read_u8(port) & !(1 << bit)) };
+}
+
+/// This function acknowledges the specified IRQ, it must be called at the end of every IRQ handler
+pub fn end_of_interrupt(irq: u8) {
+    IRQ_COUNTERS[irq as usize].fetch_add(1, Ordering::Relaxed);
+    unsafe {
+        if irq >= 8 {
+            write_u8(PIC2_COMMAND, END_OF_INTERRUPT);
+        }
+        write_u8(PIC1_COMMAND, END_OF_INTERRUPT);
+    }
+}
+
+/// This function returns the number of interrupts, which were raised by the specified IRQ
+pub fn interrupt_count(irq: u8) -> u64 {
+    IRQ_COUNTERS[irq as usize].load(Ordering::Relaxed)
+}
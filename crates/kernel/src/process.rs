@@ -0,0 +1,229 @@
+use crate::{
+    error::Error,
+    vmm::{
+        AddressSpace,
+        USER_SPACE_END,
+        USER_SPACE_START,
+    },
+};
+use alloc::vec::Vec;
+use libcore::{
+    address::VirtAddr,
+    elf::{
+        ElfFile,
+        ProgramHeader,
+        ET_DYN,
+        ET_EXEC,
+        PF_W,
+        PF_X,
+        PT_LOAD,
+        PT_PHDR,
+    },
+    paging::{
+        NO_EXECUTE,
+        PAGE_SIZE,
+        WRITABLE,
+    },
+    rng::random_u64,
+};
+
+/// Position-independent executables are loaded at the start of the user space
+pub const USER_IMAGE_BASE: u64 = USER_SPACE_START;
+
+/// The user stack is located at the end of the user space. The highest page stays unmapped, so
+/// stack underflows fault instead of leaving the user space.
+pub const USER_STACK_TOP: VirtAddr = VirtAddr::new_truncate(USER_SPACE_END - PAGE_SIZE);
+const USER_STACK_PAGES: u64 = 16;
+
+/// The types of the auxiliary vector entries, which are passed to user space (System V ABI)
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_RANDOM: u64 = 25;
+
+/// A loaded process with its address space and the initial register state
+pub struct Process {
+    pub address_space: AddressSpace,
+    pub entry: VirtAddr,
+    pub stack_pointer: VirtAddr,
+}
+
+/// This function loads the specified ELF executable into a new address space and creates the
+/// initial stack with the arguments, the environment and the auxiliary vector.
+pub fn load_process(data: &[u8], arguments: &[&str], environment: &[&str]) -> Result<Process, Error> {
+    let elf_file = ElfFile::parse(data)?;
+    let base_address = match elf_file.header.kind {
+        ET_EXEC => 0,
+        ET_DYN => USER_IMAGE_BASE,
+        kind => return Err(Error::UnsupportedProcessType(kind)),
+    };
+
+    let mut address_space = AddressSpace::new()?;
+    let mut program_headers_address = None;
+    for segment in elf_file.program_headers() {
+        let segment = segment?;
+        match segment.kind {
+            PT_LOAD => load_segment(&mut address_space, &elf_file, &segment, base_address)?,
+            PT_PHDR => program_headers_address = Some(base_address + segment.virtual_address),
+            _ => {}
+        }
+    }
+
+    // Without PT_PHDR segment, the program headers are found in the file data of a loadable segment
+    if program_headers_address.is_none() {
+        let offset = elf_file.header.program_header_offset;
+        for segment in elf_file.program_headers() {
+            let segment = segment?;
+            if segment.kind == PT_LOAD
+                && offset >= segment.offset
+                && segment
+                    .offset
+                    .checked_add(segment.file_size)
+                    .is_some_and(|end| offset < end)
+            {
+                program_headers_address =
+                    Some(base_address + segment.virtual_address + (offset - segment.offset));
+                break;
+            }
+        }
+    }
+
+    let entry = VirtAddr::try_new(base_address + elf_file.header.entry)
+        .ok_or(Error::InvalidSegmentAddress(elf_file.header.entry))?;
+    let auxiliary_vector = [
+        (AT_PHDR, program_headers_address.unwrap_or_default()),
+        (AT_PHENT, elf_file.header.program_header_entry_size as u64),
+        (AT_PHNUM, elf_file.header.program_header_count as u64),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, entry.as_u64()),
+    ];
+    let stack_pointer =
+        create_initial_stack(&mut address_space, arguments, environment, &auxiliary_vector)?;
+    Ok(Process {
+        address_space,
+        entry,
+        stack_pointer,
+    })
+}
+
+/// This function maps the pages of the specified segment and copies the file data of the segment.
+/// The remaining memory (like `.bss`) stays zeroed.
+fn load_segment(
+    address_space: &mut AddressSpace, elf_file: &ElfFile, segment: &ProgramHeader, base_address: u64,
+) -> Result<(), Error> {
+    let start = base_address + segment.virtual_address;
+    let end = start
+        .checked_add(segment.memory_size)
+        .ok_or(Error::InvalidSegmentAddress(start))?;
+    if start < USER_SPACE_START || end > stack_bottom() {
+        return Err(Error::InvalidSegmentAddress(start));
+    }
+
+    let mut flags = 0;
+    if segment.flags & PF_W != 0 {
+        flags |= WRITABLE;
+    }
+    if segment.flags & PF_X == 0 {
+        flags |= NO_EXECUTE;
+    }
+
+    // Pages, which are shared with the previous segment, are already mapped. These pages get the
+    // permissions of both segments, so they're writable or executable if one of the segments is.
+    let mut page = VirtAddr::new(start).align_down(PAGE_SIZE);
+    while page.as_u64() < end {
+        match address_space.lookup(page) {
+            Some((_, mapped_flags)) => {
+                let shared_flags =
+                    (flags | mapped_flags) & WRITABLE | flags & mapped_flags & NO_EXECUTE;
+                address_space.update_flags(page, shared_flags)?;
+            }
+            None => {
+                address_space.map_anonymous(page, flags)?;
+            }
+        }
+        page += PAGE_SIZE;
+    }
+    address_space.write_bytes(VirtAddr::new(start), elf_file.segment_data(segment)?)
+}
+
+/// This function creates the initial stack of the process as expected by the System V ABI. From the
+/// stack pointer upwards, the stack contains the argument count, the argument pointers, the
+/// environment pointers and the auxiliary vector, all terminated by a null entry. The strings and
+/// the 16 random bytes of `AT_RANDOM` are stored above.
+fn create_initial_stack(
+    address_space: &mut AddressSpace, arguments: &[&str], environment: &[&str],
+    auxiliary_vector: &[(u64, u64)],
+) -> Result<VirtAddr, Error> {
+    let stack_bottom = stack_bottom();
+    let mut page = VirtAddr::new(stack_bottom);
+    while page < USER_STACK_TOP {
+        address_space.map_anonymous(page, WRITABLE | NO_EXECUTE)?;
+        page += PAGE_SIZE;
+    }
+
+    let mut stack_pointer = USER_STACK_TOP.as_u64();
+    let mut push_bytes = |address_space: &mut AddressSpace, bytes: &[u8]| -> Result<u64, Error> {
+        stack_pointer = stack_pointer
+            .checked_sub(bytes.len() as u64)
+            .filter(|stack_pointer| *stack_pointer >= stack_bottom)
+            .ok_or(Error::ArgumentsTooLarge)?;
+        address_space.write_bytes(VirtAddr::new(stack_pointer), bytes)?;
+        Ok(stack_pointer)
+    };
+
+    // Copy the strings with the null terminator and the random bytes to the top of the stack
+    let mut push_strings = |address_space: &mut AddressSpace, strings: &[&str]| {
+        strings
+            .iter()
+            .map(|string| {
+                push_bytes(address_space, &[0])?;
+                push_bytes(address_space, string.as_bytes())
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    };
+    let argument_pointers = push_strings(address_space, arguments)?;
+    let environment_pointers = push_strings(address_space, environment)?;
+
+    // A constant seed would make the stack protector and the pointer guards of the process guessable
+    let mut random_bytes = [0; 16];
+    for chunk in random_bytes.chunks_mut(8) {
+        let value = random_u64().ok_or(Error::NoRandomGenerator)?;
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+    let random_pointer = push_bytes(address_space, &random_bytes)?;
+
+    let mut words = Vec::new();
+    words.push(arguments.len() as u64);
+    words.extend_from_slice(&argument_pointers);
+    words.push(0);
+    words.extend_from_slice(&environment_pointers);
+    words.push(0);
+    for (kind, value) in auxiliary_vector
+        .iter()
+        .chain(&[(AT_RANDOM, random_pointer)])
+    {
+        words.extend_from_slice(&[*kind, *value]);
+    }
+    words.extend_from_slice(&[AT_NULL, 0]);
+
+    // The stack pointer has to be aligned to 16 bytes at the argument count
+    let stack_pointer = stack_pointer
+        .checked_sub((words.len() * 8) as u64)
+        .map(|stack_pointer| stack_pointer & !0xF)
+        .filter(|stack_pointer| *stack_pointer >= stack_bottom)
+        .ok_or(Error::ArgumentsTooLarge)?;
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<_>>();
+    address_space.write_bytes(VirtAddr::new(stack_pointer), &bytes)?;
+    Ok(VirtAddr::new(stack_pointer))
+}
+
+#[inline]
+fn stack_bottom() -> u64 {
+    USER_STACK_TOP.as_u64() - USER_STACK_PAGES * PAGE_SIZE
+}
@@ -0,0 +1,249 @@
+//! The sampling profiler records the instruction pointer of the interrupted code with the periodic
+//! timer of the local APIC. The interrupt handler only stores the address into a fixed buffer, the
+//! samples are aggregated by the nearest exported kernel symbol, when the profile is shown. The
+//! timer of the local APIC is calibrated against the PIT, so the profiler is started after the
+//! timer driver and with interrupts enabled.
+
+use crate::{
+    error::Error,
+    interrupts::InterruptStackFrame,
+    msi,
+    symbols,
+    timer,
+};
+use alloc::{
+    collections::BTreeMap,
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicU64,
+    AtomicU8,
+    AtomicUsize,
+    Ordering,
+};
+use libcore::{
+    cmdline::CommandLine,
+    table::{
+        Alignment,
+        Column,
+        Table,
+    },
+};
+use log::{
+    info,
+    warn,
+};
+
+/// The option `profile[=<Hz>]`, which starts the profiler while booting with the specified sample
+/// frequency. The profile is dumped after the boot tasks.
+pub const PROFILE_OPTION: &str = "profile";
+pub const DEFAULT_SAMPLE_FREQUENCY: u64 = 1000;
+const MAX_SAMPLE_FREQUENCY: u64 = 10_000;
+
+/// The maximum number of samples. Later samples are dropped until the profile is reset.
+const MAX_SAMPLES: usize = 16384;
+
+/// The number of symbols, which are shown in the profile
+const MAX_PROFILE_ENTRIES: usize = 20;
+
+/// The registers of the timer of the local APIC
+const APIC_LVT_TIMER_REGISTER: u64 = 0x320;
+const APIC_INITIAL_COUNT_REGISTER: u64 = 0x380;
+const APIC_CURRENT_COUNT_REGISTER: u64 = 0x390;
+const APIC_DIVIDE_CONFIG_REGISTER: u64 = 0x3E0;
+const APIC_LVT_MASKED: u32 = 1 << 16;
+const APIC_LVT_PERIODIC: u32 = 1 << 17;
+const APIC_DIVIDE_BY_16: u32 = 0x3;
+
+/// The timer of the local APIC is calibrated over this number of PIT ticks
+const CALIBRATION_TICKS: u64 = 10;
+const MAX_CALIBRATION_SPINS: u64 = 1 << 32;
+
+/// The samples in user space are collected in one bucket
+const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_SAMPLE: AtomicU64 = AtomicU64::new(0);
+static SAMPLES: [AtomicU64; MAX_SAMPLES] = [NO_SAMPLE; MAX_SAMPLES];
+static SAMPLE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static DROPPED_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+static VECTOR: AtomicU8 = AtomicU8::new(0);
+static TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+static SAMPLE_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+static PROFILE_TABLE: Table = Table::new(&[
+    Column::new("Samples", 8, Alignment::Right),
+    Column::new("Percent", 7, Alignment::Right),
+    Column::new("Symbol", 40, Alignment::Left),
+]);
+
+/// The aggregated samples with the number of samples per symbol in descending order
+pub struct Profile {
+    pub entries: Vec<(&'static str, u64)>,
+    pub samples: u64,
+    pub dropped_samples: u64,
+}
+
+/// This function starts the profiler, if it's requested on the command line. Errors are logged,
+/// because the boot continues without profiler.
+pub fn init_profiler(command_line: &CommandLine) {
+    let frequency = match command_line.get(PROFILE_OPTION) {
+        Some(value) => value.parse().unwrap_or(0),
+        None if command_line.has_flag(PROFILE_OPTION) => DEFAULT_SAMPLE_FREQUENCY,
+        None => return,
+    };
+
+    match start_profiler(frequency) {
+        Ok(()) => {
+            info!(
+                "Started profiler with {} Hz (APIC Timer: {} kHz)\n",
+                frequency,
+                TIMER_FREQUENCY.load(Ordering::Relaxed) / 1000
+            )
+        }
+        Err(error) => warn!("Unable to start the profiler => {}\n", error),
+    }
+}
+
+/// This function starts sampling with the specified frequency in Hz. The vector is allocated and
+/// the timer is calibrated on the first start. If the profiler runs already, the frequency is
+/// changed.
+pub fn start_profiler(frequency: u64) -> Result<(), Error> {
+    if frequency == 0 || frequency > MAX_SAMPLE_FREQUENCY {
+        return Err(Error::InvalidSampleFrequency(frequency));
+    }
+
+    if VECTOR.load(Ordering::Acquire) == 0 {
+        let vector = msi::allocate_vector("profiler", sample_handler as u64)?;
+        VECTOR.store(vector, Ordering::Release);
+    }
+    if TIMER_FREQUENCY.load(Ordering::Relaxed) == 0 {
+        TIMER_FREQUENCY.store(calibrate_timer()?, Ordering::Relaxed);
+    }
+
+    let initial_count = (TIMER_FREQUENCY.load(Ordering::Relaxed) / frequency).max(1);
+    let vector = VECTOR.load(Ordering::Acquire) as u32;
+    unsafe {
+        msi::local_apic_register(APIC_DIVIDE_CONFIG_REGISTER).write_volatile(APIC_DIVIDE_BY_16);
+        msi::local_apic_register(APIC_LVT_TIMER_REGISTER).write_volatile(vector | APIC_LVT_PERIODIC);
+        msi::local_apic_register(APIC_INITIAL_COUNT_REGISTER)
+            .write_volatile(initial_count.min(u32::MAX as u64) as u32);
+    }
+    SAMPLE_FREQUENCY.store(frequency, Ordering::Relaxed);
+    Ok(())
+}
+
+/// This function stops sampling, the collected samples are kept
+pub fn stop_profiler() {
+    if VECTOR.load(Ordering::Acquire) == 0 {
+        return;
+    }
+
+    unsafe {
+        msi::local_apic_register(APIC_LVT_TIMER_REGISTER).write_volatile(APIC_LVT_MASKED);
+        msi::local_apic_register(APIC_INITIAL_COUNT_REGISTER).write_volatile(0);
+    }
+    SAMPLE_FREQUENCY.store(0, Ordering::Relaxed);
+}
+
+/// This function returns, whether the profiler is sampling
+#[inline]
+pub fn is_running() -> bool {
+    SAMPLE_FREQUENCY.load(Ordering::Relaxed) != 0
+}
+
+/// This function discards the collected samples
+pub fn reset_profile() {
+    SAMPLE_COUNT.store(0, Ordering::Release);
+    DROPPED_SAMPLES.store(0, Ordering::Relaxed);
+}
+
+/// This function aggregates the collected samples by the nearest exported kernel symbol. Samples
+/// without symbol are collected in the `<user>` and `<unknown>` buckets.
+pub fn profile() -> Profile {
+    let count = SAMPLE_COUNT.load(Ordering::Acquire).min(MAX_SAMPLES);
+    let mut buckets: BTreeMap<&'static str, u64> = BTreeMap::new();
+    for sample in &SAMPLES[..count] {
+        let address = sample.load(Ordering::Relaxed);
+        let name = match symbols::resolve_address(address) {
+            _ if address < USER_SPACE_END => "<user>",
+            Some((name, _)) => name,
+            None => "<unknown>",
+        };
+        *buckets.entry(name).or_default() += 1;
+    }
+
+    let mut entries: Vec<(&'static str, u64)> = buckets.into_iter().collect();
+    entries.sort_by(|(_, first), (_, second)| second.cmp(first));
+    Profile {
+        entries,
+        samples: count as u64,
+        dropped_samples: DROPPED_SAMPLES.load(Ordering::Relaxed),
+    }
+}
+
+/// This function writes the symbols with the most samples into the log, which is mirrored on the
+/// serial console
+pub fn dump_profile() {
+    let profile = profile();
+    info!("Profile with {} samples ({} dropped)\n", profile.samples, profile.dropped_samples);
+    info!("{}\n", PROFILE_TABLE.header());
+    info!("{}\n", PROFILE_TABLE.separator());
+    for (name, samples) in profile.entries.iter().take(MAX_PROFILE_ENTRIES) {
+        let permille = samples * 1000 / profile.samples.max(1);
+        info!(
+            "{}\n",
+            PROFILE_TABLE.row(&[
+                samples,
+                &format_args!("{}.{}%", permille / 10, permille % 10),
+                name
+            ])
+        );
+    }
+}
+
+/// This function measures the frequency of the timer of the local APIC in Hz. The timer counts down
+/// in one-shot mode with masked interrupt, while the PIT ticks.
+fn calibrate_timer() -> Result<u64, Error> {
+    unsafe {
+        msi::local_apic_register(APIC_DIVIDE_CONFIG_REGISTER).write_volatile(APIC_DIVIDE_BY_16);
+        msi::local_apic_register(APIC_LVT_TIMER_REGISTER).write_volatile(APIC_LVT_MASKED);
+    }
+
+    // Start at a tick boundary, so the measurement covers whole ticks
+    wait_for_tick(timer::ticks() + 1)?;
+    unsafe { msi::local_apic_register(APIC_INITIAL_COUNT_REGISTER).write_volatile(u32::MAX) };
+    wait_for_tick(timer::ticks() + CALIBRATION_TICKS)?;
+    let remaining = unsafe { msi::local_apic_register(APIC_CURRENT_COUNT_REGISTER).read_volatile() };
+    unsafe { msi::local_apic_register(APIC_INITIAL_COUNT_REGISTER).write_volatile(0) };
+
+    let elapsed = (u32::MAX - remaining) as u64;
+    Ok(elapsed * timer::TICKS_PER_SECOND / CALIBRATION_TICKS)
+}
+
+/// This function waits until the PIT reached the specified tick. If the PIT doesn't tick, this
+/// function returns an error instead of hanging.
+fn wait_for_tick(tick: u64) -> Result<(), Error> {
+    for _ in 0..MAX_CALIBRATION_SPINS {
+        if timer::ticks() >= tick {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Error::TimerNotTicking)
+}
+
+extern "x86-interrupt" fn sample_handler(frame: InterruptStackFrame) {
+    let index = SAMPLE_COUNT.load(Ordering::Relaxed);
+    match SAMPLES.get(index) {
+        Some(sample) => {
+            sample.store(frame.instruction_pointer, Ordering::Relaxed);
+            SAMPLE_COUNT.store(index + 1, Ordering::Release);
+        }
+        None => {
+            DROPPED_SAMPLES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    msi::end_of_interrupt(VECTOR.load(Ordering::Relaxed));
+}
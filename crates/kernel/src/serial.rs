@@ -0,0 +1,95 @@
+use libcore::{
+    kfmt::KWrite,
+    port::{
+        read_u8,
+        write_u8,
+    },
+};
+use libsync::Spinlock;
+
+pub const COM1: u16 = 0x3F8;
+pub const COM2: u16 = 0x2F8;
+
+/// The registers of the 16550 UART relative to the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// The serial console serializes the writes of the kernel and user space, so the output of
+/// concurrent writers isn't interleaved within one write.
+static SERIAL_LOCK: Spinlock<()> = Spinlock::new(());
+
+/// This function initializes COM1 with 115200 baud and the 8N1 frame format
+pub fn init_serial() {
+    init_port(COM1);
+}
+
+/// This function initializes the UART at the specified base port with 115200 baud and the 8N1 frame
+/// format
+pub fn init_port(port: u16) {
+    unsafe {
+        write_u8(port + INTERRUPT_ENABLE, 0x00);
+        write_u8(port + LINE_CONTROL, 0x80); // Enable the divisor latch
+        write_u8(port + DATA, 0x01); // Divisor 1 => 115200 baud
+        write_u8(port + INTERRUPT_ENABLE, 0x00);
+        write_u8(port + LINE_CONTROL, 0x03); // 8 data bits, no parity, one stop bit
+        write_u8(port + FIFO_CONTROL, 0xC7); // Enable and clear the FIFOs
+        write_u8(port + MODEM_CONTROL, 0x03); // DTR and RTS
+    }
+}
+
+/// This function writes the specified bytes to the serial console. Line feeds are translated into
+/// CR LF for terminals.
+pub fn write_bytes(bytes: &[u8]) {
+    let _guard = SERIAL_LOCK.lock();
+    for byte in bytes {
+        if *byte == b'\n' {
+            write_byte(COM1, b'\r');
+        }
+        write_byte(COM1, *byte);
+    }
+}
+
+/// The writer of the serial console for [libcore::kwrite], which is used on the fast paths of the
+/// kernel instead of `core::fmt`
+pub struct SerialWriter;
+
+impl KWrite for SerialWriter {
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        write_bytes(bytes);
+    }
+}
+
+/// This function writes the specified bytes without translation and without taking the lock of the
+/// serial console. It's used by the debugger, which may be entered while the lock is held.
+pub fn write_raw(port: u16, bytes: &[u8]) {
+    for byte in bytes {
+        write_byte(port, *byte);
+    }
+}
+
+/// This function waits for the next received byte of the UART at the specified base port
+pub fn read_byte(port: u16) -> u8 {
+    unsafe {
+        while read_u8(port + LINE_STATUS) & LINE_STATUS_DATA_READY == 0 {
+            core::hint::spin_loop();
+        }
+        read_u8(port + DATA)
+    }
+}
+
+fn write_byte(port: u16, byte: u8) {
+    unsafe {
+        while read_u8(port + LINE_STATUS) & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        write_u8(port + DATA, byte);
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{
+    acpi,
+    console,
+    diagnostics,
+    driver,
+    error::Error,
+    ext2,
+    frames,
+    hardening::{
+        self,
+        Mitigation,
+    },
+    heap,
+    interrupts,
+    keyboard::{
+        self,
+        Leds,
+    },
+    msi::{
+        self,
+        Msi,
+        MsiX,
+    },
+    page_cache,
+    pci,
+    pic,
+    profiler,
+};
+use alloc::{
+    string::{
+        String,
+        ToString,
+    },
+    vec::Vec,
+};
+use core::arch::x86_64::__cpuid;
+use libcore::{
+    boot_info::BootInfo,
+    cpu_features::CPU_FEATURES,
+    hexdump::{
+        find_unmapped,
+        mapped_slice,
+        HexDump,
+    },
+    keymap::KeyCode,
+    table::{
+        Alignment,
+        Column,
+        Table,
+    },
+};
+use libruntime::ResetType;
+
+/// The flag, which starts the interactive shell after booting the kernel
+pub const SHELL_OPTION: &str = "shell";
+
+const PROMPT: &str = "overflow> ";
+const MAX_LINE_LENGTH: usize = 256;
+const DEFAULT_HEXDUMP_LENGTH: u64 = 256;
+const MAX_HEXDUMP_LENGTH: u64 = 4096;
+
+macro_rules! print {
+    ($($argument:tt)*) => {
+        console::print(format_args!($($argument)*))
+    };
+}
+
+/// A command of the shell, which is called with the arguments after the command name
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    execute: fn(&BootInfo, &[&str]) -> Result<(), Error>,
+}
+
+#[rustfmt::skip]
+static COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "help", description: "Lists all commands", execute: help },
+    Command { name: "meminfo", usage: "meminfo", description: "Shows the heap, frame and page cache statistics", execute: meminfo },
+    Command { name: "lspci", usage: "lspci", description: "Lists all PCI functions", execute: lspci },
+    Command { name: "lsdrv", usage: "lsdrv", description: "Lists the drivers with their init result", execute: lsdrv },
+    Command { name: "lsirq", usage: "lsirq", description: "Lists the installed IRQ handlers", execute: lsirq },
+    Command { name: "cat", usage: "cat <path>", description: "Prints a file of the root file system or the initrd", execute: cat },
+    Command { name: "ls", usage: "ls [path]", description: "Lists a directory of the root file system", execute: ls },
+    Command { name: "mkdir", usage: "mkdir <path>", description: "Creates a directory on the root file system", execute: mkdir },
+    Command { name: "write", usage: "write <path> <text>", description: "Writes the text into a file of the root file system", execute: write },
+    Command { name: "hexdump", usage: "hexdump <address> [length]", description: "Dumps a memory range", execute: hexdump },
+    Command { name: "keyboard", usage: "keyboard [leds|typematic|set] ...", description: "Configures the keyboard", execute: configure_keyboard },
+    Command { name: "cpuinfo", usage: "cpuinfo", description: "Shows the CPU model and features", execute: cpuinfo },
+    Command { name: "mitigations", usage: "mitigations [<name> on|off]", description: "Shows or toggles the mitigations", execute: mitigations },
+    Command { name: "profile", usage: "profile [start [Hz]|stop|reset]", description: "Controls or shows the profiler", execute: profile },
+    Command { name: "trace", usage: "trace", description: "Emits the boot trace over serial", execute: trace },
+    Command { name: "date", usage: "date", description: "Shows the time of the real-time clock", execute: date },
+    Command { name: "reboot", usage: "reboot", description: "Resets the system", execute: reboot },
+    Command { name: "shutdown", usage: "shutdown", description: "Powers off the system", execute: shutdown },
+];
+
+static PCI_TABLE: Table = Table::new(&[
+    Column::new("Address", 7, Alignment::Left),
+    Column::new("Vendor", 6, Alignment::Right),
+    Column::new("Device", 6, Alignment::Right),
+    Column::new("Class", 8, Alignment::Right),
+    Column::new("Name", 24, Alignment::Left),
+    Column::new("Interrupt", 9, Alignment::Left),
+]);
+
+static DRIVER_TABLE: Table = Table::new(&[
+    Column::new("Driver", 12, Alignment::Left),
+    Column::new("kcycles", 10, Alignment::Right),
+    Column::new("Result", 40, Alignment::Left),
+]);
+
+static DIRECTORY_TABLE: Table = Table::new(&[
+    Column::new("Inode", 8, Alignment::Right),
+    Column::new("Type", 9, Alignment::Left),
+    Column::new("Mode", 4, Alignment::Right),
+    Column::new("Size", 10, Alignment::Right),
+    Column::new("Name", 32, Alignment::Left),
+]);
+
+static IRQ_TABLE: Table = Table::new(&[
+    Column::new("IRQ", 3, Alignment::Right),
+    Column::new("Vector", 6, Alignment::Right),
+    Column::new("Count", 12, Alignment::Right),
+    Column::new("Driver", 16, Alignment::Left),
+]);
+
+/// This function runs the interactive shell on the console. The commands are read from the PS/2
+/// keyboard, so the keyboard must be initialized before. The task never finishes.
+pub async fn run_shell(boot_info: &'static BootInfo) {
+    print!("OverflowOS Kernel Shell, type 'help' for a list of commands\n");
+    let mut line = String::new();
+    loop {
+        print!("{}", PROMPT);
+        read_line(&mut line).await;
+        if let Err(error) = execute_line(boot_info, &line) {
+            print!("{}\n", error);
+        }
+    }
+}
+
+/// This function reads a line from the keyboard and echoes the typed characters
+async fn read_line(line: &mut String) {
+    line.clear();
+    loop {
+        match keyboard::next_key().await {
+            KeyCode::Enter => {
+                print!("\n");
+                return;
+            }
+            KeyCode::Backspace => {
+                if line.pop().is_some() {
+                    let _ = console::erase_char();
+                }
+            }
+            KeyCode::Char(char) if line.len() < MAX_LINE_LENGTH => {
+                line.push(char);
+                print!("{}", char);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn execute_line(boot_info: &BootInfo, line: &str) -> Result<(), Error> {
+    let arguments = line.split_whitespace().collect::<Vec<_>>();
+    let Some((name, arguments)) = arguments.split_first() else {
+        return Ok(());
+    };
+
+    let command = COMMANDS
+        .iter()
+        .find(|command| command.name == *name)
+        .ok_or_else(|| Error::UnknownCommand(name.to_string()))?;
+    (command.execute)(boot_info, arguments)
+}
+
+/// This function parses a number with `0x` prefix as hexadecimal number, otherwise as decimal number
+fn parse_number(string: &str) -> Result<u64, Error> {
+    match string.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => string.parse(),
+    }
+    .map_err(|_| Error::InvalidNumber(string.to_string()))
+}
+
+fn help(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    for command in COMMANDS {
+        print!("{:<28} {}\n", command.usage, command.description);
+    }
+    Ok(())
+}
+
+fn meminfo(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    print!("Kernel Heap: {}\n", heap::heap_report());
+    match frames::frame_report() {
+        Some(report) => print!("Frame Allocator: {}\n", report),
+        None => print!("Frame Allocator: Not available\n"),
+    }
+    if let Some((allocated_frames, remaining_frames)) = frames::physical_frames() {
+        print!("Physical Frames: {} allocated, {} remaining\n", allocated_frames, remaining_frames);
+    }
+    print!("Page Cache: {}\n", page_cache::cache_report());
+    Ok(())
+}
+
+fn lspci(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    print!("{}\n{}\n", PCI_TABLE.header(), PCI_TABLE.separator());
+    for device in pci::scan_devices() {
+        print!(
+            "{}\n",
+            PCI_TABLE.row(&[
+                &format_args!("{:02X}:{:02X}.{}", device.bus, device.device, device.function),
+                &format_args!("0x{:04X}", device.vendor_id),
+                &format_args!("0x{:04X}", device.device_id),
+                &format_args!(
+                    "{:02X}:{:02X}:{:02X}",
+                    device.class, device.subclass, device.interface
+                ),
+                &device.class_name(),
+                &interrupt_kind(&device),
+            ])
+        );
+    }
+    Ok(())
+}
+
+/// This function returns the most capable interrupt mechanism, which is supported by the function
+fn interrupt_kind(device: &pci::PciDevice) -> &'static str {
+    if MsiX::find(device).is_some() {
+        "MSI-X"
+    } else if Msi::find(device).is_some() {
+        "MSI"
+    } else {
+        "INTx"
+    }
+}
+
+fn lsdrv(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    print!("{}\n{}\n", DRIVER_TABLE.header(), DRIVER_TABLE.separator());
+    for report in driver::driver_reports() {
+        let result = match &report.result {
+            Ok(()) => "Ok".to_string(),
+            Err(error) => error.to_string(),
+        };
+        print!("{}\n", DRIVER_TABLE.row(&[&report.name, &(report.cycles / 1000), &result]));
+    }
+    Ok(())
+}
+
+fn lsirq(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    print!("{}\n{}\n", IRQ_TABLE.header(), IRQ_TABLE.separator());
+    for (irq, name) in interrupts::irq_handlers() {
+        print!(
+            "{}\n",
+            IRQ_TABLE.row(&[
+                &irq,
+                &format_args!("0x{:02X}", pic::PIC1_OFFSET + irq),
+                &pic::interrupt_count(irq),
+                &name,
+            ])
+        );
+    }
+    for (vector, name) in msi::allocated_vectors() {
+        print!(
+            "{}\n",
+            IRQ_TABLE.row(&[
+                &"MSI",
+                &format_args!("0x{:02X}", vector),
+                &msi::interrupt_count(vector),
+                &name,
+            ])
+        );
+    }
+    Ok(())
+}
+
+/// Without root file system, the files are read from the initrd
+fn cat(boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    let [path] = arguments else {
+        return Err(Error::InvalidUsage("cat <path>"));
+    };
+
+    if ext2::has_root_file_system() {
+        let data = ext2::with_root_file_system(|file_system| file_system.read_file(path))?;
+        print!("{}\n", String::from_utf8_lossy(&data));
+        return Ok(());
+    }
+
+    let initrd = boot_info.initrd().ok_or(Error::NoInitrd)?;
+    let file = initrd
+        .find(path)?
+        .ok_or_else(|| Error::FileNotFound(path.to_string()))?;
+    print!("{}\n", String::from_utf8_lossy(file.data));
+    Ok(())
+}
+
+fn ls(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    let path = match arguments {
+        [] => "/",
+        [path] => path,
+        _ => return Err(Error::InvalidUsage("ls [path]")),
+    };
+
+    let entries = ext2::with_root_file_system(|file_system| {
+        let mut entries = Vec::new();
+        for entry in file_system.read_directory(path)? {
+            entries.push((file_system.inode_metadata(entry.inode)?, entry.name));
+        }
+        Ok(entries)
+    })?;
+    print!("{}\n{}\n", DIRECTORY_TABLE.header(), DIRECTORY_TABLE.separator());
+    for (metadata, name) in entries {
+        print!(
+            "{}\n",
+            DIRECTORY_TABLE.row(&[
+                &metadata.inode,
+                &metadata.kind.name(),
+                &format_args!("{:o}", metadata.permissions),
+                &metadata.size,
+                &name,
+            ])
+        );
+    }
+    Ok(())
+}
+
+fn mkdir(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    let [path] = arguments else {
+        return Err(Error::InvalidUsage("mkdir <path>"));
+    };
+    ext2::with_root_file_system(|file_system| file_system.create_directory(path))
+}
+
+/// The arguments after the path are joined with spaces and written with a trailing newline
+fn write(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    let [path, text @ ..] = arguments else {
+        return Err(Error::InvalidUsage("write <path> <text>"));
+    };
+
+    let mut data = text.join(" ");
+    data.push('\n');
+    ext2::with_root_file_system(|file_system| file_system.write_file(path, data.as_bytes()))
+}
+
+/// The pages of the range are checked before the dump, so unmapped addresses don't fault
+fn hexdump(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    let (address, length) = match arguments {
+        [address] => (parse_number(address)?, DEFAULT_HEXDUMP_LENGTH),
+        [address, length] => (parse_number(address)?, parse_number(length)?),
+        _ => return Err(Error::InvalidUsage("hexdump <address> [length]")),
+    };
+    let length = length.min(MAX_HEXDUMP_LENGTH) as usize;
+    if let Some(unmapped_address) = find_unmapped(address, length) {
+        return Err(Error::UnmappedAddress(unmapped_address));
+    }
+
+    let bytes = unsafe { mapped_slice(address, length) }.unwrap_or_default();
+    print!("{}", HexDump::new(address, bytes));
+    Ok(())
+}
+
+/// The keyboard command shows the keyboard state without arguments, otherwise it changes the LEDs
+/// (`leds caps,num,scroll` or `leds none`), the typematic delay and rate (`typematic <delay in ms>
+/// <characters per second>`) or the scancode set (`set <1-3>`).
+fn configure_keyboard(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    const USAGE: &str =
+        "keyboard [leds <caps,num,scroll|none> | typematic <delay> <rate> | set <1-3>]";
+    match arguments {
+        [] => {
+            let leds = keyboard::leds();
+            print!("Scancode Set: {}\n", keyboard::scancode_set()?);
+            print!(
+                "LEDs:         Caps Lock: {}, Num Lock: {}, Scroll Lock: {}\n",
+                leds.caps_lock, leds.num_lock, leds.scroll_lock
+            );
+            Ok(())
+        }
+        ["leds", leds] => keyboard::set_leds(parse_leds(leds).ok_or(Error::InvalidUsage(USAGE))?),
+        ["typematic", delay, rate] => {
+            let delay = parse_number(delay)?.min(u16::MAX as u64) as u16;
+            let rate = parse_number(rate)?.min(u16::MAX as u64) as u16;
+            keyboard::set_typematic(delay, rate)
+        }
+        ["set", set] => keyboard::set_scancode_set(parse_number(set)?.min(u8::MAX as u64) as u8),
+        _ => Err(Error::InvalidUsage(USAGE)),
+    }
+}
+
+/// This function parses a comma-separated list of LEDs or `none`
+fn parse_leds(list: &str) -> Option<Leds> {
+    let mut leds = Leds::default();
+    for led in list.split(',') {
+        match led {
+            "caps" => leds.caps_lock = true,
+            "num" => leds.num_lock = true,
+            "scroll" => leds.scroll_lock = true,
+            "none" => {}
+            _ => return None,
+        }
+    }
+    Some(leds)
+}
+
+fn cpuinfo(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    let vendor = unsafe { __cpuid(0) };
+    let vendor = register_string(&[vendor.ebx, vendor.edx, vendor.ecx]);
+    print!("Vendor:   {}\n", vendor);
+
+    if unsafe { __cpuid(0x8000_0000) }.eax >= 0x8000_0004 {
+        let brand = (0x8000_0002..=0x8000_0004)
+            .map(|leaf| unsafe { __cpuid(leaf) })
+            .flat_map(|result| [result.eax, result.ebx, result.ecx, result.edx])
+            .collect::<Vec<_>>();
+        print!("Model:    {}\n", register_string(&brand));
+    }
+
+    // The extended family and model are only used by the families 6 and 15
+    let signature = unsafe { __cpuid(1) }.eax;
+    let mut family = (signature >> 8) & 0xF;
+    let mut model = (signature >> 4) & 0xF;
+    if family == 0xF {
+        family += (signature >> 20) & 0xFF;
+    }
+    if family == 0x6 || family >= 0xF {
+        model |= ((signature >> 16) & 0xF) << 4;
+    }
+    print!("Family:   0x{:X}, Model: 0x{:X}, Stepping: {}\n", family, model, signature & 0xF);
+
+    print!("Features:\n");
+    for feature in CPU_FEATURES {
+        print!(
+            "  {:<8} {:<30} {}\n",
+            feature.name,
+            feature.description,
+            if feature.is_supported() { "Yes" } else { "No" }
+        );
+    }
+    Ok(())
+}
+
+/// This function converts the ASCII characters, which are stored in the CPUID registers, to a string
+fn register_string(registers: &[u32]) -> String {
+    let bytes = registers
+        .iter()
+        .flat_map(|register| register.to_le_bytes())
+        .filter(|byte| *byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+fn mitigations(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    const USAGE: &str = "mitigations [<name> on|off]";
+    match arguments {
+        [] => {}
+        [name, state] => {
+            let mitigation = Mitigation::from_name(name)
+                .ok_or_else(|| Error::UnknownMitigation(name.to_string()))?;
+            let enabled = match *state {
+                "on" => true,
+                "off" => false,
+                _ => return Err(Error::InvalidUsage(USAGE)),
+            };
+            hardening::set_mitigation(mitigation, enabled)?;
+        }
+        _ => return Err(Error::InvalidUsage(USAGE)),
+    }
+
+    for mitigation in Mitigation::ALL {
+        print!("{:<6} {}\n", mitigation.name(), hardening::mitigation_state(mitigation));
+    }
+    Ok(())
+}
+
+fn profile(_boot_info: &BootInfo, arguments: &[&str]) -> Result<(), Error> {
+    const USAGE: &str = "profile [start [Hz]|stop|reset]";
+    match arguments {
+        [] => profiler::dump_profile(),
+        ["start"] => profiler::start_profiler(profiler::DEFAULT_SAMPLE_FREQUENCY)?,
+        ["start", frequency] => profiler::start_profiler(parse_number(frequency)?)?,
+        ["stop"] => profiler::stop_profiler(),
+        ["reset"] => profiler::reset_profile(),
+        _ => return Err(Error::InvalidUsage(USAGE)),
+    }
+    Ok(())
+}
+
+fn trace(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    diagnostics::dump_boot_trace();
+    Ok(())
+}
+
+fn date(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    let (time, capabilities) = libruntime::runtime_services()?.time()?;
+    print!("{} (Resolution: {} Hz)\n", time, capabilities.resolution);
+    Ok(())
+}
+
+fn reboot(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    print!("Rebooting the system\n");
+
+    // The reset of the firmware is preferred, the PS/2 controller is only used as fallback
+    if let Ok(runtime_services) = libruntime::runtime_services() {
+        libgraphics::log::close_persistent_log();
+        runtime_services.reset(ResetType::Cold);
+    }
+    keyboard::reset_system();
+    Err(Error::ResetFailed)
+}
+
+fn shutdown(_boot_info: &BootInfo, _arguments: &[&str]) -> Result<(), Error> {
+    acpi::power_off()
+}
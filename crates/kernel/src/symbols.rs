@@ -0,0 +1,100 @@
+use crate::error::Error;
+use alloc::string::ToString;
+use core::{
+    alloc::Layout,
+    ptr,
+    slice,
+};
+use libcore::module_abi::ExportedSymbol;
+use log::Level;
+
+extern "C" {
+    static __start_kernel_symbols: ExportedSymbol;
+    static __stop_kernel_symbols: ExportedSymbol;
+}
+
+/// This macro exports the specified function to kernel modules. The symbol is placed into the
+/// `kernel_symbols` section together with the hash of its name, its signature and the current ABI
+/// version. The function is cast to the signature, so the signature can't differ from the function.
+macro_rules! export_symbol {
+    ($symbol:ident: $signature:ty) => {
+        const _: () = {
+            #[used]
+            #[link_section = "kernel_symbols"]
+            static EXPORTED_SYMBOL: libcore::module_abi::ExportedSymbol =
+                libcore::module_abi::ExportedSymbol {
+                    name: stringify!($symbol),
+                    address: $symbol as $signature as *const (),
+                    abi_hash: libcore::module_abi::symbol_abi_hash(
+                        stringify!($symbol),
+                        stringify!($signature),
+                    ),
+                };
+        };
+    };
+}
+
+/// This function returns all symbols, which are exported with the `export_symbol!` macro
+pub fn exported_symbols() -> &'static [ExportedSymbol] {
+    unsafe {
+        let start = &__start_kernel_symbols as *const ExportedSymbol;
+        let stop = &__stop_kernel_symbols as *const ExportedSymbol;
+        slice::from_raw_parts(start, stop.offset_from(start) as usize)
+    }
+}
+
+/// This function returns the address of the exported kernel symbol with the specified name. If the
+/// symbol was exported with another signature than the module imports it with (the ABI hashes
+/// differ), the lookup is refused.
+pub fn lookup_symbol(name: &str, abi_hash: u64) -> Result<u64, Error> {
+    let symbol = exported_symbols()
+        .iter()
+        .find(|symbol| symbol.name == name)
+        .ok_or_else(|| Error::UnresolvedSymbol(name.to_string()))?;
+
+    if symbol.abi_hash != abi_hash {
+        return Err(Error::SymbolAbiMismatch(name.to_string()));
+    }
+    Ok(symbol.address as u64)
+}
+
+/// This function returns the name of the nearest exported symbol below the specified address and
+/// the offset of the address into the symbol.
+pub fn resolve_address(address: u64) -> Option<(&'static str, u64)> {
+    exported_symbols()
+        .iter()
+        .filter(|symbol| symbol.address as u64 <= address)
+        .max_by_key(|symbol| symbol.address as u64)
+        .map(|symbol| (symbol.name, address - symbol.address as u64))
+}
+
+#[no_mangle]
+pub extern "C" fn kernel_log(level: usize, message: *const u8, length: usize) {
+    let message = unsafe { slice::from_raw_parts(message, length) };
+    let level = match level {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    };
+    log::log!(level, "{}\n", core::str::from_utf8(message).unwrap_or("<invalid UTF-8>"));
+}
+export_symbol!(kernel_log: extern "C" fn(usize, *const u8, usize));
+
+#[no_mangle]
+pub extern "C" fn kernel_alloc(size: usize, align: usize) -> *mut u8 {
+    match Layout::from_size_align(size, align) {
+        Ok(layout) => unsafe { alloc::alloc::alloc(layout) },
+        Err(_) => ptr::null_mut(),
+    }
+}
+export_symbol!(kernel_alloc: extern "C" fn(usize, usize) -> *mut u8);
+
+#[no_mangle]
+pub extern "C" fn kernel_free(address: *mut u8, size: usize, align: usize) {
+    if let Ok(layout) = Layout::from_size_align(size, align) {
+        unsafe { alloc::alloc::dealloc(address, layout) };
+    }
+}
+export_symbol!(kernel_free: extern "C" fn(*mut u8, usize, usize));
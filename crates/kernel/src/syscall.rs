@@ -0,0 +1,140 @@
+use crate::{
+    gdt::{
+        KERNEL_CODE_SELECTOR,
+        TSS,
+        USER_DATA_SELECTOR,
+    },
+    hardening::with_user_access,
+    serial,
+    vmm,
+};
+use core::arch::global_asm;
+use libcore::{
+    address::VirtAddr,
+    registers::{
+        read_msr,
+        write_msr,
+        EFER_SYSCALL_ENABLE,
+        IA32_EFER,
+        IA32_FMASK,
+        IA32_LSTAR,
+        IA32_STAR,
+        RFLAGS_ALIGNMENT_CHECK,
+        RFLAGS_DIRECTION,
+        RFLAGS_INTERRUPT,
+        RFLAGS_TRAP,
+    },
+};
+
+/// The numbers of the system calls, which are compatible with Linux
+pub const SYS_WRITE: u64 = 1;
+
+const STDOUT: u64 = 1;
+const STDERR: u64 = 2;
+
+/// The error numbers, which are returned negated by failed system calls (like Linux)
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+/// The user buffer is copied in chunks of this size into the kernel, so no allocation is needed
+const WRITE_CHUNK_SIZE: usize = 256;
+
+/// The stack pointer of user space is saved here, until the kernel stack is loaded
+static mut USER_STACK_POINTER: u64 = 0;
+
+extern "C" {
+    fn syscall_entry();
+}
+
+// The entry of the SYSCALL instruction switches to the kernel stack of the TSS, saves the registers,
+// which are clobbered by the dispatcher, and calls the dispatcher with the number in RAX and the
+// arguments in RDI, RSI and RDX. RCX and R11 hold the return address and the flags for SYSRET.
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "mov [rip + {user_stack}], rsp",
+    "mov rsp, [rip + {tss} + 4]",
+    "push qword ptr [rip + {user_stack}]",
+    "push rcx",
+    "push r11",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push r8",
+    "push r9",
+    "push r10",
+    "sub rsp, 8",
+    "mov rcx, rdx",
+    "mov rdx, rsi",
+    "mov rsi, rdi",
+    "mov rdi, rax",
+    "call {dispatch}",
+    "add rsp, 8",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop r11",
+    "pop rcx",
+    "pop rsp",
+    "sysretq",
+    user_stack = sym USER_STACK_POINTER,
+    tss = sym TSS,
+    dispatch = sym syscall_dispatch,
+);
+
+/// This function enables the SYSCALL instruction and installs the system call entry. Interrupts,
+/// single-stepping and the AC flag are masked on entry, so SMAP stays active in the kernel.
+pub fn init_syscalls() {
+    unsafe {
+        write_msr(IA32_EFER, read_msr(IA32_EFER) | EFER_SYSCALL_ENABLE);
+        write_msr(
+            IA32_STAR,
+            (KERNEL_CODE_SELECTOR as u64) << 32 | ((USER_DATA_SELECTOR - 8) as u64) << 48,
+        );
+        write_msr(IA32_LSTAR, syscall_entry as u64);
+        write_msr(
+            IA32_FMASK,
+            RFLAGS_INTERRUPT | RFLAGS_TRAP | RFLAGS_DIRECTION | RFLAGS_ALIGNMENT_CHECK,
+        );
+    }
+}
+
+extern "sysv64" fn syscall_dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    match number {
+        SYS_WRITE => sys_write(arg0, arg1, arg2 as usize),
+        _ => -ENOSYS,
+    }
+}
+
+/// This function writes the buffer of the calling process to the kernel console. The buffer is
+/// validated against the user space of the active address space before it's copied into the kernel.
+/// Returns the number of written bytes.
+fn sys_write(fd: u64, buffer: u64, length: usize) -> i64 {
+    if fd != STDOUT && fd != STDERR {
+        return -EBADF;
+    }
+    let Some(buffer) = VirtAddr::try_new(buffer) else {
+        return -EFAULT;
+    };
+    if !vmm::is_user_accessible(buffer, length as u64, false) {
+        return -EFAULT;
+    }
+
+    let mut chunk = [0; WRITE_CHUNK_SIZE];
+    for offset in (0..length).step_by(WRITE_CHUNK_SIZE) {
+        let size = (length - offset).min(WRITE_CHUNK_SIZE);
+        with_user_access(|| unsafe {
+            core::ptr::copy_nonoverlapping(
+                (buffer + offset as u64).as_ptr::<u8>(),
+                chunk.as_mut_ptr(),
+                size,
+            )
+        });
+        serial::write_bytes(&chunk[..size]);
+    }
+    length as i64
+}
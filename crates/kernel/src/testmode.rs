@@ -0,0 +1,204 @@
+//! The test mode is used by the QEMU test harness of the image tool. All log records are mirrored
+//! with sequence numbers into a memory buffer and the kernel asserts the milestones of the boot in
+//! their order. After boot, the buffer is dumped over the serial console in a line-based format and
+//! QEMU is exited with the result through the `isa-debug-exit` device, so the test runner can
+//! validate the expected milestones.
+
+use crate::serial;
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    Ordering,
+};
+use libcore::{
+    cmdline::CommandLine,
+    port::write_u32,
+};
+use libsync::Spinlock;
+use log::{
+    error,
+    Level,
+    Record,
+};
+
+/// The flag, which enables the test mode
+pub const TEST_MODE_OPTION: &str = "testmode";
+
+/// The milestones of the boot in the order, in which they are asserted by the kernel
+pub static BOOT_MILESTONES: &[&str] = &[
+    "console",
+    "descriptor-tables",
+    "frame-allocator",
+    "syscalls",
+    "fpu",
+    "drivers",
+    "boot-tasks",
+];
+
+/// The maximum number of captured records. Older records are dropped, if the buffer is full.
+const MAX_CAPTURED_RECORDS: usize = 2048;
+
+/// The port of the `isa-debug-exit` device of QEMU. QEMU exits with `(value << 1) | 1`.
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+const DEBUG_EXIT_SUCCESS: u32 = 0x10;
+const DEBUG_EXIT_FAILURE: u32 = 0x11;
+
+static TEST_MODE: AtomicBool = AtomicBool::new(false);
+static TEST_FAILED: AtomicBool = AtomicBool::new(false);
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+static DROPPED_RECORDS: AtomicU64 = AtomicU64::new(0);
+static CAPTURE: Spinlock<LogCapture> = Spinlock::new(LogCapture {
+    records: Vec::new(),
+    reached_milestones: 0,
+});
+
+/// A record of the log capture
+enum CapturedRecord {
+    Log {
+        sequence: u64,
+        level: Level,
+        message: String,
+    },
+    Milestone {
+        sequence: u64,
+        name: &'static str,
+    },
+}
+
+struct LogCapture {
+    records: Vec<CapturedRecord>,
+    reached_milestones: usize,
+}
+
+impl LogCapture {
+    fn push(&mut self, record: CapturedRecord) {
+        if self.records.len() == MAX_CAPTURED_RECORDS {
+            self.records.remove(0);
+            DROPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.records.push(record);
+    }
+}
+
+/// This function enables the test mode and installs the log capture, if the test mode is requested
+/// on the command line. The capture works without console, so the logger is installed if needed.
+pub fn init_test_mode(command_line: &CommandLine) {
+    if !command_line.has_flag(TEST_MODE_OPTION) {
+        return;
+    }
+
+    CAPTURE.lock().records.reserve(MAX_CAPTURED_RECORDS);
+    libgraphics::log::set_log_hook(capture_record);
+    let _ = libgraphics::log::install_logger();
+    TEST_MODE.store(true, Ordering::Release);
+}
+
+/// This function returns, whether the kernel runs in test mode
+#[inline]
+pub fn is_test_mode() -> bool {
+    TEST_MODE.load(Ordering::Acquire)
+}
+
+/// This function asserts, that the boot reached the specified milestone in the expected order. A
+/// milestone, which is unknown or out of order, fails the test run. The milestone is recorded into
+/// the boot trace, even without test mode.
+pub fn assert_boot_milestone(name: &'static str) {
+    libcore::trace::trace_instant(name);
+    if !is_test_mode() {
+        return;
+    }
+
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let mut capture = CAPTURE.lock();
+    let expected = BOOT_MILESTONES.get(capture.reached_milestones).copied();
+    capture.push(CapturedRecord::Milestone { sequence, name });
+    if expected == Some(name) {
+        capture.reached_milestones += 1;
+        return;
+    }
+    drop(capture);
+
+    TEST_FAILED.store(true, Ordering::Release);
+    error!(
+        "Boot milestone '{}' reached, but '{}' was expected\n",
+        name,
+        expected.unwrap_or("<none>")
+    );
+}
+
+/// This function dumps the captured records and the result over the serial console and exits QEMU
+/// with the result. The test run fails, if a milestone wasn't reached. If the kernel doesn't run in
+/// QEMU with the debug exit device, this function returns.
+pub fn finish_test_run() {
+    if !is_test_mode() {
+        return;
+    }
+
+    let capture = CAPTURE.lock();
+    let passed =
+        !TEST_FAILED.load(Ordering::Acquire) && capture.reached_milestones == BOOT_MILESTONES.len();
+
+    serial::write_bytes(b"@@TEST-BEGIN\n");
+    serial::write_bytes(format!("@@EXPECTED {}\n", BOOT_MILESTONES.join(",")).as_bytes());
+    for record in &capture.records {
+        let line = match record {
+            CapturedRecord::Log {
+                sequence,
+                level,
+                message,
+            } => format!("@@LOG {} {} {}\n", sequence, level, message),
+            CapturedRecord::Milestone { sequence, name } => {
+                format!("@@MILESTONE {} {}\n", sequence, name)
+            }
+        };
+        serial::write_bytes(line.as_bytes());
+    }
+    serial::write_bytes(
+        format!(
+            "@@TEST-END {} (Milestones: {}/{}, Dropped: {})\n",
+            if passed { "PASSED" } else { "FAILED" },
+            capture.reached_milestones,
+            BOOT_MILESTONES.len(),
+            DROPPED_RECORDS.load(Ordering::Relaxed)
+        )
+        .as_bytes(),
+    );
+    drop(capture);
+
+    let value = if passed {
+        DEBUG_EXIT_SUCCESS
+    } else {
+        DEBUG_EXIT_FAILURE
+    };
+    unsafe { write_u32(DEBUG_EXIT_PORT, value) };
+}
+
+/// The hook of the logger, which mirrors the records into the capture. Records of an interrupt
+/// handler, which interrupted the capture, are dropped instead of deadlocking.
+fn capture_record(record: &Record) {
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let message = record.args().to_string();
+
+    // The records are dumped line by line, so line feeds inside of a message are escaped
+    let message = message.trim_end_matches('\n').replace('\n', "\\n");
+    match CAPTURE.try_lock() {
+        Some(mut capture) => {
+            capture.push(CapturedRecord::Log {
+                sequence,
+                level: record.level(),
+                message,
+            })
+        }
+        None => {
+            DROPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
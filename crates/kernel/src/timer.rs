@@ -0,0 +1,85 @@
+use crate::{
+    interrupts::{
+        set_irq_handler,
+        InterruptStackFrame,
+    },
+    pic,
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+};
+use libcore::port::write_u8;
+
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_FREQUENCY: u64 = 1_193_182;
+const TIMER_IRQ: u8 = 0;
+
+/// The timer interrupt is raised every millisecond
+pub const TICKS_PER_SECOND: u64 = 1000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+crate::driver!("timer", ["pic"], |_| {
+    init_timer();
+    Ok(())
+});
+
+/// This function programs channel 0 of the PIT as rate generator with 1000 Hz and unmasks the
+/// timer IRQ. The PICs must be initialized before.
+pub fn init_timer() {
+    let divisor = (PIT_FREQUENCY / TICKS_PER_SECOND) as u16;
+    unsafe {
+        write_u8(PIT_COMMAND, 0x34); // Channel 0, low and high byte, rate generator
+        write_u8(PIT_CHANNEL0, divisor as u8);
+        write_u8(PIT_CHANNEL0, (divisor >> 8) as u8);
+    }
+    set_irq_handler(TIMER_IRQ, "timer", timer_handler as u64);
+    pic::unmask_irq(TIMER_IRQ);
+}
+
+extern "x86-interrupt" fn timer_handler(_frame: InterruptStackFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    pic::end_of_interrupt(TIMER_IRQ);
+}
+
+/// This function returns the number of milliseconds since the timer was initialized
+#[inline]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// This function returns a future, which resolves after the specified number of milliseconds
+pub fn sleep(milliseconds: u64) -> Sleep {
+    Sleep {
+        deadline: ticks() + milliseconds,
+    }
+}
+
+/// The sleep future doesn't register a waker in the timer interrupt. The executor checks the
+/// deadlines of the sleeping tasks after every interrupt, so the interrupt handler never locks.
+pub struct Sleep {
+    deadline: u64,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        if ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        crate::executor::wake_at(self.deadline, context.waker());
+        Poll::Pending
+    }
+}
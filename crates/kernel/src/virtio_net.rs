@@ -0,0 +1,303 @@
+use crate::{
+    dma::DmaBuffer,
+    error::Error,
+    pci::PciDevice,
+};
+use alloc::vec::Vec;
+use core::{
+    ptr::{
+        self,
+        read_volatile,
+        write_volatile,
+    },
+    sync::atomic::{
+        fence,
+        Ordering,
+    },
+};
+use libcore::{
+    address::PhysAddr,
+    pat::CacheType,
+    port::{
+        read_u16,
+        read_u32,
+        read_u8,
+        write_u16,
+        write_u32,
+        write_u8,
+    },
+};
+
+/// The vendor ID of all virtio devices
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+/// The device ID of the transitional virtio-net device, which provides the legacy I/O interface
+pub const VIRTIO_NET_DEVICE_ID: u16 = 0x1000;
+
+/// The maximal size of an Ethernet frame without the frame check sequence
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_DEVICE_STATUS: u16 = 0x12;
+const REG_MAC: u16 = 0x14;
+
+const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+const STATUS_DRIVER: u8 = 1 << 1;
+const STATUS_DRIVER_OK: u8 = 1 << 2;
+const STATUS_FAILED: u8 = 1 << 7;
+
+const FEATURE_MAC: u32 = 1 << 5;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+
+const DESCRIPTOR_WRITE: u16 = 1 << 1;
+const QUEUE_ALIGNMENT: usize = 4096;
+
+/// The legacy interface passes the page number of the queue in a 32-bit register, so the queues and
+/// their buffers are allocated below 16 TiB
+const QUEUE_ADDRESS_LIMIT: u64 = 1 << 44;
+
+/// The number of descriptors, which are used per queue. The device may provide more descriptors.
+const BUFFER_COUNT: u16 = 32;
+const BUFFER_SIZE: usize = 2048;
+
+/// The size of the legacy virtio-net header without merged receive buffers
+const NET_HEADER_SIZE: usize = 10;
+
+#[repr(C)]
+struct Descriptor {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A split virtqueue with the legacy layout, in which each descriptor owns a fixed buffer
+struct Virtqueue {
+    size: u16,
+    memory: DmaBuffer,
+    used_offset: usize,
+    buffers: DmaBuffer,
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// This function allocates the queue with the specified index and passes it to the device
+    unsafe fn new(io_base: u16, index: u16) -> Result<Self, Error> {
+        write_u16(io_base + REG_QUEUE_SELECT, index);
+        let size = read_u16(io_base + REG_QUEUE_SIZE);
+        if size < BUFFER_COUNT {
+            return Err(Error::VirtqueueUnavailable(index));
+        }
+
+        let used_offset = align_up(16 * size as usize + 6 + 2 * size as usize);
+        let queue_size = align_up(used_offset + 6 + 8 * size as usize);
+        let memory = allocate(queue_size)?;
+        let buffers = allocate(BUFFER_COUNT as usize * BUFFER_SIZE)?;
+        let queue_address = memory.physical_address().as_u64();
+
+        let queue = Self {
+            size,
+            memory,
+            used_offset,
+            buffers,
+            last_used: 0,
+        };
+        for id in 0..BUFFER_COUNT {
+            queue.descriptor(id).write(Descriptor {
+                address: queue.buffers.physical_address().as_u64()
+                    + (id as usize * BUFFER_SIZE) as u64,
+                length: BUFFER_SIZE as u32,
+                flags: 0,
+                next: 0,
+            });
+        }
+        write_u32(io_base + REG_QUEUE_ADDRESS, (queue_address >> 12) as u32);
+        Ok(queue)
+    }
+
+    #[inline]
+    fn descriptor(&self, id: u16) -> *mut Descriptor {
+        unsafe { self.memory().cast::<Descriptor>().add(id as usize) }
+    }
+
+    #[inline]
+    fn buffer(&self, id: u16) -> *mut u8 {
+        unsafe {
+            self.buffers
+                .virtual_address()
+                .as_mut_ptr::<u8>()
+                .add(id as usize * BUFFER_SIZE)
+        }
+    }
+
+    #[inline]
+    fn memory(&self) -> *mut u8 {
+        self.memory.virtual_address().as_mut_ptr()
+    }
+
+    /// This function passes the buffer of the descriptor with the specified length to the device
+    unsafe fn push_available(&mut self, id: u16, length: usize, flags: u16) {
+        let descriptor = self.descriptor(id);
+        write_volatile(ptr::addr_of_mut!((*descriptor).length), length as u32);
+        write_volatile(ptr::addr_of_mut!((*descriptor).flags), flags);
+
+        let available = self.memory().add(16 * self.size as usize) as *mut u16;
+        let index = read_volatile(available.add(1));
+        write_volatile(available.add(2 + (index % self.size) as usize), id);
+        fence(Ordering::SeqCst);
+        write_volatile(available.add(1), index.wrapping_add(1));
+    }
+
+    /// This function returns the ID and the written length of the next buffer, which was returned
+    /// by the device
+    unsafe fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used = self.memory().add(self.used_offset);
+        if read_volatile((used as *const u16).add(1)) == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let element = used.add(4 + 8 * (self.last_used % self.size) as usize) as *const u32;
+        self.last_used = self.last_used.wrapping_add(1);
+        Some((read_volatile(element) as u16, read_volatile(element.add(1)) as usize))
+    }
+}
+
+/// The driver of a legacy virtio-net device, which polls the queues instead of using interrupts
+pub struct VirtioNet {
+    io_base: u16,
+    mac_address: [u8; 6],
+    receive_queue: Virtqueue,
+    transmit_queue: Virtqueue,
+    free_transmit_buffers: Vec<u16>,
+}
+
+impl VirtioNet {
+    /// This function resets and initializes the specified virtio-net device
+    pub fn init(device: &PciDevice) -> Result<Self, Error> {
+        let io_base = device.io_bar(0).ok_or(Error::NoNetworkDevice)?;
+        device.enable_bus_master();
+
+        unsafe {
+            write_u8(io_base + REG_DEVICE_STATUS, 0);
+            write_u8(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+            write_u8(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+            let features = read_u32(io_base + REG_DEVICE_FEATURES);
+            if features & FEATURE_MAC == 0 {
+                write_u8(io_base + REG_DEVICE_STATUS, STATUS_FAILED);
+                return Err(Error::NoNetworkDevice);
+            }
+            write_u32(io_base + REG_GUEST_FEATURES, FEATURE_MAC);
+
+            let mut mac_address = [0; 6];
+            for (index, byte) in mac_address.iter_mut().enumerate() {
+                *byte = read_u8(io_base + REG_MAC + index as u16);
+            }
+
+            let (receive_queue, transmit_queue) = match (
+                Virtqueue::new(io_base, RECEIVE_QUEUE),
+                Virtqueue::new(io_base, TRANSMIT_QUEUE),
+            ) {
+                (Ok(receive_queue), Ok(transmit_queue)) => (receive_queue, transmit_queue),
+                (Err(error), _) | (_, Err(error)) => {
+                    write_u8(io_base + REG_DEVICE_STATUS, STATUS_FAILED);
+                    return Err(error);
+                }
+            };
+
+            let mut device = Self {
+                io_base,
+                mac_address,
+                receive_queue,
+                transmit_queue,
+                free_transmit_buffers: (0..BUFFER_COUNT).collect(),
+            };
+            for id in 0..BUFFER_COUNT {
+                device
+                    .receive_queue
+                    .push_available(id, BUFFER_SIZE, DESCRIPTOR_WRITE);
+            }
+            write_u8(
+                io_base + REG_DEVICE_STATUS,
+                STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK,
+            );
+            write_u16(io_base + REG_QUEUE_NOTIFY, RECEIVE_QUEUE);
+            Ok(device)
+        }
+    }
+
+    #[inline]
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// This function copies the next received frame into the buffer and returns its length. The
+    /// receive buffer is passed back to the device afterwards.
+    pub fn receive(&mut self, frame: &mut [u8]) -> Option<usize> {
+        unsafe {
+            let (id, length) = self.receive_queue.pop_used()?;
+            let length = length
+                .saturating_sub(NET_HEADER_SIZE)
+                .min(frame.len())
+                .min(BUFFER_SIZE - NET_HEADER_SIZE);
+            ptr::copy_nonoverlapping(
+                self.receive_queue.buffer(id).add(NET_HEADER_SIZE),
+                frame.as_mut_ptr(),
+                length,
+            );
+            self.receive_queue
+                .push_available(id, BUFFER_SIZE, DESCRIPTOR_WRITE);
+            write_u16(self.io_base + REG_QUEUE_NOTIFY, RECEIVE_QUEUE);
+            Some(length)
+        }
+    }
+
+    /// This function passes the specified frame with an empty virtio-net header to the device
+    pub fn transmit(&mut self, frame: &[u8]) -> Result<(), Error> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(Error::FrameTooLarge(frame.len()));
+        }
+
+        unsafe {
+            // Reclaim the buffers of the frames, which were already sent by the device
+            while let Some((id, _)) = self.transmit_queue.pop_used() {
+                self.free_transmit_buffers.push(id);
+            }
+
+            let id = self
+                .free_transmit_buffers
+                .pop()
+                .ok_or(Error::TransmitQueueFull)?;
+            let buffer = self.transmit_queue.buffer(id);
+            ptr::write_bytes(buffer, 0, NET_HEADER_SIZE);
+            ptr::copy_nonoverlapping(frame.as_ptr(), buffer.add(NET_HEADER_SIZE), frame.len());
+            self.transmit_queue
+                .push_available(id, NET_HEADER_SIZE + frame.len(), 0);
+            write_u16(self.io_base + REG_QUEUE_NOTIFY, TRANSMIT_QUEUE);
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn align_up(size: usize) -> usize {
+    (size + QUEUE_ALIGNMENT - 1) & !(QUEUE_ALIGNMENT - 1)
+}
+
+fn allocate(size: usize) -> Result<DmaBuffer, Error> {
+    DmaBuffer::allocate(
+        size,
+        PhysAddr::new(QUEUE_ADDRESS_LIMIT),
+        QUEUE_ALIGNMENT as u64,
+        CacheType::WriteBack,
+    )
+    .map_err(|_| Error::QueueAllocationFailed(size))
+}
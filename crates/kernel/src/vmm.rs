@@ -0,0 +1,364 @@
+use crate::{
+    error::Error,
+    frames,
+};
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    error::Error as CoreError,
+    paging::{
+        active_page_table,
+        flush_page,
+        flush_tlb,
+        level_page_size,
+        translate,
+        PageTable,
+        ADDRESS_MASK,
+        ENTRY_COUNT,
+        HUGE_PAGE,
+        PAGE_SIZE,
+        PRESENT,
+        USER,
+        WRITABLE,
+    },
+};
+
+/// The available bit 9 of a page table entry marks pages, which are shared copy-on-write. These
+/// pages are mapped read-only and copied by the page fault handler on the first write.
+pub const COPY_ON_WRITE: u64 = 1 << 9;
+
+/// The user space is located in the PML4 entries 128 to 255. All other PML4 entries are shared by
+/// every address space, so the identity mapping of the firmware and the kernel stay accessible.
+/// The end of the user space isn't a canonical address, so both bounds are plain integers.
+pub const USER_SPACE_START: u64 = 0x0000_4000_0000_0000;
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+const USER_ENTRIES: core::ops::Range<usize> = 128..256;
+
+/// An address space is a PML4 with its own user space and the shared kernel entries. All frames,
+/// which are mapped into the user space, are reference counted, so mappings can be shared between
+/// address spaces. The frames and page tables of the user space are released, when the address
+/// space is dropped.
+pub struct AddressSpace {
+    root: PhysAddr,
+}
+
+impl AddressSpace {
+    /// This function creates an empty address space, which shares the kernel entries with the
+    /// active address space.
+    pub fn new() -> Result<Self, Error> {
+        let root = frames::allocate_frame().ok_or(Error::OutOfFrames)?;
+        let active_table = unsafe { table(active_page_table()) };
+        let root_table = unsafe { table(root) };
+        for (index, entry) in root_table.entries.iter_mut().enumerate() {
+            if !USER_ENTRIES.contains(&index) {
+                *entry = active_table.entries[index];
+            }
+        }
+        Ok(Self { root })
+    }
+
+    #[inline]
+    pub fn root(&self) -> PhysAddr {
+        self.root
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        active_page_table() == self.root
+    }
+
+    /// This function maps the specified frame at the specified page. The reference of the caller to
+    /// the frame is moved into the mapping.
+    pub fn map(&mut self, page: VirtAddr, frame: PhysAddr, flags: u64) -> Result<(), Error> {
+        let entry = unsafe { leaf_entry(self.root, page, true) }?;
+        if *entry & PRESENT != 0 {
+            return Err(Error::PageAlreadyMapped(page.as_u64()));
+        }
+        *entry = frame.as_u64() | (flags & !ADDRESS_MASK) | PRESENT | USER;
+        Ok(())
+    }
+
+    /// This function returns the frame and the flags of the mapping of the specified page
+    pub fn lookup(&self, page: VirtAddr) -> Option<(PhysAddr, u64)> {
+        let entry = *unsafe { leaf_entry(self.root, page, false) }.ok()?;
+        if entry & PRESENT == 0 {
+            return None;
+        }
+        Some((PhysAddr::new(entry & ADDRESS_MASK), entry & !ADDRESS_MASK))
+    }
+
+    /// This function copies the specified bytes into the memory of this address space. The pages
+    /// of the range have to be mapped, copy-on-write pages are copied before they're written.
+    pub fn write_bytes(&mut self, address: VirtAddr, bytes: &[u8]) -> Result<(), Error> {
+        let mut written = 0;
+        while written < bytes.len() {
+            let current = address + written as u64;
+            let page = current.align_down(PAGE_SIZE);
+            self.handle_write_fault(page)?;
+
+            let (frame, _) = self
+                .lookup(page)
+                .ok_or(CoreError::PageNotMapped(page.as_u64()))?;
+            let offset = current - page;
+            let size = (PAGE_SIZE - offset).min((bytes.len() - written) as u64) as usize;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    bytes[written..].as_ptr(),
+                    (frame.to_virt() + offset).as_mut_ptr::<u8>(),
+                    size,
+                )
+            };
+            written += size;
+        }
+        Ok(())
+    }
+
+    /// This function maps a freshly allocated zeroed frame at the specified page
+    pub fn map_anonymous(&mut self, page: VirtAddr, flags: u64) -> Result<PhysAddr, Error> {
+        let frame = frames::allocate_frame().ok_or(Error::OutOfFrames)?;
+        if let Err(error) = self.map(page, frame, flags) {
+            frames::release_frame(frame);
+            return Err(error);
+        }
+        Ok(frame)
+    }
+
+    /// This function maps the specified frame read-only at the specified page. The frame can be
+    /// mapped into multiple address spaces (like the code of a shared library), every mapping adds
+    /// a reference to the frame.
+    pub fn map_shared(&mut self, page: VirtAddr, frame: PhysAddr, flags: u64) -> Result<(), Error> {
+        frames::share_frame(frame);
+        if let Err(error) = self.map(page, frame, flags & !(WRITABLE | COPY_ON_WRITE)) {
+            frames::release_frame(frame);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// This function replaces the flags of the mapping of the specified page. The mapped frame stays
+    /// the same.
+    pub fn update_flags(&mut self, page: VirtAddr, flags: u64) -> Result<(), Error> {
+        let entry = unsafe { leaf_entry(self.root, page, false) }?;
+        if *entry & PRESENT == 0 {
+            return Err(CoreError::PageNotMapped(page.as_u64()).into());
+        }
+
+        *entry = (*entry & ADDRESS_MASK) | (flags & !ADDRESS_MASK) | PRESENT | USER;
+        if self.is_active() {
+            flush_page(page);
+        }
+        Ok(())
+    }
+
+    /// This function removes the mapping of the specified page and returns the frame, which was
+    /// mapped. The reference of the mapping to the frame is released.
+    pub fn unmap(&mut self, page: VirtAddr) -> Result<PhysAddr, Error> {
+        let entry = unsafe { leaf_entry(self.root, page, false) }?;
+        if *entry & PRESENT == 0 {
+            return Err(CoreError::PageNotMapped(page.as_u64()).into());
+        }
+
+        let frame = PhysAddr::new(*entry & ADDRESS_MASK);
+        *entry = 0;
+        if self.is_active() {
+            flush_page(page);
+        }
+        frames::release_frame(frame);
+        Ok(frame)
+    }
+
+    /// This function creates a copy of this address space (like `fork`). The frames of the user
+    /// space aren't copied, writable pages are mapped copy-on-write into both address spaces and
+    /// read-only pages are shared.
+    pub fn clone_copy_on_write(&mut self) -> Result<AddressSpace, Error> {
+        let child = Self::new()?;
+        let result = unsafe { clone_tables(self.root, child.root, 4, 0) };
+        if self.is_active() {
+            flush_tlb();
+        }
+        result.map(|_| child)
+    }
+
+    /// This function resolves a write to a copy-on-write page. If the frame is still shared, the
+    /// content is copied into a new frame. Otherwise, the page is made writable again. Returns false,
+    /// if the page isn't a copy-on-write page.
+    pub fn handle_write_fault(&mut self, page: VirtAddr) -> Result<bool, Error> {
+        let resolved = unsafe { resolve_copy_on_write(self.root, page) }?;
+        if resolved && self.is_active() {
+            flush_page(page);
+        }
+        Ok(resolved)
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        debug_assert!(!self.is_active(), "Active address space was dropped");
+        unsafe { release_tables(self.root, 4) };
+    }
+}
+
+/// This function is called by the page fault handler on writes to present pages. If the page is a
+/// copy-on-write page of the active address space, the fault is resolved and true is returned.
+pub fn handle_copy_on_write_fault(address: VirtAddr) -> bool {
+    let page = address.align_down(PAGE_SIZE);
+    match unsafe { resolve_copy_on_write(active_page_table(), page) } {
+        Ok(true) => {
+            flush_page(page);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// This function checks, that the specified range is located in the user space of the active
+/// address space and that every page of the range is mapped for user mode (and writable, if
+/// requested). Copy-on-write pages are writable for this check, because the write is resolved by
+/// the page fault handler.
+pub fn is_user_accessible(start: VirtAddr, length: u64, writable: bool) -> bool {
+    let Some(end) = start.as_u64().checked_add(length) else {
+        return false;
+    };
+    if start.as_u64() < USER_SPACE_START || end > USER_SPACE_END {
+        return false;
+    }
+
+    let mut page = start.align_down(PAGE_SIZE);
+    while page.as_u64() < end {
+        let Some((_, flags, _)) = translate(page) else {
+            return false;
+        };
+        if flags & (PRESENT | USER) != PRESENT | USER {
+            return false;
+        }
+        if writable && flags & (WRITABLE | COPY_ON_WRITE) == 0 {
+            return false;
+        }
+        page += PAGE_SIZE;
+    }
+    true
+}
+
+#[inline]
+unsafe fn table(address: PhysAddr) -> &'static mut PageTable {
+    &mut *address.to_virt().as_mut_ptr::<PageTable>()
+}
+
+#[inline]
+fn table_index(address: VirtAddr, level: usize) -> usize {
+    ((address.as_u64() / level_page_size(level)) % ENTRY_COUNT as u64) as usize
+}
+
+/// This function walks the page tables below the specified PML4 and returns the page table entry,
+/// which maps the specified user page. Missing page tables are allocated, if requested.
+unsafe fn leaf_entry(
+    root: PhysAddr, page: VirtAddr, allocate: bool,
+) -> Result<&'static mut u64, Error> {
+    if page.as_u64() < USER_SPACE_START || page.as_u64() >= USER_SPACE_END {
+        return Err(CoreError::PageNotMapped(page.as_u64()).into());
+    }
+
+    let mut current = table(root);
+    for level in (2..=4).rev() {
+        let entry = &mut current.entries[table_index(page, level)];
+        if *entry & PRESENT == 0 {
+            if !allocate {
+                return Err(CoreError::PageNotMapped(page.as_u64()).into());
+            }
+            let frame = frames::allocate_frame().ok_or(Error::OutOfFrames)?;
+            *entry = frame.as_u64() | PRESENT | WRITABLE | USER;
+        } else if *entry & HUGE_PAGE != 0 {
+            return Err(Error::HugePageMapped(page.as_u64()));
+        }
+        current = table(PhysAddr::new(*entry & ADDRESS_MASK));
+    }
+    Ok(&mut current.entries[table_index(page, 1)])
+}
+
+/// This function copies the user space entries of the source table into the target table. Page
+/// tables are copied, the mapped frames are shared. The target entries are written before the
+/// copy of the next level, so a partially copied address space is released completely on error.
+unsafe fn clone_tables(
+    source: PhysAddr, target: PhysAddr, level: usize, base_address: u64,
+) -> Result<(), Error> {
+    let source_table = table(source);
+    let target_table = table(target);
+    for index in 0..ENTRY_COUNT {
+        if level == 4 && !USER_ENTRIES.contains(&index) {
+            continue;
+        }
+
+        let entry = &mut source_table.entries[index];
+        if *entry & PRESENT == 0 {
+            continue;
+        }
+
+        let address = base_address + index as u64 * level_page_size(level);
+
+        if level == 1 {
+            let frame = PhysAddr::new(*entry & ADDRESS_MASK);
+            if frames::share_frame(frame).is_some() && *entry & (WRITABLE | COPY_ON_WRITE) != 0 {
+                *entry = (*entry & !WRITABLE) | COPY_ON_WRITE;
+            }
+            target_table.entries[index] = *entry;
+            continue;
+        }
+
+        if *entry & HUGE_PAGE != 0 {
+            return Err(Error::HugePageMapped(address));
+        }
+        let child = frames::allocate_frame().ok_or(Error::OutOfFrames)?;
+        target_table.entries[index] = child.as_u64() | (*entry & !ADDRESS_MASK);
+        clone_tables(PhysAddr::new(*entry & ADDRESS_MASK), child, level - 1, address)?;
+    }
+    Ok(())
+}
+
+/// This function releases the frames and page tables of the user space below the specified table
+/// and the table itself.
+unsafe fn release_tables(address: PhysAddr, level: usize) {
+    for (index, entry) in table(address).entries.iter().enumerate() {
+        if level == 4 && !USER_ENTRIES.contains(&index) {
+            continue;
+        }
+        if *entry & PRESENT == 0 {
+            continue;
+        }
+
+        let frame = PhysAddr::new(*entry & ADDRESS_MASK);
+        if level == 1 {
+            frames::release_frame(frame);
+        } else {
+            release_tables(frame, level - 1);
+        }
+    }
+    frames::release_frame(address);
+}
+
+unsafe fn resolve_copy_on_write(root: PhysAddr, page: VirtAddr) -> Result<bool, Error> {
+    let entry = leaf_entry(root, page, false)?;
+    if *entry & (PRESENT | COPY_ON_WRITE) != PRESENT | COPY_ON_WRITE {
+        return Ok(false);
+    }
+
+    // Reuse the frame, if this mapping holds the last reference. Otherwise, the frame is copied.
+    let frame = PhysAddr::new(*entry & ADDRESS_MASK);
+    let flags = (*entry & !(ADDRESS_MASK | COPY_ON_WRITE)) | WRITABLE;
+    if frames::ref_count(frame) <= 1 {
+        *entry = frame.as_u64() | flags;
+        return Ok(true);
+    }
+
+    let copy = frames::allocate_frame().ok_or(Error::OutOfFrames)?;
+    core::ptr::copy_nonoverlapping(
+        frame.to_virt().as_ptr::<u8>(),
+        copy.to_virt().as_mut_ptr::<u8>(),
+        PAGE_SIZE as usize,
+    );
+    *entry = copy.as_u64() | flags;
+    frames::release_frame(frame);
+    Ok(true)
+}
@@ -0,0 +1,441 @@
+//! This module implements the subset of AML, which is needed to read static objects from the DSDT
+//! and SSDTs. The interpreter doesn't execute methods, it only walks the namespace blocks (Scope,
+//! Device and ThermalZone) and decodes the data objects of Name declarations. Unknown opcodes are
+//! skipped byte by byte until the next known opcode is found.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const NAME_OP: u8 = 0x08;
+const BYTE_PREFIX: u8 = 0x0A;
+const WORD_PREFIX: u8 = 0x0B;
+const DWORD_PREFIX: u8 = 0x0C;
+const STRING_PREFIX: u8 = 0x0D;
+const QWORD_PREFIX: u8 = 0x0E;
+const SCOPE_OP: u8 = 0x10;
+const BUFFER_OP: u8 = 0x11;
+const PACKAGE_OP: u8 = 0x12;
+const VAR_PACKAGE_OP: u8 = 0x13;
+const METHOD_OP: u8 = 0x14;
+const DUAL_NAME_PREFIX: u8 = 0x2E;
+const MULTI_NAME_PREFIX: u8 = 0x2F;
+const EXT_OP_PREFIX: u8 = 0x5B;
+const ROOT_CHAR: u8 = b'\\';
+const PARENT_PREFIX_CHAR: u8 = b'^';
+const ONES_OP: u8 = 0xFF;
+
+const EXT_DEVICE_OP: u8 = 0x82;
+const EXT_PROCESSOR_OP: u8 = 0x83;
+const EXT_POWER_RES_OP: u8 = 0x84;
+const EXT_THERMAL_ZONE_OP: u8 = 0x85;
+
+const PCI_ROOT_IDS: [&str; 2] = ["PNP0A03", "PNP0A08"];
+const BATTERY_IDS: [&str; 1] = ["PNP0C0A"];
+
+/// The value of battery fields, which are unknown to the firmware
+const UNKNOWN_VALUE: u64 = 0xFFFF_FFFF;
+
+#[derive(Clone, Debug)]
+pub enum AmlValue<'a> {
+    Integer(u64),
+    String(&'a str),
+    Package(Vec<u64>),
+    Other,
+}
+
+impl AmlValue<'_> {
+    #[inline]
+    pub fn as_integer(&self) -> Option<u64> {
+        match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// The objects, which are reported while walking the namespace
+pub enum AmlObject<'a, 'b> {
+    Device,
+    Name(&'b str, AmlValue<'a>),
+}
+
+/// The sleep type values of a sleep state, which are written into the SLP_TYP fields of the PM1a and
+/// PM1b control registers.
+#[derive(Clone, Copy, Debug)]
+pub struct SleepState {
+    pub sleep_type_a: u8,
+    pub sleep_type_b: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct PciRootBridge {
+    pub path: String,
+    pub segment: u16,
+    pub bus: u8,
+}
+
+/// The state of a control method battery, which is read from the `_BST` and `_BIF` (or `_BIX`)
+/// packages of the battery device
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryStatus {
+    pub charging: bool,
+    pub remaining_capacity: u64,
+    pub full_capacity: u64,
+}
+
+impl BatteryStatus {
+    /// This function returns the remaining capacity in percent of the last full charge capacity or
+    /// `None`, if one of the capacities is unknown to the firmware.
+    pub fn percentage(&self) -> Option<u8> {
+        if self.full_capacity == 0
+            || self.full_capacity == UNKNOWN_VALUE
+            || self.remaining_capacity == UNKNOWN_VALUE
+        {
+            return None;
+        }
+        Some((self.remaining_capacity.min(self.full_capacity) * 100 / self.full_capacity) as u8)
+    }
+}
+
+/// This function walks the namespace of the specified AML code and calls the visitor with the path
+/// of the scope and every device and name declaration.
+pub fn walk_namespace<'a>(aml: &'a [u8], visitor: &mut dyn FnMut(&str, AmlObject<'a, '_>)) {
+    walk_term_list(aml, "\\", visitor);
+}
+
+/// This function returns the sleep type values of the specified sleep state (like 5 for soft-off)
+pub fn find_sleep_state(aml: &[u8], state: u8) -> Option<SleepState> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+    let name = core::str::from_utf8(&name).ok()?;
+
+    let mut sleep_state = None;
+    walk_namespace(aml, &mut |_, object| {
+        if let AmlObject::Name(object_name, AmlValue::Package(values)) = object {
+            if object_name == name && !values.is_empty() && sleep_state.is_none() {
+                sleep_state = Some(SleepState {
+                    sleep_type_a: values[0] as u8,
+                    sleep_type_b: values.get(1).copied().unwrap_or(values[0]) as u8,
+                });
+            }
+        }
+    });
+    sleep_state
+}
+
+/// This function returns all devices with the hardware or compatible ID of a PCI or PCI Express root
+/// bridge. The segment and the base bus number are read from `_SEG` and `_BBN`, if they are static.
+pub fn find_pci_root_bridges(aml: &[u8]) -> Vec<PciRootBridge> {
+    let mut bridges: Vec<PciRootBridge> = Vec::new();
+    let mut numbers: Vec<(String, &str, u64)> = Vec::new();
+    walk_namespace(aml, &mut |scope, object| {
+        let AmlObject::Name(name, value) = object else {
+            return;
+        };
+
+        match (name, value) {
+            ("_HID" | "_CID", value) if has_id(&value, &PCI_ROOT_IDS) => {
+                if !bridges.iter().any(|bridge| bridge.path == scope) {
+                    bridges.push(PciRootBridge {
+                        path: String::from(scope),
+                        segment: 0,
+                        bus: 0,
+                    });
+                }
+            }
+            ("_SEG", AmlValue::Integer(value)) => numbers.push((String::from(scope), "_SEG", value)),
+            ("_BBN", AmlValue::Integer(value)) => numbers.push((String::from(scope), "_BBN", value)),
+            _ => {}
+        }
+    });
+
+    for bridge in bridges.iter_mut() {
+        for (scope, name, value) in numbers.iter() {
+            if *scope != bridge.path {
+                continue;
+            }
+            match *name {
+                "_SEG" => bridge.segment = *value as u16,
+                _ => bridge.bus = *value as u8,
+            }
+        }
+    }
+    bridges
+}
+
+/// This function returns the status of the first battery device, whose `_BST` and `_BIF` (or `_BIX`)
+/// objects are static packages. Most firmwares implement these objects as methods, which read the
+/// embedded controller, so the status is only available on simple firmwares (like virtual machines).
+pub fn find_battery_status(aml: &[u8]) -> Option<BatteryStatus> {
+    let mut batteries: Vec<String> = Vec::new();
+    let mut packages: Vec<(String, String, Vec<u64>)> = Vec::new();
+    walk_namespace(aml, &mut |scope, object| {
+        let AmlObject::Name(name, value) = object else {
+            return;
+        };
+
+        match (name, value) {
+            ("_HID" | "_CID", value) if has_id(&value, &BATTERY_IDS) => {
+                batteries.push(String::from(scope))
+            }
+            ("_BST" | "_BIF" | "_BIX", AmlValue::Package(values)) => {
+                packages.push((String::from(scope), String::from(name), values))
+            }
+            _ => {}
+        }
+    });
+
+    batteries.iter().find_map(|battery| {
+        let package = |name: &str| {
+            packages
+                .iter()
+                .find(|(scope, package_name, _)| scope == battery && package_name == name)
+                .map(|(_, _, values)| values)
+        };
+
+        // The _BIX package starts with the revision, so the fields are moved by one
+        let state = package("_BST")?;
+        let full_capacity = match (package("_BIF"), package("_BIX")) {
+            (Some(information), _) => *information.get(2)?,
+            (None, Some(information)) => *information.get(3)?,
+            (None, None) => return None,
+        };
+        Some(BatteryStatus {
+            charging: state.first()? & 0b10 != 0,
+            remaining_capacity: *state.get(2)?,
+            full_capacity,
+        })
+    })
+}
+
+/// This function returns the temperature of the first thermal zone with a static `_TMP` object in
+/// tenths of Kelvin. Like the battery objects, `_TMP` is a method on most firmwares.
+pub fn find_temperature(aml: &[u8]) -> Option<u64> {
+    let mut temperature = None;
+    walk_namespace(aml, &mut |_, object| {
+        if let AmlObject::Name("_TMP", AmlValue::Integer(value)) = object {
+            temperature = temperature.or(Some(value));
+        }
+    });
+    temperature
+}
+
+fn has_id(value: &AmlValue, ids: &[&str]) -> bool {
+    match value {
+        AmlValue::Integer(value) => {
+            let id = decode_eisa_id(*value as u32);
+            ids.iter().any(|known_id| known_id.as_bytes() == id)
+        }
+        AmlValue::String(value) => ids.contains(value),
+        _ => false,
+    }
+}
+
+/// This function decodes a compressed EISA ID (like `PNP0A03`)
+pub fn decode_eisa_id(value: u32) -> [u8; 7] {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let bytes = value.to_le_bytes();
+    [
+        ((bytes[0] >> 2) & 0x1F) + 0x40,
+        (((bytes[0] & 0x3) << 3) | (bytes[1] >> 5)) + 0x40,
+        (bytes[1] & 0x1F) + 0x40,
+        HEX[(bytes[2] >> 4) as usize],
+        HEX[(bytes[2] & 0xF) as usize],
+        HEX[(bytes[3] >> 4) as usize],
+        HEX[(bytes[3] & 0xF) as usize],
+    ]
+}
+
+fn walk_term_list<'a>(data: &'a [u8], scope: &str, visitor: &mut dyn FnMut(&str, AmlObject<'a, '_>)) {
+    let mut offset = 0;
+    while offset < data.len() {
+        let result = match (data[offset], data.get(offset + 1).copied()) {
+            (SCOPE_OP, _) => walk_block(data, offset + 1, scope, false, visitor),
+            (EXT_OP_PREFIX, Some(EXT_DEVICE_OP)) => {
+                walk_block(data, offset + 2, scope, true, visitor)
+            }
+            (EXT_OP_PREFIX, Some(EXT_THERMAL_ZONE_OP)) => {
+                walk_block(data, offset + 2, scope, false, visitor)
+            }
+            (EXT_OP_PREFIX, Some(EXT_PROCESSOR_OP | EXT_POWER_RES_OP)) => {
+                package_end(data, offset + 2)
+            }
+            (METHOD_OP, _) => package_end(data, offset + 1),
+            (NAME_OP, _) => {
+                parse_name_string(data, offset + 1).and_then(|(name, name_end)| {
+                    let (value, end) = parse_data_object(data, name_end)?;
+                    visitor(scope, AmlObject::Name(last_segment(&name), value));
+                    Some(end)
+                })
+            }
+            _ => None,
+        };
+
+        // Skip unknown opcodes byte by byte
+        offset = match result {
+            Some(end) if end > offset => end,
+            _ => offset + 1,
+        };
+    }
+}
+
+/// This function walks the body of a namespace block and returns the end of the block
+fn walk_block<'a>(
+    data: &'a [u8], offset: usize, scope: &str, device: bool,
+    visitor: &mut dyn FnMut(&str, AmlObject<'a, '_>),
+) -> Option<usize> {
+    let end = package_end(data, offset)?;
+    let (_, length_size) = parse_package_length(data, offset)?;
+    let (name, body_start) = parse_name_string(data, offset + length_size)?;
+    let path = resolve_path(scope, &name);
+    if device {
+        visitor(&path, AmlObject::Device);
+    }
+
+    walk_term_list(data.get(body_start..end)?, &path, visitor);
+    Some(end)
+}
+
+/// This function returns the length of the package (including the length bytes) and the number of
+/// bytes of the package length.
+fn parse_package_length(data: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let lead = *data.get(offset)?;
+    let byte_count = (lead >> 6) as usize;
+    if byte_count == 0 {
+        return Some(((lead & 0x3F) as usize, 1));
+    }
+
+    let mut length = (lead & 0x0F) as usize;
+    for index in 0..byte_count {
+        length |= (*data.get(offset + 1 + index)? as usize) << (4 + index * 8);
+    }
+    Some((length, byte_count + 1))
+}
+
+#[inline]
+fn package_end(data: &[u8], offset: usize) -> Option<usize> {
+    let (length, _) = parse_package_length(data, offset)?;
+    let end = offset + length;
+    (end <= data.len()).then_some(end)
+}
+
+/// This function parses a name string into the dotted form (like `\_SB_.PCI0`)
+fn parse_name_string(data: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    while let Some(&byte) = data.get(offset) {
+        if byte != ROOT_CHAR && byte != PARENT_PREFIX_CHAR {
+            break;
+        }
+        name.push(byte as char);
+        offset += 1;
+    }
+
+    let segment_count = match *data.get(offset)? {
+        ZERO_OP => return Some((name, offset + 1)),
+        DUAL_NAME_PREFIX => {
+            offset += 1;
+            2
+        }
+        MULTI_NAME_PREFIX => {
+            offset += 2;
+            *data.get(offset - 1)? as usize
+        }
+        _ => 1,
+    };
+
+    for index in 0..segment_count {
+        let segment = data.get(offset..offset + 4)?;
+        if !segment
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || *byte == b'_')
+        {
+            return None;
+        }
+
+        if index > 0 {
+            name.push('.');
+        }
+        name.push_str(core::str::from_utf8(segment).ok()?);
+        offset += 4;
+    }
+    Some((name, offset))
+}
+
+fn parse_data_object(data: &[u8], offset: usize) -> Option<(AmlValue, usize)> {
+    let read = |size: usize| -> Option<u64> {
+        let bytes = data.get(offset + 1..offset + 1 + size)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0u64, |value, byte| value << 8 | *byte as u64),
+        )
+    };
+
+    Some(match *data.get(offset)? {
+        ZERO_OP => (AmlValue::Integer(0), offset + 1),
+        ONE_OP => (AmlValue::Integer(1), offset + 1),
+        ONES_OP => (AmlValue::Integer(u64::MAX), offset + 1),
+        BYTE_PREFIX => (AmlValue::Integer(read(1)?), offset + 2),
+        WORD_PREFIX => (AmlValue::Integer(read(2)?), offset + 3),
+        DWORD_PREFIX => (AmlValue::Integer(read(4)?), offset + 5),
+        QWORD_PREFIX => (AmlValue::Integer(read(8)?), offset + 9),
+        STRING_PREFIX => {
+            let length = data.get(offset + 1..)?.iter().position(|byte| *byte == 0)?;
+            let string = core::str::from_utf8(&data[offset + 1..offset + 1 + length]).ok()?;
+            (AmlValue::String(string), offset + length + 2)
+        }
+        PACKAGE_OP => {
+            let end = package_end(data, offset + 1)?;
+            let (_, length_size) = parse_package_length(data, offset + 1)?;
+            let element_count = *data.get(offset + 1 + length_size)? as usize;
+
+            // Only packages of integers are decoded
+            let mut values = Vec::with_capacity(element_count);
+            let mut element_offset = offset + 2 + length_size;
+            while element_offset < end && values.len() < element_count {
+                match parse_data_object(data, element_offset) {
+                    Some((AmlValue::Integer(value), element_end)) => {
+                        values.push(value);
+                        element_offset = element_end;
+                    }
+                    _ => break,
+                }
+            }
+            (AmlValue::Package(values), end)
+        }
+        BUFFER_OP | VAR_PACKAGE_OP => (AmlValue::Other, package_end(data, offset + 1)?),
+        _ => return None,
+    })
+}
+
+/// This function resolves the specified name relative to the specified scope
+fn resolve_path(scope: &str, name: &str) -> String {
+    if name.starts_with('\\') {
+        return String::from(name);
+    }
+
+    let mut path = String::from(scope);
+    let mut name = name;
+    while let Some(rest) = name.strip_prefix('^') {
+        match path.rfind('.') {
+            Some(index) => path.truncate(index),
+            None => path.truncate(1),
+        }
+        name = rest;
+    }
+
+    if !path.ends_with('\\') {
+        path.push('.');
+    }
+    path.push_str(name);
+    path
+}
+
+#[inline]
+fn last_segment(name: &str) -> &str {
+    name.rsplit(['.', '\\', '^']).next().unwrap_or(name)
+}
@@ -0,0 +1,121 @@
+use crate::{
+    error::Error,
+    sdt::{
+        SdtHeader,
+        TableData,
+        SDT_HEADER_SIZE,
+    },
+    AcpiTable,
+};
+
+const ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 12;
+
+/// The flag of a DRHD, which marks the unit as responsible for all PCI devices of the segment, which
+/// aren't reported by another unit
+const DRHD_INCLUDE_PCI_ALL: u8 = 1 << 0;
+
+#[derive(Clone, Copy, Debug)]
+pub enum DmarEntry {
+    /// A DMA Remapping Hardware Unit Definition (DRHD) describes the registers of a remapping unit
+    RemappingUnit {
+        segment: u16,
+        register_base: u64,
+        include_pci_all: bool,
+        device_scope_count: usize,
+    },
+    /// A Reserved Memory Region Reporting (RMRR) describes memory, which is used by devices for DMA
+    /// outside of the control of the operating system (like USB legacy emulation)
+    ReservedMemory {
+        segment: u16,
+        base_address: u64,
+        limit_address: u64,
+    },
+    Unknown(u16),
+}
+
+/// The DMA Remapping Reporting table (DMAR) describes the VT-d remapping hardware of the system
+#[derive(Clone, Copy)]
+pub struct Dmar<'a> {
+    pub header: SdtHeader,
+    table: TableData<'a>,
+}
+
+impl<'a> AcpiTable<'a> for Dmar<'a> {
+    const SIGNATURE: &'static str = "DMAR";
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let table = TableData::new(Self::SIGNATURE, data)?;
+        table.read_u8(SDT_HEADER_SIZE + 1)?;
+        Ok(Self {
+            header: SdtHeader::read(data)?,
+            table,
+        })
+    }
+}
+
+impl<'a> Dmar<'a> {
+    /// This function returns the maximal DMA physical address width of the system in bits
+    pub fn host_address_width(&self) -> u8 {
+        self.table.read_u8(SDT_HEADER_SIZE).unwrap_or_default() + 1
+    }
+
+    /// This function returns true, if the platform supports interrupt remapping
+    pub fn interrupt_remapping(&self) -> bool {
+        self.table.read_u8(SDT_HEADER_SIZE + 1).unwrap_or_default() & 1 != 0
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = Result<DmarEntry, Error>> + 'a {
+        let table = self.table;
+        let mut offset = ENTRIES_OFFSET;
+        core::iter::from_fn(move || {
+            if offset + 4 > table.data.len() {
+                return None;
+            }
+
+            let result = Self::read_entry(&table, offset);
+            let length = table.read_u16(offset + 2).unwrap_or_default() as usize;
+            offset = if length < 4 {
+                table.data.len()
+            } else {
+                offset + length
+            };
+            Some(result)
+        })
+    }
+
+    fn read_entry(table: &TableData, offset: usize) -> Result<DmarEntry, Error> {
+        let length = table.read_u16(offset + 2)? as usize;
+        Ok(match table.read_u16(offset)? {
+            0 => {
+                DmarEntry::RemappingUnit {
+                    segment: table.read_u16(offset + 6)?,
+                    register_base: table.read_u64(offset + 8)?,
+                    include_pci_all: table.read_u8(offset + 4)? & DRHD_INCLUDE_PCI_ALL != 0,
+                    device_scope_count: Self::device_scope_count(table, offset + 16, offset + length),
+                }
+            }
+            1 => {
+                DmarEntry::ReservedMemory {
+                    segment: table.read_u16(offset + 6)?,
+                    base_address: table.read_u64(offset + 8)?,
+                    limit_address: table.read_u64(offset + 16)?,
+                }
+            }
+            kind => DmarEntry::Unknown(kind),
+        })
+    }
+
+    /// This function counts the device scopes between the specified offsets. Every device scope
+    /// starts with its type and its length.
+    fn device_scope_count(table: &TableData, mut offset: usize, end: usize) -> usize {
+        let mut count = 0;
+        while offset + 2 <= end {
+            match table.read_u8(offset + 1) {
+                Ok(length) if length >= 2 => offset += length as usize,
+                _ => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror_no_std::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("ACPI Error: Invalid RSDP at 0x{0:X}")]
+    InvalidRsdp(u64),
+
+    #[error("ACPI Error: Invalid checksum of table '{0}'")]
+    InvalidChecksum(&'static str),
+
+    #[error("ACPI Error: Table '{0}' not found")]
+    TableNotFound(&'static str),
+
+    #[error("ACPI Error: Table '{0}' is too short (read at offset 0x{1:X})")]
+    TableTooShort(&'static str, usize),
+
+    #[error("ACPI Error: The ACPI tables were not initialized")]
+    NotInitialized,
+}
@@ -0,0 +1,98 @@
+use crate::{
+    error::Error,
+    sdt::{
+        GenericAddress,
+        SdtHeader,
+        TableData,
+    },
+    AcpiTable,
+};
+
+/// The Fixed ACPI Description Table (FADT) describes the fixed hardware registers of the ACPI power
+/// management and the location of the DSDT.
+#[derive(Clone, Copy)]
+pub struct Fadt<'a> {
+    pub header: SdtHeader,
+    table: TableData<'a>,
+}
+
+impl<'a> AcpiTable<'a> for Fadt<'a> {
+    const SIGNATURE: &'static str = "FACP";
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let table = TableData::new(Self::SIGNATURE, data)?;
+        table.read_u32(76)?;
+        Ok(Self {
+            header: SdtHeader::read(data)?,
+            table,
+        })
+    }
+}
+
+impl Fadt<'_> {
+    /// This function returns the physical address of the DSDT. The 64-bit address is preferred, if
+    /// the table provides it.
+    pub fn dsdt_address(&self) -> u64 {
+        match self.table.read_u64(140) {
+            Ok(address) if address != 0 => address,
+            _ => self.table.read_u32(40).unwrap_or_default() as u64,
+        }
+    }
+
+    pub fn sci_interrupt(&self) -> u16 {
+        self.table.read_u16(46).unwrap_or_default()
+    }
+
+    pub fn smi_command_port(&self) -> u32 {
+        self.table.read_u32(48).unwrap_or_default()
+    }
+
+    /// This function returns the value, which is written into the SMI command port to switch the
+    /// system into ACPI mode.
+    pub fn acpi_enable_value(&self) -> u8 {
+        self.table.read_u8(52).unwrap_or_default()
+    }
+
+    pub fn pm1a_event_block(&self) -> u32 {
+        self.table.read_u32(56).unwrap_or_default()
+    }
+
+    pub fn pm1a_control_block(&self) -> u32 {
+        self.table.read_u32(64).unwrap_or_default()
+    }
+
+    pub fn pm1b_control_block(&self) -> u32 {
+        self.table.read_u32(68).unwrap_or_default()
+    }
+
+    pub fn pm_timer_block(&self) -> u32 {
+        self.table.read_u32(76).unwrap_or_default()
+    }
+
+    /// This function returns the index of the century register in the RTC, if available
+    pub fn century_register(&self) -> Option<u8> {
+        self.table
+            .read_u8(108)
+            .ok()
+            .filter(|register| *register != 0)
+    }
+
+    /// This function returns the IA-PC boot architecture flags (like the presence of a 8042)
+    pub fn boot_architecture_flags(&self) -> u16 {
+        self.table.read_u16(109).unwrap_or_default()
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.table.read_u32(112).unwrap_or_default()
+    }
+
+    /// This function returns the reset register and the value, which resets the system
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        const RESET_REG_SUPPORTED: u32 = 1 << 10;
+        if self.flags() & RESET_REG_SUPPORTED == 0 {
+            return None;
+        }
+
+        Some((GenericAddress::read(&self.table, 116).ok()?, self.table.read_u8(128).ok()?))
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{
+    error::Error,
+    sdt::{
+        GenericAddress,
+        SdtHeader,
+        TableData,
+    },
+    AcpiTable,
+};
+
+/// The High Precision Event Timer table (HPET) describes the location and capabilities of the HPET
+#[derive(Clone, Copy)]
+pub struct Hpet<'a> {
+    pub header: SdtHeader,
+    table: TableData<'a>,
+}
+
+impl<'a> AcpiTable<'a> for Hpet<'a> {
+    const SIGNATURE: &'static str = "HPET";
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let table = TableData::new(Self::SIGNATURE, data)?;
+        table.read_u16(53)?;
+        Ok(Self {
+            header: SdtHeader::read(data)?,
+            table,
+        })
+    }
+}
+
+impl Hpet<'_> {
+    fn block_id(&self) -> u32 {
+        self.table.read_u32(36).unwrap_or_default()
+    }
+
+    pub fn base_address(&self) -> GenericAddress {
+        GenericAddress::read(&self.table, 40).unwrap_or_default()
+    }
+
+    pub fn hpet_number(&self) -> u8 {
+        self.table.read_u8(52).unwrap_or_default()
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        (self.block_id() >> 16) as u16
+    }
+
+    pub fn comparator_count(&self) -> u8 {
+        ((self.block_id() >> 8) & 0x1F) as u8 + 1
+    }
+
+    pub fn is_counter_64bit(&self) -> bool {
+        self.block_id() & (1 << 13) != 0
+    }
+
+    /// This function returns the minimum clock tick in periodic mode without lost interrupts
+    pub fn minimum_tick(&self) -> u16 {
+        self.table.read_u16(53).unwrap_or_default()
+    }
+}
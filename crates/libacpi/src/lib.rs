@@ -0,0 +1,202 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod aml;
+pub mod dmar;
+pub mod error;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod sdt;
+
+use crate::{
+    error::Error,
+    sdt::{
+        SdtHeader,
+        SDT_HEADER_SIZE,
+    },
+};
+use alloc::vec::Vec;
+use core::slice;
+
+pub use dmar::Dmar;
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const RSDP_V1_SIZE: usize = 20;
+const RSDP_V2_SIZE: usize = 36;
+
+pub static mut ACPI_TABLES: Option<AcpiTables> = None;
+
+/// The mapper returns the virtual address of the specified physical memory range. The bootloader and
+/// the early kernel run with identity-mapped memory, so [identity_mapper] can be used there.
+pub type PhysicalMapper = fn(u64, usize) -> u64;
+
+pub fn identity_mapper(physical_address: u64, _size: usize) -> u64 {
+    physical_address
+}
+
+/// Every typed view of an ACPI table implements this trait, so the view can be looked up by the
+/// signature of the table in the registry.
+pub trait AcpiTable<'a>: Sized {
+    const SIGNATURE: &'static str;
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TableEntry {
+    pub signature: [u8; 4],
+    pub physical_address: u64,
+    pub length: u32,
+}
+
+impl TableEntry {
+    #[inline]
+    pub fn signature(&self) -> &str {
+        core::str::from_utf8(&self.signature).unwrap_or("????")
+    }
+}
+
+/// The registry of ACPI tables collects the addresses of all tables referenced by the RSDT or XSDT.
+/// The tables are only mapped and validated, when a view of them is requested, so every subsystem
+/// (SMP, timers, PCI) shares the same discovery.
+pub struct AcpiTables {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    mapper: PhysicalMapper,
+    tables: Vec<TableEntry>,
+}
+
+impl AcpiTables {
+    /// This function validates the RSDP at the specified physical address and collects the tables of
+    /// the XSDT (ACPI 2.0+) or the RSDT (ACPI 1.0).
+    pub fn new(rsdp_address: u64, mapper: PhysicalMapper) -> Result<Self, Error> {
+        let rsdp = map(mapper, rsdp_address, RSDP_V1_SIZE);
+        if &rsdp[..8] != RSDP_SIGNATURE || checksum(rsdp) != 0 {
+            return Err(Error::InvalidRsdp(rsdp_address));
+        }
+
+        let revision = rsdp[15];
+        let mut oem_id = [0; 6];
+        oem_id.copy_from_slice(&rsdp[9..15]);
+
+        // Use the XSDT with 64-bit pointers, if available
+        let (root_address, pointer_size) = if revision >= 2 {
+            let rsdp = map(mapper, rsdp_address, RSDP_V2_SIZE);
+            if checksum(rsdp) != 0 {
+                return Err(Error::InvalidRsdp(rsdp_address));
+            }
+            (u64::from_le_bytes(rsdp[24..32].try_into().unwrap()), 8)
+        } else {
+            (u32::from_le_bytes(rsdp[16..20].try_into().unwrap()) as u64, 4)
+        };
+
+        let root_name = if pointer_size == 8 { "XSDT" } else { "RSDT" };
+        let root = map_table(mapper, root_address, root_name)?;
+        let mut tables = Vec::new();
+        for pointer in root[SDT_HEADER_SIZE..].chunks_exact(pointer_size) {
+            let physical_address = match pointer_size {
+                8 => u64::from_le_bytes(pointer.try_into().unwrap()),
+                _ => u32::from_le_bytes(pointer.try_into().unwrap()) as u64,
+            };
+            if physical_address == 0 {
+                continue;
+            }
+
+            let header = SdtHeader::read(map(mapper, physical_address, SDT_HEADER_SIZE))?;
+            tables.push(TableEntry {
+                signature: header.signature,
+                physical_address,
+                length: header.length,
+            });
+        }
+
+        Ok(Self {
+            revision,
+            oem_id,
+            mapper,
+            tables,
+        })
+    }
+
+    #[inline]
+    pub fn tables(&self) -> &[TableEntry] {
+        &self.tables
+    }
+
+    /// This function returns the first entry with the specified signature
+    pub fn find_entry(&self, signature: &str) -> Option<&TableEntry> {
+        self.tables
+            .iter()
+            .find(|entry| entry.signature == signature.as_bytes())
+    }
+
+    /// This function maps and validates the specified table and returns the raw table data
+    /// including the header.
+    pub fn raw_table(&self, signature: &'static str) -> Result<&'static [u8], Error> {
+        let entry = self
+            .find_entry(signature)
+            .ok_or(Error::TableNotFound(signature))?;
+        map_table(self.mapper, entry.physical_address, signature)
+    }
+
+    /// This function maps, validates and decodes the table of the requested type
+    pub fn table<T: AcpiTable<'static>>(&self) -> Result<T, Error> {
+        T::from_bytes(self.raw_table(T::SIGNATURE)?)
+    }
+
+    /// This function returns the AML code of the DSDT and all SSDTs. The DSDT isn't referenced by
+    /// the XSDT, so it's located with the FADT.
+    pub fn aml_tables(&self) -> Result<Vec<&'static [u8]>, Error> {
+        let dsdt = map_table(self.mapper, self.table::<Fadt>()?.dsdt_address(), "DSDT")?;
+        let mut aml_tables = Vec::from([dsdt.get(SDT_HEADER_SIZE..).unwrap_or_default()]);
+        for entry in self
+            .tables
+            .iter()
+            .filter(|entry| entry.signature == *b"SSDT")
+        {
+            let ssdt = map_table(self.mapper, entry.physical_address, "SSDT")?;
+            aml_tables.push(ssdt.get(SDT_HEADER_SIZE..).unwrap_or_default());
+        }
+        Ok(aml_tables)
+    }
+}
+
+/// This function creates the global ACPI table registry from the RSDP
+pub fn init_acpi_tables(rsdp_address: u64, mapper: PhysicalMapper) -> Result<(), Error> {
+    let tables = AcpiTables::new(rsdp_address, mapper)?;
+    unsafe { ACPI_TABLES = Some(tables) };
+    Ok(())
+}
+
+/// This function returns the global ACPI table registry, if it was created
+pub fn acpi_tables() -> Result<&'static AcpiTables, Error> {
+    unsafe { ACPI_TABLES.as_ref() }.ok_or(Error::NotInitialized)
+}
+
+fn map(mapper: PhysicalMapper, physical_address: u64, size: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(mapper(physical_address, size) as *const u8, size) }
+}
+
+/// This function maps the complete table at the specified address and validates the checksum
+fn map_table(
+    mapper: PhysicalMapper, physical_address: u64, name: &'static str,
+) -> Result<&'static [u8], Error> {
+    let header = SdtHeader::read(map(mapper, physical_address, SDT_HEADER_SIZE))?;
+    let table = map(mapper, physical_address, header.length as usize);
+    if checksum(table) != 0 {
+        return Err(Error::InvalidChecksum(name));
+    }
+    Ok(table)
+}
+
+#[inline]
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
@@ -0,0 +1,164 @@
+use crate::{
+    error::Error,
+    sdt::{
+        SdtHeader,
+        TableData,
+        SDT_HEADER_SIZE,
+    },
+    AcpiTable,
+};
+
+const ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+
+#[derive(Clone, Copy, Debug)]
+pub enum MadtEntry {
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        enabled: bool,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        gsi_base: u32,
+    },
+    InterruptOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: u16,
+    },
+    LocalApicNmi {
+        processor_id: u8,
+        flags: u16,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        enabled: bool,
+        processor_uid: u32,
+    },
+    Unknown(u8),
+}
+
+/// The Multiple APIC Description Table (MADT) describes the interrupt controllers of the system
+#[derive(Clone, Copy)]
+pub struct Madt<'a> {
+    pub header: SdtHeader,
+    table: TableData<'a>,
+}
+
+impl<'a> AcpiTable<'a> for Madt<'a> {
+    const SIGNATURE: &'static str = "APIC";
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let table = TableData::new(Self::SIGNATURE, data)?;
+        table.read_u32(ENTRIES_OFFSET - 4)?;
+        Ok(Self {
+            header: SdtHeader::read(data)?,
+            table,
+        })
+    }
+}
+
+impl<'a> Madt<'a> {
+    /// This function returns the address of the local APIC. The 64-bit address override entry is
+    /// preferred over the 32-bit address in the table header.
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| {
+                match entry {
+                    Ok(MadtEntry::LocalApicAddressOverride { address }) => Some(address),
+                    _ => None,
+                }
+            })
+            .unwrap_or(self.table.read_u32(SDT_HEADER_SIZE).unwrap_or_default() as u64)
+    }
+
+    /// This function returns true, if the system has dual 8259 PICs, which have to be disabled
+    pub fn has_legacy_pics(&self) -> bool {
+        self.table.read_u32(SDT_HEADER_SIZE + 4).unwrap_or_default() & 1 != 0
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = Result<MadtEntry, Error>> + 'a {
+        let table = self.table;
+        let mut offset = ENTRIES_OFFSET;
+        core::iter::from_fn(move || {
+            if offset + 2 > table.data.len() {
+                return None;
+            }
+
+            let result = Self::read_entry(&table, offset);
+            let length = table.data[offset + 1] as usize;
+            offset = if length < 2 {
+                table.data.len()
+            } else {
+                offset + length
+            };
+            Some(result)
+        })
+    }
+
+    /// This function returns the number of enabled processors
+    pub fn processor_count(&self) -> usize {
+        self.entries()
+            .filter(|entry| {
+                matches!(
+                    entry,
+                    Ok(MadtEntry::LocalApic { enabled: true, .. })
+                        | Ok(MadtEntry::LocalX2Apic { enabled: true, .. })
+                )
+            })
+            .count()
+    }
+
+    fn read_entry(table: &TableData, offset: usize) -> Result<MadtEntry, Error> {
+        Ok(match table.read_u8(offset)? {
+            0 => {
+                MadtEntry::LocalApic {
+                    processor_id: table.read_u8(offset + 2)?,
+                    apic_id: table.read_u8(offset + 3)?,
+                    enabled: table.read_u32(offset + 4)? & 1 != 0,
+                }
+            }
+            1 => {
+                MadtEntry::IoApic {
+                    id: table.read_u8(offset + 2)?,
+                    address: table.read_u32(offset + 4)?,
+                    gsi_base: table.read_u32(offset + 8)?,
+                }
+            }
+            2 => {
+                MadtEntry::InterruptOverride {
+                    bus: table.read_u8(offset + 2)?,
+                    source: table.read_u8(offset + 3)?,
+                    gsi: table.read_u32(offset + 4)?,
+                    flags: table.read_u16(offset + 8)?,
+                }
+            }
+            4 => {
+                MadtEntry::LocalApicNmi {
+                    processor_id: table.read_u8(offset + 2)?,
+                    flags: table.read_u16(offset + 3)?,
+                    lint: table.read_u8(offset + 5)?,
+                }
+            }
+            5 => {
+                MadtEntry::LocalApicAddressOverride {
+                    address: table.read_u64(offset + 4)?,
+                }
+            }
+            9 => {
+                MadtEntry::LocalX2Apic {
+                    x2apic_id: table.read_u32(offset + 4)?,
+                    enabled: table.read_u32(offset + 8)? & 1 != 0,
+                    processor_uid: table.read_u32(offset + 12)?,
+                }
+            }
+            kind => MadtEntry::Unknown(kind),
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{
+    error::Error,
+    sdt::{
+        SdtHeader,
+        TableData,
+        SDT_HEADER_SIZE,
+    },
+    AcpiTable,
+};
+
+const ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+const ENTRY_SIZE: usize = 16;
+
+/// An entry of the MCFG describes the memory-mapped configuration space of a range of PCI buses
+#[derive(Clone, Copy, Debug)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    /// This function returns the address of the configuration space of the specified function, if
+    /// the bus is covered by this entry.
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+
+        let offset =
+            ((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12;
+        Some(self.base_address + offset)
+    }
+}
+
+/// The PCI Express Memory-mapped Configuration table (MCFG) describes the ECAM regions of the PCI
+/// segment groups.
+#[derive(Clone, Copy)]
+pub struct Mcfg<'a> {
+    pub header: SdtHeader,
+    table: TableData<'a>,
+}
+
+impl<'a> AcpiTable<'a> for Mcfg<'a> {
+    const SIGNATURE: &'static str = "MCFG";
+
+    fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        Ok(Self {
+            header: SdtHeader::read(data)?,
+            table: TableData::new(Self::SIGNATURE, data)?,
+        })
+    }
+}
+
+impl<'a> Mcfg<'a> {
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> + 'a {
+        let table = self.table;
+        let entry_count = table.data.len().saturating_sub(ENTRIES_OFFSET) / ENTRY_SIZE;
+        (0..entry_count).filter_map(move |index| {
+            let offset = ENTRIES_OFFSET + index * ENTRY_SIZE;
+            Some(McfgEntry {
+                base_address: table.read_u64(offset).ok()?,
+                segment_group: table.read_u16(offset + 8).ok()?,
+                start_bus: table.read_u8(offset + 10).ok()?,
+                end_bus: table.read_u8(offset + 11).ok()?,
+            })
+        })
+    }
+}
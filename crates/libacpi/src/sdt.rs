@@ -0,0 +1,99 @@
+use crate::error::Error;
+
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// The header, which is shared by all System Description Tables
+#[derive(Clone, Copy, Debug)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn read(data: &[u8]) -> Result<Self, Error> {
+        let data = data
+            .get(..SDT_HEADER_SIZE)
+            .ok_or(Error::TableTooShort("SDT", SDT_HEADER_SIZE))?;
+        Ok(Self {
+            signature: data[0..4].try_into().unwrap(),
+            length: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            revision: data[8],
+            oem_id: data[10..16].try_into().unwrap(),
+            oem_table_id: data[16..24].try_into().unwrap(),
+            oem_revision: u32::from_le_bytes(data[24..28].try_into().unwrap()),
+        })
+    }
+}
+
+/// The Generic Address Structure (GAS) describes a register in memory, I/O or PCI configuration
+/// space.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0x00;
+    pub const SYSTEM_IO: u8 = 0x01;
+
+    pub(crate) fn read(table: &TableData, offset: usize) -> Result<Self, Error> {
+        Ok(Self {
+            address_space: table.read_u8(offset)?,
+            bit_width: table.read_u8(offset + 1)?,
+            bit_offset: table.read_u8(offset + 2)?,
+            access_size: table.read_u8(offset + 3)?,
+            address: table.read_u64(offset + 4)?,
+        })
+    }
+}
+
+/// The table data provides bounds-checked little-endian reads into a mapped table
+#[derive(Clone, Copy)]
+pub(crate) struct TableData<'a> {
+    pub(crate) name: &'static str,
+    pub(crate) data: &'a [u8],
+}
+
+impl<'a> TableData<'a> {
+    pub(crate) fn new(name: &'static str, data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < SDT_HEADER_SIZE {
+            return Err(Error::TableTooShort(name, SDT_HEADER_SIZE));
+        }
+        Ok(Self { name, data })
+    }
+
+    pub(crate) fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], Error> {
+        self.data
+            .get(offset..offset + N)
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or(Error::TableTooShort(self.name, offset))
+    }
+
+    #[inline]
+    pub(crate) fn read_u8(&self, offset: usize) -> Result<u8, Error> {
+        Ok(self.bytes::<1>(offset)?[0])
+    }
+
+    #[inline]
+    pub(crate) fn read_u16(&self, offset: usize) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.bytes(offset)?))
+    }
+
+    #[inline]
+    pub(crate) fn read_u32(&self, offset: usize) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(offset)?))
+    }
+
+    #[inline]
+    pub(crate) fn read_u64(&self, offset: usize) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(offset)?))
+    }
+}
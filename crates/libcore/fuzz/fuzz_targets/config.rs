@@ -0,0 +1,19 @@
+//! The fuzz target of the boot configuration parser. The raw data is parsed like the configuration,
+//! which is read by the bootloader from the EFI system partition.
+
+#![no_main]
+
+use libcore::config::BootConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(config) = BootConfig::parse_bytes(data) else {
+        return;
+    };
+
+    let _ = config.default_index();
+    for entry in config.entries() {
+        let _ = config.entry_by_title(entry.title);
+        for _ in entry.unit_titles() {}
+    }
+});
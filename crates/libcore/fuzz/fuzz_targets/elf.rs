@@ -0,0 +1,60 @@
+//! The fuzz target of the ELF parser. Every accessor, which is used by the ELF loader of the
+//! bootloader, is called with the parsed file, so a malformed file must result in an error instead
+//! of a panic or an invalid read.
+
+#![no_main]
+
+use libcore::elf::{
+    ElfFile,
+    SHT_RELA,
+    SHT_SYMTAB,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(file) = ElfFile::parse(data) else {
+        return;
+    };
+
+    for segment in file.program_headers() {
+        let Ok(segment) = segment else {
+            break;
+        };
+        let _ = file.segment_data(&segment);
+        let _ = file.file_offset(segment.virtual_address);
+    }
+
+    for section in file.section_headers() {
+        let Ok(section) = section else {
+            break;
+        };
+        let _ = file.section_name(&section);
+        let _ = file.section_data(&section);
+        match section.kind {
+            SHT_SYMTAB => {
+                for symbol in file.symbols(&section) {
+                    if symbol.is_err() {
+                        break;
+                    }
+                }
+            }
+            SHT_RELA => {
+                for relocation in file.relocations(&section) {
+                    if relocation.is_err() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Ok(relocations) = file.dynamic_relocations() else {
+        return;
+    };
+    for relocation in relocations {
+        if relocation.is_err() {
+            break;
+        }
+    }
+});
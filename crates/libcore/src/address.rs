@@ -0,0 +1,241 @@
+use core::{
+    fmt::{
+        Debug,
+        Formatter,
+        LowerHex,
+        UpperHex,
+    },
+    ops::{
+        Add,
+        AddAssign,
+        Sub,
+        SubAssign,
+    },
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+/// Physical addresses are limited to 52 bits by the page table format
+const PHYSICAL_ADDRESS_MASK: u64 = (1 << 52) - 1;
+
+/// The offset of the physical memory map (physmap) in the virtual address space. The bootloader and
+/// the early kernel run with identity-mapped memory, so the offset is zero until the kernel creates
+/// its own address space.
+static PHYSMAP_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// This function sets the virtual address, at which the physical memory is mapped linearly
+pub fn set_physmap_offset(offset: VirtAddr) {
+    PHYSMAP_OFFSET.store(offset.as_u64(), Ordering::Release);
+}
+
+#[inline]
+pub fn physmap_offset() -> VirtAddr {
+    VirtAddr(PHYSMAP_OFFSET.load(Ordering::Acquire))
+}
+
+/// A physical address of the memory. Physical addresses can't be dereferenced directly, they have
+/// to be converted into a virtual address with [PhysAddr::to_virt] before.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysAddr(u64);
+
+/// A virtual address in the address space of the current page tables
+#[repr(transparent)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VirtAddr(u64);
+
+/// This macro implements the alignment helpers, the checked arithmetic and the formatting, which
+/// are shared by both address types.
+macro_rules! impl_address {
+    ($name: ident) => {
+        impl $name {
+            pub const NULL: Self = Self(0);
+
+            #[inline]
+            pub const fn as_u64(self) -> u64 {
+                self.0
+            }
+
+            #[inline]
+            pub const fn is_null(self) -> bool {
+                self.0 == 0
+            }
+
+            /// This function returns true, if the address is aligned to the specified power of two
+            #[inline]
+            pub const fn is_aligned(self, align: u64) -> bool {
+                self.0 & (align - 1) == 0
+            }
+
+            /// This function rounds the address down to the specified power of two
+            #[inline]
+            pub const fn align_down(self, align: u64) -> Self {
+                Self(self.0 & !(align - 1))
+            }
+
+            /// This function rounds the address up to the specified power of two
+            #[inline]
+            pub fn align_up(self, align: u64) -> Self {
+                Self::new(
+                    self.0
+                        .checked_add(align - 1)
+                        .expect("Address overflow while aligning")
+                        & !(align - 1),
+                )
+            }
+
+            #[inline]
+            pub fn checked_add(self, offset: u64) -> Option<Self> {
+                Self::try_new(self.0.checked_add(offset)?)
+            }
+
+            #[inline]
+            pub fn checked_sub(self, offset: u64) -> Option<Self> {
+                Self::try_new(self.0.checked_sub(offset)?)
+            }
+
+            /// This function returns the distance from the specified address to this address, if
+            /// the specified address isn't above this address.
+            #[inline]
+            pub const fn offset_from(self, base: Self) -> Option<u64> {
+                self.0.checked_sub(base.0)
+            }
+        }
+
+        impl Add<u64> for $name {
+            type Output = Self;
+
+            #[inline]
+            fn add(self, offset: u64) -> Self {
+                self.checked_add(offset)
+                    .expect(concat!(stringify!($name), " overflow"))
+            }
+        }
+
+        impl AddAssign<u64> for $name {
+            #[inline]
+            fn add_assign(&mut self, offset: u64) {
+                *self = *self + offset;
+            }
+        }
+
+        impl Sub<u64> for $name {
+            type Output = Self;
+
+            #[inline]
+            fn sub(self, offset: u64) -> Self {
+                self.checked_sub(offset)
+                    .expect(concat!(stringify!($name), " underflow"))
+            }
+        }
+
+        impl SubAssign<u64> for $name {
+            #[inline]
+            fn sub_assign(&mut self, offset: u64) {
+                *self = *self - offset;
+            }
+        }
+
+        impl Sub<$name> for $name {
+            type Output = u64;
+
+            #[inline]
+            fn sub(self, base: $name) -> u64 {
+                self.offset_from(base)
+                    .expect(concat!(stringify!($name), " underflow"))
+            }
+        }
+
+        impl Debug for $name {
+            fn fmt(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, concat!(stringify!($name), "(0x{:X})"), self.0)
+            }
+        }
+
+        impl UpperHex for $name {
+            #[inline]
+            fn fmt(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                UpperHex::fmt(&self.0, formatter)
+            }
+        }
+
+        impl LowerHex for $name {
+            #[inline]
+            fn fmt(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                LowerHex::fmt(&self.0, formatter)
+            }
+        }
+    };
+}
+
+impl_address!(PhysAddr);
+impl_address!(VirtAddr);
+
+impl PhysAddr {
+    /// This function creates a physical address and panics, if the address exceeds 52 bits
+    #[inline]
+    pub fn new(address: u64) -> Self {
+        Self::try_new(address).expect("Physical address exceeds 52 bits")
+    }
+
+    #[inline]
+    pub const fn try_new(address: u64) -> Option<Self> {
+        if address & !PHYSICAL_ADDRESS_MASK != 0 {
+            return None;
+        }
+        Some(Self(address))
+    }
+
+    /// This function returns the address of this physical address in the physmap
+    #[inline]
+    pub fn to_virt(self) -> VirtAddr {
+        physmap_offset() + self.0
+    }
+}
+
+impl VirtAddr {
+    /// This function creates a virtual address and panics, if the address isn't canonical
+    #[inline]
+    pub fn new(address: u64) -> Self {
+        Self::try_new(address).expect("Virtual address isn't canonical")
+    }
+
+    /// This function creates a virtual address, if the bits 48 to 63 are a sign extension of bit 47
+    #[inline]
+    pub const fn try_new(address: u64) -> Option<Self> {
+        match address >> 47 {
+            0 | 0x1FFFF => Some(Self(address)),
+            _ => None,
+        }
+    }
+
+    /// This function creates a virtual address and sign extends bit 47 into the upper bits
+    #[inline]
+    pub const fn new_truncate(address: u64) -> Self {
+        Self(((address << 16) as i64 >> 16) as u64)
+    }
+
+    #[inline]
+    pub fn from_ptr<T>(pointer: *const T) -> Self {
+        Self::new(pointer as u64)
+    }
+
+    #[inline]
+    pub const fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    #[inline]
+    pub const fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+
+    /// This function returns the physical address of this address, if it's located in the physmap.
+    /// Other addresses have to be translated with the page tables.
+    #[inline]
+    pub fn physmap_to_phys(self) -> Option<PhysAddr> {
+        PhysAddr::try_new(self.offset_from(physmap_offset())?)
+    }
+}
@@ -0,0 +1,179 @@
+use crate::table::{
+    Alignment,
+    Column,
+    Table,
+};
+use core::{
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// The upper bounds (inclusive) of the size classes in bytes. Larger allocations are counted in
+/// the last size class.
+pub const SIZE_CLASSES: [usize; 6] = [64, 256, 1024, 4096, 65536, usize::MAX];
+
+static SIZE_CLASS_TABLE: Table = Table::new(&[
+    Column::new("Size Class", 12, Alignment::Left),
+    Column::new("Allocations", 11, Alignment::Right),
+    Column::new("Frees", 11, Alignment::Right),
+    Column::new("Failures", 8, Alignment::Right),
+]);
+
+struct SizeClassCounters {
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+/// The allocation statistics are updated by an allocator on every allocation and free. All
+/// counters are atomic, so the statistics can be read without holding the lock of the allocator.
+pub struct AllocationStats {
+    bytes_in_use: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    size_classes: [SizeClassCounters; SIZE_CLASSES.len()],
+}
+
+/// The counters of one size class at the time of the report
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SizeClassReport {
+    pub limit: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failures: usize,
+}
+
+/// A snapshot of the allocation statistics, which is shown in diagnostics
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocationReport {
+    pub allocations: usize,
+    pub frees: usize,
+    pub failures: usize,
+    pub bytes_in_use: usize,
+    pub peak_bytes: usize,
+    pub size_classes: [SizeClassReport; SIZE_CLASSES.len()],
+}
+
+impl AllocationStats {
+    pub const fn new() -> Self {
+        const COUNTERS: SizeClassCounters = SizeClassCounters {
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+        };
+
+        Self {
+            bytes_in_use: AtomicUsize::new(0),
+            peak_bytes: AtomicUsize::new(0),
+            size_classes: [COUNTERS; SIZE_CLASSES.len()],
+        }
+    }
+
+    #[inline]
+    fn size_class(&self, size: usize) -> &SizeClassCounters {
+        let index = SIZE_CLASSES
+            .iter()
+            .position(|limit| size <= *limit)
+            .unwrap_or(SIZE_CLASSES.len() - 1);
+        &self.size_classes[index]
+    }
+
+    pub fn record_allocation(&self, size: usize) {
+        self.size_class(size)
+            .allocations
+            .fetch_add(1, Ordering::Relaxed);
+        let bytes_in_use = self.bytes_in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(bytes_in_use, Ordering::Relaxed);
+    }
+
+    pub fn record_free(&self, size: usize) {
+        self.size_class(size).frees.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in_use.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self, size: usize) {
+        self.size_class(size)
+            .failures
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// This function returns a snapshot of the current statistics
+    pub fn report(&self) -> AllocationReport {
+        let mut report = AllocationReport {
+            bytes_in_use: self.bytes_in_use.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            ..AllocationReport::default()
+        };
+        for ((counters, limit), size_class) in self
+            .size_classes
+            .iter()
+            .zip(SIZE_CLASSES)
+            .zip(report.size_classes.iter_mut())
+        {
+            *size_class = SizeClassReport {
+                limit,
+                allocations: counters.allocations.load(Ordering::Relaxed),
+                frees: counters.frees.load(Ordering::Relaxed),
+                failures: counters.failures.load(Ordering::Relaxed),
+            };
+            report.allocations += size_class.allocations;
+            report.frees += size_class.frees;
+            report.failures += size_class.failures;
+        }
+        report
+    }
+}
+
+impl Default for AllocationStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct SizeClassName(usize);
+
+impl Display for SizeClassName {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            usize::MAX => write!(formatter, "> {} KiB", SIZE_CLASSES[SIZE_CLASSES.len() - 2] / 1024),
+            limit if limit >= 1024 => write!(formatter, "<= {} KiB", limit / 1024),
+            limit => write!(formatter, "<= {} B", limit),
+        }
+    }
+}
+
+impl Display for AllocationReport {
+    /// The report is written as multiple lines, every line is terminated with a line break
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            formatter,
+            "{} allocations, {} frees, {} failures, {} kB in use (Peak: {} kB)",
+            self.allocations,
+            self.frees,
+            self.failures,
+            self.bytes_in_use / 1024,
+            self.peak_bytes / 1024
+        )?;
+        writeln!(formatter, "{}", SIZE_CLASS_TABLE.header())?;
+        writeln!(formatter, "{}", SIZE_CLASS_TABLE.separator())?;
+        for size_class in &self.size_classes {
+            writeln!(
+                formatter,
+                "{}",
+                SIZE_CLASS_TABLE.row(&[
+                    &SizeClassName(size_class.limit),
+                    &size_class.allocations,
+                    &size_class.frees,
+                    &size_class.failures
+                ])
+            )?;
+        }
+        Ok(())
+    }
+}
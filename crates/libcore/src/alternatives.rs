@@ -0,0 +1,141 @@
+//! Alternatives provide generic and optimized variants of hot routines (like the memory copy of
+//! the framebuffer). The best variant, whose CPU features are supported and enabled, is selected
+//! once with [apply_alternatives] and patched into the function pointer of the routine, so the
+//! routines don't check the CPU features on every call. Until the alternatives are applied, the
+//! generic variant is used.
+
+use crate::fastmem::{
+    self,
+    SimdLevel,
+};
+use core::{
+    arch::x86_64::__cpuid_count,
+    sync::atomic::{
+        AtomicPtr,
+        Ordering,
+    },
+};
+
+/// The bits of the extended features (CPUID leaf 7, EBX)
+const EXTENDED_FEATURES_LEAF: u32 = 0x07;
+const EXTENDED_FEATURE_ERMS: u32 = 1 << 9;
+
+/// The routines with alternatives, which are patched by [apply_alternatives]
+static ALTERNATIVES: &[&dyn Patch] = &[&fastmem::COPY, &fastmem::FILL];
+
+/// A CPU feature, which is required by a variant of a routine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    Sse2,
+    Avx,
+    /// Enhanced `rep movsb` and `rep stosb`
+    Erms,
+}
+
+impl Feature {
+    /// This function checks, whether the feature is supported by the CPU and enabled by the
+    /// operating system (like the AVX state in XCR0)
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Self::Sse2 => fastmem::detect_simd_level() >= SimdLevel::Sse2,
+            Self::Avx => fastmem::detect_simd_level() >= SimdLevel::Avx,
+            Self::Erms => extended_features() & EXTENDED_FEATURE_ERMS != 0,
+        }
+    }
+}
+
+/// A variant of a routine with the CPU features, which are needed by it
+pub struct Variant<F: 'static> {
+    pub name: &'static str,
+    pub features: &'static [Feature],
+    pub function: F,
+}
+
+impl<F> Variant<F> {
+    #[inline]
+    fn is_usable(&self) -> bool {
+        self.features.iter().all(Feature::is_enabled)
+    }
+}
+
+/// A routine with its variants, which are ordered from the best to the generic variant. The generic
+/// variant is the last variant and must not require any features.
+pub struct Alternative<F: 'static> {
+    pub name: &'static str,
+    variants: &'static [Variant<F>],
+    selected: AtomicPtr<Variant<F>>,
+}
+
+impl<F: Copy> Alternative<F> {
+    pub const fn new(name: &'static str, variants: &'static [Variant<F>]) -> Self {
+        Self {
+            name,
+            variants,
+            selected: AtomicPtr::new(&variants[variants.len() - 1] as *const _ as *mut _),
+        }
+    }
+
+    /// This function returns the function of the selected variant
+    #[inline]
+    pub fn get(&self) -> F {
+        unsafe { (*self.selected.load(Ordering::Relaxed)).function }
+    }
+}
+
+/// The type-erased interface of the alternatives, so routines with different signatures can be
+/// patched together
+pub trait Patch: Sync {
+    fn name(&self) -> &'static str;
+
+    /// This function selects the best usable variant and returns its name
+    fn patch(&self) -> &'static str;
+
+    /// This function returns the name of the selected variant
+    fn selected(&self) -> &'static str;
+}
+
+impl<F: Copy + Sync> Patch for Alternative<F> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn patch(&self) -> &'static str {
+        let variant = self
+            .variants
+            .iter()
+            .find(|variant| variant.is_usable())
+            .unwrap_or(&self.variants[self.variants.len() - 1]);
+        self.selected
+            .store(variant as *const _ as *mut _, Ordering::Relaxed);
+        variant.name
+    }
+
+    fn selected(&self) -> &'static str {
+        unsafe { (*self.selected.load(Ordering::Relaxed)).name }
+    }
+}
+
+/// This function selects the variants of all routines. It has to be called again after the FPU
+/// configuration was changed (like enabling XSAVE in the kernel).
+pub fn apply_alternatives() {
+    for alternative in ALTERNATIVES {
+        alternative.patch();
+    }
+}
+
+/// This function returns the names of the routines with the name of their selected variant
+pub fn alternatives() -> impl Iterator<Item = (&'static str, &'static str)> {
+    ALTERNATIVES
+        .iter()
+        .map(|alternative| (alternative.name(), alternative.selected()))
+}
+
+/// This function returns the extended feature bits. If the leaf isn't supported, no features are
+/// reported.
+fn extended_features() -> u32 {
+    let max_leaf = unsafe { __cpuid_count(0, 0) }.eax;
+    if max_leaf < EXTENDED_FEATURES_LEAF {
+        return 0;
+    }
+    unsafe { __cpuid_count(EXTENDED_FEATURES_LEAF, 0) }.ebx
+}
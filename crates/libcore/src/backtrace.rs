@@ -0,0 +1,37 @@
+use core::arch::asm;
+
+/// The maximal number of frames, which are walked. This limits the walk on corrupted stacks.
+pub const MAX_FRAMES: usize = 32;
+
+/// This function walks the frame pointer chain of the current stack and returns the return address
+/// of every frame, starting with the caller of this function. The code has to be compiled with
+/// frame pointers (`-C force-frame-pointers=yes`), otherwise the walk stops early.
+pub fn backtrace() -> impl Iterator<Item = u64> {
+    let mut frame_pointer: u64;
+    unsafe { asm!("mov {}, rbp", out(reg) frame_pointer, options(nomem, nostack)) };
+
+    let mut count = 0;
+    core::iter::from_fn(move || {
+        if frame_pointer == 0 || frame_pointer % 8 != 0 || count >= MAX_FRAMES {
+            return None;
+        }
+
+        // The frame contains the frame pointer of the caller and the return address into the caller
+        let (next_frame_pointer, return_address) = unsafe {
+            let frame = frame_pointer as *const u64;
+            (frame.read(), frame.add(1).read())
+        };
+        if return_address == 0 {
+            return None;
+        }
+
+        // The stack grows down, so the frames of the callers are located at higher addresses
+        frame_pointer = if next_frame_pointer > frame_pointer {
+            next_frame_pointer
+        } else {
+            0
+        };
+        count += 1;
+        Some(return_address)
+    })
+}
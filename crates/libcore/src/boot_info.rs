@@ -0,0 +1,868 @@
+//! The boot information is handed from the bootloader to the kernel as a self-describing structure:
+//! a header with magic, version and length is followed by typed tags (like Multiboot2). Every tag is
+//! a list of 64-bit words, so the bootloader and the kernel can evolve independently. The decoder
+//! skips unknown tags and missing tags or words are decoded with their default value (zero).
+
+use crate::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    build_stamp::BuildStamp,
+    cmdline::CommandLine,
+    error::Error,
+    initrd::Initrd,
+    memory_map::MemoryMap,
+    persistent_log::PersistentLog,
+};
+
+pub const BOOT_INFO_MAGIC: u64 = 0x4F5646_424F4F54; // "OVFBOOT"
+
+/// The version of the encoding. It's only incremented for incompatible changes of the header or the
+/// tag format, new information is added with new tags or new words at the end of a tag.
+pub const BOOT_INFO_VERSION: u32 = 1;
+
+/// The size of the header (magic, version and length) and of the tag header (type and word count)
+const HEADER_SIZE: usize = 16;
+const TAG_HEADER_SIZE: usize = 8;
+
+/// The types of the tags of the encoded boot information
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagType {
+    /// The last tag of the boot information
+    End = 0,
+    /// Address, size and slide of the kernel
+    Kernel = 1,
+    /// Address and size of the command line
+    CommandLine = 2,
+    /// Address and size of the initrd
+    Initrd = 3,
+    /// Address of the ACPI RSDP
+    Rsdp = 4,
+    /// Address of the flattened device tree
+    DeviceTree = 5,
+    /// The words of [FramebufferInfo] in the order of the fields
+    Framebuffer = 6,
+    /// The words of [FrameAllocatorHandoff] in the order of the fields
+    FrameAllocator = 7,
+    /// Address and count of the boot units
+    BootUnits = 8,
+    /// Address and size of the persistent log
+    PersistentLog = 9,
+    /// Address and page count of the memory for the crash kernel
+    CrashKernel = 10,
+    /// Address, size and descriptor size of the final memory map of the firmware
+    MemoryMap = 11,
+    /// Virtual address of the relocated Runtime Services table
+    RuntimeServices = 12,
+    /// The words of [TlsTemplate] in the order of the fields
+    Tls = 13,
+    /// The encoded [BuildStamp] of the bootloader
+    BootloaderStamp = 14,
+    /// Address of the trace buffer with the events of the bootloader
+    Trace = 15,
+}
+
+impl TagType {
+    pub const fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::End),
+            1 => Some(Self::Kernel),
+            2 => Some(Self::CommandLine),
+            3 => Some(Self::Initrd),
+            4 => Some(Self::Rsdp),
+            5 => Some(Self::DeviceTree),
+            6 => Some(Self::Framebuffer),
+            7 => Some(Self::FrameAllocator),
+            8 => Some(Self::BootUnits),
+            9 => Some(Self::PersistentLog),
+            10 => Some(Self::CrashKernel),
+            11 => Some(Self::MemoryMap),
+            12 => Some(Self::RuntimeServices),
+            13 => Some(Self::Tls),
+            14 => Some(Self::BootloaderStamp),
+            15 => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// The writer, which encodes the header and the tags into a buffer
+pub struct TagWriter<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+}
+
+impl<'a> TagWriter<'a> {
+    /// This function creates the writer. The header is written by [TagWriter::finish].
+    pub fn new(buffer: &'a mut [u8]) -> Result<Self, Error> {
+        if buffer.len() < HEADER_SIZE {
+            return Err(Error::BootInfoTooLarge(HEADER_SIZE));
+        }
+
+        Ok(Self {
+            buffer,
+            length: HEADER_SIZE,
+        })
+    }
+
+    /// This function appends a tag with the specified words. If the buffer is too small, this
+    /// function returns an error.
+    pub fn push(&mut self, tag_type: TagType, words: &[u64]) -> Result<(), Error> {
+        let end = self.length + TAG_HEADER_SIZE + words.len() * 8;
+        let tag = self
+            .buffer
+            .get_mut(self.length..end)
+            .ok_or(Error::BootInfoTooLarge(end))?;
+        tag[..4].copy_from_slice(&(tag_type as u32).to_le_bytes());
+        tag[4..8].copy_from_slice(&(words.len() as u32).to_le_bytes());
+        for (chunk, word) in tag[TAG_HEADER_SIZE..].chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        self.length = end;
+        Ok(())
+    }
+
+    /// This function appends the end tag, writes the header and returns the length of the encoded
+    /// boot information
+    pub fn finish(mut self) -> Result<usize, Error> {
+        self.push(TagType::End, &[])?;
+        self.buffer[..8].copy_from_slice(&BOOT_INFO_MAGIC.to_le_bytes());
+        self.buffer[8..12].copy_from_slice(&BOOT_INFO_VERSION.to_le_bytes());
+        self.buffer[12..16].copy_from_slice(&(self.length as u32).to_le_bytes());
+        Ok(self.length)
+    }
+}
+
+/// A tag of the encoded boot information
+#[derive(Clone, Copy)]
+pub struct Tag<'a> {
+    /// The raw type of the tag, tags of newer bootloaders have no [TagType]
+    pub tag_type: u32,
+    words: &'a [u8],
+}
+
+impl<'a> Tag<'a> {
+    #[inline]
+    pub fn word_count(&self) -> usize {
+        self.words.len() / 8
+    }
+
+    /// This function returns the word at the specified index or zero, if the tag was encoded with
+    /// less words (like by an older bootloader)
+    pub fn word(&self, index: usize) -> u64 {
+        self.words
+            .get(index * 8..index * 8 + 8)
+            .map_or(0, |word| u64::from_le_bytes(word.try_into().unwrap()))
+    }
+
+    /// This function returns the word at the specified index as physical address
+    pub fn address(&self, index: usize) -> Result<PhysAddr, Error> {
+        PhysAddr::try_new(self.word(index)).ok_or(Error::InvalidBootInfoTag(self.tag_type))
+    }
+}
+
+/// The iterator over the tags of the encoded boot information, the end tag isn't returned
+#[derive(Clone)]
+pub struct Tags<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Tags<'a> {
+    /// This function validates the header and the tags of the encoded boot information. If the
+    /// magic or the version is invalid or a tag exceeds the length, this function returns an error.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let read_u32 = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or(Error::BootInfoOutOfBounds(offset))
+        };
+        let magic = data
+            .get(..8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(Error::BootInfoOutOfBounds(0))?;
+        if magic != BOOT_INFO_MAGIC {
+            return Err(Error::InvalidBootInfoMagic(magic));
+        }
+        let version = read_u32(8)?;
+        if version == 0 || version > BOOT_INFO_VERSION {
+            return Err(Error::UnsupportedBootInfoVersion(version));
+        }
+        let length = read_u32(12)? as usize;
+        let data = data
+            .get(..length)
+            .ok_or(Error::BootInfoOutOfBounds(length))?;
+
+        // Walk over all tags, so the iterator doesn't need to handle truncated tags
+        let mut offset = HEADER_SIZE;
+        loop {
+            let tag_type = read_u32(offset)?;
+            let end = offset + TAG_HEADER_SIZE + read_u32(offset + 4)? as usize * 8;
+            if end > data.len() {
+                return Err(Error::BootInfoOutOfBounds(offset));
+            }
+            if tag_type == TagType::End as u32 {
+                break;
+            }
+            offset = end;
+        }
+
+        Ok(Self {
+            data,
+            offset: HEADER_SIZE,
+        })
+    }
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.data.get(self.offset..self.offset + TAG_HEADER_SIZE)?;
+        let tag_type = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let word_count = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        if tag_type == TagType::End as u32 {
+            return None;
+        }
+
+        let start = self.offset + TAG_HEADER_SIZE;
+        self.offset = start + word_count * 8;
+        Some(Tag {
+            tag_type,
+            words: &self.data[start..self.offset],
+        })
+    }
+}
+
+/// The boot information is created by the bootloader and passed to the kernel entry. It describes
+/// everything the kernel can't discover by itself after the UEFI Boot Services were exited. It's
+/// handed over encoded with [BootInfo::encode] and decoded by the kernel with [BootInfo::decode].
+#[derive(Clone, Copy)]
+pub struct BootInfo {
+    pub initrd_address: PhysAddr,
+    pub initrd_size: u64,
+    pub kernel_address: PhysAddr,
+    pub kernel_size: u64,
+    /// The difference between the load address and the link address of the kernel. Addresses from
+    /// the kernel symbol table have to be shifted by this value.
+    pub kernel_slide: i64,
+    pub command_line_address: PhysAddr,
+    pub command_line_size: u64,
+    /// The physical address of the ACPI RSDP, which was found in the UEFI configuration table
+    pub rsdp_address: PhysAddr,
+    /// The physical address of the flattened device tree, which was found in the UEFI configuration
+    /// table. The address is null, if the firmware doesn't provide a device tree.
+    pub device_tree_address: PhysAddr,
+    /// The framebuffer, which was used by the bootloader. The kernel takes the ownership of it.
+    pub framebuffer: FramebufferInfo,
+    /// The state of the frame allocator after the bootloader reserved all used memory
+    pub frame_allocator: FrameAllocatorHandoff,
+    /// The boot units, which were loaded besides the kernel (like test kernels or a crash kernel)
+    pub boot_units_address: PhysAddr,
+    pub boot_unit_count: u64,
+    /// The persistent log, which keeps the log output across a warm reset. The address is null, if
+    /// the persistent log isn't enabled.
+    pub persistent_log_address: PhysAddr,
+    pub persistent_log_size: u64,
+    /// The memory, which is reserved for a crash kernel. The region has no pages, if no memory was
+    /// reserved.
+    pub crash_kernel_region: ReservedRegion,
+    /// The memory map, which was retrieved while exiting the Boot Services. The address is null, if
+    /// the kernel wasn't started by the bootloader.
+    pub memory_map_address: PhysAddr,
+    pub memory_map_size: u64,
+    pub memory_descriptor_size: u64,
+    /// The Runtime Services table, which was relocated into the runtime window with
+    /// `SetVirtualAddressMap`. The address is null, if the Runtime Services weren't relocated.
+    pub runtime_services_address: VirtAddr,
+    /// The template of the thread-local storage of the kernel (`PT_TLS`)
+    pub tls_template: TlsTemplate,
+    /// The build stamp of the bootloader, so the kernel log identifies the whole boot chain
+    pub bootloader_stamp: BuildStamp,
+    /// The trace buffer with the events of the bootloader, which is continued by the kernel. The
+    /// address is null, if tracing isn't enabled.
+    pub trace_buffer_address: PhysAddr,
+}
+
+/// The template of the thread-local storage, which is copied into the TLS block of every thread.
+/// Only the first `file_size` bytes are initialized, the remaining bytes of the block (`.tbss`)
+/// have to be zeroed by the kernel.
+#[derive(Clone, Copy, Debug)]
+pub struct TlsTemplate {
+    /// The address of the template in the loaded kernel
+    pub address: VirtAddr,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub alignment: u64,
+}
+
+impl TlsTemplate {
+    pub const NONE: Self = Self {
+        address: VirtAddr::NULL,
+        file_size: 0,
+        memory_size: 0,
+        alignment: 0,
+    };
+
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.memory_size != 0
+    }
+}
+
+/// The protocol, which is used to load and enter the kernel of a boot unit
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoadProtocol {
+    /// The kernel is an ELF file, which is entered with the boot information
+    #[default]
+    Elf = 0,
+    /// The kernel has a Multiboot2 header and is entered in 32-bit protected mode with the
+    /// Multiboot2 information
+    Multiboot2 = 1,
+    /// The kernel is a Linux bzImage, which is entered with the EFI handover protocol
+    Linux = 2,
+    /// The kernel is a flat binary, which is loaded at the configured physical address and entered
+    /// at the configured offset with the boot information
+    Binary = 3,
+}
+
+impl LoadProtocol {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "elf" => Some(Self::Elf),
+            "multiboot2" => Some(Self::Multiboot2),
+            "linux" => Some(Self::Linux),
+            "bin" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Elf => "elf",
+            Self::Multiboot2 => "multiboot2",
+            Self::Linux => "linux",
+            Self::Binary => "bin",
+        }
+    }
+}
+
+/// The role of a boot unit
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootUnitRole {
+    /// The kernel, which is started by the bootloader. It's not listed in the boot units.
+    Primary = 0,
+    /// An alternative kernel, which can be started instead of the kernel (like for A/B tests)
+    Secondary = 1,
+    /// The kernel, which is started after a crash of the kernel
+    CrashKernel = 2,
+}
+
+/// A boot unit, which was loaded besides the kernel. The files are kept unchanged in reserved
+/// memory, so the kernel can start the unit later.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct BootUnitInfo {
+    pub title_address: PhysAddr,
+    pub title_size: u64,
+    pub kernel_address: PhysAddr,
+    pub kernel_size: u64,
+    pub initrd_address: PhysAddr,
+    pub initrd_size: u64,
+    pub command_line_address: PhysAddr,
+    pub command_line_size: u64,
+    pub protocol: LoadProtocol,
+    pub role: BootUnitRole,
+}
+
+impl BootUnitInfo {
+    pub fn title(&self) -> &'static str {
+        core::str::from_utf8(handoff_slice(self.title_address, self.title_size)).unwrap_or_default()
+    }
+
+    /// This function returns the unchanged file of the kernel
+    pub fn kernel_image(&self) -> &'static [u8] {
+        handoff_slice(self.kernel_address, self.kernel_size)
+    }
+
+    pub fn initrd(&self) -> Option<Initrd<'static>> {
+        let data = handoff_slice(self.initrd_address, self.initrd_size);
+        (!data.is_empty()).then(|| Initrd::new(data))
+    }
+
+    pub fn command_line(&self) -> CommandLine<'static> {
+        let data = handoff_slice(self.command_line_address, self.command_line_size);
+        CommandLine::new(core::str::from_utf8(data).unwrap_or_default())
+    }
+}
+
+/// This function returns the memory, which was handed over by the bootloader, or an empty slice,
+/// if the address is null
+fn handoff_slice(address: PhysAddr, size: u64) -> &'static [u8] {
+    if address.is_null() || size == 0 {
+        return &[];
+    }
+    unsafe { core::slice::from_raw_parts(address.to_virt().as_ptr(), size as usize) }
+}
+
+/// A region of physical memory, which is reserved and never returned by the frame allocator
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReservedRegion {
+    pub address: PhysAddr,
+    pub page_count: u64,
+}
+
+/// The state of the frame allocator, which is handed over from the bootloader to the kernel. The
+/// kernel continues with the frame table of the bootloader, so no frame is allocated twice.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FrameAllocatorHandoff {
+    pub frame_table_address: PhysAddr,
+    pub frame_table_size: u64,
+    pub start_address: PhysAddr,
+    pub stop_address: PhysAddr,
+    pub page_size: u64,
+    pub reserved_regions_address: PhysAddr,
+    pub reserved_region_count: u64,
+    /// The table of the poisoned free frames, which has the size of the frame table. The address is
+    /// null, if the bootloader was built without poison checks.
+    pub poison_table_address: PhysAddr,
+}
+
+impl FrameAllocatorHandoff {
+    pub const NONE: Self = Self {
+        frame_table_address: PhysAddr::NULL,
+        frame_table_size: 0,
+        start_address: PhysAddr::NULL,
+        stop_address: PhysAddr::NULL,
+        page_size: 0,
+        reserved_regions_address: PhysAddr::NULL,
+        reserved_region_count: 0,
+        poison_table_address: PhysAddr::NULL,
+    };
+
+    /// This function returns the regions, which were reserved by the bootloader (like the kernel,
+    /// the initrd or the firmware memory).
+    pub fn reserved_regions(&self) -> &'static [ReservedRegion] {
+        if self.reserved_regions_address.is_null() {
+            return &[];
+        }
+
+        unsafe {
+            core::slice::from_raw_parts(
+                self.reserved_regions_address.to_virt().as_ptr(),
+                self.reserved_region_count as usize,
+            )
+        }
+    }
+}
+
+/// The memory layout of the pixels in a framebuffer
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 32 bits per pixel with blue in the lowest byte (0x00RRGGBB)
+    Xrgb8888 = 0,
+    /// 32 bits per pixel with red in the lowest byte (0x00BBGGRR)
+    Xbgr8888 = 1,
+    /// 24 bits per pixel, which are packed in the byte order blue, green and red
+    Rgb888Packed = 2,
+    /// 16 bits per pixel with 5 bits red, 6 bits green and 5 bits blue (red in the highest bits)
+    Rgb565 = 3,
+}
+
+impl PixelFormat {
+    pub const fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(Self::Xrgb8888),
+            1 => Some(Self::Xbgr8888),
+            2 => Some(Self::Rgb888Packed),
+            3 => Some(Self::Rgb565),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Xrgb8888 | Self::Xbgr8888 => 4,
+            Self::Rgb888Packed => 3,
+            Self::Rgb565 => 2,
+        }
+    }
+}
+
+/// The clockwise rotation of the screen content, which is needed to show it upright on displays,
+/// which are mounted rotated (like the panels of tablets)
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Normal = 0,
+    Rotated90 = 1,
+    Rotated180 = 2,
+    Rotated270 = 3,
+}
+
+impl Orientation {
+    pub const fn from_raw(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Rotated90),
+            2 => Some(Self::Rotated180),
+            3 => Some(Self::Rotated270),
+            _ => None,
+        }
+    }
+
+    pub const fn from_degrees(degrees: u64) -> Option<Self> {
+        match degrees {
+            0 => Some(Self::Normal),
+            90 => Some(Self::Rotated90),
+            180 => Some(Self::Rotated180),
+            270 => Some(Self::Rotated270),
+            _ => None,
+        }
+    }
+
+    /// This function returns true, if width and height of the screen are swapped
+    #[inline]
+    pub const fn is_transposed(self) -> bool {
+        matches!(self, Self::Rotated90 | Self::Rotated270)
+    }
+}
+
+/// The framebuffer and the swap buffer, which are handed over from the bootloader to the kernel
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FramebufferInfo {
+    pub address: PhysAddr,
+    pub size: u64,
+    /// The swap buffer in the size of the framebuffer. If it's null, the receiver allocates one.
+    pub swap_buffer_address: PhysAddr,
+    pub width: u32,
+    pub height: u32,
+    /// The number of pixels per scan line, which can be larger than the width
+    pub stride: u32,
+    pub pixel_format: PixelFormat,
+    pub orientation: Orientation,
+}
+
+impl FramebufferInfo {
+    pub const NONE: Self = Self {
+        address: PhysAddr::NULL,
+        size: 0,
+        swap_buffer_address: PhysAddr::NULL,
+        width: 0,
+        height: 0,
+        stride: 0,
+        pixel_format: PixelFormat::Xrgb8888,
+        orientation: Orientation::Normal,
+    };
+
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        !self.address.is_null() && self.size != 0
+    }
+}
+
+impl BootInfo {
+    pub const EMPTY: Self = Self {
+        initrd_address: PhysAddr::NULL,
+        initrd_size: 0,
+        kernel_address: PhysAddr::NULL,
+        kernel_size: 0,
+        kernel_slide: 0,
+        command_line_address: PhysAddr::NULL,
+        command_line_size: 0,
+        rsdp_address: PhysAddr::NULL,
+        device_tree_address: PhysAddr::NULL,
+        framebuffer: FramebufferInfo::NONE,
+        frame_allocator: FrameAllocatorHandoff::NONE,
+        boot_units_address: PhysAddr::NULL,
+        boot_unit_count: 0,
+        persistent_log_address: PhysAddr::NULL,
+        persistent_log_size: 0,
+        crash_kernel_region: ReservedRegion {
+            address: PhysAddr::NULL,
+            page_count: 0,
+        },
+        memory_map_address: PhysAddr::NULL,
+        memory_map_size: 0,
+        memory_descriptor_size: 0,
+        runtime_services_address: VirtAddr::NULL,
+        tls_template: TlsTemplate::NONE,
+        bootloader_stamp: BuildStamp::NONE,
+        trace_buffer_address: PhysAddr::NULL,
+    };
+
+    /// This function encodes the boot information into the buffer and returns the length of the
+    /// encoded boot information. If the buffer is too small, this function returns an error.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let framebuffer = &self.framebuffer;
+        let frame_allocator = &self.frame_allocator;
+        let mut writer = TagWriter::new(buffer)?;
+        writer.push(
+            TagType::Kernel,
+            &[
+                self.kernel_address.as_u64(),
+                self.kernel_size,
+                self.kernel_slide as u64,
+            ],
+        )?;
+        writer.push(
+            TagType::CommandLine,
+            &[self.command_line_address.as_u64(), self.command_line_size],
+        )?;
+        writer.push(TagType::Initrd, &[self.initrd_address.as_u64(), self.initrd_size])?;
+        writer.push(TagType::Rsdp, &[self.rsdp_address.as_u64()])?;
+        writer.push(TagType::DeviceTree, &[self.device_tree_address.as_u64()])?;
+        writer.push(
+            TagType::Framebuffer,
+            &[
+                framebuffer.address.as_u64(),
+                framebuffer.size,
+                framebuffer.swap_buffer_address.as_u64(),
+                framebuffer.width as u64,
+                framebuffer.height as u64,
+                framebuffer.stride as u64,
+                framebuffer.pixel_format as u64,
+                framebuffer.orientation as u64,
+            ],
+        )?;
+        writer.push(
+            TagType::FrameAllocator,
+            &[
+                frame_allocator.frame_table_address.as_u64(),
+                frame_allocator.frame_table_size,
+                frame_allocator.start_address.as_u64(),
+                frame_allocator.stop_address.as_u64(),
+                frame_allocator.page_size,
+                frame_allocator.reserved_regions_address.as_u64(),
+                frame_allocator.reserved_region_count,
+                frame_allocator.poison_table_address.as_u64(),
+            ],
+        )?;
+        writer.push(TagType::BootUnits, &[self.boot_units_address.as_u64(), self.boot_unit_count])?;
+        writer.push(
+            TagType::PersistentLog,
+            &[
+                self.persistent_log_address.as_u64(),
+                self.persistent_log_size,
+            ],
+        )?;
+        writer.push(
+            TagType::CrashKernel,
+            &[
+                self.crash_kernel_region.address.as_u64(),
+                self.crash_kernel_region.page_count,
+            ],
+        )?;
+        writer.push(
+            TagType::MemoryMap,
+            &[
+                self.memory_map_address.as_u64(),
+                self.memory_map_size,
+                self.memory_descriptor_size,
+            ],
+        )?;
+        writer.push(TagType::RuntimeServices, &[self.runtime_services_address.as_u64()])?;
+        writer.push(
+            TagType::Tls,
+            &[
+                self.tls_template.address.as_u64(),
+                self.tls_template.file_size,
+                self.tls_template.memory_size,
+                self.tls_template.alignment,
+            ],
+        )?;
+        if self.bootloader_stamp.is_present() {
+            writer.push(TagType::BootloaderStamp, &self.bootloader_stamp.to_words())?;
+        }
+        writer.push(TagType::Trace, &[self.trace_buffer_address.as_u64()])?;
+        writer.finish()
+    }
+
+    /// This function decodes the encoded boot information. Unknown tags are skipped and the fields
+    /// of missing tags keep their default value. If the header or a tag is invalid, this function
+    /// returns an error.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut boot_info = Self::EMPTY;
+        for tag in Tags::parse(data)? {
+            match TagType::from_raw(tag.tag_type) {
+                Some(TagType::Kernel) => {
+                    boot_info.kernel_address = tag.address(0)?;
+                    boot_info.kernel_size = tag.word(1);
+                    boot_info.kernel_slide = tag.word(2) as i64;
+                }
+                Some(TagType::CommandLine) => {
+                    boot_info.command_line_address = tag.address(0)?;
+                    boot_info.command_line_size = tag.word(1);
+                }
+                Some(TagType::Initrd) => {
+                    boot_info.initrd_address = tag.address(0)?;
+                    boot_info.initrd_size = tag.word(1);
+                }
+                Some(TagType::Rsdp) => boot_info.rsdp_address = tag.address(0)?,
+                Some(TagType::DeviceTree) => boot_info.device_tree_address = tag.address(0)?,
+                Some(TagType::Framebuffer) => {
+                    let invalid = || Error::InvalidBootInfoTag(tag.tag_type);
+                    boot_info.framebuffer = FramebufferInfo {
+                        address: tag.address(0)?,
+                        size: tag.word(1),
+                        swap_buffer_address: tag.address(2)?,
+                        width: tag.word(3) as u32,
+                        height: tag.word(4) as u32,
+                        stride: tag.word(5) as u32,
+                        pixel_format: PixelFormat::from_raw(tag.word(6)).ok_or_else(invalid)?,
+                        orientation: Orientation::from_raw(tag.word(7)).ok_or_else(invalid)?,
+                    };
+                }
+                Some(TagType::FrameAllocator) => {
+                    boot_info.frame_allocator = FrameAllocatorHandoff {
+                        frame_table_address: tag.address(0)?,
+                        frame_table_size: tag.word(1),
+                        start_address: tag.address(2)?,
+                        stop_address: tag.address(3)?,
+                        page_size: tag.word(4),
+                        reserved_regions_address: tag.address(5)?,
+                        reserved_region_count: tag.word(6),
+                        poison_table_address: tag.address(7)?,
+                    };
+                }
+                Some(TagType::BootUnits) => {
+                    boot_info.boot_units_address = tag.address(0)?;
+                    boot_info.boot_unit_count = tag.word(1);
+                }
+                Some(TagType::PersistentLog) => {
+                    boot_info.persistent_log_address = tag.address(0)?;
+                    boot_info.persistent_log_size = tag.word(1);
+                }
+                Some(TagType::CrashKernel) => {
+                    boot_info.crash_kernel_region = ReservedRegion {
+                        address: tag.address(0)?,
+                        page_count: tag.word(1),
+                    };
+                }
+                Some(TagType::MemoryMap) => {
+                    boot_info.memory_map_address = tag.address(0)?;
+                    boot_info.memory_map_size = tag.word(1);
+                    boot_info.memory_descriptor_size = tag.word(2);
+                }
+                Some(TagType::RuntimeServices) => {
+                    boot_info.runtime_services_address = VirtAddr::try_new(tag.word(0))
+                        .ok_or(Error::InvalidBootInfoTag(tag.tag_type))?;
+                }
+                Some(TagType::Tls) => {
+                    boot_info.tls_template = TlsTemplate {
+                        address: VirtAddr::try_new(tag.word(0))
+                            .ok_or(Error::InvalidBootInfoTag(tag.tag_type))?,
+                        file_size: tag.word(1),
+                        memory_size: tag.word(2),
+                        alignment: tag.word(3),
+                    };
+                }
+                Some(TagType::BootloaderStamp) => {
+                    let words = core::array::from_fn(|index| tag.word(index));
+                    boot_info.bootloader_stamp =
+                        BuildStamp::from_words(&words).unwrap_or(BuildStamp::NONE);
+                }
+                Some(TagType::Trace) => boot_info.trace_buffer_address = tag.address(0)?,
+                // Tags of newer bootloaders are skipped
+                Some(TagType::End) | None => {}
+            }
+        }
+        Ok(boot_info)
+    }
+
+    /// This function decodes the boot information at the specified address. The length is read
+    /// from the header.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the address points to mapped memory with at least the size of
+    /// the header
+    pub unsafe fn from_address(address: VirtAddr) -> Result<Self, Error> {
+        let header = core::slice::from_raw_parts(address.as_ptr::<u8>(), HEADER_SIZE);
+        let length = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        Self::decode(core::slice::from_raw_parts(address.as_ptr(), length.max(HEADER_SIZE)))
+    }
+
+    /// This function returns the initrd, if it was loaded by the bootloader
+    pub fn initrd(&self) -> Option<Initrd<'static>> {
+        if self.initrd_address.is_null() || self.initrd_size == 0 {
+            return None;
+        }
+
+        Some(Initrd::new(unsafe {
+            core::slice::from_raw_parts(
+                self.initrd_address.to_virt().as_ptr(),
+                self.initrd_size as usize,
+            )
+        }))
+    }
+
+    /// This function returns the command line, which was passed as load options to the bootloader
+    pub fn command_line(&self) -> CommandLine<'static> {
+        if self.command_line_address.is_null() {
+            return CommandLine::new("");
+        }
+
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                self.command_line_address.to_virt().as_ptr(),
+                self.command_line_size as usize,
+            )
+        };
+        CommandLine::new(core::str::from_utf8(data).unwrap_or_default())
+    }
+
+    /// This function returns the boot units, which were loaded besides the kernel
+    pub fn boot_units(&self) -> &'static [BootUnitInfo] {
+        if self.boot_units_address.is_null() || self.boot_unit_count == 0 {
+            return &[];
+        }
+
+        unsafe {
+            core::slice::from_raw_parts(
+                self.boot_units_address.to_virt().as_ptr(),
+                self.boot_unit_count as usize,
+            )
+        }
+    }
+
+    /// This function returns the final memory map of the firmware, if it was handed over by the
+    /// bootloader
+    pub fn memory_map(&self) -> Option<MemoryMap<'static>> {
+        if self.memory_map_address.is_null() {
+            return None;
+        }
+
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(
+                self.memory_map_address.to_virt().as_mut_ptr(),
+                self.memory_map_size as usize,
+            )
+        };
+        MemoryMap::new(data, self.memory_descriptor_size as usize).ok()
+    }
+
+    /// This function returns the persistent log, which was set up by the bootloader. The log isn't
+    /// reset, so the kernel continues after the log output of the bootloader.
+    pub fn persistent_log(&self) -> Option<PersistentLog> {
+        if self.persistent_log_address.is_null() {
+            return None;
+        }
+
+        unsafe {
+            PersistentLog::new(
+                self.persistent_log_address.to_virt(),
+                self.persistent_log_size as usize,
+            )
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! The build stamp identifies the build of an artifact by its version, the git commit, the build
+//! time and the profile. The stamp is embedded into the `.build_stamp` section with the
+//! [build_stamp](crate::build_stamp!) macro, so the bootloader can read the stamp of the kernel from
+//! the ELF file. The commit and the build time are injected by the image tool at build time with the
+//! environment variables `OVERFLOW_BUILD_COMMIT` and `OVERFLOW_BUILD_TIME`.
+
+use crate::elf::ElfFile;
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+};
+
+/// The name of the section, which contains the build stamp of an artifact
+pub const BUILD_STAMP_SECTION: &str = ".build_stamp";
+
+const BUILD_STAMP_MAGIC: [u8; 8] = *b"OVFSTAMP";
+
+/// The size of the encoded build stamp in 64-bit words, like it's passed in the boot information
+pub const BUILD_STAMP_WORDS: usize = core::mem::size_of::<BuildStamp>() / 8;
+
+/// The build stamp with the fields as null-padded strings. Longer values are truncated.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BuildStamp {
+    magic: [u8; 8],
+    version: [u8; 24],
+    commit: [u8; 40],
+    timestamp: [u8; 24],
+    profile: [u8; 8],
+}
+
+impl BuildStamp {
+    pub const NONE: Self = Self {
+        magic: [0; 8],
+        version: [0; 24],
+        commit: [0; 40],
+        timestamp: [0; 24],
+        profile: [0; 8],
+    };
+
+    pub const fn new(version: &str, commit: &str, timestamp: &str, profile: &str) -> Self {
+        Self {
+            magic: BUILD_STAMP_MAGIC,
+            version: pad(version),
+            commit: pad(commit),
+            timestamp: pad(timestamp),
+            profile: pad(profile),
+        }
+    }
+
+    /// This function decodes the build stamp from the start of the specified bytes. If the bytes
+    /// are too short or the magic is invalid, this function returns [None].
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..core::mem::size_of::<Self>())?;
+        let stamp = unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Self) };
+        stamp.is_present().then_some(stamp)
+    }
+
+    /// This function reads the build stamp from the `.build_stamp` section of the ELF file
+    pub fn from_elf(elf: &ElfFile) -> Option<Self> {
+        let section = elf.find_section(BUILD_STAMP_SECTION).ok()??;
+        Self::parse(elf.section_data(&section).ok()?)
+    }
+
+    /// This function decodes the build stamp from the words of the boot information
+    pub fn from_words(words: &[u64; BUILD_STAMP_WORDS]) -> Option<Self> {
+        let mut bytes = [0; BUILD_STAMP_WORDS * 8];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Self::parse(&bytes)
+    }
+
+    /// This function encodes the build stamp into the words of the boot information
+    pub fn to_words(&self) -> [u64; BUILD_STAMP_WORDS] {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(self as *const Self as *const u8, BUILD_STAMP_WORDS * 8)
+        };
+        let mut words = [0; BUILD_STAMP_WORDS];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        words
+    }
+
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.magic == BUILD_STAMP_MAGIC
+    }
+
+    pub fn version(&self) -> &str {
+        unpad(&self.version)
+    }
+
+    pub fn commit(&self) -> &str {
+        unpad(&self.commit)
+    }
+
+    pub fn timestamp(&self) -> &str {
+        unpad(&self.timestamp)
+    }
+
+    pub fn profile(&self) -> &str {
+        unpad(&self.profile)
+    }
+}
+
+impl Display for BuildStamp {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        // The short commit hash is enough to identify the build on a screenshot
+        let commit = self.commit();
+        write!(
+            formatter,
+            "v{} (Commit: {}, Built: {}, Profile: {})",
+            self.version(),
+            commit.get(..12).unwrap_or(commit),
+            self.timestamp(),
+            self.profile()
+        )
+    }
+}
+
+/// This macro embeds the build stamp of the calling crate into the `.build_stamp` section and
+/// defines the static `BUILD_STAMP` with it
+#[macro_export]
+macro_rules! build_stamp {
+    () => {
+        #[used]
+        #[link_section = ".build_stamp"]
+        pub static BUILD_STAMP: $crate::build_stamp::BuildStamp =
+            $crate::build_stamp::BuildStamp::new(
+                env!("CARGO_PKG_VERSION"),
+                match option_env!("OVERFLOW_BUILD_COMMIT") {
+                    Some(commit) => commit,
+                    None => "unknown",
+                },
+                match option_env!("OVERFLOW_BUILD_TIME") {
+                    Some(timestamp) => timestamp,
+                    None => "unknown",
+                },
+                if cfg!(debug_assertions) {
+                    "debug"
+                } else {
+                    "release"
+                },
+            );
+    };
+}
+
+/// This function copies the string into a null-padded field
+const fn pad<const N: usize>(value: &str) -> [u8; N] {
+    let bytes = value.as_bytes();
+    let mut field = [0; N];
+    let mut index = 0;
+    while index < N && index < bytes.len() {
+        field[index] = bytes[index];
+        index += 1;
+    }
+    field
+}
+
+/// This function returns the string of a null-padded field. Invalid UTF-8 is cut off.
+fn unpad(field: &[u8]) -> &str {
+    let length = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    match core::str::from_utf8(&field[..length]) {
+        Ok(value) => value,
+        Err(error) => core::str::from_utf8(&field[..error.valid_up_to()]).unwrap_or_default(),
+    }
+}
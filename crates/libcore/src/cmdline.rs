@@ -0,0 +1,69 @@
+/// The command line is a whitespace-separated list of options. Every option is either a flag (like
+/// `nokaslr`) or a key-value pair (like `netboot=tftp://10.0.0.1/boot`). This format is shared by
+/// the load options of the bootloader and the kernel command line.
+#[derive(Clone, Copy)]
+pub struct CommandLine<'a> {
+    line: &'a str,
+}
+
+impl<'a> CommandLine<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self { line }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        self.line
+    }
+
+    /// This function returns an iterator over all options of the command line. Flags are returned
+    /// without value.
+    pub fn options(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+        self.line.split_whitespace().map(|option| {
+            match option.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (option, None),
+            }
+        })
+    }
+
+    /// This function returns the value of the last option with the specified key
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.options()
+            .filter(|(option_key, _)| *option_key == key)
+            .filter_map(|(_, value)| value)
+            .last()
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.options()
+            .any(|(option_key, value)| option_key == flag && value.is_none())
+    }
+}
+
+/// This function parses a region in the format `<size>[@<address>]`. The size accepts the suffixes
+/// `K`, `M` and `G`, the address is hexadecimal with `0x` prefix or decimal.
+pub fn parse_region(text: &str) -> Option<(u64, Option<u64>)> {
+    let (size, address) = match text.split_once('@') {
+        Some((size, address)) => (size, Some(address)),
+        None => (text, None),
+    };
+
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 1 << 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 1 << 20),
+        b'G' | b'g' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    let size = digits.parse::<u64>().ok()?.checked_mul(multiplier)?;
+    let address = match address {
+        Some(address) => {
+            Some(match address.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+                None => address.parse().ok()?,
+            })
+        }
+        None => None,
+    };
+    Some((size, address))
+}
@@ -0,0 +1,510 @@
+use crate::{
+    boot_info::{
+        LoadProtocol,
+        Orientation,
+    },
+    error::Error,
+    keymap::{
+        keymap_by_name,
+        Keymap,
+    },
+};
+use core::str::Lines;
+
+/// The value of the default option, which selects the entry by title or by index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultEntry<'a> {
+    Title(&'a str),
+    Index(usize),
+}
+
+/// The value of the display option, which selects the display of the boot menu and the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayPolicy {
+    /// The first display in the order of the firmware
+    First,
+    /// The display with the most pixels
+    Largest,
+    /// The display with the specified index
+    Index(usize),
+    /// The first display, which is copied to all displays with the same mode
+    Mirror,
+}
+
+/// The filter of the display modes, which limits the modes selected by the bootloader. Firmware
+/// offers modes (like 4K modes), which make the console slow, so the maximum resolution and the
+/// preferred aspect ratio can be configured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModeFilter {
+    pub max_resolution: Option<(usize, usize)>,
+    /// The preferred aspect ratio as width and height (like 16:9)
+    pub aspect_ratio: Option<(usize, usize)>,
+}
+
+impl ModeFilter {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.max_resolution.is_none() && self.aspect_ratio.is_none()
+    }
+
+    /// This function returns, whether the resolution doesn't exceed the maximum resolution
+    pub fn allows(&self, (width, height): (usize, usize)) -> bool {
+        self.max_resolution
+            .map_or(true, |(max_width, max_height)| width <= max_width && height <= max_height)
+    }
+
+    /// This function returns, whether the resolution has the preferred aspect ratio. Without
+    /// preferred aspect ratio, every resolution is preferred.
+    pub fn prefers(&self, (width, height): (usize, usize)) -> bool {
+        self.aspect_ratio
+            .map_or(true, |(aspect_width, aspect_height)| {
+                // The ratio is read from the config, so the products are calculated with 128 bits
+                width as u128 * aspect_height as u128 == height as u128 * aspect_width as u128
+            })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value<'a> {
+    String(&'a str),
+    Integer(u64),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BootEntry<'a> {
+    pub title: &'a str,
+    pub kernel: &'a str,
+    pub initrd: Option<&'a str>,
+    pub cmdline: &'a str,
+    pub resolution: Option<(usize, usize)>,
+    pub netboot: Option<&'a str>,
+    pub protocol: LoadProtocol,
+    /// The comma-separated titles of the entries, which are loaded as secondary boot units
+    pub units: Option<&'a str>,
+    /// The title of the entry, which is loaded as crash kernel
+    pub crashkernel: Option<&'a str>,
+    /// The physical address, at which a flat binary is loaded
+    pub load_address: Option<u64>,
+    /// The offset of the entry point of a flat binary from the load address
+    pub entry_offset: u64,
+}
+
+impl<'a> BootEntry<'a> {
+    /// This function returns the titles of the secondary boot units
+    pub fn unit_titles(&self) -> impl Iterator<Item = &'a str> {
+        self.units
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+    }
+}
+
+enum Item<'a> {
+    Global(&'a str, Value<'a>, (usize, usize)),
+    Entry(BootEntry<'a>),
+}
+
+/// The boot configuration (`overflow.cfg`) uses a minimal TOML-like format. Global options are
+/// written before the first entry, every entry starts with an `[entry]` header:
+///
+/// ```text
+/// # Boot the first entry after 5 seconds
+/// timeout = 5
+/// default = "OverflowOS"
+/// keymap = "de"
+/// rotation = 90
+/// display = "largest"
+///
+/// [entry]
+/// title = "OverflowOS"
+/// kernel = "\EFI\BOOT\KERNEL.ELF"
+/// initrd = "\EFI\BOOT\INITRD.TAR"
+/// cmdline = "dump-descriptors"
+/// resolution = "1280x720"
+/// netboot = "http://10.0.0.1/boot"
+/// protocol = "elf"
+/// load_address = "0x100000"
+/// entry_offset = 0
+/// units = "OverflowOS (Test)"
+/// crashkernel = "Crash Kernel"
+/// ```
+///
+/// Strings are enclosed in double quotes and taken literally, so UEFI paths don't need escaping.
+/// Integers are written in decimal, addresses can also be written as hexadecimal string with `0x`
+/// prefix. Comments start with `#` and end at the end of the line. `format` is an alias of
+/// `protocol`, a flat binary (`format = "bin"`) needs a page-aligned `load_address`.
+///
+/// The boot configuration is validated completely when it's parsed. The entries are decoded from the
+/// text again when they are requested, so no allocation is needed.
+#[derive(Clone, Copy)]
+pub struct BootConfig<'a> {
+    text: &'a str,
+    pub timeout: Option<u64>,
+    pub default: Option<DefaultEntry<'a>>,
+    pub keymap: Option<&'static Keymap>,
+    /// The clockwise rotation of the screen in degrees (0, 90, 180 or 270)
+    pub orientation: Option<Orientation>,
+    pub display: Option<DisplayPolicy>,
+    pub mode_filter: ModeFilter,
+}
+
+impl<'a> BootConfig<'a> {
+    /// This function parses the raw configuration, which was read from the disk. If the data isn't
+    /// valid UTF-8, an [Error::InvalidConfig] error with the position of the invalid sequence is
+    /// returned.
+    pub fn parse_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let text = core::str::from_utf8(data).map_err(|error| {
+            let valid_text = core::str::from_utf8(&data[..error.valid_up_to()]).unwrap_or_default();
+            let line = valid_text.matches('\n').count() + 1;
+            let column = valid_text.len() - valid_text.rfind('\n').map_or(0, |index| index + 1) + 1;
+            Error::InvalidConfig(line, column, "Invalid UTF-8 sequence")
+        })?;
+        Self::parse(text)
+    }
+
+    /// This function parses the specified configuration. If the configuration is malformed, an
+    /// [Error::InvalidConfig] error with the line and column of the problem is returned.
+    pub fn parse(text: &'a str) -> Result<Self, Error> {
+        let mut config = Self {
+            text,
+            timeout: None,
+            default: None,
+            keymap: None,
+            orientation: None,
+            display: None,
+            mode_filter: ModeFilter::default(),
+        };
+
+        let mut entry_count = 0;
+        let mut default_position = (0, 0);
+        for item in ConfigItems::new(text) {
+            match item? {
+                Item::Entry(_) => entry_count += 1,
+                Item::Global("timeout", Value::Integer(timeout), _) => config.timeout = Some(timeout),
+                Item::Global("default", value, position) => {
+                    default_position = position;
+                    config.default = Some(match value {
+                        Value::String(title) => DefaultEntry::Title(title),
+                        Value::Integer(index) => DefaultEntry::Index(index as usize),
+                    });
+                }
+                Item::Global("keymap", Value::String(name), (line, column)) => {
+                    let keymap = keymap_by_name(name).ok_or(Error::InvalidConfig(
+                        line,
+                        column,
+                        "Unknown keymap",
+                    ))?;
+                    config.keymap = Some(keymap);
+                }
+                Item::Global("keymap", _, (line, column)) => {
+                    return Err(Error::InvalidConfig(line, column, "Expected a string"));
+                }
+                Item::Global("rotation", Value::Integer(degrees), (line, column)) => {
+                    let orientation = Orientation::from_degrees(degrees)
+                        .ok_or(Error::InvalidConfig(line, column, "Expected 0, 90, 180 or 270"))?;
+                    config.orientation = Some(orientation);
+                }
+                Item::Global("display", Value::Integer(index), _) => {
+                    config.display = Some(DisplayPolicy::Index(index as usize));
+                }
+                Item::Global("display", Value::String(name), (line, column)) => {
+                    config.display = Some(match name {
+                        "first" => DisplayPolicy::First,
+                        "largest" => DisplayPolicy::Largest,
+                        "mirror" => DisplayPolicy::Mirror,
+                        _ => {
+                            return Err(Error::InvalidConfig(
+                                line,
+                                column,
+                                "Expected an index, \"first\", \"largest\" or \"mirror\"",
+                            ))
+                        }
+                    });
+                }
+                Item::Global("max_resolution", Value::String(resolution), (line, column)) => {
+                    let resolution = parse_resolution(resolution).ok_or(Error::InvalidConfig(
+                        line,
+                        column,
+                        "Expected a resolution like \"1920x1080\"",
+                    ))?;
+                    config.mode_filter.max_resolution = Some(resolution);
+                }
+                Item::Global("aspect_ratio", Value::String(ratio), (line, column)) => {
+                    let ratio = parse_aspect_ratio(ratio).ok_or(Error::InvalidConfig(
+                        line,
+                        column,
+                        "Expected an aspect ratio like \"16:9\"",
+                    ))?;
+                    config.mode_filter.aspect_ratio = Some(ratio);
+                }
+                Item::Global("max_resolution" | "aspect_ratio", _, (line, column)) => {
+                    return Err(Error::InvalidConfig(line, column, "Expected a string"));
+                }
+                Item::Global("timeout" | "rotation", _, (line, column)) => {
+                    return Err(Error::InvalidConfig(line, column, "Expected an integer"));
+                }
+                Item::Global(_, _, (line, column)) => {
+                    return Err(Error::InvalidConfig(line, column, "Unknown global option"));
+                }
+            }
+        }
+
+        // Validate that the default option references an existing entry
+        let (line, column) = default_position;
+        match config.default {
+            Some(DefaultEntry::Index(index)) if index >= entry_count => {
+                return Err(Error::InvalidConfig(line, column, "Default entry index out of range"));
+            }
+            Some(DefaultEntry::Title(title))
+                if !config.entries().any(|entry| entry.title == title) =>
+            {
+                return Err(Error::InvalidConfig(line, column, "Default entry title not found"));
+            }
+            _ => {}
+        }
+        Ok(config)
+    }
+
+    /// This function returns the entry with the specified title
+    pub fn entry_by_title(&self, title: &str) -> Option<BootEntry<'a>> {
+        self.entries().find(|entry| entry.title == title)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = BootEntry<'a>> {
+        ConfigItems::new(self.text).filter_map(|item| {
+            match item {
+                Ok(Item::Entry(entry)) => Some(entry),
+                _ => None,
+            }
+        })
+    }
+
+    /// This function returns the index of the default entry. If no default is configured, the
+    /// first entry is the default.
+    pub fn default_index(&self) -> usize {
+        match self.default {
+            Some(DefaultEntry::Index(index)) => index,
+            Some(DefaultEntry::Title(title)) => {
+                self.entries()
+                    .position(|entry| entry.title == title)
+                    .unwrap_or_default()
+            }
+            None => 0,
+        }
+    }
+}
+
+/// The items iterator walks the configuration line by line and returns the global options and the
+/// entries, when the entry is complete.
+struct ConfigItems<'a> {
+    lines: core::iter::Enumerate<Lines<'a>>,
+    current: Option<(BootEntry<'a>, usize)>,
+}
+
+impl<'a> ConfigItems<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().enumerate(),
+            current: None,
+        }
+    }
+
+    /// This function validates, that the mandatory options of the entry are set
+    fn finish_entry(entry: BootEntry<'a>, line: usize) -> Result<Item<'a>, Error> {
+        if entry.title.is_empty() {
+            return Err(Error::InvalidConfig(line, 1, "Entry has no title"));
+        }
+        if entry.kernel.is_empty() {
+            return Err(Error::InvalidConfig(line, 1, "Entry has no kernel"));
+        }
+        if entry.protocol == LoadProtocol::Binary && entry.load_address.is_none() {
+            return Err(Error::InvalidConfig(line, 1, "Flat binary entry has no load address"));
+        }
+        Ok(Item::Entry(entry))
+    }
+}
+
+impl<'a> Iterator for ConfigItems<'a> {
+    type Item = Result<Item<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, line) in self.lines.by_ref() {
+            let line_number = index + 1;
+            let content = strip_comment(line).trim_end();
+            let trimmed = content.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let column = content.len() - trimmed.len() + 1;
+
+            // Start a new entry and return the previous entry, if any
+            if let Some(section) = trimmed.strip_prefix('[') {
+                let result = match section.strip_suffix(']') {
+                    Some("entry") => {
+                        let previous = self.current.replace((BootEntry::default(), line_number));
+                        match previous {
+                            Some((entry, line)) => Self::finish_entry(entry, line),
+                            None => continue,
+                        }
+                    }
+                    Some(_) => Err(Error::InvalidConfig(line_number, column + 1, "Unknown section")),
+                    None => {
+                        Err(Error::InvalidConfig(line_number, column + trimmed.len(), "Expected ']'"))
+                    }
+                };
+                return Some(result);
+            }
+
+            let (key, value, value_column) = match parse_pair(trimmed, line_number, column) {
+                Ok(pair) => pair,
+                Err(error) => return Some(Err(error)),
+            };
+            let Some((entry, _)) = self.current.as_mut() else {
+                return Some(Ok(Item::Global(key, value, (line_number, value_column))));
+            };
+
+            let error = |message| Some(Err(Error::InvalidConfig(line_number, value_column, message)));
+            match (key, value) {
+                ("title", Value::String(title)) if !title.is_empty() => entry.title = title,
+                ("kernel", Value::String(kernel)) if !kernel.is_empty() => entry.kernel = kernel,
+                ("initrd", Value::String(initrd)) => entry.initrd = Some(initrd),
+                ("cmdline", Value::String(cmdline)) => entry.cmdline = cmdline,
+                ("netboot", Value::String(url)) if !url.is_empty() => entry.netboot = Some(url),
+                ("units", Value::String(units)) => entry.units = Some(units),
+                ("crashkernel", Value::String(title)) if !title.is_empty() => {
+                    entry.crashkernel = Some(title)
+                }
+                ("protocol" | "format", Value::String(name)) => {
+                    match LoadProtocol::from_name(name) {
+                        Some(protocol) => entry.protocol = protocol,
+                        None => return error("Unknown load protocol"),
+                    }
+                }
+                ("load_address", value) => {
+                    match parse_address(value) {
+                        Some(address) if address % 4096 == 0 => entry.load_address = Some(address),
+                        Some(_) => return error("Expected a page-aligned address"),
+                        None => return error("Expected an address like \"0x100000\""),
+                    }
+                }
+                ("entry_offset", value) => {
+                    match parse_address(value) {
+                        Some(offset) => entry.entry_offset = offset,
+                        None => return error("Expected an offset like \"0x1000\""),
+                    }
+                }
+                ("resolution", Value::String(resolution)) => {
+                    match parse_resolution(resolution) {
+                        Some(resolution) => entry.resolution = Some(resolution),
+                        None => return error("Expected a resolution like \"1280x720\""),
+                    }
+                }
+                ("title" | "kernel" | "netboot" | "crashkernel", _) => {
+                    return error("Expected a non-empty string")
+                }
+                ("initrd" | "cmdline" | "units" | "protocol" | "format", _) => {
+                    return error("Expected a string")
+                }
+                _ => {
+                    return Some(Err(Error::InvalidConfig(
+                        line_number,
+                        column,
+                        "Unknown entry option",
+                    )));
+                }
+            }
+        }
+
+        let (entry, line) = self.current.take()?;
+        Some(Self::finish_entry(entry, line))
+    }
+}
+
+/// This function parses a `key = value` pair and returns the key, the value and the column of the
+/// value.
+fn parse_pair(text: &str, line: usize, column: usize) -> Result<(&str, Value<'_>, usize), Error> {
+    let Some((key, value)) = text.split_once('=') else {
+        return Err(Error::InvalidConfig(line, column, "Expected 'key = value'"));
+    };
+
+    let key = key.trim_end();
+    if key.is_empty()
+        || !key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+    {
+        return Err(Error::InvalidConfig(line, column, "Invalid option name"));
+    }
+
+    let trimmed = value.trim_start();
+    let value_column = column + (text.len() - value.len()) + (value.len() - trimmed.len());
+    if trimmed.is_empty() {
+        return Err(Error::InvalidConfig(line, value_column, "Expected a value"));
+    }
+
+    let value = if let Some(string) = trimmed.strip_prefix('"') {
+        match string.find('"') {
+            Some(end) if end + 1 == string.len() => Value::String(&string[..end]),
+            Some(end) => {
+                return Err(Error::InvalidConfig(
+                    line,
+                    value_column + end + 2,
+                    "Unexpected characters after string",
+                ));
+            }
+            None => {
+                return Err(Error::InvalidConfig(
+                    line,
+                    value_column + trimmed.len(),
+                    "Unterminated string",
+                ));
+            }
+        }
+    } else {
+        match trimmed.parse::<u64>() {
+            Ok(integer) => Value::Integer(integer),
+            Err(_) => {
+                return Err(Error::InvalidConfig(line, value_column, "Expected a string or integer"));
+            }
+        }
+    };
+    Ok((key, value, value_column))
+}
+
+/// This function parses an address, which is written as decimal integer or as hexadecimal string with
+/// `0x` prefix
+fn parse_address(value: Value) -> Option<u64> {
+    match value {
+        Value::Integer(address) => Some(address),
+        Value::String(text) => u64::from_str_radix(text.strip_prefix("0x")?, 16).ok(),
+    }
+}
+
+/// This function parses a resolution in the format `<width>x<height>`
+pub fn parse_resolution(text: &str) -> Option<(usize, usize)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// This function parses an aspect ratio in the format `<width>:<height>`. Both parts must be
+/// non-zero.
+pub fn parse_aspect_ratio(text: &str) -> Option<(usize, usize)> {
+    let (width, height) = text.split_once(':')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    (width != 0 && height != 0).then_some((width, height))
+}
+
+/// This function removes the comment from the specified line. A `#` in a string doesn't start a
+/// comment.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
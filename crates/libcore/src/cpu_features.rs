@@ -0,0 +1,109 @@
+use core::{
+    arch::x86_64::{
+        CpuidResult,
+        __cpuid,
+        __cpuid_count,
+    },
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuidRegister {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    Required,
+    Optional,
+}
+
+impl Display for Requirement {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Required => formatter.write_str("Required"),
+            Self::Optional => formatter.write_str("Optional"),
+        }
+    }
+}
+
+/// A CPU feature is identified by a bit in the result of a CPUID leaf
+#[derive(Clone, Copy, Debug)]
+pub struct CpuFeature {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub leaf: u32,
+    pub subleaf: u32,
+    pub register: CpuidRegister,
+    pub bit: u32,
+    pub requirement: Requirement,
+}
+
+impl CpuFeature {
+    pub const fn new(
+        name: &'static str, description: &'static str, leaf: u32, register: CpuidRegister, bit: u32,
+        requirement: Requirement,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            leaf,
+            subleaf: 0,
+            register,
+            bit,
+            requirement,
+        }
+    }
+
+    /// This function checks the feature bit. Features of leaves, which aren't supported by the CPU,
+    /// are reported as not supported.
+    pub fn is_supported(&self) -> bool {
+        let max_leaf = unsafe { __cpuid(self.leaf & 0x8000_0000) }.eax;
+        if self.leaf > max_leaf {
+            return false;
+        }
+
+        let CpuidResult { eax, ebx, ecx, edx } = unsafe { __cpuid_count(self.leaf, self.subleaf) };
+        let value = match self.register {
+            CpuidRegister::Eax => eax,
+            CpuidRegister::Ebx => ebx,
+            CpuidRegister::Ecx => ecx,
+            CpuidRegister::Edx => edx,
+        };
+        value & (1 << self.bit) != 0
+    }
+}
+
+/// The CPU features, which are needed by OverflowOS. Missing required features abort the boot
+/// process, missing optional features are only reported.
+#[rustfmt::skip]
+pub static CPU_FEATURES: &[CpuFeature] = &[
+    CpuFeature::new("TSC", "Time Stamp Counter", 0x01, CpuidRegister::Edx, 4, Requirement::Required),
+    CpuFeature::new("MSR", "Model Specific Registers", 0x01, CpuidRegister::Edx, 5, Requirement::Required),
+    CpuFeature::new("PAE", "Physical Address Extension", 0x01, CpuidRegister::Edx, 6, Requirement::Required),
+    CpuFeature::new("APIC", "Local APIC", 0x01, CpuidRegister::Edx, 9, Requirement::Required),
+    CpuFeature::new("PGE", "Global Pages", 0x01, CpuidRegister::Edx, 13, Requirement::Required),
+    CpuFeature::new("PAT", "Page Attribute Table", 0x01, CpuidRegister::Edx, 16, Requirement::Required),
+    CpuFeature::new("FXSR", "FXSAVE and FXRSTOR", 0x01, CpuidRegister::Edx, 24, Requirement::Required),
+    CpuFeature::new("SSE2", "Streaming SIMD Extensions 2", 0x01, CpuidRegister::Edx, 26, Requirement::Required),
+    CpuFeature::new("CX16", "CMPXCHG16B Instruction", 0x01, CpuidRegister::Ecx, 13, Requirement::Required),
+    CpuFeature::new("XSAVE", "Extended State Management", 0x01, CpuidRegister::Ecx, 26, Requirement::Optional),
+    CpuFeature::new("RDRAND", "Hardware Random Numbers", 0x01, CpuidRegister::Ecx, 30, Requirement::Optional),
+    CpuFeature::new("NX", "No-Execute Pages", 0x8000_0001, CpuidRegister::Edx, 20, Requirement::Required),
+    CpuFeature::new("PDPE1GB", "1 GiB Pages", 0x8000_0001, CpuidRegister::Edx, 26, Requirement::Optional),
+    CpuFeature::new("LM", "Long Mode", 0x8000_0001, CpuidRegister::Edx, 29, Requirement::Required),
+];
+
+/// This function returns all required CPU features, which aren't supported by the current CPU
+pub fn missing_features() -> impl Iterator<Item = &'static CpuFeature> {
+    CPU_FEATURES
+        .iter()
+        .filter(|feature| feature.requirement == Requirement::Required && !feature.is_supported())
+}
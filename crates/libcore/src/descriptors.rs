@@ -0,0 +1,347 @@
+use core::{
+    arch::asm,
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+};
+
+pub const GATE_INTERRUPT: u8 = 0xE;
+pub const GATE_TRAP: u8 = 0xF;
+
+/// The content of the GDTR or IDTR register
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DescriptorTablePointer {
+    pub limit: u16,
+    pub base: u64,
+}
+
+impl DescriptorTablePointer {
+    /// This function returns the size of the table in bytes
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.limit as usize + 1
+    }
+}
+
+pub fn read_gdtr() -> DescriptorTablePointer {
+    let mut pointer = DescriptorTablePointer::default();
+    unsafe { asm!("sgdt [{}]", in(reg) &mut pointer, options(nostack)) };
+    pointer
+}
+
+pub fn read_idtr() -> DescriptorTablePointer {
+    let mut pointer = DescriptorTablePointer::default();
+    unsafe { asm!("sidt [{}]", in(reg) &mut pointer, options(nostack)) };
+    pointer
+}
+
+/// # Safety
+/// The caller has to ensure, that the table stays valid as long as it's loaded
+pub unsafe fn load_idt(pointer: &DescriptorTablePointer) {
+    asm!("lidt [{}]", in(reg) pointer, options(readonly, nostack));
+}
+
+/// # Safety
+/// The caller has to ensure, that the table stays valid as long as it's loaded and that the segment
+/// registers are reloaded with selectors of the new table
+pub unsafe fn load_gdt(pointer: &DescriptorTablePointer) {
+    asm!("lgdt [{}]", in(reg) pointer, options(readonly, nostack));
+}
+
+/// This function reloads CS with a far return and the data segment registers (DS, ES and SS) with
+/// the specified selectors. FS and GS are left unchanged, so their base addresses are kept.
+///
+/// # Safety
+/// The caller has to ensure, that the selectors reference a 64-bit code segment and a data segment
+/// of the loaded GDT
+pub unsafe fn load_segments(code_selector: u16, data_selector: u16) {
+    asm!(
+        "push {code}",
+        "lea {temp}, [rip + 2f]",
+        "push {temp}",
+        "retfq",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov ss, {data:x}",
+        code = in(reg) code_selector as u64,
+        data = in(reg) data_selector,
+        temp = lateout(reg) _,
+    );
+}
+
+/// # Safety
+/// The caller has to ensure, that the selector references an available TSS, which stays valid as
+/// long as it's loaded
+pub unsafe fn load_tss(selector: u16) {
+    asm!("ltr {:x}", in(reg) selector, options(nostack));
+}
+
+/// This function returns the selector of the current code segment
+pub fn read_cs() -> u16 {
+    let selector: u16;
+    unsafe { asm!("mov {:x}, cs", out(reg) selector, options(nomem, nostack)) };
+    selector
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentKind {
+    Null,
+    Code64,
+    Code32,
+    Data,
+    Ldt,
+    AvailableTss,
+    BusyTss,
+    System(u8),
+}
+
+impl Display for SegmentKind {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => formatter.write_str("Null"),
+            Self::Code64 => formatter.write_str("Code (64-bit)"),
+            Self::Code32 => formatter.write_str("Code (32-bit)"),
+            Self::Data => formatter.write_str("Data"),
+            Self::Ldt => formatter.write_str("LDT"),
+            Self::AvailableTss => formatter.write_str("TSS (available)"),
+            Self::BusyTss => formatter.write_str("TSS (busy)"),
+            Self::System(kind) => write!(formatter, "System (0x{:X})", kind),
+        }
+    }
+}
+
+/// The issues, which are detected by the validation of descriptors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorIssue {
+    NotPresent,
+    InvalidCodeMode,
+    NonCanonicalAddress,
+    InvalidGateType,
+    NullSelector,
+    SelectorOutOfBounds,
+    SelectorNotCode,
+}
+
+impl Display for DescriptorIssue {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::NotPresent => "Descriptor is not present",
+            Self::InvalidCodeMode => "Code segment has both L and D bit set",
+            Self::NonCanonicalAddress => "Address is not canonical",
+            Self::InvalidGateType => "Gate is neither interrupt nor trap gate",
+            Self::NullSelector => "Gate uses the null selector",
+            Self::SelectorOutOfBounds => "Selector points outside of the GDT",
+            Self::SelectorNotCode => "Selector doesn't point to a code segment",
+        })
+    }
+}
+
+/// A decoded descriptor of the Global Descriptor Table. System descriptors like the TSS occupy two
+/// entries of the table in long mode.
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentDescriptor {
+    pub selector: u16,
+    pub kind: SegmentKind,
+    pub base: u64,
+    pub limit: u32,
+    pub dpl: u8,
+    pub present: bool,
+    pub long_mode: bool,
+    pub default_size: bool,
+    pub granularity: bool,
+}
+
+impl SegmentDescriptor {
+    fn decode(selector: u16, low: u64, high: u64) -> Self {
+        let access = (low >> 40) as u8;
+        let flags = (low >> 52) as u8 & 0xF;
+        let user_segment = access & (1 << 4) != 0;
+        let long_mode = flags & 0b0010 != 0;
+        let kind = match (low, user_segment, access & 0xF) {
+            (0, ..) => SegmentKind::Null,
+            (_, true, kind) if kind & 0b1000 != 0 && long_mode => SegmentKind::Code64,
+            (_, true, kind) if kind & 0b1000 != 0 => SegmentKind::Code32,
+            (_, true, _) => SegmentKind::Data,
+            (_, false, 0x2) => SegmentKind::Ldt,
+            (_, false, 0x9) => SegmentKind::AvailableTss,
+            (_, false, 0xB) => SegmentKind::BusyTss,
+            (_, false, kind) => SegmentKind::System(kind),
+        };
+
+        let mut base = (low >> 16) & 0xFF_FFFF | ((low >> 56) & 0xFF) << 24;
+        if kind.is_system() {
+            base |= (high & 0xFFFF_FFFF) << 32;
+        }
+
+        Self {
+            selector,
+            kind,
+            base,
+            limit: (low & 0xFFFF) as u32 | ((low >> 48) as u32 & 0xF) << 16,
+            dpl: (access >> 5) & 0b11,
+            present: access & (1 << 7) != 0,
+            long_mode,
+            default_size: flags & 0b0100 != 0,
+            granularity: flags & 0b1000 != 0,
+        }
+    }
+
+    /// This function returns the first invariant, which is violated by the descriptor
+    pub fn validate(&self) -> Option<DescriptorIssue> {
+        if self.kind == SegmentKind::Null {
+            return None;
+        }
+
+        if !self.present {
+            return Some(DescriptorIssue::NotPresent);
+        }
+        if self.kind == SegmentKind::Code64 && self.default_size {
+            return Some(DescriptorIssue::InvalidCodeMode);
+        }
+        if self.kind.is_system() && !is_canonical(self.base) {
+            return Some(DescriptorIssue::NonCanonicalAddress);
+        }
+        None
+    }
+}
+
+impl SegmentKind {
+    #[inline]
+    pub fn is_system(&self) -> bool {
+        matches!(self, Self::Ldt | Self::AvailableTss | Self::BusyTss | Self::System(_))
+    }
+}
+
+/// A decoded gate descriptor of the Interrupt Descriptor Table
+#[derive(Clone, Copy, Debug)]
+pub struct GateDescriptor {
+    pub vector: u8,
+    pub handler: u64,
+    pub selector: u16,
+    pub ist: u8,
+    pub kind: u8,
+    pub dpl: u8,
+    pub present: bool,
+}
+
+impl GateDescriptor {
+    fn decode(vector: u8, low: u64, high: u64) -> Self {
+        let attributes = (low >> 40) as u8;
+        Self {
+            vector,
+            handler: (low & 0xFFFF) | ((low >> 48) & 0xFFFF) << 16 | (high & 0xFFFF_FFFF) << 32,
+            selector: (low >> 16) as u16,
+            ist: (low >> 32) as u8 & 0b111,
+            kind: attributes & 0xF,
+            dpl: (attributes >> 5) & 0b11,
+            present: attributes & (1 << 7) != 0,
+        }
+    }
+
+    /// This function encodes the gate into the two quadwords of the IDT entry
+    pub fn encode(&self) -> [u64; 2] {
+        let attributes =
+            self.kind as u64 | (self.dpl as u64 & 0b11) << 5 | (self.present as u64) << 7;
+        let low = (self.handler & 0xFFFF)
+            | (self.selector as u64) << 16
+            | (self.ist as u64 & 0b111) << 32
+            | attributes << 40
+            | ((self.handler >> 16) & 0xFFFF) << 48;
+        [low, self.handler >> 32]
+    }
+
+    #[inline]
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            GATE_INTERRUPT => "Interrupt",
+            GATE_TRAP => "Trap",
+            _ => "Invalid",
+        }
+    }
+
+    /// This function returns the first invariant, which is violated by the gate. The selector of the
+    /// gate is checked against the specified GDT.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the GDT is readable.
+    pub unsafe fn validate(&self, gdt: &DescriptorTablePointer) -> Option<DescriptorIssue> {
+        if !self.present {
+            return None;
+        }
+
+        if self.kind != GATE_INTERRUPT && self.kind != GATE_TRAP {
+            return Some(DescriptorIssue::InvalidGateType);
+        }
+        if !is_canonical(self.handler) {
+            return Some(DescriptorIssue::NonCanonicalAddress);
+        }
+
+        let index = (self.selector >> 3) as usize;
+        if index == 0 {
+            return Some(DescriptorIssue::NullSelector);
+        }
+        if (index + 1) * 8 > gdt.size() {
+            return Some(DescriptorIssue::SelectorOutOfBounds);
+        }
+
+        let entry = read_entry(gdt.base, index);
+        match SegmentDescriptor::decode(self.selector, entry, 0).kind {
+            SegmentKind::Code64 | SegmentKind::Code32 => None,
+            _ => Some(DescriptorIssue::SelectorNotCode),
+        }
+    }
+}
+
+/// This function decodes all descriptors of the specified GDT
+///
+/// # Safety
+/// The caller has to ensure, that the table is mapped and readable.
+pub unsafe fn gdt_entries(
+    gdt: &DescriptorTablePointer,
+) -> impl Iterator<Item = SegmentDescriptor> + '_ {
+    let entry_count = gdt.size() / 8;
+    let mut index = 0;
+    core::iter::from_fn(move || {
+        if index >= entry_count {
+            return None;
+        }
+
+        let low = read_entry(gdt.base, index);
+        let high = if index + 1 < entry_count {
+            read_entry(gdt.base, index + 1)
+        } else {
+            0
+        };
+        let descriptor = SegmentDescriptor::decode((index * 8) as u16, low, high);
+        index += if descriptor.kind.is_system() { 2 } else { 1 };
+        Some(descriptor)
+    })
+}
+
+/// This function decodes all gates of the specified IDT
+///
+/// # Safety
+/// The caller has to ensure, that the table is mapped and readable.
+pub unsafe fn idt_entries(idt: &DescriptorTablePointer) -> impl Iterator<Item = GateDescriptor> + '_ {
+    (0..(idt.size() / 16).min(256)).map(move |vector| {
+        let low = read_entry(idt.base, vector * 2);
+        let high = read_entry(idt.base, vector * 2 + 1);
+        GateDescriptor::decode(vector as u8, low, high)
+    })
+}
+
+/// This function checks, if the upper 17 bits of the address are equal
+#[inline]
+pub fn is_canonical(address: u64) -> bool {
+    let upper_bits = address >> 47;
+    upper_bits == 0 || upper_bits == 0x1FFFF
+}
+
+#[inline]
+unsafe fn read_entry(base: u64, index: usize) -> u64 {
+    ((base as *const u64).add(index)).read_unaligned()
+}
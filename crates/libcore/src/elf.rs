@@ -0,0 +1,446 @@
+use crate::error::Error;
+use core::mem::size_of;
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+pub const ELF_CLASS_64: u8 = 2;
+pub const ELF_DATA_LSB: u8 = 1;
+pub const ELF_MACHINE_X86_64: u16 = 0x3E;
+
+pub const ET_REL: u16 = 1;
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_PHDR: u32 = 6;
+pub const PT_TLS: u32 = 7;
+
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+pub const DT_NULL: i64 = 0;
+pub const DT_RELA: i64 = 7;
+pub const DT_RELASZ: i64 = 8;
+pub const DT_RELAENT: i64 = 9;
+
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_ABS: u16 = 0xFFF1;
+pub const SHN_COMMON: u16 = 0xFFF2;
+
+pub const R_X86_64_NONE: u32 = 0;
+pub const R_X86_64_64: u32 = 1;
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_PLT32: u32 = 4;
+pub const R_X86_64_RELATIVE: u32 = 8;
+pub const R_X86_64_32: u32 = 10;
+pub const R_X86_64_32S: u32 = 11;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ElfHeader {
+    pub identifier: [u8; 16],
+    pub kind: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub program_header_offset: u64,
+    pub section_header_offset: u64,
+    pub flags: u32,
+    pub header_size: u16,
+    pub program_header_entry_size: u16,
+    pub program_header_count: u16,
+    pub section_header_entry_size: u16,
+    pub section_header_count: u16,
+    pub section_names_index: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub virtual_address: u64,
+    pub physical_address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub align: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub kind: u32,
+    pub flags: u64,
+    pub address: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub address_align: u64,
+    pub entry_size: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Symbol {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub section_index: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Rela {
+    pub offset: u64,
+    pub info: u64,
+    pub addend: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicEntry {
+    pub tag: i64,
+    pub value: u64,
+}
+
+impl Rela {
+    #[inline]
+    pub fn symbol_index(&self) -> usize {
+        (self.info >> 32) as usize
+    }
+
+    #[inline]
+    pub fn kind(&self) -> u32 {
+        (self.info & 0xFFFF_FFFF) as u32
+    }
+}
+
+/// This structure is a read-only view over an ELF64 file in memory. All accessors are bounds-checked
+/// against the underlying data and all offsets are calculated with checked arithmetic, so malformed
+/// files result in an error instead of an invalid read or a panic.
+pub struct ElfFile<'a> {
+    pub data: &'a [u8],
+    pub header: ElfHeader,
+}
+
+impl<'a> ElfFile<'a> {
+    /// This function validates the ELF identifier and creates a view over the specified data. Only
+    /// 64-bit little-endian x86_64 files are accepted.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let header = read::<ElfHeader>(data, 0)?;
+        if header.identifier[0..4] != ELF_MAGIC {
+            return Err(Error::InvalidElfMagic);
+        }
+
+        if header.identifier[4] != ELF_CLASS_64 || header.machine != ELF_MACHINE_X86_64 {
+            return Err(Error::UnsupportedElf(header.identifier[4], header.machine));
+        }
+
+        if header.identifier[5] != ELF_DATA_LSB {
+            return Err(Error::InvalidElfHeader("big-endian files are not supported"));
+        }
+
+        // The header tables must be located completely in the file, so every entry can be read
+        if header.program_header_count != 0 {
+            if (header.program_header_entry_size as usize) < size_of::<ProgramHeader>() {
+                return Err(Error::InvalidElfHeader("program header entry size is too small"));
+            }
+            check_table(
+                data,
+                header.program_header_offset,
+                header.program_header_count as usize,
+                header.program_header_entry_size as usize,
+            )?;
+        }
+
+        if header.section_header_count != 0 {
+            if (header.section_header_entry_size as usize) < size_of::<SectionHeader>() {
+                return Err(Error::InvalidElfHeader("section header entry size is too small"));
+            }
+            if header.section_names_index >= header.section_header_count {
+                return Err(Error::InvalidElfHeader("section name table index is out of bounds"));
+            }
+            check_table(
+                data,
+                header.section_header_offset,
+                header.section_header_count as usize,
+                header.section_header_entry_size as usize,
+            )?;
+        }
+
+        Ok(Self { data, header })
+    }
+
+    /// This function reads the program header with the specified index and validates its ranges.
+    /// The file data of the segment must be located in the file and the memory of a loadable
+    /// segment must contain its file data without overflowing the address space.
+    pub fn program_header(&self, index: usize) -> Result<ProgramHeader, Error> {
+        if index >= self.header.program_header_count as usize {
+            return Err(Error::InvalidElfSegment(index, "index is out of bounds"));
+        }
+
+        let segment = read::<ProgramHeader>(
+            self.data,
+            entry_offset(
+                self.header.program_header_offset,
+                index,
+                self.header.program_header_entry_size as usize,
+            )?,
+        )?;
+        let file_end = segment.offset.checked_add(segment.file_size);
+        if file_end.map_or(true, |end| end > self.data.len() as u64) {
+            return Err(Error::InvalidElfSegment(index, "file data is out of bounds"));
+        }
+
+        if segment.kind == PT_LOAD || segment.kind == PT_TLS {
+            if segment.file_size > segment.memory_size {
+                return Err(Error::InvalidElfSegment(index, "file size exceeds memory size"));
+            }
+            if segment
+                .virtual_address
+                .checked_add(segment.memory_size)
+                .is_none()
+            {
+                return Err(Error::InvalidElfSegment(index, "memory overflows address space"));
+            }
+        }
+        Ok(segment)
+    }
+
+    pub fn program_headers(&self) -> impl Iterator<Item = Result<ProgramHeader, Error>> + '_ {
+        (0..self.header.program_header_count as usize).map(|index| self.program_header(index))
+    }
+
+    pub fn section_header(&self, index: usize) -> Result<SectionHeader, Error> {
+        if index >= self.header.section_header_count as usize {
+            return Err(Error::ElfOutOfBounds(index));
+        }
+
+        read::<SectionHeader>(
+            self.data,
+            entry_offset(
+                self.header.section_header_offset,
+                index,
+                self.header.section_header_entry_size as usize,
+            )?,
+        )
+    }
+
+    pub fn section_headers(&self) -> impl Iterator<Item = Result<SectionHeader, Error>> + '_ {
+        (0..self.header.section_header_count as usize).map(|index| self.section_header(index))
+    }
+
+    /// This function returns the file content of the specified section. Sections without file
+    /// content (like `.bss`) return an empty slice.
+    pub fn section_data(&self, section: &SectionHeader) -> Result<&'a [u8], Error> {
+        if section.kind == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        slice(self.data, to_usize(section.offset)?, to_usize(section.size)?)
+    }
+
+    pub fn segment_data(&self, segment: &ProgramHeader) -> Result<&'a [u8], Error> {
+        slice(self.data, to_usize(segment.offset)?, to_usize(segment.file_size)?)
+    }
+
+    pub fn section_name(&self, section: &SectionHeader) -> Result<&'a str, Error> {
+        let names = self.section_header(self.header.section_names_index as usize)?;
+        self.string(&names, section.name as usize)
+    }
+
+    /// This function reads the null-terminated string at the specified offset of the specified
+    /// string table section.
+    pub fn string(&self, string_table: &SectionHeader, offset: usize) -> Result<&'a str, Error> {
+        let data = self.section_data(string_table)?;
+        let bytes = data.get(offset..).ok_or(Error::InvalidElfString(offset))?;
+        let length = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(Error::InvalidElfString(offset))?;
+        core::str::from_utf8(&bytes[..length]).map_err(|_| Error::InvalidElfString(offset))
+    }
+
+    pub fn symbol(&self, symbol_table: &SectionHeader, index: usize) -> Result<Symbol, Error> {
+        read::<Symbol>(self.data, entry_offset(symbol_table.offset, index, size_of::<Symbol>())?)
+    }
+
+    pub fn symbols(
+        &self, symbol_table: &SectionHeader,
+    ) -> impl Iterator<Item = Result<Symbol, Error>> + '_ {
+        let symbol_table = *symbol_table;
+        (0..symbol_table.size as usize / size_of::<Symbol>())
+            .map(move |index| self.symbol(&symbol_table, index))
+    }
+
+    pub fn relocations(
+        &self, relocation_table: &SectionHeader,
+    ) -> impl Iterator<Item = Result<Rela, Error>> + '_ {
+        let offset = relocation_table.offset;
+        (0..relocation_table.size as usize / size_of::<Rela>()).map(move |index| {
+            read::<Rela>(self.data, entry_offset(offset, index, size_of::<Rela>())?)
+        })
+    }
+
+    /// This function translates the specified virtual address into an offset in the file, if the
+    /// address is located in the file data of a loadable segment.
+    pub fn file_offset(&self, virtual_address: u64) -> Result<Option<usize>, Error> {
+        for segment in self.program_headers() {
+            let segment = segment?;
+            // The ranges of the segment were validated by reading its program header
+            if segment.kind == PT_LOAD
+                && virtual_address >= segment.virtual_address
+                && virtual_address - segment.virtual_address < segment.file_size
+            {
+                let offset = segment.offset + (virtual_address - segment.virtual_address);
+                return Ok(Some(to_usize(offset)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// This function returns the value of the first entry with the specified tag in the dynamic
+    /// segment. If the file has no dynamic segment or no entry with this tag, [None] is returned.
+    pub fn dynamic_entry(&self, tag: i64) -> Result<Option<u64>, Error> {
+        for segment in self.program_headers() {
+            let segment = segment?;
+            if segment.kind != PT_DYNAMIC {
+                continue;
+            }
+
+            for index in 0..segment.file_size as usize / size_of::<DynamicEntry>() {
+                let entry = read::<DynamicEntry>(
+                    self.data,
+                    entry_offset(segment.offset, index, size_of::<DynamicEntry>())?,
+                )?;
+                match entry.tag {
+                    DT_NULL => break,
+                    entry_tag if entry_tag == tag => return Ok(Some(entry.value)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// This function returns the relocations of the dynamic relocation table (`DT_RELA`). Files
+    /// without dynamic relocation table return no relocations.
+    pub fn dynamic_relocations(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Rela, Error>> + '_, Error> {
+        let (offset, count, entry_size) = match self.dynamic_entry(DT_RELA)? {
+            Some(address) => {
+                let offset = self
+                    .file_offset(address)?
+                    .ok_or(Error::ElfOutOfBounds(address as usize))?;
+                let size = to_usize(self.dynamic_entry(DT_RELASZ)?.unwrap_or(0))?;
+                let entry_size = to_usize(
+                    self.dynamic_entry(DT_RELAENT)?
+                        .unwrap_or(size_of::<Rela>() as u64),
+                )?;
+                if entry_size < size_of::<Rela>() {
+                    return Err(Error::InvalidElfHeader("relocation entry size is too small"));
+                }
+                (offset, size / entry_size, entry_size)
+            }
+            None => (0, 0, size_of::<Rela>()),
+        };
+
+        Ok((0..count).map(move |index| {
+            read::<Rela>(self.data, entry_offset(offset as u64, index, entry_size)?)
+        }))
+    }
+
+    /// This function searches the first section with the specified name
+    pub fn find_section(&self, name: &str) -> Result<Option<SectionHeader>, Error> {
+        for section in self.section_headers() {
+            let section = section?;
+            if self.section_name(&section)? == name {
+                return Ok(Some(section));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// This function writes the result of the specified relocation into the target memory. The caller
+/// has to calculate the symbol value (S) and the place address (P) of the relocation.
+pub fn apply_relocation(
+    target: &mut [u8], offset: usize, kind: u32, symbol_value: u64, addend: i64, place: u64,
+) -> Result<(), Error> {
+    let value = (symbol_value as i64).wrapping_add(addend);
+    match kind {
+        R_X86_64_NONE => Ok(()),
+        R_X86_64_64 | R_X86_64_RELATIVE => write_bytes(target, offset, &value.to_le_bytes()),
+        R_X86_64_PC32 | R_X86_64_PLT32 => {
+            let value = value.wrapping_sub(place as i64) as i32;
+            write_bytes(target, offset, &value.to_le_bytes())
+        }
+        R_X86_64_32 => write_bytes(target, offset, &(value as u32).to_le_bytes()),
+        R_X86_64_32S => write_bytes(target, offset, &(value as i32).to_le_bytes()),
+        _ => Err(Error::UnsupportedRelocation(kind)),
+    }
+}
+
+fn write_bytes(target: &mut [u8], offset: usize, bytes: &[u8]) -> Result<(), Error> {
+    let end = offset
+        .checked_add(bytes.len())
+        .ok_or(Error::ElfOutOfBounds(offset))?;
+    target
+        .get_mut(offset..end)
+        .ok_or(Error::ElfOutOfBounds(offset))?
+        .copy_from_slice(bytes);
+    Ok(())
+}
+
+fn slice(data: &[u8], offset: usize, size: usize) -> Result<&[u8], Error> {
+    data.get(
+        offset
+            ..offset
+                .checked_add(size)
+                .ok_or(Error::ElfOutOfBounds(offset))?,
+    )
+    .ok_or(Error::ElfOutOfBounds(offset))
+}
+
+fn read<T: Copy>(data: &[u8], offset: usize) -> Result<T, Error> {
+    let bytes = slice(data, offset, size_of::<T>())?;
+    Ok(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// This function returns the offset of the entry with the specified index in a table of the file. If
+/// the offset overflows, this function returns an error.
+fn entry_offset(table_offset: u64, index: usize, entry_size: usize) -> Result<usize, Error> {
+    index
+        .checked_mul(entry_size)
+        .and_then(|offset| to_usize(table_offset).ok()?.checked_add(offset))
+        .ok_or(Error::ElfOutOfBounds(table_offset as usize))
+}
+
+/// This function checks, that the table with the specified number of entries is located completely
+/// in the file
+fn check_table(data: &[u8], offset: u64, count: usize, entry_size: usize) -> Result<(), Error> {
+    let size = count
+        .checked_mul(entry_size)
+        .ok_or(Error::ElfOutOfBounds(offset as usize))?;
+    slice(data, to_usize(offset)?, size).map(|_| ())
+}
+
+#[inline]
+fn to_usize(value: u64) -> Result<usize, Error> {
+    usize::try_from(value).map_err(|_| Error::ElfOutOfBounds(usize::MAX))
+}
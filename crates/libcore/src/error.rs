@@ -0,0 +1,85 @@
+use thiserror_no_std::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("ELF Error: Invalid ELF magic")]
+    InvalidElfMagic,
+
+    #[error("ELF Error: Unsupported ELF file (class {0}, machine {1})")]
+    UnsupportedElf(u8, u16),
+
+    #[error("ELF Error: Read at offset 0x{0:X} is out of bounds")]
+    ElfOutOfBounds(usize),
+
+    #[error("ELF Error: Invalid ELF header ({0})")]
+    InvalidElfHeader(&'static str),
+
+    #[error("ELF Error: Invalid program header {0} ({1})")]
+    InvalidElfSegment(usize, &'static str),
+
+    #[error("ELF Error: Invalid string at offset 0x{0:X}")]
+    InvalidElfString(usize),
+
+    #[error("ELF Error: Unsupported relocation type {0}")]
+    UnsupportedRelocation(u32),
+
+    #[error("Initrd Error: Invalid archive header at offset 0x{0:X}")]
+    InvalidInitrdHeader(usize),
+
+    #[error("Config Error: {2} in line {0}, column {1}")]
+    InvalidConfig(usize, usize, &'static str),
+
+    #[error("Paging Error: Address 0x{0:X} is not mapped")]
+    PageNotMapped(u64),
+
+    #[error("Paging Error: Unable to allocate memory for a page table")]
+    NoPageTableMemory,
+
+    #[error("Frame Allocator Error: No conventional memory for the frame table ({0} bytes)")]
+    NoFrameTableMemory(u64),
+
+    #[error("Paging Error: Memory type 0x{0:X} is not available in the PAT")]
+    UnsupportedCacheType(u8),
+
+    #[error("Device Tree Error: Invalid magic 0x{0:X}")]
+    InvalidDeviceTreeMagic(u32),
+
+    #[error("Device Tree Error: Unsupported version {0} (expected 16 or newer)")]
+    UnsupportedDeviceTreeVersion(u32),
+
+    #[error("Device Tree Error: Block is out of bounds")]
+    DeviceTreeOutOfBounds,
+
+    #[error("Boot Info Error: Invalid magic 0x{0:X}")]
+    InvalidBootInfoMagic(u64),
+
+    #[error("Boot Info Error: Unsupported version {0}")]
+    UnsupportedBootInfoVersion(u32),
+
+    #[error("Boot Info Error: Tag at offset 0x{0:X} is out of bounds")]
+    BootInfoOutOfBounds(usize),
+
+    #[error("Boot Info Error: Invalid value in tag {0}")]
+    InvalidBootInfoTag(u32),
+
+    #[error("Boot Info Error: Encoded boot information needs {0} bytes (buffer too small)")]
+    BootInfoTooLarge(usize),
+
+    #[error("Multiboot2 Error: No Multiboot2 header found in the first 32 KiB of the kernel")]
+    NoMultiboot2Header,
+
+    #[error("Multiboot2 Error: Invalid header (architecture, checksum or tag sizes)")]
+    InvalidMultiboot2Header,
+
+    #[error("Multiboot2 Error: Unsupported required header tag {0}")]
+    UnsupportedMultiboot2Tag(u16),
+
+    #[error("Multiboot2 Error: Unsupported required information tag {0}")]
+    UnsupportedMultiboot2Request(u32),
+
+    #[error("Multiboot2 Error: Information needs {0} bytes (buffer too small)")]
+    Multiboot2InfoTooLarge(usize),
+
+    #[error("Memory Map Error: Invalid descriptor size {0}")]
+    InvalidMemoryDescriptorSize(usize),
+}
@@ -0,0 +1,206 @@
+use crate::{
+    alternatives::{
+        Alternative,
+        Feature,
+        Variant,
+    },
+    registers::{
+        read_cr4,
+        CR4_OSFXSR,
+        CR4_OSXSAVE,
+    },
+};
+use core::{
+    arch::{
+        asm,
+        x86_64::{
+            __cpuid,
+            __m128i,
+            __m256i,
+            _mm256_loadu_si256,
+            _mm256_set1_epi32,
+            _mm256_store_si256,
+            _mm_loadu_si128,
+            _mm_set1_epi32,
+            _mm_store_si128,
+            _xgetbv,
+        },
+    },
+    mem,
+    ptr,
+};
+
+type CopyFunction = unsafe fn(*mut u8, *const u8, usize);
+type FillFunction = unsafe fn(*mut u8, usize, u32);
+
+/// The variants of the memory copy. `rep movsb` is preferred over SSE2, if the CPU has fast strings.
+#[rustfmt::skip]
+pub static COPY: Alternative<CopyFunction> = Alternative::new("copy", &[
+    Variant { name: "avx", features: &[Feature::Avx], function: copy_avx },
+    Variant { name: "erms", features: &[Feature::Erms], function: copy_erms },
+    Variant { name: "sse2", features: &[Feature::Sse2], function: copy_sse2 },
+    Variant { name: "generic", features: &[], function: copy_generic },
+]);
+
+/// The variants of the memory fill with a 32-bit pattern
+#[rustfmt::skip]
+pub static FILL: Alternative<FillFunction> = Alternative::new("fill", &[
+    Variant { name: "avx", features: &[Feature::Avx], function: fill_avx },
+    Variant { name: "sse2", features: &[Feature::Sse2], function: fill_sse2 },
+    Variant { name: "generic", features: &[], function: fill_scalar },
+]);
+
+/// The widest SIMD extension, which is supported by the CPU and enabled by the operating system
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar = 0,
+    Sse2 = 1,
+    Avx = 2,
+}
+
+/// This function detects the SIMD level with CPUID and the control registers, which enable the
+/// state of the extensions
+pub fn detect_simd_level() -> SimdLevel {
+    let features = unsafe { __cpuid(0x01) };
+    let cr4 = read_cr4();
+    let sse2 = features.edx & (1 << 26) != 0 && cr4 & CR4_OSFXSR != 0;
+    let avx = sse2
+        && features.ecx & (1 << 28) != 0
+        && cr4 & CR4_OSXSAVE != 0
+        && unsafe { _xgetbv(0) } & 0b110 == 0b110;
+
+    match (sse2, avx) {
+        (_, true) => SimdLevel::Avx,
+        (true, false) => SimdLevel::Sse2,
+        _ => SimdLevel::Scalar,
+    }
+}
+
+/// This function copies the source into the destination slice with the variant, which was selected
+/// by the alternatives. Both slices must have the same length.
+pub fn copy<T: Copy>(destination: &mut [T], source: &[T]) {
+    assert_eq!(destination.len(), source.len());
+    let length = mem::size_of_val(source);
+    unsafe {
+        let destination = destination.as_mut_ptr() as *mut u8;
+        let source = source.as_ptr() as *const u8;
+        COPY.get()(destination, source, length);
+    }
+}
+
+/// This function fills the destination slice with the specified value
+pub fn fill_u32(destination: &mut [u32], value: u32) {
+    unsafe {
+        fill_pattern(destination.as_mut_ptr() as *mut u8, mem::size_of_val(destination), value)
+    };
+}
+
+/// This function sets all bytes of the destination slice to zero
+pub fn zero(destination: &mut [u8]) {
+    unsafe { fill_pattern(destination.as_mut_ptr(), destination.len(), 0) };
+}
+
+/// This function fills the memory with the 32-bit pattern. The memory must be aligned to 4 bytes,
+/// unless all bytes of the pattern are equal.
+#[inline]
+unsafe fn fill_pattern(destination: *mut u8, length: usize, pattern: u32) {
+    FILL.get()(destination, length, pattern)
+}
+
+#[inline]
+unsafe fn copy_generic(destination: *mut u8, source: *const u8, length: usize) {
+    ptr::copy_nonoverlapping(source, destination, length);
+}
+
+/// The copy with `rep movsb` is fast for all sizes and alignments on CPUs with fast strings (ERMS)
+#[inline]
+unsafe fn copy_erms(destination: *mut u8, source: *const u8, length: usize) {
+    asm!(
+        "rep movsb",
+        inout("rcx") length => _,
+        inout("rdi") destination => _,
+        inout("rsi") source => _,
+        options(nostack, preserves_flags)
+    );
+}
+
+#[inline]
+unsafe fn fill_scalar(destination: *mut u8, length: usize, pattern: u32) {
+    for offset in 0..length {
+        let address = destination.add(offset);
+        address.write((pattern >> ((address as usize % 4) * 8)) as u8);
+    }
+}
+
+/// This function returns the number of bytes until the pointer is aligned to the specified alignment
+#[inline]
+fn head_length(pointer: *const u8, align: usize, length: usize) -> usize {
+    pointer.align_offset(align).min(length)
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn copy_sse2(mut destination: *mut u8, mut source: *const u8, mut length: usize) {
+    let head = head_length(destination, 16, length);
+    ptr::copy_nonoverlapping(source, destination, head);
+    destination = destination.add(head);
+    source = source.add(head);
+    length -= head;
+
+    while length >= 16 {
+        _mm_store_si128(destination as *mut __m128i, _mm_loadu_si128(source as *const __m128i));
+        destination = destination.add(16);
+        source = source.add(16);
+        length -= 16;
+    }
+    ptr::copy_nonoverlapping(source, destination, length);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn copy_avx(mut destination: *mut u8, mut source: *const u8, mut length: usize) {
+    let head = head_length(destination, 32, length);
+    ptr::copy_nonoverlapping(source, destination, head);
+    destination = destination.add(head);
+    source = source.add(head);
+    length -= head;
+
+    while length >= 32 {
+        _mm256_store_si256(destination as *mut __m256i, _mm256_loadu_si256(source as *const __m256i));
+        destination = destination.add(32);
+        source = source.add(32);
+        length -= 32;
+    }
+    ptr::copy_nonoverlapping(source, destination, length);
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn fill_sse2(mut destination: *mut u8, mut length: usize, pattern: u32) {
+    let head = head_length(destination, 16, length);
+    fill_scalar(destination, head, pattern);
+    destination = destination.add(head);
+    length -= head;
+
+    let value = _mm_set1_epi32(pattern as i32);
+    while length >= 16 {
+        _mm_store_si128(destination as *mut __m128i, value);
+        destination = destination.add(16);
+        length -= 16;
+    }
+    fill_scalar(destination, length, pattern);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn fill_avx(mut destination: *mut u8, mut length: usize, pattern: u32) {
+    let head = head_length(destination, 32, length);
+    fill_scalar(destination, head, pattern);
+    destination = destination.add(head);
+    length -= head;
+
+    let value = _mm256_set1_epi32(pattern as i32);
+    while length >= 32 {
+        _mm256_store_si256(destination as *mut __m256i, value);
+        destination = destination.add(32);
+        length -= 32;
+    }
+    fill_scalar(destination, length, pattern);
+}
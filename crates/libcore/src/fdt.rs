@@ -0,0 +1,414 @@
+//! The flattened device tree (FDT) describes the hardware of platforms without ACPI (like the virt
+//! machine of QEMU on aarch64). It's passed by the firmware in the UEFI configuration table. This
+//! parser reads the structure block in place and provides the information, which the x86 path
+//! discovers with ACPI: the memory, the UART, the interrupt controller and the virtio-mmio devices.
+
+use crate::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    error::Error,
+};
+
+pub const FDT_MAGIC: u32 = 0xD00D_FEED;
+
+/// The device tree version 16 introduced the structure block format, which is parsed here
+const FDT_MIN_VERSION: u32 = 16;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+
+/// The maximal depth of nodes, deeper nodes are skipped
+const MAX_DEPTH: usize = 16;
+
+/// The default number of cells of addresses and sizes, if the parent doesn't specify them
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+/// The interrupts of the GIC are described by 3 cells. The first cell is 0 for shared peripheral
+/// interrupts, which start at interrupt ID 32, and 1 for private peripheral interrupts, which start
+/// at interrupt ID 16.
+const GIC_SPI: u32 = 0;
+const GIC_SPI_BASE: u32 = 32;
+const GIC_PPI_BASE: u32 = 16;
+
+const UART_COMPATIBLES: [&str; 4] = ["arm,pl011", "ns16550a", "ns16550", "snps,dw-apb-uart"];
+const GICV2_COMPATIBLES: [&str; 3] = ["arm,cortex-a15-gic", "arm,gic-400", "arm,cortex-a9-gic"];
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+#[inline]
+fn align_up(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// This function reads a number with the specified number of 32-bit cells. Numbers with more than 2
+/// cells are truncated to the lower 64 bits.
+fn read_cells(data: &[u8], offset: usize, cells: u32) -> Option<u64> {
+    (0..cells as usize)
+        .try_fold(0u64, |value, index| Some(value << 32 | read_u32(data, offset + index * 4)? as u64))
+}
+
+/// This function returns the string at the specified offset, which is terminated by a null byte
+fn read_string(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let length = bytes.iter().position(|byte| *byte == 0)?;
+    core::str::from_utf8(&bytes[..length]).ok()
+}
+
+/// The device tree, which is parsed in place
+#[derive(Clone, Copy)]
+pub struct DeviceTree<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> DeviceTree<'a> {
+    /// This function validates the header of the device tree and returns the device tree. If the
+    /// magic or the version are invalid, this function returns an error.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let header = |index: usize| read_u32(data, index * 4).ok_or(Error::DeviceTreeOutOfBounds);
+        let magic = header(0)?;
+        if magic != FDT_MAGIC {
+            return Err(Error::InvalidDeviceTreeMagic(magic));
+        }
+        let version = header(5)?;
+        if version < FDT_MIN_VERSION {
+            return Err(Error::UnsupportedDeviceTreeVersion(version));
+        }
+
+        let block = |offset: u32, size: u32| {
+            data.get(offset as usize..offset as usize + size as usize)
+                .ok_or(Error::DeviceTreeOutOfBounds)
+        };
+        Ok(Self {
+            structure: block(header(2)?, header(9)?)?,
+            strings: block(header(3)?, header(8)?)?,
+        })
+    }
+
+    /// This function parses the device tree at the specified address. The size is read from the
+    /// header.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the address points to a device tree, which is mapped and
+    /// stays valid
+    pub unsafe fn from_address(address: VirtAddr) -> Result<Self, Error> {
+        let header = core::slice::from_raw_parts(address.as_ptr::<u8>(), FDT_HEADER_SIZE);
+        let size = read_u32(header, 4).ok_or(Error::DeviceTreeOutOfBounds)? as usize;
+        Self::parse(core::slice::from_raw_parts(address.as_ptr(), size.max(FDT_HEADER_SIZE)))
+    }
+
+    /// This function returns all nodes of the device tree in depth-first order
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            tree: *self,
+            offset: 0,
+            depth: 0,
+            cells: [(DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS); MAX_DEPTH],
+        }
+    }
+
+    /// This function returns the first node with the specified name. The name is compared with and
+    /// without unit address (like `memory` and `memory@40000000`).
+    pub fn find_node(&self, name: &str) -> Option<Node<'a>> {
+        self.nodes()
+            .find(|node| node.name == name || node.name.split('@').next() == Some(name))
+    }
+
+    /// This function returns all nodes, which are compatible with the specified device
+    pub fn find_compatible<'b>(&self, compatible: &'b str) -> impl Iterator<Item = Node<'a>> + 'b
+    where
+        'a: 'b,
+    {
+        self.nodes()
+            .filter(move |node| node.is_compatible(compatible))
+    }
+
+    /// This function returns the physical memory regions as address and size
+    pub fn memory_regions(&self) -> impl Iterator<Item = (PhysAddr, u64)> + 'a {
+        self.nodes()
+            .filter(|node| {
+                node.depth == 1
+                    && (node.property_str("device_type") == Some("memory")
+                        || node.name.split('@').next() == Some("memory"))
+            })
+            .flat_map(|node| node.reg())
+            .map(|(address, size)| (PhysAddr::new(address), size))
+    }
+
+    /// This function returns the total size of the physical memory in bytes
+    pub fn memory_size(&self) -> u64 {
+        self.memory_regions().map(|(_, size)| size).sum()
+    }
+
+    /// This function returns the UART of the console. The UART is selected with the `stdout-path`
+    /// of the `chosen` node, otherwise the first supported UART is returned.
+    pub fn uart(&self) -> Option<Uart<'a>> {
+        let chosen = self
+            .find_node("chosen")
+            .and_then(|chosen| chosen.property_str("stdout-path"))
+            .and_then(|path| path.split(':').next()?.rsplit('/').next())
+            .and_then(|name| self.nodes().find(|node| node.name == name));
+        let node = chosen.or_else(|| {
+            self.nodes().find(|node| {
+                UART_COMPATIBLES
+                    .iter()
+                    .any(|compatible| node.is_compatible(compatible))
+            })
+        })?;
+
+        Some(Uart {
+            address: PhysAddr::new(node.reg().next()?.0),
+            compatible: node.compatible().next().unwrap_or_default(),
+        })
+    }
+
+    /// This function returns the interrupt controller, if a GICv2 or GICv3 is described
+    pub fn gic(&self) -> Option<Gic> {
+        let version = |node: &Node| {
+            if node.is_compatible("arm,gic-v3") {
+                Some(3)
+            } else if GICV2_COMPATIBLES
+                .iter()
+                .any(|compatible| node.is_compatible(compatible))
+            {
+                Some(2)
+            } else {
+                None
+            }
+        };
+        let (version, node) = self
+            .nodes()
+            .find_map(|node| Some((version(&node)?, node)))?;
+
+        // The GICv2 has a CPU interface behind the distributor, the GICv3 has redistributors
+        let mut reg = node.reg();
+        let distributor = PhysAddr::new(reg.next()?.0);
+        let second_region = reg
+            .next()
+            .map_or(PhysAddr::NULL, |(address, _)| PhysAddr::new(address));
+        Some(Gic {
+            version,
+            distributor,
+            cpu_interface: if version == 2 {
+                second_region
+            } else {
+                PhysAddr::NULL
+            },
+            redistributor: if version == 3 {
+                second_region
+            } else {
+                PhysAddr::NULL
+            },
+        })
+    }
+
+    /// This function returns the virtio-mmio devices. Devices without register region are skipped.
+    pub fn virtio_mmio_devices(&self) -> impl Iterator<Item = MmioDevice> + 'a {
+        self.find_compatible("virtio,mmio").filter_map(|node| {
+            let (address, size) = node.reg().next()?;
+            Some(MmioDevice {
+                address: PhysAddr::new(address),
+                size,
+                interrupt: node.gic_interrupt(),
+            })
+        })
+    }
+}
+
+/// A node of the device tree
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    pub name: &'a str,
+    /// The depth of the node, the root node has the depth 0
+    pub depth: usize,
+    tree: DeviceTree<'a>,
+    properties_offset: usize,
+    /// The number of cells of addresses and sizes in the `reg` property, which are specified by
+    /// the parent node
+    address_cells: u32,
+    size_cells: u32,
+}
+
+impl<'a> Node<'a> {
+    /// This function returns the properties of the node as name and value
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let tree = self.tree;
+        let mut offset = self.properties_offset;
+        core::iter::from_fn(move || {
+            loop {
+                match read_u32(tree.structure, offset)? {
+                    FDT_NOP => offset += 4,
+                    FDT_PROP => {
+                        let length = read_u32(tree.structure, offset + 4)? as usize;
+                        let name_offset = read_u32(tree.structure, offset + 8)? as usize;
+                        let value = tree.structure.get(offset + 12..offset + 12 + length)?;
+                        offset = align_up(offset + 12 + length);
+                        return Some((read_string(tree.strings, name_offset)?, value));
+                    }
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    /// This function returns the value of the specified property
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|(property_name, _)| *property_name == name)
+            .map(|(_, value)| value)
+    }
+
+    /// This function returns the value of the specified property as string
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        read_string(self.property(name)?, 0)
+    }
+
+    /// This function returns the value of the specified property as 32-bit number
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        read_u32(self.property(name)?, 0)
+    }
+
+    /// This function returns the entries of the `compatible` property, the most specific entry is
+    /// returned first
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible")
+            .unwrap_or_default()
+            .split(|byte| *byte == 0)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| core::str::from_utf8(entry).ok())
+    }
+
+    #[inline]
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|entry| entry == compatible)
+    }
+
+    /// This function returns the register regions of the node as address and size. The addresses
+    /// aren't translated with the `ranges` of the parent nodes, the nodes of the QEMU virt machine
+    /// are located in the root address space.
+    pub fn reg(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let value = self.property("reg").unwrap_or_default();
+        let (address_cells, size_cells) = (self.address_cells, self.size_cells);
+        let entry_size = (address_cells + size_cells) as usize * 4;
+        (0..value.len().checked_div(entry_size).unwrap_or(0)).filter_map(move |index| {
+            let offset = index * entry_size;
+            Some((
+                read_cells(value, offset, address_cells)?,
+                read_cells(value, offset + address_cells as usize * 4, size_cells)?,
+            ))
+        })
+    }
+
+    /// This function returns the interrupt ID of the first interrupt of the node, if the interrupt
+    /// is described with the 3 cells of the GIC
+    pub fn gic_interrupt(&self) -> Option<u32> {
+        let interrupts = self.property("interrupts")?;
+        if interrupts.len() < 12 {
+            return None;
+        }
+
+        let number = read_u32(interrupts, 4)?;
+        Some(match read_u32(interrupts, 0)? {
+            GIC_SPI => number + GIC_SPI_BASE,
+            _ => number + GIC_PPI_BASE,
+        })
+    }
+}
+
+/// The iterator over the nodes of the device tree, which is returned by [DeviceTree::nodes]
+pub struct Nodes<'a> {
+    tree: DeviceTree<'a>,
+    offset: usize,
+    depth: usize,
+    /// The number of address and size cells, which are specified by the nodes on the current path
+    cells: [(u32, u32); MAX_DEPTH],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let structure = self.tree.structure;
+        loop {
+            match read_u32(structure, self.offset)? {
+                FDT_BEGIN_NODE => {
+                    let name = read_string(structure, self.offset + 4)?;
+                    let depth = self.depth;
+                    self.offset = align_up(self.offset + 4 + name.len() + 1);
+                    self.depth += 1;
+                    if depth >= MAX_DEPTH {
+                        continue;
+                    }
+
+                    let (address_cells, size_cells) = match depth {
+                        0 => (DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS),
+                        depth => self.cells[depth - 1],
+                    };
+                    let node = Node {
+                        name,
+                        depth,
+                        tree: self.tree,
+                        properties_offset: self.offset,
+                        address_cells,
+                        size_cells,
+                    };
+
+                    // The children of the node use the cells, which are specified by the node
+                    self.cells[depth] = (
+                        node.property_u32("#address-cells")
+                            .unwrap_or(DEFAULT_ADDRESS_CELLS),
+                        node.property_u32("#size-cells")
+                            .unwrap_or(DEFAULT_SIZE_CELLS),
+                    );
+                    return Some(node);
+                }
+                FDT_END_NODE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    self.offset += 4;
+                }
+                FDT_PROP => {
+                    let length = read_u32(structure, self.offset + 4)? as usize;
+                    self.offset = align_up(self.offset + 12 + length);
+                }
+                FDT_NOP => self.offset += 4,
+                // The end token or an invalid token ends the iteration
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// The UART, which is described by the device tree
+#[derive(Clone, Copy, Debug)]
+pub struct Uart<'a> {
+    pub address: PhysAddr,
+    /// The most specific compatible device (like `arm,pl011`)
+    pub compatible: &'a str,
+}
+
+/// The generic interrupt controller, which is described by the device tree. The addresses, which
+/// don't exist in the version of the controller, are null.
+#[derive(Clone, Copy, Debug)]
+pub struct Gic {
+    pub version: u8,
+    pub distributor: PhysAddr,
+    pub cpu_interface: PhysAddr,
+    pub redistributor: PhysAddr,
+}
+
+/// A virtio device with memory-mapped registers
+#[derive(Clone, Copy, Debug)]
+pub struct MmioDevice {
+    pub address: PhysAddr,
+    pub size: u64,
+    pub interrupt: Option<u32>,
+}
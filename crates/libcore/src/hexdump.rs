@@ -0,0 +1,113 @@
+use crate::{
+    address::VirtAddr,
+    paging::{
+        translate,
+        PAGE_SIZE,
+    },
+};
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+};
+
+pub const BYTES_PER_LINE: usize = 16;
+
+/// A hexdump of a byte slice, which is formatted with one line per 16 bytes. Every line contains the
+/// address, the bytes in hex and the printable ASCII characters (like `xxd` or `hexdump -C`).
+#[derive(Clone, Copy)]
+pub struct HexDump<'a> {
+    address: u64,
+    data: &'a [u8],
+}
+
+/// A single line of a hexdump without line feed
+#[derive(Clone, Copy)]
+pub struct HexDumpLine<'a> {
+    pub address: u64,
+    pub data: &'a [u8],
+}
+
+impl<'a> HexDump<'a> {
+    /// This function creates a hexdump of the data. The address is only used for the output, so
+    /// file offsets can be dumped too.
+    pub const fn new(address: u64, data: &'a [u8]) -> Self {
+        Self { address, data }
+    }
+
+    /// This function returns the lines of the hexdump. This is used to pass every line separately to
+    /// the logger.
+    pub fn lines(&self) -> impl Iterator<Item = HexDumpLine<'a>> {
+        let address = self.address;
+        self.data
+            .chunks(BYTES_PER_LINE)
+            .enumerate()
+            .map(move |(index, data)| {
+                HexDumpLine {
+                    address: address.wrapping_add((index * BYTES_PER_LINE) as u64),
+                    data,
+                }
+            })
+    }
+}
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(formatter, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HexDumpLine<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "0x{:016X} ", self.address)?;
+        for byte in self.data {
+            write!(formatter, " {:02X}", byte)?;
+        }
+
+        // Align the ASCII column of the last line with the previous lines
+        let padding = (BYTES_PER_LINE - self.data.len().min(BYTES_PER_LINE)) * 3;
+        write!(formatter, "{:padding$}  |", "", padding = padding)?;
+        for byte in self.data {
+            let char = match byte {
+                0x20..=0x7E => *byte as char,
+                _ => '.',
+            };
+            write!(formatter, "{}", char)?;
+        }
+        formatter.write_str("|")
+    }
+}
+
+/// This function checks with the active page tables, if every page of the specified range is
+/// mapped. Returns the first unmapped address, if a page isn't mapped.
+pub fn find_unmapped(address: u64, length: usize) -> Option<u64> {
+    let end = match address.checked_add(length as u64) {
+        Some(end) => end,
+        None => return Some(address),
+    };
+
+    let mut page = address & !(PAGE_SIZE - 1);
+    while page < end {
+        if VirtAddr::try_new(page).and_then(translate).is_none() {
+            return Some(page.max(address));
+        }
+        page += PAGE_SIZE;
+    }
+    None
+}
+
+/// This function returns the memory of the specified range, if every page of the range is mapped.
+/// This allows to inspect arbitrary addresses without page faults.
+///
+/// # Safety
+/// The memory may be changed while the slice is used, so the caller has to ensure, that it's only
+/// used for inspection.
+pub unsafe fn mapped_slice<'a>(address: u64, length: usize) -> Option<&'a [u8]> {
+    if length == 0 || find_unmapped(address, length).is_some() {
+        return None;
+    }
+    Some(core::slice::from_raw_parts(address as *const u8, length))
+}
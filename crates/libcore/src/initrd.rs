@@ -0,0 +1,116 @@
+use crate::error::Error;
+
+const BLOCK_SIZE: usize = 512;
+const USTAR_MAGIC: &[u8] = b"ustar";
+
+/// The initrd is a plain USTAR archive, which is loaded by the bootloader and passed to the kernel
+/// over the boot information. This structure provides read-only access to the files in the archive.
+pub struct Initrd<'a> {
+    data: &'a [u8],
+}
+
+pub struct InitrdFile<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+pub struct InitrdIterator<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Initrd<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn files(&self) -> InitrdIterator<'a> {
+        InitrdIterator {
+            data: self.data,
+            offset: 0,
+        }
+    }
+
+    /// This function returns the file with the specified path. A leading `/` or `./` in the
+    /// archive entry names is ignored.
+    pub fn find(&self, path: &str) -> Result<Option<InitrdFile<'a>>, Error> {
+        let path = normalize(path);
+        for file in self.files() {
+            let file = file?;
+            if file.name == path {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for InitrdIterator<'a> {
+    type Item = Result<InitrdFile<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let header = self.data.get(self.offset..self.offset + BLOCK_SIZE)?;
+
+            // The archive is terminated by zero blocks
+            if header.iter().all(|byte| *byte == 0) {
+                return None;
+            }
+
+            if &header[257..262] != USTAR_MAGIC {
+                let offset = self.offset;
+                self.offset = self.data.len();
+                return Some(Err(Error::InvalidInitrdHeader(offset)));
+            }
+
+            let size = match parse_octal(&header[124..136]) {
+                Some(size) => size,
+                None => {
+                    let offset = self.offset;
+                    self.offset = self.data.len();
+                    return Some(Err(Error::InvalidInitrdHeader(offset)));
+                }
+            };
+
+            let data_offset = self.offset + BLOCK_SIZE;
+            self.offset = data_offset + ((size + BLOCK_SIZE - 1) / BLOCK_SIZE) * BLOCK_SIZE;
+
+            // Only regular files are reported, directories and links are skipped
+            if header[156] != b'0' && header[156] != 0 {
+                continue;
+            }
+
+            let name = match core::str::from_utf8(trim_null(&header[0..100])) {
+                Ok(name) => normalize(name),
+                Err(_) => return Some(Err(Error::InvalidInitrdHeader(data_offset - BLOCK_SIZE))),
+            };
+            return match self.data.get(data_offset..data_offset + size) {
+                Some(data) => Some(Ok(InitrdFile { name, data })),
+                None => Some(Err(Error::InvalidInitrdHeader(data_offset - BLOCK_SIZE))),
+            };
+        }
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+fn trim_null(bytes: &[u8]) -> &[u8] {
+    match bytes.iter().position(|byte| *byte == 0) {
+        Some(length) => &bytes[..length],
+        None => bytes,
+    }
+}
+
+fn parse_octal(bytes: &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for byte in trim_null(bytes) {
+        match byte {
+            b'0'..=b'7' => value = value.checked_mul(8)? + (byte - b'0') as usize,
+            b' ' => continue,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
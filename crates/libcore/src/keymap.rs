@@ -0,0 +1,311 @@
+/// The scancodes (set 1) of the modifier keys and the extended key prefix
+const SCANCODE_EXTENDED: u8 = 0xE0;
+const SCANCODE_RELEASE: u8 = 0x80;
+const SCANCODE_LEFT_SHIFT: u8 = 0x2A;
+const SCANCODE_RIGHT_SHIFT: u8 = 0x36;
+const SCANCODE_CTRL: u8 = 0x1D;
+const SCANCODE_ALT: u8 = 0x38;
+const SCANCODE_CAPS_LOCK: u8 = 0x3A;
+const SCANCODE_NUM_LOCK: u8 = 0x45;
+const SCANCODE_SCROLL_LOCK: u8 = 0x46;
+
+/// The key between the left shift and Z on ISO keyboards
+const SCANCODE_ISO: u8 = 0x56;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub altgr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCode {
+    Char(char),
+    Escape,
+    Backspace,
+    Tab,
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    Function(u8),
+    /// A key without meaning in the keymap, which is identified by its scancode
+    Unknown(u8),
+}
+
+/// A keymap translates the scancodes (set 1) of the main block into characters. The tables contain
+/// one character per scancode from 0x00 to 0x39, `\0` marks keys without character.
+pub struct Keymap {
+    pub name: &'static str,
+    normal: &'static str,
+    shifted: &'static str,
+    altgr: &'static [(u8, char)],
+    iso: [char; 2],
+}
+
+#[rustfmt::skip]
+pub static US: Keymap = Keymap {
+    name: "us",
+    normal:  "\0\x1B1234567890-=\x08\tqwertyuiop[]\n\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ",
+    shifted: "\0\x1B!@#$%^&*()_+\x08\tQWERTYUIOP{}\n\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ",
+    altgr: &[],
+    iso: ['\\', '|'],
+};
+
+#[rustfmt::skip]
+pub static DE: Keymap = Keymap {
+    name: "de",
+    normal:  "\0\x1B1234567890ß´\x08\tqwertzuiopü+\n\0asdfghjklöä^\0#yxcvbnm,.-\0*\0 ",
+    shifted: "\0\x1B!\"§$%&/()=?`\x08\tQWERTZUIOPÜ*\n\0ASDFGHJKLÖÄ°\0'YXCVBNM;:_\0*\0 ",
+    altgr: &[
+        (0x03, '²'), (0x04, '³'), (0x08, '{'), (0x09, '['), (0x0A, ']'), (0x0B, '}'),
+        (0x0C, '\\'), (0x10, '@'), (0x12, '€'), (0x1B, '~'), (0x32, 'µ'), (SCANCODE_ISO, '|'),
+    ],
+    iso: ['<', '>'],
+};
+
+#[rustfmt::skip]
+pub static FR: Keymap = Keymap {
+    name: "fr",
+    normal:  "\0\x1B&é\"'(-è_çà)=\x08\tazertyuiop^$\n\0qsdfghjklmù²\0*wxcvbn,;:!\0*\0 ",
+    shifted: "\0\x1B1234567890°+\x08\tAZERTYUIOP¨£\n\0QSDFGHJKLM%\0\0µWXCVBN?./§\0*\0 ",
+    altgr: &[
+        (0x03, '~'), (0x04, '#'), (0x05, '{'), (0x06, '['), (0x07, '|'), (0x08, '`'),
+        (0x09, '\\'), (0x0A, '^'), (0x0B, '@'), (0x0C, ']'), (0x0D, '}'), (0x12, '€'),
+        (0x1B, '¤'),
+    ],
+    iso: ['<', '>'],
+};
+
+pub static KEYMAPS: [&Keymap; 3] = [&US, &DE, &FR];
+
+/// This function returns the keymap with the specified name (like `de`)
+pub fn keymap_by_name(name: &str) -> Option<&'static Keymap> {
+    KEYMAPS
+        .iter()
+        .copied()
+        .find(|keymap| keymap.name.eq_ignore_ascii_case(name))
+}
+
+impl Keymap {
+    /// This function translates the scancode of a pressed key with the specified modifiers into a
+    /// character. Caps lock only affects letters.
+    pub fn translate(&self, scancode: u8, modifiers: Modifiers) -> Option<char> {
+        if modifiers.altgr {
+            return self
+                .altgr
+                .iter()
+                .find(|(altgr_scancode, _)| *altgr_scancode == scancode)
+                .map(|(_, char)| *char);
+        }
+
+        let char = match scancode {
+            SCANCODE_ISO => self.iso[modifiers.shift as usize],
+            _ => {
+                let table = if modifiers.shift {
+                    self.shifted
+                } else {
+                    self.normal
+                };
+                table.chars().nth(scancode as usize)?
+            }
+        };
+
+        match char {
+            '\0' => None,
+            char if modifiers.caps_lock && char.is_alphabetic() => {
+                self.translate(
+                    scancode,
+                    Modifiers {
+                        shift: !modifiers.shift,
+                        caps_lock: false,
+                        ..modifiers
+                    },
+                )
+            }
+            char => Some(char),
+        }
+    }
+
+    /// This function returns the scancode and the modifiers, which produce the specified character
+    /// with this keymap.
+    pub fn position_of(&self, char: char) -> Option<(u8, Modifiers)> {
+        let find = |table: &str| table.chars().position(|table_char| table_char == char);
+        if let Some(scancode) = find(self.normal) {
+            return Some((scancode as u8, Modifiers::default()));
+        }
+        if let Some(scancode) = find(self.shifted) {
+            return Some((
+                scancode as u8,
+                Modifiers {
+                    shift: true,
+                    ..Modifiers::default()
+                },
+            ));
+        }
+        if let Some(shift) = self.iso.iter().position(|iso_char| *iso_char == char) {
+            return Some((
+                SCANCODE_ISO,
+                Modifiers {
+                    shift: shift == 1,
+                    ..Modifiers::default()
+                },
+            ));
+        }
+
+        let (scancode, _) = self
+            .altgr
+            .iter()
+            .find(|(_, altgr_char)| *altgr_char == char)?;
+        Some((
+            *scancode,
+            Modifiers {
+                altgr: true,
+                ..Modifiers::default()
+            },
+        ))
+    }
+
+    /// This function translates a character, which was produced with the specified keymap, into the
+    /// character of the same key with this keymap. This is used for firmware, which only supports
+    /// the US layout. Unknown characters are returned unchanged.
+    pub fn remap_from(&self, keymap: &Keymap, char: char) -> char {
+        keymap
+            .position_of(char)
+            .and_then(|(scancode, modifiers)| self.translate(scancode, modifiers))
+            .unwrap_or(char)
+    }
+}
+
+/// The keyboard state tracks the modifier keys and the extended key prefix of a scancode stream and
+/// translates the scancodes of pressed keys into key codes.
+pub struct KeyboardState {
+    keymap: &'static Keymap,
+    modifiers: Modifiers,
+    extended: bool,
+}
+
+impl KeyboardState {
+    pub const fn new(keymap: &'static Keymap) -> Self {
+        Self {
+            keymap,
+            modifiers: Modifiers {
+                shift: false,
+                ctrl: false,
+                alt: false,
+                altgr: false,
+                caps_lock: false,
+                num_lock: false,
+                scroll_lock: false,
+            },
+            extended: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_keymap(&mut self, keymap: &'static Keymap) {
+        self.keymap = keymap;
+    }
+
+    #[inline]
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// This function sets the state of the lock keys, like after the LEDs were changed externally
+    pub fn set_lock_keys(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) {
+        self.modifiers.caps_lock = caps_lock;
+        self.modifiers.num_lock = num_lock;
+        self.modifiers.scroll_lock = scroll_lock;
+    }
+
+    /// This function processes the next byte of the scancode stream and returns the key code, if a
+    /// key was pressed.
+    pub fn process(&mut self, scancode: u8) -> Option<KeyCode> {
+        if scancode == SCANCODE_EXTENDED {
+            self.extended = true;
+            return None;
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let released = scancode & SCANCODE_RELEASE != 0;
+        let scancode = scancode & !SCANCODE_RELEASE;
+        match (extended, scancode) {
+            (false, SCANCODE_LEFT_SHIFT | SCANCODE_RIGHT_SHIFT) => self.modifiers.shift = !released,
+            (_, SCANCODE_CTRL) => self.modifiers.ctrl = !released,
+            (false, SCANCODE_ALT) => self.modifiers.alt = !released,
+            (true, SCANCODE_ALT) => self.modifiers.altgr = !released,
+            (false, SCANCODE_CAPS_LOCK) if !released => {
+                self.modifiers.caps_lock = !self.modifiers.caps_lock
+            }
+            (false, SCANCODE_NUM_LOCK) if !released => {
+                self.modifiers.num_lock = !self.modifiers.num_lock
+            }
+            (false, SCANCODE_SCROLL_LOCK) if !released => {
+                self.modifiers.scroll_lock = !self.modifiers.scroll_lock
+            }
+            (_, _) if released => {}
+            (true, scancode) => return extended_key(scancode),
+            (false, scancode) => return self.key(scancode),
+        }
+        None
+    }
+
+    fn key(&self, scancode: u8) -> Option<KeyCode> {
+        Some(match scancode {
+            0x01 => KeyCode::Escape,
+            0x0E => KeyCode::Backspace,
+            0x0F => KeyCode::Tab,
+            0x1C => KeyCode::Enter,
+            0x3B..=0x44 => KeyCode::Function(scancode - 0x3A),
+            0x57 | 0x58 => KeyCode::Function(scancode - 0x4C),
+            _ => return self.char_key(scancode),
+        })
+    }
+
+    /// This function translates the scancode with the keymap. Keys, which aren't defined by the
+    /// keymap, fall back to the US keymap and keys without character are returned as unknown key.
+    fn char_key(&self, scancode: u8) -> Option<KeyCode> {
+        if let Some(char) = self.keymap.translate(scancode, self.modifiers) {
+            return Some(KeyCode::Char(char));
+        }
+
+        // AltGr only produces the characters, which are defined by the keymap
+        if self.modifiers.altgr {
+            return None;
+        }
+        Some(
+            US.translate(scancode, self.modifiers)
+                .map_or(KeyCode::Unknown(scancode), KeyCode::Char),
+        )
+    }
+}
+
+fn extended_key(scancode: u8) -> Option<KeyCode> {
+    Some(match scancode {
+        0x1C => KeyCode::Enter,
+        0x47 => KeyCode::Home,
+        0x48 => KeyCode::Up,
+        0x49 => KeyCode::PageUp,
+        0x4B => KeyCode::Left,
+        0x4D => KeyCode::Right,
+        0x4F => KeyCode::End,
+        0x50 => KeyCode::Down,
+        0x51 => KeyCode::PageDown,
+        0x52 => KeyCode::Insert,
+        0x53 => KeyCode::Delete,
+        _ => return None,
+    })
+}
@@ -0,0 +1,219 @@
+//! A lightweight replacement for `core::fmt` in hot paths like exception handlers and the serial
+//! fast path. The format string is interpreted at runtime with a small subset of printf, so no
+//! formatting machinery or trait objects are generated for every call site.
+//!
+//! | Specifier | Argument                   | Example                      |
+//! |-----------|----------------------------|------------------------------|
+//! | `%d`      | Signed or unsigned integer | `-42`                        |
+//! | `%u`      | Unsigned integer           | `42`                         |
+//! | `%x` `%X` | Integer in hexadecimal     | `2a`, `2A`                   |
+//! | `%s`      | String                     | `text`                       |
+//! | `%c`      | Character                  | `c`                          |
+//! | `%%`      | -                          | `%`                          |
+//!
+//! A width (`%8d`) pads with spaces and a width with leading zero (`%016X`) pads with zeros. The
+//! precision of integers (`%.3u`) prints a fixed-point number with the specified number of decimal
+//! places, so `kwrite!(writer, "%.3u ms", 12345)` prints `12.345 ms`.
+
+/// The target of the formatted output
+pub trait KWrite {
+    fn write_bytes(&mut self, bytes: &[u8]);
+}
+
+/// An argument of the formatted output, which is created by the [kwrite] macro
+#[derive(Clone, Copy, Debug)]
+pub enum Arg<'a> {
+    Signed(i64),
+    Unsigned(u64),
+    Str(&'a str),
+    Char(char),
+}
+
+macro_rules! impl_from_integer {
+    ($variant:ident, $target:ty, $($integer:ty),*) => {
+        $(
+            impl From<$integer> for Arg<'_> {
+                #[inline]
+                fn from(value: $integer) -> Self {
+                    Self::$variant(value as $target)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_integer!(Signed, i64, i8, i16, i32, i64, isize);
+impl_from_integer!(Unsigned, u64, u8, u16, u32, u64, usize);
+
+impl<'a> From<&'a str> for Arg<'a> {
+    #[inline]
+    fn from(value: &'a str) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl From<char> for Arg<'_> {
+    #[inline]
+    fn from(value: char) -> Self {
+        Self::Char(value)
+    }
+}
+
+/// This macro writes the format string with the arguments into the writer. See the [module
+/// documentation](self) for the supported specifiers.
+///
+/// ```ignore
+/// libcore::kwrite!(&mut writer, "Fault at 0x%016X (code %u)\n", address, code);
+/// ```
+#[macro_export]
+macro_rules! kwrite {
+    ($writer:expr, $format:expr $(, $argument:expr)* $(,)?) => {
+        $crate::kfmt::write($writer, $format, &[$($crate::kfmt::Arg::from($argument)),*])
+    };
+}
+
+/// This function writes the format string with the arguments into the writer. Specifiers without
+/// argument or with an argument of the wrong kind are written literally.
+pub fn write(writer: &mut impl KWrite, format: &str, arguments: &[Arg]) {
+    let bytes = format.as_bytes();
+    let mut arguments = arguments.iter();
+    let mut literal_start = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] != b'%' {
+            index += 1;
+            continue;
+        }
+
+        writer.write_bytes(&bytes[literal_start..index]);
+        let specifier_start = index;
+        index += 1;
+
+        // Parse the flags, the width and the precision of the specifier
+        let zero_padded = bytes.get(index) == Some(&b'0');
+        let width = parse_number(bytes, &mut index);
+        let precision = match bytes.get(index) {
+            Some(b'.') => {
+                index += 1;
+                Some(parse_number(bytes, &mut index))
+            }
+            _ => None,
+        };
+        let Some(&conversion) = bytes.get(index) else {
+            writer.write_bytes(&bytes[specifier_start..]);
+            return;
+        };
+        index += 1;
+        literal_start = index;
+
+        let spec = Spec {
+            width,
+            zero_padded,
+            precision,
+        };
+        match (conversion, arguments.clone().next()) {
+            (b'%', _) => writer.write_bytes(b"%"),
+            (b'd', Some(Arg::Signed(value))) => {
+                write_integer(writer, *value < 0, value.unsigned_abs(), 10, false, spec)
+            }
+            (b'd' | b'u', Some(Arg::Unsigned(value))) => {
+                write_integer(writer, false, *value, 10, false, spec)
+            }
+            (b'x' | b'X', Some(Arg::Unsigned(value))) => {
+                write_integer(writer, false, *value, 16, conversion == b'X', spec)
+            }
+            (b'x' | b'X', Some(Arg::Signed(value))) => {
+                write_integer(writer, false, *value as u64, 16, conversion == b'X', spec)
+            }
+            (b's', Some(Arg::Str(value))) => write_padded(writer, value.as_bytes(), spec.width),
+            (b'c', Some(Arg::Char(value))) => {
+                let mut buffer = [0; 4];
+                write_padded(writer, value.encode_utf8(&mut buffer).as_bytes(), spec.width);
+            }
+            _ => {
+                writer.write_bytes(&bytes[specifier_start..index]);
+                continue;
+            }
+        }
+        if conversion != b'%' {
+            arguments.next();
+        }
+    }
+    writer.write_bytes(&bytes[literal_start..]);
+}
+
+#[derive(Clone, Copy)]
+struct Spec {
+    width: usize,
+    zero_padded: bool,
+    precision: Option<usize>,
+}
+
+fn parse_number(bytes: &[u8], index: &mut usize) -> usize {
+    let mut number = 0usize;
+    while let Some(digit) = bytes.get(*index).filter(|byte| byte.is_ascii_digit()) {
+        number = number
+            .saturating_mul(10)
+            .saturating_add((digit - b'0') as usize);
+        *index += 1;
+    }
+    number
+}
+
+/// This function writes the integer into a stack buffer. With a precision, the last digits are
+/// written as decimal places.
+fn write_integer(
+    writer: &mut impl KWrite, negative: bool, value: u64, radix: u64, uppercase: bool, spec: Spec,
+) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    // 20 digits, one decimal point, one sign and up to 20 leading zeros of the decimal places
+    let mut buffer = [0u8; 42];
+    let mut position = buffer.len();
+    let decimal_places = spec.precision.unwrap_or(0).min(20);
+    let mut value = value;
+    let mut digit_count = 0;
+    while value != 0 || digit_count <= decimal_places {
+        if decimal_places > 0 && digit_count == decimal_places {
+            position -= 1;
+            buffer[position] = b'.';
+        }
+        let digit = DIGITS[(value % radix) as usize];
+        position -= 1;
+        buffer[position] = if uppercase {
+            digit.to_ascii_uppercase()
+        } else {
+            digit
+        };
+        value /= radix;
+        digit_count += 1;
+    }
+
+    // The zeros are padded between the sign and the digits
+    let sign_length = negative as usize;
+    let digits_length = buffer.len() - position;
+    if spec.zero_padded {
+        let padding = spec.width.saturating_sub(sign_length + digits_length);
+        if negative {
+            writer.write_bytes(b"-");
+        }
+        for _ in 0..padding {
+            writer.write_bytes(b"0");
+        }
+        writer.write_bytes(&buffer[position..]);
+        return;
+    }
+
+    if negative {
+        position -= 1;
+        buffer[position] = b'-';
+    }
+    write_padded(writer, &buffer[position..], spec.width);
+}
+
+fn write_padded(writer: &mut impl KWrite, bytes: &[u8], width: usize) {
+    for _ in bytes.len()..width {
+        writer.write_bytes(b" ");
+    }
+    writer.write_bytes(bytes);
+}
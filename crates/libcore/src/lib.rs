@@ -1,6 +1,56 @@
 #![feature(pointer_is_aligned)]
 #![no_std]
 
+pub mod address;
+pub mod alloc_stats;
+pub mod alternatives;
+pub mod backtrace;
+pub mod boot_info;
+pub mod build_stamp;
+pub mod cmdline;
+pub mod config;
+pub mod cpu_features;
+pub mod descriptors;
+pub mod elf;
+pub mod error;
+pub mod fastmem;
+pub mod fdt;
+pub mod hexdump;
+pub mod initrd;
+pub mod keymap;
+pub mod kfmt;
+pub mod memory_map;
+pub mod module_abi;
+pub mod multiboot2;
+pub mod paging;
+pub mod pat;
+pub mod persistent_log;
+pub mod poison;
+pub mod port;
+pub mod registers;
+pub mod rng;
+#[cfg(not(feature = "std-test"))]
+pub mod stack_protector;
+#[cfg(feature = "std-test")] pub mod stress;
+pub mod table;
+pub mod tftp;
+pub mod trace;
+
+#[cfg(feature = "std-test")] extern crate std;
+
+use crate::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    alloc_stats::AllocationStats,
+    boot_info::{
+        FrameAllocatorHandoff,
+        ReservedRegion,
+    },
+    error::Error,
+    memory_map::MemoryMap,
+};
 use core::{
     alloc::{
         GlobalAlloc,
@@ -9,12 +59,15 @@ use core::{
     cell::RefCell,
     slice,
 };
-use libcpu::MemoryAddress;
 use uefi::table::boot::{
     MemoryDescriptor,
-    MemoryMap,
+    MemoryType,
 };
 
+/// The end of the low memory, which contains the real-mode memory (like the IVT and the BDA). The
+/// frame table is never placed into it.
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
 pub struct FrameTable<'a> {
     pub frame_table: &'a mut [u8],
 }
@@ -22,140 +75,390 @@ pub struct FrameTable<'a> {
 impl FrameTable<'_> {
     pub fn toggle_frame_alloc_status(&mut self, page_index: usize) {
         let frame_block_index = page_index % 8;
-        let frame_table_index = page_index - frame_block_index;
+        let frame_table_index = page_index / 8;
         if let Some(value) = self.frame_table.get_mut(frame_table_index) {
             *value ^= (1 << frame_block_index);
         }
     }
 
+    /// This function marks the specified frame as allocated. Unlike toggling the status, marking a
+    /// frame multiple times keeps it allocated.
+    pub fn set_frame_allocated(&mut self, page_index: usize) {
+        let frame_block_index = page_index % 8;
+        let frame_table_index = page_index / 8;
+        if let Some(value) = self.frame_table.get_mut(frame_table_index) {
+            *value |= 1 << frame_block_index;
+        }
+    }
+
+    /// This function marks the specified frame as free
+    pub fn clear_frame_allocated(&mut self, page_index: usize) {
+        let frame_block_index = page_index % 8;
+        let frame_table_index = page_index / 8;
+        if let Some(value) = self.frame_table.get_mut(frame_table_index) {
+            *value &= !(1 << frame_block_index);
+        }
+    }
+
     pub fn page_allocated(&mut self, page_index: usize) -> bool {
         let frame_block_index = page_index % 8;
-        let frame_table_index = page_index - frame_block_index;
+        let frame_table_index = page_index / 8;
         if let Some(value) = self.frame_table.get_mut(frame_table_index) {
-            return (*value & (1 << frame_block_index)) == 1;
+            return (*value & (1 << frame_block_index)) != 0;
         }
         false
     }
 }
 
 pub struct FrameAllocator<'a> {
-    pub start_address: MemoryAddress,
-    pub stop_address: MemoryAddress,
+    pub start_address: PhysAddr,
+    pub stop_address: PhysAddr,
     pub page_size: u16,
     pub frame_table: RefCell<FrameTable<'a>>,
+    /// The table with a bit per frame, which is set while the frame is free and poisoned. It's only
+    /// used in debug builds and empty in release builds.
+    pub poison_table: RefCell<FrameTable<'a>>,
+    pub stats: AllocationStats,
 }
 
 unsafe impl GlobalAlloc for FrameAllocator<'_> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let base_page_count = (layout.size() / self.page_size as usize).max(1);
-        let pages = base_page_count
-            + if (layout.size() - base_page_count) % self.page_size as usize != 0 {
-                1
-            } else {
-                0
-            };
-
-        match self.find_first_frame_index(pages) {
-            None => panic!("No pages available!"),
-            Some(index) => {
-                for i in 0..pages {
-                    self.frame_table
-                        .borrow_mut()
-                        .toggle_frame_alloc_status(index + i);
-                }
-                (self.start_address + (index * 4096) as MemoryAddress) as *mut u8
+        let pages = self.page_count(&layout);
+        match self.find_first_frame_index(pages, layout.align()) {
+            None => {
+                self.stats.record_failure(pages * self.page_size as usize);
+                core::ptr::null_mut()
             }
+            Some(index) => self.allocate_frames(index, pages).to_virt().as_mut_ptr(),
         }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let base_page_count = (layout.size() / self.page_size as usize).max(1);
-        let pages = base_page_count
-            + if (layout.size() - base_page_count) % self.page_size as usize != 0 {
-                1
-            } else {
-                0
-            };
+        let pages = self.page_count(&layout);
 
-        let address = ptr as MemoryAddress;
+        let address = VirtAddr::from_ptr(ptr)
+            .physmap_to_phys()
+            .expect("Freed memory isn't located in the physmap");
+        if address < self.start_address
+            || address >= self.stop_address
+            || (address - self.start_address) % 4096 != 0
+        {
+            panic!(
+                "Invalid free of 0x{:X} (expected the start of a frame between 0x{:X} and 0x{:X})",
+                address, self.start_address, self.stop_address
+            );
+        }
 
         let page_index = ((address - self.start_address) / 4096) as usize;
         let mut frame_table = self.frame_table.borrow_mut();
         for i in 0..pages {
-            if !frame_table.page_allocated((page_index + i)) {
+            if !frame_table.page_allocated(page_index + i) {
                 panic!(
-                    "Page Fault - Free (PI: {}, CPC: {}, PC: {}, MA: 0x{:X}, SA: 0x{:X}, AO: 0x{:X})",
-                    page_index,
-                    i,
+                    "Double free of frame 0x{:X} (frame {} of {} of the allocation at 0x{:X}, \
+                     expected an allocated frame but the frame is free)",
+                    address + (i * 4096) as u64,
+                    i + 1,
                     pages,
-                    address,
-                    self.start_address,
-                    address - self.start_address
+                    address
                 );
             }
 
             frame_table.toggle_frame_alloc_status(page_index + i);
         }
+        self.stats.record_free(pages * self.page_size as usize);
+
+        // Poison the freed frames, so writes after the free are detected on the next allocation
+        if poison::POISON_ENABLED {
+            poison::poison(slice::from_raw_parts_mut(ptr, pages * 4096));
+            let mut poison_table = self.poison_table.borrow_mut();
+            for i in 0..pages {
+                poison_table.set_frame_allocated(page_index + i);
+            }
+        }
+    }
+}
+
+impl FrameAllocator<'static> {
+    /// This function re-creates the frame allocator from the state, which was handed over by the
+    /// bootloader. The frame table isn't rebuilt, so all frames, which were allocated or reserved
+    /// by the bootloader, stay allocated. If no frame table was handed over, `None` is returned.
+    pub fn from_handoff(handoff: &FrameAllocatorHandoff) -> Option<Self> {
+        if handoff.frame_table_address.is_null() || handoff.frame_table_size == 0 {
+            return None;
+        }
+
+        let frame_table = unsafe {
+            slice::from_raw_parts_mut(
+                handoff.frame_table_address.to_virt().as_mut_ptr(),
+                handoff.frame_table_size as usize,
+            )
+        };
+        // Without the poison table of the bootloader, the frames freed by it aren't checked
+        let poison_table: &mut [u8] =
+            if poison::POISON_ENABLED && !handoff.poison_table_address.is_null() {
+                unsafe {
+                    slice::from_raw_parts_mut(
+                        handoff.poison_table_address.to_virt().as_mut_ptr(),
+                        handoff.frame_table_size as usize,
+                    )
+                }
+            } else {
+                &mut []
+            };
+        Some(Self {
+            start_address: handoff.start_address,
+            stop_address: handoff.stop_address,
+            page_size: handoff.page_size as u16,
+            frame_table: RefCell::new(FrameTable { frame_table }),
+            poison_table: RefCell::new(FrameTable {
+                frame_table: poison_table,
+            }),
+            stats: AllocationStats::new(),
+        })
     }
 }
 
 impl FrameAllocator<'_> {
-    pub fn new(memory_map: &MemoryMap, page_size: u16) -> Self {
-        let table_size = (memory_map
+    /// This function creates the frame allocator for the memory of the memory map. The frame table
+    /// (and the poison table in debug builds) is placed into the first conventional memory above the
+    /// low memory, which is large enough, so it doesn't overlap the memory of the firmware or the
+    /// memory allocated by the bootloader. The frames of the tables are reserved. If no conventional
+    /// memory is large enough, this function returns an error.
+    pub fn new(memory_map: &MemoryMap, page_size: u16) -> Result<Self, Error> {
+        let table_size = memory_map
             .entries()
             .map(|desc| desc.page_count)
             .sum::<u64>()
-            >> 3);
-        let frame_table = unsafe { slice::from_raw_parts_mut(0x0001 as *mut _, table_size as usize) };
+            >> 3;
+        // In debug builds, the poison table with the same size follows the frame table
+        let poison_table_size = if poison::POISON_ENABLED {
+            table_size
+        } else {
+            0
+        };
+
+        let tables_size = table_size + poison_table_size;
+        let table_address = memory_map
+            .entries()
+            .filter(|descriptor| descriptor.ty == MemoryType::CONVENTIONAL)
+            .find_map(|descriptor| {
+                let start = descriptor.phys_start.max(LOW_MEMORY_END);
+                let end = descriptor.phys_start + descriptor.page_count * 4096;
+                (start + tables_size <= end).then(|| PhysAddr::new(start))
+            })
+            .ok_or(Error::NoFrameTableMemory(tables_size))?;
+
+        let frame_table = unsafe {
+            slice::from_raw_parts_mut(table_address.to_virt().as_mut_ptr(), table_size as usize)
+        };
         frame_table.fill(0);
+        let poison_table = unsafe {
+            slice::from_raw_parts_mut(
+                (table_address + table_size).to_virt().as_mut_ptr(),
+                poison_table_size as usize,
+            )
+        };
+        poison_table.fill(0);
 
-        let allocator = Self {
-            start_address: table_size + 1,
+        let mut allocator = Self {
+            // The first frame isn't managed, so no allocation returns the null address
+            start_address: PhysAddr::new(page_size as u64),
             stop_address: {
                 let last_descriptor = memory_map.entries().last().unwrap();
-                last_descriptor.phys_start + (last_descriptor.page_count * 4096)
+                PhysAddr::new(last_descriptor.phys_start + (last_descriptor.page_count * 4096))
             },
             page_size,
             frame_table: RefCell::new(FrameTable { frame_table }),
+            poison_table: RefCell::new(FrameTable {
+                frame_table: poison_table,
+            }),
+            stats: AllocationStats::new(),
         };
-
-        allocator
+        allocator.reserve_region(table_address, tables_size.div_ceil(page_size as u64));
+        Ok(allocator)
     }
 
+    /// This function reserves the frames of the memory described by the descriptor. Frames, which
+    /// are already reserved, stay reserved.
     pub fn reserve_memory_section(&mut self, descriptor: &MemoryDescriptor) {
-        let pages = (descriptor.page_count * 4096) / self.page_size as u64;
-        let start_page_index = descriptor.virt_start / 4096;
+        self.reserve_range(descriptor.phys_start, descriptor.page_count * 4096);
+    }
 
-        for i in 0..pages {
+    /// This function reserves the frames of a region, which was allocated before the Boot Services
+    /// were exited. Frames, which are already reserved, stay reserved.
+    pub fn reserve_region(&mut self, address: PhysAddr, page_count: u64) {
+        self.reserve_range(address.as_u64(), page_count * 4096);
+    }
+
+    /// This function marks every frame, which overlaps the specified range, as allocated. The frames
+    /// are indexed relative to the start address like in the allocations, so the parts of the range
+    /// outside of the managed memory are ignored.
+    fn reserve_range(&mut self, address: u64, size: u64) {
+        let page_size = self.page_size as u64;
+        let start = address.max(self.start_address.as_u64());
+        let end = address.saturating_add(size).min(self.stop_address.as_u64());
+        if start >= end {
+            return;
+        }
+
+        let frame_table = self.frame_table.get_mut();
+        let first_index = ((start - self.start_address.as_u64()) / page_size) as usize;
+        let end_index = ((end - self.start_address.as_u64()).div_ceil(page_size) as usize)
+            .min(frame_table.frame_table.len() * 8);
+        for index in first_index..end_index {
+            frame_table.set_frame_allocated(index);
+        }
+
+        // The reserved frames are used by their owner, so they aren't poisoned anymore
+        let poison_table = self.poison_table.get_mut();
+        for index in first_index..end_index {
+            poison_table.clear_frame_allocated(index);
+        }
+    }
+
+    /// This function returns the index of the first frame of the first free run with the specified
+    /// number of frames, whose address has the specified alignment. The run can span multiple
+    /// blocks of the frame table.
+    #[inline]
+    pub fn find_first_frame_index(&self, page_count: usize, alignment: usize) -> Option<usize> {
+        self.find_frame_run_below(page_count, alignment, self.stop_address)
+    }
+
+    /// This function returns the index of the first frame of the first free run like
+    /// [FrameAllocator::find_first_frame_index], but the run has to end at or below the specified
+    /// address. This is used for devices, which can only address a part of the physical memory.
+    pub fn find_frame_run_below(
+        &self, page_count: usize, alignment: usize, max_address: PhysAddr,
+    ) -> Option<usize> {
+        if max_address <= self.start_address {
+            return None;
+        }
+
+        let frame_table = &self.frame_table.borrow().frame_table;
+        let frame_limit = ((max_address - self.start_address) / self.page_size as u64) as usize;
+        let frame_count = self
+            .available_frames()
+            .min(frame_table.len() * 8)
+            .min(frame_limit);
+        let allocated = |index: usize| frame_table[index / 8] & (1 << (index % 8)) != 0;
+        let alignment = alignment.max(self.page_size as usize) as u64;
+
+        let mut index = 0;
+        loop {
+            // Move the start of the run to the next frame with the alignment
+            let address =
+                (self.start_address + (index * self.page_size as usize) as u64).align_up(alignment);
+            index = ((address - self.start_address) / self.page_size as u64) as usize;
+            if index + page_count > frame_count {
+                return None;
+            }
+
+            // Continue after the last allocated frame of the run, if the run isn't free
+            match (index..index + page_count)
+                .rev()
+                .find(|frame| allocated(*frame))
+            {
+                Some(allocated_index) => index = allocated_index + 1,
+                None => return Some(index),
+            }
+        }
+    }
+
+    /// This function allocates the specified number of physically contiguous frames, which end at or
+    /// below the specified address and whose first frame has the specified alignment. The frames
+    /// have to be freed with [FrameAllocator::free_contiguous].
+    pub fn alloc_contiguous(
+        &self, page_count: usize, max_address: PhysAddr, alignment: u64,
+    ) -> Option<PhysAddr> {
+        let Some(index) = self.find_frame_run_below(page_count, alignment as usize, max_address)
+        else {
+            self.stats
+                .record_failure(page_count * self.page_size as usize);
+            return None;
+        };
+        Some(self.allocate_frames(index, page_count))
+    }
+
+    /// This function frees the frames, which were allocated with [FrameAllocator::alloc_contiguous]
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the frames aren't used anymore.
+    pub unsafe fn free_contiguous(&self, address: PhysAddr, page_count: usize) {
+        let page_size = self.page_size as usize;
+        let layout = Layout::from_size_align_unchecked(page_count * page_size, page_size);
+        self.dealloc(address.to_virt().as_mut_ptr(), layout);
+    }
+
+    /// This function marks the run of frames, which starts at the specified index, as allocated and
+    /// returns the address of the first frame
+    fn allocate_frames(&self, index: usize, page_count: usize) -> PhysAddr {
+        for i in 0..page_count {
             self.frame_table
                 .borrow_mut()
-                .toggle_frame_alloc_status((start_page_index + i) as usize);
+                .toggle_frame_alloc_status(index + i);
+        }
+        let address = self.start_address + (index * 4096) as u64;
+        if poison::POISON_ENABLED {
+            self.verify_poison(index, page_count);
         }
+        self.stats
+            .record_allocation(page_count * self.page_size as usize);
+        address
     }
 
-    pub fn find_first_frame_index(&self, page_count: usize) -> Option<usize> {
-        let frame_table = &self.frame_table.borrow().frame_table;
+    /// This function returns the number of frames, which are needed for the specified layout
+    #[inline]
+    fn page_count(&self, layout: &Layout) -> usize {
+        layout.size().max(1).div_ceil(self.page_size as usize)
+    }
 
-        for i in 0..frame_table.len() {
-            let block = frame_table.get(i).unwrap();
-            if block.count_ones() == 8 {
+    /// This function verifies the poison pattern of the specified frames, which were just allocated,
+    /// and clears their bits in the poison table. Only frames, which are marked in the poison table,
+    /// were freed before. Frames, which were never allocated, contain the initial memory content and
+    /// aren't checked.
+    fn verify_poison(&self, index: usize, page_count: usize) {
+        let mut poison_table = self.poison_table.borrow_mut();
+        for i in 0..page_count {
+            if !poison_table.page_allocated(index + i) {
                 continue;
             }
+            poison_table.clear_frame_allocated(index + i);
 
-            let mut counter = 0;
-            for bit in 0..8 {
-                if *block & (1 << bit) == 0 {
-                    counter += 1;
-                } else {
-                    counter = 0;
-                }
+            let frame_address = self.start_address + ((index + i) * 4096) as u64;
+            let frame = unsafe { slice::from_raw_parts(frame_address.to_virt().as_ptr(), 4096) };
 
-                if counter >= page_count {
-                    return Some(i * 8 + bit);
-                }
+            if let Some(offset) = poison::find_corruption(frame) {
+                panic!(
+                    "Write after free in frame 0x{:X} at offset 0x{:X} (expected poison 0x{:02X}, \
+                     found 0x{:02X})",
+                    frame_address,
+                    offset,
+                    poison::POISON_BYTE,
+                    frame[offset]
+                );
             }
         }
-        None
+    }
+
+    /// This function creates the handoff state of this frame allocator with the specified reserved
+    /// regions. The frame table and the regions must stay reserved until the receiver took them
+    /// over.
+    pub fn handoff(&self, reserved_regions: &[ReservedRegion]) -> FrameAllocatorHandoff {
+        let frame_table = &self.frame_table.borrow().frame_table;
+        let poison_table = &self.poison_table.borrow().frame_table;
+        FrameAllocatorHandoff {
+            frame_table_address: PhysAddr::new(frame_table.as_ptr() as u64),
+            frame_table_size: frame_table.len() as u64,
+            poison_table_address: match poison_table.is_empty() {
+                true => PhysAddr::NULL,
+                false => PhysAddr::new(poison_table.as_ptr() as u64),
+            },
+            start_address: self.start_address,
+            stop_address: self.stop_address,
+            page_size: self.page_size as u64,
+            reserved_regions_address: PhysAddr::new(reserved_regions.as_ptr() as u64),
+            reserved_region_count: reserved_regions.len() as u64,
+        }
     }
 
     #[inline]
@@ -174,6 +477,20 @@ impl FrameAllocator<'_> {
         count
     }
 
+    /// This function returns the number of free frames, which start at or above the specified
+    /// address. This is used to verify, that no allocation returns memory above a limit.
+    pub fn free_frames_above(&self, address: PhysAddr) -> usize {
+        let frame_table = &self.frame_table.borrow().frame_table;
+        let frame_count = self.available_frames().min(frame_table.len() * 8);
+        let first_index = match address.as_u64().checked_sub(self.start_address.as_u64()) {
+            Some(offset) => (offset.div_ceil(self.page_size as u64) as usize).min(frame_count),
+            None => 0,
+        };
+        (first_index..frame_count)
+            .filter(|index| frame_table[index / 8] & (1 << (index % 8)) == 0)
+            .count()
+    }
+
     #[inline]
     pub fn remaining_frames(&self) -> usize {
         self.available_frames() - self.allocated_frames()
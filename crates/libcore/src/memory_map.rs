@@ -0,0 +1,88 @@
+//! The memory map is retrieved from the firmware while exiting the Boot Services and handed over to
+//! the kernel. The descriptors are stored with the descriptor size of the firmware, which can be
+//! larger than [MemoryDescriptor], so the map is walked with this size as stride.
+
+use crate::error::Error;
+use uefi::table::boot::MemoryDescriptor;
+
+/// The final memory map of the firmware
+pub struct MemoryMap<'a> {
+    data: &'a mut [u8],
+    descriptor_size: usize,
+}
+
+impl<'a> MemoryMap<'a> {
+    /// This function creates the memory map from the buffer, which was filled by `GetMemoryMap`. If
+    /// the descriptor size is smaller than [MemoryDescriptor], this function returns an error.
+    pub fn new(data: &'a mut [u8], descriptor_size: usize) -> Result<Self, Error> {
+        if descriptor_size < core::mem::size_of::<MemoryDescriptor>() {
+            return Err(Error::InvalidMemoryDescriptorSize(descriptor_size));
+        }
+
+        let length = data.len() - data.len() % descriptor_size;
+        Ok(Self {
+            data: &mut data[..length],
+            descriptor_size,
+        })
+    }
+
+    /// This function sorts the descriptors by their physical address. The firmware doesn't
+    /// guarantee any order, but the frame allocator expects the last descriptor at the end of the
+    /// physical memory.
+    pub fn sort(&mut self) {
+        for index in 1..self.len() {
+            let mut current = index;
+            while current > 0
+                && self.descriptor(current - 1).phys_start > self.descriptor(current).phys_start
+            {
+                let (previous, next) = self.data.split_at_mut(current * self.descriptor_size);
+                previous[(current - 1) * self.descriptor_size..]
+                    .swap_with_slice(&mut next[..self.descriptor_size]);
+                current -= 1;
+            }
+        }
+    }
+
+    /// This function returns the descriptors in the order of the buffer
+    pub fn entries(&self) -> impl Iterator<Item = &MemoryDescriptor> + Clone {
+        self.data
+            .chunks_exact(self.descriptor_size)
+            .map(|descriptor| unsafe { &*(descriptor.as_ptr() as *const MemoryDescriptor) })
+    }
+
+    /// This function returns the mutable descriptors in the order of the buffer, so the virtual
+    /// addresses can be assigned before `SetVirtualAddressMap`
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut MemoryDescriptor> {
+        self.data
+            .chunks_exact_mut(self.descriptor_size)
+            .map(|descriptor| unsafe { &mut *(descriptor.as_mut_ptr() as *mut MemoryDescriptor) })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len() / self.descriptor_size
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    #[inline]
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data
+    }
+
+    #[inline]
+    fn descriptor(&self, index: usize) -> &MemoryDescriptor {
+        let offset = index * self.descriptor_size;
+        unsafe {
+            &*(self.data[offset..offset + self.descriptor_size].as_ptr() as *const MemoryDescriptor)
+        }
+    }
+}
@@ -0,0 +1,135 @@
+/// The version of the kernel module ABI. This version must be incremented, when the signature or the
+/// semantics of an exported kernel symbol is changed. Modules, which were built against another ABI
+/// version, are refused by the kernel module loader.
+pub const KERNEL_ABI_VERSION: u32 = 1;
+
+/// An exported symbol is placed by the `export_symbol!` macro of the kernel into the
+/// `kernel_symbols` section. The ABI hash binds the symbol name to the signature and the ABI version
+/// it was exported with.
+#[repr(C)]
+pub struct ExportedSymbol {
+    pub name: &'static str,
+    pub address: *const (),
+    pub abi_hash: u64,
+}
+
+unsafe impl Sync for ExportedSymbol {}
+
+/// This function calculates the FNV-1a hash over the symbol name, the signature and the ABI version.
+/// The kernel hashes the signature of every exported symbol and the module hashes the signature of
+/// every imported symbol, so a module, which expects another signature, is refused per symbol.
+pub const fn symbol_abi_hash(name: &str, signature: &str) -> u64 {
+    let hash = fnv1a_hash(0xCBF2_9CE4_8422_2325, name.as_bytes());
+    let hash = fnv1a_hash(hash, &[0]);
+    let hash = fnv1a_hash(hash, signature.as_bytes());
+    fnv1a_hash(hash, &KERNEL_ABI_VERSION.to_le_bytes())
+}
+
+const fn fnv1a_hash(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut index = 0;
+    while index < bytes.len() {
+        hash = (hash ^ bytes[index] as u64).wrapping_mul(0x100_0000_01B3);
+        index += 1;
+    }
+    hash
+}
+
+/// This function converts the specified string into a byte array for the `.modinfo` section
+pub const fn module_info_entry<const N: usize>(entry: &str) -> [u8; N] {
+    let mut buffer = [0u8; N];
+    let bytes = entry.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() && index < N {
+        buffer[index] = bytes[index];
+        index += 1;
+    }
+    buffer
+}
+
+/// This function creates the null-terminated `abi=<version>` entry of the `.modinfo` section for
+/// the current kernel ABI version.
+pub const fn module_abi_entry() -> [u8; 16] {
+    let mut buffer = module_info_entry::<16>("abi=");
+    let mut digits = [0u8; 10];
+    let mut digit_count = 0;
+    let mut version = KERNEL_ABI_VERSION;
+    loop {
+        digits[digit_count] = b'0' + (version % 10) as u8;
+        digit_count += 1;
+        version /= 10;
+        if version == 0 {
+            break;
+        }
+    }
+
+    let mut index = 0;
+    while index < digit_count {
+        buffer[4 + index] = digits[digit_count - index - 1];
+        index += 1;
+    }
+    buffer
+}
+
+/// This function creates the null-terminated `import=<name>:<hash>` entry of the `.modinfo` section.
+/// The prefix contains `import=<name>:` and the hash is appended with 16 hexadecimal digits.
+pub const fn module_import_entry<const N: usize>(prefix: &str, abi_hash: u64) -> [u8; N] {
+    let mut buffer = module_info_entry::<N>(prefix);
+    let mut index = 0;
+    while index < 16 {
+        let digit = (abi_hash >> ((15 - index) * 4)) as u8 & 0xF;
+        buffer[prefix.len() + index] = match digit {
+            0..=9 => b'0' + digit,
+            _ => b'a' + digit - 10,
+        };
+        index += 1;
+    }
+    buffer
+}
+
+/// This macro declares the name and version of a kernel module and the kernel ABI version the module
+/// is built against. Every module must use this macro exactly once.
+///
+/// ```ignore
+/// libcore::module_info!("example", "1.0.0");
+/// ```
+#[macro_export]
+macro_rules! module_info {
+    ($name:literal, $version:literal) => {
+        #[used]
+        #[link_section = ".modinfo"]
+        static MODULE_NAME: [u8; concat!("name=", $name, "\0").len()] =
+            $crate::module_abi::module_info_entry(concat!("name=", $name, "\0"));
+
+        #[used]
+        #[link_section = ".modinfo"]
+        static MODULE_VERSION: [u8; concat!("version=", $version, "\0").len()] =
+            $crate::module_abi::module_info_entry(concat!("version=", $version, "\0"));
+
+        #[used]
+        #[link_section = ".modinfo"]
+        static MODULE_ABI: [u8; 16] = $crate::module_abi::module_abi_entry();
+    };
+}
+
+/// This macro declares a kernel symbol, which is used by the module, with its signature. The kernel
+/// refuses to resolve the symbol, if it was exported with another signature. Every kernel symbol,
+/// which is used by the module, must be declared with this macro.
+///
+/// ```ignore
+/// libcore::module_import!(kernel_alloc: extern "C" fn(usize, usize) -> *mut u8);
+/// ```
+#[macro_export]
+macro_rules! module_import {
+    ($symbol:ident: $signature:ty) => {
+        const _: () = {
+            const PREFIX: &str = concat!("import=", stringify!($symbol), ":");
+
+            #[used]
+            #[link_section = ".modinfo"]
+            static MODULE_IMPORT: [u8; PREFIX.len() + 17] = $crate::module_abi::module_import_entry(
+                PREFIX,
+                $crate::module_abi::symbol_abi_hash(stringify!($symbol), stringify!($signature)),
+            );
+        };
+    };
+}
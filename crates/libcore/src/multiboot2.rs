@@ -0,0 +1,242 @@
+//! Multiboot2 is the boot protocol of GRUB, which is implemented by many hobby kernels. The kernel
+//! contains a header with tags, which describe how the kernel is loaded and which information it
+//! requests. The bootloader passes the information as a list of tags, which are aligned to 8 bytes.
+
+use crate::error::Error;
+
+/// The magic of the Multiboot2 header and the magic, which is passed to the kernel in `eax`
+pub const HEADER_MAGIC: u32 = 0xE852_50D6;
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+/// The header must be 8-byte aligned within the first 32 KiB of the kernel
+const HEADER_SEARCH_SIZE: usize = 32768;
+const HEADER_ALIGN: usize = 8;
+const HEADER_SIZE: usize = 16;
+const ARCHITECTURE_I386: u32 = 0;
+
+const HEADER_TAG_END: u16 = 0;
+const HEADER_TAG_INFORMATION_REQUEST: u16 = 1;
+const HEADER_TAG_ADDRESS: u16 = 2;
+const HEADER_TAG_ENTRY_ADDRESS: u16 = 3;
+const HEADER_TAG_CONSOLE_FLAGS: u16 = 4;
+const HEADER_TAG_FRAMEBUFFER: u16 = 5;
+const HEADER_TAG_MODULE_ALIGN: u16 = 6;
+const HEADER_TAG_RELOCATABLE: u16 = 10;
+
+/// Header tags with this flag can be ignored by the bootloader
+const HEADER_TAG_OPTIONAL: u16 = 1;
+
+pub const INFO_TAG_END: u32 = 0;
+pub const INFO_TAG_COMMAND_LINE: u32 = 1;
+pub const INFO_TAG_BOOTLOADER_NAME: u32 = 2;
+pub const INFO_TAG_MODULE: u32 = 3;
+pub const INFO_TAG_BASIC_MEMORY: u32 = 4;
+pub const INFO_TAG_MEMORY_MAP: u32 = 6;
+pub const INFO_TAG_FRAMEBUFFER: u32 = 8;
+pub const INFO_TAG_EFI64_SYSTEM_TABLE: u32 = 12;
+pub const INFO_TAG_ACPI_OLD: u32 = 14;
+pub const INFO_TAG_ACPI_NEW: u32 = 15;
+pub const INFO_TAG_LOAD_BASE_ADDRESS: u32 = 21;
+
+/// The information tags, which are passed by the bootloader. Kernels, which require other tags, are
+/// rejected.
+const SUPPORTED_INFO_TAGS: &[u32] = &[
+    INFO_TAG_COMMAND_LINE,
+    INFO_TAG_BOOTLOADER_NAME,
+    INFO_TAG_MODULE,
+    INFO_TAG_BASIC_MEMORY,
+    INFO_TAG_MEMORY_MAP,
+    INFO_TAG_FRAMEBUFFER,
+    INFO_TAG_EFI64_SYSTEM_TABLE,
+    INFO_TAG_ACPI_OLD,
+    INFO_TAG_ACPI_NEW,
+    INFO_TAG_LOAD_BASE_ADDRESS,
+];
+
+/// The types of the entries of the memory map tag
+pub const MEMORY_AVAILABLE: u32 = 1;
+pub const MEMORY_RESERVED: u32 = 2;
+pub const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+pub const MEMORY_NVS: u32 = 4;
+pub const MEMORY_BAD: u32 = 5;
+
+/// The load addresses of kernels, which aren't ELF files (like flat binaries)
+#[derive(Clone, Copy, Debug)]
+pub struct AddressTag {
+    /// The address of the header, which is used to calculate the file offset of the load address
+    pub header_address: u32,
+    pub load_address: u32,
+    /// The end of the loaded data or zero, if the rest of the file is loaded
+    pub load_end_address: u32,
+    /// The end of the zeroed memory after the loaded data or zero, if there's no BSS
+    pub bss_end_address: u32,
+}
+
+/// The header of a Multiboot2 kernel with the tags, which are supported by the bootloader
+#[derive(Clone, Copy, Debug)]
+pub struct Multiboot2Header {
+    /// The offset of the header in the kernel file
+    pub offset: usize,
+    pub address: Option<AddressTag>,
+    pub entry_address: Option<u32>,
+    /// The preferred width, height and depth of the framebuffer (zero means no preference)
+    pub framebuffer: Option<(u32, u32, u32)>,
+}
+
+impl Multiboot2Header {
+    /// This function searches the Multiboot2 header in the first 32 KiB of the kernel and parses the
+    /// tags. If a tag or an information, which is required by the kernel, isn't supported by the
+    /// bootloader, this function returns an error.
+    pub fn find(data: &[u8]) -> Result<Self, Error> {
+        let offset = (0..data.len().min(HEADER_SEARCH_SIZE))
+            .step_by(HEADER_ALIGN)
+            .find(|offset| read_u32(data, *offset) == Some(HEADER_MAGIC))
+            .ok_or(Error::NoMultiboot2Header)?;
+
+        let architecture = read_u32(data, offset + 4).ok_or(Error::InvalidMultiboot2Header)?;
+        let length = read_u32(data, offset + 8).ok_or(Error::InvalidMultiboot2Header)?;
+        let checksum = read_u32(data, offset + 12).ok_or(Error::InvalidMultiboot2Header)?;
+        if architecture != ARCHITECTURE_I386
+            || HEADER_MAGIC
+                .wrapping_add(architecture)
+                .wrapping_add(length)
+                .wrapping_add(checksum)
+                != 0
+        {
+            return Err(Error::InvalidMultiboot2Header);
+        }
+
+        let end = offset + length as usize;
+        let mut header = Self {
+            offset,
+            address: None,
+            entry_address: None,
+            framebuffer: None,
+        };
+        let mut tag_offset = offset + HEADER_SIZE;
+        while tag_offset + 8 <= end {
+            let tag_type = read_u16(data, tag_offset).ok_or(Error::InvalidMultiboot2Header)?;
+            let flags = read_u16(data, tag_offset + 2).ok_or(Error::InvalidMultiboot2Header)?;
+            let size = read_u32(data, tag_offset + 4).ok_or(Error::InvalidMultiboot2Header)? as usize;
+            if size < 8 || tag_offset + size > end {
+                return Err(Error::InvalidMultiboot2Header);
+            }
+
+            let field = |index: usize| {
+                read_u32(data, tag_offset + 8 + index * 4).ok_or(Error::InvalidMultiboot2Header)
+            };
+            let optional = flags & HEADER_TAG_OPTIONAL != 0;
+            match tag_type {
+                HEADER_TAG_END => break,
+                HEADER_TAG_INFORMATION_REQUEST if !optional => {
+                    for index in 0..(size - 8) / 4 {
+                        let request = field(index)?;
+                        if !SUPPORTED_INFO_TAGS.contains(&request) {
+                            return Err(Error::UnsupportedMultiboot2Request(request));
+                        }
+                    }
+                }
+                HEADER_TAG_ADDRESS => {
+                    header.address = Some(AddressTag {
+                        header_address: field(0)?,
+                        load_address: field(1)?,
+                        load_end_address: field(2)?,
+                        bss_end_address: field(3)?,
+                    })
+                }
+                HEADER_TAG_ENTRY_ADDRESS => header.entry_address = Some(field(0)?),
+                HEADER_TAG_FRAMEBUFFER => {
+                    header.framebuffer = Some((field(0)?, field(1)?, field(2)?))
+                }
+
+                // The console is always a framebuffer, modules are page-aligned and relocatable
+                // kernels can be loaded at their preferred address
+                HEADER_TAG_INFORMATION_REQUEST
+                | HEADER_TAG_CONSOLE_FLAGS
+                | HEADER_TAG_MODULE_ALIGN
+                | HEADER_TAG_RELOCATABLE => {}
+                _ if optional => {}
+                _ => return Err(Error::UnsupportedMultiboot2Tag(tag_type)),
+            }
+            tag_offset += (size + 7) & !7;
+        }
+        Ok(header)
+    }
+}
+
+/// The writer, which encodes the Multiboot2 information tags into a buffer. Every tag is started
+/// with [InfoWriter::begin_tag], filled with [InfoWriter::write] and ended with
+/// [InfoWriter::end_tag].
+pub struct InfoWriter<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    tag_start: usize,
+}
+
+impl<'a> InfoWriter<'a> {
+    /// This function creates the writer. The total size is written by [InfoWriter::finish].
+    pub fn new(buffer: &'a mut [u8]) -> Result<Self, Error> {
+        if buffer.len() < 8 {
+            return Err(Error::Multiboot2InfoTooLarge(8));
+        }
+
+        buffer[..8].fill(0);
+        Ok(Self {
+            buffer,
+            length: 8,
+            tag_start: 8,
+        })
+    }
+
+    pub fn begin_tag(&mut self, tag_type: u32) -> Result<(), Error> {
+        self.tag_start = self.length;
+        self.write(&tag_type.to_le_bytes())?;
+        self.write(&0u32.to_le_bytes())
+    }
+
+    /// This function appends the data to the current tag. If the buffer is too small, this function
+    /// returns an error.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.length + data.len();
+        self.buffer
+            .get_mut(self.length..end)
+            .ok_or(Error::Multiboot2InfoTooLarge(end))?
+            .copy_from_slice(data);
+        self.length = end;
+        Ok(())
+    }
+
+    /// This function writes the size of the current tag and pads the tag to 8 bytes
+    pub fn end_tag(&mut self) -> Result<(), Error> {
+        let size = (self.length - self.tag_start) as u32;
+        self.buffer[self.tag_start + 4..self.tag_start + 8].copy_from_slice(&size.to_le_bytes());
+        while self.length % 8 != 0 {
+            self.write(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// This function appends a tag with the specified data
+    pub fn push(&mut self, tag_type: u32, data: &[u8]) -> Result<(), Error> {
+        self.begin_tag(tag_type)?;
+        self.write(data)?;
+        self.end_tag()
+    }
+
+    /// This function appends the end tag, writes the total size and returns it
+    pub fn finish(mut self) -> Result<usize, Error> {
+        self.push(INFO_TAG_END, &[])?;
+        self.buffer[..4].copy_from_slice(&(self.length as u32).to_le_bytes());
+        Ok(self.length)
+    }
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
@@ -0,0 +1,277 @@
+use crate::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    error::Error,
+    pat::{
+        pat_index,
+        CacheType,
+    },
+};
+use core::{
+    arch::asm,
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+};
+
+pub const PAGE_SIZE: u64 = 4096;
+pub const ENTRY_COUNT: usize = 512;
+
+pub const PRESENT: u64 = 1 << 0;
+pub const WRITABLE: u64 = 1 << 1;
+pub const USER: u64 = 1 << 2;
+pub const WRITE_THROUGH: u64 = 1 << 3;
+pub const CACHE_DISABLE: u64 = 1 << 4;
+pub const ACCESSED: u64 = 1 << 5;
+pub const DIRTY: u64 = 1 << 6;
+pub const HUGE_PAGE: u64 = 1 << 7;
+pub const GLOBAL: u64 = 1 << 8;
+pub const NO_EXECUTE: u64 = 1 << 63;
+
+/// The PAT bit is at bit 7 in entries of 4 KiB pages and at bit 12 in entries of huge pages
+pub const PAT: u64 = 1 << 7;
+pub const HUGE_PAT: u64 = 1 << 12;
+
+pub const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const HUGE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_E000;
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [u64; ENTRY_COUNT],
+}
+
+/// This function returns the physical address of the active top-level page table (PML4)
+pub fn active_page_table() -> PhysAddr {
+    let value: u64;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack)) };
+    PhysAddr::new(value & ADDRESS_MASK)
+}
+
+/// This function flushes all non-global entries of the TLB by reloading CR3
+pub fn flush_tlb() {
+    unsafe {
+        asm!(
+            "mov {0}, cr3",
+            "mov cr3, {0}",
+            out(reg) _,
+            options(nostack)
+        )
+    };
+}
+
+/// This function invalidates the TLB entry of the page, which contains the specified address
+#[inline]
+pub fn flush_page(address: VirtAddr) {
+    unsafe { asm!("invlpg [{}]", in(reg) address.as_u64(), options(nostack)) };
+}
+
+/// This function returns the size of the memory, which is mapped by one entry of the specified page
+/// table level (1 = PT, 2 = PD, 3 = PDPT, 4 = PML4).
+#[inline]
+pub const fn level_page_size(level: usize) -> u64 {
+    1 << (12 + 9 * (level - 1))
+}
+
+/// One step of a page table walk with the level of the table (4 = PML4), the index into the table
+/// and the entry at this index.
+#[derive(Clone, Copy, Debug)]
+pub struct MappingStep {
+    pub level: usize,
+    pub index: usize,
+    pub entry: u64,
+}
+
+impl MappingStep {
+    #[inline]
+    pub fn level_name(&self) -> &'static str {
+        match self.level {
+            4 => "PML4E",
+            3 => "PDPTE",
+            2 => "PDE",
+            _ => "PTE",
+        }
+    }
+
+    /// This function returns true, if the entry maps a page instead of referencing a page table
+    #[inline]
+    pub fn is_leaf(&self) -> bool {
+        self.level == 1 || (self.level <= 3 && self.entry & HUGE_PAGE != 0)
+    }
+}
+
+/// The flags of a page table entry, which are displayed as list of short names
+#[derive(Clone, Copy, Debug)]
+pub struct EntryFlags(pub u64);
+
+impl Display for EntryFlags {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        const NAMES: [(u64, &str); 10] = [
+            (PRESENT, "P"),
+            (WRITABLE, "W"),
+            (USER, "U"),
+            (WRITE_THROUGH, "PWT"),
+            (CACHE_DISABLE, "PCD"),
+            (ACCESSED, "A"),
+            (DIRTY, "D"),
+            (HUGE_PAGE, "PS"),
+            (GLOBAL, "G"),
+            (NO_EXECUTE, "NX"),
+        ];
+
+        let mut first = true;
+        for (flag, name) in NAMES {
+            if self.0 & flag == 0 {
+                continue;
+            }
+            if !first {
+                formatter.write_str(" ")?;
+            }
+            formatter.write_str(name)?;
+            first = false;
+        }
+        if first {
+            formatter.write_str("-")?;
+        }
+        Ok(())
+    }
+}
+
+/// This function walks the active page tables for the specified address and returns the entries of
+/// every level until the leaf entry or the first non-present entry.
+///
+/// # Safety
+/// The caller has to ensure, that the page tables are accessible through the physmap.
+pub unsafe fn mapping_chain(address: VirtAddr) -> impl Iterator<Item = MappingStep> {
+    let mut table: *const PageTable = active_page_table().to_virt().as_ptr();
+    let mut level = 4;
+    core::iter::from_fn(move || {
+        if level == 0 {
+            return None;
+        }
+
+        let index = ((address.as_u64() / level_page_size(level)) % ENTRY_COUNT as u64) as usize;
+        let step = MappingStep {
+            level,
+            index,
+            entry: (*table).entries[index],
+        };
+
+        // Stop after leaf entries and non-present entries
+        if step.entry & PRESENT == 0 || step.is_leaf() {
+            level = 0;
+        } else {
+            table = PhysAddr::new(step.entry & ADDRESS_MASK).to_virt().as_ptr();
+            level -= 1;
+        }
+        Some(step)
+    })
+}
+
+/// This function translates the specified virtual address with the active page tables and returns
+/// the physical address, the flags of the leaf entry and the size of the mapped page.
+pub fn translate(address: VirtAddr) -> Option<(PhysAddr, u64, u64)> {
+    let leaf = unsafe { mapping_chain(address) }.last()?;
+    if leaf.entry & PRESENT == 0 {
+        return None;
+    }
+
+    let page_size = level_page_size(leaf.level);
+    let (base_address, flags) = match leaf.level {
+        1 => (leaf.entry & ADDRESS_MASK, leaf.entry & !ADDRESS_MASK),
+        _ => (leaf.entry & HUGE_ADDRESS_MASK & !(page_size - 1), leaf.entry & !HUGE_ADDRESS_MASK),
+    };
+    Some((PhysAddr::new(base_address + address.as_u64() % page_size), flags, page_size))
+}
+
+/// This function returns the PAT, PCD and PWT bits, which select the specified memory type
+pub fn cache_type_flags(cache_type: CacheType, huge_page: bool) -> Option<u64> {
+    let index = pat_index(cache_type)?;
+    let mut flags = 0;
+    if index & 0b001 != 0 {
+        flags |= WRITE_THROUGH;
+    }
+    if index & 0b010 != 0 {
+        flags |= CACHE_DISABLE;
+    }
+    if index & 0b100 != 0 {
+        flags |= if huge_page { HUGE_PAT } else { PAT };
+    }
+    Some(flags)
+}
+
+/// This function changes the memory type of the specified range in the active page tables. The page
+/// tables must map the memory identically (like the page tables of the UEFI firmware). Huge pages,
+/// which are partially covered by the range, are split into smaller pages with page tables from the
+/// specified allocator.
+///
+/// # Safety
+/// The caller has to ensure, that the page tables are writable and that the range isn't used with
+/// another memory type by other mappings.
+pub unsafe fn set_cache_type(
+    start_address: VirtAddr, size: u64, cache_type: CacheType,
+    allocate_table: &mut dyn FnMut() -> Option<PhysAddr>,
+) -> Result<(), Error> {
+    let mut address = start_address.align_down(PAGE_SIZE).as_u64();
+    let end_address = (start_address + size).align_up(PAGE_SIZE).as_u64();
+    while address < end_address {
+        let mut table: *mut PageTable = active_page_table().to_virt().as_mut_ptr();
+        for level in (1..=4).rev() {
+            let page_size = level_page_size(level);
+            let index = ((address / page_size) % ENTRY_COUNT as u64) as usize;
+            let entry = &mut (*table).entries[index];
+            if *entry & PRESENT == 0 {
+                return Err(Error::PageNotMapped(address));
+            }
+
+            // Update the leaf entry, if the page is completely covered by the range. Otherwise, the
+            // huge page is split into smaller pages.
+            if level == 1 || (level <= 3 && *entry & HUGE_PAGE != 0) {
+                let huge_page = level != 1;
+                if address % page_size == 0 && address + page_size <= end_address {
+                    let flags = cache_type_flags(cache_type, huge_page)
+                        .ok_or(Error::UnsupportedCacheType(cache_type as u8))?;
+                    let pat = if huge_page { HUGE_PAT } else { PAT };
+                    *entry = (*entry & !(WRITE_THROUGH | CACHE_DISABLE | pat)) | flags;
+                    address += page_size;
+                    break;
+                }
+                split_huge_page(entry, level, allocate_table)?;
+            }
+            table = PhysAddr::new(*entry & ADDRESS_MASK).to_virt().as_mut_ptr();
+        }
+    }
+
+    flush_tlb();
+    Ok(())
+}
+
+/// This function replaces the specified huge page entry with a page table, which maps the same
+/// memory with the same attributes in smaller pages.
+unsafe fn split_huge_page(
+    entry: &mut u64, level: usize, allocate_table: &mut dyn FnMut() -> Option<PhysAddr>,
+) -> Result<(), Error> {
+    let table_address = allocate_table().ok_or(Error::NoPageTableMemory)?;
+    let table = &mut *table_address.to_virt().as_mut_ptr::<PageTable>();
+
+    let base_address = *entry & HUGE_ADDRESS_MASK;
+    let child_size = level_page_size(level - 1);
+    let mut flags = *entry & !HUGE_ADDRESS_MASK;
+    if level == 2 {
+        // Entries of 4 KiB pages have no huge page bit and the PAT bit is at bit 7
+        let pat = flags & HUGE_PAT != 0;
+        flags &= !(HUGE_PAGE | HUGE_PAT);
+        if pat {
+            flags |= PAT;
+        }
+    }
+
+    for (index, child) in table.entries.iter_mut().enumerate() {
+        *child = (base_address + index as u64 * child_size) | flags;
+    }
+    *entry = table_address.as_u64() | (*entry & (USER | ACCESSED)) | PRESENT | WRITABLE;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use crate::registers::{
+    read_msr,
+    write_msr,
+};
+use core::arch::asm;
+
+const IA32_PAT: u32 = 0x277;
+
+/// The memory types, which can be selected by an entry of the Page Attribute Table (PAT)
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheType {
+    Uncacheable = 0x00,
+    WriteCombining = 0x01,
+    WriteThrough = 0x04,
+    WriteProtected = 0x05,
+    WriteBack = 0x06,
+    Uncached = 0x07,
+}
+
+/// The layout of the PAT, which is programmed by [init_pat]. The first four entries match the
+/// power-on default, so existing mappings of the firmware keep their memory type. The fifth and
+/// sixth entries are used for write-combining mappings like the framebuffer.
+pub const PAT_LAYOUT: [CacheType; 8] = [
+    CacheType::WriteBack,
+    CacheType::WriteThrough,
+    CacheType::Uncached,
+    CacheType::Uncacheable,
+    CacheType::WriteBack,
+    CacheType::WriteCombining,
+    CacheType::Uncached,
+    CacheType::Uncacheable,
+];
+
+/// This function programs the PAT with the [PAT_LAYOUT]. The caches are written back before and
+/// after the PAT is changed, so no cache line with the old memory type survives.
+pub fn init_pat() {
+    let value = PAT_LAYOUT
+        .iter()
+        .enumerate()
+        .fold(0u64, |value, (index, cache_type)| value | (*cache_type as u64) << (index * 8));
+
+    unsafe {
+        asm!("wbinvd", options(nostack));
+        write_msr(IA32_PAT, value);
+        asm!("wbinvd", options(nostack));
+    }
+}
+
+/// This function returns the index of the first PAT entry with the specified memory type
+pub fn pat_index(cache_type: CacheType) -> Option<usize> {
+    PAT_LAYOUT.iter().position(|entry| *entry == cache_type)
+}
+
+/// This function returns the current value of the PAT MSR
+pub fn read_pat() -> u64 {
+    unsafe { read_msr(IA32_PAT) }
+}
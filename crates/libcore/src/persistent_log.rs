@@ -0,0 +1,162 @@
+//! The persistent log is a ring buffer in a fixed physical region, which keeps the log output across
+//! a warm reset. The bootloader checks the region at the next boot. If the header is valid and the
+//! last boot didn't end with a clean shutdown, the log of the last boot is offered in the boot menu.
+//!
+//! The region starts with a header, which is guarded by a magic value and a checksum. The data area
+//! is guarded by the wrapping sum of all bytes, which is updated with every written byte, so it's
+//! very unlikely, that a region with random content after a cold boot is taken as log.
+
+use crate::address::VirtAddr;
+
+/// The load option `pstore[=<size>[@<address>]]`, which enables the persistent log
+pub const PSTORE_OPTION: &str = "pstore";
+
+pub const PERSISTENT_LOG_MAGIC: u64 = 0x4F5646_5F4C4F47; // "OVF_LOG"
+
+/// The log was closed by a clean shutdown or reboot
+const FLAG_CLEAN: u32 = 1 << 0;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    capacity: u64,
+    flags: u32,
+    /// The wrapping sum of all bytes of the data area
+    data_sum: u32,
+    /// The number of bytes, which were written since the log was reset
+    written: u64,
+    checksum: u64,
+}
+
+impl Header {
+    fn calculate_checksum(&self) -> u64 {
+        [
+            self.magic,
+            self.capacity,
+            self.flags as u64,
+            self.data_sum as u64,
+            self.written,
+        ]
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325, |checksum, value| {
+            (checksum ^ value).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+}
+
+/// The persistent log in a fixed physical region
+pub struct PersistentLog {
+    header: *mut Header,
+    data: *mut u8,
+    capacity: usize,
+}
+
+unsafe impl Send for PersistentLog {}
+
+impl PersistentLog {
+    /// This function creates the log in the specified region without changing it. If the region is
+    /// too small for the header and the data, this function returns `None`.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the region is mapped and only used for the log
+    pub unsafe fn new(address: VirtAddr, size: usize) -> Option<Self> {
+        let capacity = size.checked_sub(core::mem::size_of::<Header>())?;
+        if capacity == 0 {
+            return None;
+        }
+
+        Some(Self {
+            header: address.as_mut_ptr(),
+            data: address
+                .as_mut_ptr::<u8>()
+                .add(core::mem::size_of::<Header>()),
+            capacity,
+        })
+    }
+
+    #[inline]
+    fn header(&self) -> &Header {
+        unsafe { &*self.header }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut Header {
+        unsafe { &mut *self.header }
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.data, self.capacity) }
+    }
+
+    /// This function returns true, if the magic, the checksum of the header and the sum of the data
+    /// are valid
+    pub fn is_valid(&self) -> bool {
+        let header = self.header();
+        header.magic == PERSISTENT_LOG_MAGIC
+            && header.capacity == self.capacity as u64
+            && header.checksum == header.calculate_checksum()
+            && header.data_sum == data_sum(self.data())
+    }
+
+    /// This function returns true, if the log was closed by a clean shutdown or reboot
+    pub fn is_clean(&self) -> bool {
+        self.header().flags & FLAG_CLEAN != 0
+    }
+
+    /// This function returns the content of the log. The first slice contains the older bytes.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        let written = self.header().written;
+        let position = (written % self.capacity as u64) as usize;
+        if written < self.capacity as u64 {
+            (&[], &self.data()[..position])
+        } else {
+            (&self.data()[position..], &self.data()[..position])
+        }
+    }
+
+    /// This function clears the log and writes a valid header
+    pub fn reset(&mut self) {
+        unsafe { core::ptr::write_bytes(self.data, 0, self.capacity) };
+        let capacity = self.capacity as u64;
+        let header = self.header_mut();
+        header.magic = PERSISTENT_LOG_MAGIC;
+        header.capacity = capacity;
+        header.flags = 0;
+        header.data_sum = 0;
+        header.written = 0;
+        header.checksum = header.calculate_checksum();
+    }
+
+    /// This function appends the bytes to the log. The oldest bytes are overwritten, if the log is
+    /// full.
+    pub fn write(&mut self, bytes: &[u8]) {
+        let mut data_sum = self.header().data_sum;
+        let mut written = self.header().written;
+        for byte in bytes {
+            let slot = unsafe { &mut *self.data.add((written % self.capacity as u64) as usize) };
+            data_sum = data_sum
+                .wrapping_sub(*slot as u32)
+                .wrapping_add(*byte as u32);
+            *slot = *byte;
+            written += 1;
+        }
+
+        let header = self.header_mut();
+        header.data_sum = data_sum;
+        header.written = written;
+        header.checksum = header.calculate_checksum();
+    }
+
+    /// This function marks the log as closed by a clean shutdown or reboot
+    pub fn mark_clean(&mut self) {
+        let header = self.header_mut();
+        header.flags |= FLAG_CLEAN;
+        header.checksum = header.calculate_checksum();
+    }
+}
+
+fn data_sum(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32))
+}
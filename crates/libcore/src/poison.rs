@@ -0,0 +1,20 @@
+/// The byte, which is written into freed memory in debug builds. A read of this pattern indicates
+/// a use after free and a changed pattern indicates a write after free.
+pub const POISON_BYTE: u8 = 0x5A;
+
+/// The poison checks are only enabled in debug builds, because every allocation and free touches the
+/// whole memory block.
+pub const POISON_ENABLED: bool = cfg!(debug_assertions);
+
+/// This function fills the specified memory with the poison pattern
+#[inline]
+pub fn poison(memory: &mut [u8]) {
+    memory.fill(POISON_BYTE);
+}
+
+/// This function returns the offset of the first byte, which doesn't match the poison pattern, or
+/// `None`, if the memory is completely poisoned.
+#[inline]
+pub fn find_corruption(memory: &[u8]) -> Option<usize> {
+    memory.iter().position(|byte| *byte != POISON_BYTE)
+}
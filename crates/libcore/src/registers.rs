@@ -0,0 +1,122 @@
+use core::arch::asm;
+
+pub const CR0_MONITOR_COPROCESSOR: u64 = 1 << 1;
+pub const CR0_EMULATION: u64 = 1 << 2;
+pub const CR0_TASK_SWITCHED: u64 = 1 << 3;
+pub const CR0_NUMERIC_ERROR: u64 = 1 << 5;
+pub const CR0_WRITE_PROTECT: u64 = 1 << 16;
+
+pub const CR4_OSFXSR: u64 = 1 << 9;
+pub const CR4_OSXMMEXCPT: u64 = 1 << 10;
+pub const CR4_UMIP: u64 = 1 << 11;
+pub const CR4_OSXSAVE: u64 = 1 << 18;
+pub const CR4_SMEP: u64 = 1 << 20;
+pub const CR4_SMAP: u64 = 1 << 21;
+
+pub const IA32_SPEC_CTRL: u32 = 0x48;
+pub const IA32_PRED_CMD: u32 = 0x49;
+pub const IA32_EFER: u32 = 0xC000_0080;
+pub const IA32_STAR: u32 = 0xC000_0081;
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+pub const IA32_FMASK: u32 = 0xC000_0084;
+
+pub const EFER_SYSCALL_ENABLE: u64 = 1 << 0;
+pub const EFER_NO_EXECUTE_ENABLE: u64 = 1 << 11;
+
+pub const SPEC_CTRL_IBRS: u64 = 1 << 0;
+pub const SPEC_CTRL_SSBD: u64 = 1 << 2;
+pub const PRED_CMD_IBPB: u64 = 1 << 0;
+
+pub const RFLAGS_TRAP: u64 = 1 << 8;
+pub const RFLAGS_INTERRUPT: u64 = 1 << 9;
+pub const RFLAGS_DIRECTION: u64 = 1 << 10;
+pub const RFLAGS_ALIGNMENT_CHECK: u64 = 1 << 18;
+
+#[inline]
+pub fn read_cr0() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+/// # Safety
+/// The caller has to ensure, that the new value doesn't break the execution of the kernel
+#[inline]
+pub unsafe fn write_cr0(value: u64) {
+    asm!("mov cr0, {}", in(reg) value, options(nostack));
+}
+
+/// This function returns the current stack pointer
+#[inline(always)]
+pub fn read_rsp() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, rsp", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+/// This function returns the address, which caused the last page fault
+#[inline]
+pub fn read_cr2() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+#[inline]
+pub fn read_cr4() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+/// # Safety
+/// The caller has to ensure, that the new value doesn't break the execution of the kernel
+#[inline]
+pub unsafe fn write_cr4(value: u64) {
+    asm!("mov cr4, {}", in(reg) value, options(nostack));
+}
+
+/// # Safety
+/// The caller has to ensure, that the MSR is supported by the CPU
+#[inline]
+pub unsafe fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack));
+    (high as u64) << 32 | low as u64
+}
+
+/// # Safety
+/// The caller has to ensure, that the MSR is supported by the CPU and that the new value doesn't
+/// break the execution of the kernel
+#[inline]
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack)
+    );
+}
+
+/// # Safety
+/// The caller has to ensure, that a valid IDT with handlers for all unmasked interrupts is loaded
+#[inline]
+pub unsafe fn enable_interrupts() {
+    asm!("sti", options(nomem, nostack));
+}
+
+#[inline]
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+/// This function enables interrupts and halts the CPU until the next interrupt. Both instructions
+/// are executed without an interrupt in between, so an interrupt can't be missed before halting.
+///
+/// # Safety
+/// The caller has to ensure, that a valid IDT with handlers for all unmasked interrupts is loaded
+#[inline]
+pub unsafe fn wait_for_interrupt() {
+    asm!("sti", "hlt", options(nomem, nostack));
+}
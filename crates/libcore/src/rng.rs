@@ -0,0 +1,257 @@
+use core::{
+    arch::{
+        asm,
+        x86_64::{
+            __cpuid,
+            __cpuid_count,
+            _rdtsc,
+        },
+    },
+    fmt,
+};
+
+const RETRY_COUNT: usize = 10;
+const JITTER_SAMPLES: usize = 64;
+
+pub static mut RANDOM_GENERATOR: Option<RandomGenerator> = None;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomSource {
+    RdSeed,
+    RdRand,
+    ChaCha20,
+}
+
+impl fmt::Display for RandomSource {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            RandomSource::RdSeed => "RDSEED",
+            RandomSource::RdRand => "RDRAND",
+            RandomSource::ChaCha20 => "ChaCha20 (TSC jitter)",
+        })
+    }
+}
+
+/// The random generator uses the hardware random number generator of the CPU (RDSEED or RDRAND), if
+/// the CPU supports it. Otherwise or if the hardware generator fails, a ChaCha20 generator seeded
+/// from TSC jitter and the specified seed material (like the current time) is used.
+pub struct RandomGenerator {
+    source: RandomSource,
+    fallback: ChaCha20,
+}
+
+impl RandomGenerator {
+    pub fn new(seed_material: u64) -> Self {
+        let source = if cpu_supports_rdseed() {
+            RandomSource::RdSeed
+        } else if cpu_supports_rdrand() {
+            RandomSource::RdRand
+        } else {
+            RandomSource::ChaCha20
+        };
+
+        Self {
+            source,
+            fallback: ChaCha20::from_seed(jitter_seed(seed_material)),
+        }
+    }
+
+    /// This function creates a deterministic generator, which only uses ChaCha20 with a seed derived
+    /// from the specified seed. The same seed always produces the same values, so it's used for
+    /// reproducible tests like the allocator stress harness.
+    pub fn from_seed(seed: u64) -> Self {
+        let first = splitmix64(seed);
+        let second = splitmix64(first);
+        let third = splitmix64(second);
+        Self {
+            source: RandomSource::ChaCha20,
+            fallback: ChaCha20::from_seed([first, second, third, splitmix64(third)]),
+        }
+    }
+
+    #[inline]
+    pub fn source(&self) -> RandomSource {
+        self.source
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let value = match self.source {
+            RandomSource::RdSeed => rdseed64().or_else(rdrand64),
+            RandomSource::RdRand => rdrand64(),
+            RandomSource::ChaCha20 => None,
+        };
+        value.unwrap_or_else(|| self.fallback.next_u64())
+    }
+
+    /// This function returns a random value in the range `0..bound`
+    pub fn next_bounded(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+
+        // Reject values in the incomplete last range to avoid modulo bias
+        let limit = u64::MAX - (u64::MAX % bound);
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return value % bound;
+            }
+        }
+    }
+
+    pub fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            let value = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+    }
+}
+
+/// This function creates the global random generator. The seed material is mixed into the seed of
+/// the fallback generator.
+pub fn init_random_generator(seed_material: u64) -> RandomSource {
+    let generator = RandomGenerator::new(seed_material);
+    let source = generator.source();
+    unsafe { RANDOM_GENERATOR = Some(generator) };
+    source
+}
+
+/// This function returns a random value from the global random generator, if it was created.
+pub fn random_u64() -> Option<u64> {
+    unsafe { RANDOM_GENERATOR.as_mut() }.map(|generator| generator.next_u64())
+}
+
+pub fn cpu_supports_rdrand() -> bool {
+    unsafe { __cpuid(0x01) }.ecx & (1 << 30) != 0
+}
+
+pub fn cpu_supports_rdseed() -> bool {
+    unsafe { __cpuid(0x00).eax >= 0x07 && __cpuid_count(0x07, 0x00).ebx & (1 << 18) != 0 }
+}
+
+fn rdrand64() -> Option<u64> {
+    for _ in 0..RETRY_COUNT {
+        let value: u64;
+        let success: u8;
+        unsafe {
+            asm!("rdrand {0}", "setc {1}", out(reg) value, out(reg_byte) success, options(nomem, nostack))
+        };
+        if success != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn rdseed64() -> Option<u64> {
+    for _ in 0..RETRY_COUNT {
+        let value: u64;
+        let success: u8;
+        unsafe {
+            asm!("rdseed {0}", "setc {1}", out(reg) value, out(reg_byte) success, options(nomem, nostack))
+        };
+        if success != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// This function collects the jitter of the timestamp counter over a few memory accesses and mixes it
+/// with the seed material into a 256-bit seed.
+fn jitter_seed(seed_material: u64) -> [u64; 4] {
+    let mut seed = [seed_material, 0, 0, 0];
+    let mut scratch = [0u64; 16];
+    for sample in 0..JITTER_SAMPLES {
+        let start = unsafe { _rdtsc() };
+        for (index, value) in scratch.iter_mut().enumerate() {
+            *value = unsafe { core::ptr::read_volatile(value) }.wrapping_add(index as u64 ^ start);
+        }
+        let delta = unsafe { _rdtsc() }.wrapping_sub(start);
+        seed[sample % 4] = splitmix64(seed[sample % 4] ^ delta.rotate_left(sample as u32));
+    }
+    seed
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+/// This is the ChaCha20 stream cipher (RFC 8439) used as cryptographically secure pseudo random
+/// number generator.
+struct ChaCha20 {
+    state: [u32; 16],
+    block: [u32; 16],
+    index: usize,
+}
+
+impl ChaCha20 {
+    fn from_seed(seed: [u64; 4]) -> Self {
+        let mut state = [0u32; 16];
+        state[0..4].copy_from_slice(&[0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574]);
+        for (index, value) in seed.iter().enumerate() {
+            state[4 + index * 2] = *value as u32;
+            state[5 + index * 2] = (*value >> 32) as u32;
+        }
+
+        Self {
+            state,
+            block: [0; 16],
+            index: 16,
+        }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.index >= 16 {
+            self.generate_block();
+        }
+
+        let value = self.block[self.index];
+        self.index += 1;
+        value
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+
+    fn generate_block(&mut self) {
+        let mut working_state = self.state;
+        for _ in 0..10 {
+            quarter_round(&mut working_state, 0, 4, 8, 12);
+            quarter_round(&mut working_state, 1, 5, 9, 13);
+            quarter_round(&mut working_state, 2, 6, 10, 14);
+            quarter_round(&mut working_state, 3, 7, 11, 15);
+            quarter_round(&mut working_state, 0, 5, 10, 15);
+            quarter_round(&mut working_state, 1, 6, 11, 12);
+            quarter_round(&mut working_state, 2, 7, 8, 13);
+            quarter_round(&mut working_state, 3, 4, 9, 14);
+        }
+
+        for (index, value) in self.block.iter_mut().enumerate() {
+            *value = working_state[index].wrapping_add(self.state[index]);
+        }
+
+        // Increment 64-bit block counter
+        self.state[12] = self.state[12].wrapping_add(1);
+        if self.state[12] == 0 {
+            self.state[13] = self.state[13].wrapping_add(1);
+        }
+        self.index = 0;
+    }
+}
+
+#[inline]
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
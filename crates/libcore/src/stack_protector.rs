@@ -0,0 +1,81 @@
+use crate::rng::{
+    random_u64,
+    RandomGenerator,
+};
+use core::arch::global_asm;
+
+/// The resolver returns the name of the symbol, which contains the specified address, and the offset
+/// of the address into the symbol.
+pub type SymbolResolver = fn(u64) -> Option<(&'static str, u64)>;
+
+/// The canary, which is placed by the compiler between the local variables and the return address
+/// of protected functions. The value is replaced with a random value by [init_stack_guard].
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static mut __stack_chk_guard: u64 = 0x595E_9FBD_94FD_A700;
+
+/// The canary of the MSVC environment. LLVM treats the UEFI target as MSVC target, so the functions
+/// of the bootloader use this canary (XORed with the stack pointer) instead of [__stack_chk_guard].
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static mut __security_cookie: u64 = 0x0000_2B99_2DDF_A232;
+
+static mut SYMBOL_RESOLVER: Option<SymbolResolver> = None;
+
+// The failure handler is called by protected functions, if the canary was overwritten. The return
+// address on top of the stack points into the function with the smashed stack, so it's passed as
+// first argument to the handler. The handler uses the System V ABI on all targets.
+global_asm!(
+    ".global __stack_chk_fail",
+    "__stack_chk_fail:",
+    "mov rdi, [rsp]",
+    "sub rsp, 8",
+    "call {handler}",
+    "ud2",
+    handler = sym stack_check_failed,
+);
+
+// The check of the MSVC environment is called by protected functions with the canary of the stack
+// frame in RCX. If the canary doesn't match, the check fails like `__stack_chk_fail`, the return
+// address on top of the stack points into the function with the smashed stack.
+global_asm!(
+    ".global __security_check_cookie",
+    "__security_check_cookie:",
+    "cmp rcx, [rip + __security_cookie]",
+    "jne 2f",
+    "ret",
+    "2:",
+    "mov rdi, [rsp]",
+    "sub rsp, 8",
+    "call {handler}",
+    "ud2",
+    handler = sym stack_check_failed,
+);
+
+/// This function replaces the stack canaries of both environments with a random value. The lowest
+/// byte of the canary is always zero, so the canary can't be leaked or overwritten by string
+/// functions.
+///
+/// This function must be called from a function, which never returns. Every protected function,
+/// which is on the stack while the canary is changed, fails the check on return.
+pub fn init_stack_guard() {
+    let guard = random_u64().unwrap_or_else(|| RandomGenerator::new(0).next_u64());
+    unsafe {
+        __stack_chk_guard = guard & !0xFF;
+        __security_cookie = guard & !0xFF;
+    }
+}
+
+/// This function sets the resolver, which is used to name the function with the smashed stack
+pub fn set_symbol_resolver(resolver: SymbolResolver) {
+    unsafe { SYMBOL_RESOLVER = Some(resolver) };
+}
+
+extern "sysv64" fn stack_check_failed(return_address: u64) -> ! {
+    match unsafe { SYMBOL_RESOLVER }.and_then(|resolver| resolver(return_address)) {
+        Some((name, offset)) => {
+            panic!("Stack smashing detected in {}+0x{:X} (0x{:X})", name, offset, return_address)
+        }
+        None => panic!("Stack smashing detected at 0x{:X}", return_address),
+    }
+}
@@ -0,0 +1,431 @@
+//! The stress harness runs random sequences of allocations and frees against an allocator and
+//! validates every result against a model allocator. The sequence is generated from a seed, so a
+//! failing sequence is reproduced by running the harness with the seed of the failure. The harness
+//! is used by the host-side tests of the `std-test` feature for the frame allocator and can be used
+//! for every allocator, which implements [GlobalAlloc]. Allocators with more operations (like the
+//! contiguous allocations and the reservations of the frame allocator) implement [StressTarget], so
+//! the operations are part of the random sequence.
+
+use crate::{
+    address::VirtAddr,
+    rng::RandomGenerator,
+    FrameAllocator,
+};
+use core::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+    },
+    fmt::{
+        self,
+        Display,
+        Formatter,
+    },
+    ptr,
+};
+use std::{
+    format,
+    string::String,
+    vec::Vec,
+};
+
+/// The parameters of a stress run
+#[derive(Clone, Copy, Debug)]
+pub struct StressConfig {
+    pub seed: u64,
+    pub operations: usize,
+    /// The maximum size of an allocation in bytes
+    pub max_size: usize,
+    /// The maximum alignment of an allocation, which must be a power of two
+    pub max_alignment: usize,
+    /// The maximum number of allocations, which are live at the same time
+    pub max_live: usize,
+}
+
+/// The memory, which is managed by the tested allocator. With the memory, the model knows, whether
+/// an allocation has to succeed.
+#[derive(Clone, Copy, Debug)]
+pub struct ModelMemory {
+    pub start: usize,
+    pub size: usize,
+    /// The granularity of the allocations (like the frame size), every block is rounded up to it
+    pub granularity: usize,
+}
+
+/// The allocator, which is tested by the stress harness. Only the allocation and the free are
+/// required, the other operations are skipped in the sequence if the allocator doesn't support them.
+pub trait StressTarget {
+    /// This function allocates a block with the layout like [GlobalAlloc::alloc]
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the layout has a non-zero size.
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// This function frees a block, which was allocated with [StressTarget::allocate]
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the block was allocated with the same layout.
+    unsafe fn free(&self, pointer: *mut u8, layout: Layout);
+
+    /// This function allocates a block with the layout, which ends at or below the limit. If the
+    /// allocator doesn't support this operation, `None` is returned.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the layout has a non-zero size.
+    unsafe fn allocate_below(&self, _layout: Layout, _limit: usize) -> Option<*mut u8> {
+        None
+    }
+
+    /// This function frees a block, which was allocated with [StressTarget::allocate_below]
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the block was allocated with the same layout.
+    unsafe fn free_below(&self, pointer: *mut u8, layout: Layout) {
+        self.free(pointer, layout);
+    }
+
+    /// This function reserves the memory of the specified range, so it's never allocated again. If
+    /// the allocator doesn't support this operation, false is returned.
+    fn reserve(&mut self, _address: usize, _size: usize) -> bool {
+        false
+    }
+}
+
+/// The stress target of an allocator, which only implements [GlobalAlloc]
+struct GlobalTarget<'a>(&'a dyn GlobalAlloc);
+
+impl StressTarget for GlobalTarget<'_> {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc(layout)
+    }
+
+    unsafe fn free(&self, pointer: *mut u8, layout: Layout) {
+        self.0.dealloc(pointer, layout);
+    }
+}
+
+/// The frame allocator is tested with the contiguous allocations and the reservations. The addresses
+/// of the harness are virtual addresses in the physmap.
+impl StressTarget for FrameAllocator<'_> {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+    }
+
+    unsafe fn free(&self, pointer: *mut u8, layout: Layout) {
+        self.dealloc(pointer, layout);
+    }
+
+    unsafe fn allocate_below(&self, layout: Layout, limit: usize) -> Option<*mut u8> {
+        let max_address = VirtAddr::new(limit as u64).physmap_to_phys()?;
+        let page_count = layout.size().max(1).div_ceil(self.page_size as usize);
+        let address = self.alloc_contiguous(page_count, max_address, layout.align() as u64);
+        Some(address.map_or(ptr::null_mut(), |address| address.to_virt().as_mut_ptr()))
+    }
+
+    unsafe fn free_below(&self, pointer: *mut u8, layout: Layout) {
+        let address = VirtAddr::from_ptr(pointer).physmap_to_phys().unwrap();
+        let page_count = layout.size().max(1).div_ceil(self.page_size as usize);
+        self.free_contiguous(address, page_count);
+    }
+
+    fn reserve(&mut self, address: usize, size: usize) -> bool {
+        let Some(address) = VirtAddr::new(address as u64).physmap_to_phys() else {
+            return false;
+        };
+        let page_count = size.div_ceil(self.page_size as usize) as u64;
+        self.reserve_region(address, page_count);
+        true
+    }
+}
+
+struct ModelBlock {
+    address: usize,
+    layout: Layout,
+    pattern: u8,
+    /// Whether the block was allocated with [StressTarget::allocate_below]
+    below: bool,
+}
+
+/// The model allocator tracks the live allocations and the reserved ranges of the tested allocator
+pub struct ModelAllocator {
+    blocks: Vec<ModelBlock>,
+    reserved: Vec<(usize, usize)>,
+    memory: Option<ModelMemory>,
+}
+
+impl ModelAllocator {
+    /// This function creates the model. Without memory, failed allocations are accepted (like in a
+    /// heap, which has allocation headers).
+    pub fn new(memory: Option<ModelMemory>) -> Self {
+        Self {
+            blocks: Vec::new(),
+            reserved: Vec::new(),
+            memory,
+        }
+    }
+
+    /// This function marks the range as reserved like the memory, which was reserved in the tested
+    /// allocator before the run
+    pub fn reserve(&mut self, address: usize, size: usize) {
+        self.reserved.push((address, address + size));
+    }
+
+    /// This function returns the size of the block of the specified layout in the tested allocator
+    fn block_size(&self, layout: Layout) -> usize {
+        let granularity = self.memory.map_or(1, |memory| memory.granularity);
+        layout.size().max(1).div_ceil(granularity) * granularity
+    }
+
+    /// This function returns the ranges of the live blocks and the reserved ranges
+    fn used_ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.blocks
+            .iter()
+            .map(|block| (block.address, block.address + self.block_size(block.layout)))
+            .chain(self.reserved.iter().copied())
+    }
+
+    /// This function returns the size of all reserved ranges
+    fn reserved_size(&self) -> usize {
+        self.reserved.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// This function returns, whether the memory has a free block with the size and the alignment of
+    /// the layout, which ends at or below the limit. Without memory, this function returns false.
+    fn has_free_block(&self, layout: Layout, limit: usize) -> bool {
+        let Some(memory) = self.memory else {
+            return false;
+        };
+
+        let mut ranges = self.used_ranges().collect::<Vec<_>>();
+        ranges.sort_unstable();
+
+        let alignment = layout.align().max(memory.granularity);
+        let size = self.block_size(layout);
+        let mut address = memory.start;
+        for (start, end) in ranges.into_iter().chain([(memory.start + memory.size, 0)]) {
+            let aligned = address.next_multiple_of(alignment);
+            if aligned + size <= start && aligned + size <= limit {
+                return true;
+            }
+            address = address.max(end);
+        }
+        false
+    }
+
+    /// This function validates the block, which was returned by the tested allocator. The block has
+    /// to end at or below the limit.
+    fn validate(&self, address: usize, layout: Layout, limit: usize) -> Result<(), String> {
+        if address % layout.align() != 0 {
+            return Err(format!("0x{:X} isn't aligned to {} bytes", address, layout.align()));
+        }
+
+        let end = address + self.block_size(layout);
+        if end > limit {
+            return Err(format!("0x{:X}..0x{:X} ends above the limit 0x{:X}", address, end, limit));
+        }
+
+        if let Some(memory) = self.memory {
+            if address < memory.start || end > memory.start + memory.size {
+                return Err(format!("0x{:X}..0x{:X} is outside of the memory", address, end));
+            }
+        }
+
+        for (block_start, block_end) in self.used_ranges() {
+            if address < block_end && block_start < end {
+                return Err(format!(
+                    "0x{:X}..0x{:X} overlaps the used range 0x{:X}..0x{:X}",
+                    address, end, block_start, block_end
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The statistics of a stress run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StressReport {
+    pub allocations: usize,
+    pub frees: usize,
+    pub failures: usize,
+    /// The size of the memory in bytes, which was reserved by the run and stays reserved
+    pub reserved: usize,
+}
+
+/// The failure of a stress run with the seed and the number of the operation, which failed
+#[derive(Debug)]
+pub struct StressFailure {
+    pub seed: u64,
+    pub operation: usize,
+    pub reason: String,
+}
+
+impl Display for StressFailure {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Stress run with seed 0x{:X} failed at operation {} => {}",
+            self.seed, self.operation, self.reason
+        )
+    }
+}
+
+/// This function runs the stress sequence of the config against the allocator like
+/// [run_stress_target] with allocations and frees only.
+///
+/// # Safety
+/// The caller has to ensure, that the memory of the allocator can be written through the returned
+/// pointers.
+pub unsafe fn run_stress(
+    allocator: &dyn GlobalAlloc, model: &mut ModelAllocator, config: &StressConfig,
+) -> Result<StressReport, StressFailure> {
+    run_stress_target(&mut GlobalTarget(allocator), model, config)
+}
+
+/// This function runs the stress sequence of the config against the target. Every block is filled
+/// with a pattern, which is verified before the block is freed, so writes into overlapping blocks
+/// are detected. The sequence contains allocations below a random limit and reservations of free
+/// ranges, if the target supports them. All blocks are freed at the end of the run, the reserved
+/// ranges stay reserved.
+///
+/// # Safety
+/// The caller has to ensure, that the memory of the target can be written through the returned
+/// pointers.
+pub unsafe fn run_stress_target(
+    target: &mut dyn StressTarget, model: &mut ModelAllocator, config: &StressConfig,
+) -> Result<StressReport, StressFailure> {
+    let mut random = RandomGenerator::from_seed(config.seed);
+    let mut report = StressReport::default();
+    let alignment_steps = config.max_alignment.trailing_zeros() as u64 + 1;
+    let failure = |operation: usize, reason: String| {
+        StressFailure {
+            seed: config.seed,
+            operation,
+            reason,
+        }
+    };
+
+    for operation in 0..config.operations {
+        let allocate = model.blocks.is_empty()
+            || (model.blocks.len() < config.max_live && random.next_bounded(3) != 0);
+        if !allocate {
+            let index = random.next_bounded(model.blocks.len() as u64) as usize;
+            let block = model.blocks.swap_remove(index);
+            free_block(target, &block).map_err(|reason| failure(operation, reason))?;
+            report.frees += 1;
+            continue;
+        }
+
+        // Every 16th operation reserves a free range and every 8th operation allocates below a limit
+        let kind = random.next_bounded(16);
+        if kind == 0 {
+            reserve_range(target, model, &mut random, &mut report);
+            continue;
+        }
+
+        // Small allocations are more common than large allocations
+        let max_size = match random.next_bounded(4) {
+            0 => config.max_size,
+            _ => (config.max_size / 16).max(1),
+        };
+        let size = 1 + random.next_bounded(max_size as u64) as usize;
+        let alignment = 1 << random.next_bounded(alignment_steps);
+        let layout = Layout::from_size_align(size, alignment).unwrap();
+
+        let (pointer, limit) = match (kind, model.memory) {
+            (1 | 2, Some(memory)) => {
+                let frames = (memory.size / memory.granularity) as u64;
+                let limit =
+                    memory.start + (1 + random.next_bounded(frames)) as usize * memory.granularity;
+                match target.allocate_below(layout, limit) {
+                    Some(pointer) => (pointer, Some(limit)),
+                    None => (target.allocate(layout), None),
+                }
+            }
+            _ => (target.allocate(layout), None),
+        };
+        if pointer.is_null() {
+            if model.has_free_block(layout, limit.unwrap_or(usize::MAX)) {
+                return Err(failure(
+                    operation,
+                    format!("Allocation of {:?} failed, but the memory has a free block", layout),
+                ));
+            }
+            report.failures += 1;
+            continue;
+        }
+
+        let address = pointer as usize;
+        model
+            .validate(address, layout, limit.unwrap_or(usize::MAX))
+            .map_err(|reason| failure(operation, reason))?;
+        let pattern = random.next_u64() as u8;
+        core::ptr::write_bytes(pointer, pattern, size);
+        model.blocks.push(ModelBlock {
+            address,
+            layout,
+            pattern,
+            below: limit.is_some(),
+        });
+        report.allocations += 1;
+    }
+
+    // Free the remaining blocks, so the allocator is empty after the run
+    while let Some(block) = model.blocks.pop() {
+        free_block(target, &block).map_err(|reason| failure(config.operations, reason))?;
+        report.frees += 1;
+    }
+    Ok(report)
+}
+
+/// This function reserves a random free range of the memory, if the target supports reservations.
+/// The reserved ranges are limited to an eighth of the memory, so the allocations still succeed.
+fn reserve_range(
+    target: &mut dyn StressTarget, model: &mut ModelAllocator, random: &mut RandomGenerator,
+    report: &mut StressReport,
+) {
+    let Some(memory) = model.memory else {
+        return;
+    };
+
+    let frames = memory.size / memory.granularity;
+    let count = 1 + random.next_bounded(4) as usize;
+    let first = random.next_bounded(frames as u64) as usize;
+    let size = count * memory.granularity;
+    if first + count > frames || model.reserved_size() + size > memory.size / 8 {
+        return;
+    }
+
+    // Reserving a live block would break the free of the block
+    let start = memory.start + first * memory.granularity;
+    let end = start + size;
+    if model
+        .used_ranges()
+        .any(|(used_start, used_end)| start < used_end && used_start < end)
+    {
+        return;
+    }
+
+    if target.reserve(start, size) {
+        model.reserved.push((start, end));
+        report.reserved += size;
+    }
+}
+
+/// This function verifies the pattern of the block and frees it
+unsafe fn free_block(target: &dyn StressTarget, block: &ModelBlock) -> Result<(), String> {
+    let data = core::slice::from_raw_parts(block.address as *const u8, block.layout.size());
+    if let Some(offset) = data.iter().position(|byte| *byte != block.pattern) {
+        return Err(format!(
+            "Block 0x{:X} was overwritten at offset 0x{:X} (expected 0x{:02X}, found 0x{:02X})",
+            block.address, offset, block.pattern, data[offset]
+        ));
+    }
+
+    if block.below {
+        target.free_below(block.address as *mut u8, block.layout);
+    } else {
+        target.free(block.address as *mut u8, block.layout);
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+    Write,
+};
+
+const CELL_CAPACITY: usize = 128;
+const ELLIPSIS: &str = "...";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Column<'a> {
+    pub header: &'a str,
+    pub width: usize,
+    pub alignment: Alignment,
+}
+
+impl<'a> Column<'a> {
+    pub const fn new(header: &'a str, width: usize, alignment: Alignment) -> Self {
+        Self {
+            header,
+            width,
+            alignment,
+        }
+    }
+}
+
+/// The table formats rows of cells into aligned columns with fixed widths. Cells, which are wider
+/// than their column, are truncated with an ellipsis. The header, separator and rows implement
+/// [Display], so they can be passed directly to the logger or written into any [Write].
+pub struct Table<'a> {
+    columns: &'a [Column<'a>],
+}
+
+pub struct TableHeader<'a> {
+    table: &'a Table<'a>,
+}
+
+pub struct TableSeparator<'a> {
+    table: &'a Table<'a>,
+}
+
+pub struct TableRow<'a> {
+    table: &'a Table<'a>,
+    cells: &'a [&'a dyn Display],
+}
+
+impl<'a> Table<'a> {
+    pub const fn new(columns: &'a [Column<'a>]) -> Self {
+        Self { columns }
+    }
+
+    pub fn header(&self) -> TableHeader<'_> {
+        TableHeader { table: self }
+    }
+
+    pub fn separator(&self) -> TableSeparator<'_> {
+        TableSeparator { table: self }
+    }
+
+    pub fn row<'b>(&'b self, cells: &'b [&'b dyn Display]) -> TableRow<'b> {
+        TableRow { table: self, cells }
+    }
+
+    /// This function writes the header line and the separator line into the specified writer
+    pub fn write_header<W: Write>(&self, writer: &mut W) -> fmt::Result {
+        writeln!(writer, "{}", TableHeader { table: self })?;
+        writeln!(writer, "{}", TableSeparator { table: self })
+    }
+
+    pub fn write_row<W: Write>(&self, writer: &mut W, cells: &[&dyn Display]) -> fmt::Result {
+        for (index, column) in self.columns.iter().enumerate() {
+            if index > 0 {
+                writer.write_str("  ")?;
+            }
+
+            match cells.get(index) {
+                Some(cell) => write_cell(writer, column, cell)?,
+                None => write_cell(writer, column, &"")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for TableHeader<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for (index, column) in self.table.columns.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str("  ")?;
+            }
+            write_cell(formatter, column, &column.header)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TableSeparator<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for (index, column) in self.table.columns.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str("  ")?;
+            }
+
+            for _ in 0..column.width {
+                formatter.write_char('-')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for TableRow<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        self.table.write_row(formatter, self.cells)
+    }
+}
+
+fn write_cell<W: Write + ?Sized>(
+    writer: &mut W, column: &Column, value: &dyn Display,
+) -> fmt::Result {
+    let mut cell = CellBuffer {
+        data: [0; CELL_CAPACITY],
+        length: 0,
+        overflowed: false,
+    };
+    let _ = write!(cell, "{}", value);
+
+    // Truncate the cell content with an ellipsis, if the content doesn't fit into the column
+    let text = cell.as_str();
+    let char_count = text.chars().count();
+    let (text, ellipsis) = if char_count > column.width || cell.overflowed {
+        let visible_chars = column.width.saturating_sub(ELLIPSIS.len());
+        let end = text
+            .char_indices()
+            .nth(visible_chars)
+            .map(|(index, _)| index)
+            .unwrap_or(text.len());
+        (&text[..end], &ELLIPSIS[..column.width.min(ELLIPSIS.len())])
+    } else {
+        (text, "")
+    };
+
+    let padding = column
+        .width
+        .saturating_sub(text.chars().count() + ellipsis.len());
+    if column.alignment == Alignment::Right {
+        write_padding(writer, padding)?;
+    }
+    writer.write_str(text)?;
+    writer.write_str(ellipsis)?;
+    if column.alignment == Alignment::Left {
+        write_padding(writer, padding)?;
+    }
+    Ok(())
+}
+
+fn write_padding<W: Write + ?Sized>(writer: &mut W, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        writer.write_char(' ')?;
+    }
+    Ok(())
+}
+
+/// The cell buffer holds the formatted content of a single cell. Content, which exceeds the
+/// capacity, is dropped at a character boundary and marks the buffer as overflowed.
+struct CellBuffer {
+    data: [u8; CELL_CAPACITY],
+    length: usize,
+    overflowed: bool,
+}
+
+impl CellBuffer {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.data[..self.length]).unwrap_or_default()
+    }
+}
+
+impl Write for CellBuffer {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for char in string.chars() {
+            let char_length = char.len_utf8();
+            if self.length + char_length > CELL_CAPACITY {
+                self.overflowed = true;
+                return Ok(());
+            }
+
+            char.encode_utf8(&mut self.data[self.length..]);
+            self.length += char_length;
+        }
+        Ok(())
+    }
+}
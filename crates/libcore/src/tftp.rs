@@ -0,0 +1,25 @@
+//! The packets of TFTP (RFC 1350), which are reported by the callback of the PXE Base Code Protocol
+//! while a file is downloaded. The callback only inspects the packets to report the progress, the
+//! transfer itself is done by the firmware.
+
+/// The function of the PXE Base Code Protocol, which is reported for TFTP transfers
+/// (`EFI_PXE_BASE_CODE_FUNCTION_MTFTP`)
+pub const PXE_FUNCTION_MTFTP: u32 = 3;
+
+/// The opcode of TFTP data packets, which is followed by the block number and the data
+pub const OPCODE_DATA: u16 = 3;
+pub const HEADER_SIZE: usize = 4;
+
+/// The error code of the error packet of the server, if the requested file doesn't exist
+pub const ERROR_FILE_NOT_FOUND: u8 = 1;
+
+/// This function returns the number of payload bytes of the packet, which was passed to the PXE
+/// callback. If the packet isn't a received TFTP data packet, this function returns `None`.
+pub fn received_payload_length(function: u32, received: bool, packet: &[u8]) -> Option<usize> {
+    if function != PXE_FUNCTION_MTFTP || !received || packet.len() < HEADER_SIZE {
+        return None;
+    }
+
+    let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+    (opcode == OPCODE_DATA).then_some(packet.len() - HEADER_SIZE)
+}
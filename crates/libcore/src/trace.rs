@@ -0,0 +1,302 @@
+//! The boot trace records the phases of the bootloader and the kernel as events with timestamps of
+//! the TSC into a shared buffer. The bootloader records into a static buffer until the load options
+//! are known. If tracing is enabled, the events are moved into a reserved buffer, which is handed
+//! to the kernel with the boot information. The kernel appends its own events and emits the trace
+//! in the JSON format of `chrome://tracing` (Trace Event Format of Catapult).
+
+use crate::address::VirtAddr;
+use core::{
+    arch::x86_64::_rdtsc,
+    fmt::{
+        self,
+        Write,
+    },
+    ptr::null_mut,
+    sync::atomic::{
+        AtomicPtr,
+        AtomicU64,
+        AtomicU8,
+        Ordering,
+    },
+};
+
+/// The flag, which enables the boot trace and emits it over the serial console after boot
+pub const TRACE_OPTION: &str = "trace";
+
+/// The maximum number of events in the trace buffer. Later events are dropped.
+pub const MAX_TRACE_EVENTS: usize = 512;
+
+/// The size of the trace buffer, which is handed to the kernel
+pub const TRACE_BUFFER_SIZE: usize = core::mem::size_of::<TraceBuffer>();
+
+const TRACE_MAGIC: u64 = u64::from_le_bytes(*b"OVFTRACE");
+const TRACE_NAME_LENGTH: usize = 32;
+
+static TRACE_BUFFER: AtomicPtr<TraceBuffer> = AtomicPtr::new(null_mut());
+static TRACE_PROCESS: AtomicU8 = AtomicU8::new(TraceProcess::Bootloader as u8);
+
+/// The component, which recorded an event. It's shown as process in the trace viewer.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceProcess {
+    Bootloader = 0,
+    Kernel = 1,
+}
+
+impl TraceProcess {
+    pub const fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Bootloader),
+            1 => Some(Self::Kernel),
+            _ => None,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Bootloader => "Bootloader",
+            Self::Kernel => "Kernel",
+        }
+    }
+}
+
+/// The phase of an event, which is encoded like the `ph` field of the Trace Event Format
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracePhase {
+    /// The start of a phase
+    Begin = b'B',
+    /// The end of the last started phase
+    End = b'E',
+    /// A point in time without duration (like a boot milestone)
+    Instant = b'i',
+}
+
+/// An event of the boot trace with the name as null-padded string. Longer names are truncated.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TraceEvent {
+    name: [u8; TRACE_NAME_LENGTH],
+    timestamp: u64,
+    process: u8,
+    phase: u8,
+    _reserved: [u8; 6],
+}
+
+impl TraceEvent {
+    const EMPTY: Self = Self {
+        name: [0; TRACE_NAME_LENGTH],
+        timestamp: 0,
+        process: 0,
+        phase: 0,
+        _reserved: [0; 6],
+    };
+
+    pub fn name(&self) -> &str {
+        let length = self
+            .name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(TRACE_NAME_LENGTH);
+        core::str::from_utf8(&self.name[..length]).unwrap_or_default()
+    }
+}
+
+/// The buffer of the boot trace. The layout is shared between bootloader and kernel.
+#[repr(C)]
+pub struct TraceBuffer {
+    magic: u64,
+    /// The frequency of the TSC in Hz, which converts the timestamps into microseconds
+    tsc_frequency: AtomicU64,
+    event_count: AtomicU64,
+    dropped_events: AtomicU64,
+    events: [TraceEvent; MAX_TRACE_EVENTS],
+}
+
+impl TraceBuffer {
+    pub const fn new() -> Self {
+        Self {
+            magic: TRACE_MAGIC,
+            tsc_frequency: AtomicU64::new(0),
+            event_count: AtomicU64::new(0),
+            dropped_events: AtomicU64::new(0),
+            events: [TraceEvent::EMPTY; MAX_TRACE_EVENTS],
+        }
+    }
+
+    /// This function returns the recorded events
+    pub fn events(&self) -> &[TraceEvent] {
+        let count = self.event_count.load(Ordering::Acquire) as usize;
+        &self.events[..count.min(MAX_TRACE_EVENTS)]
+    }
+
+    #[inline]
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    /// This function converts the TSC timestamp into microseconds. Without known TSC frequency, the
+    /// timestamp is returned in kcycles.
+    fn microseconds(&self, timestamp: u64) -> u64 {
+        match self.tsc_frequency.load(Ordering::Relaxed) {
+            0 => timestamp / 1000,
+            frequency => (timestamp as u128 * 1_000_000 / frequency as u128) as u64,
+        }
+    }
+
+    /// This function writes the events in the JSON format of `chrome://tracing`. The processes are
+    /// named with metadata events, so the viewer shows the bootloader and the kernel separately.
+    pub fn write_json(&self, writer: &mut impl Write) -> fmt::Result {
+        writer.write_str("{\"traceEvents\":[")?;
+        for process in [TraceProcess::Bootloader, TraceProcess::Kernel] {
+            writer.write_str("{\"name\":\"process_name\",\"ph\":\"M\",")?;
+            write!(
+                writer,
+                "\"pid\":{},\"tid\":0,\"args\":{{\"name\":\"{}\"}}}},",
+                process as u8,
+                process.name()
+            )?;
+        }
+
+        let start = self.events().first().map_or(0, |event| event.timestamp);
+        for (index, event) in self.events().iter().enumerate() {
+            if index != 0 {
+                writer.write_char(',')?;
+            }
+            writer.write_str("{\"name\":\"")?;
+            for char in event.name().chars() {
+                match char {
+                    '"' | '\\' => write!(writer, "\\{}", char)?,
+                    char if char.is_control() => write!(writer, "\\u{:04x}", char as u32)?,
+                    char => writer.write_char(char)?,
+                }
+            }
+            write!(
+                writer,
+                "\",\"ph\":\"{}\",\"ts\":{},\"pid\":{},\"tid\":0",
+                event.phase as char,
+                self.microseconds(event.timestamp.saturating_sub(start)),
+                event.process
+            )?;
+            if event.phase == TracePhase::Instant as u8 {
+                writer.write_str(",\"s\":\"g\"")?;
+            }
+            writer.write_char('}')?;
+        }
+        writer.write_str("],\"displayTimeUnit\":\"ms\",")?;
+        write!(
+            writer,
+            "\"otherData\":{{\"tscFrequency\":{},\"droppedEvents\":{}}}}}",
+            self.tsc_frequency.load(Ordering::Relaxed),
+            self.dropped_events()
+        )
+    }
+}
+
+/// The guard of a phase, which records the end of the phase when it's dropped
+pub struct TraceScope {
+    name: &'static str,
+}
+
+impl Drop for TraceScope {
+    fn drop(&mut self) {
+        trace_event(TracePhase::End, self.name);
+    }
+}
+
+/// This function sets the buffer, which the events of this component are recorded into. If a buffer
+/// was set before, its events are copied into the new buffer.
+///
+/// # Safety
+/// The caller has to ensure, that the buffer is valid for the whole runtime of the component and is
+/// not used by other code.
+pub unsafe fn set_trace_buffer(buffer: *mut TraceBuffer, process: TraceProcess) {
+    let previous = TRACE_BUFFER.load(Ordering::Acquire);
+    buffer.write(TraceBuffer::new());
+    if let Some(previous) = previous.as_ref() {
+        let target = &mut *buffer;
+        let events = previous.events();
+        target.events[..events.len()].copy_from_slice(events);
+        target
+            .event_count
+            .store(events.len() as u64, Ordering::Release);
+        target
+            .dropped_events
+            .store(previous.dropped_events(), Ordering::Relaxed);
+        target
+            .tsc_frequency
+            .store(previous.tsc_frequency.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+    TRACE_PROCESS.store(process as u8, Ordering::Relaxed);
+    TRACE_BUFFER.store(buffer, Ordering::Release);
+}
+
+/// This function continues the trace in the buffer at the specified address, which was handed over
+/// by the bootloader. If the buffer has no valid magic, this function returns false.
+///
+/// # Safety
+/// The caller has to ensure, that the address points to mapped memory with the size of the trace
+/// buffer, which is not used by other code.
+pub unsafe fn attach_trace_buffer(address: VirtAddr, process: TraceProcess) -> bool {
+    let buffer = address.as_mut_ptr::<TraceBuffer>();
+    if (*buffer).magic != TRACE_MAGIC {
+        return false;
+    }
+    TRACE_PROCESS.store(process as u8, Ordering::Relaxed);
+    TRACE_BUFFER.store(buffer, Ordering::Release);
+    true
+}
+
+/// This function returns the trace buffer, if tracing is active
+pub fn trace_buffer() -> Option<&'static TraceBuffer> {
+    unsafe { TRACE_BUFFER.load(Ordering::Acquire).as_ref() }
+}
+
+/// This function stores the frequency of the TSC in Hz, which is used to convert the timestamps
+pub fn set_tsc_frequency(frequency: u64) {
+    if let Some(buffer) = trace_buffer() {
+        buffer.tsc_frequency.store(frequency, Ordering::Relaxed);
+    }
+}
+
+/// This function records an event with the current TSC timestamp. The events are recorded by the
+/// boot processor only. Without trace buffer, this function does nothing.
+pub fn trace_event(phase: TracePhase, name: &str) {
+    let buffer = TRACE_BUFFER.load(Ordering::Acquire);
+    if buffer.is_null() {
+        return;
+    }
+
+    let buffer = unsafe { &mut *buffer };
+    let index = buffer.event_count.load(Ordering::Relaxed) as usize;
+    if index >= MAX_TRACE_EVENTS {
+        buffer.dropped_events.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let mut event = TraceEvent {
+        timestamp: unsafe { _rdtsc() },
+        process: TRACE_PROCESS.load(Ordering::Relaxed),
+        phase: phase as u8,
+        ..TraceEvent::EMPTY
+    };
+    let length = name.len().min(TRACE_NAME_LENGTH);
+    event.name[..length].copy_from_slice(&name.as_bytes()[..length]);
+    buffer.events[index] = event;
+    buffer
+        .event_count
+        .store(index as u64 + 1, Ordering::Release);
+}
+
+/// This function records the start of a phase and returns the guard, which records the end
+#[must_use]
+pub fn trace_scope(name: &'static str) -> TraceScope {
+    trace_event(TracePhase::Begin, name);
+    TraceScope { name }
+}
+
+/// This function records a point in time without duration
+#[inline]
+pub fn trace_instant(name: &str) {
+    trace_event(TracePhase::Instant, name);
+}
@@ -0,0 +1,316 @@
+//! The host-side stress tests of the frame allocator. The allocator is created from a synthetic
+//! memory map and the frames are backed by a buffer of the host, which is mapped into the allocator
+//! with the physmap offset. Run them with
+//! `cargo test -p libcore --features std-test`. A failure prints the seed of the failed run, which
+//! can be reproduced by adding it to [SEEDS].
+
+use core::alloc::{
+    GlobalAlloc,
+    Layout,
+};
+use libcore::{
+    address::{
+        set_physmap_offset,
+        PhysAddr,
+        VirtAddr,
+    },
+    memory_map::MemoryMap,
+    stress::{
+        run_stress_target,
+        ModelAllocator,
+        ModelMemory,
+        StressConfig,
+    },
+    FrameAllocator,
+};
+use std::sync::Mutex;
+use uefi::table::boot::{
+    MemoryAttribute,
+    MemoryDescriptor,
+    MemoryType,
+};
+
+const FRAME_SIZE: usize = 4096;
+const FRAME_COUNT: usize = 1024;
+const MAX_ALIGNMENT: usize = 64 * 1024;
+
+const SEEDS: &[u64] = &[
+    0x0,
+    0x1,
+    0x5EED,
+    0xC0FF_EE00,
+    0xDEAD_BEEF,
+    0x0123_4567_89AB_CDEF,
+];
+
+/// The physmap offset is global, so the tests, which use it, don't run in parallel
+static PHYSMAP_LOCK: Mutex<()> = Mutex::new(());
+
+/// The byte, which fills the host buffer before the frame allocator is created
+const FILL_BYTE: u8 = 0xCC;
+
+/// This function calls the function with a frame allocator, which is created by [FrameAllocator::new]
+/// from a memory map with the specified descriptors (type, first frame and number of frames). The
+/// physical memory starts at address 0 and is backed by a buffer of the host, which is filled with
+/// [FILL_BYTE] and passed with the allocator.
+fn with_memory_map<R>(
+    descriptors: &[(MemoryType, usize, usize)],
+    function: impl FnOnce(&mut FrameAllocator, *mut u8) -> R,
+) -> R {
+    let _guard = PHYSMAP_LOCK
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    let frame_count = descriptors
+        .iter()
+        .map(|(_, start, count)| start + count)
+        .max()
+        .unwrap();
+    let layout = Layout::from_size_align(frame_count * FRAME_SIZE, MAX_ALIGNMENT).unwrap();
+    let memory = unsafe { std::alloc::alloc(layout) };
+    assert!(!memory.is_null());
+    unsafe { memory.write_bytes(FILL_BYTE, layout.size()) };
+    set_physmap_offset(VirtAddr::from_ptr(memory));
+
+    let descriptor_size = core::mem::size_of::<MemoryDescriptor>();
+    let mut data = vec![0u8; descriptors.len() * descriptor_size];
+    for (index, (ty, start, count)) in descriptors.iter().enumerate() {
+        let descriptor = MemoryDescriptor {
+            ty: *ty,
+            phys_start: (start * FRAME_SIZE) as u64,
+            virt_start: 0,
+            page_count: *count as u64,
+            att: MemoryAttribute::empty(),
+        };
+        let pointer = data[index * descriptor_size..].as_mut_ptr() as *mut MemoryDescriptor;
+        unsafe { pointer.write_unaligned(descriptor) };
+    }
+    let memory_map = MemoryMap::new(&mut data, descriptor_size).unwrap();
+    let mut allocator = FrameAllocator::new(&memory_map, FRAME_SIZE as u16).unwrap();
+
+    let result = function(&mut allocator, memory);
+    unsafe { std::alloc::dealloc(memory, layout) };
+    result
+}
+
+/// This function calls the function with a frame allocator like [with_memory_map], whose memory map
+/// has one conventional descriptor with the specified number of frames
+fn with_mapped_frame_allocator<R>(
+    frame_count: usize, function: impl FnOnce(&mut FrameAllocator, *mut u8) -> R,
+) -> R {
+    with_memory_map(&[(MemoryType::CONVENTIONAL, 0, frame_count)], function)
+}
+
+/// This function returns the host address and the size of the frames of the frame table, which are
+/// reserved by [FrameAllocator::new]
+fn frame_table_frames(allocator: &FrameAllocator) -> (usize, usize) {
+    let address = allocator.frame_table.borrow().frame_table.as_ptr() as usize;
+    (address, allocator.allocated_frames() * FRAME_SIZE)
+}
+
+/// This function runs the stress run with the config against a frame allocator, which manages a
+/// buffer of the host. Only the reserved frames stay allocated after the run.
+fn stress_frame_allocator(config: StressConfig) {
+    let (result, table_size, allocated_frames) =
+        with_mapped_frame_allocator(FRAME_COUNT, |allocator, _| {
+            let (table_address, table_size) = frame_table_frames(allocator);
+            let mut model = ModelAllocator::new(Some(ModelMemory {
+                start: allocator.start_address.to_virt().as_u64() as usize,
+                size: allocator.available_frames() * FRAME_SIZE,
+                granularity: FRAME_SIZE,
+            }));
+            model.reserve(table_address, table_size);
+            let result = unsafe { run_stress_target(allocator, &mut model, &config) };
+            (result, table_size, allocator.allocated_frames())
+        });
+
+    match result {
+        Ok(report) => {
+            assert_eq!(report.allocations, report.frees);
+            assert_eq!(
+                allocated_frames,
+                (table_size + report.reserved) / FRAME_SIZE,
+                "Frames are leaked after seed 0x{:X}",
+                config.seed
+            );
+        }
+        Err(failure) => panic!("{}", failure),
+    }
+}
+
+#[test]
+fn small_allocations() {
+    for seed in SEEDS {
+        stress_frame_allocator(StressConfig {
+            seed: *seed,
+            operations: 4096,
+            max_size: FRAME_SIZE,
+            max_alignment: FRAME_SIZE,
+            max_live: 256,
+        });
+    }
+}
+
+#[test]
+fn large_aligned_allocations() {
+    for seed in SEEDS {
+        stress_frame_allocator(StressConfig {
+            seed: *seed,
+            operations: 2048,
+            max_size: 64 * FRAME_SIZE,
+            max_alignment: MAX_ALIGNMENT,
+            max_live: 32,
+        });
+    }
+}
+
+#[test]
+fn exhaustion() {
+    for seed in SEEDS {
+        stress_frame_allocator(StressConfig {
+            seed: *seed,
+            operations: 4096,
+            max_size: 128 * FRAME_SIZE,
+            max_alignment: 2 * FRAME_SIZE,
+            max_live: FRAME_COUNT,
+        });
+    }
+}
+
+#[test]
+fn contiguous_allocations_below_limit() {
+    const PAGE_COUNT: usize = 4;
+    let alignment = (PAGE_COUNT * FRAME_SIZE) as u64;
+
+    with_mapped_frame_allocator(FRAME_COUNT, |allocator, _| {
+        let table_frames = allocator.allocated_frames();
+        let limit = allocator.start_address + (64 * FRAME_SIZE) as u64;
+        // Shift the free frames, so the alignment has to be applied
+        let offset = allocator
+            .alloc_contiguous(1, limit, FRAME_SIZE as u64)
+            .unwrap();
+
+        let mut addresses = Vec::new();
+        while let Some(address) = allocator.alloc_contiguous(PAGE_COUNT, limit, alignment) {
+            assert!(address.is_aligned(alignment), "0x{:X} isn't aligned", address);
+            assert!(address + alignment <= limit, "0x{:X} exceeds the limit", address);
+            addresses.push(address);
+        }
+        assert_eq!(addresses.len(), 64 / PAGE_COUNT - 1);
+        assert!(allocator
+            .alloc_contiguous(PAGE_COUNT, allocator.start_address, alignment)
+            .is_none());
+
+        for address in addresses {
+            unsafe { allocator.free_contiguous(address, PAGE_COUNT) };
+        }
+        unsafe { allocator.free_contiguous(offset, 1) };
+        assert_eq!(allocator.allocated_frames(), table_frames);
+    });
+}
+
+#[test]
+fn unaligned_frame_table() {
+    const PAGE_COUNT: usize = 3;
+    let alignment = (4 * FRAME_SIZE) as u64;
+    let limit = PhysAddr::new((512 * FRAME_SIZE) as u64);
+
+    // The frame table of 1000 frames has 125 bytes, so the frame table doesn't fill its frame
+    with_mapped_frame_allocator(1000, |allocator, memory| {
+        assert!(allocator.start_address.is_aligned(FRAME_SIZE as u64));
+        let table_frames = allocator.allocated_frames();
+
+        let address = allocator
+            .alloc_contiguous(PAGE_COUNT, limit, alignment)
+            .unwrap();
+        assert!(address.is_aligned(alignment), "0x{:X} isn't aligned", address);
+        assert!(address + (PAGE_COUNT * FRAME_SIZE) as u64 <= limit);
+
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).unwrap();
+        let pointer = unsafe { allocator.alloc(layout) };
+        assert!(!pointer.is_null());
+        assert_eq!((pointer as usize - memory as usize) % FRAME_SIZE, 0);
+
+        unsafe {
+            allocator.dealloc(pointer, layout);
+            allocator.free_contiguous(address, PAGE_COUNT);
+        }
+        assert_eq!(allocator.allocated_frames(), table_frames);
+    });
+}
+
+#[test]
+fn reserved_memory_above_limit() {
+    with_mapped_frame_allocator(FRAME_COUNT, |allocator, memory| {
+        // Hide all frames above the limit like the memory limit of the bootloader
+        let limit = PhysAddr::new((256 * FRAME_SIZE) as u64);
+        let page_count = (allocator.stop_address - limit).div_ceil(FRAME_SIZE as u64);
+        allocator.reserve_region(limit, page_count);
+        assert_eq!(allocator.free_frames_above(limit), 0);
+
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).unwrap();
+        let mut pointers = Vec::new();
+        loop {
+            let pointer = unsafe { allocator.alloc(layout) };
+            if pointer.is_null() {
+                break;
+            }
+            let address = PhysAddr::new((pointer as usize - memory as usize) as u64);
+            assert!(address < limit, "0x{:X} is above the limit", address);
+            pointers.push(pointer);
+        }
+        assert!(allocator
+            .alloc_contiguous(1, allocator.stop_address, FRAME_SIZE as u64)
+            .is_none());
+        assert_eq!(pointers.len(), allocator.available_frames() - page_count as usize);
+
+        for pointer in pointers {
+            unsafe { allocator.dealloc(pointer, layout) };
+        }
+        assert_eq!(allocator.free_frames_above(PhysAddr::new(0)), 256 - 1);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Write after free")]
+fn write_after_free_at_frame_start() {
+    with_mapped_frame_allocator(FRAME_COUNT, |allocator, _| {
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).unwrap();
+        unsafe {
+            let pointer = allocator.alloc(layout);
+            allocator.dealloc(pointer, layout);
+
+            // The first byte of the freed frame is overwritten, so the frame doesn't look poisoned
+            pointer.write(0);
+            allocator.alloc(layout);
+        }
+    });
+}
+
+#[test]
+fn frame_table_in_conventional_memory() {
+    // The low memory is skipped and the second MiB is used by the loader
+    let descriptors = [
+        (MemoryType::CONVENTIONAL, 0, 256),
+        (MemoryType::LOADER_DATA, 256, 256),
+        (MemoryType::CONVENTIONAL, 512, 512),
+    ];
+    with_memory_map(&descriptors, |allocator, memory| {
+        let (table_address, table_size) = frame_table_frames(allocator);
+        assert_eq!(table_address, memory as usize + 512 * FRAME_SIZE);
+        assert_eq!(table_size, FRAME_SIZE);
+
+        // The memory of the other descriptors isn't touched
+        let untouched = unsafe { core::slice::from_raw_parts(memory, 512 * FRAME_SIZE) };
+        assert!(untouched.iter().all(|byte| *byte == FILL_BYTE));
+
+        // The frames of the frame table are never allocated
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).unwrap();
+        loop {
+            let pointer = unsafe { allocator.alloc(layout) } as usize;
+            if pointer == 0 {
+                break;
+            }
+            assert!(pointer + FRAME_SIZE <= table_address || pointer >= table_address + table_size);
+        }
+    });
+}
@@ -0,0 +1,267 @@
+//! The host-side tests of the encoding of the boot information. The bootloader and the kernel are
+//! updated independently, so the decoder has to accept the tags of older and newer bootloaders. Run
+//! them with `cargo test -p libcore --features std-test`.
+
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::{
+        BootInfo,
+        FrameAllocatorHandoff,
+        FramebufferInfo,
+        Orientation,
+        PixelFormat,
+        ReservedRegion,
+        TagType,
+        TagWriter,
+        Tags,
+        TlsTemplate,
+        BOOT_INFO_VERSION,
+    },
+    build_stamp::BuildStamp,
+    error::Error,
+};
+
+/// The raw type of a tag, which is unknown to this decoder (like a tag of a newer bootloader)
+const UNKNOWN_TAG_TYPE: u32 = 0x1000;
+
+/// This function returns boot information, in which every field has a distinct value, so a field,
+/// which is encoded into the wrong word, is detected
+fn sample_boot_info() -> BootInfo {
+    BootInfo {
+        initrd_address: PhysAddr::new(0x0300_0000),
+        initrd_size: 0x2_0000,
+        kernel_address: PhysAddr::new(0x0100_0000),
+        kernel_size: 0x4_0000,
+        kernel_slide: -0x1000,
+        command_line_address: PhysAddr::new(0x0200_0000),
+        command_line_size: 42,
+        rsdp_address: PhysAddr::new(0x000E_0000),
+        device_tree_address: PhysAddr::new(0x0400_0000),
+        framebuffer: FramebufferInfo {
+            address: PhysAddr::new(0x8000_0000),
+            size: 1024 * 768 * 2,
+            swap_buffer_address: PhysAddr::new(0x0500_0000),
+            width: 1024,
+            height: 768,
+            stride: 1088,
+            pixel_format: PixelFormat::Rgb565,
+            orientation: Orientation::Rotated270,
+        },
+        frame_allocator: FrameAllocatorHandoff {
+            frame_table_address: PhysAddr::new(0x1000),
+            frame_table_size: 0x800,
+            start_address: PhysAddr::new(0x2000),
+            stop_address: PhysAddr::new(0x4000_0000),
+            page_size: 4096,
+            reserved_regions_address: PhysAddr::new(0x0600_0000),
+            reserved_region_count: 7,
+            poison_table_address: PhysAddr::new(0x0B00_0000),
+        },
+        boot_units_address: PhysAddr::new(0x0700_0000),
+        boot_unit_count: 3,
+        persistent_log_address: PhysAddr::new(0x0800_0000),
+        persistent_log_size: 0x1_0000,
+        crash_kernel_region: ReservedRegion {
+            address: PhysAddr::new(0x1000_0000),
+            page_count: 0x4000,
+        },
+        memory_map_address: PhysAddr::new(0x0900_0000),
+        memory_map_size: 0x3000,
+        memory_descriptor_size: 48,
+        runtime_services_address: VirtAddr::new(0xFFFF_FFFE_0000_0000),
+        tls_template: TlsTemplate {
+            address: VirtAddr::new(0xFFFF_FFFF_8010_0000),
+            file_size: 0x80,
+            memory_size: 0x200,
+            alignment: 64,
+        },
+        bootloader_stamp: BuildStamp::new("1.0.0-dev.1", "0123456789abcdef", "2026-01-01", "release"),
+        trace_buffer_address: PhysAddr::new(0x0A00_0000),
+    }
+}
+
+/// This function encodes the boot information into a new buffer
+fn encode(boot_info: &BootInfo) -> Vec<u8> {
+    let mut buffer = vec![0u8; 4096];
+    let length = boot_info.encode(&mut buffer).unwrap();
+    buffer.truncate(length);
+    buffer
+}
+
+#[test]
+fn round_trip_of_all_tags() {
+    let expected = sample_boot_info();
+    let data = encode(&expected);
+
+    // Every tag except the end tag is encoded exactly once
+    let tag_types = Tags::parse(&data)
+        .unwrap()
+        .map(|tag| tag.tag_type)
+        .collect::<Vec<_>>();
+    let all_tag_types = (1..)
+        .map_while(TagType::from_raw)
+        .map(|tag_type| tag_type as u32)
+        .collect::<Vec<_>>();
+    assert_eq!(tag_types, all_tag_types);
+
+    let decoded = BootInfo::decode(&data).unwrap();
+    assert_eq!(decoded.initrd_address, expected.initrd_address);
+    assert_eq!(decoded.initrd_size, expected.initrd_size);
+    assert_eq!(decoded.kernel_address, expected.kernel_address);
+    assert_eq!(decoded.kernel_size, expected.kernel_size);
+    assert_eq!(decoded.kernel_slide, expected.kernel_slide);
+    assert_eq!(decoded.command_line_address, expected.command_line_address);
+    assert_eq!(decoded.command_line_size, expected.command_line_size);
+    assert_eq!(decoded.rsdp_address, expected.rsdp_address);
+    assert_eq!(decoded.device_tree_address, expected.device_tree_address);
+
+    let (framebuffer, expected_framebuffer) = (decoded.framebuffer, expected.framebuffer);
+    assert_eq!(framebuffer.address, expected_framebuffer.address);
+    assert_eq!(framebuffer.size, expected_framebuffer.size);
+    assert_eq!(framebuffer.swap_buffer_address, expected_framebuffer.swap_buffer_address);
+    assert_eq!(framebuffer.width, expected_framebuffer.width);
+    assert_eq!(framebuffer.height, expected_framebuffer.height);
+    assert_eq!(framebuffer.stride, expected_framebuffer.stride);
+    assert_eq!(framebuffer.pixel_format, expected_framebuffer.pixel_format);
+    assert_eq!(framebuffer.orientation, expected_framebuffer.orientation);
+
+    let (frame_allocator, expected_allocator) = (decoded.frame_allocator, expected.frame_allocator);
+    assert_eq!(frame_allocator.frame_table_address, expected_allocator.frame_table_address);
+    assert_eq!(frame_allocator.frame_table_size, expected_allocator.frame_table_size);
+    assert_eq!(frame_allocator.start_address, expected_allocator.start_address);
+    assert_eq!(frame_allocator.stop_address, expected_allocator.stop_address);
+    assert_eq!(frame_allocator.page_size, expected_allocator.page_size);
+    assert_eq!(frame_allocator.reserved_regions_address, expected_allocator.reserved_regions_address);
+    assert_eq!(frame_allocator.reserved_region_count, expected_allocator.reserved_region_count);
+    assert_eq!(frame_allocator.poison_table_address, expected_allocator.poison_table_address);
+
+    assert_eq!(decoded.boot_units_address, expected.boot_units_address);
+    assert_eq!(decoded.boot_unit_count, expected.boot_unit_count);
+    assert_eq!(decoded.persistent_log_address, expected.persistent_log_address);
+    assert_eq!(decoded.persistent_log_size, expected.persistent_log_size);
+    assert_eq!(decoded.crash_kernel_region.address, expected.crash_kernel_region.address);
+    assert_eq!(decoded.crash_kernel_region.page_count, expected.crash_kernel_region.page_count);
+    assert_eq!(decoded.memory_map_address, expected.memory_map_address);
+    assert_eq!(decoded.memory_map_size, expected.memory_map_size);
+    assert_eq!(decoded.memory_descriptor_size, expected.memory_descriptor_size);
+    assert_eq!(decoded.runtime_services_address, expected.runtime_services_address);
+    assert_eq!(decoded.tls_template.address, expected.tls_template.address);
+    assert_eq!(decoded.tls_template.file_size, expected.tls_template.file_size);
+    assert_eq!(decoded.tls_template.memory_size, expected.tls_template.memory_size);
+    assert_eq!(decoded.tls_template.alignment, expected.tls_template.alignment);
+    assert_eq!(decoded.bootloader_stamp.to_words(), expected.bootloader_stamp.to_words());
+    assert_eq!(decoded.trace_buffer_address, expected.trace_buffer_address);
+}
+
+#[test]
+fn unknown_tags_are_skipped() {
+    let mut buffer = [0u8; 256];
+    let mut writer = TagWriter::new(&mut buffer).unwrap();
+    writer.push(TagType::Rsdp, &[0x000E_0000]).unwrap();
+    writer
+        .push(TagType::DeviceTree, &[0x0400_0000, 1, 2])
+        .unwrap();
+    writer.push(TagType::Trace, &[0x0A00_0000]).unwrap();
+    let length = writer.finish().unwrap();
+
+    // Change the device tree tag into a tag of a newer bootloader with more words
+    let offset = 16 + 8 + 8;
+    buffer[offset..offset + 4].copy_from_slice(&UNKNOWN_TAG_TYPE.to_le_bytes());
+    let data = &buffer[..length];
+
+    let tag_types = Tags::parse(data)
+        .unwrap()
+        .map(|tag| tag.tag_type)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tag_types,
+        [
+            TagType::Rsdp as u32,
+            UNKNOWN_TAG_TYPE,
+            TagType::Trace as u32
+        ]
+    );
+
+    let boot_info = BootInfo::decode(data).unwrap();
+    assert_eq!(boot_info.rsdp_address, PhysAddr::new(0x000E_0000));
+    assert!(boot_info.device_tree_address.is_null());
+    assert_eq!(boot_info.trace_buffer_address, PhysAddr::new(0x0A00_0000));
+}
+
+#[test]
+fn invalid_magic_is_rejected() {
+    let mut data = encode(&sample_boot_info());
+    data[0] ^= 0xFF;
+    assert!(matches!(BootInfo::decode(&data), Err(Error::InvalidBootInfoMagic(_))));
+    assert!(matches!(BootInfo::decode(&data[..4]), Err(Error::BootInfoOutOfBounds(0))));
+}
+
+#[test]
+fn unsupported_version_is_rejected() {
+    let mut data = encode(&sample_boot_info());
+    for version in [0, BOOT_INFO_VERSION + 1] {
+        data[8..12].copy_from_slice(&version.to_le_bytes());
+        assert!(matches!(
+            BootInfo::decode(&data),
+            Err(Error::UnsupportedBootInfoVersion(found)) if found == version
+        ));
+    }
+}
+
+#[test]
+fn truncated_data_is_rejected() {
+    let data = encode(&sample_boot_info());
+
+    // The data is shorter than the length in the header
+    for length in [16, 20, data.len() / 2, data.len() - 1] {
+        assert!(matches!(BootInfo::decode(&data[..length]), Err(Error::BootInfoOutOfBounds(_))));
+    }
+
+    // The length in the header cuts the end tag
+    let mut truncated = data.clone();
+    truncated[12..16].copy_from_slice(&(data.len() as u32 - 8).to_le_bytes());
+    assert!(matches!(BootInfo::decode(&truncated), Err(Error::BootInfoOutOfBounds(_))));
+
+    // The word count of the first tag exceeds the length
+    let mut truncated = data;
+    truncated[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(BootInfo::decode(&truncated), Err(Error::BootInfoOutOfBounds(16))));
+}
+
+#[test]
+fn older_boot_info_is_decoded() {
+    // An older bootloader encoded the framebuffer without the pixel format and the orientation, the
+    // kernel without the slide and had no TLS, stamp and trace tags
+    let mut buffer = [0u8; 256];
+    let mut writer = TagWriter::new(&mut buffer).unwrap();
+    writer
+        .push(TagType::Kernel, &[0x0100_0000, 0x4_0000])
+        .unwrap();
+    writer
+        .push(TagType::Framebuffer, &[0x8000_0000, 800 * 600 * 4, 0, 800, 600, 800])
+        .unwrap();
+    writer.push(TagType::Rsdp, &[0x000E_0000]).unwrap();
+    let length = writer.finish().unwrap();
+
+    let boot_info = BootInfo::decode(&buffer[..length]).unwrap();
+    assert_eq!(boot_info.kernel_address, PhysAddr::new(0x0100_0000));
+    assert_eq!(boot_info.kernel_size, 0x4_0000);
+    assert_eq!(boot_info.kernel_slide, 0);
+    assert_eq!(boot_info.framebuffer.address, PhysAddr::new(0x8000_0000));
+    assert_eq!(boot_info.framebuffer.width, 800);
+    assert_eq!(boot_info.framebuffer.stride, 800);
+    assert!(boot_info.framebuffer.swap_buffer_address.is_null());
+    assert_eq!(boot_info.framebuffer.pixel_format, PixelFormat::Xrgb8888);
+    assert_eq!(boot_info.framebuffer.orientation, Orientation::Normal);
+    assert_eq!(boot_info.rsdp_address, PhysAddr::new(0x000E_0000));
+
+    // The fields of the missing tags keep their default value
+    assert!(!boot_info.tls_template.is_present());
+    assert!(!boot_info.bootloader_stamp.is_present());
+    assert!(boot_info.trace_buffer_address.is_null());
+    assert!(boot_info.runtime_services_address.is_null());
+    assert!(boot_info.frame_allocator.start_address.is_null());
+}
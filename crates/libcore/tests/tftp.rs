@@ -0,0 +1,37 @@
+//! The host-side tests of the TFTP packets, which are reported by the PXE callback of the network
+//! boot. Run them with `cargo test -p libcore --features std-test`.
+
+use libcore::tftp::{
+    received_payload_length,
+    PXE_FUNCTION_MTFTP,
+};
+
+/// The function of the PXE Base Code Protocol for UDP reads, which isn't a TFTP transfer
+const PXE_FUNCTION_UDP_READ: u32 = 5;
+
+/// This function returns a TFTP data packet with the block number and the specified payload length
+fn data_packet(block: u16, payload_length: usize) -> Vec<u8> {
+    let mut packet = vec![0, 3];
+    packet.extend_from_slice(&block.to_be_bytes());
+    packet.resize(packet.len() + payload_length, 0xAB);
+    packet
+}
+
+#[test]
+fn data_packet_reports_payload() {
+    assert_eq!(PXE_FUNCTION_MTFTP, 3);
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, true, &data_packet(1, 512)), Some(512));
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, true, &data_packet(7, 0)), Some(0));
+}
+
+#[test]
+fn other_packets_are_ignored() {
+    let packet = data_packet(1, 512);
+    assert_eq!(received_payload_length(PXE_FUNCTION_UDP_READ, true, &packet), None);
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, false, &packet), None);
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, true, &packet[..3]), None);
+
+    // Acknowledgements (opcode 4) and errors (opcode 5) have no payload
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, true, &[0, 4, 0, 1]), None);
+    assert_eq!(received_payload_length(PXE_FUNCTION_MTFTP, true, &[0, 5, 0, 1, 0]), None);
+}
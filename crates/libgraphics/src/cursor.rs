@@ -0,0 +1,137 @@
+use crate::{
+    error::Error,
+    present,
+    GRAPHICS_CONTEXT,
+};
+use alloc::vec::Vec;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::RgbColor,
+};
+
+/// The sprite of the cursor, `#` is drawn black, `.` is drawn white and spaces are transparent
+#[rustfmt::skip]
+const CURSOR_SPRITE: [&str; 16] = [
+    "#",
+    "##",
+    "#.#",
+    "#..#",
+    "#...#",
+    "#....#",
+    "#.....#",
+    "#......#",
+    "#.......#",
+    "#........#",
+    "#.....#####",
+    "#..#..#",
+    "#.# #..#",
+    "##  #..#",
+    "     #..#",
+    "     ###",
+];
+
+/// The cursor is drawn directly into the framebuffer over the content of the swap buffer. The
+/// pixels below the cursor are saved, so the cursor can be moved without redrawing the screen.
+static mut CURSOR: Option<CursorOverlay> = None;
+
+struct CursorOverlay {
+    x: usize,
+    y: usize,
+    /// The positions and the colors of the framebuffer pixels, which are covered by the cursor
+    saved_pixels: Vec<(usize, Rgb888)>,
+}
+
+impl CursorOverlay {
+    /// This function saves the pixels below the cursor and draws the cursor into the framebuffer
+    fn draw(&mut self) -> Result<(), Error> {
+        let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+        let (width, height) = context.logical_resolution();
+        self.saved_pixels.clear();
+        for (row, line) in CURSOR_SPRITE.iter().enumerate() {
+            for (column, pixel) in line.bytes().enumerate() {
+                let (x, y) = (self.x + column, self.y + row);
+                let color = match pixel {
+                    b'#' => Rgb888::BLACK,
+                    b'.' => Rgb888::WHITE,
+                    _ => continue,
+                };
+                if x >= width || y >= height {
+                    continue;
+                }
+
+                let index = context.pixel_index(x, y);
+                self.saved_pixels
+                    .push((index, context.framebuffer.get_pixel(index)?));
+                context.framebuffer.set_pixel(index, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// This function restores the pixels, which were covered by the cursor
+    fn restore(&mut self) -> Result<(), Error> {
+        let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+        for (index, color) in self.saved_pixels.drain(..) {
+            context.framebuffer.set_pixel(index, color)?;
+        }
+        Ok(())
+    }
+
+    /// This function moves the cursor to the specified position and shows the changed pixels
+    fn move_to(&mut self, x: usize, y: usize) -> Result<(), Error> {
+        self.restore()?;
+        self.x = x;
+        self.y = y;
+        self.draw()?;
+        present(unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?)
+    }
+}
+
+/// This function shows the cursor at the specified position or moves it there, if it's already
+/// shown. If no context is created, this function returns a [Error::NoContext] error.
+pub fn show_cursor(x: usize, y: usize) -> Result<(), Error> {
+    match unsafe { CURSOR.as_mut() } {
+        Some(cursor) => cursor.move_to(x, y),
+        None => {
+            let mut cursor = CursorOverlay {
+                x,
+                y,
+                saved_pixels: Vec::new(),
+            };
+            cursor.move_to(x, y)?;
+            unsafe { CURSOR = Some(cursor) };
+            Ok(())
+        }
+    }
+}
+
+/// This function removes the cursor from the screen and restores the pixels below the cursor
+pub fn hide_cursor() -> Result<(), Error> {
+    match unsafe { CURSOR.take() } {
+        Some(mut cursor) => {
+            cursor.restore()?;
+            present(unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?)
+        }
+        None => Ok(()),
+    }
+}
+
+/// This function returns the position of the cursor, if the cursor is shown
+pub fn cursor_position() -> Option<(usize, usize)> {
+    unsafe { CURSOR.as_ref() }.map(|cursor| (cursor.x, cursor.y))
+}
+
+/// This function draws the cursor again after the framebuffer was overwritten by the swap buffer.
+/// The saved pixels are outdated, so they are discarded instead of restored.
+pub(crate) fn redraw_cursor() -> Result<(), Error> {
+    match unsafe { CURSOR.as_mut() } {
+        Some(cursor) => cursor.draw(),
+        None => Ok(()),
+    }
+}
+
+/// This function forgets the cursor without restoring the pixels, because the framebuffer was
+/// replaced
+pub(crate) fn discard_cursor() {
+    unsafe { CURSOR = None };
+}
@@ -0,0 +1,321 @@
+use crate::{
+    change_mode,
+    cursor,
+    error::Error,
+    fill_buffer,
+    open_context,
+    swap_buffers,
+    GraphicsContext,
+    GRAPHICS_CONTEXT,
+};
+use alloc::vec::Vec;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::RgbColor,
+};
+use libcore::{
+    config::{
+        DisplayPolicy,
+        ModeFilter,
+    },
+    fastmem,
+};
+use uefi::{
+    prelude::BootServices,
+    proto::console::gop::GraphicsOutput,
+    table::boot::{
+        ScopedProtocol,
+        SearchType,
+    },
+    Handle,
+    Identify,
+};
+
+/// The identifier of a display. The displays are numbered in the order of their GOP handles, handles
+/// without usable framebuffer are skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayId(pub usize);
+
+/// The information about a display, which is returned by [displays]
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayInfo {
+    pub id: DisplayId,
+    /// The resolution of the current mode without rotation
+    pub resolution: (usize, usize),
+    /// The primary display is the display, which is drawn to by all graphical operations
+    pub primary: bool,
+    /// Mirrored displays show a copy of the primary display
+    pub mirrored: bool,
+}
+
+/// All displays of the bootloader. The context of the primary display is moved into
+/// [GRAPHICS_CONTEXT], the other displays keep their contexts until they are selected.
+static mut DISPLAYS: Option<Displays> = None;
+
+struct Displays {
+    displays: Vec<Display>,
+    primary: usize,
+    mirroring: bool,
+}
+
+struct Display {
+    handle: Handle,
+    context: Option<GraphicsContext<'static>>,
+}
+
+impl Displays {
+    fn context(&self, index: usize) -> Option<&GraphicsContext<'static>> {
+        match self.displays.get(index)?.context.as_ref() {
+            Some(context) => Some(context),
+            None => unsafe { GRAPHICS_CONTEXT.as_ref() },
+        }
+    }
+}
+
+/// This function creates a Graphics Context for every GOP handle and installs the context of the
+/// first display. Handles, which can't be opened or which have no supported framebuffer, are
+/// skipped. If no display is usable, this function returns a [Error::NoFramebuffer] error.
+pub(crate) fn open_displays(boot_services: &BootServices) -> Result<(), Error> {
+    let handles =
+        boot_services.locate_handle_buffer(SearchType::ByProtocol(&GraphicsOutput::GUID))?;
+    let mut displays = Vec::new();
+    for handle in handles.iter() {
+        if let Ok(context) = open_context(boot_services, *handle) {
+            displays.push(Display {
+                handle: *handle,
+                context: Some(context),
+            });
+        }
+    }
+
+    let context = displays
+        .first_mut()
+        .and_then(|display| display.context.take())
+        .ok_or(Error::NoFramebuffer)?;
+    unsafe {
+        GRAPHICS_CONTEXT = Some(context);
+        DISPLAYS = Some(Displays {
+            displays,
+            primary: 0,
+            mirroring: false,
+        });
+    }
+    Ok(())
+}
+
+/// This function forgets all displays for the handoff to the kernel. The buffers of the displays
+/// can't be returned to the pool after exiting the Boot Services.
+pub(crate) fn release_displays() {
+    if let Some(displays) = unsafe { DISPLAYS.take() } {
+        core::mem::forget(displays);
+    }
+}
+
+/// This function returns the GOP handle of the primary display, if the displays were opened by the
+/// bootloader
+pub(crate) fn primary_handle() -> Option<Handle> {
+    let displays = unsafe { DISPLAYS.as_ref() }?;
+    Some(displays.displays[displays.primary].handle)
+}
+
+/// This function copies the swap buffer of the primary display into the framebuffers of all
+/// displays with the same mode, if mirroring is enabled
+pub(crate) fn mirror_swap_buffer(source: &GraphicsContext) {
+    let Some(displays) = unsafe { DISPLAYS.as_mut() }.filter(|displays| displays.mirroring) else {
+        return;
+    };
+
+    let bytes_per_pixel = source.pixel_format.bytes_per_pixel();
+    let (width, height) = source.resolution;
+    let row_length = width * bytes_per_pixel;
+    for target in displays
+        .displays
+        .iter_mut()
+        .filter_map(|display| display.context.as_mut())
+        .filter(|target| can_mirror(source, target))
+    {
+        // The strides of the displays may differ, so the rows are copied separately
+        for row in 0..height {
+            let source_start = row * source.stride * bytes_per_pixel;
+            let target_start = row * target.stride * bytes_per_pixel;
+            fastmem::copy(
+                &mut target.framebuffer.as_bytes_mut()[target_start..target_start + row_length],
+                &source.swap_buffer.as_bytes()[source_start..source_start + row_length],
+            );
+        }
+    }
+}
+
+/// This function returns, whether the target can show a copy of the source. Displays in a BltOnly
+/// mode aren't mirrored, because the copy isn't transferred with Blt.
+fn can_mirror(source: &GraphicsContext, target: &GraphicsContext) -> bool {
+    source.resolution == target.resolution
+        && source.pixel_format == target.pixel_format
+        && target.blt_output.is_none()
+}
+
+fn clear_framebuffer(context: &mut GraphicsContext) -> Result<(), Error> {
+    let (_, height) = context.resolution;
+    let length = (context.stride * height).min(context.framebuffer.len());
+    context.framebuffer.fill_pixels(0, length, Rgb888::BLACK)
+}
+
+/// This function returns the information about all displays. If the context wasn't created by the
+/// bootloader, no displays are returned.
+pub fn displays() -> Vec<DisplayInfo> {
+    let Some(displays) = (unsafe { DISPLAYS.as_ref() }) else {
+        return Vec::new();
+    };
+    let Some(primary) = displays.context(displays.primary) else {
+        return Vec::new();
+    };
+
+    (0..displays.displays.len())
+        .filter_map(|index| {
+            let context = displays.context(index)?;
+            let is_primary = index == displays.primary;
+            Some(DisplayInfo {
+                id: DisplayId(index),
+                resolution: context.resolution,
+                primary: is_primary,
+                mirrored: displays.mirroring && !is_primary && can_mirror(primary, context),
+            })
+        })
+        .collect()
+}
+
+/// This function returns the identifier of the primary display. If no context is created by the
+/// bootloader, this function returns a [Error::NoContext] error.
+pub fn primary_display() -> Result<DisplayId, Error> {
+    Ok(DisplayId(
+        unsafe { DISPLAYS.as_ref() }
+            .ok_or(Error::NoContext)?
+            .primary,
+    ))
+}
+
+/// This function makes the specified display the primary display. The orientation is taken over
+/// from the previous primary display, which is cleared unless it's mirrored. The swap buffer of the
+/// new primary display is cleared. If the display doesn't exist, this function returns a
+/// [Error::UnknownDisplay] error.
+pub fn select_display(id: DisplayId) -> Result<(), Error> {
+    let displays = unsafe { DISPLAYS.as_mut() }.ok_or(Error::NoContext)?;
+    if id.0 >= displays.displays.len() {
+        return Err(Error::UnknownDisplay(id.0));
+    }
+    if id.0 == displays.primary {
+        return Ok(());
+    }
+
+    // The cursor restores the pixels of the previous primary display, before the context is moved
+    cursor::hide_cursor()?;
+    let mut context = displays.displays[id.0]
+        .context
+        .take()
+        .ok_or(Error::NoContext)?;
+    let mut previous = unsafe { GRAPHICS_CONTEXT.take() }.ok_or(Error::NoContext)?;
+    context.orientation = previous.orientation;
+    if !displays.mirroring {
+        clear_framebuffer(&mut previous)?;
+    }
+
+    displays.displays[displays.primary].context = Some(previous);
+    displays.primary = id.0;
+    unsafe { GRAPHICS_CONTEXT = Some(context) };
+    fill_buffer(Rgb888::BLACK)
+}
+
+/// This function enables or disables the mirroring of the primary display. When it's enabled, the
+/// other displays are switched into the mode of the primary display, if they support it. Displays
+/// without this mode stay dark. If no context is created by the bootloader, this function returns
+/// a [Error::NoContext] error.
+pub fn set_mirroring(boot_services: &BootServices, enabled: bool) -> Result<(), Error> {
+    let displays = unsafe { DISPLAYS.as_mut() }.ok_or(Error::NoContext)?;
+    let (width, height) = unsafe { GRAPHICS_CONTEXT.as_ref() }
+        .ok_or(Error::NoContext)?
+        .resolution;
+    for display in displays.displays.iter_mut() {
+        let Some(context) = display.context.as_mut() else {
+            continue;
+        };
+        if enabled && context.resolution != (width, height) {
+            let _ = change_mode(boot_services, display.handle, context, width, height);
+        }
+        clear_framebuffer(context)?;
+    }
+
+    displays.mirroring = enabled;
+    swap_buffers()
+}
+
+/// This function returns, whether the primary display is mirrored to the other displays
+pub fn is_mirroring() -> bool {
+    unsafe { DISPLAYS.as_ref() }.map_or(false, |displays| displays.mirroring)
+}
+
+/// This function selects the primary display with the specified policy. If the policy references a
+/// display, which doesn't exist, this function returns a [Error::UnknownDisplay] error.
+pub fn apply_display_policy(
+    boot_services: &BootServices, policy: DisplayPolicy,
+) -> Result<(), Error> {
+    match policy {
+        DisplayPolicy::First => select_display(DisplayId(0)),
+        DisplayPolicy::Index(index) => select_display(DisplayId(index)),
+        DisplayPolicy::Largest => {
+            // The iterator is reversed, so the first display wins if the sizes are equal
+            let largest = displays()
+                .into_iter()
+                .rev()
+                .max_by_key(|display| display.resolution.0 * display.resolution.1)
+                .ok_or(Error::NoContext)?;
+            select_display(largest.id)
+        }
+        DisplayPolicy::Mirror => {
+            select_display(DisplayId(0))?;
+            set_mirroring(boot_services, true)
+        }
+    }
+}
+
+/// This function switches every display, whose current mode isn't accepted by the filter, into the
+/// largest mode below the maximum resolution. Modes with the preferred aspect ratio are selected
+/// before larger modes with another aspect ratio. If a display has no mode below the maximum
+/// resolution, this function returns a [Error::NoAllowedMode] error after the other displays.
+pub fn apply_mode_filter(boot_services: &BootServices, filter: ModeFilter) -> Result<(), Error> {
+    let displays = unsafe { DISPLAYS.as_mut() }.ok_or(Error::NoContext)?;
+    let mut result = Ok(());
+    for (index, display) in displays.displays.iter_mut().enumerate() {
+        let context = match display.context.as_mut() {
+            Some(context) => context,
+            None => unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?,
+        };
+        if filter.allows(context.resolution) && filter.prefers(context.resolution) {
+            continue;
+        }
+
+        let Some((width, height)) = filtered_mode(boot_services, display.handle, filter)? else {
+            result = Err(Error::NoAllowedMode(index));
+            continue;
+        };
+        if context.resolution != (width, height) {
+            change_mode(boot_services, display.handle, context, width, height)?;
+        }
+    }
+
+    cursor::discard_cursor();
+    fill_buffer(Rgb888::BLACK)?;
+    result
+}
+
+/// This function returns the resolution of the best mode of the GOP on the specified handle, which
+/// is accepted by the filter
+fn filtered_mode(
+    boot_services: &BootServices, handle: Handle, filter: ModeFilter,
+) -> Result<Option<(usize, usize)>, Error> {
+    let protocol: ScopedProtocol<GraphicsOutput> = boot_services.open_protocol_exclusive(handle)?;
+    Ok(protocol
+        .modes()
+        .map(|mode| mode.info().resolution())
+        .filter(|resolution| filter.allows(*resolution))
+        .max_by_key(|resolution| (filter.prefers(*resolution), resolution.0 * resolution.1)))
+}
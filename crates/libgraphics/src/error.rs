@@ -6,4 +6,9 @@ pub enum Error {
     OutOfBounds,
     NoContext,
     ContextAlreadyCreated,
+    UnsupportedMode(usize, usize),
+    NoFramebuffer,
+    UnsupportedPixelFormat,
+    UnknownDisplay(usize),
+    NoAllowedMode(usize),
 }
@@ -2,45 +2,189 @@
 
 extern crate alloc;
 
+pub mod cursor;
+pub mod display;
 pub mod error;
 pub mod log;
+pub mod pixel;
+pub mod screenshot;
 pub mod text;
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    pixel::{
+        create_pixel_buffer,
+        PixelBuffer,
+        PixelFormat,
+    },
+};
 use ::embedded_graphics::image::Image;
+use alloc::{
+    boxed::Box,
+    vec,
+};
+use core::ptr::NonNull;
 use embedded_graphics::{
     pixelcolor::Rgb888,
     prelude::*,
 };
+use libcore::{
+    address::{
+        PhysAddr,
+        VirtAddr,
+    },
+    boot_info::{
+        FramebufferInfo,
+        Orientation,
+    },
+    fastmem,
+};
 use uefi::{
     prelude::BootServices,
     proto::console::gop::{
+        self,
+        BltOp,
+        BltPixel,
+        BltRegion,
         GraphicsOutput,
         ModeInfo,
+        PixelBitmask,
     },
     table::boot::{
         MemoryType,
+        OpenProtocolAttributes,
+        OpenProtocolParams,
         ScopedProtocol,
-        SearchType,
     },
-    Identify,
+    Handle,
 };
 
 pub mod embedded_graphics {
     pub use embedded_graphics::*;
 }
 
+/// A rectangle in pixel coordinates of the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
 pub static mut GRAPHICS_CONTEXT: Option<GraphicsContext> = None;
 
 pub struct GraphicsContext<'a> {
-    swap_buffer: &'a mut [u32],
-    framebuffer: &'a mut [u32],
-    current_mode: ModeInfo,
+    swap_buffer: Box<dyn PixelBuffer + 'a>,
+    framebuffer: Box<dyn PixelBuffer + 'a>,
+    pixel_format: PixelFormat,
+    resolution: (usize, usize),
+    stride: usize,
+    orientation: Orientation,
+    /// The GOP of displays in a BltOnly mode. These modes have no linear framebuffer, so the
+    /// framebuffer is a copy of the screen in memory, which is transferred with Blt. It can only be
+    /// used while the Boot Services exist.
+    blt_output: Option<NonNull<GraphicsOutput>>,
+}
+
+/// The framebuffer of the current mode of a GOP
+struct ModeFramebuffer {
+    memory: &'static mut [u8],
+    pixel_format: PixelFormat,
+    stride: usize,
+    blt_output: Option<NonNull<GraphicsOutput>>,
+}
+
+impl GraphicsContext<'static> {
+    /// This function creates a Graphics Context for the framebuffer, which was handed over by the
+    /// bootloader. The swap buffer of the bootloader is reused, if it was handed over. Otherwise a
+    /// swap buffer is allocated on the heap.
+    pub fn from_boot_info(info: &FramebufferInfo) -> Result<Self, Error> {
+        if !info.is_present() {
+            return Err(Error::NoFramebuffer);
+        }
+
+        let size = info.size as usize;
+        let swap_buffer = if info.swap_buffer_address.is_null() {
+            vec![0; size].leak()
+        } else {
+            unsafe {
+                core::slice::from_raw_parts_mut(info.swap_buffer_address.to_virt().as_mut_ptr(), size)
+            }
+        };
+        let framebuffer =
+            unsafe { core::slice::from_raw_parts_mut(info.address.to_virt().as_mut_ptr(), size) };
+        Ok(Self {
+            framebuffer: create_pixel_buffer(info.pixel_format, framebuffer),
+            swap_buffer: create_pixel_buffer(info.pixel_format, swap_buffer),
+            pixel_format: info.pixel_format,
+            resolution: (info.width as usize, info.height as usize),
+            stride: info.stride as usize,
+            orientation: info.orientation,
+            blt_output: None,
+        })
+    }
+}
+
+impl GraphicsContext<'_> {
+    /// This function returns the resolution, which is seen by the drawing functions. Width and
+    /// height are swapped, if the screen is rotated by 90 or 270 degrees.
+    fn logical_resolution(&self) -> (usize, usize) {
+        let (width, height) = self.resolution;
+        if self.orientation.is_transposed() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// This function transforms the specified rectangle of the rotated screen into the rectangle of
+    /// the framebuffer, which shows it
+    fn physical_rect(&self, rect: Rect) -> Rect {
+        let (width, height) = self.resolution;
+        match self.orientation {
+            Orientation::Normal => rect,
+            Orientation::Rotated90 => {
+                Rect::new(width - rect.y - rect.height, rect.x, rect.height, rect.width)
+            }
+            Orientation::Rotated180 => {
+                Rect::new(
+                    width - rect.x - rect.width,
+                    height - rect.y - rect.height,
+                    rect.width,
+                    rect.height,
+                )
+            }
+            Orientation::Rotated270 => {
+                Rect::new(rect.y, height - rect.x - rect.width, rect.height, rect.width)
+            }
+        }
+    }
+
+    /// This function returns the index of the framebuffer pixel, which shows the specified position
+    /// of the rotated screen
+    #[inline]
+    fn pixel_index(&self, x: usize, y: usize) -> usize {
+        let rect = self.physical_rect(Rect::new(x, y, 1, 1));
+        rect.y * self.stride + rect.x
+    }
 }
 
 impl OriginDimensions for GraphicsContext<'_> {
     fn size(&self) -> Size {
-        Size::new(self.current_mode.resolution().0 as u32, self.current_mode.resolution().1 as u32)
+        let (width, height) = self.logical_resolution();
+        Size::new(width as u32, height as u32)
     }
 }
 
@@ -52,8 +196,14 @@ impl DrawTarget for GraphicsContext<'_> {
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
+        // Pixels outside of the screen are clipped
+        let (width, height) = self.logical_resolution();
         for Pixel(point, color) in pixels {
-            set_pixel_at(point.x as usize, point.y as usize, color)?;
+            let (x, y) = (point.x as usize, point.y as usize);
+            if point.x >= 0 && point.y >= 0 && x < width && y < height {
+                let index = self.pixel_index(x, y);
+                self.swap_buffer.set_pixel(index, color)?;
+            }
         }
         Ok(())
     }
@@ -62,36 +212,238 @@ impl DrawTarget for GraphicsContext<'_> {
 /// This function tries to get the GraphicsOutputProtocol (GOP) and creates a Graphics Context for
 /// all graphical operations with the help of GOP. The context must be created, when the UEFI
 /// application is in the Boot Services, so this library can allocate the memory for a swap buffer.
-pub fn create_context<'a>(boot_services: &'a BootServices) -> Result<(), Error> {
+/// A context is created for every display, the first display is the primary display until another
+/// display is selected with [display::select_display].
+pub fn create_context(boot_services: &BootServices) -> Result<(), Error> {
     if unsafe { GRAPHICS_CONTEXT.is_some() } {
         return Err(Error::ContextAlreadyCreated);
     }
+    display::open_displays(boot_services)
+}
 
-    let first_handle = *boot_services
-        .locate_handle_buffer(SearchType::ByProtocol(&GraphicsOutput::GUID))
-        .unwrap()
-        .first()
-        .unwrap();
-    let mut protocol: ScopedProtocol<'a, GraphicsOutput> =
-        boot_services.open_protocol_exclusive(first_handle)?;
-
-    let memory = boot_services
-        .allocate_pool(MemoryType::LOADER_DATA, protocol.frame_buffer().size())
-        .unwrap();
-
-    unsafe {
-        GRAPHICS_CONTEXT = Some(GraphicsContext {
-            framebuffer: core::slice::from_raw_parts_mut(
-                protocol.frame_buffer().as_mut_ptr() as *mut u32,
-                protocol.frame_buffer().size(),
-            ),
-            current_mode: protocol.current_mode_info(),
-            swap_buffer: core::slice::from_raw_parts_mut(
-                memory as *mut u32,
-                protocol.frame_buffer().size(),
-            ),
+/// This function creates a Graphics Context for the current mode of the GOP on the specified handle
+fn open_context(
+    boot_services: &BootServices, handle: Handle,
+) -> Result<GraphicsContext<'static>, Error> {
+    let mut protocol: ScopedProtocol<GraphicsOutput> =
+        boot_services.open_protocol_exclusive(handle)?;
+
+    let mode_info = protocol.current_mode_info();
+    let framebuffer = mode_framebuffer(boot_services, handle, &mut protocol)?;
+    let size = framebuffer.memory.len();
+    let memory = boot_services.allocate_pool(MemoryType::LOADER_DATA, size)?;
+    Ok(GraphicsContext {
+        framebuffer: create_pixel_buffer(framebuffer.pixel_format, framebuffer.memory),
+        swap_buffer: create_pixel_buffer(framebuffer.pixel_format, unsafe {
+            core::slice::from_raw_parts_mut(memory, size)
+        }),
+        pixel_format: framebuffer.pixel_format,
+        resolution: mode_info.resolution(),
+        stride: framebuffer.stride,
+        orientation: Orientation::Normal,
+        blt_output: framebuffer.blt_output,
+    })
+}
+
+/// This function returns the framebuffer of the current mode of the GOP. For BltOnly modes, a copy
+/// of the screen is allocated from the pool and the GOP is kept open for the Blt transfers. The GOP
+/// is opened without closing it, because it's used until the Boot Services are exited.
+fn mode_framebuffer(
+    boot_services: &BootServices, handle: Handle, protocol: &mut GraphicsOutput,
+) -> Result<ModeFramebuffer, Error> {
+    let mode_info = protocol.current_mode_info();
+    if mode_info.pixel_format() != gop::PixelFormat::BltOnly {
+        let size = protocol.frame_buffer().size();
+        return Ok(ModeFramebuffer {
+            memory: unsafe {
+                core::slice::from_raw_parts_mut(protocol.frame_buffer().as_mut_ptr(), size)
+            },
+            pixel_format: mode_pixel_format(&mode_info)?,
+            stride: mode_info.stride(),
+            blt_output: None,
         });
     }
+
+    // Blt pixels are stored in the byte order blue, green, red and reserved
+    let (width, height) = mode_info.resolution();
+    let size = width * height * core::mem::size_of::<BltPixel>();
+    let memory = boot_services.allocate_pool(MemoryType::LOADER_DATA, size)?;
+    let mut blt_output = unsafe {
+        boot_services.open_protocol::<GraphicsOutput>(
+            OpenProtocolParams {
+                handle,
+                agent: boot_services.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )?
+    };
+    let pointer = NonNull::from(&mut *blt_output);
+    core::mem::forget(blt_output);
+    Ok(ModeFramebuffer {
+        memory: unsafe { core::slice::from_raw_parts_mut(memory, size) },
+        pixel_format: PixelFormat::Xrgb8888,
+        stride: width,
+        blt_output: Some(pointer),
+    })
+}
+
+/// This function transfers the framebuffer to the screen, if the display is in a BltOnly mode.
+/// Displays with a linear framebuffer show the framebuffer directly.
+pub(crate) fn present(context: &mut GraphicsContext) -> Result<(), Error> {
+    let Some(mut blt_output) = context.blt_output else {
+        return Ok(());
+    };
+
+    let pixels = context.framebuffer.as_bytes();
+    let buffer = unsafe {
+        core::slice::from_raw_parts(
+            pixels.as_ptr() as *const BltPixel,
+            pixels.len() / core::mem::size_of::<BltPixel>(),
+        )
+    };
+    unsafe { blt_output.as_mut() }.blt(BltOp::BufferToVideo {
+        buffer,
+        src: BltRegion::Full,
+        dest: (0, 0),
+        dims: context.resolution,
+    })?;
+    Ok(())
+}
+
+/// This function returns the pixel format of the specified GOP mode. Modes without framebuffer
+/// (BltOnly), which are handled by [mode_framebuffer], and bitmasks, which don't describe a
+/// supported format, return a [Error::UnsupportedPixelFormat] error.
+fn mode_pixel_format(mode_info: &ModeInfo) -> Result<PixelFormat, Error> {
+    match (mode_info.pixel_format(), mode_info.pixel_bitmask()) {
+        (gop::PixelFormat::Bgr, _) => Ok(PixelFormat::Xrgb8888),
+        (gop::PixelFormat::Rgb, _) => Ok(PixelFormat::Xbgr8888),
+        (
+            gop::PixelFormat::Bitmask,
+            Some(PixelBitmask {
+                red,
+                green,
+                blue,
+                reserved,
+            }),
+        ) => {
+            // The number of bits per pixel is given by the highest bit of all masks
+            let bits = 32 - (red | green | blue | reserved).leading_zeros();
+            match (bits, red, green, blue) {
+                (32, 0xFF_0000, 0xFF00, 0xFF) => Ok(PixelFormat::Xrgb8888),
+                (32, 0xFF, 0xFF00, 0xFF_0000) => Ok(PixelFormat::Xbgr8888),
+                (24, 0xFF_0000, 0xFF00, 0xFF) => Ok(PixelFormat::Rgb888Packed),
+                (16, 0xF800, 0x07E0, 0x001F) => Ok(PixelFormat::Rgb565),
+                _ => Err(Error::UnsupportedPixelFormat),
+            }
+        }
+        _ => Err(Error::UnsupportedPixelFormat),
+    }
+}
+
+/// This function installs the specified Graphics Context as the context of all graphical
+/// operations. It's used by the kernel to install the context, which was created from the boot
+/// information. If a context is already installed, this function returns a
+/// [Error::ContextAlreadyCreated] error.
+pub fn install_context(context: GraphicsContext<'static>) -> Result<(), Error> {
+    if unsafe { GRAPHICS_CONTEXT.is_some() } {
+        return Err(Error::ContextAlreadyCreated);
+    }
+
+    unsafe { GRAPHICS_CONTEXT = Some(context) };
+    Ok(())
+}
+
+/// This function prepares the graphics for exiting the Boot Services. Displays in a BltOnly mode
+/// can't be drawn to without the Boot Services, so the contexts are released without handoff and
+/// the graphical output ends here. The kernel falls back to its other consoles (like the serial
+/// port). This function returns, whether the primary display was released.
+pub fn prepare_exit_boot_services() -> bool {
+    let blt_only =
+        unsafe { GRAPHICS_CONTEXT.as_ref() }.map_or(false, |context| context.blt_output.is_some());
+    if blt_only {
+        // The pool memory of the buffers can't be returned after exiting the Boot Services
+        core::mem::forget(unsafe { GRAPHICS_CONTEXT.take() });
+        unsafe { text::TEXT_WRITER_CONTEXT = None };
+        cursor::discard_cursor();
+        display::release_displays();
+    }
+    blt_only
+}
+
+/// This function tears down the Graphics Context and the Text Writer Context and returns the
+/// framebuffer information for the handoff to the kernel. After this call, all graphical
+/// operations fail with a [Error::NoContext] error, so the buffers aren't used after the kernel
+/// took the ownership of them.
+pub fn release_context() -> Result<FramebufferInfo, Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.take() }.ok_or_else(|| Error::NoContext)?;
+    unsafe { text::TEXT_WRITER_CONTEXT = None };
+    cursor::discard_cursor();
+    display::release_displays();
+
+    let (width, height) = context.resolution;
+    let info = FramebufferInfo {
+        address: PhysAddr::new(context.framebuffer.as_bytes().as_ptr() as u64),
+        size: context.framebuffer.as_bytes().len() as u64,
+        swap_buffer_address: PhysAddr::new(context.swap_buffer.as_bytes().as_ptr() as u64),
+        width: width as u32,
+        height: height as u32,
+        stride: context.stride as u32,
+        pixel_format: context.pixel_format,
+        orientation: context.orientation,
+    };
+
+    // The context is released after exiting the Boot Services, so the pixel buffers can't be
+    // returned to the pool anymore
+    core::mem::forget(context);
+    Ok(info)
+}
+
+/// This function switches the GOP of the primary display into the mode with the specified
+/// resolution and replaces the swap buffer with a buffer in the size of the new frame buffer. If no
+/// context is created, this function returns a [Error::NoContext] error.
+pub fn set_resolution(
+    boot_services: &BootServices, width: usize, height: usize,
+) -> Result<(), Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    let handle = display::primary_handle().ok_or_else(|| Error::NoContext)?;
+    change_mode(boot_services, handle, context, width, height)?;
+    cursor::discard_cursor();
+    fill_buffer(Rgb888::BLACK)
+}
+
+/// This function switches the GOP on the specified handle into the mode with the specified
+/// resolution and replaces the buffers of the specified context
+fn change_mode(
+    boot_services: &BootServices, handle: Handle, context: &mut GraphicsContext, width: usize,
+    height: usize,
+) -> Result<(), Error> {
+    let mut protocol: ScopedProtocol<GraphicsOutput> =
+        boot_services.open_protocol_exclusive(handle)?;
+    let mode = protocol
+        .modes()
+        .find(|mode| mode.info().resolution() == (width, height))
+        .ok_or_else(|| Error::UnsupportedMode(width, height))?;
+    protocol.set_mode(&mode)?;
+
+    // Replace the swap buffer, the size of the frame buffer and the pixel format depend on the mode.
+    // The copy of the screen of a BltOnly mode is replaced too.
+    let mode_info = protocol.current_mode_info();
+    let framebuffer = mode_framebuffer(boot_services, handle, &mut protocol)?;
+    let size = framebuffer.memory.len();
+    let memory = boot_services.allocate_pool(MemoryType::LOADER_DATA, size)?;
+    boot_services.free_pool(context.swap_buffer.as_bytes_mut().as_mut_ptr())?;
+    if context.blt_output.is_some() {
+        boot_services.free_pool(context.framebuffer.as_bytes_mut().as_mut_ptr())?;
+    }
+    context.framebuffer = create_pixel_buffer(framebuffer.pixel_format, framebuffer.memory);
+    context.swap_buffer = create_pixel_buffer(framebuffer.pixel_format, unsafe {
+        core::slice::from_raw_parts_mut(memory, size)
+    });
+    context.pixel_format = framebuffer.pixel_format;
+    context.resolution = mode_info.resolution();
+    context.stride = framebuffer.stride;
+    context.blt_output = framebuffer.blt_output;
     Ok(())
 }
 
@@ -99,51 +451,107 @@ pub fn create_context<'a>(boot_services: &'a BootServices) -> Result<(), Error>
 /// created. If no context is created, this function returns a [Error::NoContext] error.
 pub fn set_pixel_at(x: usize, y: usize, color: Rgb888) -> Result<(), Error> {
     let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
-    *context
-        .swap_buffer
-        .get_mut(y * context.current_mode.stride() + x)
-        .ok_or_else(|| Error::OutOfBounds)? =
-        (color.r() as u32) << 16 | (color.g() as u32) << 8 | (color.b() as u32);
-    Ok(())
+    let (width, height) = context.logical_resolution();
+    if x >= width || y >= height {
+        return Err(Error::OutOfBounds);
+    }
+    let index = context.pixel_index(x, y);
+    context.swap_buffer.set_pixel(index, color)
 }
 
 /// This function gets the color on the specified positions, if the context was already created. If
 /// no context is created, this function returns a [Error::NoContext] error.
-pub fn get_pixel_at(x: usize, y: usize) -> Result<u32, Error> {
+pub fn get_pixel_at(x: usize, y: usize) -> Result<Rgb888, Error> {
     let context = unsafe { GRAPHICS_CONTEXT.as_ref() }.ok_or_else(|| Error::NoContext)?;
-    Ok(*context
-        .framebuffer
-        .get(y * context.current_mode.stride() + x)
-        .ok_or_else(|| Error::OutOfBounds)?)
+    let (width, height) = context.logical_resolution();
+    if x >= width || y >= height {
+        return Err(Error::OutOfBounds);
+    }
+    context.framebuffer.get_pixel(context.pixel_index(x, y))
 }
 
 /// This function fills the complete buffer with the specified color, if the context was already
 /// created. If no context is created, this function returns a [Error::NoContext] error.
 pub fn fill_buffer(color: Rgb888) -> Result<(), Error> {
-    let (width, height) = unsafe { GRAPHICS_CONTEXT.as_ref() }
-        .ok_or_else(|| Error::NoContext)?
-        .current_mode
-        .resolution();
-
-    for x in 0..width {
-        for y in 0..height {
-            set_pixel_at(x, y, color)?;
-        }
-    }
-    Ok(())
+    let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    let (_, height) = context.resolution;
+    let length = (context.stride * height).min(context.swap_buffer.len());
+    context.swap_buffer.fill_pixels(0, length, color)
 }
 
 /// This function fills the specified region of the framebuffer with the specified color. If no
 /// context is created, this function returns a [Error::NoContext] error.
 pub fn fill(x: usize, y: usize, width: usize, height: usize, color: Rgb888) -> Result<(), Error> {
-    for cx in x..(x + width) {
-        for cy in y..(y + height) {
-            set_pixel_at(cx, cy, color)?;
-        }
+    let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    let (screen_width, screen_height) = context.logical_resolution();
+    if x + width > screen_width || y + height > screen_height {
+        return Err(Error::OutOfBounds);
+    }
+
+    // A rotated rectangle is still a rectangle, so the rows of the framebuffer are filled
+    let region = context.physical_rect(Rect::new(x, y, width, height));
+    let stride = context.stride;
+    for row in region.y..(region.y + region.height) {
+        context
+            .swap_buffer
+            .fill_pixels(row * stride + region.x, region.width, color)?;
     }
     Ok(())
 }
 
+/// This function copies the pixels of the source rectangle to the destination point in the swap
+/// buffer. The rows are moved with memmove in an order, that overlapping rectangles are copied
+/// correctly. If no context is created, this function returns a [Error::NoContext] error.
+pub fn blit(source: Rect, destination_x: usize, destination_y: usize) -> Result<(), Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    let (screen_width, screen_height) = context.logical_resolution();
+    if source.x + source.width > screen_width
+        || source.y + source.height > screen_height
+        || destination_x + source.width > screen_width
+        || destination_y + source.height > screen_height
+    {
+        return Err(Error::OutOfBounds);
+    }
+
+    // The rotation maps the move to a move of the physical rectangle, which is copied by rows
+    let destination =
+        context.physical_rect(Rect::new(destination_x, destination_y, source.width, source.height));
+    let source = context.physical_rect(source);
+    let (destination_x, destination_y) = (destination.x, destination.y);
+
+    let stride = context.stride;
+    let mut copy_row = |row: usize| {
+        let source_start = (source.y + row) * stride + source.x;
+        let destination_start = (destination_y + row) * stride + destination_x;
+        context
+            .swap_buffer
+            .copy_pixels(source_start, destination_start, source.width)
+    };
+
+    // Copy from bottom to top, if the destination is below the source
+    if destination_y > source.y {
+        (0..source.height).rev().try_for_each(&mut copy_row)
+    } else {
+        (0..source.height).try_for_each(&mut copy_row)
+    }
+}
+
+/// This function scrolls the content of the specified region up by the specified number of pixel
+/// rows and fills the released rows at the bottom with the specified color. If no context is
+/// created, this function returns a [Error::NoContext] error.
+pub fn scroll_up(region: Rect, rows: usize, fill_color: Rgb888) -> Result<(), Error> {
+    if rows >= region.height {
+        return fill(region.x, region.y, region.width, region.height, fill_color);
+    }
+
+    blit(
+        Rect::new(region.x, region.y + rows, region.width, region.height - rows),
+        region.x,
+        region.y,
+    )?;
+    fill(region.x, region.y + region.height - rows, region.width, rows, fill_color)
+}
+
 /// This functions creates a image at the specified position and writes it into the framebuffer. If
 /// no context is created, this function returns a [Error::NoContext] error.
 pub fn draw_image<T: ImageDrawable<Color = Rgb888>>(
@@ -156,16 +564,67 @@ pub fn draw_image<T: ImageDrawable<Color = Rgb888>>(
 }
 
 /// This function copies the content of the swap buffer into the frame buffer and shows the drawn
-/// screen to the user. If no context is created, this function returns a [Error::NoContext] error.
+/// screen to the user. The content is also copied to the mirrored displays and the text cursor and
+/// the cursor are drawn again on top of the new content. If no context is created, this function
+/// returns a [Error::NoContext] error.
 pub fn swap_buffers() -> Result<(), Error> {
     let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
-    context.framebuffer.copy_from_slice(context.swap_buffer);
-    Ok(())
+    fastmem::copy(context.framebuffer.as_bytes_mut(), context.swap_buffer.as_bytes());
+    display::mirror_swap_buffer(context);
+    text::draw_text_cursor(context)?;
+    cursor::redraw_cursor()?;
+    present(context)
+}
+
+/// This function returns the address and the size in bytes of the frame buffer, which is shown on
+/// the screen. If no context is created, this function returns a [Error::NoContext] error. Displays
+/// in a BltOnly mode have no frame buffer, which is shown on the screen, so this function returns a
+/// [Error::NoFramebuffer] error for them.
+pub fn framebuffer_region() -> Result<(VirtAddr, usize), Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.as_ref() }.ok_or_else(|| Error::NoContext)?;
+    if context.blt_output.is_some() {
+        return Err(Error::NoFramebuffer);
+    }
+
+    let (_, height) = context.resolution;
+    Ok((
+        VirtAddr::from_ptr(context.framebuffer.as_bytes().as_ptr()),
+        context.stride * height * context.pixel_format.bytes_per_pixel(),
+    ))
+}
+
+/// This function returns the pixel format of the framebuffer. If no context is created, this
+/// function returns a [Error::NoContext] error.
+pub fn pixel_format() -> Result<PixelFormat, Error> {
+    Ok(unsafe { GRAPHICS_CONTEXT.as_ref() }
+        .ok_or_else(|| Error::NoContext)?
+        .pixel_format)
 }
 
+/// This function returns the resolution of the screen. Width and height are swapped, if the screen
+/// is rotated by 90 or 270 degrees. If no context is created, this function returns a
+/// [Error::NoContext] error.
 pub fn resolution() -> Result<(usize, usize), Error> {
     Ok(unsafe { GRAPHICS_CONTEXT.as_mut() }
         .ok_or_else(|| Error::NoContext)?
-        .current_mode
-        .resolution())
+        .logical_resolution())
+}
+
+/// This function sets the rotation of the screen content. All drawing functions transform the
+/// coordinates, so the content is shown upright on rotated displays. The content of the swap buffer
+/// is cleared, because it was drawn for the previous orientation. If no context is created, this
+/// function returns a [Error::NoContext] error.
+pub fn set_orientation(orientation: Orientation) -> Result<(), Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    context.orientation = orientation;
+    cursor::discard_cursor();
+    fill_buffer(Rgb888::BLACK)
+}
+
+/// This function returns the rotation of the screen content. If no context is created, this
+/// function returns a [Error::NoContext] error.
+pub fn orientation() -> Result<Orientation, Error> {
+    Ok(unsafe { GRAPHICS_CONTEXT.as_ref() }
+        .ok_or_else(|| Error::NoContext)?
+        .orientation)
 }
@@ -10,11 +10,15 @@ use crate::text::{
     RED,
     TEXT_WRITER_CONTEXT,
 };
-use core::fmt::Write;
+use core::fmt::{
+    self,
+    Write,
+};
 use embedded_graphics::{
     pixelcolor::Rgb888,
     prelude::RgbColor,
 };
+use libcore::persistent_log::PersistentLog;
 use log::{
     set_logger,
     set_max_level,
@@ -26,14 +30,136 @@ use log::{
 
 pub static LOGGER: GOPLogger = GOPLogger;
 
+const LOG_TAIL_SIZE: usize = 4096;
+
+/// The last 4 KiB of the log output, which are written into crash reports
+static mut LOG_TAIL: LogTail = LogTail {
+    buffer: [0; LOG_TAIL_SIZE],
+    position: 0,
+    wrapped: false,
+    written: 0,
+};
+
+/// The persistent log, which keeps the log output across a warm reset. It's installed by the
+/// bootloader and taken over by the kernel.
+static mut PERSISTENT_LOG: Option<PersistentLog> = None;
+
+/// The hook, which receives every log record before it's drawn (like the log capture of the kernel
+/// test mode). Records are passed to the hook, even if the text writer isn't available.
+static mut LOG_HOOK: Option<fn(&Record)> = None;
+
+/// The log tail is a ring buffer, which keeps the newest bytes of the log output
+pub struct LogTail {
+    buffer: [u8; LOG_TAIL_SIZE],
+    position: usize,
+    wrapped: bool,
+    written: u64,
+}
+
+impl LogTail {
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.buffer[self.position] = *byte;
+            self.position = (self.position + 1) % LOG_TAIL_SIZE;
+            self.wrapped |= self.position == 0;
+        }
+        self.written += bytes.len() as u64;
+
+        if let Some(persistent_log) = unsafe { PERSISTENT_LOG.as_mut() } {
+            persistent_log.write(bytes);
+        }
+    }
+}
+
+impl fmt::Write for LogTail {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl LogTail {
+    /// This function returns the content of the ring buffer as two slices, the older bytes are in
+    /// the first slice.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        if self.wrapped {
+            (&self.buffer[self.position..], &self.buffer[..self.position])
+        } else {
+            (&self.buffer[..self.position], &[])
+        }
+    }
+
+    /// This function returns the total number of bytes, which were written into the log tail
+    #[inline]
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// This function returns the bytes, which were written after the specified position of the log
+    /// output, as two slices like [LogTail::contents]. Bytes, which were already overwritten, are
+    /// skipped.
+    pub fn since(&self, position: u64) -> (&[u8], &[u8]) {
+        let (older, newer) = self.contents();
+        let count = self.written.saturating_sub(position) as usize;
+        let count = count.min(older.len() + newer.len());
+        if count <= newer.len() {
+            (&newer[newer.len() - count..], &[])
+        } else {
+            (&older[older.len() - (count - newer.len())..], newer)
+        }
+    }
+}
+
+/// This function appends output, which isn't written with the log macros, to the log tail, so it's
+/// kept for crash reports
+pub fn append_to_log_tail(bytes: &[u8]) {
+    unsafe { LOG_TAIL.push_bytes(bytes) };
+}
+
+/// This function returns the last 4 KiB of the log output
+pub fn log_tail() -> &'static LogTail {
+    unsafe { &LOG_TAIL }
+}
+
+/// This function installs the persistent log, all following log output is also written into it
+pub fn set_persistent_log(persistent_log: PersistentLog) {
+    unsafe { PERSISTENT_LOG = Some(persistent_log) };
+}
+
+/// This function marks the persistent log as closed by a clean shutdown or reboot, so it isn't
+/// offered in the boot menu of the next boot. Without persistent log, this function does nothing.
+pub fn close_persistent_log() {
+    if let Some(persistent_log) = unsafe { PERSISTENT_LOG.as_mut() } {
+        persistent_log.mark_clean();
+    }
+}
+
+/// This function installs the hook, which receives every following log record
+pub fn set_log_hook(hook: fn(&Record)) {
+    unsafe { LOG_HOOK = Some(hook) };
+}
+
 pub struct GOPLogger;
 
 impl Log for GOPLogger {
     fn enabled(&self, _: &Metadata) -> bool {
-        unsafe { TEXT_WRITER_CONTEXT.is_some() }
+        unsafe { TEXT_WRITER_CONTEXT.is_some() || LOG_HOOK.is_some() }
     }
 
     fn log(&self, record: &Record) {
+        if let Some(hook) = unsafe { LOG_HOOK } {
+            hook(record);
+        }
+
+        // The log macros don't check, if the logger is enabled. Without context (e.g. after the
+        // context was released for the kernel handoff), the record is dropped.
+        if unsafe { TEXT_WRITER_CONTEXT.is_none() } {
+            return;
+        }
+
+        // Keep the output for crash reports, before it's drawn
+        unsafe { write!(LOG_TAIL, "[{}] {}", record.level(), record.args()) }.unwrap();
+
         set_color(Rgb888::BLACK, DARK_GRAY).unwrap();
         write_char('[').unwrap();
         match record.level() {
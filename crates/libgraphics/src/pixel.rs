@@ -0,0 +1,242 @@
+use crate::error::Error;
+use alloc::boxed::Box;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::RgbColor,
+};
+pub use libcore::boot_info::PixelFormat;
+use libcore::fastmem;
+
+/// A pixel buffer encodes the colors into the memory layout of a pixel format. All pixels are
+/// addressed by their index (`y * stride + x`), so the drawing functions don't depend on the number
+/// of bytes per pixel.
+pub trait PixelBuffer {
+    /// This function returns the number of pixels in this buffer
+    fn len(&self) -> usize;
+
+    fn set_pixel(&mut self, index: usize, color: Rgb888) -> Result<(), Error>;
+
+    fn get_pixel(&self, index: usize) -> Result<Rgb888, Error>;
+
+    /// This function fills the specified number of pixels from the start index with the color
+    fn fill_pixels(&mut self, start: usize, count: usize, color: Rgb888) -> Result<(), Error>;
+
+    /// This function copies the specified number of pixels from the source index to the destination
+    /// index. The regions may overlap.
+    fn copy_pixels(&mut self, source: usize, destination: usize, count: usize) -> Result<(), Error>;
+
+    fn as_bytes(&self) -> &[u8];
+
+    fn as_bytes_mut(&mut self) -> &mut [u8];
+}
+
+/// This function creates the pixel buffer of the specified pixel format over the memory
+pub fn create_pixel_buffer<'a>(
+    format: PixelFormat, memory: &'a mut [u8],
+) -> Box<dyn PixelBuffer + 'a> {
+    match format {
+        PixelFormat::Xrgb8888 => Box::new(PixelBuffer32::new(memory, false)),
+        PixelFormat::Xbgr8888 => Box::new(PixelBuffer32::new(memory, true)),
+        PixelFormat::Rgb888Packed => Box::new(PixelBuffer24 { bytes: memory }),
+        PixelFormat::Rgb565 => Box::new(PixelBuffer16::new(memory)),
+    }
+}
+
+#[inline]
+fn check_range(length: usize, start: usize, count: usize) -> Result<(), Error> {
+    match start.checked_add(count) {
+        Some(end) if end <= length => Ok(()),
+        _ => Err(Error::OutOfBounds),
+    }
+}
+
+/// A buffer with 32 bits per pixel. The highest byte is unused.
+pub struct PixelBuffer32<'a> {
+    pixels: &'a mut [u32],
+    red_first: bool,
+}
+
+impl<'a> PixelBuffer32<'a> {
+    fn new(memory: &'a mut [u8], red_first: bool) -> Self {
+        let pixels = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u32, memory.len() / 4)
+        };
+        Self { pixels, red_first }
+    }
+
+    #[inline]
+    fn encode(&self, color: Rgb888) -> u32 {
+        let (low, high) = match self.red_first {
+            true => (color.r(), color.b()),
+            false => (color.b(), color.r()),
+        };
+        (high as u32) << 16 | (color.g() as u32) << 8 | low as u32
+    }
+}
+
+impl PixelBuffer for PixelBuffer32<'_> {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb888) -> Result<(), Error> {
+        let value = self.encode(color);
+        *self.pixels.get_mut(index).ok_or(Error::OutOfBounds)? = value;
+        Ok(())
+    }
+
+    fn get_pixel(&self, index: usize) -> Result<Rgb888, Error> {
+        let value = *self.pixels.get(index).ok_or(Error::OutOfBounds)?;
+        let (low, high) = ((value & 0xFF) as u8, (value >> 16) as u8);
+        let green = (value >> 8) as u8;
+        Ok(match self.red_first {
+            true => Rgb888::new(low, green, high),
+            false => Rgb888::new(high, green, low),
+        })
+    }
+
+    fn fill_pixels(&mut self, start: usize, count: usize, color: Rgb888) -> Result<(), Error> {
+        check_range(self.pixels.len(), start, count)?;
+        let value = self.encode(color);
+        fastmem::fill_u32(&mut self.pixels[start..start + count], value);
+        Ok(())
+    }
+
+    fn copy_pixels(&mut self, source: usize, destination: usize, count: usize) -> Result<(), Error> {
+        check_range(self.pixels.len(), source, count)?;
+        check_range(self.pixels.len(), destination, count)?;
+        self.pixels.copy_within(source..source + count, destination);
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self.pixels.as_ptr() as *const u8, self.pixels.len() * 4)
+        }
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.pixels.as_mut_ptr() as *mut u8,
+                self.pixels.len() * 4,
+            )
+        }
+    }
+}
+
+/// A buffer with 24 bits per pixel, which are packed in the byte order blue, green and red
+pub struct PixelBuffer24<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl PixelBuffer for PixelBuffer24<'_> {
+    fn len(&self) -> usize {
+        self.bytes.len() / 3
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb888) -> Result<(), Error> {
+        check_range(self.len(), index, 1)?;
+        self.bytes[index * 3..index * 3 + 3].copy_from_slice(&[color.b(), color.g(), color.r()]);
+        Ok(())
+    }
+
+    fn get_pixel(&self, index: usize) -> Result<Rgb888, Error> {
+        check_range(self.len(), index, 1)?;
+        let pixel = &self.bytes[index * 3..index * 3 + 3];
+        Ok(Rgb888::new(pixel[2], pixel[1], pixel[0]))
+    }
+
+    fn fill_pixels(&mut self, start: usize, count: usize, color: Rgb888) -> Result<(), Error> {
+        check_range(self.len(), start, count)?;
+        let pixel = [color.b(), color.g(), color.r()];
+        for chunk in self.bytes[start * 3..(start + count) * 3].chunks_exact_mut(3) {
+            chunk.copy_from_slice(&pixel);
+        }
+        Ok(())
+    }
+
+    fn copy_pixels(&mut self, source: usize, destination: usize, count: usize) -> Result<(), Error> {
+        check_range(self.len(), source, count)?;
+        check_range(self.len(), destination, count)?;
+        self.bytes
+            .copy_within(source * 3..(source + count) * 3, destination * 3);
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+}
+
+/// A buffer with 16 bits per pixel in the 5:6:5 format (red in the highest bits)
+pub struct PixelBuffer16<'a> {
+    pixels: &'a mut [u16],
+}
+
+impl<'a> PixelBuffer16<'a> {
+    fn new(memory: &'a mut [u8]) -> Self {
+        let pixels = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u16, memory.len() / 2)
+        };
+        Self { pixels }
+    }
+
+    #[inline]
+    fn encode(color: Rgb888) -> u16 {
+        (color.r() as u16 >> 3) << 11 | (color.g() as u16 >> 2) << 5 | color.b() as u16 >> 3
+    }
+}
+
+impl PixelBuffer for PixelBuffer16<'_> {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb888) -> Result<(), Error> {
+        *self.pixels.get_mut(index).ok_or(Error::OutOfBounds)? = Self::encode(color);
+        Ok(())
+    }
+
+    fn get_pixel(&self, index: usize) -> Result<Rgb888, Error> {
+        let value = *self.pixels.get(index).ok_or(Error::OutOfBounds)?;
+
+        // Expand the channels to 8 bits, so white stays white
+        let red = ((value >> 11) & 0x1F) as u8;
+        let green = ((value >> 5) & 0x3F) as u8;
+        let blue = (value & 0x1F) as u8;
+        Ok(Rgb888::new(red << 3 | red >> 2, green << 2 | green >> 4, blue << 3 | blue >> 2))
+    }
+
+    fn fill_pixels(&mut self, start: usize, count: usize, color: Rgb888) -> Result<(), Error> {
+        check_range(self.pixels.len(), start, count)?;
+        self.pixels[start..start + count].fill(Self::encode(color));
+        Ok(())
+    }
+
+    fn copy_pixels(&mut self, source: usize, destination: usize, count: usize) -> Result<(), Error> {
+        check_range(self.pixels.len(), source, count)?;
+        check_range(self.pixels.len(), destination, count)?;
+        self.pixels.copy_within(source..source + count, destination);
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self.pixels.as_ptr() as *const u8, self.pixels.len() * 2)
+        }
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.pixels.as_mut_ptr() as *mut u8,
+                self.pixels.len() * 2,
+            )
+        }
+    }
+}
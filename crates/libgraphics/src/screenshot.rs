@@ -0,0 +1,47 @@
+use crate::{
+    error::Error,
+    GRAPHICS_CONTEXT,
+};
+use alloc::vec::Vec;
+use embedded_graphics::prelude::RgbColor;
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+
+/// This function encodes the content of the swap buffer as uncompressed 24-bit BMP image. The image
+/// is upright, also if the screen is rotated. If no context is created, this function returns a
+/// [Error::NoContext] error.
+pub fn screenshot() -> Result<Vec<u8>, Error> {
+    let context = unsafe { GRAPHICS_CONTEXT.as_ref() }.ok_or(Error::NoContext)?;
+    let (width, height) = context.logical_resolution();
+
+    // The rows are padded to a multiple of 4 bytes
+    let row_size = (width * 3 + 3) & !3;
+    let image_size = row_size * height;
+    let header_size = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+    let mut image = Vec::with_capacity(header_size + image_size);
+
+    // File header with the file size and the offset of the pixel data
+    image.extend_from_slice(b"BM");
+    image.extend_from_slice(&((header_size + image_size) as u32).to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&(header_size as u32).to_le_bytes());
+
+    // Info header with the size, one plane and 24 bits per pixel without compression
+    image.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    image.extend_from_slice(&(width as i32).to_le_bytes());
+    image.extend_from_slice(&(height as i32).to_le_bytes());
+    image.extend_from_slice(&1u16.to_le_bytes());
+    image.extend_from_slice(&24u16.to_le_bytes());
+    image.extend_from_slice(&[0; 24]);
+
+    // The rows are stored from the bottom to the top with the byte order blue, green and red
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let color = context.swap_buffer.get_pixel(context.pixel_index(x, y))?;
+            image.extend_from_slice(&[color.b(), color.g(), color.r()]);
+        }
+        image.resize(image.len() + row_size - width * 3, 0);
+    }
+    Ok(image)
+}
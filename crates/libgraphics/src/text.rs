@@ -1,9 +1,24 @@
 use crate::{
     embedded_graphics::Drawable,
     error::Error,
+    pixel::{
+        create_pixel_buffer,
+        PixelBuffer,
+        PixelFormat,
+    },
+    GraphicsContext,
+    Rect,
     GRAPHICS_CONTEXT,
 };
-use core::fmt;
+use alloc::{
+    boxed::Box,
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    ops::RangeInclusive,
+};
 use embedded_graphics::{
     mono_font::{
         MonoFont,
@@ -11,8 +26,12 @@ use embedded_graphics::{
     },
     pixelcolor::Rgb888,
     prelude::{
+        DrawTarget,
+        OriginDimensions,
+        Pixel,
         Point,
         RgbColor,
+        Size,
     },
     text::{
         Alignment,
@@ -20,6 +39,7 @@ use embedded_graphics::{
         TextStyleBuilder,
     },
 };
+use libcore::boot_info::Orientation;
 
 pub static DARK_GRAY: Rgb888 = Rgb888::new(90, 90, 90);
 pub static RED: Rgb888 = Rgb888::new(255, 0, 0);
@@ -30,12 +50,173 @@ pub static LIGHT_BLUE: Rgb888 = Rgb888::new(51, 51, 255);
 
 pub static mut TEXT_WRITER_CONTEXT: Option<TextWriterContext> = None;
 
+/// The characters, which are pre-rendered into the glyph atlas. Other characters are rasterized
+/// every time they are drawn.
+const ATLAS_CHARACTERS: RangeInclusive<u8> = b' '..=b'~';
+
+/// The maximum number of glyph atlases (one per color pair), the oldest atlas is dropped first
+const MAX_GLYPH_ATLASES: usize = 4;
+
 pub struct TextWriterContext<'a> {
     font: MonoFont<'a>,
     current_x: usize,
     current_y: usize,
     current_foreground_color: Rgb888,
     current_background_color: Rgb888,
+    glyph_atlases: Vec<GlyphAtlas>,
+    cursor_shape: Option<CursorShape>,
+    /// The phase of the blinking cursor, the cursor is shown again after text was written
+    cursor_hidden: bool,
+}
+
+/// The shape of the text cursor, which shows the cell of the next character
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Underscore,
+}
+
+/// The height of the underscore cursor in pixels
+const UNDERSCORE_HEIGHT: usize = 2;
+
+/// The colors of a text, which is drawn with [draw_text]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextStyle {
+    pub foreground_color: Rgb888,
+    pub background_color: Rgb888,
+}
+
+/// The glyph atlas contains the characters of [ATLAS_CHARACTERS], which are rasterized once with a
+/// color pair in the pixel format of the screen. The glyphs are stacked vertically, so every row of
+/// a glyph is copied with one copy into the swap buffer.
+struct GlyphAtlas {
+    style: TextStyle,
+    pixel_format: PixelFormat,
+    pixels: Vec<u8>,
+}
+
+impl GlyphAtlas {
+    /// This function rasterizes the characters of the atlas with the font and the style
+    fn render(font: &MonoFont, style: TextStyle, pixel_format: PixelFormat) -> Result<Self, Error> {
+        let (width, height) = (font.character_size.width, font.character_size.height);
+        let glyph_count = ATLAS_CHARACTERS.len();
+        let mut pixels =
+            vec![0; glyph_count * (width * height) as usize * pixel_format.bytes_per_pixel()];
+
+        let mut target = AtlasTarget {
+            buffer: create_pixel_buffer(pixel_format, &mut pixels),
+            size: Size::new(width, height * glyph_count as u32),
+        };
+        for (index, character) in ATLAS_CHARACTERS.enumerate() {
+            let position = Point::new(0, (index as u32 * height) as i32);
+            draw_character(&mut target, character as char, position, font, style)?;
+        }
+        drop(target);
+
+        Ok(Self {
+            style,
+            pixel_format,
+            pixels,
+        })
+    }
+
+    /// This function returns the pixels of the glyph of the specified character, if the character
+    /// is part of the atlas
+    fn glyph(&self, character: char, glyph_size: usize) -> Option<&[u8]> {
+        let index = u8::try_from(character)
+            .ok()
+            .filter(|character| ATLAS_CHARACTERS.contains(character))?
+            - ATLAS_CHARACTERS.start();
+        self.pixels.chunks_exact(glyph_size).nth(index as usize)
+    }
+}
+
+/// The draw target, which rasterizes the glyphs into the pixels of the atlas
+struct AtlasTarget<'a> {
+    buffer: Box<dyn PixelBuffer + 'a>,
+    size: Size,
+}
+
+impl OriginDimensions for AtlasTarget<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for AtlasTarget<'_> {
+    type Color = Rgb888;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (width, height) = (self.size.width as i32, self.size.height as i32);
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && point.x < width && point.y < height {
+                self.buffer
+                    .set_pixel((point.y * width + point.x) as usize, color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TextWriterContext<'_> {
+    /// This function returns the index of the glyph atlas of the style. If the atlas doesn't exist,
+    /// it's rendered and the oldest atlas is dropped, if the cache is full.
+    fn glyph_atlas(&mut self, style: TextStyle, pixel_format: PixelFormat) -> Result<usize, Error> {
+        let position = self
+            .glyph_atlases
+            .iter()
+            .position(|atlas| atlas.style == style && atlas.pixel_format == pixel_format);
+        if let Some(index) = position {
+            return Ok(index);
+        }
+
+        let atlas = GlyphAtlas::render(&self.font, style, pixel_format)?;
+        if self.glyph_atlases.len() == MAX_GLYPH_ATLASES {
+            self.glyph_atlases.remove(0);
+        }
+        self.glyph_atlases.push(atlas);
+        Ok(self.glyph_atlases.len() - 1)
+    }
+
+    #[inline]
+    fn style(&self) -> TextStyle {
+        TextStyle {
+            foreground_color: self.current_foreground_color,
+            background_color: self.current_background_color,
+        }
+    }
+}
+
+/// The cursor of [draw_text], which walks the character cells of a text. The cursor moves to the
+/// next row after a line feed and after the last column of a row.
+struct CellCursor {
+    column: usize,
+    row: usize,
+    columns: usize,
+}
+
+impl CellCursor {
+    /// This function advances the cursor over the character and returns the cell of the character.
+    /// Line feeds have no cell.
+    fn advance(&mut self, char: char) -> Option<(usize, usize)> {
+        if char == '\n' {
+            self.column = 0;
+            self.row += 1;
+            return None;
+        }
+
+        let cell = (self.column, self.row);
+        self.column += 1;
+        if self.column >= self.columns {
+            self.column = 0;
+            self.row += 1;
+        }
+        Some(cell)
+    }
 }
 
 impl fmt::Write for TextWriterContext<'_> {
@@ -61,6 +242,9 @@ pub fn create_text_writer_context(font: MonoFont<'static>) -> Result<(), Error>
             current_y: 0,
             current_foreground_color: Rgb888::WHITE,
             current_background_color: Rgb888::BLACK,
+            glyph_atlases: Vec::new(),
+            cursor_shape: None,
+            cursor_hidden: false,
         });
     }
     Ok(())
@@ -76,51 +260,178 @@ pub fn invalidate_text_write_context() -> Result<(), Error> {
 }
 
 pub fn write_char(char: char) -> Result<(), Error> {
-    let graphics_context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
-    let text_writer_context =
-        unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    let mut buffer = [0u8; 4];
+    write_str(char.encode_utf8(&mut buffer))
+}
+
+/// This function draws the text with the style into the character cells from the specified column
+/// and row and returns the column and row after the text. The glyph atlas of the style is looked up
+/// once and line feeds and the right edge of the screen continue the text in the next row. If the
+/// text leaves the screen, the screen is scrolled once by all rows before the text is drawn.
+pub fn draw_text(
+    text: &str, (column, row): (usize, usize), style: TextStyle,
+) -> Result<(usize, usize), Error> {
+    let text_writer_context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    let (width, height) = (
+        text_writer_context.font.character_size.width as usize,
+        text_writer_context.font.character_size.height as usize,
+    );
+    let (screen_width, screen_height) = crate::resolution()?;
+    let (columns, rows) = (screen_width / width, screen_height / height);
+    if columns == 0 || rows == 0 {
+        return Err(Error::OutOfBounds);
+    }
+
+    // The end of the text determines the number of rows, which are scrolled out of the screen
+    let mut cursor = CellCursor {
+        column,
+        row,
+        columns,
+    };
+    text.chars().for_each(|char| {
+        cursor.advance(char);
+    });
+    let scrolled_rows = (cursor.row + 1).saturating_sub(rows);
+    if scrolled_rows > 0 {
+        crate::scroll_up(
+            Rect::new(0, 0, screen_width, rows * height),
+            scrolled_rows * height,
+            style.background_color,
+        )?;
+    }
+
+    let graphics_context = unsafe { GRAPHICS_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    let atlas_index = text_writer_context.glyph_atlas(style, graphics_context.pixel_format)?;
+    let atlas = &text_writer_context.glyph_atlases[atlas_index];
+    let font = &text_writer_context.font;
+    let glyph_size = width * height * graphics_context.pixel_format.bytes_per_pixel();
+
+    // The characters, which were scrolled out of the screen, aren't drawn
+    let mut cursor = CellCursor {
+        column,
+        row,
+        columns,
+    };
+    for char in text.chars() {
+        let Some((column, row)) = cursor.advance(char) else {
+            continue;
+        };
+        let Some(row) = row.checked_sub(scrolled_rows) else {
+            continue;
+        };
+
+        // Characters of the atlas are copied from the atlas, other characters and characters, which
+        // are clipped by the screen, are rasterized
+        let (x, y) = (column * width, row * height);
+        let glyph = atlas
+            .glyph(char, glyph_size)
+            .filter(|_| x + width <= screen_width && y + height <= screen_height);
+        match glyph {
+            Some(glyph) => copy_glyph(graphics_context, glyph, Rect::new(x, y, width, height))?,
+            None => {
+                draw_character(graphics_context, char, Point::new(x as i32, y as i32), font, style)?
+            }
+        }
+    }
+    Ok((cursor.column, cursor.row - scrolled_rows))
+}
 
-    let mut buffer = [0u8; 2];
+/// This function rasterizes the character at the specified position into the draw target
+fn draw_character<T: DrawTarget<Color = Rgb888, Error = Error>>(
+    target: &mut T, char: char, position: Point, font: &MonoFont, style: TextStyle,
+) -> Result<(), Error> {
+    let mut buffer = [0u8; 4];
     Text::with_text_style(
         char.encode_utf8(&mut buffer),
-        Point::new(
-            (text_writer_context.current_x * text_writer_context.font.character_size.width as usize)
-                as i32,
-            (text_writer_context.current_y * text_writer_context.font.character_size.height as usize)
-                as i32,
-        ),
+        position,
         MonoTextStyleBuilder::new()
-            .font(&text_writer_context.font)
-            .text_color(text_writer_context.current_foreground_color)
-            .background_color(text_writer_context.current_background_color)
+            .font(font)
+            .text_color(style.foreground_color)
+            .background_color(style.background_color)
             .build(),
         TextStyleBuilder::new()
             .alignment(Alignment::Left)
             .baseline(embedded_graphics::text::Baseline::Top)
             .build(),
     )
-    .draw(graphics_context)?;
+    .draw(target)?;
+    Ok(())
+}
 
-    text_writer_context.current_x += 1;
-    if text_writer_context.current_x
-        >= graphics_context.current_mode.stride()
-            / text_writer_context.font.character_size.width as usize
-    {
-        next_row()?;
+/// This function copies the pixels of a glyph from the atlas into the specified rectangle of the
+/// swap buffer. Without rotation, the rows of the glyph are copied at once.
+fn copy_glyph(context: &mut GraphicsContext, glyph: &[u8], rect: Rect) -> Result<(), Error> {
+    let bytes_per_pixel = context.pixel_format.bytes_per_pixel();
+    let row_length = rect.width * bytes_per_pixel;
+    let stride = context.stride;
+    if context.orientation != Orientation::Normal {
+        for (index, pixel) in glyph.chunks_exact(bytes_per_pixel).enumerate() {
+            let offset = context
+                .pixel_index(rect.x + index % rect.width, rect.y + index / rect.width)
+                * bytes_per_pixel;
+            context
+                .swap_buffer
+                .as_bytes_mut()
+                .get_mut(offset..offset + bytes_per_pixel)
+                .ok_or(Error::OutOfBounds)?
+                .copy_from_slice(pixel);
+        }
+        return Ok(());
+    }
+
+    let swap_buffer = context.swap_buffer.as_bytes_mut();
+    for (row, pixels) in glyph.chunks_exact(row_length).enumerate() {
+        let offset = ((rect.y + row) * stride + rect.x) * bytes_per_pixel;
+        swap_buffer
+            .get_mut(offset..offset + row_length)
+            .ok_or(Error::OutOfBounds)?
+            .copy_from_slice(pixels);
     }
     Ok(())
 }
 
+/// This function writes the string at the cursor with the current colors and moves the cursor
+/// behind the string
 pub fn write_str(string: &str) -> Result<(), Error> {
-    for char in string.chars() {
-        match char {
-            '\n' => next_row()?,
-            _ => write_char(char)?,
-        }
-    }
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_ref() }.ok_or(Error::NoContext)?;
+    let (column, row) = draw_text(string, (context.current_x, context.current_y), context.style())?;
+    set_cursor(column, row)?;
+    unsafe { TEXT_WRITER_CONTEXT.as_mut() }
+        .ok_or(Error::NoContext)?
+        .cursor_hidden = false;
     Ok(())
 }
 
+/// This function moves the cursor to the specified column and row
+pub fn set_cursor(x: usize, y: usize) -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
+    context.current_x = x;
+    context.current_y = y;
+    Ok(())
+}
+
+/// This function returns the column and row of the cursor
+pub fn cursor() -> Result<(usize, usize), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_ref() }.ok_or_else(|| Error::NoContext)?;
+    Ok((context.current_x, context.current_y))
+}
+
+/// This function returns the width and height of a character cell in pixels
+pub fn character_size() -> Result<(usize, usize), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_ref() }.ok_or_else(|| Error::NoContext)?;
+    Ok((context.font.character_size.width as usize, context.font.character_size.height as usize))
+}
+
+/// This function returns the number of columns and rows of text, which fit on the screen
+pub fn text_size() -> Result<(usize, usize), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_ref() }.ok_or_else(|| Error::NoContext)?;
+    let (width, height) = crate::resolution()?;
+    Ok((
+        width / context.font.character_size.width as usize,
+        height / context.font.character_size.height as usize,
+    ))
+}
+
 pub fn set_color(background_color: Rgb888, foreground_color: Rgb888) -> Result<(), Error> {
     let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
     context.current_foreground_color = foreground_color;
@@ -128,9 +439,79 @@ pub fn set_color(background_color: Rgb888, foreground_color: Rgb888) -> Result<(
     Ok(())
 }
 
+/// This function moves the cursor to the start of the next row. If the cursor leaves the screen, the
+/// text on the screen is scrolled up by one row.
 pub fn next_row() -> Result<(), Error> {
     let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or_else(|| Error::NoContext)?;
     context.current_y += 1;
     context.current_x = 0;
+
+    let (width, height) = crate::resolution()?;
+    let row_height = context.font.character_size.height as usize;
+    let row_count = height / row_height;
+    if context.current_y >= row_count {
+        crate::scroll_up(
+            Rect::new(0, 0, width, row_count * row_height),
+            row_height,
+            context.current_background_color,
+        )?;
+        context.current_y = row_count - 1;
+    }
+    Ok(())
+}
+
+/// This function sets the shape of the text cursor or removes the cursor. The cursor is drawn over
+/// the content of the swap buffer, when the buffers are swapped the next time.
+pub fn set_text_cursor(shape: Option<CursorShape>) -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    context.cursor_shape = shape;
+    context.cursor_hidden = false;
+    Ok(())
+}
+
+/// This function toggles the phase of the blinking text cursor and shows the screen with the
+/// changed cursor. Without text cursor, this function does nothing.
+pub fn blink_text_cursor() -> Result<(), Error> {
+    let context = unsafe { TEXT_WRITER_CONTEXT.as_mut() }.ok_or(Error::NoContext)?;
+    if context.cursor_shape.is_none() {
+        return Ok(());
+    }
+    context.cursor_hidden = !context.cursor_hidden;
+    crate::swap_buffers()
+}
+
+/// This function draws the text cursor into the framebuffer over the content of the swap buffer.
+/// The cursor is removed by the next swap of the buffers.
+pub(crate) fn draw_text_cursor(graphics_context: &mut GraphicsContext) -> Result<(), Error> {
+    let Some(context) = (unsafe { TEXT_WRITER_CONTEXT.as_ref() }) else {
+        return Ok(());
+    };
+    let Some(shape) = context.cursor_shape.filter(|_| !context.cursor_hidden) else {
+        return Ok(());
+    };
+
+    let (width, height) =
+        (context.font.character_size.width as usize, context.font.character_size.height as usize);
+    let (x, y) = (context.current_x * width, context.current_y * height);
+    let (screen_width, screen_height) = graphics_context.logical_resolution();
+    if x + width > screen_width || y + height > screen_height {
+        return Ok(());
+    }
+
+    let cursor = match shape {
+        CursorShape::Block => Rect::new(x, y, width, height),
+        CursorShape::Underscore => {
+            Rect::new(x, y + height - UNDERSCORE_HEIGHT, width, UNDERSCORE_HEIGHT)
+        }
+    };
+    let region = graphics_context.physical_rect(cursor);
+    let stride = graphics_context.stride;
+    for row in region.y..(region.y + region.height) {
+        graphics_context.framebuffer.fill_pixels(
+            row * stride + region.x,
+            region.width,
+            context.current_foreground_color,
+        )?;
+    }
     Ok(())
 }
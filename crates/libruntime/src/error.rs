@@ -0,0 +1,25 @@
+use thiserror_no_std::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Runtime Error: Invalid Runtime Services table at 0x{0:X}")]
+    InvalidTable(u64),
+
+    #[error("Runtime Error: The service failed with status 0x{0:X}")]
+    Status(usize),
+
+    #[error("Runtime Error: The service isn't supported by the firmware")]
+    Unsupported,
+
+    #[error("Runtime Error: Variable not found")]
+    VariableNotFound,
+
+    #[error("Runtime Error: The buffer is too small, the variable has {0} bytes")]
+    BufferTooSmall(usize),
+
+    #[error("Runtime Error: The variable name is longer than {0} characters")]
+    NameTooLong(usize),
+
+    #[error("Runtime Error: The Runtime Services were not initialized")]
+    NotInitialized,
+}
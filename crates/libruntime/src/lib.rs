@@ -0,0 +1,215 @@
+//! The Runtime Services of the UEFI firmware stay usable after exiting the Boot Services. The
+//! bootloader relocates them with `SetVirtualAddressMap` into the runtime window of the higher half,
+//! so the kernel calls the services at the addresses in the window instead of their physical
+//! addresses. The firmware doesn't support concurrent calls, so all calls are serialized by a lock.
+
+#![no_std]
+
+pub mod error;
+pub mod time;
+pub mod variable;
+
+use crate::{
+    error::Error,
+    time::{
+        Time,
+        TimeCapabilities,
+    },
+    variable::{
+        Guid,
+        MAX_NAME_LENGTH,
+    },
+};
+use core::ffi::c_void;
+use libsync::spinlock::Spinlock;
+
+/// The runtime window maps the first 512 GiB of the physical memory with one entry of the PML4 into
+/// the higher half. The memory of the Runtime Services is relocated into this window.
+pub const RUNTIME_WINDOW_OFFSET: u64 = 0xFFFF_FE00_0000_0000;
+pub const RUNTIME_WINDOW_SIZE: u64 = 512 * 1024 * 1024 * 1024;
+
+const RUNTIME_SERVICES_SIGNATURE: u64 = 0x5652_4553_544E_5552;
+
+const ERROR_BIT: usize = 1 << (usize::BITS - 1);
+const UNSUPPORTED: usize = ERROR_BIT | 3;
+const BUFFER_TOO_SMALL: usize = ERROR_BIT | 5;
+const NOT_FOUND: usize = ERROR_BIT | 14;
+
+pub static mut RUNTIME_SERVICES: Option<RuntimeServices> = None;
+
+/// The lock, which serializes the calls into the firmware
+static CALL_LOCK: Spinlock<()> = Spinlock::new(());
+
+/// The type of reset, which is requested with [RuntimeServices::reset]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetType {
+    /// Resets all processors and devices of the system (cold reset)
+    Cold = 0,
+    /// Resets the processors without resetting the devices (warm reset)
+    Warm = 1,
+    /// Powers off the system
+    Shutdown = 2,
+}
+
+/// The table of the Runtime Services (`EFI_RUNTIME_SERVICES`). Only the services, which are wrapped
+/// by [RuntimeServices], are typed.
+#[repr(C)]
+struct RuntimeServicesTable {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+    get_time: unsafe extern "efiapi" fn(*mut Time, *mut TimeCapabilities) -> usize,
+    set_time: unsafe extern "efiapi" fn(*const Time) -> usize,
+    get_wakeup_time: usize,
+    set_wakeup_time: usize,
+    set_virtual_address_map: usize,
+    convert_pointer: usize,
+    get_variable: unsafe extern "efiapi" fn(
+        *const u16,
+        *const Guid,
+        *mut u32,
+        *mut usize,
+        *mut c_void,
+    ) -> usize,
+    get_next_variable_name: usize,
+    set_variable:
+        unsafe extern "efiapi" fn(*const u16, *const Guid, u32, usize, *const c_void) -> usize,
+    get_next_high_monotonic_count: usize,
+    reset_system: unsafe extern "efiapi" fn(u32, usize, usize, *const c_void) -> !,
+}
+
+/// The wrapper of the Runtime Services
+pub struct RuntimeServices {
+    table: &'static RuntimeServicesTable,
+}
+
+impl RuntimeServices {
+    /// This function creates the wrapper of the Runtime Services table at the specified virtual
+    /// address. If the signature of the table is invalid, this function returns an error.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the table and the memory of the Runtime Services are mapped at
+    /// the virtual addresses, which were set with `SetVirtualAddressMap`.
+    pub unsafe fn new(address: u64) -> Result<Self, Error> {
+        let table = &*(address as *const RuntimeServicesTable);
+        if table.signature != RUNTIME_SERVICES_SIGNATURE {
+            return Err(Error::InvalidTable(address));
+        }
+        Ok(Self { table })
+    }
+
+    /// This function returns the revision of the UEFI specification, which is implemented by the
+    /// firmware, as major and minor version
+    #[inline]
+    pub fn revision(&self) -> (u16, u16) {
+        ((self.table.revision >> 16) as u16, (self.table.revision & 0xFFFF) as u16)
+    }
+
+    /// This function reads the current time and the capabilities of the real-time clock
+    pub fn time(&self) -> Result<(Time, TimeCapabilities), Error> {
+        let mut time = Time::default();
+        let mut capabilities = TimeCapabilities::default();
+        let _guard = CALL_LOCK.lock();
+        status_to_result(unsafe { (self.table.get_time)(&mut time, &mut capabilities) })?;
+        Ok((time, capabilities))
+    }
+
+    /// This function sets the time of the real-time clock
+    pub fn set_time(&self, time: &Time) -> Result<(), Error> {
+        let _guard = CALL_LOCK.lock();
+        status_to_result(unsafe { (self.table.set_time)(time) })
+    }
+
+    /// This function reads the specified variable into the buffer and returns the size and the
+    /// attributes of the variable. If the buffer is too small, this function returns an error with
+    /// the size of the variable.
+    pub fn variable(
+        &self, name: &str, vendor: &Guid, buffer: &mut [u8],
+    ) -> Result<(usize, u32), Error> {
+        let name = encode_name(name)?;
+        let mut attributes = 0;
+        let mut size = buffer.len();
+        let _guard = CALL_LOCK.lock();
+        let status = unsafe {
+            (self.table.get_variable)(
+                name.as_ptr(),
+                vendor,
+                &mut attributes,
+                &mut size,
+                buffer.as_mut_ptr() as *mut c_void,
+            )
+        };
+        match status {
+            BUFFER_TOO_SMALL => Err(Error::BufferTooSmall(size)),
+            status => status_to_result(status).map(|_| (size, attributes)),
+        }
+    }
+
+    /// This function writes the specified variable with the attributes. An empty value deletes the
+    /// variable.
+    pub fn set_variable(
+        &self, name: &str, vendor: &Guid, attributes: u32, value: &[u8],
+    ) -> Result<(), Error> {
+        let name = encode_name(name)?;
+        let _guard = CALL_LOCK.lock();
+        status_to_result(unsafe {
+            (self.table.set_variable)(
+                name.as_ptr(),
+                vendor,
+                attributes,
+                value.len(),
+                value.as_ptr() as *const c_void,
+            )
+        })
+    }
+
+    /// This function resets the system with the specified reset type. The lock isn't acquired, so
+    /// the system can be reset while another call (like in a panic) doesn't return.
+    pub fn reset(&self, reset_type: ResetType) -> ! {
+        unsafe { (self.table.reset_system)(reset_type as u32, 0, 0, core::ptr::null()) }
+    }
+}
+
+/// This function initializes the global Runtime Services with the table at the specified virtual
+/// address
+///
+/// # Safety
+/// The caller has to ensure, that the requirements of [RuntimeServices::new] are fulfilled.
+pub unsafe fn init_runtime_services(address: u64) -> Result<&'static RuntimeServices, Error> {
+    RUNTIME_SERVICES = Some(RuntimeServices::new(address)?);
+    runtime_services()
+}
+
+/// This function returns the global Runtime Services, if they were initialized
+pub fn runtime_services() -> Result<&'static RuntimeServices, Error> {
+    unsafe { RUNTIME_SERVICES.as_ref() }.ok_or(Error::NotInitialized)
+}
+
+/// This function converts the name into a null-terminated UCS-2 string
+fn encode_name(name: &str) -> Result<[u16; MAX_NAME_LENGTH + 1], Error> {
+    let mut buffer = [0; MAX_NAME_LENGTH + 1];
+    let mut length = 0;
+    for character in name.encode_utf16() {
+        if length == MAX_NAME_LENGTH {
+            return Err(Error::NameTooLong(MAX_NAME_LENGTH));
+        }
+        buffer[length] = character;
+        length += 1;
+    }
+    Ok(buffer)
+}
+
+#[inline]
+fn status_to_result(status: usize) -> Result<(), Error> {
+    match status {
+        0 => Ok(()),
+        UNSUPPORTED => Err(Error::Unsupported),
+        NOT_FOUND => Err(Error::VariableNotFound),
+        status if status & ERROR_BIT != 0 => Err(Error::Status(status)),
+        // Warnings are ignored
+        _ => Ok(()),
+    }
+}
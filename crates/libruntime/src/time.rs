@@ -0,0 +1,74 @@
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+};
+
+/// The time zone of a time, which is interpreted as local time
+pub const UNSPECIFIED_TIMEZONE: i16 = 0x07FF;
+
+/// The time of the real-time clock (`EFI_TIME`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pad1: u8,
+    pub nanosecond: u32,
+    /// The offset to UTC in minutes or [UNSPECIFIED_TIMEZONE]
+    pub timezone: i16,
+    pub daylight: u8,
+    pad2: u8,
+}
+
+/// The capabilities of the real-time clock (`EFI_TIME_CAPABILITIES`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeCapabilities {
+    /// The resolution of the clock in counts per second
+    pub resolution: u32,
+    /// The accuracy of the clock in parts per million multiplied by 1000000
+    pub accuracy: u32,
+    /// Whether setting the time clears the time below the resolution
+    pub sets_to_zero: bool,
+}
+
+impl Time {
+    /// This function returns the seconds since 1970-01-01 00:00 UTC. If the time zone is
+    /// unspecified, the time is interpreted as UTC.
+    pub fn unix_timestamp(&self) -> u64 {
+        // Days since the epoch of the proleptic Gregorian calendar (with March as the first month)
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        let mut seconds =
+            days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        if self.timezone != UNSPECIFIED_TIMEZONE {
+            seconds -= self.timezone as i64 * 60;
+        }
+        seconds.max(0) as u64
+    }
+}
+
+impl Display for Time {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        match self.timezone {
+            UNSPECIFIED_TIMEZONE => Ok(()),
+            offset => write!(formatter, " UTC{:+03}:{:02}", offset / 60, offset.abs() % 60),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use core::fmt::{
+    self,
+    Display,
+    Formatter,
+};
+
+/// The variable is stored in non-volatile memory and survives a reset
+pub const NON_VOLATILE: u32 = 0x01;
+/// The variable can be accessed with the Boot Services
+pub const BOOTSERVICE_ACCESS: u32 = 0x02;
+/// The variable can be accessed with the Runtime Services
+pub const RUNTIME_ACCESS: u32 = 0x04;
+
+/// The maximum number of characters of a variable name, which is converted to UCS-2 on the stack
+pub const MAX_NAME_LENGTH: usize = 63;
+
+/// The GUID of the vendor, which owns a variable (`EFI_GUID`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        Self {
+            data1,
+            data2,
+            data3,
+            data4,
+        }
+    }
+}
+
+/// The vendor of the architectural variables of the UEFI specification (like `BootOrder`)
+pub const GLOBAL_VARIABLE: Guid =
+    Guid::new(0x8BE4_DF61, 0x93CA, 0x11D2, [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]);
+
+impl Display for Guid {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            self.data1, self.data2, self.data3, self.data4[0], self.data4[1]
+        )?;
+        for byte in &self.data4[2..] {
+            write!(formatter, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
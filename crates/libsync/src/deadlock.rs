@@ -0,0 +1,123 @@
+use core::{
+    arch::x86_64::{
+        __cpuid,
+        _rdtsc,
+    },
+    panic::Location,
+    ptr,
+    sync::atomic::{
+        AtomicPtr,
+        AtomicU32,
+        AtomicU64,
+        Ordering,
+    },
+};
+
+/// The frequency of the timestamp counter is assumed to be 4 GHz until the real frequency is set
+/// with [set_tsc_frequency]. This overestimates the frequency of most CPUs, so the timeout is never
+/// shorter than configured.
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(4_000_000_000);
+static DEADLOCK_TIMEOUT_MS: AtomicU64 = AtomicU64::new(5000);
+
+/// This function sets the frequency of the timestamp counter in Hz, which is used to convert the
+/// deadlock timeout into TSC ticks.
+pub fn set_tsc_frequency(frequency: u64) {
+    TSC_FREQUENCY.store(frequency.max(1), Ordering::Relaxed);
+}
+
+/// This function sets the time in milliseconds a lock can be spun on, before a deadlock is reported
+pub fn set_deadlock_timeout(timeout_ms: u64) {
+    DEADLOCK_TIMEOUT_MS.store(timeout_ms, Ordering::Relaxed);
+}
+
+/// The lock owner records the CPU, the source location and the timestamp of the last acquisition of
+/// a spinlock.
+pub(crate) struct LockOwner {
+    cpu_id: AtomicU32,
+    location: AtomicPtr<Location<'static>>,
+    timestamp: AtomicU64,
+}
+
+impl LockOwner {
+    pub(crate) const fn new() -> Self {
+        Self {
+            cpu_id: AtomicU32::new(0),
+            location: AtomicPtr::new(ptr::null_mut()),
+            timestamp: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, location: &'static Location<'static>) {
+        self.cpu_id.store(current_cpu_id(), Ordering::Relaxed);
+        self.location
+            .store(location as *const _ as *mut _, Ordering::Relaxed);
+        self.timestamp.store(read_tsc(), Ordering::Relaxed);
+    }
+}
+
+pub(crate) struct SpinWatchdog {
+    start_timestamp: u64,
+    timeout_ticks: u64,
+}
+
+impl SpinWatchdog {
+    pub(crate) fn start() -> Self {
+        let frequency = TSC_FREQUENCY.load(Ordering::Relaxed);
+        Self {
+            start_timestamp: read_tsc(),
+            timeout_ticks: DEADLOCK_TIMEOUT_MS
+                .load(Ordering::Relaxed)
+                .saturating_mul(frequency / 1000),
+        }
+    }
+
+    /// This function panics with the information of the waiting and the owning party, if the lock is
+    /// spun on for longer than the deadlock timeout.
+    pub(crate) fn check(
+        &self, lock: *const (), owner: &LockOwner, waiter: &'static Location<'static>,
+    ) {
+        let now = read_tsc();
+        if now.wrapping_sub(self.start_timestamp) < self.timeout_ticks {
+            return;
+        }
+
+        let ticks_per_ms = (TSC_FREQUENCY.load(Ordering::Relaxed) / 1000).max(1);
+        let owner_location = owner.location.load(Ordering::Relaxed);
+        let held_ms = now.wrapping_sub(owner.timestamp.load(Ordering::Relaxed)) / ticks_per_ms;
+        match unsafe { owner_location.as_ref() } {
+            Some(owner_location) => {
+                panic!(
+                    "Deadlock detected on spinlock {:p}: CPU {} waited {} ms at {}, lock held by \
+                     CPU {} since {} ms (acquired at {})",
+                    lock,
+                    current_cpu_id(),
+                    now.wrapping_sub(self.start_timestamp) / ticks_per_ms,
+                    waiter,
+                    owner.cpu_id.load(Ordering::Relaxed),
+                    held_ms,
+                    owner_location
+                )
+            }
+            None => {
+                panic!(
+                    "Deadlock detected on spinlock {:p}: CPU {} waited {} ms at {}, lock owner \
+                     unknown",
+                    lock,
+                    current_cpu_id(),
+                    now.wrapping_sub(self.start_timestamp) / ticks_per_ms,
+                    waiter
+                )
+            }
+        }
+    }
+}
+
+/// This function returns the initial local APIC ID of the current CPU
+fn current_cpu_id() -> u32 {
+    unsafe { __cpuid(0x01) }.ebx >> 24
+}
+
+#[inline]
+fn read_tsc() -> u64 {
+    unsafe { _rdtsc() }
+}
@@ -0,0 +1,18 @@
+#![no_std]
+
+pub mod ring;
+pub mod spinlock;
+pub mod waker;
+
+#[cfg(feature = "deadlock-detection")]
+pub mod deadlock;
+
+pub use ring::{
+    SpscRing,
+    WaitRing,
+};
+pub use spinlock::{
+    Spinlock,
+    SpinlockGuard,
+};
+pub use waker::AtomicWaker;
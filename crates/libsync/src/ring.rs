@@ -0,0 +1,213 @@
+use crate::waker::AtomicWaker;
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    hint::spin_loop,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{
+        AtomicBool,
+        AtomicUsize,
+        Ordering,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+/// The ring buffer is a lock-free single-producer single-consumer queue with a fixed capacity. The
+/// producer can push from interrupt context, because it never waits for the consumer. One slot
+/// stays empty to distinguish a full from an empty ring, so `N - 1` values fit into the ring.
+///
+/// A second producer or consumer, which uses the ring at the same time, is detected and panics.
+pub struct SpscRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    producing: AtomicBool,
+    consuming: AtomicBool,
+}
+
+unsafe impl<T: Send, const N: usize> Send for SpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    const EMPTY_SLOT: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+
+    pub const fn new() -> Self {
+        assert!(N >= 2, "The ring needs at least two slots");
+        Self {
+            slots: [Self::EMPTY_SLOT; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            producing: AtomicBool::new(false),
+            consuming: AtomicBool::new(false),
+        }
+    }
+
+    /// This function appends the value to the ring. If the ring is full, the value is returned.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let _guard = SideGuard::acquire(&self.producing, "producer");
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next_tail = (tail + 1) % N;
+        if next_tail == self.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+
+        unsafe { (*self.slots[tail].get()).write(value) };
+        self.tail.store(next_tail, Ordering::Release);
+        Ok(())
+    }
+
+    /// This function removes the oldest value from the ring or returns `None`, if the ring is empty
+    pub fn pop(&self) -> Option<T> {
+        let _guard = SideGuard::acquire(&self.consuming, "consumer");
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { (*self.slots[head].get()).assume_init_read() };
+        self.head.store((head + 1) % N, Ordering::Release);
+        Some(value)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// The side guard marks the producer or consumer side as used, so concurrent use of one side is
+/// detected instead of corrupting the ring.
+struct SideGuard<'a>(&'a AtomicBool);
+
+impl<'a> SideGuard<'a> {
+    #[inline]
+    fn acquire(flag: &'a AtomicBool, side: &str) -> Self {
+        if flag.swap(true, Ordering::Acquire) {
+            panic!("Ring buffer is used by a second {} at the same time", side);
+        }
+        Self(flag)
+    }
+}
+
+impl Drop for SideGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// The waiting ring extends the ring buffer with a waker, so the consumer can wait for the next
+/// value, either by spinning or as future in an async executor. The producer wakes the consumer on
+/// every push.
+pub struct WaitRing<T, const N: usize> {
+    ring: SpscRing<T, N>,
+    waker: AtomicWaker,
+}
+
+impl<T, const N: usize> WaitRing<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            ring: SpscRing::new(),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// This function appends the value to the ring and wakes the consumer. If the ring is full, the
+    /// value is returned.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let result = self.ring.push(value);
+        self.waker.wake();
+        result
+    }
+
+    #[inline]
+    pub fn try_pop(&self) -> Option<T> {
+        self.ring.pop()
+    }
+
+    /// This function spins until a value is available and returns it. The caller must ensure that
+    /// the producer can run while spinning (e.g. interrupts are enabled).
+    pub fn pop_blocking(&self) -> T {
+        loop {
+            if let Some(value) = self.ring.pop() {
+                return value;
+            }
+            spin_loop();
+        }
+    }
+
+    /// This function returns a future, which resolves to the next value of the ring
+    #[inline]
+    pub fn pop(&self) -> PopFuture<'_, T, N> {
+        PopFuture { ring: self }
+    }
+
+    /// This function polls the ring for the next value. If the ring is empty, the waker of the
+    /// context is woken by the next push.
+    pub fn poll_pop(&self, context: &Context<'_>) -> Poll<T> {
+        if let Some(value) = self.ring.pop() {
+            return Poll::Ready(value);
+        }
+
+        // Check again after registering, so a push between both checks isn't missed
+        self.waker.register(context.waker());
+        match self.ring.pop() {
+            Some(value) => {
+                self.waker.take();
+                Poll::Ready(value)
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+impl<T, const N: usize> Default for WaitRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct PopFuture<'a, T, const N: usize> {
+    ring: &'a WaitRing<T, N>,
+}
+
+impl<T, const N: usize> Future for PopFuture<'_, T, N> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        self.ring.poll_pop(context)
+    }
+}
@@ -0,0 +1,129 @@
+use core::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    panic::Location,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+#[cfg(feature = "deadlock-detection")]
+use crate::deadlock::{
+    LockOwner,
+    SpinWatchdog,
+};
+
+/// The spinlock is a mutual exclusion primitive, which busy-waits until the lock is released. With
+/// the `deadlock-detection` feature, the lock records its owner and panics with the information of
+/// both parties, if it's spun on for longer than the configured timeout.
+pub struct Spinlock<T: ?Sized> {
+    locked: AtomicBool,
+    #[cfg(feature = "deadlock-detection")]
+    owner: LockOwner,
+    value: UnsafeCell<T>,
+}
+
+pub struct SpinlockGuard<'a, T: ?Sized> {
+    lock: &'a Spinlock<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Spinlock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Spinlock<T> {}
+
+impl<T> Spinlock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            #[cfg(feature = "deadlock-detection")]
+            owner: LockOwner::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Spinlock<T> {
+    /// This function acquires the lock and spins until the lock is released by the current owner
+    #[track_caller]
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        let caller = Location::caller();
+
+        #[cfg(feature = "deadlock-detection")]
+        let watchdog = SpinWatchdog::start();
+
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+
+                #[cfg(feature = "deadlock-detection")]
+                watchdog.check(self as *const Self as *const (), &self.owner, caller);
+            }
+        }
+
+        self.acquired(caller);
+        SpinlockGuard { lock: self }
+    }
+
+    /// This function acquires the lock, if the lock isn't held by another owner
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        self.acquired(Location::caller());
+        Some(SpinlockGuard { lock: self })
+    }
+
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// This function releases the lock without a guard. This is only intended for situations like
+    /// the panic handler, where the owner of the lock will never release it.
+    ///
+    /// # Safety
+    /// The caller has to ensure, that the protected value isn't used by the previous owner anymore.
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    #[inline]
+    #[allow(unused_variables)]
+    fn acquired(&self, caller: &'static Location<'static>) {
+        #[cfg(feature = "deadlock-detection")]
+        self.owner.record(caller);
+    }
+}
+
+impl<T: ?Sized> Deref for SpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for SpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
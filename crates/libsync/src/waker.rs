@@ -0,0 +1,85 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+    task::Waker,
+};
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 1 << 0;
+const WAKING: usize = 1 << 1;
+
+/// The atomic waker stores the waker of a single consumer, which is woken by a producer. Unlike a
+/// waker behind a spinlock, waking never blocks, so it can be done from interrupt context while
+/// the consumer registers its waker.
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// This function registers the specified waker, which is woken by the next call of
+    /// [AtomicWaker::wake]. If a wake happens while registering, the waker is woken immediately.
+    pub fn register(&self, waker: &Waker) {
+        match self
+            .state
+            .compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe { *self.waker.get() = Some(waker.clone()) };
+
+                // A producer tried to wake while the waker was stored, so the wake is forwarded
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    let waker = unsafe { (*self.waker.get()).take() };
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            Err(WAKING) => waker.wake_by_ref(),
+            Err(_) => {}
+        }
+    }
+
+    /// This function wakes the registered waker, if a waker is registered
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+
+    /// This function removes the registered waker without waking it
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}